@@ -0,0 +1,55 @@
+//! Cost of adding and removing endpoints of a client while it sends gets, against an
+//! in-process `MockEtcd` recording the endpoints of its balanced channel.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use etcd_client::test_util::MockEtcd;
+
+/// The number of gets sent between the changes of the endpoints.
+const GETS: usize = 16;
+
+fn bench_endpoint_churn(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = runtime.block_on(async { MockEtcd::new().client().await.unwrap() });
+
+    let mut group = c.benchmark_group("endpoint churn");
+    for endpoints in [1, 16] {
+        let urls: Vec<String> = (0..endpoints)
+            .map(|i| format!("http://mock-{}:2379", i))
+            .collect();
+        group.throughput(Throughput::Elements(endpoints as u64));
+        group.bench_with_input(
+            BenchmarkId::new("add and remove", endpoints),
+            &urls,
+            |b, urls| {
+                b.to_async(&runtime).iter(|| async {
+                    for url in urls {
+                        client.add_endpoint(url).await.unwrap();
+                    }
+                    for url in urls {
+                        client.remove_endpoint(url).await.unwrap();
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("add and remove between gets", endpoints),
+            &urls,
+            |b, urls| {
+                b.to_async(&runtime).iter(|| async {
+                    let mut kv = client.kv_client();
+                    for url in urls {
+                        client.add_endpoint(url).await.unwrap();
+                        for _ in 0..GETS {
+                            Box::pin(kv.get("key", None)).await.unwrap();
+                        }
+                        client.remove_endpoint(url).await.unwrap();
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_endpoint_churn);
+criterion_main!(benches);
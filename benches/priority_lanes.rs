@@ -0,0 +1,200 @@
+//! Latency of unary gets while a snapshot is downloaded over the same link, a local mock
+//! server behind a proxy sharing a bandwidth of [`BANDWIDTH`] between all its connections,
+//! with a single lane and with priority lanes.
+//!
+//! With a single lane, a get waits on the connection behind the chunks of the snapshot the
+//! server wrote already. With priority lanes, the snapshot has a connection of its own and
+//! a get only waits for its share of the link. The 99th percentile of the latencies of the
+//! gets is printed after each benchmark.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use etcd_client::{Client, ConnectOptions};
+use http_body::Frame;
+use http_body_util::StreamBody;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+use tonic::codegen::Bytes;
+
+/// The size of the snapshot, without its checksum.
+const SNAPSHOT_SIZE: usize = 16 << 20;
+
+/// The size of the chunks etcd streams a snapshot in.
+const CHUNK_SIZE: usize = 32 << 10;
+
+/// The bandwidth of the link between the client and the server, in bytes per second.
+const BANDWIDTH: u64 = 64 << 20;
+
+/// The size of the segments the proxy forwards.
+const SEGMENT_SIZE: usize = 16 << 10;
+
+/// Encodes a snapshot of `SNAPSHOT_SIZE` bytes and its checksum as the gRPC frames of the
+/// `SnapshotResponse` messages, in chunks of `CHUNK_SIZE` bytes.
+fn snapshot_frames() -> Vec<Bytes> {
+    let mut snapshot: Vec<u8> = (0..SNAPSHOT_SIZE).map(|i| (i % 251) as u8).collect();
+    let checksum = Sha256::digest(&snapshot);
+    snapshot.extend_from_slice(&checksum);
+
+    snapshot
+        .chunks(CHUNK_SIZE)
+        .map(|blob| {
+            // The `blob` field of the message, number 3.
+            let mut msg = vec![0x1a];
+            prost::encoding::encode_varint(blob.len() as u64, &mut msg);
+            msg.extend_from_slice(blob);
+
+            let mut frame = vec![0];
+            frame.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+            frame.extend_from_slice(&msg);
+            Bytes::from(frame)
+        })
+        .collect()
+}
+
+/// Serves the snapshot `frames` to every Snapshot request, and an empty response to every
+/// Range request.
+async fn server(frames: Vec<Bytes>) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            socket.set_nodelay(true).unwrap();
+            let frames = frames.clone();
+            let service = hyper::service::service_fn(move |req: http::Request<_>| {
+                let frames = match req.uri().path() {
+                    "/etcdserverpb.Maintenance/Snapshot" => frames.clone(),
+                    "/etcdserverpb.KV/Range" => vec![Bytes::from_static(&[0, 0, 0, 0, 0])],
+                    path => panic!("unexpected request: {}", path),
+                };
+                let mut trailers = http::HeaderMap::new();
+                tonic::Status::ok("").add_header(&mut trailers).unwrap();
+                let frames = frames
+                    .into_iter()
+                    .map(Frame::data)
+                    .chain([Frame::trailers(trailers)])
+                    .map(Ok::<_, Infallible>);
+                let resp = http::Response::builder()
+                    .header("content-type", "application/grpc")
+                    .body(StreamBody::new(tokio_stream::iter(frames)))
+                    .unwrap();
+                async move { Ok::<_, Infallible>(resp) }
+            });
+            let http2 = hyper::server::conn::http2::Builder::new(TokioExecutor::new());
+            tokio::spawn(http2.serve_connection(TokioIo::new(socket), service));
+        }
+    });
+    addr
+}
+
+/// Forwards the bytes read from `from` to `to` in segments, each sent when the `link` is
+/// free, which all the connections share.
+async fn forward_throttled(
+    mut from: impl AsyncRead + Unpin,
+    mut to: impl AsyncWrite + Unpin,
+    link: Arc<Mutex<Instant>>,
+) {
+    let mut buf = vec![0; SEGMENT_SIZE];
+    while let Ok(n @ 1..) = from.read(&mut buf).await {
+        let at = {
+            let mut free = link.lock().unwrap();
+            let at = (*free).max(Instant::now())
+                + Duration::from_nanos(n as u64 * 1_000_000_000 / BANDWIDTH);
+            *free = at;
+            at
+        };
+        tokio::time::sleep_until(at).await;
+        if to.write_all(&buf[..n]).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Proxies the connections to `upstream` over a link of [`BANDWIDTH`].
+async fn throttling_proxy(upstream: SocketAddr) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let link = Arc::new(Mutex::new(Instant::now()));
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let server = tokio::net::TcpStream::connect(upstream).await.unwrap();
+            socket.set_nodelay(true).unwrap();
+            server.set_nodelay(true).unwrap();
+            let (client_read, client_write) = socket.into_split();
+            let (server_read, server_write) = server.into_split();
+            tokio::spawn(forward_throttled(client_read, server_write, link.clone()));
+            tokio::spawn(forward_throttled(server_read, client_write, link.clone()));
+        }
+    });
+    addr
+}
+
+/// The 99th percentile of `latencies`.
+fn p99(latencies: &mut [Duration]) -> Duration {
+    latencies.sort_unstable();
+    latencies[(latencies.len() * 99 / 100).min(latencies.len() - 1)]
+}
+
+fn bench_priority_lanes(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = runtime.block_on(async {
+        let server = server(snapshot_frames()).await;
+        throttling_proxy(server).await
+    });
+
+    let mut group = c.benchmark_group("get during snapshot");
+    group.sample_size(20);
+
+    for (name, priority_lanes) in [("single lane", false), ("priority lanes", true)] {
+        let client = runtime.block_on(async {
+            let options = ConnectOptions::new().with_priority_lanes(priority_lanes);
+            Client::connect([addr.to_string()], Some(options))
+                .await
+                .unwrap()
+        });
+        // The snapshots are downloaded one after another for the whole benchmark.
+        let snapshots = runtime.spawn({
+            let mut maintenance = client.maintenance_client();
+            async move {
+                loop {
+                    maintenance
+                        .snapshot_to(tokio::io::sink(), None)
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+
+        let latencies = Mutex::new(Vec::new());
+        group.bench_function(name, |b| {
+            b.to_async(&runtime).iter_custom(|iters| {
+                let mut kv = client.kv_client();
+                let latencies = &latencies;
+                async move {
+                    let mut total = Duration::ZERO;
+                    for _ in 0..iters {
+                        let start = Instant::now();
+                        kv.get("key", None).await.unwrap();
+                        let latency = start.elapsed();
+                        latencies.lock().unwrap().push(latency);
+                        total += latency;
+                    }
+                    total
+                }
+            })
+        });
+        snapshots.abort();
+
+        let mut latencies = latencies.into_inner().unwrap();
+        println!("{}: p99 {:?}", name, p99(&mut latencies));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_priority_lanes);
+criterion_main!(benches);
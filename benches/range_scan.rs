@@ -0,0 +1,194 @@
+//! Throughput of scanning a prefix from a local mock server answering every range after a
+//! fixed latency, of `KvClient::get_stream_parallel` scanning sub-ranges concurrently,
+//! against paginating the whole range sequentially and against a single request.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use etcd_client::raw::{etcdserverpb, mvccpb};
+use etcd_client::{Client, GetOptions, ParallelScanOptions, Revision, ScanOrder};
+use http_body::Frame;
+use http_body_util::{BodyExt, StreamBody};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use prost::Message;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::codegen::Bytes;
+
+/// The number of keys under the scanned prefix.
+const KEYS: usize = 100_000;
+
+/// The number of keys per page.
+const BATCH_SIZE: i64 = 1000;
+
+/// The number of pages a shard reads ahead, all of its pages.
+const PREFETCH: usize = KEYS / BATCH_SIZE as usize;
+
+/// The time the server takes to answer a range.
+const LATENCY: Duration = Duration::from_millis(1);
+
+/// The revision of the store.
+const REVISION: i64 = 10;
+
+/// The sorted keys of the store.
+fn store() -> Arc<Vec<Vec<u8>>> {
+    Arc::new(
+        (0..KEYS)
+            .map(|i| format!("scan/{:08}", i).into_bytes())
+            .collect(),
+    )
+}
+
+/// Answers `req` from the sorted `keys`.
+fn range(keys: &[Vec<u8>], req: etcdserverpb::RangeRequest) -> etcdserverpb::RangeResponse {
+    let start = keys.partition_point(|key| key[..] < req.key[..]);
+    let end = match &req.range_end[..] {
+        [] => start + usize::from(keys.get(start).is_some_and(|key| key[..] == req.key[..])),
+        [0] => keys.len(),
+        range_end => keys.partition_point(|key| key[..] < *range_end),
+    };
+    let count = end.saturating_sub(start);
+    let limit = match req.limit {
+        0 => count,
+        limit => count.min(limit as usize),
+    };
+    let kvs = match req.count_only {
+        true => Vec::new(),
+        false => keys[start..start + limit]
+            .iter()
+            .map(|key| mvccpb::KeyValue {
+                key: key.clone(),
+                value: b"value".to_vec(),
+                mod_revision: REVISION,
+                ..Default::default()
+            })
+            .collect(),
+    };
+    etcdserverpb::RangeResponse {
+        header: Some(etcdserverpb::ResponseHeader {
+            revision: REVISION,
+            ..Default::default()
+        }),
+        more: !req.count_only && limit < count,
+        count: count as i64,
+        kvs,
+    }
+}
+
+/// Serves the ranges of the `keys` store after [`LATENCY`].
+async fn range_server(keys: Arc<Vec<Vec<u8>>>) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            socket.set_nodelay(true).unwrap();
+            let keys = keys.clone();
+            let service =
+                hyper::service::service_fn(move |req: http::Request<hyper::body::Incoming>| {
+                    assert_eq!(req.uri().path(), "/etcdserverpb.KV/Range");
+                    let keys = keys.clone();
+                    async move {
+                        let body = req.into_body().collect().await.unwrap().to_bytes();
+                        // The message after the compression flag and length of its frame.
+                        let req = etcdserverpb::RangeRequest::decode(&body[5..]).unwrap();
+                        let msg = range(&keys, req).encode_to_vec();
+                        let mut frame = vec![0];
+                        frame.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+                        frame.extend_from_slice(&msg);
+                        tokio::time::sleep(LATENCY).await;
+
+                        let mut trailers = http::HeaderMap::new();
+                        tonic::Status::ok("").add_header(&mut trailers).unwrap();
+                        let frames = [Frame::data(Bytes::from(frame)), Frame::trailers(trailers)];
+                        let resp = http::Response::builder()
+                            .header("content-type", "application/grpc")
+                            .body(StreamBody::new(tokio_stream::iter(
+                                frames.into_iter().map(Ok::<_, Infallible>),
+                            )))
+                            .unwrap();
+                        Ok::<_, Infallible>(resp)
+                    }
+                });
+            let http2 = hyper::server::conn::http2::Builder::new(TokioExecutor::new());
+            tokio::spawn(http2.serve_connection(TokioIo::new(socket), service));
+        }
+    });
+    addr
+}
+
+fn bench_range_scan(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = runtime.block_on(async {
+        let addr = range_server(store()).await;
+        Client::connect([addr.to_string()], None).await.unwrap()
+    });
+
+    let mut group = c.benchmark_group("range scan");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(KEYS as u64));
+
+    group.bench_function("single request", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let options = GetOptions::new().with_prefix();
+            let resp = client
+                .kv_client()
+                .get("scan/", Some(options))
+                .await
+                .unwrap();
+            assert_eq!(resp.kvs().len(), KEYS);
+        })
+    });
+
+    group.bench_function("sequential", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let mut kv = client.kv_client();
+            let (mut key, end) = (b"scan/".to_vec(), b"scan0".to_vec());
+            let mut revision = Revision::current();
+            let mut scanned = 0;
+            loop {
+                let options = GetOptions::new()
+                    .with_range(end.clone())
+                    .with_revision(revision)
+                    .with_limit(BATCH_SIZE);
+                let resp = kv.get(key.clone(), Some(options)).await.unwrap();
+                revision = resp.header().unwrap().revision();
+                scanned += resp.kvs().len();
+                match resp.kvs().last() {
+                    Some(last) if resp.more() => key = [last.key(), b"\0"].concat(),
+                    _ => break,
+                }
+            }
+            assert_eq!(scanned, KEYS);
+        })
+    });
+
+    for order in [ScanOrder::KeyOrdered, ScanOrder::Interleaved] {
+        for shards in [1, 4, 8] {
+            let id = BenchmarkId::new(format!("parallel {:?}", order), shards);
+            group.bench_with_input(id, &shards, |b, &shards| {
+                b.to_async(&runtime).iter(|| async {
+                    let options = ParallelScanOptions::new()
+                        .with_shards(shards)
+                        .with_batch_size(BATCH_SIZE)
+                        .with_prefetch(PREFETCH)
+                        .with_order(order);
+                    let mut stream = client
+                        .kv_client()
+                        .get_stream_parallel("scan/", Some(options))
+                        .await
+                        .unwrap();
+                    let mut scanned = 0;
+                    while let Some(page) = stream.message().await.unwrap() {
+                        scanned += page.len();
+                    }
+                    assert_eq!(scanned, KEYS);
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_range_scan);
+criterion_main!(benches);
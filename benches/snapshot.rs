@@ -0,0 +1,114 @@
+//! Throughput of snapshot downloads from a local mock server, of
+//! `MaintenanceClient::snapshot_to` sharing the chunks' buffers, against copying every
+//! chunk out of its response.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use etcd_client::Client;
+use http_body::Frame;
+use http_body_util::StreamBody;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tokio::io::AsyncWriteExt;
+use tonic::codegen::Bytes;
+
+/// The size of the snapshot, without its checksum.
+const SNAPSHOT_SIZE: usize = 16 << 20;
+
+/// The size of the chunks etcd streams a snapshot in.
+const CHUNK_SIZE: usize = 32 << 10;
+
+/// Encodes a snapshot of `SNAPSHOT_SIZE` bytes and its checksum as the gRPC frames of the
+/// `SnapshotResponse` messages, in chunks of `CHUNK_SIZE` bytes.
+fn snapshot_frames() -> Vec<Bytes> {
+    let mut snapshot: Vec<u8> = (0..SNAPSHOT_SIZE).map(|i| (i % 251) as u8).collect();
+    let checksum = Sha256::digest(&snapshot);
+    snapshot.extend_from_slice(&checksum);
+
+    snapshot
+        .chunks(CHUNK_SIZE)
+        .map(|blob| {
+            // The `blob` field of the message, number 3.
+            let mut msg = vec![0x1a];
+            prost::encoding::encode_varint(blob.len() as u64, &mut msg);
+            msg.extend_from_slice(blob);
+
+            let mut frame = vec![0];
+            frame.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+            frame.extend_from_slice(&msg);
+            Bytes::from(frame)
+        })
+        .collect()
+}
+
+/// Serves the snapshot `frames` to every Snapshot request.
+async fn snapshot_server(frames: Vec<Bytes>) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let frames = frames.clone();
+            let service = hyper::service::service_fn(move |req: http::Request<_>| {
+                assert_eq!(req.uri().path(), "/etcdserverpb.Maintenance/Snapshot");
+                let mut trailers = http::HeaderMap::new();
+                tonic::Status::ok("").add_header(&mut trailers).unwrap();
+                let frames = frames
+                    .clone()
+                    .into_iter()
+                    .map(Frame::data)
+                    .chain([Frame::trailers(trailers)])
+                    .map(Ok::<_, Infallible>);
+                let resp = http::Response::builder()
+                    .header("content-type", "application/grpc")
+                    .body(StreamBody::new(tokio_stream::iter(frames)))
+                    .unwrap();
+                async move { Ok::<_, Infallible>(resp) }
+            });
+            let http2 = hyper::server::conn::http2::Builder::new(TokioExecutor::new());
+            tokio::spawn(http2.serve_connection(TokioIo::new(socket), service));
+        }
+    });
+    addr
+}
+
+fn bench_snapshot(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = runtime.block_on(async {
+        let addr = snapshot_server(snapshot_frames()).await;
+        Client::connect([addr.to_string()], None).await.unwrap()
+    });
+
+    let mut group = c.benchmark_group("snapshot");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(SNAPSHOT_SIZE as u64));
+
+    group.bench_function("copied", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let mut stream = client.maintenance_client().snapshot().await.unwrap();
+            let mut writer = tokio::io::sink();
+            let mut hasher = Sha256::new();
+            while let Some(resp) = stream.message().await.unwrap() {
+                let blob = resp.blob().to_vec();
+                hasher.update(&blob);
+                writer.write_all(&blob).await.unwrap();
+            }
+            hasher.finalize()
+        })
+    });
+
+    group.bench_function("shared", |b| {
+        b.to_async(&runtime).iter(|| async {
+            client
+                .maintenance_client()
+                .snapshot_to(tokio::io::sink(), None)
+                .await
+                .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_snapshot);
+criterion_main!(benches);
@@ -0,0 +1,168 @@
+//! Throughput of snapshot downloads over a link with a round trip of [`ROUND_TRIP`], a
+//! local mock server behind a proxy delaying everything it forwards, by the HTTP2 flow
+//! control windows of the client: the default ones, windows covering the bandwidth-delay
+//! product and adaptive windows.
+//!
+//! The server sends a window per round trip at most, so the default windows throttle the
+//! download to about 2 MiB per round trip.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use etcd_client::{Client, ConnectOptions};
+use http_body::Frame;
+use http_body_util::StreamBody;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+use tonic::codegen::Bytes;
+
+/// The size of the snapshot, without its checksum.
+const SNAPSHOT_SIZE: usize = 32 << 20;
+
+/// The size of the chunks etcd streams a snapshot in.
+const CHUNK_SIZE: usize = 32 << 10;
+
+/// The round trip of the link between the client and the server.
+const ROUND_TRIP: Duration = Duration::from_millis(40);
+
+/// Encodes a snapshot of `SNAPSHOT_SIZE` bytes and its checksum as the gRPC frames of the
+/// `SnapshotResponse` messages, in chunks of `CHUNK_SIZE` bytes.
+fn snapshot_frames() -> Vec<Bytes> {
+    let mut snapshot: Vec<u8> = (0..SNAPSHOT_SIZE).map(|i| (i % 251) as u8).collect();
+    let checksum = Sha256::digest(&snapshot);
+    snapshot.extend_from_slice(&checksum);
+
+    snapshot
+        .chunks(CHUNK_SIZE)
+        .map(|blob| {
+            // The `blob` field of the message, number 3.
+            let mut msg = vec![0x1a];
+            prost::encoding::encode_varint(blob.len() as u64, &mut msg);
+            msg.extend_from_slice(blob);
+
+            let mut frame = vec![0];
+            frame.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+            frame.extend_from_slice(&msg);
+            Bytes::from(frame)
+        })
+        .collect()
+}
+
+/// Serves the snapshot `frames` to every Snapshot request.
+async fn snapshot_server(frames: Vec<Bytes>) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            socket.set_nodelay(true).unwrap();
+            let frames = frames.clone();
+            let service = hyper::service::service_fn(move |req: http::Request<_>| {
+                assert_eq!(req.uri().path(), "/etcdserverpb.Maintenance/Snapshot");
+                let mut trailers = http::HeaderMap::new();
+                tonic::Status::ok("").add_header(&mut trailers).unwrap();
+                let frames = frames
+                    .clone()
+                    .into_iter()
+                    .map(Frame::data)
+                    .chain([Frame::trailers(trailers)])
+                    .map(Ok::<_, Infallible>);
+                let resp = http::Response::builder()
+                    .header("content-type", "application/grpc")
+                    .body(StreamBody::new(tokio_stream::iter(frames)))
+                    .unwrap();
+                async move { Ok::<_, Infallible>(resp) }
+            });
+            let http2 = hyper::server::conn::http2::Builder::new(TokioExecutor::new());
+            tokio::spawn(http2.serve_connection(TokioIo::new(socket), service));
+        }
+    });
+    addr
+}
+
+/// Forwards the bytes read from `from` to `to` half a round trip after reading them.
+async fn forward_delayed(
+    mut from: impl AsyncRead + Unpin,
+    mut to: impl AsyncWrite + Unpin + Send + 'static,
+) {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<(Instant, Vec<u8>)>();
+    tokio::spawn(async move {
+        while let Some((at, bytes)) = rx.recv().await {
+            tokio::time::sleep_until(at).await;
+            if to.write_all(&bytes).await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut buf = vec![0; 64 << 10];
+    while let Ok(n @ 1..) = from.read(&mut buf).await {
+        let _ = tx.send((Instant::now() + ROUND_TRIP / 2, buf[..n].to_vec()));
+    }
+}
+
+/// Proxies the connections to `upstream` over a link with a round trip of [`ROUND_TRIP`].
+async fn delaying_proxy(upstream: SocketAddr) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let server = tokio::net::TcpStream::connect(upstream).await.unwrap();
+            socket.set_nodelay(true).unwrap();
+            server.set_nodelay(true).unwrap();
+            let (client_read, client_write) = socket.into_split();
+            let (server_read, server_write) = server.into_split();
+            tokio::spawn(forward_delayed(client_read, server_write));
+            tokio::spawn(forward_delayed(server_read, client_write));
+        }
+    });
+    addr
+}
+
+fn bench_snapshot_window(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = runtime.block_on(async {
+        let server = snapshot_server(snapshot_frames()).await;
+        delaying_proxy(server).await
+    });
+
+    let mut group = c.benchmark_group("snapshot window");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(SNAPSHOT_SIZE as u64));
+
+    let windows = [
+        ("default", ConnectOptions::new()),
+        (
+            "16 MiB",
+            ConnectOptions::new()
+                .with_initial_stream_window_size(16 << 20)
+                .with_initial_connection_window_size(32 << 20),
+        ),
+        (
+            "adaptive",
+            ConnectOptions::new().with_http2_adaptive_window(true),
+        ),
+    ];
+    for (name, options) in windows {
+        let client = runtime.block_on(async {
+            Client::connect([addr.to_string()], Some(options))
+                .await
+                .unwrap()
+        });
+        group.bench_function(name, |b| {
+            b.to_async(&runtime).iter(|| async {
+                client
+                    .maintenance_client()
+                    .snapshot_to(tokio::io::sink(), None)
+                    .await
+                    .unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_snapshot_window);
+criterion_main!(benches);
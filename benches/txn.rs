@@ -0,0 +1,42 @@
+//! Cost of building a `Txn` and encoding it as the `TxnRequest` sent to etcd, by the number
+//! of its operations.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use etcd_client::raw::etcdserverpb;
+use etcd_client::{Compare, CompareOp, PutOptions, Txn, TxnOp};
+use prost::Message;
+
+/// Builds a compare-and-swap of `ops` keys: their puts if all their versions match, their
+/// gets otherwise.
+fn txn(ops: usize) -> Txn {
+    let keys = (0..ops).map(|i| format!("txn/{:04}", i));
+    let compares: Vec<_> = keys
+        .clone()
+        .map(|key| Compare::version(key, CompareOp::Equal, 3))
+        .collect();
+    let puts: Vec<_> = keys
+        .clone()
+        .map(|key| TxnOp::put(key, "value", Some(PutOptions::new().with_prev_key())))
+        .collect();
+    let gets: Vec<_> = keys.map(|key| TxnOp::get(key, None)).collect();
+    Txn::new().when(compares).and_then(puts).or_else(gets)
+}
+
+fn bench_txn(c: &mut Criterion) {
+    let mut group = c.benchmark_group("txn");
+    for ops in [1, 16, 128] {
+        group.throughput(Throughput::Elements(ops as u64));
+        group.bench_with_input(BenchmarkId::new("build", ops), &ops, |b, &ops| {
+            b.iter(|| txn(ops))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("build and encode", ops),
+            &ops,
+            |b, &ops| b.iter(|| etcdserverpb::TxnRequest::from(txn(ops)).encode_to_vec()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_txn);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+//! Throughput and latency of unary puts and gets through the whole client stack, the
+//! interceptors, auth service and balanced channel, against an in-process `MockEtcd`, by the
+//! number of requests in flight, each spawned as a task.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use etcd_client::test_util::MockEtcd;
+use tokio::task::JoinSet;
+
+/// The number of keys of the store the gets read.
+const KEYS: usize = 1000;
+
+fn bench_unary(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = runtime.block_on(async {
+        let client = MockEtcd::new().client().await.unwrap();
+        for i in 0..KEYS {
+            let key = format!("key/{:04}", i);
+            client.kv_client().put(key, "value", None).await.unwrap();
+        }
+        client
+    });
+
+    let mut group = c.benchmark_group("unary");
+    for in_flight in [1, 16] {
+        group.throughput(Throughput::Elements(in_flight as u64));
+        group.bench_with_input(BenchmarkId::new("put", in_flight), &in_flight, |b, &n| {
+            b.to_async(&runtime).iter(|| {
+                let mut requests = JoinSet::new();
+                for i in 0..n {
+                    let mut kv = client.kv_client();
+                    requests
+                        .spawn(async move { kv.put(format!("key/{:04}", i), "value", None).await });
+                }
+                requests.join_all()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("get", in_flight), &in_flight, |b, &n| {
+            b.to_async(&runtime).iter(|| {
+                let mut requests = JoinSet::new();
+                for i in 0..n {
+                    let mut kv = client.kv_client();
+                    requests.spawn(async move { kv.get(format!("key/{:04}", i), None).await });
+                }
+                requests.join_all()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_unary);
+criterion_main!(benches);
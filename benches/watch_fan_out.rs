@@ -0,0 +1,54 @@
+//! Latency of delivering the event of a put to the consumers of as many watches of its key,
+//! all created by one client, each on a stream of its own, against an in-process
+//! `MockEtcd`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use etcd_client::test_util::MockEtcd;
+use etcd_client::{WatchStream, Watcher};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Receives the events of `stream`, adding a permit to `received` for each of them. The
+/// watch is kept open by its `watcher`.
+async fn consume(_watcher: Watcher, mut stream: WatchStream, received: Arc<Semaphore>) {
+    while let Ok(Some(resp)) = stream.message().await {
+        received.add_permits(resp.events().len());
+    }
+}
+
+fn bench_watch_fan_out(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("watch fan-out");
+    for consumers in [1u32, 16, 128] {
+        let (client, received) = runtime.block_on(async {
+            let client = MockEtcd::new().client().await.unwrap();
+            let received = Arc::new(Semaphore::new(0));
+            for _ in 0..consumers {
+                let (watcher, stream) = client.watch_client().watch("key", None).await.unwrap();
+                tokio::spawn(consume(watcher, stream, received.clone()));
+            }
+            (client, received)
+        });
+
+        group.throughput(Throughput::Elements(consumers as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(consumers),
+            &consumers,
+            |b, &consumers| {
+                b.to_async(&runtime).iter(|| {
+                    let mut kv = client.kv_client();
+                    let received = received.clone();
+                    async move {
+                        kv.put("key", "value", None).await.unwrap();
+                        received.acquire_many(consumers).await.unwrap().forget();
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_watch_fan_out);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+//! Cost of keeping the headers of a synthetic watch event stream, copied out of the
+//! responses as `ResponseHeader` is `Copy`, against borrowing them and against sharing
+//! them behind an `Arc`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use etcd_client::raw::{etcdserverpb, mvccpb};
+use etcd_client::{ResponseHeader, WatchResponse};
+use std::hint::black_box;
+use std::sync::Arc;
+
+/// The number of responses of the stream.
+const RESPONSES: usize = 10_000;
+
+/// The number of events per response.
+const EVENTS: usize = 4;
+
+/// The responses of a watch stream, of [`EVENTS`] puts each at increasing revisions.
+fn event_stream() -> Vec<WatchResponse> {
+    (0..RESPONSES as i64)
+        .map(|revision| {
+            let events = (0..EVENTS)
+                .map(|i| mvccpb::Event {
+                    kv: Some(mvccpb::KeyValue {
+                        key: format!("key/{}", i).into_bytes(),
+                        value: b"value".to_vec(),
+                        mod_revision: revision,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                .collect();
+            WatchResponse::from(etcdserverpb::WatchResponse {
+                header: Some(etcdserverpb::ResponseHeader {
+                    cluster_id: 1,
+                    member_id: 2,
+                    revision,
+                    raft_term: 3,
+                }),
+                watch_id: 1,
+                events,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+fn bench_watch_headers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("watch headers");
+    group.throughput(Throughput::Elements(RESPONSES as u64));
+
+    group.bench_function("borrowed", |b| {
+        b.iter_batched_ref(
+            event_stream,
+            |stream: &mut Vec<WatchResponse>| {
+                stream
+                    .iter()
+                    .filter_map(|resp| resp.header().map(ResponseHeader::revision))
+                    .max()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("copied", |b| {
+        b.iter_batched_ref(
+            event_stream,
+            |stream: &mut Vec<WatchResponse>| {
+                let headers: Vec<ResponseHeader> = stream
+                    .iter()
+                    .filter_map(|resp| resp.header().copied())
+                    .collect();
+                black_box(headers)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("shared", |b| {
+        b.iter_batched_ref(
+            event_stream,
+            |stream: &mut Vec<WatchResponse>| {
+                let headers: Vec<Arc<ResponseHeader>> = stream
+                    .iter()
+                    .filter_map(|resp| resp.header().copied().map(Arc::new))
+                    .collect();
+                black_box(headers)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_watch_headers);
+criterion_main!(benches);
@@ -8,12 +8,133 @@ fn should_build_server() -> bool {
     false
 }
 
+/// The fields whose names are not in snake case, and so are renamed by prost.
+#[cfg(feature = "serde")]
+const RENAMED_FIELDS: &[&str] = &[
+    "ID",
+    "TTL",
+    "clientURLs",
+    "dbSize",
+    "dbSizeInUse",
+    "grantedTTL",
+    "isLearner",
+    "keyPermission",
+    "memberID",
+    "peerURLs",
+    "permType",
+    "raftAppliedIndex",
+    "raftIndex",
+    "raftTerm",
+    "remaining_TTL",
+    "targetID",
+];
+
+/// The `bytes` fields, suffixes of their paths.
+#[cfg(feature = "serde")]
+const BYTES_FIELDS: &[&str] = &[
+    "key",
+    "value",
+    "range_end",
+    "blob",
+    "authpb.User.name",
+    "authpb.User.password",
+    "authpb.Role.name",
+    "v3electionpb.CampaignRequest.name",
+    "v3electionpb.LeaderKey.name",
+    "v3electionpb.LeaderRequest.name",
+    "v3lockpb.LockRequest.name",
+];
+
+/// Derives serde for the messages, named after their proto fields and encoding bytes as
+/// base64.
+#[cfg(feature = "serde")]
+fn configure_serde(config: &mut prost_build::Config) {
+    config
+        .type_attribute(".", "#[derive(::serde::Serialize, ::serde::Deserialize)]")
+        .message_attribute(".", "#[serde(default)]")
+        // The variants of oneofs are named after their fields.
+        .enum_attribute(".", "#[serde(rename_all = \"snake_case\")]")
+        .field_attribute(
+            "LeaseTimeToLiveResponse.keys",
+            "#[serde(with = \"crate::serialize::bytes_list\")]",
+        );
+    for field in RENAMED_FIELDS {
+        config.field_attribute(field, format!("#[serde(rename = \"{field}\")]"));
+    }
+    for field in BYTES_FIELDS {
+        config.field_attribute(field, "#[serde(with = \"crate::serialize::bytes\")]");
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn configure_serde(_config: &mut prost_build::Config) {}
+
+/// The messages implementing `Debug` by hand, printing their bytes readably.
+const CUSTOM_DEBUG: &[&str] = &[".mvccpb.KeyValue"];
+
+/// The messages no RPC sends, which etcd only stores, or sends between its members.
+const UNUSED_MESSAGES: &[&str] = &[
+    ".authpb.User",
+    ".authpb.Role",
+    ".etcdserverpb.LeaseCheckpoint",
+    ".etcdserverpb.LeaseCheckpointRequest",
+    ".etcdserverpb.LeaseCheckpointResponse",
+];
+
+/// The `bytes` fields held as `Bytes`: the chunks of snapshots share the buffer of the
+/// response instead of being copied out of it, the keys and values of KV requests are
+/// shared by the clones of the requests sent again, e.g. retried or hedged.
+const SHARED_BYTES: &[&str] = &[
+    ".etcdserverpb.SnapshotResponse.blob",
+    ".etcdserverpb.RangeRequest.key",
+    ".etcdserverpb.RangeRequest.range_end",
+    ".etcdserverpb.PutRequest.key",
+    ".etcdserverpb.PutRequest.value",
+    ".etcdserverpb.DeleteRangeRequest.key",
+    ".etcdserverpb.DeleteRangeRequest.range_end",
+    ".etcdserverpb.Compare.key",
+    ".etcdserverpb.Compare.range_end",
+    ".etcdserverpb.Compare.value",
+];
+
+/// Generates the gRPC clients, and servers with `build-server`, of the services whose
+/// features are enabled, e.g. `kv` for the `KV` service. The messages of all the services
+/// are generated regardless.
+struct EnabledServices(Box<dyn prost_build::ServiceGenerator>);
+
+impl prost_build::ServiceGenerator for EnabledServices {
+    fn generate(&mut self, service: prost_build::Service, buf: &mut String) {
+        let feature = format!("CARGO_FEATURE_{}", service.name.to_uppercase());
+        if std::env::var_os(feature).is_some() {
+            self.0.generate(service, buf);
+        }
+    }
+
+    fn finalize(&mut self, buf: &mut String) {
+        self.0.finalize(buf);
+    }
+
+    fn finalize_package(&mut self, package: &str, buf: &mut String) {
+        self.0.finalize_package(package, buf);
+    }
+}
+
 fn main() {
     let proto_root = "proto";
     println!("cargo:rerun-if-changed={}", proto_root);
 
-    tonic_build::configure()
+    let services = tonic_build::configure()
         .build_server(should_build_server())
+        .service_generator();
+    let mut config = prost_build::Config::new();
+    configure_serde(&mut config);
+    for message in UNUSED_MESSAGES {
+        config.message_attribute(message, "#[allow(dead_code)]");
+    }
+    config
+        .skip_debug(CUSTOM_DEBUG)
+        .bytes(SHARED_BYTES)
+        .service_generator(Box::new(EnabledServices(services)))
         .compile_protos(
             &[
                 "proto/auth.proto",
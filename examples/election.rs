@@ -7,13 +7,13 @@ async fn main() -> Result<(), Error> {
     let mut client = Client::connect(["localhost:2379"], None).await?;
     let resp = client.lease_grant(10, None).await?;
     let lease_id = resp.id();
-    println!("grant ttl:{:?}, id:{:?}", resp.ttl(), resp.id());
+    println!("grant ttl:{:?}, id:{}", resp.ttl(), resp.id());
 
     // campaign
     let resp = client.campaign("myElection", "123", lease_id).await?;
     let leader = resp.leader().unwrap();
     println!(
-        "election name:{:?}, leaseId:{:?}",
+        "election name:{:?}, leaseId:{}",
         leader.name_str(),
         leader.lease()
     );
@@ -19,16 +19,12 @@ async fn main() -> Result<(), Error> {
 
     // make a lease
     let resp = client.lease_grant(60, None).await?;
-    println!(
-        "grant a lease with id {:?}, ttl {:?}",
-        resp.id(),
-        resp.ttl()
-    );
+    println!("grant a lease with id {}, ttl {:?}", resp.id(), resp.ttl());
     let lease_id = resp.id();
 
     // lock with lease
     println!(
-        "try to lock with name \'lock-test2\' and lease {:?}",
+        "try to lock with name \'lock-test2\' and lease {}",
         lease_id
     );
     let lock_options = LockOptions::new().with_lease(lease_id);
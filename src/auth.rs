@@ -1,20 +1,40 @@
 //! Authentication service.
 
-use crate::lock::RwLockExt;
+use arc_swap::ArcSwapOption;
 use http::{header::AUTHORIZATION, HeaderValue, Request};
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use tower_service::Service;
 
+/// The auth token shared by the clients of a [`Client`](crate::Client), encoded as the
+/// value of the authorization header once, when it is set.
+///
+/// It is read by every request, so it is swapped rather than locked: requests never wait
+/// for one another nor for a refresh.
+pub(crate) type AuthToken = Arc<ArcSwapOption<HeaderValue>>;
+
+/// Replaces the auth token `current`, read before authenticating again, with `token`.
+///
+/// A concurrent refresh which replaced `current` first wins, its token kept: refreshes
+/// racing each other authenticate once each yet swap the token once, and a token never
+/// replaces one issued after it.
+pub(crate) fn refresh_token(
+    auth_token: &AuthToken,
+    current: &Option<Arc<HeaderValue>>,
+    token: HeaderValue,
+) {
+    auth_token.compare_and_swap(current, Some(Arc::new(token)));
+}
+
 #[derive(Debug, Clone)]
 pub struct AuthService<S> {
     inner: S,
-    token: Arc<RwLock<Option<HeaderValue>>>,
+    token: AuthToken,
 }
 
 impl<S> AuthService<S> {
     #[inline]
-    pub fn new(inner: S, token: Arc<RwLock<Option<HeaderValue>>>) -> Self {
+    pub fn new(inner: S, token: AuthToken) -> Self {
         Self { inner, token }
     }
 }
@@ -34,8 +54,10 @@ where
 
     #[inline]
     fn call(&mut self, mut request: Request<Body>) -> Self::Future {
-        if let Some(token) = self.token.read_unpoisoned().as_ref() {
-            request.headers_mut().insert(AUTHORIZATION, token.clone());
+        if let Some(token) = &*self.token.load() {
+            request
+                .headers_mut()
+                .insert(AUTHORIZATION, HeaderValue::clone(token));
         }
 
         self.inner.call(request)
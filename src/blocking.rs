@@ -0,0 +1,645 @@
+//! Synchronous client, running the asynchronous client on a tokio runtime.
+//!
+//! [`Client`] either owns a multi-thread runtime, see [`Client::connect`], or borrows one
+//! through its [`Handle`], see [`Client::connect_with_handle`]. Its methods block the calling
+//! thread until the call completes, so they must not be called from within an async runtime,
+//! whose worker they would block: they return [`Error::BlockingInRuntime`] instead of
+//! panicking.
+//!
+//! Watch and lease keep-alive streams are forwarded from a task of the runtime to iterators.
+//! Dropping an iterator closes its stream.
+//!
+//! ```no_run
+//! use etcd_client::blocking::Client;
+//!
+//! fn main() -> Result<(), etcd_client::Error> {
+//!     let mut client = Client::connect(["localhost:2379"], None)?;
+//!     client.put("foo", "bar", None)?;
+//!     let (_watcher, stream) = client.watch("foo", None)?;
+//!     for resp in stream {
+//!         println!("{:?}", resp?.events());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::{Error, Result};
+#[cfg(feature = "watch")]
+use crate::rpc::watch::WatchResponse;
+use crate::ConnectOptions;
+#[cfg(feature = "watch")]
+use crate::WatchOptions;
+#[cfg(feature = "maintenance")]
+use crate::{
+    AlarmAction, AlarmMember, AlarmOptions, AlarmResponse, AlarmType, ClusterHealth,
+    ConsistencyReport, DefragOptions, DefragmentResponse, DowngradeResponse, HashKvResponse,
+    HashResponse, MemberDefragmentResult, MoveLeaderResponse, SnapshotOptions, SnapshotSummary,
+    SnapshotVerification, StatusResponse, VerifySnapshotOptions,
+};
+#[cfg(feature = "auth")]
+use crate::{
+    AuthDisableResponse, AuthEnableResponse, Permission, RoleAddResponse, RoleDeleteResponse,
+    RoleGetResponse, RoleGrantPermissionResponse, RoleListResponse, RoleRevokePermissionOptions,
+    RoleRevokePermissionResponse, UserAddOptions, UserAddResponse, UserChangePasswordResponse,
+    UserDeleteResponse, UserGetResponse, UserGrantRoleResponse, UserListResponse,
+    UserRevokeRoleResponse,
+};
+#[cfg(feature = "kv")]
+use crate::{
+    CompactionOptions, CompactionResponse, DeleteOptions, DeleteResponse, GetOptions, GetResponse,
+    PutOptions, PutResponse, Revision, Txn, TxnResponse,
+};
+#[cfg(feature = "lease")]
+use crate::{
+    LeaseGrantOptions, LeaseGrantResponse, LeaseId, LeaseKeepAliveResponse, LeaseLeasesResponse,
+    LeaseRevokeResponse, LeaseTimeToLiveOptions, LeaseTimeToLiveResponse,
+};
+#[cfg(feature = "lock")]
+use crate::{LockOptions, LockResponse, UnlockResponse};
+#[cfg(feature = "cluster")]
+use crate::{
+    MemberAddOptions, MemberAddResponse, MemberListOptions, MemberListResponse,
+    MemberPromoteResponse, MemberRemoveResponse, MemberUpdateResponse, PromoteOptions,
+};
+#[cfg(feature = "maintenance")]
+use http::Uri;
+use std::future::Future;
+#[cfg(feature = "maintenance")]
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+
+/// The time an owned runtime waits for its blocking tasks, e.g. file writes of snapshots,
+/// when the last [`Client`], [`Watcher`] or stream using it is dropped.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The number of responses a stream buffers before its iterator reads them.
+const FORWARD_BUFFER: usize = 16;
+
+/// Defines methods blocking on the methods of the same name of `self.inner`, the
+/// `$target` of the crate.
+macro_rules! blocking {
+    ($target:ident; $(
+        fn $name:ident(&mut self $(, $arg:ident: $ty:ty)* $(,)?) -> $ret:ty;
+    )*) => {
+        $(
+            #[doc = concat!(
+                "Blocking version of [`", stringify!($target), "::", stringify!($name),
+                "`](crate::", stringify!($target), "::", stringify!($name), ")."
+            )]
+            #[inline]
+            pub fn $name(&mut self $(, $arg: $ty)*) -> Result<$ret> {
+                self.runtime.block_on(self.inner.$name($($arg),*))
+            }
+        )*
+    };
+}
+
+/// The runtime the blocking calls run on.
+#[derive(Debug)]
+struct Runtime {
+    /// The runtime if owned, shut down once dropped.
+    owned: Option<tokio::runtime::Runtime>,
+    handle: Handle,
+}
+
+impl Runtime {
+    /// Builds a multi-thread runtime with a single worker running the background tasks of
+    /// the client, the calls themselves run on the threads blocking on them.
+    fn new() -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("etcd-client")
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            handle: runtime.handle().clone(),
+            owned: Some(runtime),
+        })
+    }
+
+    #[inline]
+    const fn borrowed(handle: Handle) -> Self {
+        Self {
+            owned: None,
+            handle,
+        }
+    }
+
+    /// Returns [`Error::BlockingInRuntime`] if the current thread runs an async runtime.
+    #[inline]
+    fn check() -> Result<()> {
+        match Handle::try_current() {
+            Ok(_) => Err(Error::BlockingInRuntime),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Blocks the current thread on the call `fut`.
+    #[inline]
+    fn block_on<T>(&self, fut: impl Future<Output = Result<T>>) -> Result<T> {
+        Self::check()?;
+        self.handle.block_on(fut)
+    }
+
+    /// Shuts the runtime down if owned, waiting up to `timeout` for its blocking tasks.
+    fn shutdown(&mut self, timeout: Duration) {
+        let Some(runtime) = self.owned.take() else {
+            return;
+        };
+        // A runtime can not block a thread running another one.
+        if Self::check().is_ok() {
+            runtime.shutdown_timeout(timeout);
+        } else {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl Drop for Runtime {
+    #[inline]
+    fn drop(&mut self) {
+        self.shutdown(DEFAULT_SHUTDOWN_TIMEOUT);
+    }
+}
+
+/// Synchronous `etcd` client using v3 API.
+///
+/// Clones share the runtime, which is shut down once the last clone, [`Watcher`],
+/// [`LeaseKeeper`] and stream using it are dropped, or with [`Client::shutdown`].
+#[derive(Clone)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    /// Connect to `etcd` servers from given `endpoints`, running the client on a runtime
+    /// owned by it.
+    ///
+    /// See [`Client::connect`](crate::Client::connect).
+    pub fn connect<E: AsRef<str>, S: AsRef<[E]>>(
+        endpoints: S,
+        options: Option<ConnectOptions>,
+    ) -> Result<Self> {
+        Runtime::check()?;
+        Self::connect_on(Runtime::new()?, endpoints, options)
+    }
+
+    /// Connect to `etcd` servers from given `endpoints`, running the client on the runtime
+    /// of `handle`.
+    ///
+    /// The runtime must be a multi-thread one, a current-thread runtime only makes progress
+    /// while a thread blocks on it.
+    pub fn connect_with_handle<E: AsRef<str>, S: AsRef<[E]>>(
+        handle: Handle,
+        endpoints: S,
+        options: Option<ConnectOptions>,
+    ) -> Result<Self> {
+        Self::connect_on(Runtime::borrowed(handle), endpoints, options)
+    }
+
+    fn connect_on<E: AsRef<str>, S: AsRef<[E]>>(
+        runtime: Runtime,
+        endpoints: S,
+        options: Option<ConnectOptions>,
+    ) -> Result<Self> {
+        let inner = runtime.block_on(crate::Client::connect(endpoints, options))?;
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// The asynchronous client, e.g. to use the APIs which are not mirrored.
+    #[inline]
+    pub fn async_client(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    /// The handle of the runtime the client runs on.
+    #[inline]
+    pub fn handle(&self) -> &Handle {
+        &self.runtime.handle
+    }
+
+    /// Drops the client, shutting its runtime down if this is the last user of an owned
+    /// runtime, and waiting up to `timeout` for its blocking tasks.
+    pub fn shutdown(self, timeout: Duration) {
+        let Self { inner, runtime } = self;
+        drop(inner);
+        if let Ok(mut runtime) = Arc::try_unwrap(runtime) {
+            runtime.shutdown(timeout);
+        }
+    }
+}
+
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+impl Client {
+    blocking! { Client;
+        fn put(
+            &mut self,
+            key: impl Into<Vec<u8>>,
+            value: impl Into<Vec<u8>>,
+            options: Option<PutOptions>,
+        ) -> PutResponse;
+        fn get(&mut self, key: impl Into<Vec<u8>>, options: Option<GetOptions>) -> GetResponse;
+        fn delete(
+            &mut self,
+            key: impl Into<Vec<u8>>,
+            options: Option<DeleteOptions>,
+        ) -> DeleteResponse;
+        fn compact(
+            &mut self,
+            revision: impl Into<Revision>,
+            options: Option<CompactionOptions>,
+        ) -> CompactionResponse;
+        fn txn(&mut self, txn: Txn) -> TxnResponse;
+    }
+}
+
+#[cfg(feature = "watch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+impl Client {
+    /// Blocking version of [`Client::watch`](crate::Client::watch).
+    ///
+    /// The responses are received by the returned iterator.
+    pub fn watch(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<WatchOptions>,
+    ) -> Result<(Watcher, WatchStream)> {
+        let (watcher, stream) = self.runtime.block_on(self.inner.watch(key, options))?;
+        let watcher = Watcher {
+            inner: watcher,
+            runtime: self.runtime.clone(),
+        };
+        Ok((watcher, WatchStream(Forward::spawn(&self.runtime, stream))))
+    }
+}
+
+#[cfg(feature = "lease")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lease")))]
+impl Client {
+    blocking! { Client;
+        fn lease_grant(
+            &mut self,
+            ttl: i64,
+            options: Option<LeaseGrantOptions>,
+        ) -> LeaseGrantResponse;
+        fn lease_revoke(&mut self, id: impl Into<LeaseId>) -> LeaseRevokeResponse;
+        fn lease_time_to_live(
+            &mut self,
+            id: impl Into<LeaseId>,
+            options: Option<LeaseTimeToLiveOptions>,
+        ) -> LeaseTimeToLiveResponse;
+        fn leases(&mut self) -> LeaseLeasesResponse;
+    }
+
+    /// Blocking version of [`Client::lease_keep_alive`](crate::Client::lease_keep_alive).
+    ///
+    /// The responses are received by the returned iterator.
+    pub fn lease_keep_alive(
+        &mut self,
+        id: impl Into<LeaseId>,
+    ) -> Result<(LeaseKeeper, LeaseKeepAliveStream)> {
+        let (keeper, stream) = self.runtime.block_on(self.inner.lease_keep_alive(id))?;
+        let keeper = LeaseKeeper {
+            inner: keeper,
+            runtime: self.runtime.clone(),
+        };
+        Ok((
+            keeper,
+            LeaseKeepAliveStream(Forward::spawn(&self.runtime, stream)),
+        ))
+    }
+}
+
+#[cfg(feature = "lock")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lock")))]
+impl Client {
+    blocking! { Client;
+        fn lock(&mut self, name: impl Into<Vec<u8>>, options: Option<LockOptions>) -> LockResponse;
+        fn unlock(&mut self, key: impl Into<Vec<u8>>) -> UnlockResponse;
+    }
+}
+
+#[cfg(feature = "auth")]
+#[cfg_attr(docsrs, doc(cfg(feature = "auth")))]
+impl Client {
+    blocking! { Client;
+        fn auth_enable(&mut self) -> AuthEnableResponse;
+        fn auth_disable(&mut self) -> AuthDisableResponse;
+        fn role_add(&mut self, name: impl Into<String>) -> RoleAddResponse;
+        fn role_delete(&mut self, name: impl Into<String>) -> RoleDeleteResponse;
+        fn role_get(&mut self, name: impl Into<String>) -> RoleGetResponse;
+        fn role_list(&mut self) -> RoleListResponse;
+        fn role_grant_permission(
+            &mut self,
+            name: impl Into<String>,
+            perm: Permission,
+        ) -> RoleGrantPermissionResponse;
+        fn role_revoke_permission(
+            &mut self,
+            name: impl Into<String>,
+            key: impl Into<Vec<u8>>,
+            options: Option<RoleRevokePermissionOptions>,
+        ) -> RoleRevokePermissionResponse;
+        fn user_add(
+            &mut self,
+            name: impl Into<String>,
+            password: impl Into<String>,
+            options: Option<UserAddOptions>,
+        ) -> UserAddResponse;
+        fn user_get(&mut self, name: impl Into<String>) -> UserGetResponse;
+        fn user_list(&mut self) -> UserListResponse;
+        fn user_delete(&mut self, name: impl Into<String>) -> UserDeleteResponse;
+        fn user_change_password(
+            &mut self,
+            name: impl Into<String>,
+            password: impl Into<String>,
+        ) -> UserChangePasswordResponse;
+        fn user_grant_role(
+            &mut self,
+            user: impl Into<String>,
+            role: impl Into<String>,
+        ) -> UserGrantRoleResponse;
+        fn user_revoke_role(
+            &mut self,
+            user: impl Into<String>,
+            role: impl Into<String>,
+        ) -> UserRevokeRoleResponse;
+        fn set_client_auth(&mut self, name: String, password: String) -> ();
+    }
+
+    /// Removes client-side authentication.
+    #[inline]
+    pub fn remove_client_auth(&mut self) {
+        self.inner.remove_client_auth()
+    }
+}
+
+#[cfg(feature = "maintenance")]
+#[cfg_attr(docsrs, doc(cfg(feature = "maintenance")))]
+impl Client {
+    blocking! { Client;
+        fn alarm(
+            &mut self,
+            alarm_action: AlarmAction,
+            alarm_type: AlarmType,
+            options: Option<AlarmOptions>,
+        ) -> AlarmResponse;
+        fn alarm_list(&mut self) -> AlarmResponse;
+        fn alarm_disarm(&mut self, member_id: u64, alarm_type: AlarmType) -> AlarmResponse;
+        fn alarm_disarm_all(&mut self) -> Vec<AlarmMember>;
+        fn recover_nospace(&mut self, revision: impl Into<Revision>) -> Vec<AlarmMember>;
+        fn status(&mut self) -> StatusResponse;
+        fn status_all(&mut self) -> Vec<(Uri, Result<StatusResponse>)>;
+        fn cluster_health(&mut self) -> ClusterHealth;
+        fn cluster_leader(&mut self) -> Option<(u64, Uri, u64)>;
+        fn raft_term(&mut self) -> u64;
+        fn defragment(&mut self) -> DefragmentResponse;
+        fn defragment_all(&mut self, options: Option<DefragOptions>) -> Vec<MemberDefragmentResult>;
+        fn hash(&mut self) -> HashResponse;
+        fn hash_kv(&mut self, revision: impl Into<Revision>) -> HashKvResponse;
+        fn hash_kv_all(&mut self, revision: impl Into<Revision>) -> ConsistencyReport;
+        fn snapshot_to_file(
+            &mut self,
+            path: impl AsRef<Path>,
+            options: Option<SnapshotOptions>,
+        ) -> SnapshotSummary;
+        fn verify_snapshot(
+            &mut self,
+            summary: &SnapshotSummary,
+            options: Option<VerifySnapshotOptions>,
+        ) -> SnapshotVerification;
+        fn move_leader(&mut self, target_id: u64) -> MoveLeaderResponse;
+        fn downgrade_validate(&mut self, target_version: impl Into<String>) -> DowngradeResponse;
+        fn downgrade_enable(&mut self, target_version: impl Into<String>) -> DowngradeResponse;
+        fn downgrade_cancel(&mut self) -> DowngradeResponse;
+    }
+}
+
+#[cfg(feature = "cluster")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cluster")))]
+impl Client {
+    /// Blocking version of [`Client::member_add`](crate::Client::member_add).
+    #[inline]
+    pub fn member_add<E: AsRef<str>, S: AsRef<[E]>>(
+        &mut self,
+        urls: S,
+        options: Option<MemberAddOptions>,
+    ) -> Result<MemberAddResponse> {
+        self.runtime.block_on(self.inner.member_add(urls, options))
+    }
+
+    blocking! { Client;
+        fn member_remove(&mut self, id: u64) -> MemberRemoveResponse;
+        fn member_update(&mut self, id: u64, url: impl Into<Vec<String>>) -> MemberUpdateResponse;
+        fn member_promote(&mut self, id: u64) -> MemberPromoteResponse;
+        fn add_and_promote(
+            &mut self,
+            urls: impl Into<Vec<String>>,
+            options: Option<PromoteOptions>,
+        ) -> MemberPromoteResponse;
+        fn member_list(&mut self) -> MemberListResponse;
+        fn member_list_with_options(
+            &mut self,
+            options: Option<MemberListOptions>,
+        ) -> MemberListResponse;
+        fn members_cached(&mut self, max_age: Duration) -> MemberListResponse;
+    }
+
+    /// Drops the cached member list, so that the next `members_cached` call refreshes it.
+    #[inline]
+    pub fn invalidate_members_cache(&self) {
+        self.inner.invalidate_members_cache()
+    }
+}
+
+/// The watching handle, see [`Watcher`](crate::Watcher).
+#[cfg(feature = "watch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+#[derive(Debug)]
+pub struct Watcher {
+    inner: crate::Watcher,
+    runtime: Arc<Runtime>,
+}
+
+#[cfg(feature = "watch")]
+impl Watcher {
+    /// The ID of the watcher.
+    #[inline]
+    pub const fn watch_id(&self) -> i64 {
+        self.inner.watch_id()
+    }
+
+    blocking! { Watcher;
+        fn watch(&mut self, key: impl Into<Vec<u8>>, options: Option<WatchOptions>) -> ();
+        fn cancel(&mut self) -> ();
+        fn cancel_by_id(&mut self, watch_id: i64) -> ();
+        fn request_progress(&mut self) -> ();
+    }
+}
+
+/// The lease keep alive handle, see [`LeaseKeeper`](crate::LeaseKeeper).
+#[cfg(feature = "lease")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lease")))]
+#[derive(Debug)]
+pub struct LeaseKeeper {
+    inner: crate::LeaseKeeper,
+    runtime: Arc<Runtime>,
+}
+
+#[cfg(feature = "lease")]
+impl LeaseKeeper {
+    /// The lease id which user want to keep alive.
+    #[inline]
+    pub const fn id(&self) -> LeaseId {
+        self.inner.id()
+    }
+
+    blocking! { LeaseKeeper;
+        fn keep_alive(&mut self) -> ();
+    }
+}
+
+/// The watch response iterator, which ends once the watch stream is closed.
+///
+/// Iterating from within an async runtime yields [`Error::BlockingInRuntime`].
+#[cfg(feature = "watch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+#[derive(Debug)]
+pub struct WatchStream(Forward<WatchResponse>);
+
+#[cfg(feature = "watch")]
+impl Iterator for WatchStream {
+    type Item = Result<WatchResponse>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// The lease keep alive response iterator, which ends once the keep alive stream is closed.
+///
+/// Iterating from within an async runtime yields [`Error::BlockingInRuntime`].
+#[cfg(feature = "lease")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lease")))]
+#[derive(Debug)]
+pub struct LeaseKeepAliveStream(Forward<LeaseKeepAliveResponse>);
+
+#[cfg(feature = "lease")]
+impl Iterator for LeaseKeepAliveStream {
+    type Item = Result<LeaseKeepAliveResponse>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// The items of a stream forwarded by a task of the runtime.
+#[derive(Debug)]
+struct Forward<T> {
+    rx: mpsc::Receiver<Result<T>>,
+    /// Keeps the runtime running the task.
+    _runtime: Arc<Runtime>,
+}
+
+impl<T: Send + 'static> Forward<T> {
+    /// Spawns the task forwarding `stream` on `runtime`, up to its first error.
+    fn spawn<S>(runtime: &Arc<Runtime>, mut stream: S) -> Self
+    where
+        S: Stream<Item = Result<T>> + Send + Unpin + 'static,
+    {
+        let (tx, rx) = mpsc::channel(FORWARD_BUFFER);
+        runtime.handle.spawn(async move {
+            loop {
+                let item = tokio::select! {
+                    // Dropping the stream closes it.
+                    _ = tx.closed() => return,
+                    item = stream.next() => item,
+                };
+                let Some(item) = item else {
+                    return;
+                };
+                let failed = item.is_err();
+                if tx.send(item).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Self {
+            rx,
+            _runtime: runtime.clone(),
+        }
+    }
+
+    /// Blocks the current thread until the next item is forwarded.
+    fn next(&mut self) -> Option<Result<T>> {
+        if let Err(e) = Runtime::check() {
+            return Some(Err(e));
+        }
+        self.rx.blocking_recv()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "kv")]
+    #[test]
+    fn test_in_runtime() {
+        // Connections are established lazily.
+        let mut client = Client::connect(["http://127.0.0.1:1"], None).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let err = client.put("foo", "bar", None).unwrap_err();
+            assert!(matches!(err, Error::BlockingInRuntime), "{err:?}");
+            assert!(matches!(
+                Client::connect(["http://127.0.0.1:1"], None),
+                Err(Error::BlockingInRuntime)
+            ));
+            // Dropping the runtime of the client does not block the thread.
+            drop(client);
+        });
+    }
+
+    #[test]
+    fn test_connect_with_handle() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let client =
+            Client::connect_with_handle(runtime.handle().clone(), ["http://127.0.0.1:1"], None)
+                .unwrap();
+        client.shutdown(Duration::from_secs(1));
+        // The borrowed runtime is left running.
+        assert_eq!(runtime.block_on(async { 1 }), 1);
+    }
+
+    #[test]
+    fn test_forward() {
+        let runtime = Arc::new(Runtime::new().unwrap());
+        let items = vec![Ok(1), Ok(2), Err(Error::WatchError("reset".into())), Ok(3)];
+        let mut forward = Forward::spawn(&runtime, tokio_stream::iter(items));
+        assert_eq!(forward.next().unwrap().unwrap(), 1);
+        assert_eq!(forward.next().unwrap().unwrap(), 2);
+        // The stream is closed by its first error.
+        assert!(matches!(forward.next(), Some(Err(Error::WatchError(_)))));
+        assert!(forward.next().is_none());
+
+        let (tx, rx) = mpsc::channel::<Result<i32>>(1);
+        let mut forward = Forward::spawn(&runtime, tokio_stream::wrappers::ReceiverStream::new(rx));
+        tx.blocking_send(Ok(1)).unwrap();
+        assert_eq!(forward.next().unwrap().unwrap(), 1);
+        // Dropping the iterator drops the stream.
+        drop(forward);
+        runtime.handle.block_on(tx.closed());
+    }
+}
@@ -0,0 +1,105 @@
+//! Readable formatting of keys and values.
+
+use std::fmt::{self, Debug, Formatter, Write};
+
+/// The number of leading bytes of keys and values printed by the `Debug` implementations of
+/// key-values, and of the events and responses holding them.
+///
+/// The precision of the format sets another number, e.g. `{:.1024?}` prints up to 1024
+/// bytes of every key and value, `{:#.16?}` up to 16 bytes pretty-printed.
+pub const DEBUG_BYTES_LIMIT: usize = 256;
+
+/// Formats bytes for `Debug`, as a string if they are printable UTF-8, or as a byte string
+/// escaping the other bytes in hex otherwise, e.g. `"foo"` or `b"\x00\x01foo"`.
+///
+/// Only the leading [`DEBUG_BYTES_LIMIT`] bytes, or the precision of the format, are
+/// printed, followed by `...` and the length of the whole if the bytes are longer.
+pub(crate) struct DebugBytes<'a>(pub(crate) &'a [u8]);
+
+impl Debug for DebugBytes<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let limit = f.precision().unwrap_or(DEBUG_BYTES_LIMIT);
+        let truncated = self.0.len() > limit;
+        let mut shown = &self.0[..self.0.len().min(limit)];
+        let text = match std::str::from_utf8(shown) {
+            Ok(text) => Some(text),
+            // A character cut by the truncation, the bytes before it may still be printable.
+            Err(e) if truncated && e.error_len().is_none() => {
+                shown = &shown[..e.valid_up_to()];
+                std::str::from_utf8(shown).ok()
+            }
+            Err(_) => None,
+        };
+
+        match text {
+            Some(text) if text.chars().all(|c| !c.is_control() || c.is_whitespace()) => {
+                write!(f, "{:?}", text)?;
+            }
+            _ => {
+                f.write_str("b\"")?;
+                for &byte in shown {
+                    for c in std::ascii::escape_default(byte) {
+                        f.write_char(char::from(c))?;
+                    }
+                }
+                f.write_char('"')?;
+            }
+        }
+        if truncated {
+            write!(f, "... ({} bytes)", self.0.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// The lowercase hex encoding of `bytes`.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        // Writing to a string never fails.
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_bytes() {
+        assert_eq!(format!("{:?}", DebugBytes(b"foo")), r#""foo""#);
+        assert_eq!(format!("{:?}", DebugBytes(b"a\"b\n")), r#""a\"b\n""#);
+        assert_eq!(format!("{:?}", DebugBytes("clé".as_bytes())), r#""clé""#);
+        assert_eq!(format!("{:?}", DebugBytes(b"")), r#""""#);
+        assert_eq!(
+            format!("{:?}", DebugBytes(b"\x00\x01foo\xff")),
+            r#"b"\x00\x01foo\xff""#
+        );
+
+        let long = "a".repeat(DEBUG_BYTES_LIMIT + 1);
+        assert_eq!(
+            format!("{:?}", DebugBytes(long.as_bytes())),
+            format!("{:?}... (257 bytes)", &long[..DEBUG_BYTES_LIMIT])
+        );
+        assert_eq!(
+            format!("{:.2?}", DebugBytes(b"\x00\x01\x02")),
+            r#"b"\x00\x01"... (3 bytes)"#
+        );
+        assert_eq!(
+            format!("{:.8?}", DebugBytes(long.as_bytes())),
+            r#""aaaaaaaa"... (257 bytes)"#
+        );
+        // The truncation cuts the `é`, the rest is still printed as a string.
+        assert_eq!(
+            format!("{:.3?}", DebugBytes("clé".as_bytes())),
+            r#""cl"... (4 bytes)"#
+        );
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex(b""), "");
+        assert_eq!(hex(b"\x00\x0fK\xff"), "000f4bff");
+    }
+}
@@ -1,9 +1,22 @@
 use std::{future::Future, pin::Pin, task::ready};
 
+use crate::circuit_breaker::{CircuitBreakerLayer, CircuitBreakerOptions};
+use crate::lock::RwLockExt;
+use crate::observe::Observer;
+use crate::task::{Task, Tasks};
 use http::Uri;
-use tokio::sync::mpsc::Sender;
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tokio::sync::mpsc::{Permit, Sender};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
 use tonic::transport::Endpoint;
-use tower::{util::BoxCloneService, Service};
+use tower::balance::p2c::Balance;
+use tower::buffer::Buffer;
+use tower::{util::BoxCloneService, Layer, Service};
 
 /// A change in the service set.
 #[derive(Debug, Clone)]
@@ -14,10 +27,444 @@ pub enum Change<K, V> {
     Remove(K),
 }
 
-/// A type alias to make the below types easier to represent.
-pub type EndpointUpdater = Sender<Change<Uri, Endpoint>>;
+/// The name of the task forwarding endpoint changes to the balancer, for the builders which
+/// need one.
+pub(crate) const BRIDGE_TASK: &str = "balanced channel bridge";
+
+/// The name of the task marking an [`EndpointUpdater`] gone once its balanced channel is
+/// closed.
+pub(crate) const WATCHDOG_TASK: &str = "balanced channel watchdog";
+
+impl<K, V> Change<K, V> {
+    /// The change of tonic's balanced channel.
+    #[inline]
+    fn into_tonic(self) -> tonic::transport::channel::Change<K, V> {
+        match self {
+            Change::Insert(k, v) => tonic::transport::channel::Change::Insert(k, v),
+            Change::Remove(k) => tonic::transport::channel::Change::Remove(k),
+        }
+    }
+
+    /// The change of a `tower` balancer.
+    #[inline]
+    fn into_discover(self) -> tower::discover::Change<K, V> {
+        match self {
+            Change::Insert(k, v) => tower::discover::Change::Insert(k, v),
+            Change::Remove(k) => tower::discover::Change::Remove(k),
+        }
+    }
+}
+
+/// The sender of the endpoint changes of a balanced channel, like a [`Sender`].
+///
+/// The changes are translated to the ones of the balancer as they are sent, so the capacity
+/// and the closing of the sender are the ones of the balancer's channel.
+///
+/// With priority lanes, see
+/// [`ConnectOptions::with_priority_lanes`](crate::ConnectOptions::with_priority_lanes), the
+/// changes are sent to the balanced channel of the bulk lane too, which connects to the
+/// same endpoints.
+#[derive(Clone)]
+pub struct EndpointUpdater {
+    sender: ChangeSender,
+    /// The changes of the balanced channel of the bulk lane, if any.
+    bulk: Option<ChangeSender>,
+    observer: Observer,
+    /// The endpoints inserted and not removed since, shared by the clones.
+    endpoints: Arc<RwLock<Vec<Uri>>>,
+    /// Set by the watchdog once the balanced channel is closed, shared by the clones.
+    gone: Arc<AtomicBool>,
+}
+
+/// Whether a change could not be sent as the channel was full or closed, see
+/// [`UpdateError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateErrorKind {
+    /// The channel had no capacity for the change, in time for
+    /// [`EndpointUpdater::update_timeout`].
+    Full,
+    /// The balanced channel is closed, changes can no longer be sent.
+    Closed,
+}
+
+/// The error of [`EndpointUpdater::try_update`] and [`EndpointUpdater::update_timeout`],
+/// with the change not sent.
+#[derive(Debug)]
+pub struct UpdateError {
+    /// Boxed, as an endpoint is large.
+    change: Box<Change<Uri, Endpoint>>,
+    kind: UpdateErrorKind,
+    pending: usize,
+}
+
+impl UpdateError {
+    /// Whether the channel was full or closed.
+    #[inline]
+    pub const fn kind(&self) -> UpdateErrorKind {
+        self.kind
+    }
+
+    /// Returns `true` if the channel was full.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.kind == UpdateErrorKind::Full
+    }
+
+    /// Returns `true` if the balanced channel is closed.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.kind == UpdateErrorKind::Closed
+    }
+
+    /// The number of changes sent and not yet taken by the balancer when the change failed.
+    #[inline]
+    pub const fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// The change not sent.
+    #[inline]
+    pub fn change(&self) -> &Change<Uri, Endpoint> {
+        &self.change
+    }
+
+    /// Takes the change not sent, e.g. to send it again.
+    #[inline]
+    pub fn into_change(self) -> Change<Uri, Endpoint> {
+        *self.change
+    }
+}
+
+impl Display for UpdateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            UpdateErrorKind::Full => {
+                write!(f, "endpoint changes full, {} changes pending", self.pending)
+            }
+            UpdateErrorKind::Closed => write!(
+                f,
+                "balanced channel closed, {} changes pending",
+                self.pending
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {}
+
+/// The channel of the changes of a balancer.
+#[derive(Clone)]
+enum ChangeSender {
+    /// The changes of tonic's balanced channel.
+    Tonic(Sender<tonic::transport::channel::Change<Uri, Endpoint>>),
+    /// The changes of a balancer discovering them from a `tower` stream.
+    Discover(Sender<tower::discover::Change<Uri, Endpoint>>),
+    /// The changes as they are, to a builder translating them itself.
+    Forward(Sender<Change<Uri, Endpoint>>),
+}
+
+/// The capacity reserved for a change in a [`ChangeSender`].
+enum ChangePermit<'a> {
+    Tonic(Permit<'a, tonic::transport::channel::Change<Uri, Endpoint>>),
+    Discover(Permit<'a, tower::discover::Change<Uri, Endpoint>>),
+    Forward(Permit<'a, Change<Uri, Endpoint>>),
+}
+
+impl ChangeSender {
+    /// Waits for capacity for a change, `None` if the balancer is closed.
+    async fn reserve(&self) -> Option<ChangePermit<'_>> {
+        match self {
+            ChangeSender::Tonic(tx) => tx.reserve().await.ok().map(ChangePermit::Tonic),
+            ChangeSender::Discover(tx) => tx.reserve().await.ok().map(ChangePermit::Discover),
+            ChangeSender::Forward(tx) => tx.reserve().await.ok().map(ChangePermit::Forward),
+        }
+    }
+
+    /// Reserves capacity for a change if there is some.
+    fn try_reserve(&self) -> Result<ChangePermit<'_>, TrySendError<()>> {
+        match self {
+            ChangeSender::Tonic(tx) => tx.try_reserve().map(ChangePermit::Tonic),
+            ChangeSender::Discover(tx) => tx.try_reserve().map(ChangePermit::Discover),
+            ChangeSender::Forward(tx) => tx.try_reserve().map(ChangePermit::Forward),
+        }
+    }
+
+    #[inline]
+    fn is_closed(&self) -> bool {
+        match self {
+            ChangeSender::Tonic(tx) => tx.is_closed(),
+            ChangeSender::Discover(tx) => tx.is_closed(),
+            ChangeSender::Forward(tx) => tx.is_closed(),
+        }
+    }
+
+    #[inline]
+    fn capacity(&self) -> usize {
+        match self {
+            ChangeSender::Tonic(tx) => tx.capacity(),
+            ChangeSender::Discover(tx) => tx.capacity(),
+            ChangeSender::Forward(tx) => tx.capacity(),
+        }
+    }
+
+    /// The number of changes sent and not yet received.
+    #[inline]
+    fn pending(&self) -> usize {
+        let max_capacity = match self {
+            ChangeSender::Tonic(tx) => tx.max_capacity(),
+            ChangeSender::Discover(tx) => tx.max_capacity(),
+            ChangeSender::Forward(tx) => tx.max_capacity(),
+        };
+        max_capacity - self.capacity()
+    }
+
+    /// Waits for the balancer to be closed.
+    async fn closed(&self) {
+        match self {
+            ChangeSender::Tonic(tx) => tx.closed().await,
+            ChangeSender::Discover(tx) => tx.closed().await,
+            ChangeSender::Forward(tx) => tx.closed().await,
+        }
+    }
+}
+
+impl ChangePermit<'_> {
+    /// Sends `change`, translated to the change of the balancer.
+    #[inline]
+    fn send(self, change: Change<Uri, Endpoint>) {
+        match self {
+            ChangePermit::Tonic(permit) => permit.send(change.into_tonic()),
+            ChangePermit::Discover(permit) => permit.send(change.into_discover()),
+            ChangePermit::Forward(permit) => permit.send(change),
+        }
+    }
+}
+
+impl EndpointUpdater {
+    /// Creates an updater sending to `sender`, observed by the current observer.
+    #[inline]
+    fn new(sender: ChangeSender) -> Self {
+        Self {
+            sender,
+            bulk: None,
+            observer: Observer::current(),
+            endpoints: Arc::default(),
+            gone: Arc::default(),
+        }
+    }
+
+    /// Sends the changes to the balanced channel of the bulk lane, updated by `bulk`, too.
+    #[inline]
+    pub(crate) fn with_bulk_lane(mut self, bulk: EndpointUpdater) -> Self {
+        self.bulk = Some(bulk.sender);
+        self
+    }
+
+    /// The endpoints sent to the balanced channel, in the order they were inserted.
+    #[inline]
+    pub(crate) fn endpoints(&self) -> Vec<Uri> {
+        self.endpoints.read_unpoisoned().clone()
+    }
+
+    /// Spawns the watchdog marking the updater and its clones gone once the balanced
+    /// channel is closed, e.g. by the death of the task forwarding the changes to it.
+    ///
+    /// The watchdog holds a clone of the updater, the task must be aborted along with the
+    /// client for the bridge of a builder to see the changes end.
+    pub(crate) fn spawn_watchdog(&self, tasks: &Tasks) -> Task {
+        let updater = self.clone();
+        tasks.spawn(WATCHDOG_TASK, async move {
+            match &updater.bulk {
+                Some(bulk) => {
+                    tokio::select! {
+                        _ = updater.sender.closed() => {}
+                        _ = bulk.closed() => {}
+                    }
+                }
+                None => updater.sender.closed().await,
+            }
+            updater.gone.store(true, Ordering::Release);
+            tracing::warn!("etcd client balanced channel closed");
+        })
+    }
+
+    /// Returns `true` if the watchdog of the client found the balanced channel closed.
+    #[inline]
+    pub(crate) fn is_gone(&self) -> bool {
+        self.gone.load(Ordering::Acquire)
+    }
+
+    /// Waits for capacity for a change in every lane, `None` if a balancer is closed.
+    async fn reserve(&self) -> Option<(ChangePermit<'_>, Option<ChangePermit<'_>>)> {
+        let permit = self.sender.reserve().await?;
+        let bulk = match &self.bulk {
+            Some(bulk) => Some(bulk.reserve().await?),
+            None => None,
+        };
+        Some((permit, bulk))
+    }
+
+    /// Sends `change`, waiting for capacity if the channel is full.
+    ///
+    /// Fails with the change if the balanced channel is closed.
+    pub async fn send(
+        &self,
+        change: Change<Uri, Endpoint>,
+    ) -> Result<(), SendError<Change<Uri, Endpoint>>> {
+        let Some((permit, bulk)) = self.reserve().await else {
+            return Err(SendError(change));
+        };
+        self.send_reserved(permit, bulk, change);
+        Ok(())
+    }
+
+    /// Sends `change` if the channel has capacity for it, without waiting.
+    ///
+    /// Like [`EndpointUpdater::try_send`], but fails with an [`UpdateError`] telling whether
+    /// the channel was full or closed, and how many changes were pending.
+    pub fn try_update(&self, change: Change<Uri, Endpoint>) -> Result<(), UpdateError> {
+        if self.is_gone() {
+            return Err(self.update_error(change, UpdateErrorKind::Closed));
+        }
+        match self.try_send(change) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(change)) => {
+                Err(self.update_error(change, UpdateErrorKind::Full))
+            }
+            Err(TrySendError::Closed(change)) => {
+                Err(self.update_error(change, UpdateErrorKind::Closed))
+            }
+        }
+    }
+
+    /// Sends `change`, waiting for capacity for at most `timeout` if the channel is full.
+    ///
+    /// Fails with an [`UpdateError`] of kind [`UpdateErrorKind::Full`] if there is still no
+    /// capacity after `timeout`, e.g. as the balancer does not take the changes while it is
+    /// not used, and of kind [`UpdateErrorKind::Closed`] if the balanced channel is closed.
+    pub async fn update_timeout(
+        &self,
+        change: Change<Uri, Endpoint>,
+        timeout: Duration,
+    ) -> Result<(), UpdateError> {
+        if self.is_gone() {
+            return Err(self.update_error(change, UpdateErrorKind::Closed));
+        }
+        match tokio::time::timeout(timeout, self.reserve()).await {
+            Ok(Some((permit, bulk))) => {
+                self.send_reserved(permit, bulk, change);
+                Ok(())
+            }
+            Ok(None) => Err(self.update_error(change, UpdateErrorKind::Closed)),
+            Err(_) => Err(self.update_error(change, UpdateErrorKind::Full)),
+        }
+    }
+
+    /// Sends `change` if the channel has capacity for it.
+    ///
+    /// Fails with the change if the channel is full or the balanced channel is closed.
+    #[allow(clippy::result_large_err)]
+    pub fn try_send(
+        &self,
+        change: Change<Uri, Endpoint>,
+    ) -> Result<(), TrySendError<Change<Uri, Endpoint>>> {
+        let reserved = self.sender.try_reserve().and_then(|permit| {
+            let bulk = self
+                .bulk
+                .as_ref()
+                .map(ChangeSender::try_reserve)
+                .transpose()?;
+            Ok((permit, bulk))
+        });
+        match reserved {
+            Ok((permit, bulk)) => {
+                self.send_reserved(permit, bulk, change);
+                Ok(())
+            }
+            Err(TrySendError::Full(())) => Err(TrySendError::Full(change)),
+            Err(TrySendError::Closed(())) => Err(TrySendError::Closed(change)),
+        }
+    }
+
+    /// Returns `true` if the balanced channel is closed, changes can no longer be sent.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.is_gone()
+            || self.sender.is_closed()
+            || self.bulk.as_ref().is_some_and(ChangeSender::is_closed)
+    }
+
+    /// The number of changes sent and not yet taken by the balancer, the most of the lanes.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        let pending = self.sender.pending();
+        match &self.bulk {
+            Some(bulk) => pending.max(bulk.pending()),
+            None => pending,
+        }
+    }
+
+    /// The number of changes which can be sent without waiting.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        let capacity = self.sender.capacity();
+        match &self.bulk {
+            Some(bulk) => capacity.min(bulk.capacity()),
+            None => capacity,
+        }
+    }
+
+    /// The error of `change` not sent, with the changes pending.
+    #[inline]
+    fn update_error(&self, change: Change<Uri, Endpoint>, kind: UpdateErrorKind) -> UpdateError {
+        UpdateError {
+            change: Box::new(change),
+            kind,
+            pending: self.pending(),
+        }
+    }
+
+    /// Observes, records and sends `change` with the capacity reserved for it.
+    fn send_reserved(
+        &self,
+        permit: ChangePermit<'_>,
+        bulk: Option<ChangePermit<'_>>,
+        change: Change<Uri, Endpoint>,
+    ) {
+        let change = self.observed(change);
+        if let Some(bulk) = bulk {
+            bulk.send(change.clone());
+        }
+        permit.send(change);
+    }
+
+    /// Observes and records `change`, about to be sent.
+    fn observed(&self, change: Change<Uri, Endpoint>) -> Change<Uri, Endpoint> {
+        self.observer.endpoint_changed(&change);
+        let mut endpoints = self.endpoints.write_unpoisoned();
+        match &change {
+            Change::Insert(uri, _) if !endpoints.contains(uri) => endpoints.push(uri.clone()),
+            Change::Insert(..) => {}
+            Change::Remove(uri) => endpoints.retain(|u| u != uri),
+        }
+        change
+    }
+}
+
+impl From<Sender<Change<Uri, Endpoint>>> for EndpointUpdater {
+    /// Creates an updater sending the changes as they are, for a builder translating them
+    /// itself, e.g. by a task spawned as [`BRIDGE_TASK`].
+    #[inline]
+    fn from(sender: Sender<Change<Uri, Endpoint>>) -> Self {
+        Self::new(ChangeSender::Forward(sender))
+    }
+}
 
 /// Creates a balanced channel.
+///
+/// A builder used to return the `Sender<Change<Uri, Endpoint>>` of its channel, it now
+/// returns an [`EndpointUpdater`]: wrap the sender with [`EndpointUpdater::from`] to keep
+/// sending the changes to it as they are.
 pub trait BalancedChannelBuilder {
     type Error;
 
@@ -40,22 +487,48 @@ impl BalancedChannelBuilder for Tonic {
         buffer_size: usize,
     ) -> Result<(Channel, EndpointUpdater), Self::Error> {
         let (chan, tx) = tonic::transport::Channel::balance_channel(buffer_size);
+        Ok((
+            Channel::Tonic(chan),
+            EndpointUpdater::new(ChangeSender::Tonic(tx)),
+        ))
+    }
+}
 
-        let (bridge_tx, mut rx) = tokio::sync::mpsc::channel(buffer_size);
-        tokio::spawn(async move {
-            while let Some(change) = rx.recv().await {
-                let change = match change {
-                    Change::Insert(k, v) => tonic::transport::channel::Change::Insert(k, v),
-                    Change::Remove(k) => tonic::transport::channel::Change::Remove(k),
-                };
+/// Create a Tonic channel balancing over endpoints wrapped in circuit breakers.
+///
+/// Tonic's own balanced channel only accepts endpoints, so the balancer is built here.
+pub struct CircuitBreaking {
+    pub(crate) options: CircuitBreakerOptions,
+}
 
-                if let Err(_) = tx.send(change).await {
-                    break;
-                }
-            }
-        });
+impl BalancedChannelBuilder for CircuitBreaking {
+    type Error = tonic::transport::Error;
+
+    #[inline]
+    fn balanced_channel(
+        self,
+        buffer_size: usize,
+    ) -> Result<(Channel, EndpointUpdater), Self::Error> {
+        let (tx, rx) = tokio::sync::mpsc::channel(buffer_size);
+        // The endpoints are wrapped as the balancer discovers them.
+        let discover =
+            ReceiverStream::new(rx).map(move |change: tower::discover::Change<Uri, Endpoint>| {
+                Ok::<_, tower::BoxError>(match change {
+                    tower::discover::Change::Insert(k, v) => {
+                        let layer = CircuitBreakerLayer::new(self.options.clone(), k.clone());
+                        tower::discover::Change::Insert(k, layer.layer(v.connect_lazy()))
+                    }
+                    tower::discover::Change::Remove(k) => tower::discover::Change::Remove(k),
+                })
+            });
 
-        Ok((Channel::Tonic(chan), bridge_tx))
+        let balance = Balance::new(discover);
+        // The buffer makes the balancer `Clone`.
+        let chan = Buffer::new(balance, 1024);
+        Ok((
+            Channel::Custom(BoxCloneService::new(chan)),
+            EndpointUpdater::new(ChangeSender::Discover(tx)),
+        ))
     }
 }
 
@@ -63,6 +536,7 @@ impl BalancedChannelBuilder for Tonic {
 #[cfg(feature = "tls-openssl")]
 pub struct Openssl {
     pub(crate) conn: crate::openssl_tls::OpenSslConnector,
+    pub(crate) circuit_breaker: Option<CircuitBreakerOptions>,
 }
 
 #[cfg(feature = "tls-openssl")]
@@ -74,27 +548,18 @@ impl BalancedChannelBuilder for Openssl {
         self,
         buffer_size: usize,
     ) -> Result<(Channel, EndpointUpdater), Self::Error> {
-        let (chan, tx) = crate::openssl_tls::balanced_channel(self.conn)?;
-        let (bridge_tx, mut rx) = tokio::sync::mpsc::channel(buffer_size);
-        tokio::spawn(async move {
-            while let Some(change) = rx.recv().await {
-                let change = match change {
-                    Change::Insert(k, v) => tower::discover::Change::Insert(k, v),
-                    Change::Remove(k) => tower::discover::Change::Remove(k),
-                };
-
-                if let Err(_) = tx.send(change).await {
-                    break;
-                }
-            }
-        });
-
-        Ok((Channel::Openssl(chan), bridge_tx))
+        let (chan, tx) =
+            crate::openssl_tls::balanced_channel(self.conn, self.circuit_breaker, buffer_size)?;
+        Ok((
+            Channel::Openssl(chan),
+            EndpointUpdater::new(ChangeSender::Discover(tx)),
+        ))
     }
 }
 
 type TonicRequest = http::Request<tonic::body::Body>;
 type TonicResponse = http::Response<tonic::body::Body>;
+/// A channel of any `tower` service, boxed, see [`Channel::Custom`].
 pub type CustomChannel = BoxCloneService<TonicRequest, TonicResponse, tower::BoxError>;
 
 /// Represents a channel that can be created by a BalancedChannelBuilder
@@ -112,6 +577,18 @@ pub enum Channel {
     Custom(CustomChannel),
 }
 
+impl Channel {
+    /// Boxes the channel into a [`CustomChannel`], e.g. to store it alongside other boxed
+    /// services, or to wrap it in the layers of another client.
+    #[inline]
+    pub fn into_boxed(self) -> CustomChannel {
+        match self {
+            Channel::Custom(custom) => custom,
+            channel => BoxCloneService::new(channel),
+        }
+    }
+}
+
 impl std::fmt::Debug for Channel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Channel").finish_non_exhaustive()
@@ -207,3 +684,265 @@ impl Service<TonicRequest> for Channel {
         }
     }
 }
+
+/// The buffer of a [`CustomChannel`], `Sync` as its worker owns the channel.
+type BufferedChannel = Buffer<TonicRequest, <CustomChannel as Service<TonicRequest>>::Future>;
+
+/// A [`Channel`] which is `Sync`, so the clients sending requests over it can be shared
+/// without a lock.
+///
+/// Tonic and OpenSSL channels are `Sync` already, a custom one is moved behind a [`Buffer`].
+#[doc(hidden)]
+#[derive(Clone)]
+pub enum SyncChannel {
+    Tonic(tonic::transport::Channel),
+    #[cfg(feature = "tls-openssl")]
+    Openssl(crate::openssl_tls::OpenSslChannel),
+    Buffered(BufferedChannel),
+}
+
+impl From<Channel> for SyncChannel {
+    /// Wraps `channel`, a custom one in a [`Buffer`], which must be done in a Tokio runtime.
+    #[inline]
+    fn from(channel: Channel) -> Self {
+        match channel {
+            Channel::Tonic(channel) => SyncChannel::Tonic(channel),
+            #[cfg(feature = "tls-openssl")]
+            Channel::Openssl(openssl) => SyncChannel::Openssl(openssl),
+            Channel::Custom(custom) => SyncChannel::Buffered(Buffer::new(custom, 1024)),
+        }
+    }
+}
+
+/// The future of a [`SyncChannel`].
+#[doc(hidden)]
+pub enum SyncChannelFuture {
+    Channel(ChannelFuture),
+    Buffered(<BufferedChannel as Service<TonicRequest>>::Future),
+}
+
+impl std::future::Future for SyncChannelFuture {
+    type Output = Result<TonicResponse, tower::BoxError>;
+
+    #[inline]
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        // Safety: trivial projection
+        unsafe {
+            match self.get_unchecked_mut() {
+                SyncChannelFuture::Channel(fut) => Future::poll(Pin::new_unchecked(fut), cx),
+                SyncChannelFuture::Buffered(fut) => Future::poll(Pin::new_unchecked(fut), cx),
+            }
+        }
+    }
+}
+
+impl Service<TonicRequest> for SyncChannel {
+    type Response = TonicResponse;
+    type Error = tower::BoxError;
+    type Future = SyncChannelFuture;
+
+    #[inline]
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        match self {
+            SyncChannel::Tonic(channel) => {
+                let result = ready!(channel.poll_ready(cx));
+                result.map_err(|e| Box::new(e) as tower::BoxError).into()
+            }
+            #[cfg(feature = "tls-openssl")]
+            SyncChannel::Openssl(openssl) => openssl.poll_ready(cx),
+            SyncChannel::Buffered(buffered) => buffered.poll_ready(cx),
+        }
+    }
+
+    #[inline]
+    fn call(&mut self, req: TonicRequest) -> Self::Future {
+        match self {
+            SyncChannel::Tonic(channel) => {
+                SyncChannelFuture::Channel(ChannelFuture::from_tonic(channel.call(req)))
+            }
+            #[cfg(feature = "tls-openssl")]
+            SyncChannel::Openssl(openssl) => {
+                SyncChannelFuture::Channel(ChannelFuture::from_openssl(openssl.call(req)))
+            }
+            SyncChannel::Buffered(buffered) => SyncChannelFuture::Buffered(buffered.call(req)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// The removal of the endpoint `i`.
+    fn remove(i: u16) -> Change<Uri, Endpoint> {
+        Change::Remove(format!("http://127.0.0.1:{}", 2379 + i).parse().unwrap())
+    }
+
+    /// Returns the removed endpoint of `change`.
+    fn removed(change: Change<Uri, Endpoint>) -> Uri {
+        match change {
+            Change::Remove(uri) => uri,
+            Change::Insert(uri, _) => panic!("unexpected insert of {}", uri),
+        }
+    }
+
+    /// The endpoint removed by [`remove`].
+    fn remove_uri(i: u16) -> Uri {
+        removed(remove(i))
+    }
+
+    /// Balanced channels of every builder, updated through channels of `buffer_size` changes.
+    fn balanced_channels(buffer_size: usize) -> Vec<(Channel, EndpointUpdater)> {
+        let circuit_breaking = CircuitBreaking {
+            options: CircuitBreakerOptions::new(),
+        };
+        #[allow(unused_mut)]
+        let mut channels = vec![
+            Tonic.balanced_channel(buffer_size).unwrap(),
+            circuit_breaking.balanced_channel(buffer_size).unwrap(),
+        ];
+        #[cfg(feature = "tls-openssl")]
+        channels.push(
+            Openssl {
+                conn: crate::openssl_tls::OpenSslConnector::create_default().unwrap(),
+                circuit_breaker: None,
+            }
+            .balanced_channel(buffer_size)
+            .unwrap(),
+        );
+        channels
+    }
+
+    #[tokio::test]
+    async fn test_updater_full() {
+        for (_channel, updater) in balanced_channels(2) {
+            assert_eq!(updater.capacity(), 2);
+            updater.try_send(remove(0)).unwrap();
+            updater.send(remove(1)).await.unwrap();
+
+            // The balancer only takes changes when used, the changes are kept until then.
+            match updater.try_send(remove(2)) {
+                Err(TrySendError::Full(change)) => assert_eq!(removed(change), remove_uri(2)),
+                other => panic!("unexpected result: {:?}", other),
+            }
+            let send = updater.send(remove(2));
+            assert!(tokio::time::timeout(Duration::from_millis(50), send)
+                .await
+                .is_err());
+            assert!(!updater.is_closed());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_updater_closed() {
+        for (channel, updater) in balanced_channels(2) {
+            let other = updater.clone();
+            drop(channel);
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while !updater.is_closed() {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("dropping the channel must close its updaters");
+
+            assert!(other.is_closed());
+            let err = updater.send(remove(0)).await.unwrap_err();
+            assert_eq!(removed(err.0), remove_uri(0));
+            match other.try_send(remove(1)) {
+                Err(TrySendError::Closed(change)) => assert_eq!(removed(change), remove_uri(1)),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_try_update() {
+        for (channel, updater) in balanced_channels(2) {
+            updater.try_update(remove(0)).unwrap();
+            let timeout = Duration::from_millis(50);
+            updater.update_timeout(remove(1), timeout).await.unwrap();
+            assert_eq!(updater.pending(), 2);
+
+            let err = updater.try_update(remove(2)).unwrap_err();
+            assert_eq!((err.kind(), err.pending()), (UpdateErrorKind::Full, 2));
+            assert_eq!(err.to_string(), "endpoint changes full, 2 changes pending");
+            let err = updater
+                .update_timeout(remove(3), timeout)
+                .await
+                .unwrap_err();
+            assert!(err.is_full());
+            assert_eq!(removed(err.into_change()), remove_uri(3));
+
+            drop(channel);
+            let err = updater
+                .update_timeout(remove(4), Duration::from_secs(5))
+                .await
+                .unwrap_err();
+            assert!(err.is_closed());
+            assert_eq!(removed(err.into_change()), remove_uri(4));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watchdog() {
+        let (tx, rx) = tokio::sync::mpsc::channel(2);
+        let updater = EndpointUpdater::from(tx);
+        let watchdog = updater.spawn_watchdog(&Tasks::default());
+        updater.try_update(remove(0)).unwrap();
+        assert!(!updater.is_gone());
+
+        // The bridge dies with a change pending.
+        drop(rx);
+        watchdog.join().await.unwrap();
+        assert!(updater.is_gone());
+        assert!(updater.clone().is_closed());
+        let err = updater.try_update(remove(1)).unwrap_err();
+        assert!(err.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_updater_bulk_lane() {
+        for (_channel, updater) in balanced_channels(4) {
+            let (bulk, mut changes) = tokio::sync::mpsc::channel(2);
+            let updater = updater.with_bulk_lane(EndpointUpdater::from(bulk));
+            assert_eq!(updater.capacity(), 2);
+            updater.send(remove(0)).await.unwrap();
+            updater.try_send(remove(1)).unwrap();
+            assert_eq!(removed(changes.recv().await.unwrap()), remove_uri(0));
+            assert_eq!(removed(changes.recv().await.unwrap()), remove_uri(1));
+
+            // Sent to both lanes or to none.
+            updater.try_send(remove(2)).unwrap();
+            updater.try_send(remove(3)).unwrap();
+            match updater.try_send(remove(4)) {
+                Err(TrySendError::Full(change)) => assert_eq!(removed(change), remove_uri(4)),
+                other => panic!("unexpected result: {:?}", other),
+            }
+            assert_eq!(updater.capacity(), 0);
+
+            drop(changes);
+            assert!(updater.is_closed());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_updater_endpoints() {
+        for (_channel, updater) in balanced_channels(4) {
+            for i in [0, 1, 0] {
+                let uri = remove_uri(i);
+                let endpoint = Endpoint::from(uri.clone());
+                updater.send(Change::Insert(uri, endpoint)).await.unwrap();
+            }
+            updater.clone().send(remove(0)).await.unwrap();
+            assert_eq!(updater.endpoints(), vec![remove_uri(1)]);
+        }
+    }
+}
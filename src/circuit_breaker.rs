@@ -0,0 +1,506 @@
+//! Per-endpoint circuit breaker.
+//!
+//! A [`CircuitBreaker`] wraps the service of one endpoint before it enters the balance set.
+//! After a number of consecutive failures the circuit opens and the endpoint reports not-ready,
+//! so the balancer sends the requests to the other endpoints instead of failing them. Once the
+//! open duration elapsed, a few probe requests are let through and the circuit closes again
+//! if they all succeed.
+
+use crate::lock::MutexExt;
+use http::Uri;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll, Waker};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+use tower::load::Load;
+use tower::{Layer, Service};
+
+/// The state of the circuit of an endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CircuitState {
+    /// Requests flow to the endpoint.
+    Closed,
+    /// The endpoint failed too often and receives no requests.
+    Open,
+    /// The endpoint receives a few probe requests to find out whether it recovered.
+    HalfOpen,
+}
+
+type StateChangeHook = Arc<dyn Fn(&Uri, CircuitState) + Send + Sync>;
+
+/// Options for the circuit breaker of every endpoint,
+/// see [`ConnectOptions::with_circuit_breaker`](crate::ConnectOptions::with_circuit_breaker).
+#[derive(Clone)]
+pub struct CircuitBreakerOptions {
+    failure_threshold: u32,
+    open_duration: Duration,
+    half_open_probes: u32,
+    on_state_change: Option<StateChangeHook>,
+}
+
+impl CircuitBreakerOptions {
+    /// Creates a `CircuitBreakerOptions`, opening the circuit for 10s after 5 consecutive
+    /// failures and closing it after 1 successful probe.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(10),
+            half_open_probes: 1,
+            on_state_change: None,
+        }
+    }
+
+    /// Sets the number of consecutive failures opening the circuit.
+    #[inline]
+    pub const fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold;
+        self
+    }
+
+    /// Sets the duration the circuit stays open before probing the endpoint.
+    #[inline]
+    pub const fn with_open_duration(mut self, open_duration: Duration) -> Self {
+        self.open_duration = open_duration;
+        self
+    }
+
+    /// Sets the number of probe requests which must succeed to close the circuit.
+    #[inline]
+    pub const fn with_half_open_probes(mut self, half_open_probes: u32) -> Self {
+        self.half_open_probes = half_open_probes;
+        self
+    }
+
+    /// Sets a hook called with the endpoint and the new state on every state transition,
+    /// e.g. to record metrics.
+    #[inline]
+    pub fn with_on_state_change(
+        mut self,
+        hook: impl Fn(&Uri, CircuitState) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_state_change = Some(Arc::new(hook));
+        self
+    }
+
+    /// The settings of the circuit breaker which can not be honoured, e.g. a circuit
+    /// opening without any failure.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.failure_threshold == 0 {
+            problems.push(String::from("circuit breaker failure threshold is zero"));
+        }
+        if self.half_open_probes == 0 {
+            problems.push(String::from("circuit breaker half-open probes is zero"));
+        }
+        problems
+    }
+}
+
+impl Default for CircuitBreakerOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for CircuitBreakerOptions {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreakerOptions")
+            .field("failure_threshold", &self.failure_threshold)
+            .field("open_duration", &self.open_duration)
+            .field("half_open_probes", &self.half_open_probes)
+            .field("on_state_change", &self.on_state_change.is_some())
+            .finish()
+    }
+}
+
+/// Layer wrapping the service of the endpoint `uri` in a [`CircuitBreaker`].
+pub(crate) struct CircuitBreakerLayer {
+    options: CircuitBreakerOptions,
+    uri: Uri,
+}
+
+impl CircuitBreakerLayer {
+    /// Creates a layer for the endpoint `uri`.
+    #[inline]
+    pub(crate) const fn new(options: CircuitBreakerOptions, uri: Uri) -> Self {
+        Self { options, uri }
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreaker<S>;
+
+    #[inline]
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreaker {
+            inner,
+            handle: BreakerHandle {
+                options: self.options.clone(),
+                uri: self.uri.clone(),
+                state: Arc::new(Mutex::new(BreakerState::new())),
+            },
+            sleep: None,
+        }
+    }
+}
+
+/// Service of one endpoint which is not ready while its circuit is open.
+pub(crate) struct CircuitBreaker<S> {
+    inner: S,
+    handle: BreakerHandle,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+struct BreakerState {
+    circuit: CircuitState,
+    /// Consecutive failures while closed.
+    failures: u32,
+    opened_at: Instant,
+    /// Probes sent while half-open, and the ones which succeeded.
+    probes: u32,
+    successes: u32,
+    /// The task waiting for a probe to complete.
+    waker: Option<Waker>,
+}
+
+impl BreakerState {
+    fn new() -> Self {
+        Self {
+            circuit: CircuitState::Closed,
+            failures: 0,
+            opened_at: Instant::now(),
+            probes: 0,
+            successes: 0,
+            waker: None,
+        }
+    }
+
+    fn transition(&mut self, circuit: CircuitState) -> Option<CircuitState> {
+        if self.circuit == circuit {
+            return None;
+        }
+        self.circuit = circuit;
+        self.failures = 0;
+        self.probes = 0;
+        self.successes = 0;
+        if circuit == CircuitState::Open {
+            self.opened_at = Instant::now();
+        }
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        Some(circuit)
+    }
+}
+
+#[derive(Clone)]
+struct BreakerHandle {
+    options: CircuitBreakerOptions,
+    uri: Uri,
+    state: Arc<Mutex<BreakerState>>,
+}
+
+impl BreakerHandle {
+    /// Reports a transition outside of the lock.
+    fn report(&self, transition: Option<CircuitState>) {
+        if let (Some(circuit), Some(hook)) = (transition, &self.options.on_state_change) {
+            hook(&self.uri, circuit);
+        }
+    }
+
+    fn success(&self, probe: bool) {
+        let mut state = self.state.lock_unpoisoned();
+        let transition = match state.circuit {
+            CircuitState::Closed => {
+                state.failures = 0;
+                None
+            }
+            CircuitState::HalfOpen if probe => {
+                state.successes += 1;
+                if state.successes >= self.options.half_open_probes {
+                    state.transition(CircuitState::Closed)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        drop(state);
+        self.report(transition);
+    }
+
+    fn failure(&self) {
+        let mut state = self.state.lock_unpoisoned();
+        let transition = match state.circuit {
+            CircuitState::Closed => {
+                state.failures += 1;
+                if state.failures >= self.options.failure_threshold {
+                    state.transition(CircuitState::Open)
+                } else {
+                    None
+                }
+            }
+            CircuitState::HalfOpen => state.transition(CircuitState::Open),
+            CircuitState::Open => None,
+        };
+        drop(state);
+        self.report(transition);
+    }
+}
+
+impl<S> CircuitBreaker<S> {
+    /// The current state of the circuit.
+    #[cfg(test)]
+    fn circuit(&self) -> CircuitState {
+        self.handle.state.lock_unpoisoned().circuit
+    }
+}
+
+impl<S, B, RB> Service<http::Request<B>> for CircuitBreaker<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<RB>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BreakerFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        loop {
+            let mut state = self.handle.state.lock_unpoisoned();
+            match state.circuit {
+                CircuitState::Closed => break,
+                CircuitState::Open => {
+                    let until = state.opened_at + self.handle.options.open_duration;
+                    if Instant::now() >= until {
+                        let transition = state.transition(CircuitState::HalfOpen);
+                        drop(state);
+                        self.sleep = None;
+                        self.handle.report(transition);
+                        continue;
+                    }
+                    drop(state);
+                    let sleep = self
+                        .sleep
+                        .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(until)));
+                    if sleep.deadline() != until {
+                        sleep.as_mut().reset(until);
+                    }
+                    ready!(sleep.as_mut().poll(cx));
+                }
+                CircuitState::HalfOpen => {
+                    if state.probes < self.handle.options.half_open_probes {
+                        break;
+                    }
+                    // Wait for the probes to complete.
+                    state.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let probe = {
+            let mut state = self.handle.state.lock_unpoisoned();
+            let probe = state.circuit == CircuitState::HalfOpen;
+            if probe {
+                state.probes += 1;
+            }
+            probe
+        };
+        BreakerFuture {
+            inner: self.inner.call(req),
+            handle: self.handle.clone(),
+            probe,
+        }
+    }
+}
+
+impl<S> Load for CircuitBreaker<S> {
+    type Metric = u32;
+
+    /// Endpoints which recently failed are less preferred.
+    #[inline]
+    fn load(&self) -> Self::Metric {
+        self.handle.state.lock_unpoisoned().failures
+    }
+}
+
+/// The gRPC status code `UNAVAILABLE`, sent in the headers of a trailers-only response.
+const GRPC_STATUS_UNAVAILABLE: &str = "14";
+
+/// Response future recording the outcome of the request into the circuit.
+pub(crate) struct BreakerFuture<F> {
+    inner: F,
+    handle: BreakerHandle,
+    probe: bool,
+}
+
+impl<F, RB, E> Future for BreakerFuture<F>
+where
+    F: Future<Output = Result<http::Response<RB>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: trivial projection.
+        let (inner, handle, probe) = unsafe {
+            let this = self.get_unchecked_mut();
+            (
+                Pin::new_unchecked(&mut this.inner),
+                &this.handle,
+                this.probe,
+            )
+        };
+
+        let result = ready!(inner.poll(cx));
+        let failed = match &result {
+            Ok(resp) => resp
+                .headers()
+                .get("grpc-status")
+                .is_some_and(|status| status == GRPC_STATUS_UNAVAILABLE),
+            Err(_) => true,
+        };
+        if failed {
+            handle.failure();
+        } else {
+            handle.success(probe);
+        }
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tower::ServiceExt;
+
+    /// A mock endpoint failing its first `failures` requests, then recovering.
+    fn flapping(
+        failures: u32,
+    ) -> impl Service<http::Request<()>, Response = http::Response<()>, Error = &'static str> {
+        let calls = Arc::new(AtomicU32::new(0));
+        tower::service_fn(move |_req: http::Request<()>| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if call < failures {
+                    Err("connection refused")
+                } else {
+                    Ok(http::Response::new(()))
+                }
+            }
+        })
+    }
+
+    fn options(transitions: Arc<Mutex<Vec<CircuitState>>>) -> CircuitBreakerOptions {
+        CircuitBreakerOptions::new()
+            .with_failure_threshold(3)
+            .with_open_duration(Duration::from_millis(50))
+            .with_on_state_change(move |uri, state| {
+                assert_eq!(uri, "http://10.0.0.1:2379/");
+                transitions.lock_unpoisoned().push(state);
+            })
+    }
+
+    async fn is_ready<S: Service<http::Request<()>>>(service: &mut S) -> bool {
+        tokio::time::timeout(Duration::from_millis(10), service.ready())
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let layer = CircuitBreakerLayer::new(
+            options(transitions.clone()),
+            "http://10.0.0.1:2379".parse().unwrap(),
+        );
+        let mut service = layer.layer(flapping(4));
+
+        for _ in 0..3 {
+            let result = service.ready().await.unwrap().call(http::Request::new(()));
+            assert!(result.await.is_err());
+        }
+        assert_eq!(service.circuit(), CircuitState::Open);
+        assert!(!is_ready(&mut service).await);
+
+        // The probe fails as the endpoint has not recovered yet.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(is_ready(&mut service).await);
+        assert_eq!(service.circuit(), CircuitState::HalfOpen);
+        assert!(service.call(http::Request::new(())).await.is_err());
+        assert_eq!(service.circuit(), CircuitState::Open);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(is_ready(&mut service).await);
+        let probe = service.call(http::Request::new(()));
+        // Only one probe is let through at a time.
+        assert!(!is_ready(&mut service).await);
+        assert!(probe.await.is_ok());
+        assert_eq!(service.circuit(), CircuitState::Closed);
+        assert!(is_ready(&mut service).await);
+
+        assert_eq!(
+            *transitions.lock_unpoisoned(),
+            [
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Closed,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_unavailable_status() {
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let layer = CircuitBreakerLayer::new(
+            options(transitions).with_failure_threshold(2),
+            "http://10.0.0.1:2379".parse().unwrap(),
+        );
+        let mut service = layer.layer(tower::service_fn(|_req: http::Request<()>| async {
+            let mut resp = http::Response::new(());
+            resp.headers_mut()
+                .insert("grpc-status", GRPC_STATUS_UNAVAILABLE.parse().unwrap());
+            Ok::<_, &'static str>(resp)
+        }));
+
+        for _ in 0..2 {
+            let result = service.ready().await.unwrap().call(http::Request::new(()));
+            assert!(result.await.is_ok());
+        }
+        assert_eq!(service.circuit(), CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_balance() {
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let options = options(transitions.clone()).with_open_duration(Duration::from_secs(3600));
+        let uri: Uri = "http://10.0.0.1:2379".parse().unwrap();
+        let mut down =
+            CircuitBreakerLayer::new(options.clone(), uri.clone()).layer(flapping(u32::MAX));
+        for _ in 0..3 {
+            let result = down.ready().await.unwrap().call(http::Request::new(()));
+            assert!(result.await.is_err());
+        }
+        let up = CircuitBreakerLayer::new(options, uri).layer(flapping(0));
+        let mut balance =
+            tower::balance::p2c::Balance::new(tower::discover::ServiceList::new([down, up]));
+
+        // The open endpoint is not ready, so no request is sent to it.
+        for _ in 0..100 {
+            let result = balance.ready().await.unwrap().call(http::Request::new(()));
+            assert!(result.await.is_ok());
+        }
+        assert_eq!(*transitions.lock_unpoisoned(), [CircuitState::Open]);
+    }
+}
@@ -0,0 +1,214 @@
+//! Compression of requests and responses.
+//!
+//! The encodings are enabled by the features `gzip` and `zstd`. A client sends compressed
+//! requests until a server rejects their encoding, then falls back to uncompressed requests,
+//! see [`Compressing`].
+
+use crate::auth::AuthService;
+use crate::intercept::InterceptedChannel;
+use crate::rpc::pb::{etcdserverpb, v3electionpb, v3lockpb};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tonic::codec::CompressionEncoding;
+
+/// Compression of the requests and responses of a client.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Compression {
+    send: Option<CompressionEncoding>,
+    accept: Vec<CompressionEncoding>,
+    rejected: Rejected,
+}
+
+impl Compression {
+    /// Creates a `Compression` sending and accepting uncompressed messages only.
+    #[inline]
+    pub(crate) const fn new() -> Self {
+        Self {
+            send: None,
+            accept: Vec::new(),
+            rejected: Rejected::new(),
+        }
+    }
+
+    /// Compresses requests with `encoding`.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    #[inline]
+    pub(crate) fn set_send(&mut self, encoding: CompressionEncoding) {
+        self.send = Some(encoding);
+    }
+
+    /// Accepts responses compressed with `encodings`.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    #[inline]
+    pub(crate) fn set_accept(&mut self, encodings: &[CompressionEncoding]) {
+        self.accept = encodings.to_vec();
+    }
+
+    /// The compression of a new client, which has not seen any rejection yet.
+    #[inline]
+    pub(crate) fn for_client(&self) -> Self {
+        Self {
+            send: self.send,
+            accept: self.accept.clone(),
+            rejected: Rejected(self.send.is_some().then(Default::default)),
+        }
+    }
+
+    /// Whether the server rejected the encoding of the requests of the client.
+    #[cfg_attr(not(any(feature = "gzip", feature = "zstd")), allow(dead_code))]
+    #[inline]
+    pub(crate) fn rejected(&self) -> &Rejected {
+        &self.rejected
+    }
+}
+
+/// Whether a server rejected the encoding of the requests of a client, shared by its
+/// sub-clients.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Rejected(Option<Arc<AtomicBool>>);
+
+impl Rejected {
+    #[inline]
+    const fn new() -> Self {
+        Self(None)
+    }
+
+    /// Returns `true` if the encoding was rejected.
+    #[inline]
+    fn get(&self) -> bool {
+        self.0.as_ref().is_some_and(|r| r.load(Ordering::Relaxed))
+    }
+
+    /// Records that the encoding was rejected if the request failed with the status `code`
+    /// and `message`.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    #[inline]
+    pub(crate) fn check(&self, code: Option<tonic::Code>, message: &str) {
+        if let Some(rejected) = &self.0 {
+            if is_rejection(code, message) && !rejected.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    message,
+                    "etcd rejected compressed request, sending uncompressed"
+                );
+            }
+        }
+    }
+}
+
+/// Returns `true` if a request failed with the status `code` and `message` because the server
+/// does not support its encoding, e.g. `grpc: Decompressor is not installed for
+/// grpc-encoding "zstd"`.
+pub(crate) fn is_rejection(code: Option<tonic::Code>, message: &str) -> bool {
+    code == Some(tonic::Code::Unimplemented)
+        && (message.contains("grpc-encoding") || message.contains("compress"))
+}
+
+/// Generated clients whose messages can be compressed.
+pub(crate) trait Compressible: Clone {
+    fn send_compressed(self, encoding: CompressionEncoding) -> Self;
+    fn accept_compressed(self, encoding: CompressionEncoding) -> Self;
+}
+
+macro_rules! impl_compressible {
+    ($($feature:literal => $($client:ident)::+,)*) => {
+        $(
+            #[cfg(feature = $feature)]
+            impl Compressible for $($client)::+<AuthService<InterceptedChannel>> {
+                #[inline]
+                fn send_compressed(self, encoding: CompressionEncoding) -> Self {
+                    $($client)::+::send_compressed(self, encoding)
+                }
+
+                #[inline]
+                fn accept_compressed(self, encoding: CompressionEncoding) -> Self {
+                    $($client)::+::accept_compressed(self, encoding)
+                }
+            }
+        )*
+    };
+}
+
+impl_compressible!(
+    "auth" => etcdserverpb::auth_client::AuthClient,
+    "cluster" => etcdserverpb::cluster_client::ClusterClient,
+    "election" => v3electionpb::election_client::ElectionClient,
+    "kv" => etcdserverpb::kv_client::KvClient,
+    "lease" => etcdserverpb::lease_client::LeaseClient,
+    "lock" => v3lockpb::lock_client::LockClient,
+    "maintenance" => etcdserverpb::maintenance_client::MaintenanceClient,
+    "watch" => etcdserverpb::watch_client::WatchClient,
+);
+
+/// A generated client, dereferencing to the one compressing requests until the server
+/// rejects their encoding, and to the one sending them uncompressed from then on.
+///
+/// Both accept compressed responses. The compressed client is boxed to keep the futures of
+/// the RPCs small.
+#[derive(Clone)]
+pub(crate) struct Compressing<C> {
+    plain: C,
+    compressed: Option<Box<C>>,
+    rejected: Rejected,
+}
+
+impl<C: Compressible> Compressing<C> {
+    /// Wraps `client`, sending uncompressed requests.
+    #[inline]
+    pub(crate) fn new(client: C) -> Self {
+        Self {
+            plain: client,
+            compressed: None,
+            rejected: Rejected::new(),
+        }
+    }
+
+    /// Compresses the messages according to `compression`.
+    pub(crate) fn with_compression(self, compression: &Compression) -> Self {
+        let plain = compression
+            .accept
+            .iter()
+            .fold(self.plain, |client, &encoding| {
+                client.accept_compressed(encoding)
+            });
+        let compressed = compression
+            .send
+            .map(|encoding| Box::new(plain.clone().send_compressed(encoding)));
+        Self {
+            plain,
+            compressed,
+            rejected: compression.rejected.clone(),
+        }
+    }
+
+    /// Applies `f` to both clients, e.g. to set the limits of the message sizes.
+    pub(crate) fn map(self, f: impl Fn(C) -> C) -> Self {
+        Self {
+            plain: f(self.plain),
+            compressed: self.compressed.map(|compressed| Box::new(f(*compressed))),
+            rejected: self.rejected,
+        }
+    }
+}
+
+impl<C> Deref for Compressing<C> {
+    type Target = C;
+
+    #[inline]
+    fn deref(&self) -> &C {
+        match &self.compressed {
+            Some(compressed) if !self.rejected.get() => compressed,
+            _ => &self.plain,
+        }
+    }
+}
+
+impl<C> DerefMut for Compressing<C> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut C {
+        match &mut self.compressed {
+            Some(compressed) if !self.rejected.get() => compressed,
+            _ => &mut self.plain,
+        }
+    }
+}
@@ -0,0 +1,505 @@
+//! Client config files, enabled by the `config` feature.
+
+use crate::client::{invalid_options, normalize_url};
+use crate::error::{Error, Result};
+use crate::logging::log_event;
+#[cfg(not(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl")))]
+use crate::settings::TLS_REQUIRED;
+use crate::settings::{format_duration, parse_duration};
+use crate::{ConnectOptions, EndpointConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The config of a client, as read from a YAML or JSON file, e.g. the one the Kubernetes
+/// components are given.
+///
+/// The fields follow the config files of the Go client, in kebab case:
+///
+/// ```yaml
+/// endpoints:
+///   - https://10.0.0.1:2379
+///   - https://10.0.0.2:2379
+/// dial-timeout: 5s
+/// dial-keep-alive-time: 30s
+/// dial-keep-alive-timeout: 10s
+/// request-timeout: 1m
+/// permit-without-stream: true
+/// username: root
+/// password: secret
+/// trusted-ca-file: certs/ca.pem
+/// cert-file: certs/client.pem
+/// key-file: certs/client-key.pem
+/// ```
+///
+/// Every field is optional. The durations are in the format of Go, e.g. `5s`, `1.5s`,
+/// `1m30s` or `300ms`, or numbers of nanoseconds. The relative paths of a file are relative
+/// to the directory of the file.
+///
+/// Unknown fields fail the strict reads, the others skip them with a warning, logged with
+/// the `logging` feature, see [`ClientConfig::unknown_fields`].
+#[cfg_attr(docsrs, doc(cfg(feature = "config")))]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ClientConfig {
+    /// The URLs of the endpoints, see [`EndpointConfig`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<String>,
+    /// See [`ConnectOptions::with_connect_timeout`].
+    #[serde(default, with = "duration", skip_serializing_if = "Option::is_none")]
+    pub dial_timeout: Option<Duration>,
+    /// The interval of [`ConnectOptions::with_keep_alive`].
+    #[serde(default, with = "duration", skip_serializing_if = "Option::is_none")]
+    pub dial_keep_alive_time: Option<Duration>,
+    /// The timeout of [`ConnectOptions::with_keep_alive`], requires `dial-keep-alive-time`.
+    #[serde(default, with = "duration", skip_serializing_if = "Option::is_none")]
+    pub dial_keep_alive_timeout: Option<Duration>,
+    /// See [`ConnectOptions::with_request_timeout`].
+    #[serde(default, with = "duration", skip_serializing_if = "Option::is_none")]
+    pub request_timeout: Option<Duration>,
+    /// See [`ConnectOptions::with_keep_alive_while_idle`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub permit_without_stream: bool,
+    /// The name of the user, requires the password and the `auth` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// The password of the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// The path of the PEM file of the CA certificate the servers are verified against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trusted_ca_file: Option<PathBuf>,
+    /// The path of the PEM file of the certificate of the client, requires `key-file`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_file: Option<PathBuf>,
+    /// The path of the PEM file of the private key of the client, requires `cert-file`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+    /// The unknown fields of a lenient read.
+    #[serde(flatten, skip_serializing)]
+    unknown: BTreeMap<String, serde_json::Value>,
+}
+
+impl ClientConfig {
+    /// Reads the config file at `path`, in JSON if its extension is `json`, in YAML
+    /// otherwise, failing on unknown fields if `strict`.
+    ///
+    /// The relative paths of the certificate files are resolved against the directory of
+    /// the file. Fails with an [`Error::InvalidOptions`] naming the file.
+    pub fn from_file(path: impl AsRef<Path>, strict: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let in_file = |e: Error| match e {
+            Error::InvalidOptions(message) => {
+                Error::InvalidOptions(format!("{}: {}", path.display(), message))
+            }
+            e => e,
+        };
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            Error::InvalidOptions(format!("can not read {}: {}", path.display(), e))
+        })?;
+        let mut config = match path.extension() {
+            Some(extension) if extension == "json" => Self::from_json(&content, strict),
+            _ => Self::from_yaml(&content, strict),
+        }
+        .map_err(in_file)?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for file in [
+            &mut config.trusted_ca_file,
+            &mut config.cert_file,
+            &mut config.key_file,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if file.is_relative() {
+                *file = dir.join(&*file);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Parses the YAML config `yaml`, failing on unknown fields if `strict`.
+    ///
+    /// The relative paths are left as they are, i.e. relative to the working directory.
+    pub fn from_yaml(yaml: &str, strict: bool) -> Result<Self> {
+        serde_yaml::from_str::<Self>(yaml)
+            .map_err(|e| Error::InvalidOptions(format!("invalid YAML config: {}", e)))?
+            .check_unknown(strict)
+    }
+
+    /// Parses the JSON config `json`, failing on unknown fields if `strict`.
+    ///
+    /// The relative paths are left as they are, i.e. relative to the working directory.
+    pub fn from_json(json: &str, strict: bool) -> Result<Self> {
+        serde_json::from_str::<Self>(json)
+            .map_err(|e| Error::InvalidOptions(format!("invalid JSON config: {}", e)))?
+            .check_unknown(strict)
+    }
+
+    /// The config of `endpoints` and of the settings of `options` a config can hold, i.e.
+    /// all of them but the TLS options.
+    pub fn from_options<E: AsRef<str>>(endpoints: &[E], options: &ConnectOptions) -> Self {
+        #[cfg(feature = "auth")]
+        let (username, password) = match &options.user {
+            Some((name, password)) => (Some(name.clone()), Some(password.clone())),
+            None => (None, None),
+        };
+        #[cfg(not(feature = "auth"))]
+        let (username, password) = (None, None);
+        Self {
+            endpoints: endpoints.iter().map(|e| e.as_ref().to_owned()).collect(),
+            dial_timeout: options.connect_timeout,
+            dial_keep_alive_time: options.keep_alive_interval,
+            dial_keep_alive_timeout: options.keep_alive_timeout,
+            request_timeout: options.request_timeout,
+            permit_without_stream: options.keep_alive_while_idle,
+            username,
+            password,
+            ..Self::default()
+        }
+    }
+
+    /// The names of the unknown fields skipped by a lenient read.
+    pub fn unknown_fields(&self) -> impl Iterator<Item = &str> {
+        self.unknown.keys().map(String::as_str)
+    }
+
+    /// The endpoints of the config, failing with an [`Error::InvalidOptions`] naming the
+    /// invalid ones.
+    pub fn endpoint_configs(&self) -> Result<Vec<EndpointConfig>> {
+        let mut problems = Vec::new();
+        if self.endpoints.is_empty() {
+            problems.push(String::from("endpoints is empty"));
+        }
+        for endpoint in &self.endpoints {
+            if let Err(reason) = normalize_url(endpoint) {
+                problems.push(format!("endpoint {} is invalid: {}", endpoint, reason));
+            }
+        }
+        invalid_options(problems)?;
+        Ok(self.endpoints.iter().map(EndpointConfig::new).collect())
+    }
+
+    /// The options set by the config, reading the certificate files.
+    ///
+    /// Fails with an [`Error::InvalidOptions`] listing the fields which are missing,
+    /// require a feature that is off, or name files which can not be read.
+    pub fn connect_options(&self) -> Result<ConnectOptions> {
+        let mut problems = Vec::new();
+        let mut options = ConnectOptions::new();
+        if let Some(timeout) = self.dial_timeout {
+            options = options.with_connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.request_timeout {
+            options = options.with_request_timeout(timeout);
+        }
+        match (self.dial_keep_alive_time, self.dial_keep_alive_timeout) {
+            (Some(interval), timeout) => {
+                let timeout = timeout.unwrap_or(crate::DEFAULT_KEEP_ALIVE_TIMEOUT);
+                options = options.with_keep_alive(interval, timeout);
+            }
+            (None, Some(_)) => problems.push(String::from(
+                "dial-keep-alive-timeout is set without dial-keep-alive-time",
+            )),
+            (None, None) => {}
+        }
+        if self.permit_without_stream {
+            options = options.with_keep_alive_while_idle(true);
+        }
+
+        match (&self.username, &self.password) {
+            #[cfg(feature = "auth")]
+            (Some(name), Some(password)) => options = options.with_user(name, password),
+            #[cfg(not(feature = "auth"))]
+            (Some(_), Some(_)) => {
+                problems.push(String::from("username requires the `auth` feature"))
+            }
+            (Some(_), None) => problems.push(String::from("username is set without password")),
+            (None, Some(_)) => problems.push(String::from("password is set without username")),
+            (None, None) => {}
+        }
+
+        let identity = match (&self.cert_file, &self.key_file) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (Some(_), None) => {
+                problems.push(String::from("cert-file is set without key-file"));
+                None
+            }
+            (None, Some(_)) => {
+                problems.push(String::from("key-file is set without cert-file"));
+                None
+            }
+            (None, None) => None,
+        };
+        if self.trusted_ca_file.is_some() || identity.is_some() {
+            #[cfg(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl"))]
+            {
+                let mut read = |field: &str, path: &Path| match std::fs::read(path) {
+                    Ok(content) => Some(content),
+                    Err(e) => {
+                        problems.push(format!(
+                            "{} {} can not be read: {}",
+                            field,
+                            path.display(),
+                            e
+                        ));
+                        None
+                    }
+                };
+                let ca = self
+                    .trusted_ca_file
+                    .as_ref()
+                    .and_then(|path| read("trusted-ca-file", path));
+                let identity = identity.and_then(|(cert, key)| {
+                    let cert = read("cert-file", cert);
+                    let key = read("key-file", key);
+                    Some((cert?, key?))
+                });
+                options = crate::settings::with_tls_pem(options, ca, identity);
+            }
+            #[cfg(not(any(
+                feature = "tls-ring",
+                feature = "tls-aws-lc",
+                feature = "tls-openssl"
+            )))]
+            problems.push(format!("the certificate files {}", TLS_REQUIRED));
+        }
+
+        invalid_options(problems)?;
+        Ok(options)
+    }
+
+    /// Fails on the unknown fields if `strict`, or warns of them.
+    fn check_unknown(self, strict: bool) -> Result<Self> {
+        if strict {
+            invalid_options(
+                self.unknown_fields()
+                    .map(|field| format!("unknown field `{}`", field))
+                    .collect(),
+            )?;
+        }
+        for field in self.unknown_fields() {
+            log_event!(
+                Warn,
+                "skipping unknown etcd client config field",
+                field = field
+            );
+        }
+        Ok(self)
+    }
+}
+
+impl ConnectOptions {
+    /// Creates the options set by the config file at `path`, skipping unknown fields, see
+    /// [`ClientConfig::from_file`] and [`ClientConfig::connect_options`].
+    ///
+    /// The endpoints are left out, use [`ClientConfig::endpoint_configs`] for them.
+    #[cfg_attr(docsrs, doc(cfg(feature = "config")))]
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        ClientConfig::from_file(path, false)?.connect_options()
+    }
+}
+
+/// The durations of Go, e.g. `5s`, or numbers of nanoseconds.
+mod duration {
+    use super::*;
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::ser::Serializer;
+    use std::fmt::{self, Formatter};
+
+    pub(super) fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => serializer.serialize_str(&format_duration(*duration)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Option<Duration>, D::Error> {
+        struct DurationVisitor;
+
+        impl Visitor<'_> for DurationVisitor {
+            type Value = Option<Duration>;
+
+            fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                f.write_str("a duration, e.g. \"5s\" or \"1m30s\", or a number of nanoseconds")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> std::result::Result<Self::Value, E> {
+                parse_duration(s)
+                    .map(Some)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Str(s), &self))
+            }
+
+            fn visit_u64<E: de::Error>(self, nanos: u64) -> std::result::Result<Self::Value, E> {
+                Ok(Some(Duration::from_nanos(nanos)))
+            }
+
+            fn visit_i64<E: de::Error>(self, nanos: i64) -> std::result::Result<Self::Value, E> {
+                u64::try_from(nanos)
+                    .map(|nanos| Some(Duration::from_nanos(nanos)))
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(nanos), &self))
+            }
+
+            fn visit_none<E: de::Error>(self) -> std::result::Result<Self::Value, E> {
+                Ok(None)
+            }
+
+            fn visit_unit<E: de::Error>(self) -> std::result::Result<Self::Value, E> {
+                Ok(None)
+            }
+        }
+
+        deserializer.deserialize_any(DurationVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/config")
+            .join(name)
+    }
+
+    #[test]
+    fn test_from_file() {
+        let fixtures = fixture("");
+        let expected = ClientConfig {
+            endpoints: vec![
+                String::from("https://10.0.0.1:2379"),
+                String::from("https://10.0.0.2:2379"),
+            ],
+            dial_timeout: Some(Duration::from_secs(5)),
+            dial_keep_alive_time: Some(Duration::from_secs(30)),
+            dial_keep_alive_timeout: Some(Duration::from_secs(10)),
+            request_timeout: Some(Duration::from_millis(1500)),
+            permit_without_stream: true,
+            username: Some(String::from("root")),
+            password: Some(String::from("secret")),
+            trusted_ca_file: Some(fixtures.join("certs/ca.pem")),
+            cert_file: Some(fixtures.join("certs/client.pem")),
+            key_file: Some(PathBuf::from("/etc/etcd/client-key.pem")),
+            ..ClientConfig::default()
+        };
+        for name in ["client.yaml", "client.json"] {
+            let config = ClientConfig::from_file(fixture(name), true).unwrap();
+            assert_eq!(config, expected, "{}", name);
+        }
+
+        let err = ClientConfig::from_file(fixture("missing.yaml"), false).unwrap_err();
+        assert!(err.to_string().contains("missing.yaml"), "{}", err);
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let yaml = "endpoints: [http://127.0.0.1:2379]\nauto-sync-interval: 30s\nfoo: 1\n";
+        let config = ClientConfig::from_yaml(yaml, false).unwrap();
+        assert_eq!(
+            config.unknown_fields().collect::<Vec<_>>(),
+            ["auto-sync-interval", "foo"]
+        );
+        assert_eq!(config.endpoints, ["http://127.0.0.1:2379"]);
+
+        match ClientConfig::from_yaml(yaml, true) {
+            Err(Error::InvalidOptions(problems)) => assert_eq!(
+                problems,
+                "unknown field `auto-sync-interval`; unknown field `foo`"
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(ClientConfig::from_json(r#"{"foo": true}"#, true).is_err());
+        assert!(ClientConfig::from_json(r#"{"foo": true}"#, false).is_ok());
+    }
+
+    #[test]
+    fn test_durations() {
+        let config = ClientConfig::from_json(
+            r#"{"dial-timeout": 2000000000, "request-timeout": null}"#,
+            true,
+        )
+        .unwrap();
+        assert_eq!(config.dial_timeout, Some(Duration::from_secs(2)));
+        assert_eq!(config.request_timeout, None);
+
+        for invalid in [r#"{"dial-timeout": "5"}"#, r#"{"dial-timeout": -1}"#] {
+            let err = ClientConfig::from_json(invalid, false).unwrap_err();
+            assert!(err.to_string().contains("a duration"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let config = ClientConfig {
+            endpoints: vec![String::from("http://10.0.0.1:2379")],
+            dial_timeout: Some(Duration::from_secs(3)),
+            dial_keep_alive_time: Some(Duration::from_secs(30)),
+            dial_keep_alive_timeout: Some(Duration::from_secs(10)),
+            request_timeout: Some(Duration::from_secs(90)),
+            permit_without_stream: true,
+            #[cfg(feature = "auth")]
+            username: Some(String::from("root")),
+            #[cfg(feature = "auth")]
+            password: Some(String::from("secret")),
+            ..ClientConfig::default()
+        };
+        let endpoints: Vec<_> = config
+            .endpoint_configs()
+            .unwrap()
+            .iter()
+            .map(|e| e.url().to_owned())
+            .collect();
+        let options = config.connect_options().unwrap();
+        assert_eq!(ClientConfig::from_options(&endpoints, &options), config);
+
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(yaml.contains("request-timeout: 1m30s"), "{}", yaml);
+        assert_eq!(ClientConfig::from_yaml(&yaml, true).unwrap(), config);
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(ClientConfig::from_json(&json, true).unwrap(), config);
+    }
+
+    #[test]
+    fn test_connect_options_problems() {
+        let config = ClientConfig {
+            endpoints: vec![String::from("ftp://10.0.0.1")],
+            dial_keep_alive_timeout: Some(Duration::from_secs(1)),
+            password: Some(String::from("secret")),
+            key_file: Some(fixture("certs/client-key.pem")),
+            ..ClientConfig::default()
+        };
+        let err = config.connect_options().unwrap_err().to_string();
+        for problem in [
+            "dial-keep-alive-timeout is set without dial-keep-alive-time",
+            "password is set without username",
+            "key-file is set without cert-file",
+        ] {
+            assert!(err.contains(problem), "{:?} in {:?}", problem, err);
+        }
+        assert!(!err.contains("secret"), "{}", err);
+        let err = config.endpoint_configs().unwrap_err().to_string();
+        assert!(
+            err.contains("endpoint ftp://10.0.0.1 is invalid"),
+            "{}",
+            err
+        );
+
+        let config = ClientConfig {
+            trusted_ca_file: Some(fixture("certs/missing.pem")),
+            ..ClientConfig::default()
+        };
+        let err = config.connect_options().unwrap_err().to_string();
+        #[cfg(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl"))]
+        assert!(err.contains("trusted-ca-file"), "{}", err);
+        #[cfg(not(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl")))]
+        assert!(err.contains(TLS_REQUIRED), "{}", err);
+    }
+}
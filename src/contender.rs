@@ -0,0 +1,87 @@
+//! The contenders for a lock or an election, listed from the keys under its prefix.
+//!
+//! The Lock and Election services both hold a key `<name>/<lease id in hex>` per contender,
+//! attached to its lease. The contender whose key was created first holds the lock or leads
+//! the election, the others wait in the order their keys were created.
+
+use crate::error::Result;
+use crate::ids::{LeaseId, Revision};
+use crate::rpc::kv::{GetOptions, KvClient, SortOrder, SortTarget};
+use crate::rpc::KeyValue;
+
+/// A contender for a lock or an election, see
+/// [`LockClient::waiters`](crate::LockClient::waiters) and
+/// [`ElectionClient::candidates`](crate::ElectionClient::candidates).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contender {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    lease: LeaseId,
+    create_revision: Revision,
+    age: i64,
+}
+
+impl Contender {
+    /// The contender of the key `kv`, read at `revision`.
+    #[inline]
+    fn new(kv: KeyValue, revision: i64) -> Self {
+        Self {
+            lease: kv.lease(),
+            create_revision: kv.create_revision(),
+            age: revision - kv.create_revision().get(),
+            key: kv.key().to_vec(),
+            value: kv.value().to_vec(),
+        }
+    }
+
+    /// The key of the contender, e.g. to delete it.
+    #[inline]
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// The value of the key, empty for a lock, the proposal of a candidate for an election.
+    #[inline]
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// The lease the key is attached to, the contender is gone once the lease expires.
+    #[inline]
+    pub const fn lease(&self) -> LeaseId {
+        self.lease
+    }
+
+    /// The revision the key was created at, the position of the contender in the queue.
+    #[inline]
+    pub const fn create_revision(&self) -> Revision {
+        self.create_revision
+    }
+
+    /// The number of revisions since the key was created, as of the read listing it.
+    ///
+    /// etcd records no time of the keys, a contender which has been waiting for long while
+    /// the cluster is busy shows a large age.
+    #[inline]
+    pub const fn age(&self) -> i64 {
+        self.age
+    }
+}
+
+impl KvClient {
+    /// The contenders under the prefix `<name>/`, in the order their keys were created.
+    pub(crate) async fn contenders(&mut self, name: Vec<u8>) -> Result<Vec<Contender>> {
+        let mut prefix = name;
+        prefix.push(b'/');
+        let options = GetOptions::new()
+            .with_prefix()
+            .with_sort(SortTarget::Create, SortOrder::Ascend);
+        let mut resp = self.get(prefix, Some(options)).await?;
+        let revision = resp.header().map_or(0, |header| header.revision().get());
+        Ok(resp
+            .take_kvs()
+            .into_iter()
+            .map(|kv| Contender::new(kv, revision))
+            .collect())
+    }
+}
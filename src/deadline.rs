@@ -0,0 +1,191 @@
+//! Deadlines and cooperative cancellation of calls.
+//!
+//! A call is aborted, i.e. its in-flight request future is dropped, when its deadline passes
+//! or its [`CancellationToken`] is cancelled. The deadline covers every retry of the call.
+
+use crate::error::{Error, Result};
+use crate::metadata::Metadata;
+#[cfg(feature = "kv")]
+use crate::route::RouteTo;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// Deadline, cancellation token, metadata and route of a single call.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CallOptions {
+    deadline: Option<Instant>,
+    cancel: Option<CancellationToken>,
+    metadata: Metadata,
+    #[cfg(feature = "kv")]
+    route: RouteTo,
+}
+
+impl CallOptions {
+    /// Creates a `CallOptions`.
+    #[inline]
+    pub(crate) const fn new() -> Self {
+        Self {
+            deadline: None,
+            cancel: None,
+            metadata: Metadata::new(),
+            #[cfg(feature = "kv")]
+            route: RouteTo::Any,
+        }
+    }
+
+    /// Sets the instant the call must be completed by.
+    #[inline]
+    pub(crate) fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Sets the token cancelling the call.
+    #[inline]
+    pub(crate) fn set_cancel(&mut self, token: CancellationToken) {
+        self.cancel = Some(token);
+    }
+
+    /// Sets the member the call is sent to.
+    #[cfg(feature = "kv")]
+    #[inline]
+    pub(crate) fn set_route(&mut self, route: RouteTo) {
+        self.route = route;
+    }
+
+    /// Takes the member the call is sent to out of the options, leaving [`RouteTo::Any`].
+    #[cfg(feature = "kv")]
+    #[inline]
+    pub(crate) fn take_route(&mut self) -> RouteTo {
+        std::mem::take(&mut self.route)
+    }
+
+    /// Takes the token cancelling the call out of the options.
+    #[cfg(feature = "watch")]
+    #[inline]
+    pub(crate) fn take_cancel(&mut self) -> Option<CancellationToken> {
+        self.cancel.take()
+    }
+
+    /// Adds the metadata `key: value` sent with the requests of the call.
+    #[inline]
+    pub(crate) fn append_metadata(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        self.metadata.append(key, value)
+    }
+
+    /// The token cancelling the call, also used to cancel the stream it establishes.
+    #[inline]
+    pub(crate) fn cancel(&self) -> Option<&CancellationToken> {
+        self.cancel.as_ref()
+    }
+
+    /// Runs the call `fut` of the RPC `rpc` with the metadata, aborting it when the deadline
+    /// passes or the token is cancelled.
+    ///
+    /// `default_deadline` applies from now on if the call has no deadline.
+    pub(crate) async fn run<T>(
+        &self,
+        rpc: &'static str,
+        default_deadline: Option<Duration>,
+        fut: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        // Boxed, as the layers below would each hold a copy of the size of the call.
+        let fut = Box::pin(fut);
+        let start = Instant::now();
+        let deadline = self
+            .deadline
+            .or_else(|| default_deadline.map(|deadline| start + deadline));
+        let fut = async {
+            match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline.into(), fut)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(Error::Deadline {
+                            rpc,
+                            elapsed: start.elapsed(),
+                        })
+                    }),
+                None => fut.await,
+            }
+        };
+        let fut = self.metadata.clone().scope(fut);
+        match &self.cancel {
+            Some(token) => token
+                .run_until_cancelled(fut)
+                .await
+                .unwrap_or(Err(Error::Cancelled { rpc })),
+            None => fut.await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// A call which never completes, and records being dropped.
+    fn hanging(dropped: Arc<AtomicBool>) -> impl Future<Output = Result<()>> {
+        struct Guard(Arc<AtomicBool>);
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+        let guard = Guard(dropped);
+        async move {
+            let _guard = guard;
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deadline() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let result = CallOptions::new()
+            .run(
+                "Range",
+                Some(Duration::from_millis(10)),
+                hanging(dropped.clone()),
+            )
+            .await;
+        match result {
+            Err(Error::Deadline { rpc, elapsed }) => {
+                assert_eq!(rpc, "Range");
+                assert!(elapsed >= Duration::from_millis(10));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(dropped.load(Ordering::SeqCst));
+
+        // The deadline of the call overrides the default one.
+        let mut call = CallOptions::new();
+        call.set_deadline(Instant::now() + Duration::from_millis(10));
+        let result = call
+            .run("Range", Some(Duration::from_secs(3600)), hanging(dropped))
+            .await;
+        assert!(matches!(result, Err(Error::Deadline { .. })));
+
+        let result = CallOptions::new()
+            .run("Range", Some(Duration::from_secs(3600)), async { Ok(1) })
+            .await;
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let token = CancellationToken::new();
+        let mut call = CallOptions::new();
+        call.set_cancel(token.clone());
+        let dropped = Arc::new(AtomicBool::new(false));
+        let call = tokio::spawn({
+            let dropped = dropped.clone();
+            async move { call.run("Put", None, hanging(dropped)).await }
+        });
+        token.cancel();
+        let result = call.await.unwrap();
+        assert!(matches!(result, Err(Error::Cancelled { rpc: "Put" })));
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+}
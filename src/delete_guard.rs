@@ -0,0 +1,129 @@
+//! Deleting ranges of keys behind a safety interlock: dry runs and limited deletes.
+
+use crate::error::{Error, Result};
+use crate::ids::Revision;
+use crate::rpc::kv::{
+    Compare, CompareOp, DeleteOptions, DeleteResponse, GetOptions, GetResponse, KvClient, Txn,
+    TxnOp,
+};
+use crate::rpc::{KeyValue, ResponseHeader};
+
+/// The keys a delete would remove, see [`KvClient::delete_dry_run`].
+#[derive(Debug, Clone)]
+pub struct DeleteDryRun {
+    get: GetResponse,
+}
+
+impl DeleteDryRun {
+    /// The header of the count, the keys are counted at its revision.
+    #[inline]
+    pub fn header(&self) -> Option<&ResponseHeader> {
+        self.get.header()
+    }
+
+    /// The revision the keys were counted at.
+    #[inline]
+    pub fn revision(&self) -> Revision {
+        self.header()
+            .map_or(Revision::current(), |header| header.revision())
+    }
+
+    /// The number of keys the delete would remove.
+    #[inline]
+    pub fn count(&self) -> i64 {
+        self.get.count()
+    }
+
+    /// The keys the delete would remove, without their values, up to the limit given to
+    /// [`DeleteOptions::with_dry_run_keys`].
+    #[inline]
+    pub fn kvs(&self) -> &[KeyValue] {
+        self.get.kvs()
+    }
+
+    /// Returns `true` if [`DeleteDryRun::kvs`] lists every key the delete would remove.
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.kvs().len() as i64 == self.count()
+    }
+}
+
+impl KvClient {
+    /// Counts the keys deleting `key` with `options` would remove, without deleting them,
+    /// e.g. to check a prefix built from a variable before deleting it. The keys themselves
+    /// are listed up to the limit given to [`DeleteOptions::with_dry_run_keys`], if any.
+    pub async fn delete_dry_run(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteDryRun> {
+        let mut options = options.unwrap_or_default().with_key(key);
+        let limit = options.dry_run().unwrap_or(0);
+        let call = options.take_call();
+        let mut client = self.clone();
+        call.run("DeleteRange", self.default_deadline, async move {
+            client.count_deletions(&options, limit).await
+        })
+        .await
+    }
+
+    /// Deletes with the interlock of `options`, a dry run or a limited delete.
+    pub(crate) async fn delete_guarded(
+        &mut self,
+        mut options: DeleteOptions,
+    ) -> Result<DeleteResponse> {
+        let call = options.take_call();
+        let mut client = self.clone();
+        call.run("DeleteRange", self.default_deadline, async move {
+            if let Some(limit) = options.dry_run() {
+                let dry_run = client.count_deletions(&options, limit).await?;
+                return Ok(DeleteResponse::from_dry_run(dry_run.get));
+            }
+            let limit = options.max_deletions().unwrap_or(i64::MAX);
+            let (key, range_end) = options.range();
+            loop {
+                let counted = client.count_deletions(&options, 0).await?;
+                if counted.count() > limit {
+                    return Err(Error::DeletionLimitExceeded {
+                        would_delete: counted.count(),
+                        limit,
+                    });
+                }
+
+                // No key of the range has been put since the count, created keys included.
+                let mut compare =
+                    Compare::mod_revision(key.clone(), CompareOp::Less, counted.revision() + 1);
+                if !range_end.is_empty() {
+                    compare = compare.with_range(range_end.clone());
+                }
+                let delete = TxnOp::delete(key.clone(), Some(options.clone()));
+                let resp = client
+                    .txn(Txn::new().when([compare]).and_then([delete]))
+                    .await?;
+                if resp.succeeded() {
+                    return Ok(DeleteResponse::from_txn(resp));
+                }
+            }
+        })
+        .await
+    }
+
+    /// Counts the keys in the range of `options`, listing up to `limit` of them.
+    async fn count_deletions(
+        &mut self,
+        options: &DeleteOptions,
+        limit: i64,
+    ) -> Result<DeleteDryRun> {
+        let (key, range_end) = options.range();
+        let mut get = GetOptions::new().with_keys_only();
+        get = match limit {
+            0 => get.with_count_only(),
+            limit => get.with_limit(limit),
+        };
+        if !range_end.is_empty() {
+            get = get.with_range(range_end);
+        }
+        let get = self.get(key, Some(get)).await?;
+        Ok(DeleteDryRun { get })
+    }
+}
@@ -0,0 +1,297 @@
+//! Membership driven endpoint updates.
+//!
+//! An [`EndpointSync`] polls the member list of the cluster and feeds the client URLs of
+//! added and removed members into the balanced channel of a [`Client`](crate::Client), so
+//! that the client follows membership changes made through the Cluster API by anyone.
+
+use crate::channel::{Change, EndpointUpdater};
+use crate::client::Client;
+use crate::client::ConnectOptions;
+use crate::logging::log_event;
+use crate::rpc::cluster::{ClusterClient, Member};
+use crate::task::{Task, Tasks};
+use http::Uri;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::transport::Endpoint;
+
+/// The default interval of polling the member list.
+pub const DEFAULT_ENDPOINT_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+type EndpointFilter = Arc<dyn Fn(&Member, &str) -> Option<String> + Send + Sync>;
+
+/// Options for [`Client::sync_endpoints`].
+#[derive(Clone)]
+pub struct EndpointSyncOptions {
+    interval: Duration,
+    filter: Option<EndpointFilter>,
+}
+
+impl EndpointSyncOptions {
+    /// Creates an `EndpointSyncOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            interval: DEFAULT_ENDPOINT_SYNC_INTERVAL,
+            filter: None,
+        }
+    }
+
+    /// Sets the interval of polling the member list.
+    #[inline]
+    pub const fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets a hook called with every client URL of a member before it is added.
+    ///
+    /// The hook returns the URL to connect to, which may be rewritten, e.g. to the address
+    /// of a service mesh, or `None` to skip the URL.
+    #[inline]
+    pub fn with_filter(
+        mut self,
+        filter: impl Fn(&Member, &str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+}
+
+impl Default for EndpointSyncOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for EndpointSyncOptions {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EndpointSyncOptions")
+            .field("interval", &self.interval)
+            .field("filter", &self.filter.is_some())
+            .finish()
+    }
+}
+
+/// The name of the task of an endpoint sync.
+const SYNC_TASK: &str = "endpoint sync";
+
+/// Keeps the endpoints of a client in sync with the members of the cluster.
+///
+/// Only the endpoints added by the sync are ever removed, endpoints the client was
+/// connected with are left alone. Dropping the sync stops it.
+pub struct EndpointSync {
+    task: Task,
+}
+
+impl EndpointSync {
+    /// Starts polling the member list with `cluster` and updating the endpoints with `tx`.
+    pub(crate) fn spawn(
+        tasks: &Tasks,
+        mut cluster: ClusterClient,
+        tx: EndpointUpdater,
+        connect_options: Option<ConnectOptions>,
+        options: Option<EndpointSyncOptions>,
+    ) -> Self {
+        let options = options.unwrap_or_default();
+        let task = tasks.spawn(SYNC_TASK, async move {
+            let mut synced = Synced::default();
+            let mut interval = tokio::time::interval(options.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                // The member list is shared with `members_cached` callers, a list fetched
+                // by them within the interval is reused instead of polling again.
+                // Failures are transient, e.g. no member is reachable, retry on the next tick.
+                let resp = match cluster.members_cached(options.interval).await {
+                    Ok(resp) => resp,
+                    Err(e) => {
+                        log_event!(Warn, "etcd endpoint sync failed", error = &e);
+                        continue;
+                    }
+                };
+                let cluster_id = resp.header().map(|h| h.cluster_id()).unwrap_or_default();
+                let changes = synced.update(
+                    cluster_id,
+                    resp.members(),
+                    options.filter.as_ref(),
+                    &connect_options,
+                );
+                for change in changes {
+                    if tx.send(change).await.is_err() {
+                        // The client has gone away.
+                        return;
+                    }
+                }
+            }
+        });
+        Self { task }
+    }
+
+    /// Returns `true` if the sync stopped, i.e. the client has been dropped or the sync
+    /// panicked.
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Stops the sync, the endpoints added so far are kept.
+    #[inline]
+    pub fn stop(self) {}
+}
+
+impl Drop for EndpointSync {
+    #[inline]
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The endpoints added by the sync, and the fingerprint of the membership they came from.
+#[derive(Default)]
+struct Synced {
+    fingerprint: Option<u64>,
+    endpoints: HashMap<Uri, Endpoint>,
+}
+
+impl Synced {
+    /// Returns the changes to apply for the given membership.
+    fn update(
+        &mut self,
+        cluster_id: u64,
+        members: &[Member],
+        filter: Option<&EndpointFilter>,
+        connect_options: &Option<ConnectOptions>,
+    ) -> Vec<Change<Uri, Endpoint>> {
+        let fingerprint = fingerprint(cluster_id, members);
+        if self.fingerprint == Some(fingerprint) {
+            return Vec::new();
+        }
+        self.fingerprint = Some(fingerprint);
+
+        let mut endpoints = HashMap::new();
+        // Members without client URLs, e.g. learners being provisioned, are skipped.
+        for member in members {
+            for url in member.client_urls() {
+                let url = match filter {
+                    Some(filter) => match filter(member, url) {
+                        Some(url) => url,
+                        None => continue,
+                    },
+                    None => url.clone(),
+                };
+                // A URL which can not be connected to must not break the others.
+                let Ok(endpoint) = Client::build_endpoint(&url, connect_options) else {
+                    continue;
+                };
+                endpoints.insert(endpoint.uri().clone(), endpoint);
+            }
+        }
+
+        let mut changes = Vec::new();
+        for uri in self.endpoints.keys() {
+            if !endpoints.contains_key(uri) {
+                changes.push(Change::Remove(uri.clone()));
+            }
+        }
+        for (uri, endpoint) in &endpoints {
+            if !self.endpoints.contains_key(uri) {
+                changes.push(Change::Insert(uri.clone(), endpoint.clone()));
+            }
+        }
+        self.endpoints = endpoints;
+        changes
+    }
+}
+
+/// Hashes the cluster id and the client URLs of all members, regardless of their order.
+fn fingerprint(cluster_id: u64, members: &[Member]) -> u64 {
+    let mut members: Vec<(u64, &[String])> =
+        members.iter().map(|m| (m.id(), m.client_urls())).collect();
+    members.sort_unstable();
+
+    let mut hasher = DefaultHasher::new();
+    cluster_id.hash(&mut hasher);
+    members.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::pb::etcdserverpb::Member as PbMember;
+
+    fn member(id: u64, client_urls: &[&str]) -> Member {
+        let member = PbMember {
+            id,
+            client_ur_ls: client_urls.iter().map(|url| url.to_string()).collect(),
+            ..Default::default()
+        };
+        <&Member>::from(&member).clone()
+    }
+
+    fn summary(changes: &[Change<Uri, Endpoint>]) -> Vec<String> {
+        let mut summary: Vec<String> = changes
+            .iter()
+            .map(|change| match change {
+                Change::Insert(uri, _) => format!("+{}", uri),
+                Change::Remove(uri) => format!("-{}", uri),
+            })
+            .collect();
+        summary.sort();
+        summary
+    }
+
+    #[test]
+    fn test_synced_update() {
+        let mut synced = Synced::default();
+        let members = [
+            member(1, &["http://10.0.0.1:2379"]),
+            member(2, &["http://10.0.0.2:2379"]),
+            member(3, &[]),
+        ];
+        let changes = synced.update(7, &members, None, &None);
+        assert_eq!(
+            summary(&changes),
+            ["+http://10.0.0.1:2379/", "+http://10.0.0.2:2379/"]
+        );
+
+        // Unchanged membership, in any order, yields nothing.
+        let members = [members[2].clone(), members[1].clone(), members[0].clone()];
+        assert!(synced.update(7, &members, None, &None).is_empty());
+
+        let members = [
+            member(2, &["http://10.0.0.2:2379"]),
+            member(4, &["http://10.0.0.4:2379"]),
+        ];
+        let changes = synced.update(7, &members, None, &None);
+        assert_eq!(
+            summary(&changes),
+            ["+http://10.0.0.4:2379/", "-http://10.0.0.1:2379/"]
+        );
+    }
+
+    #[test]
+    fn test_synced_filter() {
+        let filter: EndpointFilter = Arc::new(|member, url| {
+            if member.id() == 1 {
+                None
+            } else {
+                Some(url.replace("10.0.0.2", "mesh.local"))
+            }
+        });
+        let members = [
+            member(1, &["http://10.0.0.1:2379"]),
+            member(2, &["http://10.0.0.2:2379"]),
+        ];
+        let mut synced = Synced::default();
+        let changes = synced.update(7, &members, Some(&filter), &None);
+        assert_eq!(summary(&changes), ["+http://mesh.local:2379/"]);
+    }
+}
@@ -0,0 +1,448 @@
+//! Connect options from the environment variables of `etcdctl`, enabled by the `env` feature.
+
+use crate::client::{invalid_options, normalize_url, DEFAULT_KEEP_ALIVE_TIMEOUT};
+use crate::error::Result;
+use crate::settings::parse_duration;
+#[cfg(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl"))]
+use crate::settings::with_tls_pem;
+#[cfg(not(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl")))]
+use crate::settings::TLS_REQUIRED;
+use crate::{Client, ConnectOptions, EndpointConfig};
+use std::time::Duration;
+
+/// The endpoint connected to if no endpoint is set, as by `etcdctl`.
+const DEFAULT_ENDPOINT: &str = "127.0.0.1:2379";
+
+/// The prefixes of the variables, in decreasing precedence.
+const PREFIXES: [&str; 2] = ["ETCDCTL_", "ETCD_"];
+
+/// The variables of `etcdctl` this crate has no equivalent of.
+const UNSUPPORTED: [&str; 3] = [
+    "DISCOVERY_SRV",
+    "DISCOVERY_SRV_NAME",
+    "INSECURE_SKIP_TLS_VERIFY",
+];
+
+impl ConnectOptions {
+    /// Creates the options set by the environment variables of `etcdctl`, failing with an
+    /// [`Error::InvalidOptions`](crate::Error::InvalidOptions) listing all the variables
+    /// which are malformed, missing or contradict each other.
+    ///
+    /// Every setting is read from its `ETCDCTL_` variable, or else from its `ETCD_` one,
+    /// the two must not be set to different values. Empty variables are ignored.
+    ///
+    /// - `DIAL_TIMEOUT`: [`ConnectOptions::with_connect_timeout`],
+    /// - `COMMAND_TIMEOUT`: [`ConnectOptions::with_request_timeout`],
+    /// - `KEEPALIVE_TIME` and `KEEPALIVE_TIMEOUT`: [`ConnectOptions::with_keep_alive`], the
+    ///   timeout defaults to [`DEFAULT_KEEP_ALIVE_TIMEOUT`] but requires the time,
+    /// - `USER`, as `name` or `name:password`, and `PASSWORD`: `ConnectOptions::with_user`,
+    ///   the password is set by exactly one of them,
+    /// - `CACERT`, and `CERT` with `KEY`: the paths of the PEM files of the CA certificate,
+    ///   and of the certificate and private key of the client, for the TLS options.
+    ///
+    /// The timeouts are in the format of `etcdctl`, e.g. `5s`, `1.5s`, `1m30s` or `300ms`.
+    /// The options are the base the setters called afterwards override, e.g.
+    /// `ConnectOptions::from_env()?.with_connect_timeout(timeout)` ignores `DIAL_TIMEOUT`.
+    #[cfg_attr(docsrs, doc(cfg(feature = "env")))]
+    pub fn from_env() -> Result<Self> {
+        load(|name| std::env::var(name).ok()).map(|(_, options)| options)
+    }
+}
+
+impl EndpointConfig {
+    /// The endpoints set by the `ETCDCTL_ENDPOINTS` variable, or else by `ETCD_ENDPOINTS`,
+    /// separated by commas, `127.0.0.1:2379` if neither is set.
+    ///
+    /// Fails with an [`Error::InvalidOptions`](crate::Error::InvalidOptions) naming the
+    /// invalid endpoints.
+    #[cfg_attr(docsrs, doc(cfg(feature = "env")))]
+    pub fn from_env() -> Result<Vec<Self>> {
+        let mut env = Env::new(|name: &str| std::env::var(name).ok());
+        let endpoints = env.endpoints();
+        invalid_options(env.problems)?;
+        Ok(endpoints)
+    }
+}
+
+impl Client {
+    /// Connect to the `etcd` servers set by the environment variables of `etcdctl`, with
+    /// the options they set passed through `configure`, e.g. `|options| options` to use
+    /// them as they are.
+    ///
+    /// The problems of all the variables are reported together, see
+    /// [`EndpointConfig::from_env`] and [`ConnectOptions::from_env`]. The options set by
+    /// `configure` override the ones of the environment.
+    #[cfg_attr(docsrs, doc(cfg(feature = "env")))]
+    pub async fn connect_from_env(
+        configure: impl FnOnce(ConnectOptions) -> ConnectOptions,
+    ) -> Result<Self> {
+        let (endpoints, options) = load(|name| std::env::var(name).ok())?;
+        Self::connect_with_endpoints(endpoints, Some(configure(options))).await
+    }
+}
+
+/// Loads the endpoints and the options from the variables returned by `lookup`.
+fn load(lookup: impl Fn(&str) -> Option<String>) -> Result<(Vec<EndpointConfig>, ConnectOptions)> {
+    let mut env = Env::new(lookup);
+    let endpoints = env.endpoints();
+    let mut options = ConnectOptions::new();
+    if let Some((_, timeout)) = env.duration("DIAL_TIMEOUT") {
+        options = options.with_connect_timeout(timeout);
+    }
+    if let Some((_, timeout)) = env.duration("COMMAND_TIMEOUT") {
+        options = options.with_request_timeout(timeout);
+    }
+    match (
+        env.duration("KEEPALIVE_TIME"),
+        env.duration("KEEPALIVE_TIMEOUT"),
+    ) {
+        (Some((_, interval)), timeout) => {
+            let timeout = timeout.map_or(DEFAULT_KEEP_ALIVE_TIMEOUT, |(_, timeout)| timeout);
+            options = options.with_keep_alive(interval, timeout);
+        }
+        (None, Some((var, _))) => env
+            .problems
+            .push(format!("{} is set without ETCDCTL_KEEPALIVE_TIME", var)),
+        (None, None) => {}
+    }
+    let options = env.user(options);
+    let options = env.tls(options);
+    for name in UNSUPPORTED {
+        if let Some((var, _)) = env.var(name) {
+            env.problems.push(format!("{} is not supported", var));
+        }
+    }
+    invalid_options(env.problems)?;
+    Ok((endpoints, options))
+}
+
+/// The variables of `lookup`, collecting their problems.
+struct Env<F> {
+    lookup: F,
+    problems: Vec<String>,
+}
+
+impl<F: Fn(&str) -> Option<String>> Env<F> {
+    #[inline]
+    fn new(lookup: F) -> Self {
+        Self {
+            lookup,
+            problems: Vec::new(),
+        }
+    }
+
+    /// The name and the value of the variable of the setting `name` of highest precedence,
+    /// if any is set.
+    fn var(&mut self, name: &str) -> Option<(String, String)> {
+        let mut found: Option<(String, String)> = None;
+        for prefix in PREFIXES {
+            let var = format!("{}{}", prefix, name);
+            let Some(value) = (self.lookup)(&var).filter(|value| !value.is_empty()) else {
+                continue;
+            };
+            match &found {
+                None => found = Some((var, value)),
+                // The values are not shown, they may be passwords.
+                Some((first, first_value)) if *first_value != value => self
+                    .problems
+                    .push(format!("{} and {} are set to different values", first, var)),
+                Some(_) => {}
+            }
+        }
+        found
+    }
+
+    /// The variable of the setting `name` and the duration it is set to, if any.
+    fn duration(&mut self, name: &str) -> Option<(String, Duration)> {
+        let (var, value) = self.var(name)?;
+        match parse_duration(&value) {
+            Some(duration) => Some((var, duration)),
+            None => {
+                self.problems.push(format!(
+                    "{} is not a duration, expected e.g. \"5s\" or \"1m30s\", found {:?}",
+                    var, value
+                ));
+                None
+            }
+        }
+    }
+
+    /// The endpoints set by the variables, or the default one.
+    fn endpoints(&mut self) -> Vec<EndpointConfig> {
+        let Some((var, value)) = self.var("ENDPOINTS") else {
+            return vec![EndpointConfig::new(DEFAULT_ENDPOINT)];
+        };
+        let mut endpoints = Vec::new();
+        for endpoint in value.split(',').map(str::trim) {
+            match normalize_url(endpoint) {
+                Ok(_) => endpoints.push(EndpointConfig::new(endpoint)),
+                Err(reason) => self.problems.push(format!(
+                    "{} has the invalid endpoint {:?}: {}",
+                    var, endpoint, reason
+                )),
+            }
+        }
+        endpoints
+    }
+
+    /// Sets the user of `options` set by the variables, if any.
+    fn user(&mut self, options: ConnectOptions) -> ConnectOptions {
+        let (name, password) = match (self.var("USER"), self.var("PASSWORD")) {
+            (None, None) => return options,
+            (None, Some((var, _))) => {
+                self.problems
+                    .push(format!("{} is set without ETCDCTL_USER", var));
+                return options;
+            }
+            (Some((var, user)), password) => match (user.split_once(':'), password) {
+                (Some((name, password)), None) => (name.to_owned(), password.to_owned()),
+                (None, Some((_, password))) => (user, password),
+                (Some(_), Some((password_var, _))) => {
+                    self.problems.push(format!(
+                        "{} sets a password, and so does {}",
+                        var, password_var
+                    ));
+                    return options;
+                }
+                (None, None) => {
+                    self.problems.push(format!(
+                        "{} is set without a password, set it as name:password or set \
+                         ETCDCTL_PASSWORD",
+                        var
+                    ));
+                    return options;
+                }
+            },
+        };
+
+        #[cfg(feature = "auth")]
+        {
+            options.with_user(name, password)
+        }
+        #[cfg(not(feature = "auth"))]
+        {
+            let _ = (name, password);
+            self.problems
+                .push(String::from("ETCDCTL_USER requires the `auth` feature"));
+            options
+        }
+    }
+
+    /// Sets the TLS options of `options` set by the variables, if any.
+    #[cfg(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl"))]
+    fn tls(&mut self, options: ConnectOptions) -> ConnectOptions {
+        let ca = self.var("CACERT");
+        let identity = match (self.var("CERT"), self.var("KEY")) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (Some((var, _)), None) => {
+                self.problems
+                    .push(format!("{} is set without ETCDCTL_KEY", var));
+                None
+            }
+            (None, Some((var, _))) => {
+                self.problems
+                    .push(format!("{} is set without ETCDCTL_CERT", var));
+                None
+            }
+            (None, None) => None,
+        };
+        if ca.is_none() && identity.is_none() {
+            return options;
+        }
+
+        let ca = ca.and_then(|ca| self.read(ca));
+        let identity = identity.and_then(|(cert, key)| Some((self.read(cert)?, self.read(key)?)));
+
+        with_tls_pem(options, ca, identity)
+    }
+
+    /// Reports the TLS variables set, without a TLS implementation to use them.
+    #[cfg(not(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl")))]
+    fn tls(&mut self, options: ConnectOptions) -> ConnectOptions {
+        for name in ["CACERT", "CERT", "KEY"] {
+            if let Some((var, _)) = self.var(name) {
+                self.problems.push(format!("{} {}", var, TLS_REQUIRED));
+            }
+        }
+        options
+    }
+
+    /// The content of the file named by the variable `var`.
+    #[cfg(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl"))]
+    fn read(&mut self, (var, path): (String, String)) -> Option<Vec<u8>> {
+        match std::fs::read(&path) {
+            Ok(content) => Some(content),
+            Err(e) => {
+                self.problems.push(format!(
+                    "{} names {:?}, which can not be read: {}",
+                    var, path, e
+                ));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::collections::HashMap;
+
+    fn load_from(vars: &[(&str, &str)]) -> Result<(Vec<EndpointConfig>, ConnectOptions)> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        load(|name| vars.get(name).cloned())
+    }
+
+    fn problems(vars: &[(&str, &str)]) -> String {
+        match load_from(vars) {
+            Err(Error::InvalidOptions(problems)) => problems,
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("{:?} are valid", vars),
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let (endpoints, options) = load_from(&[]).unwrap();
+        let urls: Vec<_> = endpoints.iter().map(EndpointConfig::url).collect();
+        assert_eq!(urls, [DEFAULT_ENDPOINT]);
+        assert!(format!("{:?}", options).contains("connect_timeout: None"));
+
+        let (endpoints, options) = load_from(&[
+            ("ETCDCTL_ENDPOINTS", "http://10.0.0.1:2379, 10.0.0.2:2379"),
+            ("ETCD_ENDPOINTS", "http://10.0.0.1:2379, 10.0.0.2:2379"),
+            ("ETCDCTL_DIAL_TIMEOUT", "2s"),
+            ("ETCD_COMMAND_TIMEOUT", "1m"),
+            ("ETCDCTL_KEEPALIVE_TIME", "500ms"),
+            ("ETCDCTL_USER", ""),
+        ])
+        .unwrap();
+        let urls: Vec<_> = endpoints.iter().map(EndpointConfig::url).collect();
+        assert_eq!(urls, ["http://10.0.0.1:2379", "10.0.0.2:2379"]);
+        let options = format!("{:?}", options);
+        assert!(options.contains("connect_timeout: Some(2s)"), "{}", options);
+        assert!(
+            options.contains("request_timeout: Some(60s)"),
+            "{}",
+            options
+        );
+        assert!(
+            options.contains("keep_alive_interval: Some(500ms)"),
+            "{}",
+            options
+        );
+        assert!(
+            options.contains("keep_alive_timeout: Some(20s)"),
+            "{}",
+            options
+        );
+    }
+
+    #[test]
+    fn test_load_problems() {
+        // All the problems are reported together.
+        let problems = problems(&[
+            ("ETCDCTL_ENDPOINTS", "http://10.0.0.1:2379,,ftp://10.0.0.2"),
+            ("ETCDCTL_DIAL_TIMEOUT", "2s"),
+            ("ETCD_DIAL_TIMEOUT", "3s"),
+            ("ETCD_COMMAND_TIMEOUT", "5"),
+            ("ETCDCTL_KEEPALIVE_TIMEOUT", "5s"),
+            ("ETCDCTL_INSECURE_SKIP_TLS_VERIFY", "true"),
+        ]);
+        for problem in [
+            r#"ETCDCTL_ENDPOINTS has the invalid endpoint "": the URL is empty"#,
+            r#"ETCDCTL_ENDPOINTS has the invalid endpoint "ftp://10.0.0.2": unsupported scheme "ftp""#,
+            "ETCDCTL_DIAL_TIMEOUT and ETCD_DIAL_TIMEOUT are set to different values",
+            r#"ETCD_COMMAND_TIMEOUT is not a duration, expected e.g. "5s" or "1m30s", found "5""#,
+            "ETCDCTL_KEEPALIVE_TIMEOUT is set without ETCDCTL_KEEPALIVE_TIME",
+            "ETCDCTL_INSECURE_SKIP_TLS_VERIFY is not supported",
+        ] {
+            assert!(
+                problems.contains(problem),
+                "{:?} in {:?}",
+                problem,
+                problems
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_user() {
+        assert!(problems(&[("ETCDCTL_PASSWORD", "secret")])
+            .contains("ETCDCTL_PASSWORD is set without ETCDCTL_USER"));
+        assert!(problems(&[("ETCDCTL_USER", "root")])
+            .contains("ETCDCTL_USER is set without a password"));
+        let problems = problems(&[("ETCDCTL_USER", "root:secret"), ("ETCD_PASSWORD", "other")]);
+        assert!(problems.contains("ETCDCTL_USER sets a password, and so does ETCD_PASSWORD"));
+        assert!(!problems.contains("secret"), "{}", problems);
+
+        for vars in [
+            &[("ETCDCTL_USER", "root:secret")][..],
+            &[("ETCD_USER", "root"), ("ETCDCTL_PASSWORD", "secret")][..],
+        ] {
+            #[cfg(feature = "auth")]
+            {
+                let (_, options) = load_from(vars).unwrap();
+                let options = format!("{:?}", options);
+                assert!(
+                    options.contains(r#"user: Some(("root", "secret"))"#),
+                    "{}",
+                    options
+                );
+            }
+            #[cfg(not(feature = "auth"))]
+            assert!(problems(vars).contains("requires the `auth` feature"));
+        }
+    }
+
+    #[test]
+    fn test_load_tls() {
+        #[cfg(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl"))]
+        {
+            assert!(problems(&[("ETCDCTL_CERT", "client.pem")])
+                .contains("ETCDCTL_CERT is set without ETCDCTL_KEY"));
+            assert!(problems(&[("ETCD_KEY", "client.key")])
+                .contains("ETCD_KEY is set without ETCDCTL_CERT"));
+        }
+
+        let dir = std::env::temp_dir().join(format!("etcd-client-env-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ca = dir.join("ca.pem");
+        std::fs::write(&ca, "").unwrap();
+        let ca = ca.to_str().unwrap();
+        let missing = dir.join("missing.pem");
+        let missing = missing.to_str().unwrap();
+
+        let problems = problems(&[("ETCDCTL_CACERT", missing)]);
+        #[cfg(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl"))]
+        assert!(
+            problems.contains(&format!(
+                "ETCDCTL_CACERT names {:?}, which can not be read",
+                missing
+            )),
+            "{}",
+            problems
+        );
+        #[cfg(not(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl")))]
+        assert!(problems.contains("ETCDCTL_CACERT requires the `tls-ring`"));
+
+        #[cfg(all(
+            any(feature = "tls-ring", feature = "tls-aws-lc"),
+            not(feature = "tls-openssl")
+        ))]
+        {
+            let (_, options) = load_from(&[("ETCDCTL_CACERT", ca)]).unwrap();
+            assert!(format!("{:?}", options).contains("tls: Some("));
+        }
+        #[cfg(any(
+            not(any(feature = "tls-ring", feature = "tls-aws-lc")),
+            feature = "tls-openssl"
+        ))]
+        let _ = ca;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
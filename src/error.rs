@@ -1,16 +1,85 @@
 //! Etcd Client Error handling.
 
+use crate::bytes::DebugBytes;
+use crate::ids::{LeaseId, Revision};
+#[cfg(any(feature = "kv", feature = "maintenance"))]
+use crate::route::RouteTo;
+#[cfg(all(feature = "kv", feature = "watch"))]
+use crate::rpc::kv::GetResponse;
 use std::fmt::{Display, Formatter};
 use std::str::Utf8Error;
+use tonic::metadata::{MetadataMap, MetadataValue};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// The error type for `etcd` client.
+///
+/// # Predicates
+///
+/// The predicates classify errors by their typed variant, or by the gRPC code of errors
+/// without one:
+///
+/// | etcd error | predicate |
+/// |---|---|
+/// | transport failure, `Unavailable` | [`Error::is_retryable`] |
+/// | no leader, leader changed, request timed out | [`Error::is_retryable`] |
+/// | key, lease, member, user or role not found, `NotFound` | [`Error::is_not_found`] |
+/// | key, member, peer URLs, lease, user or role exists, `AlreadyExists` | [`Error::is_already_exists`] |
+/// | permission denied, `PermissionDenied` | [`Error::is_permission_denied`] |
+/// | too many txn operations, duplicate key, request too large, `InvalidArgument` | [`Error::is_invalid_argument`] |
+/// | required revision has been compacted | [`Error::is_compacted`] |
+///
+/// ```
+/// use etcd_client::Error;
+/// use tonic::{Code, Status};
+///
+/// let table: &[(Code, &str, fn(&Error) -> bool)] = &[
+///     (Code::Unavailable, "etcdserver: no leader", Error::is_retryable),
+///     (Code::Unavailable, "etcdserver: leader changed", Error::is_retryable),
+///     (Code::Unavailable, "etcdserver: request timed out", Error::is_retryable),
+///     (Code::InvalidArgument, "etcdserver: key not found", Error::is_not_found),
+///     (Code::NotFound, "etcdserver: requested lease not found", Error::is_not_found),
+///     (Code::NotFound, "etcdserver: member not found", Error::is_not_found),
+///     (Code::FailedPrecondition, "etcdserver: user name not found", Error::is_not_found),
+///     (Code::FailedPrecondition, "etcdserver: role name not found", Error::is_not_found),
+///     (Code::FailedPrecondition, "etcdserver: member ID already exist", Error::is_already_exists),
+///     (Code::FailedPrecondition, "etcdserver: Peer URLs already exists", Error::is_already_exists),
+///     (Code::FailedPrecondition, "etcdserver: lease already exists", Error::is_already_exists),
+///     (Code::FailedPrecondition, "etcdserver: user name already exists", Error::is_already_exists),
+///     (Code::FailedPrecondition, "etcdserver: role name already exists", Error::is_already_exists),
+///     (Code::PermissionDenied, "etcdserver: permission denied", Error::is_permission_denied),
+///     (Code::InvalidArgument, "etcdserver: too many operations in txn request", Error::is_invalid_argument),
+///     (Code::InvalidArgument, "etcdserver: duplicate key given in txn request", Error::is_invalid_argument),
+///     (Code::InvalidArgument, "etcdserver: request is too large", Error::is_invalid_argument),
+///     (Code::InvalidArgument, "etcdserver: key is not provided", Error::is_invalid_argument),
+///     (Code::OutOfRange, "etcdserver: mvcc: required revision has been compacted", Error::is_compacted),
+/// ];
+/// for (code, message, predicate) in table {
+///     assert!(predicate(&Error::from(Status::new(*code, *message))), "{}", message);
+/// }
+///
+/// // A key which was not found is not an invalid argument, although etcd says so.
+/// let err = Error::from(Status::invalid_argument("etcdserver: key not found"));
+/// assert!(!err.is_invalid_argument());
+/// ```
 #[derive(Debug)]
 pub enum Error {
     /// Invalid arguments
     InvalidArgs(String),
 
+    /// Invalid connect options, listing all the problems found, see
+    /// [`ConnectOptionsBuilder::build`](crate::ConnectOptionsBuilder::build)
+    InvalidOptions(String),
+
+    /// Invalid key or value of custom metadata
+    InvalidMetadata(String),
+
+    /// Custom metadata under a key the client or gRPC rely on, e.g. `authorization`
+    ReservedMetadata {
+        /// The reserved key.
+        key: String,
+    },
+
     /// Invalid URI
     InvalidUri(http::uri::InvalidUri),
 
@@ -41,9 +110,311 @@ pub enum Error {
     /// Endpoint error
     EndpointError(String),
 
+    /// Connecting to etcd failed, raised by [`Client::connect`](crate::Client::connect)
+    Connect(ConnectError),
+
     /// Endpoint set is not managed by this client
     EndpointsNotManaged,
 
+    /// The balanced channel of the client is closed, e.g. as the task forwarding the
+    /// endpoint changes to it died, endpoints can no longer be added or removed
+    BalancerGone,
+
+    /// Member is not part of the cluster
+    MemberNotFound(u64),
+
+    /// Key exists already, e.g. a barrier held by someone else
+    KeyExists(Vec<u8>),
+
+    /// STM transaction kept conflicting with concurrent writes, see
+    /// [`KvClient::stm`](crate::KvClient::stm)
+    StmRetriesExhausted {
+        /// The number of times the transaction ran again.
+        retries: u32,
+    },
+
+    /// RPC is not implemented by the server
+    UnsupportedByServer {
+        /// The name of the RPC.
+        rpc: &'static str,
+        /// The version of the server, if it could be retrieved.
+        server_version: Option<String>,
+    },
+
+    /// Call was cancelled by its cancellation token
+    Cancelled {
+        /// The name of the RPC.
+        rpc: &'static str,
+    },
+
+    /// Call did not complete before its deadline
+    Deadline {
+        /// The name of the RPC.
+        rpc: &'static str,
+        /// The time elapsed since the call started.
+        elapsed: std::time::Duration,
+    },
+
+    /// A background task of the client panicked, the calls relying on it can not complete
+    InternalTaskFailed {
+        /// The name of the task.
+        task: &'static str,
+        /// The message of the panic.
+        panic_message: String,
+    },
+
+    /// Blocking client was called from within an async runtime, whose thread it would block
+    BlockingInRuntime,
+
+    /// Snapshot checksum does not match the one sent by etcd
+    SnapshotChecksumMismatch {
+        /// The checksum sent by etcd.
+        expected: Vec<u8>,
+        /// The checksum computed over the received snapshot.
+        actual: Vec<u8>,
+    },
+
+    /// Requested key was not found, e.g. by a lease attach
+    KeyNotFound {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Requested revision has been compacted
+    Compacted {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Requested revision is a future revision
+    FutureRevision {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Backend database space quota is exhausted, see the `NOSPACE` alarm
+    NoSpace {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Requested lease was not found, it may have expired or been revoked
+    LeaseNotFound {
+        /// The ID of the lease, if known by the request.
+        id: Option<LeaseId>,
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Lease expired or was revoked, e.g. while a put checked with
+    /// [`PutOptions::with_lease_checked`](crate::PutOptions::with_lease_checked) was sent
+    LeaseExpired {
+        /// The ID of the lease.
+        id: LeaseId,
+        /// The original gRPC status, if the request failed because of the lease.
+        status: Option<tonic::Status>,
+    },
+
+    /// Requested lease TTL exceeds the maximum
+    LeaseTtlTooLarge {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Requested lease TTL is under the one second granularity of leases, see
+    /// [`KvClient::put_with_ttl`](crate::KvClient::put_with_ttl)
+    LeaseTtlTooSmall {
+        /// The TTL requested.
+        ttl: std::time::Duration,
+    },
+
+    /// Lease has too little of its TTL left to be kept alive in time, see
+    /// [`Session::adopt`](crate::Session::adopt)
+    LeaseTooShort {
+        /// The ID of the lease.
+        id: LeaseId,
+        /// The seconds left before the lease expires.
+        ttl: i64,
+        /// The seconds needed at least.
+        min: i64,
+    },
+
+    /// Session was adopted already by another process, from the same handle, see
+    /// [`Session::adopt`](crate::Session::adopt)
+    SessionAdopted {
+        /// The ID of the lease of the session.
+        id: LeaseId,
+        /// The generation of the handle adopted twice.
+        generation: u64,
+    },
+
+    /// Txn request has more operations than the server allows, see `--max-txn-ops`
+    TxnTooManyOps {
+        /// The maximum number of operations of a txn, if known. etcd does not report it,
+        /// [`KvClient::txn_chunked`](crate::KvClient::txn_chunked) probes it once a chunk is
+        /// rejected.
+        limit: Option<usize>,
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Txn operations can not be applied by chunks, as one is a txn with compares which
+    /// would only guard the operations of its own chunk, see
+    /// [`KvClient::txn_chunked`](crate::KvClient::txn_chunked)
+    TxnNotSplittable {
+        /// The index of the operation with compares.
+        op: usize,
+    },
+
+    /// Delete would remove more keys than allowed, see
+    /// [`DeleteOptions::with_max_deletions`](crate::DeleteOptions::with_max_deletions)
+    DeletionLimitExceeded {
+        /// The number of keys the delete would have removed.
+        would_delete: i64,
+        /// The maximum number of keys to delete.
+        limit: i64,
+    },
+
+    /// Member a call is routed to can not be reached, and the client routes strictly, see
+    /// [`ConnectOptions::with_strict_routing`](crate::ConnectOptions::with_strict_routing)
+    #[cfg(any(feature = "kv", feature = "maintenance"))]
+    RouteUnreachable {
+        /// The route of the call.
+        route: RouteTo,
+        /// Why the member can not be reached.
+        source: Box<Error>,
+    },
+
+    /// Read observed an older revision than the client observed before, from every member
+    /// it was tried against, see
+    /// [`Client::with_ordering_guard`](crate::Client::with_ordering_guard)
+    StaleRead {
+        /// The latest revision the read observed.
+        seen: Revision,
+        /// The revision the client observed before.
+        required: Revision,
+    },
+
+    /// Value of a key can not be decoded, by the codec of the client or from JSON, see
+    /// [`Client::with_value_codec`](crate::Client::with_value_codec)
+    #[cfg(feature = "kv")]
+    ValueDecode {
+        /// The key of the value.
+        key: Vec<u8>,
+        /// Why the value can not be decoded.
+        source: std::io::Error,
+    },
+
+    /// Txn request modifies the same key more than once
+    DuplicateKey {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Request exceeds the size the server or gRPC allows
+    RequestTooLarge {
+        /// The size of the request in bytes, if known.
+        size: Option<usize>,
+        /// The size limit in bytes, if reported by gRPC or configured by
+        /// [`SizeAccountingOptions::with_max_request_bytes`](crate::SizeAccountingOptions::with_max_request_bytes).
+        limit: Option<usize>,
+        /// The original gRPC status, `None` if the client refused to send the request.
+        status: Option<tonic::Status>,
+    },
+
+    /// Server is rate limiting requests
+    TooManyRequests {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Client refused to send the request, exceeding its rate limit, see
+    /// [`ConnectOptions::with_rate_limit`](crate::ConnectOptions::with_rate_limit).
+    RateLimited {
+        /// How long until the rate limit allows the request.
+        retry_after: std::time::Duration,
+        /// The gRPC status failing the request.
+        status: tonic::Status,
+    },
+
+    /// User lacks the permission for the request
+    PermissionDenied {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// User name or password is invalid
+    AuthFailed {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Auth token is invalid or has expired
+    InvalidAuthToken {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Cluster has no leader
+    NoLeader {
+        /// When the client started to believe the cluster has no leader, set if the client
+        /// failed the request at once rather than sending it, see
+        /// [`ConnectOptions::with_fail_fast_on_no_leader`](crate::ConnectOptions::with_fail_fast_on_no_leader).
+        since: Option<std::time::Instant>,
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Request must be served by the leader
+    NotLeader {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Leader changed while the request was processed
+    LeaderChanged {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Request timed out on the server, e.g. because the leader failed
+    Timeout {
+        /// The original gRPC status.
+        status: tonic::Status,
+    },
+
+    /// Watch following a get could not be created, raised by
+    /// [`WatchClient::get_and_watch`](crate::WatchClient::get_and_watch)
+    #[cfg(all(feature = "kv", feature = "watch"))]
+    WatchAfterGetFailed {
+        /// The response of the get.
+        get: Box<GetResponse>,
+        /// The revision the get was served at.
+        revision: Revision,
+        /// `true` if the get response can still be relied on, the watch may be created
+        /// again from the revision after `revision`; `false` if the keys must be read again.
+        resumable: bool,
+        /// The error creating the watch.
+        source: Box<Error>,
+    },
+
+    /// Member answered an HTTP probe with an unexpected status or body, raised by
+    /// [`MaintenanceClient::http_health`](crate::MaintenanceClient::http_health)
+    #[cfg(feature = "http-probe")]
+    UnexpectedHttpResponse {
+        /// The endpoint probed.
+        endpoint: String,
+        /// The HTTP status of the response.
+        status: http::StatusCode,
+        /// The body of the response.
+        body: String,
+    },
+
+    /// Replayed calls diverged from their recording, raised by
+    /// [`replay::verify`](crate::replay::verify)
+    #[cfg(feature = "record-replay")]
+    ReplayDiverged(String),
+
     /// OpenSSL errors.
     #[cfg(feature = "tls-openssl")]
     OpenSsl(openssl::error::ErrorStack),
@@ -52,8 +423,16 @@ pub enum Error {
 impl Display for Error {
     #[inline]
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let rpc = self.as_status().and_then(status_rpc);
+        if let Some(rpc) = rpc {
+            write!(f, "{} failed: ", rpc)?;
+        }
         match self {
+            Error::GRpcStatus(e) if rpc.is_some() => write!(f, "{:?}: {}", e.code(), e.message()),
             Error::InvalidArgs(e) => write!(f, "invalid arguments: {}", e),
+            Error::InvalidOptions(e) => write!(f, "invalid connect options: {}", e),
+            Error::InvalidMetadata(e) => write!(f, "invalid metadata: {}", e),
+            Error::ReservedMetadata { key } => write!(f, "metadata key {} is reserved", key),
             Error::InvalidUri(e) => write!(f, "invalid uri: {}", e),
             Error::IoError(e) => write!(f, "io error: {}", e),
             Error::TransportError(e) => write!(f, "transport error: {}", e),
@@ -64,14 +443,915 @@ impl Display for Error {
             Error::ElectError(e) => write!(f, "election error: {}", e),
             Error::InvalidHeaderValue(e) => write!(f, "invalid metadata value: {}", e),
             Error::EndpointError(e) => write!(f, "endpoint error: {}", e),
+            Error::Connect(e) => write!(f, "{}", e),
             Error::EndpointsNotManaged => write!(f, "endpoints not managed by this client"),
+            Error::BalancerGone => write!(f, "balanced channel of the client is closed"),
+            Error::MemberNotFound(id) => write!(f, "member {:x} not found", id),
+            Error::KeyExists(key) => write!(f, "key {:?} exists already", DebugBytes(key)),
+            Error::StmRetriesExhausted { retries } => {
+                write!(
+                    f,
+                    "stm transaction still conflicting after {} retries",
+                    retries
+                )
+            }
+            Error::UnsupportedByServer {
+                rpc,
+                server_version: Some(version),
+            } => write!(f, "{} is not supported by server version {}", rpc, version),
+            Error::UnsupportedByServer {
+                rpc,
+                server_version: None,
+            } => write!(f, "{} is not supported by server", rpc),
+            Error::Cancelled { rpc } => write!(f, "{} was cancelled", rpc),
+            Error::Deadline { rpc, elapsed } => {
+                write!(f, "{} exceeded its deadline after {:?}", rpc, elapsed)
+            }
+            Error::InternalTaskFailed {
+                task,
+                panic_message,
+            } => write!(f, "internal task {} failed: {}", task, panic_message),
+            Error::BlockingInRuntime => {
+                write!(f, "blocking client called from within an async runtime")
+            }
+            Error::SnapshotChecksumMismatch { expected, actual } => write!(
+                f,
+                "snapshot checksum mismatch: expected {}, actual {}",
+                Hex(expected),
+                Hex(actual)
+            ),
+            Error::KeyNotFound { .. } => write!(f, "key not found"),
+            Error::Compacted { .. } => write!(f, "required revision has been compacted"),
+            Error::FutureRevision { .. } => write!(f, "required revision is a future revision"),
+            Error::NoSpace { .. } => write!(f, "database space exceeded"),
+            Error::LeaseNotFound { id: Some(id), .. } => write!(f, "lease {:x} not found", id),
+            Error::LeaseNotFound { id: None, .. } => write!(f, "lease not found"),
+            Error::LeaseExpired { id, .. } => write!(f, "lease {:x} expired", id),
+            Error::LeaseTtlTooLarge { .. } => write!(f, "too large lease TTL"),
+            Error::LeaseTtlTooSmall { ttl } => write!(f, "lease TTL {:?} is under one second", ttl),
+            Error::LeaseTooShort { id, ttl, min } => {
+                write!(
+                    f,
+                    "lease {:x} has {}s left, under the {}s needed",
+                    id, ttl, min
+                )
+            }
+            Error::SessionAdopted { id, generation } => write!(
+                f,
+                "session of lease {:x} at generation {} adopted already",
+                id, generation
+            ),
+            Error::TxnTooManyOps {
+                limit: Some(limit), ..
+            } => write!(f, "txn request has more than {} operations", limit),
+            Error::TxnTooManyOps { limit: None, .. } => {
+                write!(f, "too many operations in txn request")
+            }
+            Error::TxnNotSplittable { op } => {
+                write!(f, "txn operation {} has compares, it can not be split", op)
+            }
+            Error::DeletionLimitExceeded {
+                would_delete,
+                limit,
+            } => write!(
+                f,
+                "delete would remove {} keys, more than the limit of {}",
+                would_delete, limit
+            ),
+            #[cfg(any(feature = "kv", feature = "maintenance"))]
+            Error::RouteUnreachable { route, source } => {
+                write!(f, "{} unreachable: {}", route, source)
+            }
+            Error::StaleRead { seen, required } => write!(
+                f,
+                "read observed revision {}, older than the revision {} observed before",
+                seen, required
+            ),
+            #[cfg(feature = "kv")]
+            Error::ValueDecode { key, source } => write!(
+                f,
+                "failed to decode the value of {:?}: {}",
+                DebugBytes(key),
+                source
+            ),
+            Error::DuplicateKey { .. } => write!(f, "duplicate key given in txn request"),
+            Error::RequestTooLarge {
+                size: Some(size),
+                limit: Some(limit),
+                ..
+            } => write!(
+                f,
+                "request of {} bytes is larger than {} bytes",
+                size, limit
+            ),
+            Error::RequestTooLarge {
+                limit: Some(limit), ..
+            } => write!(f, "request is larger than {} bytes", limit),
+            Error::RequestTooLarge { limit: None, .. } => write!(f, "request is too large"),
+            Error::TooManyRequests { .. } => write!(f, "too many requests"),
+            Error::RateLimited { retry_after, .. } => {
+                write!(f, "rate limited, retry after {:?}", retry_after)
+            }
+            Error::PermissionDenied { .. } => write!(f, "permission denied"),
+            Error::AuthFailed { .. } => write!(f, "authentication failed"),
+            Error::InvalidAuthToken { .. } => write!(f, "invalid auth token"),
+            Error::NoLeader { .. } => write!(f, "no leader"),
+            Error::NotLeader { .. } => write!(f, "not leader"),
+            Error::LeaderChanged { .. } => write!(f, "leader changed"),
+            Error::Timeout { status } => write!(f, "{}", status.message()),
+            #[cfg(all(feature = "kv", feature = "watch"))]
+            Error::WatchAfterGetFailed {
+                revision, source, ..
+            } => match revision.next() {
+                Ok(next) => write!(
+                    f,
+                    "failed to watch from revision {} after get: {}",
+                    next, source
+                ),
+                Err(_) => write!(f, "failed to watch after get: {}", source),
+            },
+            #[cfg(feature = "http-probe")]
+            Error::UnexpectedHttpResponse {
+                endpoint,
+                status,
+                body,
+            } => write!(
+                f,
+                "unexpected HTTP response from {}: {}: {}",
+                endpoint, status, body
+            ),
+            #[cfg(feature = "record-replay")]
+            Error::ReplayDiverged(e) => write!(f, "replay diverged: {}", e),
             #[cfg(feature = "tls-openssl")]
             Error::OpenSsl(e) => write!(f, "open ssl error: {}", e),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    #[inline]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // The wrapped errors are already part of the message, their sources are the next
+        // link of the chain.
+        match self {
+            Error::InvalidUri(e) => e.source(),
+            Error::IoError(e) => e.source(),
+            Error::TransportError(e) => e.source(),
+            Error::GRpcStatus(status) => status.source(),
+            Error::Utf8Error(e) => e.source(),
+            Error::InvalidHeaderValue(e) => e.source(),
+            Error::Connect(e) => e.source(),
+            #[cfg(all(feature = "kv", feature = "watch"))]
+            Error::WatchAfterGetFailed { source, .. } => source.source(),
+            #[cfg(any(feature = "kv", feature = "maintenance"))]
+            Error::RouteUnreachable { source, .. } => source.source(),
+            #[cfg(feature = "kv")]
+            Error::ValueDecode { source, .. } => source.source(),
+            #[cfg(feature = "tls-openssl")]
+            Error::OpenSsl(e) => e.source(),
+            _ => self.as_status().map(|status| status as _),
+        }
+    }
+}
+
+/// The error of connecting to etcd, naming the endpoint involved.
+///
+/// Connections are established lazily, so failures to resolve, connect to or handshake with
+/// an endpoint are only raised while connecting if a user is authenticated, and by the
+/// request which established the connection otherwise.
+#[derive(Debug)]
+pub enum ConnectError {
+    /// Endpoint is invalid, e.g. its URI can not be parsed or its TLS options are invalid
+    InvalidEndpoint {
+        /// The endpoint as given.
+        endpoint: String,
+        /// The error building the endpoint.
+        source: Box<Error>,
+    },
+
+    /// Host name of the endpoint could not be resolved
+    Dns {
+        /// The URI of the endpoint.
+        endpoint: String,
+        /// The error of the request.
+        source: Box<Error>,
+    },
+
+    /// TCP connection to the endpoint could not be established
+    Tcp {
+        /// The URI of the endpoint.
+        endpoint: String,
+        /// The error of the request.
+        source: Box<Error>,
+    },
+
+    /// TLS handshake with the endpoint failed, e.g. its certificate is not trusted
+    TlsHandshake {
+        /// The URI of the endpoint.
+        endpoint: String,
+        /// The error of the request.
+        source: Box<Error>,
+    },
+
+    /// Initial authentication was rejected
+    Auth {
+        /// The URI of the endpoint.
+        endpoint: String,
+        /// The error of the Authenticate request.
+        source: Box<Error>,
+    },
+}
+
+impl ConnectError {
+    /// Classifies the error `err` of the initial request sent to `endpoint`.
+    pub(crate) fn new(endpoint: String, err: Error) -> Self {
+        let source = Box::new(err);
+        if !source.is_transport() {
+            return ConnectError::Auth { endpoint, source };
+        }
+
+        let mut cause: Option<&(dyn std::error::Error + 'static)> = Some(&*source);
+        while let Some(e) = cause {
+            // The messages of the connect errors of hyper.
+            let message = e.to_string();
+            if message.starts_with("dns error") {
+                return ConnectError::Dns { endpoint, source };
+            }
+            if message.starts_with("tcp ") {
+                return ConnectError::Tcp { endpoint, source };
+            }
+            #[cfg(feature = "tls-openssl")]
+            if e.is::<openssl::ssl::Error>() || e.is::<openssl::error::ErrorStack>() {
+                return ConnectError::TlsHandshake { endpoint, source };
+            }
+            // Rustls reports failed handshakes as invalid data.
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::InvalidData)
+            {
+                return ConnectError::TlsHandshake { endpoint, source };
+            }
+            cause = e.source();
+        }
+        // The connection was lost otherwise, e.g. reset by the peer.
+        ConnectError::Tcp { endpoint, source }
+    }
+
+    /// The endpoint involved, or the comma separated endpoints of a balanced channel.
+    #[inline]
+    pub fn endpoint(&self) -> &str {
+        match self {
+            ConnectError::InvalidEndpoint { endpoint, .. }
+            | ConnectError::Dns { endpoint, .. }
+            | ConnectError::Tcp { endpoint, .. }
+            | ConnectError::TlsHandshake { endpoint, .. }
+            | ConnectError::Auth { endpoint, .. } => endpoint,
+        }
+    }
+
+    /// The error of building the endpoint or sending the initial request.
+    #[inline]
+    pub fn error(&self) -> &Error {
+        match self {
+            ConnectError::InvalidEndpoint { source, .. }
+            | ConnectError::Dns { source, .. }
+            | ConnectError::Tcp { source, .. }
+            | ConnectError::TlsHandshake { source, .. }
+            | ConnectError::Auth { source, .. } => source,
+        }
+    }
+}
+
+impl Display for ConnectError {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectError::InvalidEndpoint { endpoint, source } => {
+                write!(f, "invalid endpoint {}: {}", endpoint, source)
+            }
+            ConnectError::Dns { endpoint, source } => {
+                write!(f, "failed to resolve {}: {}", endpoint, source)
+            }
+            ConnectError::Tcp { endpoint, source } => {
+                write!(f, "failed to connect to {}: {}", endpoint, source)
+            }
+            ConnectError::TlsHandshake { endpoint, source } => {
+                write!(f, "TLS handshake with {} failed: {}", endpoint, source)
+            }
+            ConnectError::Auth { endpoint, source } => {
+                write!(f, "failed to authenticate with {}: {}", endpoint, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // The error is already part of the message.
+        self.error().source()
+    }
+}
+
+impl From<ConnectError> for Error {
+    #[inline]
+    fn from(e: ConnectError) -> Self {
+        Error::Connect(e)
+    }
+}
+
+/// The metadata key of a status recording the name of the RPC which failed with it.
+const RPC_METADATA_KEY: &str = "etcd-client-rpc";
+
+/// Returns the name of the RPC recorded in `status`.
+fn status_rpc(status: &tonic::Status) -> Option<&str> {
+    status.metadata().get(RPC_METADATA_KEY)?.to_str().ok()
+}
+
+/// Returns when the client started to believe the cluster has no leader, recorded in
+/// `status` if the client failed the request at once.
+fn no_leader_since(status: &tonic::Status) -> Option<std::time::Instant> {
+    let elapsed = status.metadata().get(crate::leader::NO_LEADER_SINCE_KEY)?;
+    let elapsed = elapsed.to_str().ok()?.parse().ok()?;
+    std::time::Instant::now().checked_sub(std::time::Duration::from_millis(elapsed))
+}
+
+/// Returns how long until the rate limit of the client allows the request, recorded in
+/// `status` if the client refused to send it.
+fn retry_after(status: &tonic::Status) -> std::time::Duration {
+    status
+        .metadata()
+        .get(crate::rate_limit::RETRY_AFTER_KEY)
+        .and_then(|millis| millis.to_str().ok()?.parse().ok())
+        .map_or(std::time::Duration::ZERO, std::time::Duration::from_millis)
+}
+
+/// Attaches the name of the RPC to the error of a gRPC call.
+pub(crate) trait RpcResultExt<T> {
+    /// Converts the status into an [`Error`] of the RPC `rpc`.
+    fn for_rpc(self, rpc: &'static str) -> Result<T>;
+}
+
+impl<T> RpcResultExt<T> for std::result::Result<T, tonic::Status> {
+    #[inline]
+    fn for_rpc(self, rpc: &'static str) -> Result<T> {
+        self.map_err(|status| Error::from(status).with_rpc(rpc))
+    }
+}
+
+/// The gRPC message of etcd when a requested member does not exist.
+const MEMBER_NOT_FOUND_MESSAGE: &str = "etcdserver: member not found";
+
+/// The gRPC message of etcd when a membership change would leave the cluster unhealthy.
+const UNHEALTHY_MESSAGE: &str = "etcdserver: unhealthy cluster";
+
+/// The gRPC messages of etcd when a requested user or role does not exist.
+const FAILED_PRECONDITION_NOT_FOUND_MESSAGES: &[&str] = &[
+    "etcdserver: user name not found",
+    "etcdserver: role name not found",
+];
+
+/// The gRPC messages of etcd when a member, lease, user or role to be created exists.
+const ALREADY_EXISTS_MESSAGES: &[&str] = &[
+    "etcdserver: member ID already exist",
+    "etcdserver: Peer URLs already exists",
+    "etcdserver: lease already exists",
+    "etcdserver: user name already exists",
+    "etcdserver: role name already exists",
+];
+
+/// The gRPC message of etcd when a learner is promoted before being in sync with the leader.
+const LEARNER_NOT_READY_MESSAGE: &str =
+    "etcdserver: can only promote a learner member which is in sync with leader";
+
+/// The gRPC message of etcd when the leadership can not be transferred to the target.
+const BAD_LEADER_TRANSFEREE_MESSAGE: &str = "etcdserver: bad leader transferee";
+
+/// Maps a gRPC status into a typed error.
+type StatusMapping = fn(tonic::Status) -> Error;
+
+/// Maps the etcd errors which are distinguished by gRPC code and message, in etcd 3.4, 3.5
+/// and 3.6, see `api/v3rpc/rpctypes/error.go` of etcd. A message ending with `*` is a prefix.
+const STATUS_ERRORS: &[(tonic::Code, &str, StatusMapping)] = &[
+    (
+        tonic::Code::InvalidArgument,
+        "etcdserver: key not found",
+        |status| Error::KeyNotFound { status },
+    ),
+    (
+        tonic::Code::OutOfRange,
+        "etcdserver: mvcc: required revision has been compacted",
+        |status| Error::Compacted { status },
+    ),
+    (
+        tonic::Code::OutOfRange,
+        "etcdserver: mvcc: required revision is a future revision",
+        |status| Error::FutureRevision { status },
+    ),
+    (
+        tonic::Code::ResourceExhausted,
+        "etcdserver: mvcc: database space exceeded",
+        |status| Error::NoSpace { status },
+    ),
+    (
+        tonic::Code::NotFound,
+        "etcdserver: requested lease not found",
+        |status| Error::LeaseNotFound { id: None, status },
+    ),
+    (
+        tonic::Code::OutOfRange,
+        "etcdserver: too large lease TTL",
+        |status| Error::LeaseTtlTooLarge { status },
+    ),
+    (
+        tonic::Code::InvalidArgument,
+        "etcdserver: too many operations in txn request",
+        |status| Error::TxnTooManyOps {
+            limit: None,
+            status,
+        },
+    ),
+    (
+        tonic::Code::InvalidArgument,
+        "etcdserver: duplicate key given in txn request",
+        |status| Error::DuplicateKey { status },
+    ),
+    (
+        tonic::Code::InvalidArgument,
+        "etcdserver: request is too large",
+        |status| Error::RequestTooLarge {
+            size: None,
+            limit: None,
+            status: Some(status),
+        },
+    ),
+    (
+        tonic::Code::ResourceExhausted,
+        "grpc: received message larger than max*",
+        |status| {
+            let sizes = grpc_sizes(status.message());
+            Error::RequestTooLarge {
+                size: sizes.map(|(size, _)| size),
+                limit: sizes.map(|(_, limit)| limit),
+                status: Some(status),
+            }
+        },
+    ),
+    (
+        tonic::Code::ResourceExhausted,
+        "grpc: trying to send message larger than max*",
+        |status| {
+            let sizes = grpc_sizes(status.message());
+            Error::RequestTooLarge {
+                size: sizes.map(|(size, _)| size),
+                limit: sizes.map(|(_, limit)| limit),
+                status: Some(status),
+            }
+        },
+    ),
+    (
+        tonic::Code::ResourceExhausted,
+        "etcdserver: too many requests",
+        |status| Error::TooManyRequests { status },
+    ),
+    (
+        tonic::Code::ResourceExhausted,
+        crate::rate_limit::RATE_LIMITED_MESSAGE,
+        |status| Error::RateLimited {
+            retry_after: retry_after(&status),
+            status,
+        },
+    ),
+    (
+        tonic::Code::PermissionDenied,
+        "etcdserver: permission denied",
+        |status| Error::PermissionDenied { status },
+    ),
+    (
+        tonic::Code::InvalidArgument,
+        "etcdserver: authentication failed, invalid user ID or password",
+        |status| Error::AuthFailed { status },
+    ),
+    (
+        tonic::Code::Unauthenticated,
+        "etcdserver: invalid auth token",
+        |status| Error::InvalidAuthToken { status },
+    ),
+    (
+        tonic::Code::Unavailable,
+        "etcdserver: no leader",
+        |status| Error::NoLeader {
+            since: no_leader_since(&status),
+            status,
+        },
+    ),
+    (
+        tonic::Code::FailedPrecondition,
+        "etcdserver: not leader",
+        |status| Error::NotLeader { status },
+    ),
+    (
+        tonic::Code::Unavailable,
+        "etcdserver: leader changed",
+        |status| Error::LeaderChanged { status },
+    ),
+    (
+        tonic::Code::Unavailable,
+        "etcdserver: request timed out*",
+        |status| Error::Timeout { status },
+    ),
+];
+
+/// Parses the size and the limit out of a gRPC message like
+/// `... larger than max (5000000 vs. 4194304)`.
+fn grpc_sizes(message: &str) -> Option<(usize, usize)> {
+    let (_, sizes) = message.rsplit_once('(')?;
+    let (size, limit) = sizes.strip_suffix(')')?.split_once(" vs. ")?;
+    Some((size.parse().ok()?, limit.parse().ok()?))
+}
+
+impl Error {
+    /// Maps a gRPC status into a typed error by its code and message, falling back to
+    /// [`Error::GRpcStatus`].
+    fn from_status(status: tonic::Status) -> Self {
+        let mapping = STATUS_ERRORS.iter().find(|(code, message, _)| {
+            *code == status.code()
+                && match message.strip_suffix('*') {
+                    Some(prefix) => status.message().starts_with(prefix),
+                    None => status.message() == *message,
+                }
+        });
+        match mapping {
+            Some((_, _, map)) => map(status),
+            None => Error::GRpcStatus(status),
+        }
+    }
+
+    /// The original gRPC status of the error, if it was returned by the server.
+    #[inline]
+    pub fn as_status(&self) -> Option<&tonic::Status> {
+        match self {
+            Error::GRpcStatus(status)
+            | Error::KeyNotFound { status }
+            | Error::Compacted { status }
+            | Error::FutureRevision { status }
+            | Error::NoSpace { status }
+            | Error::LeaseNotFound { status, .. }
+            | Error::LeaseTtlTooLarge { status }
+            | Error::TxnTooManyOps { status, .. }
+            | Error::DuplicateKey { status }
+            | Error::TooManyRequests { status }
+            | Error::RateLimited { status, .. }
+            | Error::PermissionDenied { status }
+            | Error::AuthFailed { status }
+            | Error::InvalidAuthToken { status }
+            | Error::NoLeader { status, .. }
+            | Error::NotLeader { status }
+            | Error::LeaderChanged { status }
+            | Error::Timeout { status } => Some(status),
+            Error::LeaseExpired {
+                status: Some(status),
+                ..
+            }
+            | Error::RequestTooLarge {
+                status: Some(status),
+                ..
+            } => Some(status),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn as_status_mut(&mut self) -> Option<&mut tonic::Status> {
+        match self {
+            Error::GRpcStatus(status)
+            | Error::KeyNotFound { status }
+            | Error::Compacted { status }
+            | Error::FutureRevision { status }
+            | Error::NoSpace { status }
+            | Error::LeaseNotFound { status, .. }
+            | Error::LeaseTtlTooLarge { status }
+            | Error::TxnTooManyOps { status, .. }
+            | Error::DuplicateKey { status }
+            | Error::TooManyRequests { status }
+            | Error::RateLimited { status, .. }
+            | Error::PermissionDenied { status }
+            | Error::AuthFailed { status }
+            | Error::InvalidAuthToken { status }
+            | Error::NoLeader { status, .. }
+            | Error::NotLeader { status }
+            | Error::LeaderChanged { status }
+            | Error::Timeout { status } => Some(status),
+            Error::LeaseExpired {
+                status: Some(status),
+                ..
+            }
+            | Error::RequestTooLarge {
+                status: Some(status),
+                ..
+            } => Some(status),
+            _ => None,
+        }
+    }
+
+    /// Records the name of the RPC which failed with the status of the error, unless the
+    /// error already names one.
+    pub(crate) fn with_rpc(mut self, rpc: &'static str) -> Self {
+        if let Some(status) = self.as_status_mut() {
+            let metadata = status.metadata_mut();
+            if !metadata.contains_key(RPC_METADATA_KEY) {
+                metadata.insert(RPC_METADATA_KEY, MetadataValue::from_static(rpc));
+            }
+        }
+        self
+    }
+
+    /// The name of the RPC which failed, e.g. `Range`, if known.
+    #[inline]
+    pub fn rpc(&self) -> Option<&str> {
+        match self {
+            Error::UnsupportedByServer { rpc, .. }
+            | Error::Cancelled { rpc }
+            | Error::Deadline { rpc, .. } => Some(rpc),
+            _ => self.as_status().and_then(status_rpc),
+        }
+    }
+
+    /// The gRPC code of the error.
+    ///
+    /// This is the code of the status returned by the server or the transport, or
+    /// [`Cancelled`](tonic::Code::Cancelled) and
+    /// [`DeadlineExceeded`](tonic::Code::DeadlineExceeded) for calls aborted by the client.
+    #[inline]
+    pub fn code(&self) -> Option<tonic::Code> {
+        match self {
+            Error::Cancelled { .. } => Some(tonic::Code::Cancelled),
+            Error::Deadline { .. } => Some(tonic::Code::DeadlineExceeded),
+            _ => self.as_status().map(|status| status.code()),
+        }
+    }
+
+    /// The message of the error, i.e. the gRPC message of a status, or an empty string
+    /// for errors without a message.
+    #[inline]
+    pub fn message(&self) -> &str {
+        match self {
+            Error::InvalidArgs(message)
+            | Error::InvalidOptions(message)
+            | Error::InvalidMetadata(message)
+            | Error::WatchError(message)
+            | Error::LeaseKeepAliveError(message)
+            | Error::ElectError(message)
+            | Error::EndpointError(message) => message,
+            _ => self.as_status().map_or("", |status| status.message()),
+        }
+    }
+
+    /// The metadata of the status returned by the server.
+    ///
+    /// The name of the failed RPC is recorded under the `etcd-client-rpc` key.
+    #[inline]
+    pub fn metadata(&self) -> Option<&MetadataMap> {
+        self.as_status().map(|status| status.metadata())
+    }
+
+    /// The encoded `google.rpc.Status` details of the status returned by the server, sent in
+    /// the `grpc-status-details-bin` metadata.
+    #[inline]
+    pub fn status_details(&self) -> Option<&[u8]> {
+        self.as_status()
+            .map(|status| status.details())
+            .filter(|details| !details.is_empty())
+    }
+
+    /// The `google.rpc.ErrorInfo` of the status details, e.g. telling quota exhaustion from
+    /// other causes of `ResourceExhausted`.
+    #[cfg(feature = "status-details")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "status-details")))]
+    #[inline]
+    pub fn error_info(&self) -> Option<crate::ErrorInfo> {
+        crate::ErrorInfo::decode(self.status_details()?)
+    }
+
+    /// The `google.rpc.QuotaFailure` of the status details.
+    #[cfg(feature = "status-details")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "status-details")))]
+    #[inline]
+    pub fn quota_failure(&self) -> Option<crate::QuotaFailure> {
+        crate::QuotaFailure::decode(self.status_details()?)
+    }
+
+    /// Returns `true` if the error is caused by the transport, e.g. the connection to the
+    /// server could not be established or was lost, rather than by the server.
+    #[inline]
+    pub fn is_transport(&self) -> bool {
+        match self {
+            Error::TransportError(_) => true,
+            Error::Connect(
+                ConnectError::Dns { .. }
+                | ConnectError::Tcp { .. }
+                | ConnectError::TlsHandshake { .. },
+            ) => true,
+            // Tonic keeps the transport error as the source of the status.
+            _ => self
+                .as_status()
+                .is_some_and(|status| std::error::Error::source(status).is_some()),
+        }
+    }
+
+    /// The kind of the I/O error the error was caused by, e.g.
+    /// [`ConnectionRefused`](std::io::ErrorKind::ConnectionRefused) or
+    /// [`ConnectionReset`](std::io::ErrorKind::ConnectionReset) for a transport error.
+    pub fn io_error_kind(&self) -> Option<std::io::ErrorKind> {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = match self {
+            Error::IoError(e) => return Some(e.kind()),
+            Error::Connect(e) => return e.error().io_error_kind(),
+            Error::TransportError(e) => Some(e),
+            _ => self.as_status().map(|status| status as _),
+        };
+        while let Some(e) = source {
+            if let Some(e) = e.downcast_ref::<std::io::Error>() {
+                return Some(e.kind());
+            }
+            source = e.source();
+        }
+        None
+    }
+
+    /// Sets the ID of a lease which was not found.
+    #[inline]
+    pub(crate) fn with_lease_id(self, lease: impl Into<LeaseId>) -> Self {
+        match self {
+            Error::LeaseNotFound { status, .. } => Error::LeaseNotFound {
+                id: Some(lease.into()),
+                status,
+            },
+            e => e,
+        }
+    }
+
+    /// Converts a lease which was not found into the lease `lease` which expired, as it was
+    /// found before the request was sent.
+    #[inline]
+    pub(crate) fn with_lease_expired(self, lease: impl Into<LeaseId>) -> Self {
+        match self {
+            Error::LeaseNotFound { status, .. } => Error::LeaseExpired {
+                id: lease.into(),
+                status: Some(status),
+            },
+            e => e,
+        }
+    }
+
+    /// Returns `true` if the error is caused by requesting a revision that has been compacted.
+    #[inline]
+    pub fn is_compacted(&self) -> bool {
+        matches!(self, Error::Compacted { .. })
+    }
+
+    /// Returns `true` if the error is caused by the server rejecting the compression of the
+    /// request, in which case the client sends uncompressed requests from then on.
+    #[inline]
+    pub fn is_compression_rejected(&self) -> bool {
+        crate::compression::is_rejection(self.code(), self.message())
+    }
+
+    /// Returns `true` if the request may succeed when retried, i.e. the transport failed, or
+    /// the server was unavailable, had no leader, or timed out.
+    ///
+    /// Whether a request which is not idempotent may be retried safely is up to the caller.
+    #[inline]
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::TransportError(_)
+                | Error::NoLeader { .. }
+                | Error::LeaderChanged { .. }
+                | Error::Timeout { .. }
+        ) || self.is_transport()
+            || matches!(self, Error::GRpcStatus(status) if status.code() == tonic::Code::Unavailable)
+    }
+
+    /// Returns `true` if the error is caused by a key, lease, member, user or role that
+    /// does not exist, including a lease which expired.
+    #[inline]
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::KeyNotFound { .. }
+            | Error::LeaseNotFound { .. }
+            | Error::LeaseExpired { .. }
+            | Error::MemberNotFound(_) => true,
+            Error::GRpcStatus(status) => {
+                status.code() == tonic::Code::NotFound
+                    || status.code() == tonic::Code::FailedPrecondition
+                        && FAILED_PRECONDITION_NOT_FOUND_MESSAGES.contains(&status.message())
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the error is caused by creating a key, member, lease, user or role
+    /// that exists already.
+    #[inline]
+    pub fn is_already_exists(&self) -> bool {
+        match self {
+            Error::KeyExists(_) => true,
+            Error::GRpcStatus(status) => {
+                status.code() == tonic::Code::AlreadyExists
+                    || status.code() == tonic::Code::FailedPrecondition
+                        && ALREADY_EXISTS_MESSAGES.contains(&status.message())
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the user lacks the permission for the request.
+    #[inline]
+    pub fn is_permission_denied(&self) -> bool {
+        match self {
+            Error::PermissionDenied { .. } => true,
+            Error::GRpcStatus(status) => status.code() == tonic::Code::PermissionDenied,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the request is rejected because of its arguments, e.g. a txn with
+    /// too many operations or a request which is too large.
+    ///
+    /// Errors reported by etcd as invalid arguments which have a predicate of their own,
+    /// i.e. a key which was not found or failed authentication, are excluded.
+    #[inline]
+    pub fn is_invalid_argument(&self) -> bool {
+        match self {
+            Error::InvalidArgs(_)
+            | Error::InvalidOptions(_)
+            | Error::InvalidMetadata(_)
+            | Error::ReservedMetadata { .. }
+            | Error::TxnTooManyOps { .. }
+            | Error::DuplicateKey { .. }
+            | Error::RequestTooLarge { .. } => true,
+            Error::GRpcStatus(status) => status.code() == tonic::Code::InvalidArgument,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the error is caused by requesting a revision that does not exist yet.
+    #[inline]
+    pub fn is_future_revision(&self) -> bool {
+        matches!(self, Error::FutureRevision { .. })
+    }
+
+    /// Returns `true` if the error is caused by sending a leader-only request to a follower.
+    #[inline]
+    pub fn is_not_leader(&self) -> bool {
+        matches!(self, Error::NotLeader { .. })
+    }
+
+    /// Returns `true` if the error is caused by moving the leadership to an invalid target,
+    /// e.g. a learner or a member that is not in the cluster.
+    #[inline]
+    pub fn is_bad_leader_transferee(&self) -> bool {
+        self.is_status(
+            tonic::Code::FailedPrecondition,
+            BAD_LEADER_TRANSFEREE_MESSAGE,
+        )
+    }
+
+    /// Returns `true` if the error is caused by promoting a learner which is not in sync yet.
+    #[inline]
+    pub fn is_learner_not_ready(&self) -> bool {
+        self.is_status(tonic::Code::FailedPrecondition, LEARNER_NOT_READY_MESSAGE)
+    }
+
+    /// Returns `true` if the error is caused by a member that is not part of the cluster.
+    #[inline]
+    pub fn is_member_not_found(&self) -> bool {
+        matches!(self, Error::MemberNotFound(_))
+            || self.is_status(tonic::Code::NotFound, MEMBER_NOT_FOUND_MESSAGE)
+    }
+
+    /// Returns `true` if the error is caused by a membership change rejected because
+    /// it would leave the cluster unhealthy, e.g. removing a member while others are down.
+    #[inline]
+    pub fn is_unhealthy_cluster(&self) -> bool {
+        self.is_status(tonic::Code::Unavailable, UNHEALTHY_MESSAGE)
+    }
+
+    #[inline]
+    fn is_status(&self, code: tonic::Code, message: &str) -> bool {
+        matches!(self, Error::GRpcStatus(status)
+            if status.code() == code && status.message() == message)
+    }
+}
+
+/// Displays bytes in lowercase hex.
+struct Hex<'a>(&'a [u8]);
+
+impl Display for Hex<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
 
 impl From<http::uri::InvalidUri> for Error {
     #[inline]
@@ -97,7 +1377,7 @@ impl From<tonic::transport::Error> for Error {
 impl From<tonic::Status> for Error {
     #[inline]
     fn from(e: tonic::Status) -> Self {
-        Error::GRpcStatus(e)
+        Error::from_status(e)
     }
 }
 
@@ -122,3 +1402,373 @@ impl From<openssl::error::ErrorStack> for Error {
         Self::OpenSsl(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    type Check = fn(&Error) -> bool;
+
+    #[test]
+    fn test_from_status() {
+        let table: &[(Code, &str, Check)] = &[
+            (Code::InvalidArgument, "etcdserver: key not found", |e| {
+                matches!(e, Error::KeyNotFound { .. })
+            }),
+            (
+                Code::OutOfRange,
+                "etcdserver: mvcc: required revision has been compacted",
+                |e| matches!(e, Error::Compacted { .. }) && e.is_compacted(),
+            ),
+            (
+                Code::OutOfRange,
+                "etcdserver: mvcc: required revision is a future revision",
+                |e| matches!(e, Error::FutureRevision { .. }) && e.is_future_revision(),
+            ),
+            (
+                Code::ResourceExhausted,
+                "etcdserver: mvcc: database space exceeded",
+                |e| matches!(e, Error::NoSpace { .. }),
+            ),
+            (
+                Code::NotFound,
+                "etcdserver: requested lease not found",
+                |e| matches!(e, Error::LeaseNotFound { id: None, .. }),
+            ),
+            (Code::OutOfRange, "etcdserver: too large lease TTL", |e| {
+                matches!(e, Error::LeaseTtlTooLarge { .. })
+            }),
+            (
+                Code::InvalidArgument,
+                "etcdserver: too many operations in txn request",
+                |e| matches!(e, Error::TxnTooManyOps { .. }),
+            ),
+            (
+                Code::InvalidArgument,
+                "etcdserver: duplicate key given in txn request",
+                |e| matches!(e, Error::DuplicateKey { .. }),
+            ),
+            (
+                Code::InvalidArgument,
+                "etcdserver: request is too large",
+                |e| matches!(e, Error::RequestTooLarge { limit: None, .. }),
+            ),
+            (
+                Code::ResourceExhausted,
+                "grpc: received message larger than max (5242880 vs. 4194304)",
+                |e| {
+                    matches!(
+                        e,
+                        Error::RequestTooLarge {
+                            size: Some(5242880),
+                            limit: Some(4194304),
+                            ..
+                        }
+                    )
+                },
+            ),
+            (
+                Code::ResourceExhausted,
+                "grpc: trying to send message larger than max (2097152 vs. 1048576)",
+                |e| {
+                    matches!(
+                        e,
+                        Error::RequestTooLarge {
+                            size: Some(2097152),
+                            limit: Some(1048576),
+                            ..
+                        }
+                    )
+                },
+            ),
+            (
+                Code::ResourceExhausted,
+                "etcdserver: too many requests",
+                |e| matches!(e, Error::TooManyRequests { .. }),
+            ),
+            (Code::ResourceExhausted, "etcd-client: rate limited", |e| {
+                matches!(e, Error::RateLimited { .. })
+            }),
+            (
+                Code::PermissionDenied,
+                "etcdserver: permission denied",
+                |e| matches!(e, Error::PermissionDenied { .. }),
+            ),
+            (
+                Code::InvalidArgument,
+                "etcdserver: authentication failed, invalid user ID or password",
+                |e| matches!(e, Error::AuthFailed { .. }),
+            ),
+            (
+                Code::Unauthenticated,
+                "etcdserver: invalid auth token",
+                |e| matches!(e, Error::InvalidAuthToken { .. }),
+            ),
+            (Code::Unavailable, "etcdserver: no leader", |e| {
+                matches!(e, Error::NoLeader { .. })
+            }),
+            (Code::FailedPrecondition, "etcdserver: not leader", |e| {
+                matches!(e, Error::NotLeader { .. }) && e.is_not_leader()
+            }),
+            (Code::Unavailable, "etcdserver: leader changed", |e| {
+                matches!(e, Error::LeaderChanged { .. })
+            }),
+            (Code::Unavailable, "etcdserver: request timed out", |e| {
+                matches!(e, Error::Timeout { .. })
+            }),
+            (
+                Code::Unavailable,
+                "etcdserver: request timed out, possibly due to previous leader failure",
+                |e| matches!(e, Error::Timeout { .. }),
+            ),
+            (
+                Code::Unavailable,
+                "etcdserver: request timed out, possibly due to connection lost",
+                |e| matches!(e, Error::Timeout { .. }),
+            ),
+            (
+                Code::Unavailable,
+                "etcdserver: request timed out, waiting for the applied index took too long",
+                |e| matches!(e, Error::Timeout { .. }),
+            ),
+            // The code must match as well as the message.
+            (Code::Unknown, "etcdserver: no leader", |e| {
+                matches!(e, Error::GRpcStatus(_))
+            }),
+            (Code::NotFound, "etcdserver: member not found", |e| {
+                matches!(e, Error::GRpcStatus(_)) && e.is_member_not_found()
+            }),
+            (
+                Code::FailedPrecondition,
+                "etcdserver: can only promote a learner member which is in sync with leader",
+                |e| matches!(e, Error::GRpcStatus(_)) && e.is_learner_not_ready(),
+            ),
+        ];
+
+        for (code, message, check) in table {
+            let err = Error::from(tonic::Status::new(*code, *message));
+            assert!(check(&err), "{:?} {} mapped to {:?}", code, message, err);
+            let status = err.as_status().unwrap();
+            assert_eq!(status.code(), *code);
+            assert_eq!(status.message(), *message);
+        }
+    }
+
+    #[test]
+    fn test_source() {
+        use std::error::Error as _;
+
+        let err = Error::from(tonic::Status::unavailable("etcdserver: no leader"));
+        assert_eq!(err.to_string(), "no leader");
+        let source = err.source().unwrap();
+        assert!(source.downcast_ref::<tonic::Status>().is_some());
+
+        let err = Error::from(tonic::Status::unavailable("other"));
+        assert!(err.source().is_none());
+        assert!(Error::EndpointsNotManaged.as_status().is_none());
+        assert!(Error::BalancerGone.as_status().is_none());
+
+        let err = Error::from(tonic::Status::not_found(
+            "etcdserver: requested lease not found",
+        ))
+        .with_lease_id(0x10);
+        assert_eq!(err.to_string(), "lease 10 not found");
+        let err = err.with_lease_expired(0x10);
+        assert_eq!(err.to_string(), "lease 10 expired");
+        assert!(err.is_not_found());
+        assert!(err.source().is_some());
+        let err = Error::LeaseExpired {
+            id: LeaseId::new(0x10),
+            status: None,
+        };
+        assert!(err.source().is_none());
+
+        let err = Error::KeyExists(b"barrier".to_vec());
+        assert_eq!(err.to_string(), r#"key "barrier" exists already"#);
+        assert!(err.is_already_exists());
+
+        let mut status = tonic::Status::unavailable("error trying to connect");
+        status.set_source(std::sync::Arc::new(std::io::Error::from(
+            std::io::ErrorKind::ConnectionRefused,
+        )));
+        let err = Error::from(status);
+        assert!(err.is_transport());
+        let source = err.source().unwrap();
+        assert!(source.downcast_ref::<std::io::Error>().is_some());
+    }
+
+    /// An error of a request which failed with `source`.
+    fn transport_error(source: impl std::error::Error + Send + Sync + 'static) -> Error {
+        let mut status = tonic::Status::unavailable("error trying to connect");
+        status.set_source(std::sync::Arc::new(source));
+        Error::from(status)
+    }
+
+    #[derive(Debug)]
+    struct HyperConnectError(&'static str);
+
+    impl Display for HyperConnectError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl std::error::Error for HyperConnectError {}
+
+    #[test]
+    fn test_connect_error() {
+        let endpoint = || "http://10.0.0.1:2379/".to_string();
+        let err = ConnectError::new(endpoint(), transport_error(HyperConnectError("dns error")));
+        assert!(matches!(err, ConnectError::Dns { .. }));
+        let err = ConnectError::new(
+            endpoint(),
+            transport_error(HyperConnectError("tcp connect error")),
+        );
+        assert!(matches!(err, ConnectError::Tcp { .. }));
+        assert_eq!(err.endpoint(), "http://10.0.0.1:2379/");
+        let err = ConnectError::new(
+            endpoint(),
+            transport_error(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid peer certificate: UnknownIssuer",
+            )),
+        );
+        assert!(matches!(err, ConnectError::TlsHandshake { .. }));
+        let err = Error::from(err);
+        assert!(err.is_transport());
+        assert!(err.is_retryable());
+        assert!(err
+            .to_string()
+            .starts_with("TLS handshake with http://10.0.0.1:2379/ failed"));
+
+        let err = ConnectError::new(
+            endpoint(),
+            Error::from(tonic::Status::invalid_argument(
+                "etcdserver: authentication failed, invalid user ID or password",
+            )),
+        );
+        assert!(matches!(err.error(), Error::AuthFailed { .. }));
+        assert!(matches!(err, ConnectError::Auth { .. }));
+        assert!(!Error::from(err).is_transport());
+    }
+
+    #[test]
+    fn test_accessors() {
+        let err = Err::<(), _>(tonic::Status::unavailable("connection reset"))
+            .for_rpc("Range")
+            .unwrap_err();
+        assert_eq!(err.rpc(), Some("Range"));
+        assert_eq!(err.code(), Some(Code::Unavailable));
+        assert_eq!(err.message(), "connection reset");
+        assert!(err.metadata().unwrap().contains_key(RPC_METADATA_KEY));
+        assert!(!err.is_transport());
+        assert_eq!(
+            err.to_string(),
+            "Range failed: Unavailable: connection reset"
+        );
+
+        // The innermost RPC is kept.
+        let err = err.with_rpc("Status");
+        assert_eq!(err.rpc(), Some("Range"));
+
+        let err = Err::<(), _>(tonic::Status::unavailable("etcdserver: no leader"))
+            .for_rpc("Put")
+            .unwrap_err();
+        assert!(matches!(err, Error::NoLeader { .. }));
+        assert_eq!(err.to_string(), "Put failed: no leader");
+
+        let err = Error::from(tonic::Status::unavailable("connection reset"));
+        assert_eq!(err.rpc(), None);
+        assert_eq!(
+            err.to_string(),
+            "grpc request error: status: Unavailable, message: \"connection reset\", details: [], metadata: MetadataMap { headers: {} }"
+        );
+
+        let err = Error::Deadline {
+            rpc: "Txn",
+            elapsed: std::time::Duration::from_secs(1),
+        };
+        assert_eq!(err.rpc(), Some("Txn"));
+        assert_eq!(err.code(), Some(Code::DeadlineExceeded));
+        assert_eq!(err.message(), "");
+        assert!(err.metadata().is_none());
+
+        let err = Error::from(tonic::Status::with_details(
+            Code::ResourceExhausted,
+            "quota exceeded",
+            vec![8, 8].into(),
+        ));
+        assert_eq!(err.status_details(), Some(&[8, 8][..]));
+
+        let err = Error::WatchError("canceled".to_string());
+        assert_eq!(err.status_details(), None);
+        assert_eq!(err.code(), None);
+        assert_eq!(err.message(), "canceled");
+    }
+
+    #[cfg(feature = "kv")]
+    #[tokio::test]
+    async fn test_status_round_trip() {
+        use crate::auth::AuthToken;
+        use crate::channel::Channel;
+        use crate::intercept::{InterceptedChannel, Interceptor};
+        use crate::rpc::kv::KvClient;
+        use tower::util::BoxCloneService;
+        use tower::ServiceExt;
+
+        let service = tower::service_fn(|_req: http::Request<tonic::body::Body>| async {
+            let mut metadata = MetadataMap::new();
+            metadata.insert("cluster-id", "14841639068965178418".parse().unwrap());
+            let status = tonic::Status::with_metadata(
+                Code::PermissionDenied,
+                "etcdserver: permission denied",
+                metadata,
+            );
+            Ok::<_, tower::BoxError>(status.into_http::<tonic::body::Body>())
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor::default(),
+        );
+        let mut client = KvClient::new(channel, AuthToken::default());
+
+        let err = client.put("key", "value", None).await.unwrap_err();
+        assert!(matches!(err, Error::PermissionDenied { .. }));
+        let status = err.as_status().unwrap();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert_eq!(status.message(), "etcdserver: permission denied");
+        assert_eq!(
+            status.metadata().get("cluster-id").unwrap(),
+            "14841639068965178418"
+        );
+        assert_eq!(err.rpc(), Some("Put"));
+        assert_eq!(err.io_error_kind(), None);
+    }
+
+    #[cfg(feature = "kv")]
+    #[tokio::test]
+    async fn test_io_error_kind() {
+        // A port nothing listens on.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let mut client = crate::Client::connect([endpoint], None).await.unwrap();
+        let err = client.get("key", None).await.unwrap_err();
+        assert!(err.is_transport());
+        assert_eq!(
+            err.io_error_kind(),
+            Some(std::io::ErrorKind::ConnectionRefused)
+        );
+        // The Debug representation prints the whole chain down to the I/O error.
+        assert!(
+            format!("{:?}", err).contains("ConnectionRefused"),
+            "{:?}",
+            err
+        );
+
+        let err = Error::from(std::io::Error::from(std::io::ErrorKind::TimedOut));
+        assert_eq!(err.io_error_kind(), Some(std::io::ErrorKind::TimedOut));
+    }
+}
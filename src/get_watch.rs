@@ -0,0 +1,286 @@
+//! Reading keys, then watching them from the revision they were read at.
+
+use crate::error::{Error, Result};
+use crate::ids::Revision;
+use crate::rpc::kv::{GetOptions, GetResponse};
+use crate::rpc::watch::{WatchClient, WatchOptions, WatchStream, Watcher};
+
+impl WatchClient {
+    /// Gets `key`, then watches the keys read from the revision following the get, so that
+    /// the events of the stream pick up right after the response, without missing or
+    /// repeating a change made in between.
+    ///
+    /// The watch covers the prefix or range of `get_options`, unless `watch_options` sets a
+    /// range of its own. Its start revision is always the one following the get.
+    ///
+    /// If the watch can not be created, fails with an [`Error::WatchAfterGetFailed`]
+    /// holding the get response and its revision, which tells whether the watch may be
+    /// created again from there or the keys must be read again.
+    pub async fn get_and_watch(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        get_options: Option<GetOptions>,
+        watch_options: Option<WatchOptions>,
+    ) -> Result<(GetResponse, Watcher, WatchStream)> {
+        let key = key.into();
+        let get_options = get_options.unwrap_or_default();
+        let mut watch_options = watch_options.unwrap_or_default().or_range_of(&get_options);
+        let get = self.kv.get(key.clone(), Some(get_options)).await?;
+
+        let revision = get
+            .header()
+            .map_or(Revision::current(), |header| header.revision());
+        // Without the revision of the get, the watch can only start from now.
+        if let Ok(next) = revision.next() {
+            watch_options = watch_options.with_start_revision(next);
+        }
+        match self.watch(key, Some(watch_options)).await {
+            Ok((watcher, stream)) => Ok((get, watcher, stream)),
+            Err(e) => Err(Error::WatchAfterGetFailed {
+                get: Box::new(get),
+                revision,
+                resumable: !revision.is_current() && !e.is_compacted(),
+                source: Box::new(e),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::channel::Channel;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::lock::MutexExt;
+    use crate::rpc::pb::etcdserverpb::watch_request::RequestUnion as WatchRequestUnion;
+    use crate::rpc::pb::etcdserverpb::{
+        RangeRequest as PbRangeRequest, RangeResponse as PbRangeResponse,
+        ResponseHeader as PbResponseHeader, WatchCreateRequest, WatchRequest as PbWatchRequest,
+        WatchResponse as PbWatchResponse,
+    };
+    use crate::rpc::pb::mvccpb::{Event as PbEvent, KeyValue as PbKeyValue};
+    use crate::rpc::watch::EventType;
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::mpsc::unbounded_channel;
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tokio_stream::StreamExt;
+    use tonic::codec::ProstCodec;
+    use tonic::server::Grpc;
+    use tonic::{Status, Streaming};
+    use tower::util::BoxCloneService;
+
+    /// A store where `key/a`, put at revision 5, is deleted at revision 6 right after being
+    /// read, before any watch is created.
+    #[derive(Clone, Default)]
+    struct Racing {
+        /// The events of the store, by revision.
+        history: Arc<Mutex<Vec<PbEvent>>>,
+        /// The watches created.
+        creates: Arc<Mutex<Vec<WatchCreateRequest>>>,
+        /// Fails creating watches with `PermissionDenied`.
+        deny_watch: bool,
+    }
+
+    impl Racing {
+        fn client(&self) -> WatchClient {
+            let racing = self.clone();
+            let service = tower::service_fn(move |req: http::Request<tonic::body::Body>| {
+                let racing = racing.clone();
+                async move { Ok::<_, tower::BoxError>(racing.serve(req).await) }
+            });
+            let channel = InterceptedChannel::new(
+                Channel::Custom(BoxCloneService::new(service)),
+                Interceptor::default(),
+            );
+            WatchClient::new(channel, AuthToken::default())
+        }
+
+        async fn serve(
+            self,
+            req: http::Request<tonic::body::Body>,
+        ) -> http::Response<tonic::body::Body> {
+            match req.uri().path() {
+                "/etcdserverpb.KV/Range" => {
+                    let service = tower::service_fn(|_: tonic::Request<PbRangeRequest>| {
+                        let resp = self.range();
+                        async move { Ok::<_, Status>(tonic::Response::new(resp)) }
+                    });
+                    Grpc::new(ProstCodec::default()).unary(service, req).await
+                }
+                "/etcdserverpb.Watch/Watch" => {
+                    let service =
+                        tower::service_fn(|req: tonic::Request<Streaming<PbWatchRequest>>| {
+                            let resp = match self.deny_watch {
+                                true => Err(Status::permission_denied("permission denied")),
+                                false => Ok(tonic::Response::new(self.watch(req.into_inner()))),
+                            };
+                            async move { resp }
+                        });
+                    Grpc::new(ProstCodec::default())
+                        .streaming(service, req)
+                        .await
+                }
+                path => Status::unimplemented(path.to_string()).into_http(),
+            }
+        }
+
+        /// Reads `key/a` at revision 5, then deletes it at revision 6.
+        fn range(&self) -> PbRangeResponse {
+            let kv = PbKeyValue {
+                key: b"key/a".to_vec(),
+                value: b"1".to_vec(),
+                create_revision: 5,
+                mod_revision: 5,
+                version: 1,
+                ..Default::default()
+            };
+            let delete = PbEvent {
+                r#type: EventType::Delete as i32,
+                kv: Some(PbKeyValue {
+                    key: kv.key.clone(),
+                    mod_revision: 6,
+                    ..Default::default()
+                }),
+                prev_kv: None,
+            };
+            let mut history = self.history.lock_unpoisoned();
+            history.push(PbEvent {
+                kv: Some(kv.clone()),
+                ..Default::default()
+            });
+            history.push(delete);
+            PbRangeResponse {
+                header: Some(PbResponseHeader {
+                    revision: 5,
+                    ..Default::default()
+                }),
+                kvs: vec![kv],
+                count: 1,
+                ..Default::default()
+            }
+        }
+
+        /// Creates the watches of `requests`, replaying the events from their start revision.
+        fn watch(
+            &self,
+            mut requests: Streaming<PbWatchRequest>,
+        ) -> UnboundedReceiverStream<std::result::Result<PbWatchResponse, Status>> {
+            let (sender, receiver) = unbounded_channel();
+            let racing = self.clone();
+            tokio::spawn(async move {
+                while let Some(Ok(req)) = requests.next().await {
+                    let Some(WatchRequestUnion::CreateRequest(create)) = req.request_union else {
+                        continue;
+                    };
+                    let header = Some(PbResponseHeader {
+                        revision: 6,
+                        ..Default::default()
+                    });
+                    let events = racing
+                        .history
+                        .lock_unpoisoned()
+                        .iter()
+                        .filter(|event| {
+                            let kv = event.kv.as_ref().unwrap();
+                            kv.mod_revision >= create.start_revision
+                                && kv.key >= create.key
+                                && (kv.key == create.key || kv.key < create.range_end)
+                        })
+                        .cloned()
+                        .collect();
+                    racing.creates.lock_unpoisoned().push(create);
+                    let created = PbWatchResponse {
+                        header,
+                        watch_id: 1,
+                        created: true,
+                        ..Default::default()
+                    };
+                    let replay = PbWatchResponse {
+                        header,
+                        watch_id: 1,
+                        events,
+                        ..Default::default()
+                    };
+                    let _ = sender.send(Ok(created));
+                    let _ = sender.send(Ok(replay));
+                }
+            });
+            UnboundedReceiverStream::new(receiver)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_between_get_and_watch() {
+        let racing = Racing::default();
+        let (get, _watcher, mut stream) = racing
+            .client()
+            .get_and_watch("key/", Some(GetOptions::new().with_prefix()), None)
+            .await
+            .unwrap();
+        assert_eq!(get.kvs()[0].key(), b"key/a");
+
+        // The watch covers the prefix read, from the revision after the get.
+        let create = racing.creates.lock_unpoisoned()[0].clone();
+        assert_eq!(create.key, b"key/");
+        assert_eq!(create.range_end, b"key0");
+        assert_eq!(create.start_revision, 6);
+
+        // The delete is the first event, the put already read is not repeated.
+        let resp = stream.message().await.unwrap().unwrap();
+        assert_eq!(resp.events().len(), 1);
+        let event = &resp.events()[0];
+        assert_eq!(event.event_type(), EventType::Delete);
+        assert_eq!(event.kv().unwrap().key(), b"key/a");
+        assert_eq!(event.kv().unwrap().mod_revision(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_watch_range_overrides_get() {
+        let racing = Racing::default();
+        let watch_options = WatchOptions::new().with_range("key/b");
+        racing
+            .client()
+            .get_and_watch(
+                "key/",
+                Some(GetOptions::new().with_prefix()),
+                Some(watch_options),
+            )
+            .await
+            .unwrap();
+        let create = racing.creates.lock_unpoisoned()[0].clone();
+        assert_eq!(create.range_end, b"key/b");
+        assert_eq!(create.start_revision, 6);
+    }
+
+    #[tokio::test]
+    async fn test_watch_after_get_failed() {
+        let racing = Racing {
+            deny_watch: true,
+            ..Default::default()
+        };
+        let err = racing
+            .client()
+            .get_and_watch("key/", Some(GetOptions::new().with_prefix()), None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to watch from revision 6 after get: Watch failed: PermissionDenied: permission denied"
+        );
+        match err {
+            Error::WatchAfterGetFailed {
+                get,
+                revision,
+                resumable,
+                source,
+            } => {
+                assert_eq!(get.kvs()[0].key(), b"key/a");
+                assert_eq!(revision, 5);
+                assert!(resumable);
+                assert!(source.is_permission_denied(), "{:?}", source);
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! Hedging of reads across endpoints.
+//!
+//! With [`ConnectOptions::with_read_hedging`](crate::ConnectOptions::with_read_hedging), the
+//! attempts of a Range request are sent over channels to single endpoints. If an attempt has
+//! not completed within the hedging delay, the request is sent again to another endpoint, and
+//! the first successful response wins while the other attempts are cancelled. All attempts
+//! share the deadline, the cancellation token and the auth token of the call.
+
+use crate::error::{Error, Result};
+use crate::lock::{MutexExt, RwLockExt};
+use crate::metadata::Metadata;
+use http::Uri;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+type HedgeHook = Arc<dyn Fn(&Uri, HedgeEvent) + Send + Sync>;
+
+/// An event of a hedged read, reported to the hook set by [`ReadHedging::with_on_hedge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HedgeEvent {
+    /// A hedged attempt was sent to the endpoint, because the previous attempts were slow
+    /// or failed.
+    Fired,
+    /// The hedged attempt sent to the endpoint returned the response.
+    Won,
+}
+
+/// Options for [`ConnectOptions::with_read_hedging`](crate::ConnectOptions::with_read_hedging).
+#[derive(Clone)]
+pub struct ReadHedging {
+    delay: Duration,
+    max_fanout: usize,
+    on_hedge: Option<HedgeHook>,
+}
+
+impl ReadHedging {
+    /// Creates a `ReadHedging` sending a hedged attempt to a second endpoint if the first
+    /// attempt has not completed within `delay`.
+    #[inline]
+    pub const fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            max_fanout: 2,
+            on_hedge: None,
+        }
+    }
+
+    /// Sets the maximum number of attempts in flight, including the first one.
+    ///
+    /// Every `delay` without a response, another attempt is sent to another endpoint until
+    /// this number is reached.
+    #[inline]
+    pub const fn with_max_fanout(mut self, max_fanout: usize) -> Self {
+        self.max_fanout = max_fanout;
+        self
+    }
+
+    /// Sets a hook called with the endpoint of every hedged attempt which is fired and of
+    /// every hedged attempt which wins, e.g. to count them.
+    #[inline]
+    pub fn with_on_hedge(
+        mut self,
+        hook: impl Fn(&Uri, HedgeEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_hedge = Some(Arc::new(hook));
+        self
+    }
+
+    /// The settings of the hedging which can not be honoured, e.g. no attempt in flight.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        if self.max_fanout == 0 {
+            vec![String::from("read hedging max fanout is zero")]
+        } else {
+            Vec::new()
+        }
+    }
+
+    #[inline]
+    fn report(&self, uri: &Uri, event: HedgeEvent) {
+        if let Some(hook) = &self.on_hedge {
+            hook(uri, event);
+        }
+    }
+}
+
+impl Debug for ReadHedging {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadHedging")
+            .field("delay", &self.delay)
+            .field("max_fanout", &self.max_fanout)
+            .field("on_hedge", &self.on_hedge.is_some())
+            .finish()
+    }
+}
+
+type Connect<C> = Box<dyn Fn(&Uri) -> Result<C> + Send + Sync>;
+
+/// Sends the attempts of hedged reads to the endpoints with clients of type `C`.
+pub(crate) struct Hedger<C> {
+    options: ReadHedging,
+    endpoints: RwLock<Vec<Uri>>,
+    clients: Mutex<HashMap<Uri, C>>,
+    next: AtomicUsize,
+    connect: Connect<C>,
+}
+
+impl<C: Clone> Hedger<C> {
+    /// Creates a hedger over `endpoints`, whose clients are created by `connect` when first
+    /// used.
+    pub(crate) fn new(
+        options: ReadHedging,
+        endpoints: Vec<Uri>,
+        connect: impl Fn(&Uri) -> Result<C> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            options,
+            endpoints: RwLock::new(endpoints),
+            clients: Mutex::new(HashMap::new()),
+            next: AtomicUsize::new(0),
+            connect: Box::new(connect),
+        }
+    }
+
+    /// Adds an endpoint.
+    pub(crate) fn insert(&self, uri: Uri) {
+        let mut endpoints = self.endpoints.write_unpoisoned();
+        if !endpoints.contains(&uri) {
+            endpoints.push(uri);
+        }
+    }
+
+    /// Removes an endpoint.
+    pub(crate) fn remove(&self, uri: &Uri) {
+        self.endpoints.write_unpoisoned().retain(|u| u != uri);
+        self.clients.lock_unpoisoned().remove(uri);
+    }
+
+    /// The clients of the endpoints to send the attempts of a request to, starting with
+    /// another endpoint for every request.
+    fn targets(&self) -> Vec<(Uri, C)> {
+        let endpoints = self.endpoints.read_unpoisoned().clone();
+        if endpoints.is_empty() {
+            return Vec::new();
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % endpoints.len();
+        let mut clients = self.clients.lock_unpoisoned();
+        endpoints[start..]
+            .iter()
+            .chain(&endpoints[..start])
+            .take(self.options.max_fanout.max(1))
+            .filter_map(|uri| {
+                let client = match clients.get(uri) {
+                    Some(client) => client.clone(),
+                    // An endpoint which can not be connected to does not break the others.
+                    None => {
+                        let client = (self.connect)(uri).ok()?;
+                        clients.insert(uri.clone(), client.clone());
+                        client
+                    }
+                };
+                Some((uri.clone(), client))
+            })
+            .collect()
+    }
+
+    /// Runs the read made by `f`, hedging it across the endpoints, or with `fallback` if
+    /// there are none.
+    pub(crate) async fn run<T, F, Fut>(&self, fallback: C, f: F) -> Result<T>
+    where
+        F: Fn(C) -> Fut,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let mut targets = self.targets().into_iter();
+        let Some((uri, client)) = targets.next() else {
+            return f(fallback).await;
+        };
+
+        // Dropping the set aborts the attempts still in flight.
+        let mut attempts = JoinSet::new();
+        // The attempts are spawned, so the metadata of the call is passed on.
+        let metadata = Metadata::current();
+        let fut = metadata
+            .clone()
+            .scope(crate::trace::endpoint(&uri, f(client)));
+        attempts.spawn(async move { (uri, false, fut.await) });
+        let mut next_hedge = Instant::now() + self.options.delay;
+        let mut last_err = None;
+        loop {
+            let fire = tokio::select! {
+                joined = attempts.join_next(), if !attempts.is_empty() => {
+                    let (uri, hedged, result) = match joined {
+                        Some(Ok(attempt)) => attempt,
+                        Some(Err(e)) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                        _ => continue,
+                    };
+                    match result {
+                        Ok(resp) => {
+                            if hedged {
+                                self.options.report(&uri, HedgeEvent::Won);
+                            }
+                            return Ok(resp);
+                        }
+                        // Other endpoints would fail alike.
+                        Err(e) if !e.is_retryable() => return Err(e),
+                        // Hedge right away if no attempt is left in flight.
+                        Err(e) => {
+                            last_err = Some(e);
+                            attempts.is_empty()
+                        }
+                    }
+                }
+                _ = tokio::time::sleep_until(next_hedge), if targets.len() > 0 => true,
+            };
+            if !fire {
+                continue;
+            }
+            let Some((uri, client)) = targets.next() else {
+                return Err(last_err.unwrap_or(Error::EndpointsNotManaged));
+            };
+            tracing::debug!(endpoint = %uri, "hedging etcd read");
+            self.options.report(&uri, HedgeEvent::Fired);
+            let fut = metadata
+                .clone()
+                .scope(crate::trace::endpoint(&uri, f(client)));
+            attempts.spawn(async move { (uri, true, fut.await) });
+            next_hedge = Instant::now() + self.options.delay;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    type Events = Arc<Mutex<Vec<(String, HedgeEvent)>>>;
+
+    /// A hedger over endpoints `a`, `b` and `c`, whose clients are their names.
+    fn hedger(options: ReadHedging) -> (Hedger<&'static str>, Events) {
+        let events = Events::default();
+        let options = options.with_on_hedge({
+            let events = events.clone();
+            move |uri, event| {
+                events
+                    .lock_unpoisoned()
+                    .push((uri.host().unwrap().to_string(), event))
+            }
+        });
+        let endpoints = ["http://a", "http://b", "http://c"]
+            .iter()
+            .map(|uri| uri.parse().unwrap())
+            .collect();
+        let hedger = Hedger::new(options, endpoints, |uri| match uri.host() {
+            Some("a") => Ok("a"),
+            Some("b") => Ok("b"),
+            _ => Ok("c"),
+        });
+        (hedger, events)
+    }
+
+    #[tokio::test]
+    async fn test_hedge_slow() {
+        let (hedger, events) = hedger(ReadHedging::new(Duration::from_millis(10)));
+        let result = hedger
+            .run("none", |client| async move {
+                if client == "a" {
+                    std::future::pending::<()>().await;
+                }
+                Ok(client)
+            })
+            .await;
+        assert_eq!(result.unwrap(), "b");
+        assert_eq!(
+            *events.lock_unpoisoned(),
+            [
+                ("b".to_string(), HedgeEvent::Fired),
+                ("b".to_string(), HedgeEvent::Won)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hedge_fast() {
+        let (hedger, events) = hedger(ReadHedging::new(Duration::from_secs(3600)));
+        let result = hedger.run("none", |client| async move { Ok(client) }).await;
+        assert_eq!(result.unwrap(), "a");
+        // The next request starts with another endpoint.
+        let result = hedger.run("none", |client| async move { Ok(client) }).await;
+        assert_eq!(result.unwrap(), "b");
+        assert!(events.lock_unpoisoned().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hedge_failed() {
+        let (hedger, _) = hedger(ReadHedging::new(Duration::from_secs(3600)).with_max_fanout(3));
+        let attempts = Arc::new(AtomicU32::new(0));
+        let result = hedger
+            .run("none", |client| {
+                let attempts = attempts.clone();
+                async move {
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    match client {
+                        "c" => Ok(client),
+                        _ => Err(Error::from(tonic::Status::unavailable("connection reset"))),
+                    }
+                }
+            })
+            .await;
+        // Failed attempts are hedged right away.
+        assert_eq!(result.unwrap(), "c");
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let result = hedger
+            .run("none", |_| async {
+                Err::<(), _>(Error::from(tonic::Status::out_of_range(
+                    "etcdserver: mvcc: required revision has been compacted",
+                )))
+            })
+            .await;
+        assert!(result.unwrap_err().is_compacted());
+
+        hedger.remove(&"http://a".parse().unwrap());
+        hedger.remove(&"http://b".parse().unwrap());
+        hedger.remove(&"http://c".parse().unwrap());
+        let result = hedger.run("none", |client| async move { Ok(client) }).await;
+        assert_eq!(result.unwrap(), "none");
+    }
+}
@@ -0,0 +1,340 @@
+//! Probing the health and the version of members over HTTP, without gRPC.
+
+use crate::auth::AuthToken;
+use crate::client::{Connector, DEFAULT_ENDPOINT_PORT};
+use crate::error::{ConnectError, Error, Result};
+use crate::rpc::maintenance::MaintenanceClient;
+use http::{StatusCode, Uri};
+use http_body_util::{BodyExt, Empty};
+use hyper_util::rt::TokioIo;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tonic::codegen::Bytes;
+use tower::ServiceExt;
+
+/// The path of the health endpoint of etcd.
+const HEALTH_PATH: &str = "/health";
+
+/// The path of the version endpoint of etcd.
+const VERSION_PATH: &str = "/version";
+
+/// The health of a member, reported by its `/health` endpoint, see
+/// [`MaintenanceClient::http_health`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    health: bool,
+    reason: String,
+}
+
+impl Health {
+    /// Returns `true` if the member is healthy, i.e. the cluster has a leader and no alarm
+    /// is raised.
+    #[inline]
+    pub const fn is_healthy(&self) -> bool {
+        self.health
+    }
+
+    /// Why the member is not healthy, e.g. `RAFT NO LEADER`, empty if it is or the server
+    /// does not tell.
+    #[inline]
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+/// The body of the `/health` endpoint, whose health is `"true"` or `"false"`.
+#[derive(Deserialize)]
+struct HealthBody {
+    health: String,
+    #[serde(default)]
+    reason: String,
+}
+
+/// The versions of a member, reported by its `/version` endpoint, see
+/// [`MaintenanceClient::http_version`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct VersionInfo {
+    etcdserver: String,
+    etcdcluster: String,
+    #[serde(default)]
+    storage: Option<String>,
+}
+
+impl VersionInfo {
+    /// The version of the server, e.g. `3.5.9`.
+    #[inline]
+    pub fn etcdserver(&self) -> &str {
+        &self.etcdserver
+    }
+
+    /// The version of the cluster, the lowest of its members with the patch version set
+    /// to zero, e.g. `3.5.0`, or `not_decided` before the cluster decides it.
+    #[inline]
+    pub fn etcdcluster(&self) -> &str {
+        &self.etcdcluster
+    }
+
+    /// The version of the storage of the member, reported by etcd 3.6 and later.
+    #[inline]
+    pub fn storage(&self) -> Option<&str> {
+        self.storage.as_deref()
+    }
+}
+
+impl MaintenanceClient {
+    /// Gets the health of the member at `endpoint` from its `/health` endpoint, served over
+    /// HTTP on the client port, without the timeouts of gRPC requests.
+    ///
+    /// Tells a member up but unhealthy, e.g. without quorum, from a member down: the
+    /// former answers a [`Health`] which is not healthy, the latter fails with an
+    /// [`Error::Connect`], a [`ConnectError::Tcp`] if it refused the connection or a
+    /// [`ConnectError::TlsHandshake`] if the TLS handshake failed. Other answers fail with
+    /// an [`Error::UnexpectedHttpResponse`].
+    ///
+    /// The HTTPS endpoints, and the endpoints without a scheme if TLS is configured, are
+    /// probed over HTTP2 with the TLS options of the client, the others over HTTP1.
+    pub async fn http_health(&self, endpoint: &str) -> Result<Health> {
+        let (status, body) = self.http_get(endpoint, HEALTH_PATH).await?;
+        // Unhealthy members answer with a service unavailable status.
+        let health: HealthBody = parse(
+            endpoint,
+            status,
+            body,
+            &[StatusCode::OK, StatusCode::SERVICE_UNAVAILABLE],
+        )?;
+        Ok(Health {
+            health: health.health == "true",
+            reason: health.reason,
+        })
+    }
+
+    /// Gets the versions of the member at `endpoint` from its `/version` endpoint, see
+    /// [`MaintenanceClient::http_health`].
+    pub async fn http_version(&self, endpoint: &str) -> Result<VersionInfo> {
+        let (status, body) = self.http_get(endpoint, VERSION_PATH).await?;
+        parse(endpoint, status, body, &[StatusCode::OK])
+    }
+
+    /// Gets `path` from the member at `endpoint`.
+    async fn http_get(&self, endpoint: &str, path: &str) -> Result<(StatusCode, Bytes)> {
+        let uri: Uri = endpoint.parse()?;
+        let connector = self
+            .connector
+            .clone()
+            .unwrap_or_else(|| Connector::new(None, AuthToken::default(), Default::default()));
+        let tls = match uri.scheme_str() {
+            Some(scheme) => scheme == "https",
+            None => connector.has_tls(),
+        };
+        if !tls {
+            return http1_get(&uri, path).await.map_err(|e| {
+                Error::Connect(ConnectError::Tcp {
+                    endpoint: endpoint.to_owned(),
+                    source: Box::new(Error::IoError(e)),
+                })
+            });
+        }
+
+        let connect_error = |e: Error| Error::from(ConnectError::new(endpoint.to_owned(), e));
+        let req = http::Request::get(path)
+            .body(tonic::body::Body::empty())
+            .map_err(|e| Error::InvalidArgs(e.to_string()))?;
+        let resp = connector
+            .raw_channel(&uri)?
+            .oneshot(req)
+            .await
+            .map_err(|e| connect_error(tonic::Status::from_error(e).into()))?;
+        let status = resp.status();
+        let body = resp
+            .into_body()
+            .collect()
+            .await
+            .map_err(|status| connect_error(status.into()))?;
+        Ok((status, body.to_bytes()))
+    }
+}
+
+/// Gets `path` from the server at `uri` over a connection of its own, speaking HTTP1.
+async fn http1_get(uri: &Uri, path: &str) -> std::io::Result<(StatusCode, Bytes)> {
+    let host = uri.host().unwrap_or_default();
+    let port = uri.port_u16().unwrap_or(DEFAULT_ENDPOINT_PORT);
+    let stream = TcpStream::connect((host.trim_matches(['[', ']']), port)).await?;
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(std::io::Error::other)?;
+    let req = http::Request::get(path)
+        .header(http::header::HOST, format!("{}:{}", host, port))
+        .body(Empty::<Bytes>::new())
+        .map_err(std::io::Error::other)?;
+    let get = async {
+        let resp = sender.send_request(req).await?;
+        let status = resp.status();
+        Ok((status, resp.into_body().collect().await?.to_bytes()))
+    };
+
+    // The connection is driven along with the request, until the response is read.
+    tokio::pin!(conn);
+    tokio::select! {
+        result = get => result.map_err(|e: hyper::Error| std::io::Error::other(e)),
+        Err(e) = &mut conn => Err(std::io::Error::other(e)),
+    }
+}
+
+/// Parses the JSON `body` answered by `endpoint` with `status`, which must be one of
+/// `expected`.
+fn parse<T: DeserializeOwned>(
+    endpoint: &str,
+    status: StatusCode,
+    body: Bytes,
+    expected: &[StatusCode],
+) -> Result<T> {
+    match serde_json::from_slice(&body) {
+        Ok(parsed) if expected.contains(&status) => Ok(parsed),
+        _ => Err(Error::UnexpectedHttpResponse {
+            endpoint: endpoint.to_owned(),
+            status,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::Channel;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tower::util::BoxCloneService;
+
+    /// Serves `status` and the JSON `body` to every request, returning its endpoint.
+    async fn serve(status: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                // The requests of the probes fit in a single read.
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn client() -> MaintenanceClient {
+        let service = tower::service_fn(|_: http::Request<tonic::body::Body>| async {
+            Err::<http::Response<tonic::body::Body>, tower::BoxError>("no gRPC".into())
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service)),
+            Interceptor::default(),
+        );
+        MaintenanceClient::new(channel, AuthToken::default())
+    }
+
+    #[tokio::test]
+    async fn test_http_health() {
+        let endpoint = serve("200 OK", r#"{"health":"true","reason":""}"#).await;
+        let health = client().http_health(&endpoint).await.unwrap();
+        assert!(health.is_healthy());
+        assert_eq!(health.reason(), "");
+
+        let body = r#"{"health":"false","reason":"RAFT NO LEADER"}"#;
+        let endpoint = serve("503 Service Unavailable", body).await;
+        let health = client().http_health(&endpoint).await.unwrap();
+        assert!(!health.is_healthy());
+        assert_eq!(health.reason(), "RAFT NO LEADER");
+    }
+
+    #[tokio::test]
+    async fn test_http_version() {
+        let body = r#"{"etcdserver":"3.5.9","etcdcluster":"3.5.0"}"#;
+        let endpoint = serve("200 OK", body).await;
+        let version = client().http_version(&endpoint).await.unwrap();
+        assert_eq!(version.etcdserver(), "3.5.9");
+        assert_eq!(version.etcdcluster(), "3.5.0");
+        assert_eq!(version.storage(), None);
+
+        let body = r#"{"etcdserver":"3.6.0","etcdcluster":"3.6.0","storage":"3.6.0"}"#;
+        let endpoint = serve("200 OK", body).await;
+        let version = client().http_version(&endpoint).await.unwrap();
+        assert_eq!(version.storage(), Some("3.6.0"));
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_http_response() {
+        let endpoint = serve("404 Not Found", "404 page not found").await;
+        let err = client().http_health(&endpoint).await.unwrap_err();
+        match &err {
+            Error::UnexpectedHttpResponse { status, body, .. } => {
+                assert_eq!(*status, StatusCode::NOT_FOUND);
+                assert_eq!(body, "404 page not found");
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "unexpected HTTP response from {}: 404 Not Found: 404 page not found",
+                endpoint
+            )
+        );
+
+        // A version is not a health, even if served successfully.
+        let endpoint = serve("200 OK", r#"{"health":"true"}"#).await;
+        let err = client().http_version(&endpoint).await.unwrap_err();
+        assert!(
+            matches!(err, Error::UnexpectedHttpResponse { .. }),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_connection_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let err = client().http_health(&endpoint).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::Connect(ConnectError::Tcp { endpoint: e, .. }) if *e == endpoint),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            err.io_error_kind(),
+            Some(std::io::ErrorKind::ConnectionRefused)
+        );
+    }
+
+    #[cfg(any(feature = "tls-ring", feature = "tls-aws-lc"))]
+    #[tokio::test]
+    async fn test_http_tls_handshake_failed() {
+        use crate::{ConnectOptions, TlsOptions};
+
+        // The server does not speak TLS.
+        let endpoint = serve("200 OK", r#"{"health":"true"}"#).await;
+        let endpoint = endpoint.replace("http://", "https://");
+        let options = ConnectOptions::new().with_tls(TlsOptions::new());
+        let connector = Connector::new(Some(options), AuthToken::default(), Default::default());
+        let err = client()
+            .with_connector(connector)
+            .http_health(&endpoint)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::Connect(ConnectError::TlsHandshake { .. })),
+            "{:?}",
+            err
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Revisions and lease ids, typed so that they are not mixed up with each other or with the
+//! other integers of etcd, e.g. the versions of keys.
+//!
+//! Both wrap the `i64` of the protocol, and convert from and into it for interop: the
+//! methods taking a revision or a lease id accept either.
+
+use crate::error::{Error, Result};
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter, LowerHex, UpperHex};
+use std::ops::{Add, Sub};
+
+/// A revision of the key-value store.
+///
+/// The revision 0 is a sentinel, [`Revision::current`]: a read at that revision is served at
+/// the current revision of the store, a watch from it starts after the current revision.
+///
+/// # Examples
+///
+/// ```
+/// use etcd_client::Revision;
+///
+/// let revision = Revision::new(41);
+/// assert_eq!(revision.next().unwrap(), 42);
+/// assert!(Revision::current().next().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+#[repr(transparent)]
+pub struct Revision(i64);
+
+impl Revision {
+    /// Creates a revision from the `i64` of the protocol.
+    #[inline]
+    pub const fn new(revision: i64) -> Self {
+        Self(revision)
+    }
+
+    /// The sentinel revision 0, standing for the current revision of the store.
+    #[inline]
+    pub const fn current() -> Self {
+        Self(0)
+    }
+
+    /// Returns `true` if the revision is the sentinel [`Revision::current`].
+    #[inline]
+    pub const fn is_current(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The `i64` of the protocol.
+    #[inline]
+    pub const fn get(self) -> i64 {
+        self.0
+    }
+
+    /// The revision after this one, e.g. to watch from the revision after a read.
+    ///
+    /// Fails with [`Error::InvalidArgs`] for the sentinel [`Revision::current`], which has
+    /// no revision after it, and for a negative or the largest revision.
+    pub fn next(self) -> Result<Self> {
+        if self.0 <= 0 {
+            return Err(Error::InvalidArgs(format!(
+                "no revision after revision {}",
+                self
+            )));
+        }
+        match self.0.checked_add(1) {
+            Some(next) => Ok(Self(next)),
+            None => Err(Error::InvalidArgs(format!(
+                "no revision after revision {}",
+                self
+            ))),
+        }
+    }
+
+    /// The revision before this one, e.g. to read the keys as they were before a write.
+    ///
+    /// Fails with [`Error::InvalidArgs`] for the sentinel [`Revision::current`], for the
+    /// revision 1, whose revision before would be the sentinel, and for a negative revision.
+    pub fn prev(self) -> Result<Self> {
+        if self.0 <= 1 {
+            return Err(Error::InvalidArgs(format!(
+                "no revision before revision {}",
+                self
+            )));
+        }
+        Ok(Self(self.0 - 1))
+    }
+}
+
+impl Display for Revision {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl From<i64> for Revision {
+    #[inline]
+    fn from(revision: i64) -> Self {
+        Self(revision)
+    }
+}
+
+impl From<Revision> for i64 {
+    #[inline]
+    fn from(revision: Revision) -> Self {
+        revision.0
+    }
+}
+
+impl PartialEq<i64> for Revision {
+    #[inline]
+    fn eq(&self, other: &i64) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<Revision> for i64 {
+    #[inline]
+    fn eq(&self, other: &Revision) -> bool {
+        *self == other.0
+    }
+}
+
+impl PartialOrd<i64> for Revision {
+    #[inline]
+    fn partial_cmp(&self, other: &i64) -> Option<Ordering> {
+        self.0.partial_cmp(other)
+    }
+}
+
+impl PartialOrd<Revision> for i64 {
+    #[inline]
+    fn partial_cmp(&self, other: &Revision) -> Option<Ordering> {
+        self.partial_cmp(&other.0)
+    }
+}
+
+/// Offsets the revision, like the `i64` of the protocol, e.g. `header.revision() + 1`.
+///
+/// Unlike [`Revision::next`], the sentinel [`Revision::current`] is not checked for.
+impl Add<i64> for Revision {
+    type Output = Revision;
+
+    #[inline]
+    fn add(self, rhs: i64) -> Revision {
+        Self(self.0 + rhs)
+    }
+}
+
+/// Offsets the revision back, like the `i64` of the protocol.
+///
+/// Unlike [`Revision::prev`], the sentinel [`Revision::current`] is not checked for.
+impl Sub<i64> for Revision {
+    type Output = Revision;
+
+    #[inline]
+    fn sub(self, rhs: i64) -> Revision {
+        Self(self.0 - rhs)
+    }
+}
+
+/// The number of revisions between two revisions.
+impl Sub for Revision {
+    type Output = i64;
+
+    #[inline]
+    fn sub(self, rhs: Revision) -> i64 {
+        self.0 - rhs.0
+    }
+}
+
+/// The id of a lease.
+///
+/// The id 0 stands for no lease, e.g. in the key-values not attached to one. Lease ids are
+/// displayed in hexadecimal, like `etcdctl` does.
+///
+/// # Examples
+///
+/// ```
+/// use etcd_client::LeaseId;
+///
+/// let id = LeaseId::new(0x694d77aa9e38260f);
+/// assert_eq!(id.to_string(), "694d77aa9e38260f");
+/// assert!(LeaseId::none().is_none());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+#[repr(transparent)]
+pub struct LeaseId(i64);
+
+impl LeaseId {
+    /// Creates a lease id from the `i64` of the protocol.
+    #[inline]
+    pub const fn new(id: i64) -> Self {
+        Self(id)
+    }
+
+    /// The id 0, standing for no lease.
+    #[inline]
+    pub const fn none() -> Self {
+        Self(0)
+    }
+
+    /// Returns `true` if the id stands for no lease.
+    #[inline]
+    pub const fn is_none(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The `i64` of the protocol.
+    #[inline]
+    pub const fn get(self) -> i64 {
+        self.0
+    }
+}
+
+impl Display for LeaseId {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        LowerHex::fmt(&self.0, f)
+    }
+}
+
+impl LowerHex for LeaseId {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        LowerHex::fmt(&self.0, f)
+    }
+}
+
+impl UpperHex for LeaseId {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        UpperHex::fmt(&self.0, f)
+    }
+}
+
+impl From<i64> for LeaseId {
+    #[inline]
+    fn from(id: i64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<LeaseId> for i64 {
+    #[inline]
+    fn from(id: LeaseId) -> Self {
+        id.0
+    }
+}
+
+impl PartialEq<i64> for LeaseId {
+    #[inline]
+    fn eq(&self, other: &i64) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<LeaseId> for i64 {
+    #[inline]
+    fn eq(&self, other: &LeaseId) -> bool {
+        *self == other.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revision_next_prev() {
+        assert_eq!(Revision::new(1).next().unwrap(), Revision::new(2));
+        assert_eq!(Revision::new(2).prev().unwrap(), Revision::new(1));
+        assert_eq!(
+            Revision::current().next().unwrap_err().to_string(),
+            "invalid arguments: no revision after revision 0"
+        );
+        assert!(Revision::new(-1).next().is_err());
+        assert!(Revision::new(i64::MAX).next().is_err());
+        assert!(Revision::new(1).prev().is_err());
+        assert!(Revision::current().prev().is_err());
+    }
+
+    #[test]
+    fn test_revision_interop() {
+        let revision = Revision::from(5);
+        assert_eq!(i64::from(revision), 5);
+        assert_eq!(revision, 5);
+        assert!(revision > 4 && 6 > revision);
+        assert!(Revision::current() < revision);
+        assert!(Revision::default().is_current());
+        assert_eq!(revision.to_string(), "5");
+        assert_eq!(revision + 1, 6);
+        assert_eq!(revision - 1, Revision::new(4));
+        assert_eq!(revision - Revision::new(2), 3);
+    }
+
+    #[test]
+    fn test_lease_id_display() {
+        let id = LeaseId::from(0x694d77aa9e38260f);
+        assert_eq!(id.to_string(), "694d77aa9e38260f");
+        assert_eq!(format!("{:X}", id), "694D77AA9E38260F");
+        assert_eq!(format!("{:?}", id), "LeaseId(7587852521871779343)");
+        assert_eq!(id, 0x694d77aa9e38260f);
+        assert_eq!(i64::from(LeaseId::none()), 0);
+    }
+}
@@ -1,17 +1,25 @@
-use crate::channel::Channel;
+use crate::channel::{Channel, SyncChannel};
+use crate::metadata::Metadata;
+use std::task::{Context, Poll};
 use tonic::{
     metadata::AsciiMetadataValue,
     service::{interceptor::InterceptedService, Interceptor as TonicInterceptor},
 };
+use tower_service::Service;
 
 const REQUIRE_LEADER_KEY: &str = "hasleader";
 const REQUIRE_LEADER_VALUE: &str = "true";
+pub(crate) const CLIENT_NAME_KEY: &str = "client-name";
 
-/// An interceptor that conditionally attaches a leader requirement
-/// to the underlying service.
-#[derive(Clone)]
+/// An interceptor that conditionally attaches a leader requirement, the client name
+/// and custom metadata to the underlying service.
+#[derive(Clone, Default)]
 pub struct Interceptor {
-    pub require_leader: bool,
+    pub(crate) require_leader: bool,
+    pub(crate) client_name: Option<AsciiMetadataValue>,
+    pub(crate) metadata: Metadata,
+    #[cfg(feature = "tracing")]
+    pub(crate) tracing: Option<crate::trace::TraceOptions>,
 }
 
 impl TonicInterceptor for Interceptor {
@@ -19,15 +27,55 @@ impl TonicInterceptor for Interceptor {
         &mut self,
         mut request: tonic::Request<()>,
     ) -> Result<tonic::Request<()>, tonic::Status> {
+        self.metadata.apply(request.metadata_mut());
         if self.require_leader {
             request.metadata_mut().append(
                 REQUIRE_LEADER_KEY,
                 AsciiMetadataValue::from_static(REQUIRE_LEADER_VALUE),
             );
         }
+        if let Some(name) = &self.client_name {
+            request.metadata_mut().insert(CLIENT_NAME_KEY, name.clone());
+        }
+        #[cfg(feature = "tracing")]
+        if let Some(traceparent) = self.tracing.as_ref().and_then(|t| t.traceparent()) {
+            request
+                .metadata_mut()
+                .insert(crate::trace::TRACEPARENT_KEY, traceparent);
+        }
         Ok(request)
     }
 }
 
-/// A type alias to make using this interceptor easier.
-pub type InterceptedChannel = InterceptedService<Channel, Interceptor>;
+type TonicRequest = http::Request<tonic::body::Body>;
+type Intercepted = InterceptedService<SyncChannel, Interceptor>;
+
+/// A channel with the interceptor in front, `Sync` so the clients sending requests over it
+/// can be shared without a lock.
+#[derive(Clone)]
+pub struct InterceptedChannel(Intercepted);
+
+impl InterceptedChannel {
+    /// Intercepts the requests sent over `channel`, which must be done in a Tokio runtime as
+    /// a custom channel is moved behind a buffer.
+    #[inline]
+    pub(crate) fn new(channel: Channel, interceptor: Interceptor) -> Self {
+        Self(InterceptedService::new(channel.into(), interceptor))
+    }
+}
+
+impl Service<TonicRequest> for InterceptedChannel {
+    type Response = <Intercepted as Service<TonicRequest>>::Response;
+    type Error = <Intercepted as Service<TonicRequest>>::Error;
+    type Future = <Intercepted as Service<TonicRequest>>::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&mut self, req: TonicRequest) -> Self::Future {
+        self.0.call(req)
+    }
+}
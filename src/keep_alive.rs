@@ -0,0 +1,514 @@
+//! Keep-alives of the leases of sessions, coalesced on a single timer.
+//!
+//! The leases of all the [`Session`](crate::Session)s of a client are kept alive by a single
+//! task over a single keep-alive stream, rather than by a task, a timer and a stream per
+//! session. The time is divided into buckets of the granularity set by
+//! [`ConnectOptions::with_lease_keep_alive_granularity`](crate::ConnectOptions::with_lease_keep_alive_granularity):
+//! a lease is due a third of its TTL after its last keep-alive, rounded down to the start of
+//! its bucket, and the task wakes up once per bucket holding leases, sending the keep-alives
+//! of all of them as a batch. The TTL returned by the keep-alive of a lease schedules its
+//! next one.
+//!
+//! The task stops once no lease is left, and the next lease registered opens a new stream.
+
+use crate::error::{Error, Result};
+use crate::logging::log_event;
+use crate::observe::Observer;
+use crate::rpc::lease::{LeaseClient, LeaseKeepAliveStream, LeaseKeeper};
+use crate::task::Task;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{oneshot, watch, Mutex};
+use tokio::time::Instant;
+use tokio_stream::StreamExt;
+
+/// The name of the task keeping the leases of sessions alive.
+const KEEP_ALIVE_TASK: &str = "session keep alive";
+
+/// The default granularity of the timer of the keep-alives of sessions.
+pub const DEFAULT_LEASE_KEEP_ALIVE_GRANULARITY: Duration = Duration::from_millis(500);
+
+/// The reply to the registration of a lease, once its first keep-alive is answered.
+type Reply = oneshot::Sender<Result<watch::Receiver<bool>>>;
+
+/// A command sent to the keep-alive task.
+enum Command {
+    /// Keeps the lease `id` alive, whose TTL is `ttl` seconds.
+    Register { id: i64, ttl: i64, reply: Reply },
+    /// Drops a registration of the lease.
+    Unregister(i64),
+}
+
+/// The keep-alive task of a pool.
+#[derive(Clone)]
+struct Running {
+    commands: UnboundedSender<Command>,
+    task: Arc<Task>,
+}
+
+/// Keeps the leases of the sessions of a client alive, shared by the clones of its lease
+/// client.
+#[derive(Clone)]
+pub(crate) struct KeepAlivePool {
+    granularity: Duration,
+    running: Arc<Mutex<Option<Running>>>,
+    wakeups: Arc<AtomicU64>,
+}
+
+impl KeepAlivePool {
+    /// Creates a pool waking up at most once per `granularity`.
+    #[inline]
+    pub(crate) fn new(granularity: Duration) -> Self {
+        Self {
+            granularity,
+            running: Arc::default(),
+            wakeups: Arc::default(),
+        }
+    }
+
+    /// Keeps the lease `id` of `ttl` seconds alive until the returned handle is dropped,
+    /// opening a keep-alive stream with `lease` if none is open.
+    pub(crate) async fn register(
+        &self,
+        lease: &mut LeaseClient,
+        id: i64,
+        ttl: i64,
+    ) -> Result<KeptAlive> {
+        loop {
+            let (reply, replied) = oneshot::channel();
+            let running = {
+                let mut running = self.running.lock().await;
+                let running = match running.as_ref() {
+                    Some(running) if !running.commands.is_closed() => running.clone(),
+                    _ => {
+                        let (keeper, stream) = lease.keep_alive(id).await?;
+                        let (commands, receiver) = unbounded_channel();
+                        let task = KeepAliveTask {
+                            granularity: self.granularity,
+                            start: Instant::now(),
+                            observer: lease.observer().clone(),
+                            keeper,
+                            leases: HashMap::new(),
+                            wheel: BTreeMap::new(),
+                            wakeups: self.wakeups.clone(),
+                        };
+                        let task = lease
+                            .tasks()
+                            .spawn(KEEP_ALIVE_TASK, task.run(stream, receiver));
+                        let new = Running {
+                            commands,
+                            task: Arc::new(task),
+                        };
+                        *running = Some(new.clone());
+                        new
+                    }
+                };
+                // Sent under the lock, so that a new task gets its first lease at once, which
+                // is registered like the others.
+                let _ = running.commands.send(Command::Register { id, ttl, reply });
+                running
+            };
+
+            // The task stopped in the meantime if the reply is dropped, start another one.
+            if let Ok(done) = replied.await {
+                return done.map(|done| KeptAlive { id, done, running });
+            }
+        }
+    }
+
+    /// The number of times the timers of the pool woke up.
+    #[cfg(test)]
+    fn wakeups(&self) -> u64 {
+        self.wakeups.load(Ordering::Relaxed)
+    }
+}
+
+/// A lease kept alive by a [`KeepAlivePool`] until dropped.
+pub(crate) struct KeptAlive {
+    id: i64,
+    done: watch::Receiver<bool>,
+    running: Running,
+}
+
+impl KeptAlive {
+    /// Returns `true` if the lease is no longer being kept alive.
+    #[inline]
+    pub(crate) fn is_done(&self) -> bool {
+        *self.done.borrow() || self.running.task.is_failed()
+    }
+
+    /// Returns [`Error::InternalTaskFailed`] if the keep-alive task panicked.
+    #[inline]
+    pub(crate) fn check(&self) -> Result<()> {
+        self.running.task.check()
+    }
+
+    /// Subscribes to the done state of the lease.
+    #[inline]
+    pub(crate) fn subscribe(&self) -> watch::Receiver<bool> {
+        self.done.clone()
+    }
+}
+
+impl Drop for KeptAlive {
+    #[inline]
+    fn drop(&mut self) {
+        let _ = self.running.commands.send(Command::Unregister(self.id));
+    }
+}
+
+/// A lease kept alive by the keep-alive task.
+struct Lease {
+    /// The TTL in seconds, as last returned by a keep-alive.
+    ttl: i64,
+    /// The bucket the next keep-alive is due in, `0` until scheduled.
+    bucket: u64,
+    /// The number of registrations of the lease.
+    refs: usize,
+    done: watch::Sender<bool>,
+    /// The registrations waiting for the first keep-alive of the lease.
+    pending: Vec<Reply>,
+}
+
+impl Lease {
+    #[inline]
+    fn new(ttl: i64, done: watch::Sender<bool>) -> Self {
+        Self {
+            ttl,
+            bucket: 0,
+            refs: 1,
+            done,
+            pending: Vec::new(),
+        }
+    }
+}
+
+/// The task keeping the leases of a pool alive over a single stream.
+struct KeepAliveTask {
+    granularity: Duration,
+    /// The start of the bucket `0`.
+    start: Instant,
+    observer: Observer,
+    keeper: LeaseKeeper,
+    leases: HashMap<i64, Lease>,
+    /// The leases due in every bucket, some of which may have been rescheduled since.
+    wheel: BTreeMap<u64, Vec<i64>>,
+    wakeups: Arc<AtomicU64>,
+}
+
+impl KeepAliveTask {
+    async fn run(
+        mut self,
+        mut stream: LeaseKeepAliveStream,
+        mut commands: UnboundedReceiver<Command>,
+    ) {
+        let reason = loop {
+            let next = self.wheel.keys().next().map(|&bucket| self.instant(bucket));
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(Command::Register { id, ttl, reply }) => {
+                        if let Err(e) = self.register(id, ttl, reply).await {
+                            break e.to_string();
+                        }
+                    }
+                    Some(Command::Unregister(id)) => self.unregister(id),
+                    None => return,
+                },
+                resp = stream.next() => match resp {
+                    Some(Ok(resp)) => self.renewed(resp.id().get(), resp.ttl()),
+                    Some(Err(e)) => break e.to_string(),
+                    None => break String::from("keep alive stream closed"),
+                },
+                () = tokio::time::sleep_until(next.unwrap_or(self.start)), if next.is_some() => {
+                    if let Err(e) = self.tick().await {
+                        break e.to_string();
+                    }
+                }
+            }
+            // The first registration is queued before the task starts, so it only stops once
+            // the leases registered since are gone.
+            if self.leases.is_empty() {
+                return;
+            }
+        };
+
+        // The pending registrations are dropped, and retried on a new stream.
+        for (id, lease) in self.leases {
+            log_event!(
+                Error,
+                "etcd session stopped keeping its lease alive",
+                lease = id,
+                reason = &reason,
+            );
+            lease.done.send_replace(true);
+        }
+    }
+
+    /// The bucket of `instant`.
+    #[inline]
+    fn bucket(&self, instant: Instant) -> u64 {
+        (instant.saturating_duration_since(self.start).as_nanos() / self.granularity.as_nanos())
+            as u64
+    }
+
+    /// The start of `bucket`.
+    #[inline]
+    fn instant(&self, bucket: u64) -> Instant {
+        let offset = self.granularity.as_nanos() * u128::from(bucket);
+        self.start + Duration::from_nanos(offset.try_into().unwrap_or(u64::MAX))
+    }
+
+    /// Schedules the next keep-alive of the lease `id` a third of its TTL after `from`, in
+    /// a bucket after the one of `from`.
+    fn schedule(&mut self, id: i64, from: Instant) {
+        let current = self.bucket(from);
+        let Some(ttl) = self.leases.get(&id).map(|lease| lease.ttl) else {
+            return;
+        };
+        let due = from + Duration::from_millis(ttl.max(1) as u64 * 1000 / 3);
+        let bucket = self.bucket(due).max(current + 1);
+        if let Some(lease) = self.leases.get_mut(&id) {
+            if lease.bucket != bucket {
+                lease.bucket = bucket;
+                self.wheel.entry(bucket).or_default().push(id);
+            }
+        }
+    }
+
+    /// Sends the keep-alives of the leases due, as a batch.
+    async fn tick(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let current = self.bucket(now);
+        let mut batch = Vec::new();
+        while let Some(entry) = self.wheel.first_entry() {
+            if *entry.key() > current {
+                break;
+            }
+            let (bucket, ids) = entry.remove_entry();
+            let leases = &self.leases;
+            batch.extend(
+                ids.into_iter()
+                    .filter(|id| leases.get(id).is_some_and(|lease| lease.bucket == bucket)),
+            );
+        }
+
+        self.wakeups.fetch_add(1, Ordering::Relaxed);
+        self.observer.keep_alive_batch(batch.len());
+        for id in batch {
+            self.keeper.send(id).await?;
+            // Scheduled by the TTL known so far, until the keep-alive returns the new one.
+            self.schedule(id, now);
+        }
+        Ok(())
+    }
+
+    /// Registers the lease `id` of `ttl` seconds, keeping it alive at once unless it is
+    /// already.
+    async fn register(&mut self, id: i64, ttl: i64, reply: Reply) -> Result<()> {
+        match self.leases.entry(id) {
+            Entry::Occupied(mut entry) => {
+                let lease = entry.get_mut();
+                if !lease.pending.is_empty() {
+                    lease.refs += 1;
+                    lease.pending.push(reply);
+                } else if reply.send(Ok(lease.done.subscribe())).is_ok() {
+                    lease.refs += 1;
+                }
+                Ok(())
+            }
+            Entry::Vacant(entry) => {
+                let (done, _) = watch::channel(false);
+                let lease = entry.insert(Lease::new(ttl, done));
+                lease.pending.push(reply);
+                self.keeper.send(id).await?;
+                self.schedule(id, Instant::now());
+                Ok(())
+            }
+        }
+    }
+
+    /// Drops a registration of the lease `id`, which is no longer kept alive once it has
+    /// none left.
+    fn unregister(&mut self, id: i64) {
+        if let Entry::Occupied(mut entry) = self.leases.entry(id) {
+            entry.get_mut().refs -= 1;
+            if entry.get().refs == 0 {
+                entry.remove();
+            }
+        }
+    }
+
+    /// Handles the keep-alive of the lease `id`, which returned `ttl`.
+    fn renewed(&mut self, id: i64, ttl: i64) {
+        let Entry::Occupied(mut entry) = self.leases.entry(id) else {
+            return;
+        };
+        if ttl <= 0 {
+            let lease = entry.remove();
+            log_event!(
+                Error,
+                "etcd session stopped keeping its lease alive",
+                lease = id,
+                reason = "lease expired",
+            );
+            lease.done.send_replace(true);
+            for reply in lease.pending {
+                let not_found = Error::LeaseKeepAliveError(String::from("lease not found"));
+                let _ = reply.send(Err(not_found));
+            }
+            return;
+        }
+
+        let lease = entry.get_mut();
+        lease.ttl = ttl;
+        for reply in std::mem::take(&mut lease.pending) {
+            // The registration was given up on.
+            if reply.send(Ok(lease.done.subscribe())).is_err() {
+                lease.refs -= 1;
+            }
+        }
+        if lease.refs == 0 {
+            entry.remove();
+            return;
+        }
+        self.schedule(id, Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::channel::Channel;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::lock::MutexExt;
+    use crate::rpc::pb::etcdserverpb::{
+        LeaseKeepAliveRequest as PbLeaseKeepAliveRequest,
+        LeaseKeepAliveResponse as PbLeaseKeepAliveResponse,
+    };
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tonic::codec::ProstCodec;
+    use tonic::server::Grpc;
+    use tonic::{Status, Streaming};
+    use tower::util::BoxCloneService;
+
+    /// The TTL of the leases, in seconds.
+    const TTL: i64 = 2;
+
+    /// The expiry of every lease of a server, pushed back by its keep-alives.
+    type Expiries = Arc<std::sync::Mutex<HashMap<i64, Instant>>>;
+
+    /// A lease client of a server keeping the leases of `expiries` alive, which expire if
+    /// not kept alive within their TTL.
+    fn lease_client(expiries: Expiries) -> LeaseClient {
+        let service = tower::service_fn(move |req: http::Request<tonic::body::Body>| {
+            let expiries = expiries.clone();
+            async move {
+                let service = tower::service_fn(
+                    move |req: tonic::Request<Streaming<PbLeaseKeepAliveRequest>>| {
+                        let mut requests = req.into_inner();
+                        let expiries = expiries.clone();
+                        let (sender, receiver) = unbounded_channel();
+                        tokio::spawn(async move {
+                            while let Some(Ok(req)) = requests.next().await {
+                                let now = Instant::now();
+                                let ttl = match expiries.lock_unpoisoned().get_mut(&req.id) {
+                                    Some(expiry) if *expiry > now => {
+                                        *expiry = now + Duration::from_secs(TTL as u64);
+                                        TTL
+                                    }
+                                    _ => -1,
+                                };
+                                let resp = PbLeaseKeepAliveResponse {
+                                    id: req.id,
+                                    ttl,
+                                    ..Default::default()
+                                };
+                                if sender.send(Ok::<_, Status>(resp)).is_err() {
+                                    break;
+                                }
+                            }
+                        });
+                        let resp = UnboundedReceiverStream::new(receiver);
+                        async move { Ok::<_, Status>(tonic::Response::new(resp)) }
+                    },
+                );
+                Ok::<_, tower::BoxError>(
+                    Grpc::new(ProstCodec::default())
+                        .streaming(service, req)
+                        .await,
+                )
+            }
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service)),
+            Interceptor::default(),
+        );
+        LeaseClient::new(channel, AuthToken::default())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_coalesced() {
+        const LEASES: i64 = 1000;
+        let granularity = Duration::from_millis(500);
+        let start = Instant::now();
+        let expiries = Expiries::default();
+        expiries
+            .lock_unpoisoned()
+            .extend((1..=LEASES).map(|id| (id, start + Duration::from_secs(TTL as u64))));
+
+        let mut client = lease_client(expiries.clone());
+        let pool = KeepAlivePool::new(granularity);
+        let mut kept = Vec::new();
+        for id in 1..=LEASES {
+            kept.push(pool.register(&mut client, id, TTL).await.unwrap());
+        }
+
+        let elapsed = Duration::from_secs(30);
+        tokio::time::sleep(elapsed).await;
+        let now = Instant::now();
+        assert!(expiries
+            .lock_unpoisoned()
+            .values()
+            .all(|expiry| *expiry > now));
+        assert!(kept.iter().all(|kept| !kept.is_done()));
+
+        // Bounded by the buckets elapsed, however many leases are kept alive.
+        let buckets = (now - start).as_millis() / granularity.as_millis();
+        assert!(pool.wakeups() > 0);
+        assert!(u128::from(pool.wakeups()) <= buckets + 1);
+
+        // The lease is no longer kept alive once all its registrations are dropped.
+        drop(kept.pop());
+        tokio::time::sleep(Duration::from_secs(TTL as u64 * 2)).await;
+        let now = Instant::now();
+        assert!(expiries.lock_unpoisoned()[&LEASES] <= now);
+        assert!(kept.iter().all(|kept| !kept.is_done()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_first_lease_expired() {
+        let expiries = Expiries::default();
+        let start = Instant::now();
+        expiries
+            .lock_unpoisoned()
+            .extend([(1, start), (2, start + Duration::from_secs(TTL as u64))]);
+
+        // The first lease, starting the task, is checked like the following ones.
+        let mut client = lease_client(expiries.clone());
+        let pool = KeepAlivePool::new(Duration::from_millis(500));
+        let err = pool.register(&mut client, 1, TTL).await.err().unwrap();
+        assert!(err.to_string().contains("lease not found"), "{}", err);
+        let kept = pool.register(&mut client, 2, TTL).await.unwrap();
+        let err = pool.register(&mut client, 1, TTL).await.err().unwrap();
+        assert!(err.to_string().contains("lease not found"), "{}", err);
+
+        tokio::time::sleep(Duration::from_secs(TTL as u64 * 2)).await;
+        assert!(expiries.lock_unpoisoned()[&2] > Instant::now());
+        assert!(!kept.is_done());
+    }
+}
@@ -0,0 +1,261 @@
+//! Observing the latest value of a key, e.g. of a configuration.
+
+use crate::error::{Error, Result};
+use crate::logging::log_event;
+use crate::rpc::kv::KvClient;
+use crate::rpc::watch::{EventType, WatchClient, WatchOptions};
+use crate::rpc::KeyValue;
+use crate::task::Task;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// The name of the task observing a key.
+const OBSERVE_TASK: &str = "key observer";
+
+/// The delay before watching an observed key again after failing to.
+const OBSERVE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Options for [`WatchClient::observe_key`].
+#[derive(Debug, Default, Clone)]
+pub struct ObserveOptions {
+    debounce: Duration,
+}
+
+impl ObserveOptions {
+    /// Creates an `ObserveOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            debounce: Duration::ZERO,
+        }
+    }
+
+    /// Waits for `debounce` after a change for the ones following it, and only updates
+    /// the observed value once with the last of them. No debounce by default.
+    #[inline]
+    pub const fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+}
+
+/// Whether the value of a [`KeyObserver`] is up to date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObserverStatus {
+    /// The key is being watched, the value is the latest one.
+    Live,
+    /// The watch of the key is broken, e.g. while the cluster is unreachable, the value
+    /// may be out of date until it is watched again.
+    Stale,
+}
+
+/// The latest value of a key, see [`WatchClient::observe_key`].
+///
+/// Dropping the observer stops watching the key.
+pub struct KeyObserver {
+    value: watch::Receiver<Option<KeyValue>>,
+    status: watch::Receiver<ObserverStatus>,
+    task: Task,
+}
+
+impl KeyObserver {
+    /// The latest key-value of the key, or `None` if it does not exist.
+    #[inline]
+    pub fn get(&self) -> Option<KeyValue> {
+        self.value.borrow().clone()
+    }
+
+    /// Waits for the key to change since the last [`KeyObserver::get`] or
+    /// [`KeyObserver::changed`].
+    ///
+    /// Fails with an [`Error::InternalTaskFailed`] if the task observing the key panicked.
+    pub async fn changed(&mut self) -> Result<()> {
+        self.value.borrow_and_update();
+        if self.value.changed().await.is_err() {
+            self.task.check()?;
+        }
+        Ok(())
+    }
+
+    /// Subscribes to the latest key-value of the key, e.g. to hand it to another task.
+    #[inline]
+    pub fn subscribe(&self) -> watch::Receiver<Option<KeyValue>> {
+        self.value.clone()
+    }
+
+    /// Returns `true` if the key is being watched, see [`ObserverStatus`].
+    #[inline]
+    pub fn is_live(&self) -> bool {
+        *self.status.borrow() == ObserverStatus::Live
+    }
+
+    /// Subscribes to whether the value is up to date.
+    #[inline]
+    pub fn status(&self) -> watch::Receiver<ObserverStatus> {
+        self.status.clone()
+    }
+}
+
+impl Drop for KeyObserver {
+    #[inline]
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl WatchClient {
+    /// Observes the latest value of `key`, only keeping the last of changes made in a row.
+    ///
+    /// The value is read before returning, then updated from a watch of the key, as soon
+    /// as it changes or once a burst of changes is over, see
+    /// [`ObserveOptions::with_debounce`]. The watch survives reconnects, resuming from the
+    /// last revision seen, and compactions, after which the key is read again. The
+    /// [`KeyObserver::status`] tells whether the value is up to date meanwhile.
+    pub async fn observe_key(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<ObserveOptions>,
+    ) -> Result<KeyObserver> {
+        let key = key.into();
+        let options = options.unwrap_or_default();
+        let (kv, revision) = read(&mut self.kv, &key).await?;
+        let (value_tx, value) = watch::channel(kv);
+        let (status_tx, status) = watch::channel(ObserverStatus::Stale);
+
+        let observer = Observer {
+            kv: self.kv.clone(),
+            watch: self.clone(),
+            key,
+            debounce: options.debounce,
+            revision,
+            value: value_tx,
+            status: status_tx,
+        };
+        let task = self.tasks.spawn(OBSERVE_TASK, observer.run());
+        Ok(KeyObserver {
+            value,
+            status,
+            task,
+        })
+    }
+}
+
+/// Reads `key`, returning its key-value if it exists and the revision it was read at.
+async fn read(kv: &mut KvClient, key: &[u8]) -> Result<(Option<KeyValue>, i64)> {
+    let mut resp = kv.get(key, None).await?;
+    let revision = resp.header().map_or(0, |header| header.revision().get());
+    Ok((resp.take_kvs().into_iter().next(), revision))
+}
+
+/// The task observing a key.
+struct Observer {
+    kv: KvClient,
+    watch: WatchClient,
+    key: Vec<u8>,
+    debounce: Duration,
+    /// The revision the value is up to date with.
+    revision: i64,
+    value: watch::Sender<Option<KeyValue>>,
+    status: watch::Sender<ObserverStatus>,
+}
+
+impl Observer {
+    /// Watches the key until the observer is dropped, which aborts the task.
+    async fn run(mut self) {
+        loop {
+            if let Err(e) = self.watch_key().await {
+                self.status.send_replace(ObserverStatus::Stale);
+                log_event!(
+                    Warn,
+                    "etcd key observer failed to watch its key",
+                    reason = e.to_string(),
+                );
+                tokio::time::sleep(OBSERVE_BACKOFF).await;
+            }
+        }
+    }
+
+    /// Watches the key from the revision after the last one seen, reading it again if the
+    /// revision has been compacted.
+    async fn watch_key(&mut self) -> Result<()> {
+        let options = WatchOptions::new().with_start_revision(self.revision + 1);
+        let (_watcher, mut stream) = match self.watch.watch(self.key.clone(), Some(options)).await {
+            Err(e) if e.is_compacted() => return self.reread().await,
+            watch => watch?,
+        };
+        self.status.send_replace(ObserverStatus::Live);
+
+        // The last change not published yet, and when to publish it.
+        let mut pending: Option<(Option<KeyValue>, Instant)> = None;
+        loop {
+            let resp = match &pending {
+                Some((_, deadline)) => {
+                    tokio::select! {
+                        resp = stream.message() => resp,
+                        _ = tokio::time::sleep_until(*deadline) => {
+                            if let Some((kv, _)) = pending.take() {
+                                self.publish(kv);
+                            }
+                            continue;
+                        }
+                    }
+                }
+                None => stream.message().await,
+            };
+            let resp = match resp {
+                Ok(Some(resp)) if !resp.canceled() => resp,
+                end => {
+                    // The last change is not held back by the watch ending.
+                    if let Some((kv, _)) = pending.take() {
+                        self.publish(kv);
+                    }
+                    return match end? {
+                        Some(_) => self.reread().await,
+                        None => Err(Error::WatchError(String::from("watch stream closed"))),
+                    };
+                }
+            };
+            let Some(event) = resp.events().last() else {
+                continue;
+            };
+            let Some(kv) = event.kv() else {
+                continue;
+            };
+            self.revision = kv.mod_revision().get();
+            let kv = match event.event_type() {
+                EventType::Put => Some(kv.clone()),
+                EventType::Delete => None,
+            };
+            if self.debounce.is_zero() {
+                self.publish(kv);
+            } else {
+                // The deadline of the first change of a burst is kept.
+                let deadline = pending
+                    .take()
+                    .map_or_else(|| Instant::now() + self.debounce, |(_, deadline)| deadline);
+                pending = Some((kv, deadline));
+            }
+        }
+    }
+
+    /// Reads the key again, its changes since the last revision seen being compacted.
+    async fn reread(&mut self) -> Result<()> {
+        let (kv, revision) = read(&mut self.kv, &self.key).await?;
+        self.revision = revision;
+        self.publish(kv);
+        Ok(())
+    }
+
+    /// Publishes `kv` as the latest value, unless it is the one already published.
+    fn publish(&self, kv: Option<KeyValue>) {
+        self.value.send_if_modified(|value| {
+            let revision = |kv: &Option<KeyValue>| kv.as_ref().map(KeyValue::mod_revision);
+            if revision(value) == revision(&kv) {
+                return false;
+            }
+            *value = kv;
+            true
+        });
+    }
+}
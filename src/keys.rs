@@ -0,0 +1,403 @@
+//! Keys of hierarchies whose lexicographic order is the order of their components.
+//!
+//! A [`KeyPath`] joins its components with `/`. Numbers are written big-endian at a fixed
+//! width, so that e.g. job 10 sorts after job 9, and every byte up to `0`, the separator
+//! included, is escaped as `0` followed by the byte plus `0x30`, so that a string holding a
+//! `/` can not be mistaken for two components. The keys of two paths then compare like
+//! their components do, in turn, a path sorting before the paths it is a prefix of.
+//!
+//! Letters and most punctuation are kept as they are, so the keys of strings stay readable,
+//! e.g. `jobs/acme`. A [`KeyReader`] reads the components of a key back, e.g. from a get or
+//! a watch.
+//!
+//! ```no_run
+//! use etcd_client::keys::{KeyPath, KeyReader};
+//! use etcd_client::{Client, Error, GetOptions};
+//!
+//! # async fn jobs(client: &mut Client) -> Result<(), Error> {
+//! let jobs = KeyPath::new("jobs").push_str("acme");
+//! client.put(jobs.clone().push_u64_be(9).build(), "done", None).await?;
+//! client.put(jobs.clone().push_u64_be(10).build(), "pending", None).await?;
+//!
+//! let options = GetOptions::new().with_prefix();
+//! let resp = client.get(jobs.child_prefix(), Some(options)).await?;
+//! for kv in resp.kvs() {
+//!     let mut key = KeyReader::new(kv.key());
+//!     let (_, tenant, job) = (key.next_str()?, key.next_str()?, key.next_u64_be()?);
+//!     key.finish()?;
+//!     println!("{} {}: {}", tenant, job, kv.value_str()?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::bytes::DebugBytes;
+use crate::error::{Error, Result};
+use std::fmt::{self, Debug, Formatter};
+
+/// The separator of the components of a key.
+pub const KEY_SEPARATOR: u8 = b'/';
+
+/// The byte escaping the bytes up to it in the components of a key.
+const ESCAPE: u8 = KEY_SEPARATOR + 1;
+
+/// A key of a hierarchy built component by component, see the [module](self) docs.
+///
+/// Paths compare like their keys, and so like their components. The default path has no
+/// component.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyPath {
+    key: Vec<u8>,
+    /// The number of components, as the first one of an empty root can not be told apart
+    /// by its key.
+    components: usize,
+}
+
+impl KeyPath {
+    /// Creates a path of the single component `root`.
+    #[inline]
+    pub fn new(root: impl AsRef<[u8]>) -> Self {
+        Self::default().push_bytes(root)
+    }
+
+    /// Appends the string `component`.
+    #[inline]
+    pub fn push_str(self, component: impl AsRef<str>) -> Self {
+        self.push_bytes(component.as_ref())
+    }
+
+    /// Appends the bytes `component`.
+    pub fn push_bytes(mut self, component: impl AsRef<[u8]>) -> Self {
+        if self.components > 0 {
+            self.key.push(KEY_SEPARATOR);
+        }
+        self.components += 1;
+        for &byte in component.as_ref() {
+            if byte <= ESCAPE {
+                self.key.extend_from_slice(&[ESCAPE, byte + ESCAPE]);
+            } else {
+                self.key.push(byte);
+            }
+        }
+        self
+    }
+
+    /// Appends the number `component`, written big-endian on 4 bytes.
+    #[inline]
+    pub fn push_u32_be(self, component: u32) -> Self {
+        self.push_bytes(component.to_be_bytes())
+    }
+
+    /// Appends the number `component`, written big-endian on 8 bytes.
+    #[inline]
+    pub fn push_u64_be(self, component: u64) -> Self {
+        self.push_bytes(component.to_be_bytes())
+    }
+
+    /// Appends the signed number `component`, written big-endian on 8 bytes with its sign
+    /// bit flipped, so that negative numbers sort before positive ones.
+    #[inline]
+    pub fn push_i64_be(self, component: i64) -> Self {
+        self.push_u64_be(component as u64 ^ (1 << 63))
+    }
+
+    /// The key of the path.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Builds the key of the path.
+    #[inline]
+    pub fn build(self) -> Vec<u8> {
+        self.key
+    }
+
+    /// The prefix of the keys of the paths below this one, to get or watch them with
+    /// [`GetOptions::with_prefix`](crate::GetOptions::with_prefix) or
+    /// [`WatchOptions::with_prefix`](crate::WatchOptions::with_prefix).
+    ///
+    /// The key of the path itself is left out.
+    #[inline]
+    pub fn child_prefix(&self) -> Vec<u8> {
+        let mut prefix = Vec::with_capacity(self.key.len() + 1);
+        prefix.extend_from_slice(&self.key);
+        prefix.push(KEY_SEPARATOR);
+        prefix
+    }
+
+    /// The key and the range end of the keys of the paths below this one, to get or watch
+    /// them with [`GetOptions::with_range`](crate::GetOptions::with_range) or
+    /// [`WatchOptions::with_range`](crate::WatchOptions::with_range).
+    ///
+    /// The range is the one of [`KeyPath::child_prefix`].
+    #[inline]
+    pub fn range_for_child_prefix(&self) -> (Vec<u8>, Vec<u8>) {
+        let key = self.child_prefix();
+        let mut end = key.clone();
+        // The prefix end, the separator plus one.
+        *end.last_mut().unwrap() = ESCAPE;
+        (key, end)
+    }
+}
+
+impl Debug for KeyPath {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("KeyPath")
+            .field(&DebugBytes(&self.key))
+            .finish()
+    }
+}
+
+impl AsRef<[u8]> for KeyPath {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+impl From<KeyPath> for Vec<u8> {
+    #[inline]
+    fn from(path: KeyPath) -> Self {
+        path.key
+    }
+}
+
+/// A reader of the components of a key built by a [`KeyPath`], see the [module](self) docs.
+///
+/// The components are read in turn, as the type they were pushed as.
+#[derive(Clone)]
+pub struct KeyReader<'a> {
+    key: &'a [u8],
+    /// The part of the key left to read, `None` once every component was read.
+    rest: Option<&'a [u8]>,
+}
+
+impl<'a> KeyReader<'a> {
+    /// Creates a reader of the components of `key`.
+    #[inline]
+    pub fn new(key: &'a [u8]) -> Self {
+        Self {
+            key,
+            rest: Some(key),
+        }
+    }
+
+    /// Whether every component was read.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.rest.is_none()
+    }
+
+    /// Reads the next component, pushed as bytes.
+    pub fn next_bytes(&mut self) -> Result<Vec<u8>> {
+        let Some(rest) = self.rest else {
+            return Err(self.invalid("no component left"));
+        };
+        let (component, rest) = match rest.iter().position(|&b| b == KEY_SEPARATOR) {
+            Some(end) => (&rest[..end], Some(&rest[end + 1..])),
+            None => (rest, None),
+        };
+        let mut bytes = Vec::with_capacity(component.len());
+        let mut escaped = component.iter();
+        while let Some(&byte) = escaped.next() {
+            match byte {
+                ESCAPE => match escaped.next() {
+                    Some(&byte) if (ESCAPE..=2 * ESCAPE).contains(&byte) => {
+                        bytes.push(byte - ESCAPE)
+                    }
+                    _ => return Err(self.invalid("bad escape")),
+                },
+                byte if byte < ESCAPE => return Err(self.invalid("unescaped byte")),
+                byte => bytes.push(byte),
+            }
+        }
+        self.rest = rest;
+        Ok(bytes)
+    }
+
+    /// Reads the next component, pushed as a string.
+    #[inline]
+    pub fn next_str(&mut self) -> Result<String> {
+        let bytes = self.next_bytes()?;
+        String::from_utf8(bytes).map_err(|e| Error::Utf8Error(e.utf8_error()))
+    }
+
+    /// Reads the next component, pushed as a `u32`.
+    #[inline]
+    pub fn next_u32_be(&mut self) -> Result<u32> {
+        self.next_array().map(u32::from_be_bytes)
+    }
+
+    /// Reads the next component, pushed as a `u64`.
+    #[inline]
+    pub fn next_u64_be(&mut self) -> Result<u64> {
+        self.next_array().map(u64::from_be_bytes)
+    }
+
+    /// Reads the next component, pushed as an `i64`.
+    #[inline]
+    pub fn next_i64_be(&mut self) -> Result<i64> {
+        self.next_u64_be().map(|n| (n ^ (1 << 63)) as i64)
+    }
+
+    /// Fails unless every component was read.
+    #[inline]
+    pub fn finish(&self) -> Result<()> {
+        match self.rest {
+            Some(_) => Err(self.invalid("components left")),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads the next component as a number of `N` bytes.
+    fn next_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self.next_bytes()?;
+        bytes
+            .try_into()
+            .map_err(|_| self.invalid(&format!("number not {} bytes long", N)))
+    }
+
+    /// The error of the key read being invalid for `reason`.
+    #[inline]
+    fn invalid(&self, reason: &str) -> Error {
+        Error::InvalidArgs(format!(
+            "invalid key {:?}: {}",
+            DebugBytes(self.key),
+            reason
+        ))
+    }
+}
+
+impl Debug for KeyReader<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyReader")
+            .field("key", &DebugBytes(self.key))
+            .field("rest", &self.rest.map(DebugBytes))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A xorshift generator, for reproducible random components.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Bytes favoring the separator, the escape and the bytes around them.
+        fn bytes(&mut self) -> Vec<u8> {
+            const TRICKY: &[u8] = &[0, 1, b'.', b'/', b'0', b'1', b'`', b'a', 0xFE, 0xFF];
+            let len = self.next() % 5;
+            (0..len)
+                .map(|_| match self.next() % 3 {
+                    0 => self.next() as u8,
+                    _ => TRICKY[self.next() as usize % TRICKY.len()],
+                })
+                .collect()
+        }
+
+        /// A number favoring the ones around the bytes escaped.
+        fn number(&mut self) -> u64 {
+            match self.next() % 3 {
+                0 => self.next(),
+                1 => self.next() % 0x40,
+                _ => u64::MAX - self.next() % 0x40,
+            }
+        }
+    }
+
+    fn path(components: &[Vec<u8>]) -> KeyPath {
+        let mut path = KeyPath::new(&components[0]);
+        for component in &components[1..] {
+            path = path.push_bytes(component);
+        }
+        path
+    }
+
+    #[test]
+    fn test_order_preserved() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        for _ in 0..10_000 {
+            let a: Vec<_> = (0..1 + rng.next() % 3).map(|_| rng.bytes()).collect();
+            let b: Vec<_> = (0..1 + rng.next() % 3).map(|_| rng.bytes()).collect();
+            assert_eq!(path(&a).cmp(&path(&b)), a.cmp(&b), "{:?} {:?}", a, b);
+
+            let a = (rng.bytes(), rng.number(), rng.number() as i64);
+            let b = match rng.next() % 3 {
+                0 => (a.0.clone(), a.1, rng.number() as i64),
+                1 => (a.0.clone(), rng.number(), rng.number() as i64),
+                _ => (rng.bytes(), rng.number(), rng.number() as i64),
+            };
+            let typed = |(s, n, i): &(Vec<u8>, u64, i64)| {
+                KeyPath::new(s).push_u64_be(*n).push_i64_be(*i).build()
+            };
+            assert_eq!(typed(&a).cmp(&typed(&b)), a.cmp(&b), "{:?} {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        for _ in 0..10_000 {
+            let components: Vec<_> = (0..1 + rng.next() % 4).map(|_| rng.bytes()).collect();
+            let (n, i) = (rng.number(), rng.number() as i64);
+            let key = path(&components).push_u64_be(n).push_i64_be(i).build();
+
+            let mut reader = KeyReader::new(&key);
+            for component in &components {
+                assert_eq!(&reader.next_bytes().unwrap(), component);
+            }
+            assert_eq!(reader.next_u64_be().unwrap(), n);
+            assert_eq!(reader.next_i64_be().unwrap(), i);
+            assert!(reader.is_empty());
+            reader.finish().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_separator_escaped() {
+        let key = KeyPath::new("jobs").push_str("a/b").build();
+        assert_eq!(key, b"jobs/a0_b");
+        let mut reader = KeyReader::new(&key);
+        assert_eq!(reader.next_str().unwrap(), "jobs");
+        assert_eq!(reader.next_str().unwrap(), "a/b");
+        reader.finish().unwrap();
+
+        let (start, end) = KeyPath::new("jobs").range_for_child_prefix();
+        assert_eq!((start, end), (b"jobs/".to_vec(), b"jobs0".to_vec()));
+        // The paths next to the children are out of their range.
+        assert!(KeyPath::new("jobs0").build() > b"jobs0".to_vec());
+        assert_eq!(KeyPath::new("").push_str("jobs").build(), b"/jobs");
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let mut reader = KeyReader::new(b"jobs/a\x00");
+        reader.next_str().unwrap();
+        assert!(reader.next_str().is_err());
+
+        let mut reader = KeyReader::new(b"jobs/0");
+        reader.next_str().unwrap();
+        assert!(reader.next_bytes().is_err());
+
+        let mut reader = KeyReader::new(b"jobs/abc");
+        assert!(reader.finish().is_err());
+        reader.next_str().unwrap();
+        let err = reader.next_u32_be().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid arguments: invalid key \"jobs/abc\": number not 4 bytes long"
+        );
+        assert!(reader.next_bytes().is_err());
+    }
+}
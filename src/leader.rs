@@ -0,0 +1,454 @@
+//! Fast failure of the requests needing a leader while the cluster has none.
+//!
+//! With [`ConnectOptions::with_fail_fast_on_no_leader`](crate::ConnectOptions::with_fail_fast_on_no_leader)
+//! the balanced channels of the client go through a [`LeaderGate`]. The client believes the
+//! cluster lost its leader once a response fails with `etcdserver: no leader`, or times out
+//! because of the failure of the leader. From then on, the gate fails the requests needing
+//! a leader at once, with an [`Error::NoLeader`](crate::Error::NoLeader) whose `since` is
+//! set, rather than letting them wait for their timeout. The requests served by a member on
+//! its own still go through: serializable ranges, watches, and the status, hashes,
+//! snapshots and defragmentations of members. Serializable ranges are told apart by an
+//! extension of their requests, which, unlike a task-local, reaches a gate driven by the
+//! worker of a buffer.
+//!
+//! The belief is cleared by the first request needing a leader which succeeds, or by a
+//! probe: while the belief lasts, the status of a member is requested every
+//! [`PROBE_INTERVAL`], until a member reports a leader.
+
+use crate::channel::Channel;
+use crate::lock::MutexExt;
+use crate::rpc::pb::etcdserverpb::{StatusRequest, StatusResponse};
+use crate::task::{Task, Tasks};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tonic::codec::ProstCodec;
+use tonic::metadata::MetadataValue;
+use tonic::Status;
+use tower::util::BoxCloneService;
+use tower::Service;
+
+type Request = http::Request<tonic::body::Body>;
+type Response = http::Response<tonic::body::Body>;
+
+/// The metadata key of the statuses of the requests failed fast, the milliseconds elapsed
+/// since the client believes the cluster has no leader.
+pub(crate) const NO_LEADER_SINCE_KEY: &str = "etcd-client-no-leader-ms";
+
+/// The interval of the probes of the leader, while the cluster is believed to have none.
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The path of the RPC the probes request the status of a member with.
+const STATUS_PATH: &str = "/etcdserverpb.Maintenance/Status";
+
+/// The name of the task probing the leader.
+const PROBE_TASK: &str = "leader probe";
+
+/// The gRPC message of etcd when the cluster has no leader.
+const NO_LEADER_MESSAGE: &str = "etcdserver: no leader";
+
+/// The gRPC messages of etcd telling that the cluster has no leader.
+const NO_LEADER_MESSAGES: &[&str] = &[
+    NO_LEADER_MESSAGE,
+    "etcdserver: request timed out, possibly due to previous leader failure",
+];
+
+/// The paths of the RPCs which members serve on their own, without a leader.
+const LEADERLESS_PATHS: &[&str] = &[
+    "/etcdserverpb.Watch/Watch",
+    STATUS_PATH,
+    "/etcdserverpb.Maintenance/Hash",
+    "/etcdserverpb.Maintenance/HashKV",
+    "/etcdserverpb.Maintenance/Snapshot",
+    "/etcdserverpb.Maintenance/Defragment",
+];
+
+/// The extension of the requests served without a leader, on top of [`LEADERLESS_PATHS`].
+#[derive(Debug, Clone, Copy)]
+struct Serializable;
+
+/// Wraps the message `message` into a request, served without a leader if `serializable`.
+#[cfg(feature = "kv")]
+#[inline]
+pub(crate) fn serializable<T>(serializable: bool, message: T) -> tonic::Request<T> {
+    let mut req = tonic::Request::new(message);
+    if serializable {
+        req.extensions_mut().insert(Serializable);
+    }
+    req
+}
+
+/// Returns `true` if the request `req` needs a leader to be served.
+fn needs_leader(req: &Request) -> bool {
+    let path = req.uri().path();
+    !LEADERLESS_PATHS.contains(&path)
+        && (path != "/etcdserverpb.KV/Range" || req.extensions().get::<Serializable>().is_none())
+}
+
+/// Whether the cluster is believed to have a leader, shared by the gates of a client.
+#[derive(Clone)]
+pub(crate) struct LeaderState(Arc<Mutex<State>>);
+
+struct State {
+    /// When the cluster was first believed to have no leader, `None` while it has one.
+    no_leader_since: Option<Instant>,
+    /// The channel the probes are sent over.
+    channel: Channel,
+    tasks: Tasks,
+    probe: Option<Task>,
+}
+
+impl LeaderState {
+    /// Creates the state of a cluster believed to have a leader, probing it over `channel`
+    /// with `tasks` once it is believed to have none.
+    #[inline]
+    pub(crate) fn new(channel: Channel, tasks: Tasks) -> Self {
+        Self(Arc::new(Mutex::new(State {
+            no_leader_since: None,
+            channel,
+            tasks,
+            probe: None,
+        })))
+    }
+
+    /// When the cluster was first believed to have no leader, if it is.
+    #[inline]
+    fn no_leader_since(&self) -> Option<Instant> {
+        self.0.lock_unpoisoned().no_leader_since
+    }
+
+    /// Believes the cluster has no leader, probing it until it has one again.
+    fn lost(&self) {
+        let mut state = self.0.lock_unpoisoned();
+        state.no_leader_since.get_or_insert_with(Instant::now);
+        if !state
+            .probe
+            .as_ref()
+            .is_some_and(|probe| !probe.is_finished())
+        {
+            let probe = probe(Arc::downgrade(&self.0), state.channel.clone());
+            state.probe = Some(state.tasks.spawn(PROBE_TASK, probe));
+        }
+    }
+
+    /// Believes the cluster has a leader.
+    #[inline]
+    fn found(&self) {
+        self.0.lock_unpoisoned().no_leader_since = None;
+    }
+}
+
+/// Requests the status of a member every [`PROBE_INTERVAL`] until one reports a leader, or
+/// the leader is found otherwise, or the client is dropped.
+async fn probe(state: Weak<Mutex<State>>, channel: Channel) {
+    let mut client = tonic::client::Grpc::new(channel);
+    loop {
+        tokio::time::sleep(PROBE_INTERVAL).await;
+        match state.upgrade() {
+            Some(state) if state.lock_unpoisoned().no_leader_since.is_some() => {}
+            _ => return,
+        }
+        let leader = match status(&mut client).await {
+            Ok(resp) => resp.leader,
+            Err(_) => 0,
+        };
+        if leader != 0 {
+            if let Some(state) = state.upgrade() {
+                LeaderState(state).found();
+            }
+            return;
+        }
+    }
+}
+
+/// Requests the status of a member over `client`, which works without the `maintenance`
+/// feature as the generated client is not needed.
+async fn status(client: &mut tonic::client::Grpc<Channel>) -> Result<StatusResponse, Status> {
+    client
+        .ready()
+        .await
+        .map_err(|e| Status::unavailable(e.to_string()))?;
+    let path = http::uri::PathAndQuery::from_static(STATUS_PATH);
+    let resp = client
+        .unary(
+            tonic::Request::new(StatusRequest {}),
+            path,
+            ProstCodec::default(),
+        )
+        .await?;
+    Ok(resp.into_inner())
+}
+
+/// The channel failing the requests needing a leader at once, while the cluster is believed
+/// to have none.
+#[derive(Clone)]
+pub(crate) struct LeaderGate {
+    inner: Channel,
+    state: LeaderState,
+}
+
+impl LeaderGate {
+    /// Gates the requests sent over `inner` according to `state`.
+    #[inline]
+    pub(crate) fn new(inner: Channel, state: LeaderState) -> Self {
+        Self { inner, state }
+    }
+}
+
+impl Service<Request> for LeaderGate {
+    type Response = Response;
+    type Error = tower::BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, tower::BoxError>> + Send>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let needs_leader = needs_leader(&req);
+        if let Some(since) = self.state.no_leader_since().filter(|_| needs_leader) {
+            return Box::pin(std::future::ready(Ok(no_leader(since))));
+        }
+        let resp = self.inner.call(req);
+        let state = self.state.clone();
+        Box::pin(async move {
+            let resp = resp.await?;
+            // etcd answers the unary requests failing with trailers only.
+            match Status::from_header_map(resp.headers()) {
+                Some(status) if NO_LEADER_MESSAGES.contains(&status.message()) => state.lost(),
+                Some(status) if status.code() != tonic::Code::Ok => {}
+                _ if needs_leader => state.found(),
+                _ => {}
+            }
+            Ok(resp)
+        })
+    }
+}
+
+/// Gates `channel` according to `state`, if any.
+#[inline]
+pub(crate) fn gate(channel: Channel, state: Option<&LeaderState>) -> Channel {
+    match state {
+        Some(state) => Channel::Custom(BoxCloneService::new(LeaderGate::new(
+            channel,
+            state.clone(),
+        ))),
+        None => channel,
+    }
+}
+
+/// The response failing a request fast, the cluster having no leader `since`.
+fn no_leader(since: Instant) -> Response {
+    let mut status = Status::unavailable(NO_LEADER_MESSAGE);
+    let elapsed = since.elapsed().as_millis().to_string();
+    if let Ok(elapsed) = MetadataValue::try_from(elapsed) {
+        status.metadata_mut().insert(NO_LEADER_SINCE_KEY, elapsed);
+    }
+    status.into_http()
+}
+
+#[cfg(all(test, feature = "maintenance"))]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::error::Error;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::rpc::kv::{GetOptions, KvClient};
+    use crate::rpc::maintenance::MaintenanceClient;
+    use crate::rpc::pb::etcdserverpb::{
+        PutRequest as PbPutRequest, PutResponse as PbPutResponse, RangeRequest as PbRangeRequest,
+        RangeResponse as PbRangeResponse, StatusResponse,
+    };
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use tonic::codec::ProstCodec;
+    use tonic::server::Grpc;
+
+    /// How long etcd takes to fail a request needing a leader while the cluster has none.
+    const NO_LEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A member of a cluster which may have lost its leader.
+    #[derive(Clone, Default)]
+    struct Member {
+        leaderless: Arc<AtomicBool>,
+        /// The Range and Put requests the member received.
+        received: Arc<AtomicUsize>,
+    }
+
+    impl Member {
+        fn channel(&self) -> Channel {
+            let member = self.clone();
+            let service = tower::service_fn(move |req: Request| {
+                let member = member.clone();
+                async move { Ok::<_, tower::BoxError>(member.serve(req).await) }
+            });
+            Channel::Custom(BoxCloneService::new(service))
+        }
+
+        /// The clients of the member, failing fast while it has no leader.
+        fn clients(&self) -> (KvClient, MaintenanceClient) {
+            let state = LeaderState::new(self.channel(), Tasks::new(None));
+            let channel =
+                InterceptedChannel::new(gate(self.channel(), Some(&state)), Interceptor::default());
+            (
+                KvClient::new(channel.clone(), AuthToken::default()),
+                MaintenanceClient::new(channel, AuthToken::default()),
+            )
+        }
+
+        async fn serve(self, req: Request) -> Response {
+            let leaderless = self.leaderless.load(Ordering::SeqCst);
+            match req.uri().path() {
+                "/etcdserverpb.KV/Range" => {
+                    self.received.fetch_add(1, Ordering::SeqCst);
+                    let service = tower::service_fn(|req: tonic::Request<PbRangeRequest>| {
+                        let serializable = req.get_ref().serializable;
+                        let resp = PbRangeResponse::default();
+                        answer(leaderless && !serializable, resp)
+                    });
+                    Grpc::new(ProstCodec::default()).unary(service, req).await
+                }
+                "/etcdserverpb.KV/Put" => {
+                    self.received.fetch_add(1, Ordering::SeqCst);
+                    let service = tower::service_fn(|_: tonic::Request<PbPutRequest>| {
+                        answer(leaderless, PbPutResponse::default())
+                    });
+                    Grpc::new(ProstCodec::default()).unary(service, req).await
+                }
+                "/etcdserverpb.Maintenance/Status" => {
+                    let service = tower::service_fn(|_: tonic::Request<StatusRequest>| {
+                        let resp = StatusResponse {
+                            leader: u64::from(!leaderless),
+                            ..Default::default()
+                        };
+                        answer(false, resp)
+                    });
+                    Grpc::new(ProstCodec::default()).unary(service, req).await
+                }
+                path => Status::unimplemented(path.to_string()).into_http(),
+            }
+        }
+    }
+
+    /// Answers `resp`, or fails after [`NO_LEADER_TIMEOUT`] if the member has no leader.
+    async fn answer<T>(
+        leaderless: bool,
+        resp: T,
+    ) -> std::result::Result<tonic::Response<T>, Status> {
+        if leaderless {
+            tokio::time::sleep(NO_LEADER_TIMEOUT).await;
+            return Err(Status::unavailable(NO_LEADER_MESSAGE));
+        }
+        Ok(tonic::Response::new(resp))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fail_fast() {
+        let member = Member::default();
+        let (mut kv, _) = member.clients();
+        kv.put("key", "value", None).await.unwrap();
+
+        member.leaderless.store(true, Ordering::SeqCst);
+        let start = tokio::time::Instant::now();
+        let err = kv.put("key", "value", None).await.unwrap_err();
+        assert!(
+            matches!(err, Error::NoLeader { since: None, .. }),
+            "{:?}",
+            err
+        );
+        assert_eq!(start.elapsed(), NO_LEADER_TIMEOUT);
+        assert_eq!(member.received.load(Ordering::SeqCst), 2);
+
+        // The requests needing a leader fail at once, without being sent.
+        let start = tokio::time::Instant::now();
+        let err = kv.put("key", "value", None).await.unwrap_err();
+        assert!(
+            matches!(err, Error::NoLeader { since: Some(_), .. }),
+            "{:?}",
+            err
+        );
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        let err = kv.get("key", None).await.unwrap_err();
+        assert!(
+            matches!(err, Error::NoLeader { since: Some(_), .. }),
+            "{:?}",
+            err
+        );
+        assert_eq!(member.received.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_leaderless_requests_pass() {
+        let member = Member {
+            leaderless: Arc::new(AtomicBool::new(true)),
+            ..Default::default()
+        };
+        let (mut kv, mut maintenance) = member.clients();
+        kv.put("key", "value", None).await.unwrap_err();
+
+        // Members serve serializable reads and their status on their own.
+        let options = GetOptions::new().with_serializable();
+        kv.get("key", Some(options)).await.unwrap();
+        assert_eq!(member.received.load(Ordering::SeqCst), 2);
+        assert_eq!(maintenance.status().await.unwrap().leader(), 0);
+
+        // They do not clear the belief.
+        let err = kv.get("key", None).await.unwrap_err();
+        assert!(
+            matches!(err, Error::NoLeader { since: Some(_), .. }),
+            "{:?}",
+            err
+        );
+        assert_eq!(member.received.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_probe_clears() {
+        let member = Member {
+            leaderless: Arc::new(AtomicBool::new(true)),
+            ..Default::default()
+        };
+        let (mut kv, _) = member.clients();
+        kv.put("key", "value", None).await.unwrap_err();
+
+        // The probes find no leader.
+        tokio::time::sleep(PROBE_INTERVAL * 3).await;
+        kv.put("key", "value", None).await.unwrap_err();
+        assert_eq!(member.received.load(Ordering::SeqCst), 1);
+
+        // The next probe finds the new leader.
+        member.leaderless.store(false, Ordering::SeqCst);
+        tokio::time::sleep(PROBE_INTERVAL * 3 / 2).await;
+        kv.put("key", "value", None).await.unwrap();
+        assert_eq!(member.received.load(Ordering::SeqCst), 2);
+    }
+
+    /// A request to `path`, served without a leader if `serializable`.
+    fn request(path: &str, serializable: bool) -> Request {
+        let mut req = Request::new(tonic::body::Body::empty());
+        *req.uri_mut() = path.parse().unwrap();
+        if serializable {
+            req.extensions_mut().insert(Serializable);
+        }
+        req
+    }
+
+    #[test]
+    fn test_needs_leader() {
+        let cases = [
+            ("/etcdserverpb.KV/Put", false, true),
+            ("/etcdserverpb.KV/Range", false, true),
+            ("/etcdserverpb.Lease/LeaseGrant", false, true),
+            ("/etcdserverpb.Watch/Watch", false, false),
+            ("/etcdserverpb.Maintenance/Status", false, false),
+            ("/etcdserverpb.KV/Range", true, false),
+            ("/etcdserverpb.KV/Put", true, true),
+        ];
+        for (path, serializable, expected) in cases {
+            let req = request(path, serializable);
+            assert_eq!(needs_leader(&req), expected, "{}", path);
+        }
+    }
+}
@@ -12,6 +12,8 @@
 //! - Lock
 //! - Election
 //!
+//! The `recipes` module builds distributed synchronization recipes on top of them, like those of the Go client, with the `kv`, `watch` and `lease` features.
+//!
 //! # Usage
 //!
 //! Add this to your `Cargo.toml`:
@@ -49,30 +51,202 @@
 //! # Feature Flags
 //!
 //! - `tls`: Enables the `rustls`-based TLS connection. Not enabled by default.
-//! - `tls-roots`: Adds system trust roots to `rustls`-based TLS connection using the `rustls-native-certs` crate. Not enabled by default.
+//! - `tls-roots`: Allows trusting the system trust roots in `rustls`-based TLS connection using the `rustls-native-certs` crate, see `TlsOptions::with_native_roots`. Not enabled by default.
+//! - `tls-webpki-roots`: Allows trusting the Mozilla roots bundled by the `webpki-roots` crate in `rustls`-based TLS connection, see `TlsOptions::with_webpki_roots`. Not enabled by default.
 //! - `pub-response-field`: Exposes structs used to create regular `etcd-client` responses including internal protobuf representations. Useful for mocking. Not enabled by default.
 //! - `tls-openssl`: Enables the `openssl`-based TLS connections. This would make your binary dynamically link to `libssl`.
 //! - `tls-openssl-vendored`: Like `tls-openssl`, however compile openssl from source code and statically link to it.
 //! - `build-server`: Builds a server variant of the etcd protobuf and re-exports it under the same `proto` package as the `pub-response-field` feature does.
 //! - `raw-channel`: Allows the caller to construct the underlying Tonic channel used by the client.
+//! - `raw-proto`: Re-exports the generated protobuf messages and gRPC clients under `raw`, converts the wrappers from and into them, and exposes the generated clients over the channel of a client, e.g. `Client::kv_raw`. The generated types follow the etcd proto files rather than the semver of this crate. Not enabled by default.
+//! - `status-details`: Decodes the `google.rpc.Status` details of server errors, e.g. `ErrorInfo` and `QuotaFailure`, using the `prost-types` crate. Not enabled by default.
+//! - `tracing`: Traces every RPC in a span named after the method, e.g. `etcd.Range`, and propagates the W3C `traceparent` of the span. Not enabled by default.
+//! - `metrics`: Reports counters, gauges and histograms of RPCs, watch events, lease keep-alives, endpoint changes and reconnects to the `metrics` crate. Not enabled by default.
+//! - `logging`: Logs retries, endpoint changes, snapshot resumptions and background failures to the `log` crate, as rate-limited messages with structured key-values. Not enabled by default.
+//! - `blocking`: Exposes `blocking::Client`, a synchronous client running the asynchronous one on its own or a borrowed tokio runtime. Not enabled by default.
+//! - `gzip`: Enables the gzip compression of requests and responses, see `ConnectOptions::with_send_compression`. Not enabled by default.
+//! - `zstd`: Enables the zstd compression of requests and responses, see `ConnectOptions::with_send_compression`. Not enabled by default.
+//! - `env`: Loads `ConnectOptions` and endpoints from the `ETCDCTL_` and `ETCD_` environment variables of `etcdctl`, see `ConnectOptions::from_env` and `Client::connect_from_env`. Not enabled by default.
+//! - `config`: Reads `ClientConfig` from YAML or JSON config files in the layout of the Go client, converted into `ConnectOptions` and endpoints, see `ConnectOptions::from_file`. Not enabled by default.
+//! - `http-probe`: Probes the `/health` and `/version` endpoints of members over HTTP, telling a member down from a member up without quorum, see `MaintenanceClient::http_health` and `MaintenanceClient::http_version`. Enables `maintenance`. Not enabled by default.
+//! - `record-replay`: Records the gRPC exchanges of a channel with `replay::RecordingChannel` and replays them without a cluster with `replay::ReplayChannel`, e.g. in hermetic tests checked by `replay::verify`. Enables `raw-channel`. Not enabled by default.
+//! - `value-zstd`: Provides `ZstdCodec`, compressing the values of keys with zstd for `Client::with_value_codec`. Enables `kv`. Not enabled by default.
+//! - `test-util`: Exposes `test_util::EtcdCluster`, starting etcd clusters from the `etcd` binary on the `PATH` or from docker containers for integration tests, with members that can be stopped and partitioned, and `test_util::MockEtcd`, an in-process mock of the KV and Watch RPCs. Not enabled by default.
+//! - `kv`, `watch`, `lease`, `lock`, `election`, `maintenance`, `cluster`, `auth`: Enable the clients of the etcd services, and the generated gRPC clients they wrap. `lock` enables `lease`, `election` enables `lease` and `watch`, `maintenance` enables `kv` and `cluster`; endpoint sync requires `cluster`, authenticating with a user requires `auth`. Enabled by default, use `default-features = false` to compile only the services in use.
+//! - `serde`: Implements `Serialize` and `Deserialize` for the responses, key-values and request options, named after the proto fields with bytes encoded as base64, and `Etcdctl` writing the layout of `etcdctl -w json`, e.g. by `GetResponse::to_etcdctl_json`. Not enabled by default.
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
+// The shared plumbing is only used in full by the clients of all the services.
+#![cfg_attr(
+    not(all(
+        feature = "kv",
+        feature = "watch",
+        feature = "lease",
+        feature = "lock",
+        feature = "election",
+        feature = "maintenance",
+        feature = "cluster",
+        feature = "auth"
+    )),
+    allow(dead_code, unused_imports, unused_macros)
+)]
 
 mod auth;
+#[cfg(feature = "blocking")]
+#[cfg_attr(docsrs, doc(cfg(feature = "blocking")))]
+pub mod blocking;
+mod bytes;
 mod channel;
+mod circuit_breaker;
 mod client;
+mod compression;
+#[cfg(feature = "config")]
+mod config;
+#[cfg(all(feature = "kv", any(feature = "lock", feature = "election")))]
+mod contender;
+mod deadline;
+#[cfg(feature = "kv")]
+mod delete_guard;
+#[cfg(feature = "cluster")]
+mod endpoint_sync;
+#[cfg(feature = "env")]
+mod env;
 mod error;
+#[cfg(all(feature = "kv", feature = "watch"))]
+mod get_watch;
+#[cfg(feature = "kv")]
+mod hedge;
+#[cfg(feature = "http-probe")]
+mod http_probe;
+mod ids;
 mod intercept;
+#[cfg(feature = "lease")]
+mod keep_alive;
+#[cfg(all(feature = "kv", feature = "watch"))]
+mod key_observer;
+pub mod keys;
+mod leader;
 mod lock;
+mod logging;
+mod metadata;
+#[cfg(feature = "metrics")]
+mod metric;
 mod namespace;
+mod observe;
 mod openssl_tls;
+#[cfg(feature = "kv")]
+mod ordering;
+mod rate_limit;
+#[cfg(feature = "raw-proto")]
+#[cfg_attr(docsrs, doc(cfg(feature = "raw-proto")))]
+pub mod raw;
+#[cfg(all(feature = "kv", feature = "watch", feature = "lease"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(all(feature = "kv", feature = "watch", feature = "lease")))
+)]
+pub mod recipes;
+#[cfg(feature = "kv")]
+mod rename;
+#[cfg(feature = "record-replay")]
+#[cfg_attr(docsrs, doc(cfg(feature = "record-replay")))]
+pub mod replay;
+mod retry;
+#[cfg(any(feature = "kv", feature = "maintenance"))]
+mod route;
 mod rpc;
+#[cfg(feature = "kv")]
+mod scan;
+#[cfg(feature = "auth")]
+mod secret;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "lease")]
+mod session;
+#[cfg(any(feature = "env", feature = "config"))]
+mod settings;
+#[cfg(feature = "watch")]
+mod shared_watch;
+#[cfg(feature = "kv")]
+mod size_accounting;
+#[cfg(feature = "status-details")]
+mod status_details;
+#[cfg(feature = "kv")]
+mod stm;
+mod task;
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod test_util;
+#[cfg(any(feature = "tls-ring", feature = "tls-aws-lc"))]
+mod tls;
+mod trace;
+#[cfg(all(feature = "kv", feature = "lease"))]
+mod ttl_put;
+#[cfg(feature = "kv")]
+mod txn_chunk;
+#[cfg(feature = "kv")]
+mod value_codec;
 mod vec;
+mod warm_up;
 
-pub use crate::channel::{BalancedChannelBuilder, Channel};
-pub use crate::client::{Client, ConnectOptions};
-pub use crate::error::Error;
-pub use crate::namespace::{KvClientPrefix, LeaseClientPrefix};
+pub use crate::bytes::DEBUG_BYTES_LIMIT;
+pub use crate::channel::{
+    BalancedChannelBuilder, Change, Channel, CustomChannel, EndpointUpdater, UpdateError,
+    UpdateErrorKind,
+};
+pub use crate::circuit_breaker::{CircuitBreakerOptions, CircuitState};
+pub use crate::client::{
+    Client, ConnectOptions, ConnectOptionsBuilder, EndpointConfig, DEFAULT_CONNECT_TIMEOUT,
+    DEFAULT_ENDPOINT_PORT, DEFAULT_KEEP_ALIVE_TIMEOUT, DEFAULT_USER_AGENT,
+};
+#[cfg(feature = "config")]
+#[cfg_attr(docsrs, doc(cfg(feature = "config")))]
+pub use crate::config::ClientConfig;
+#[cfg(all(feature = "kv", any(feature = "lock", feature = "election")))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(all(feature = "kv", any(feature = "lock", feature = "election"))))
+)]
+pub use crate::contender::Contender;
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+pub use crate::delete_guard::DeleteDryRun;
+#[cfg(feature = "cluster")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cluster")))]
+pub use crate::endpoint_sync::{EndpointSync, EndpointSyncOptions, DEFAULT_ENDPOINT_SYNC_INTERVAL};
+pub use crate::error::{ConnectError, Error};
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+pub use crate::hedge::{HedgeEvent, ReadHedging};
+#[cfg(feature = "http-probe")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http-probe")))]
+pub use crate::http_probe::{Health, VersionInfo};
+pub use crate::ids::{LeaseId, Revision};
+#[cfg(feature = "lease")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lease")))]
+pub use crate::keep_alive::DEFAULT_LEASE_KEEP_ALIVE_GRANULARITY;
+#[cfg(all(feature = "kv", feature = "watch"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "kv", feature = "watch"))))]
+pub use crate::key_observer::{KeyObserver, ObserveOptions, ObserverStatus};
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+pub use crate::namespace::KvClientPrefix;
+#[cfg(feature = "lease")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lease")))]
+pub use crate::namespace::LeaseClientPrefix;
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+pub use crate::ordering::{OrderedKvClient, DEFAULT_ORDERING_RETRIES};
+pub use crate::rate_limit::{RateLimit, RateLimitMode};
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+pub use crate::rename::{RenameOptions, RenameResult, SwapResult};
+pub use crate::retry::{RetryPolicy, DEFAULT_READ_RETRIES};
+#[cfg(any(feature = "kv", feature = "maintenance"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "kv", feature = "maintenance"))))]
+pub use crate::route::RouteTo;
+#[cfg(feature = "auth")]
+#[cfg_attr(docsrs, doc(cfg(feature = "auth")))]
 pub use crate::rpc::auth::{
     AuthClient, AuthDisableResponse, AuthEnableResponse, AuthenticateResponse, Permission,
     PermissionType, RoleAddResponse, RoleDeleteResponse, RoleGetResponse,
@@ -81,39 +255,122 @@ pub use crate::rpc::auth::{
     UserDeleteResponse, UserGetResponse, UserGrantRoleResponse, UserListResponse,
     UserRevokeRoleResponse,
 };
+#[cfg(feature = "cluster")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cluster")))]
 pub use crate::rpc::cluster::{
-    ClusterClient, Member, MemberAddOptions, MemberAddResponse, MemberListResponse,
-    MemberPromoteResponse, MemberRemoveResponse, MemberUpdateResponse,
+    ClusterClient, Member, MemberAddOptions, MemberAddResponse, MemberListOptions,
+    MemberListResponse, MemberPromoteResponse, MemberRemoveResponse, MemberUpdateResponse,
+    PromoteOptions,
 };
+#[cfg(feature = "election")]
+#[cfg_attr(docsrs, doc(cfg(feature = "election")))]
 pub use crate::rpc::election::{
-    CampaignResponse, ElectionClient, LeaderKey, LeaderResponse, ObserveStream, ProclaimOptions,
-    ProclaimResponse, ResignOptions, ResignResponse,
+    CampaignResponse, ElectionClient, ElectionOptions, LeaderKey, LeaderResponse, LeadershipEvent,
+    LeadershipEvents, LeadershipGuard, ObserveStream, ProclaimOptions, ProclaimResponse,
+    ResignOptions, ResignResponse,
 };
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
 pub use crate::rpc::kv::{
     CompactionOptions, CompactionResponse, Compare, CompareOp, DeleteOptions, DeleteResponse,
     GetOptions, GetResponse, KvClient, PutOptions, PutResponse, SortOrder, SortTarget, Txn, TxnOp,
     TxnOpResponse, TxnResponse,
 };
+#[cfg(feature = "lease")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lease")))]
 pub use crate::rpc::lease::{
     LeaseClient, LeaseGrantOptions, LeaseGrantResponse, LeaseKeepAliveResponse,
     LeaseKeepAliveStream, LeaseKeeper, LeaseLeasesResponse, LeaseRevokeResponse, LeaseStatus,
     LeaseTimeToLiveOptions, LeaseTimeToLiveResponse,
 };
-pub use crate::rpc::lock::{LockClient, LockOptions, LockResponse, UnlockResponse};
+#[cfg(all(feature = "lock", feature = "kv"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "lock", feature = "kv"))))]
+pub use crate::rpc::lock::BreakOptions;
+#[cfg(feature = "lock")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lock")))]
+pub use crate::rpc::lock::{LockClient, LockGuard, LockOptions, LockResponse, UnlockResponse};
+#[cfg(feature = "maintenance")]
+#[cfg_attr(docsrs, doc(cfg(feature = "maintenance")))]
 pub use crate::rpc::maintenance::{
-    AlarmAction, AlarmMember, AlarmOptions, AlarmResponse, AlarmType, DefragmentResponse,
-    HashKvResponse, HashResponse, MaintenanceClient, MoveLeaderResponse, SnapshotResponse,
-    SnapshotStreaming, StatusResponse,
+    AlarmAction, AlarmMember, AlarmOptions, AlarmResponse, AlarmType, ClusterHealth,
+    ConsistencyReport, DefragOptions, DefragmentResponse, DowngradeResponse, HashKvResponse,
+    HashResponse, MaintenanceClient, MemberDefragmentResult, MemberHashKvResult, MemberResult,
+    MoveLeaderResponse, SnapshotHashCheck, SnapshotOptions, SnapshotResponse, SnapshotStreaming,
+    SnapshotSummary, SnapshotVerification, StatusResponse, VerifySnapshotOptions,
 };
+#[cfg(feature = "watch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
 pub use crate::rpc::watch::{
     Event, EventType, WatchClient, WatchFilterType, WatchOptions, WatchResponse, WatchStream,
     Watcher,
 };
-pub use crate::rpc::{KeyValue, ResponseHeader};
+pub use crate::rpc::{HasResponseHeader, KeyValue, ResponseHeader};
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+pub use crate::scan::{
+    ParallelScanOptions, ScanOrder, ScanStream, DEFAULT_SCAN_BATCH_SIZE, DEFAULT_SCAN_PREFETCH,
+    DEFAULT_SCAN_SHARDS,
+};
+#[cfg(feature = "auth")]
+#[cfg_attr(docsrs, doc(cfg(feature = "auth")))]
+pub use crate::secret::Secret;
+#[cfg(feature = "serde")]
+pub use crate::serialize::Etcdctl;
+#[cfg(feature = "lease")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lease")))]
+pub use crate::session::{Session, SessionHandle, SessionOptions, DEFAULT_SESSION_TTL};
+#[cfg(feature = "watch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+pub use crate::shared_watch::{SharedWatchEvent, SharedWatchStream};
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+pub use crate::size_accounting::{
+    QuotaWarning, SizeAccountingOptions, WriteStats, DEFAULT_QUOTA_CHECK_INTERVAL,
+    DEFAULT_QUOTA_WARN_FRACTION,
+};
+pub use tokio_util::sync::CancellationToken;
 
 #[cfg(any(feature = "tls-ring", feature = "tls-aws-lc"))]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "tls-ring", feature = "tls-aws-lc"))))]
-pub use tonic::transport::{Certificate, ClientTlsConfig as TlsOptions, Identity};
+pub use crate::tls::TlsOptions;
+#[cfg(any(feature = "tls-ring", feature = "tls-aws-lc"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "tls-ring", feature = "tls-aws-lc"))))]
+pub use tonic::transport::{Certificate, Identity};
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "gzip", feature = "zstd"))))]
+pub use tonic::codec::CompressionEncoding;
+
+#[cfg(feature = "status-details")]
+#[cfg_attr(docsrs, doc(cfg(feature = "status-details")))]
+pub use crate::status_details::{ErrorInfo, QuotaFailure, QuotaViolation};
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+pub use crate::stm::{IsolationLevel, Stm, DEFAULT_STM_RETRIES};
+pub use crate::warm_up::EndpointWarmUp;
+
+#[cfg(feature = "tracing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+pub use crate::trace::{TraceKeys, TraceOptions};
+#[cfg(all(feature = "kv", feature = "lease"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "kv", feature = "lease"))))]
+pub use crate::ttl_put::{PutTtlOptions, TtlPut};
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+pub use crate::txn_chunk::{ChunkedTxnOptions, ChunkedTxnResponse, DEFAULT_MAX_TXN_OPS};
+#[cfg(all(feature = "kv", feature = "watch"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "kv", feature = "watch"))))]
+pub use crate::value_codec::ValueCodecWatchStream;
+#[cfg(feature = "value-zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "value-zstd")))]
+pub use crate::value_codec::ZstdCodec;
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+pub use crate::value_codec::{ValueCodec, ValueCodecClient};
+
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+pub use crate::metric::DEFAULT_METRICS_PREFIX;
 
 #[cfg(feature = "tls-openssl")]
 #[cfg_attr(docsrs, doc(cfg(feature = "tls-openssl")))]
@@ -141,8 +398,9 @@ pub mod proto {
         AuthUserRevokeRoleResponse as PbAuthUserRevokeRoleResponse,
         AuthenticateResponse as PbAuthenticateResponse, CompactionResponse as PbCompactionResponse,
         Compare as PbCompare, DefragmentResponse as PbDefragmentResponse,
-        DeleteRangeResponse as PbDeleteResponse, HashKvResponse as PbHashKvResponse,
-        HashResponse as PbHashResponse, LeaseGrantResponse as PbLeaseGrantResponse,
+        DeleteRangeResponse as PbDeleteResponse, DowngradeResponse as PbDowngradeResponse,
+        HashKvResponse as PbHashKvResponse, HashResponse as PbHashResponse,
+        LeaseGrantResponse as PbLeaseGrantResponse,
         LeaseKeepAliveResponse as PbLeaseKeepAliveResponse,
         LeaseLeasesResponse as PbLeaseLeasesResponse, LeaseRevokeResponse as PbLeaseRevokeResponse,
         LeaseStatus as PbLeaseStatus, LeaseTimeToLiveResponse as PbLeaseTimeToLiveResponse,
@@ -1,7 +1,4 @@
-use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
-
-#[cfg(feature = "tls-openssl")]
-use std::sync::{Mutex, MutexGuard};
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 pub trait RwLockExt<T: ?Sized> {
     fn read_unpoisoned(&self) -> RwLockReadGuard<'_, T>;
@@ -30,12 +27,10 @@ impl<T: ?Sized> RwLockExt<T> for RwLock<T> {
     }
 }
 
-#[cfg(feature = "tls-openssl")]
 pub trait MutexExt<T: ?Sized> {
     fn lock_unpoisoned(&self) -> MutexGuard<'_, T>;
 }
 
-#[cfg(feature = "tls-openssl")]
 impl<T: ?Sized> MutexExt<T> for Mutex<T> {
     fn lock_unpoisoned(&self) -> MutexGuard<'_, T> {
         match self.lock() {
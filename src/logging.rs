@@ -0,0 +1,279 @@
+//! Logging through the `log` facade, enabled by the `logging` feature.
+//!
+//! The client logs what it does to ride out an outage, under the targets of its modules,
+//! e.g. `etcd_client::retry`:
+//!
+//! - `warn`: an RPC is retried, with `rpc`, `attempt`, `delay_ms` and `error`,
+//! - `warn`: a snapshot stream is re-established, with `attempt`, `revision`, `offset` and
+//!   `error`, and the endpoint sync fails to list the members, with `error`,
+//! - `warn`: a call routed to the leader or a given member is sent to any member as that
+//!   member can not be reached, with `route` and `error`,
+//! - `warn`: a record can not be written to the sink of a recording channel, with `error`,
+//! - `info`: an endpoint is added to or removed from a balanced channel, with `endpoint`,
+//! - `error`: a retried RPC runs out of attempts, with `rpc`, `attempts` and `error`, a
+//!   session stops keeping its lease alive, with `lease` and `reason`, and a background
+//!   task panics, with `task` and `panic`.
+//!
+//! Every message carries its fields both as structured key-values and as `key=value` pairs
+//! appended to the text, for loggers ignoring key-values. Every call site logs bursts of up
+//! to [`BURST`] messages, then one message per [`REFILL`], so that e.g. a flapping endpoint
+//! does not flood the logs. The next message logged reports the number of messages
+//! suppressed in between as `suppressed`.
+//!
+//! Without the feature, none of this is compiled.
+
+/// Logs the event `$message` with the fields `$key` at the level `$level`, subject to the
+/// rate limit of the call site.
+#[cfg(feature = "logging")]
+macro_rules! log_event {
+    ($level:ident, $message:literal $(, $key:ident = $value:expr)* $(,)?) => {{
+        static LIMIT: $crate::logging::RateLimit = $crate::logging::RateLimit::new();
+        if log::log_enabled!(log::Level::$level) {
+            if let Some(suppressed) = LIMIT.acquire(std::time::Instant::now()) {
+                match ($($value,)*) {
+                    ($($key,)*) => log::log!(
+                        log::Level::$level,
+                        $($key:% = $key,)* suppressed = suppressed;
+                        concat!($message $(, " ", stringify!($key), "={}")*, "{}"),
+                        $($key,)* $crate::logging::Suppressed(suppressed),
+                    ),
+                }
+            }
+        }
+    }};
+}
+
+/// Logs the event `$message` with the fields `$key` at the level `$level`, subject to the
+/// rate limit of the call site.
+#[cfg(not(feature = "logging"))]
+macro_rules! log_event {
+    ($level:ident, $message:literal $(, $key:ident = $value:expr)* $(,)?) => {{
+        $(let _ = &$value;)*
+    }};
+}
+
+pub(crate) use log_event;
+
+#[cfg(feature = "logging")]
+pub(crate) use enabled::*;
+
+#[cfg(feature = "logging")]
+mod enabled {
+    use crate::lock::MutexExt;
+    use std::fmt::{self, Display, Formatter};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    /// The number of messages a call site logs in a burst.
+    pub(crate) const BURST: u32 = 10;
+
+    /// The interval a call site regains one message of its burst after.
+    pub(crate) const REFILL: Duration = Duration::from_secs(1);
+
+    /// The rate limit of a call site, a token bucket of [`BURST`] messages.
+    pub(crate) struct RateLimit(Mutex<Bucket>);
+
+    struct Bucket {
+        tokens: u32,
+        refilled: Option<Instant>,
+        suppressed: u64,
+    }
+
+    impl RateLimit {
+        #[inline]
+        pub(crate) const fn new() -> Self {
+            Self(Mutex::new(Bucket {
+                tokens: BURST,
+                refilled: None,
+                suppressed: 0,
+            }))
+        }
+
+        /// Returns the number of messages suppressed since the last one if a message may be
+        /// logged at `now`, or records a suppressed message.
+        pub(crate) fn acquire(&self, now: Instant) -> Option<u64> {
+            let mut bucket = self.0.lock_unpoisoned();
+            let refilled = *bucket.refilled.get_or_insert(now);
+            let refills = now.saturating_duration_since(refilled).as_nanos() / REFILL.as_nanos();
+            if refills >= u128::from(BURST) {
+                bucket.tokens = BURST;
+                bucket.refilled = Some(now);
+            } else if refills > 0 {
+                // Below the burst, so that the conversions can not overflow.
+                bucket.tokens = (bucket.tokens + refills as u32).min(BURST);
+                bucket.refilled = Some(refilled + REFILL * refills as u32);
+            }
+
+            if bucket.tokens == 0 {
+                bucket.suppressed += 1;
+                return None;
+            }
+            bucket.tokens -= 1;
+            Some(std::mem::take(&mut bucket.suppressed))
+        }
+    }
+
+    /// Displays the number of suppressed messages, if any, at the end of a message.
+    pub(crate) struct Suppressed(pub(crate) u64);
+
+    impl Display for Suppressed {
+        #[inline]
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            match self.0 {
+                0 => Ok(()),
+                suppressed => write!(f, " suppressed={}", suppressed),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "logging"))]
+mod tests {
+    use super::*;
+    use crate::channel::Change;
+    use crate::error::Error;
+    use crate::observe::Observer;
+    use crate::retry::{retry, RetryPolicy};
+    use log::kv::{Key, Value, VisitSource};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::sync::Once;
+    use std::time::{Duration, Instant};
+
+    /// A record captured by [`Capture`].
+    #[derive(Debug)]
+    struct Record {
+        level: log::Level,
+        message: String,
+        fields: HashMap<String, String>,
+    }
+
+    thread_local! {
+        static RECORDS: RefCell<Option<Vec<Record>>> = const { RefCell::new(None) };
+    }
+
+    /// Captures the records logged on the threads running [`capture`], the only threads
+    /// the logger is enabled on, so that the tests running concurrently do not interfere.
+    struct Capture;
+
+    impl log::Log for Capture {
+        fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+            RECORDS.with_borrow(Option::is_some)
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            struct Fields(HashMap<String, String>);
+
+            impl<'kvs> VisitSource<'kvs> for Fields {
+                fn visit_pair(
+                    &mut self,
+                    key: Key<'kvs>,
+                    value: Value<'kvs>,
+                ) -> Result<(), log::kv::Error> {
+                    self.0.insert(key.to_string(), value.to_string());
+                    Ok(())
+                }
+            }
+
+            let mut fields = Fields(HashMap::new());
+            record.key_values().visit(&mut fields).unwrap();
+            RECORDS.with_borrow_mut(|records| {
+                if let Some(records) = records {
+                    records.push(Record {
+                        level: record.level(),
+                        message: record.args().to_string(),
+                        fields: fields.0,
+                    });
+                }
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Runs `f` on the current thread, returning the records it logged.
+    async fn capture<F: std::future::Future>(f: F) -> Vec<Record> {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&Capture).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+        RECORDS.with_borrow_mut(|records| *records = Some(Vec::new()));
+        f.await;
+        RECORDS.with_borrow_mut(Option::take).unwrap()
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limit = RateLimit::new();
+        let now = Instant::now();
+        for _ in 0..BURST {
+            assert_eq!(limit.acquire(now), Some(0));
+        }
+        assert_eq!(limit.acquire(now), None);
+        assert_eq!(limit.acquire(now + REFILL / 2), None);
+        assert_eq!(limit.acquire(now + REFILL), Some(2));
+        assert_eq!(limit.acquire(now + REFILL), None);
+        // A quiet call site regains its burst, but not more.
+        let later = now + REFILL * 100;
+        for _ in 0..BURST {
+            assert!(limit.acquire(later).is_some());
+        }
+        assert_eq!(limit.acquire(later), None);
+
+        assert_eq!(Suppressed(0).to_string(), "");
+        assert_eq!(Suppressed(3).to_string(), " suppressed=3");
+    }
+
+    #[tokio::test]
+    async fn test_retry_logged() {
+        let policy = RetryPolicy::new()
+            .with_max_attempts(3)
+            .with_initial_backoff(Duration::from_millis(1))
+            .with_jitter(0.0);
+        let records = capture(async {
+            let result = retry(Some(&policy), 0, "Range", true, || async {
+                Err::<(), _>(Error::from(tonic::Status::unavailable(
+                    "etcdserver: request timed out",
+                )))
+            })
+            .await;
+            assert!(result.is_err());
+        })
+        .await;
+
+        assert_eq!(records.len(), 3, "{:?}", records);
+        for (record, attempt) in records[..2].iter().zip(["1", "2"]) {
+            assert_eq!(record.level, log::Level::Warn);
+            assert_eq!(record.fields["rpc"], "Range");
+            assert_eq!(record.fields["attempt"], attempt);
+            assert!(record.fields["error"].contains("request timed out"));
+            assert!(record.message.starts_with(&format!(
+                "retrying etcd request rpc=Range attempt={}",
+                attempt
+            )));
+        }
+        assert_eq!(records[2].level, log::Level::Error);
+        assert_eq!(records[2].fields["attempts"], "3");
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_change_logged() {
+        let uri = http::Uri::from_static("http://127.0.0.1:2379");
+        let records = capture(async {
+            let observer = Observer::default();
+            observer.endpoint_changed(&Change::Insert(uri.clone(), ()));
+            observer.endpoint_changed(&Change::<_, ()>::Remove(uri));
+        })
+        .await;
+
+        assert_eq!(records.len(), 2, "{:?}", records);
+        assert!(records.iter().all(|r| r.level == log::Level::Info));
+        assert_eq!(
+            records[0].message,
+            "etcd endpoint added endpoint=http://127.0.0.1:2379/"
+        );
+        assert_eq!(records[1].fields["endpoint"], "http://127.0.0.1:2379/");
+        assert_eq!(records[1].fields["suppressed"], "0");
+    }
+}
@@ -0,0 +1,127 @@
+//! Custom metadata sent with requests.
+//!
+//! The metadata of the client is attached to every request by the [`Interceptor`], along
+//! with the metadata of the current call, which is kept in a task-local while the call runs.
+//!
+//! [`Interceptor`]: crate::intercept::Interceptor
+
+use crate::error::{Error, Result};
+use std::future::Future;
+use std::sync::Arc;
+use tonic::metadata::{
+    AsciiMetadataKey, AsciiMetadataValue, BinaryMetadataKey, BinaryMetadataValue, KeyAndValueRef,
+    MetadataMap,
+};
+
+/// Keys of the metadata set by the client itself, or by the gRPC transport.
+const RESERVED_KEYS: &[&str] = &[
+    "authorization",
+    "token",
+    "hasleader",
+    "te",
+    "content-type",
+    "user-agent",
+    crate::intercept::CLIENT_NAME_KEY,
+];
+
+/// Prefix of the keys reserved by gRPC.
+const RESERVED_PREFIX: &str = "grpc-";
+
+tokio::task_local! {
+    static CALL: Metadata;
+}
+
+/// Metadata validated when it is added, cheap to clone.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Metadata(Option<Arc<MetadataMap>>);
+
+impl Metadata {
+    /// Creates an empty `Metadata`.
+    #[inline]
+    pub(crate) const fn new() -> Self {
+        Self(None)
+    }
+
+    /// Adds the value `value` to the key `key`, whose value is binary if it ends with
+    /// `-bin`, as required by gRPC.
+    pub(crate) fn append(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        let invalid = |what: &str| Error::InvalidMetadata(format!("invalid {} of {}", what, key));
+        if key.ends_with("-bin") {
+            let key = BinaryMetadataKey::from_bytes(key.as_bytes()).map_err(|_| invalid("key"))?;
+            check_reserved(key.as_str())?;
+            self.map_mut()
+                .append_bin(key, BinaryMetadataValue::from_bytes(value));
+        } else {
+            let key = AsciiMetadataKey::from_bytes(key.as_bytes()).map_err(|_| invalid("key"))?;
+            check_reserved(key.as_str())?;
+            let value = AsciiMetadataValue::try_from(value).map_err(|_| invalid("value"))?;
+            self.map_mut().append(key, value);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn map_mut(&mut self) -> &mut MetadataMap {
+        Arc::make_mut(self.0.get_or_insert_with(Default::default))
+    }
+
+    /// Adds the metadata of the client, `self`, and of the current call to `map`, the
+    /// metadata of the call replacing the one of the client under the same keys.
+    pub(crate) fn apply(&self, map: &mut MetadataMap) {
+        let call = CALL.try_with(|call| call.0.clone()).ok().flatten();
+        if let Some(client) = &self.0 {
+            for entry in client.iter() {
+                match entry {
+                    KeyAndValueRef::Ascii(key, value) => {
+                        if !call.as_ref().is_some_and(|c| c.contains_key(key)) {
+                            map.append(key.clone(), value.clone());
+                        }
+                    }
+                    KeyAndValueRef::Binary(key, value) => {
+                        if !call.as_ref().is_some_and(|c| c.contains_key(key)) {
+                            map.append_bin(key.clone(), value.clone());
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(call) = &call {
+            for entry in call.iter() {
+                match entry {
+                    KeyAndValueRef::Ascii(key, value) => {
+                        map.append(key.clone(), value.clone());
+                    }
+                    KeyAndValueRef::Binary(key, value) => {
+                        map.append_bin(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// The metadata of the current call, e.g. to pass it on to the tasks the call spawns.
+    #[inline]
+    pub(crate) fn current() -> Self {
+        CALL.try_with(Clone::clone).unwrap_or_default()
+    }
+
+    /// Runs `fut` as a call sending the metadata `self`, or as part of the current call if
+    /// `self` is empty.
+    pub(crate) async fn scope<F: Future>(self, fut: F) -> F::Output {
+        if self.0.is_some() {
+            CALL.scope(self, fut).await
+        } else {
+            fut.await
+        }
+    }
+}
+
+/// Fails if `key` is one of the keys the client or gRPC rely on.
+fn check_reserved(key: &str) -> Result<()> {
+    if RESERVED_KEYS.contains(&key) || key.starts_with(RESERVED_PREFIX) {
+        return Err(Error::ReservedMetadata {
+            key: key.to_owned(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,420 @@
+//! Metrics of RPCs, the backend of `crate::observe` enabled by the `metrics` feature.
+//!
+//! The metrics are reported to the recorder installed for the `metrics` crate, and named
+//! with the prefix set by
+//! [`ConnectOptions::with_metrics_prefix`](crate::ConnectOptions::with_metrics_prefix),
+//! [`DEFAULT_METRICS_PREFIX`] by default:
+//!
+//! - `<prefix>_requests_total`: counter of the attempts of RPCs, labeled by `rpc` and by
+//!   `code`, the gRPC code the attempt completed with, e.g. `Ok` or `Unavailable`,
+//! - `<prefix>_request_duration_seconds`: histogram of the latency of the attempts of
+//!   RPCs, labeled alike,
+//! - `<prefix>_requests_in_flight`: gauge of the attempts of RPCs in flight, labeled by
+//!   `rpc`,
+//! - `<prefix>_watch_events_total`: counter of the events received on watch streams,
+//! - `<prefix>_lease_keep_alives_total`: counter of the keep-alives of leases, labeled by
+//!   `result`: `success` if the lease was renewed, `failure` if it expired or the stream
+//!   failed,
+//! - `<prefix>_lease_keep_alive_wakeups_total`: counter of the wakeups of the timer shared by
+//!   the keep-alives of the leases of sessions, whose rate is the wakeups per second,
+//! - `<prefix>_lease_keep_alive_batch_size`: histogram of the number of leases kept alive
+//!   per wakeup of that timer,
+//! - `<prefix>_endpoint_changes_total`: counter of the endpoints inserted into and removed
+//!   from balanced channels, labeled by `change`: `insert` or `remove`,
+//! - `<prefix>_reconnects_total`: counter of the attempts of RPCs which failed because the
+//!   connection to the endpoint was lost or could not be established, after which the
+//!   channel reconnects to the endpoint, labeled by `rpc`,
+//! - `<prefix>_db_quota_usage`: gauge of the fraction of the quota used by the database,
+//!   checked by the size accounting of
+//!   [`SizeAccountingOptions::with_quota`](crate::SizeAccountingOptions::with_quota),
+//! - `<prefix>_rate_limit_utilization`: gauge of the fraction of the burst of
+//!   [`ConnectOptions::with_rate_limit`](crate::ConnectOptions::with_rate_limit) in use,
+//!   above 1 while RPCs are delayed, labeled by `class`: `read` or `write`.
+//!
+//! The attempt of a streaming RPC completes when the stream is opened.
+
+use crate::observe::ObservedError;
+use metrics::{Gauge, SharedString};
+use std::sync::Arc;
+use tokio::time::Instant;
+use tonic::Code;
+
+/// The default prefix of the names of metrics.
+pub const DEFAULT_METRICS_PREFIX: &str = "etcd_client";
+
+/// The names of the metrics.
+#[derive(Debug)]
+struct Names {
+    requests: SharedString,
+    request_duration: SharedString,
+    requests_in_flight: SharedString,
+    watch_events: SharedString,
+    lease_keep_alives: SharedString,
+    lease_keep_alive_wakeups: SharedString,
+    lease_keep_alive_batch: SharedString,
+    endpoint_changes: SharedString,
+    reconnects: SharedString,
+    db_quota_usage: SharedString,
+    rate_limit_utilization: SharedString,
+}
+
+/// Reports metrics named with a prefix.
+#[derive(Debug, Clone)]
+pub(crate) struct Metrics(Arc<Names>);
+
+impl Metrics {
+    /// Creates a `Metrics` whose names start with `prefix`.
+    pub(crate) fn new(prefix: &str) -> Self {
+        let name = |name: &str| SharedString::from(Arc::<str>::from(format!("{prefix}_{name}")));
+        Self(Arc::new(Names {
+            requests: name("requests_total"),
+            request_duration: name("request_duration_seconds"),
+            requests_in_flight: name("requests_in_flight"),
+            watch_events: name("watch_events_total"),
+            lease_keep_alives: name("lease_keep_alives_total"),
+            lease_keep_alive_wakeups: name("lease_keep_alive_wakeups_total"),
+            lease_keep_alive_batch: name("lease_keep_alive_batch_size"),
+            endpoint_changes: name("endpoint_changes_total"),
+            reconnects: name("reconnects_total"),
+            db_quota_usage: name("db_quota_usage"),
+            rate_limit_utilization: name("rate_limit_utilization"),
+        }))
+    }
+
+    /// Reports the start of an attempt of the RPC `rpc`.
+    pub(crate) fn start(&self, rpc: &'static str) -> InFlight {
+        let gauge = metrics::gauge!(self.0.requests_in_flight.clone(), "rpc" => rpc);
+        gauge.increment(1.0);
+        InFlight {
+            metrics: self.clone(),
+            rpc,
+            gauge,
+            start: Instant::now(),
+        }
+    }
+
+    /// Reports `events` events received on a watch stream.
+    #[inline]
+    pub(crate) fn watch_events(&self, events: usize) {
+        metrics::counter!(self.0.watch_events.clone()).increment(events as u64);
+    }
+
+    /// Reports a keep-alive of a lease, which renewed the lease if `renewed`.
+    #[inline]
+    pub(crate) fn keep_alive(&self, renewed: bool) {
+        let result = if renewed { "success" } else { "failure" };
+        metrics::counter!(self.0.lease_keep_alives.clone(), "result" => result).increment(1);
+    }
+
+    /// Reports a wakeup of the keep-alive timer of sessions, keeping `leases` leases alive.
+    #[inline]
+    pub(crate) fn keep_alive_batch(&self, leases: usize) {
+        metrics::counter!(self.0.lease_keep_alive_wakeups.clone()).increment(1);
+        metrics::histogram!(self.0.lease_keep_alive_batch.clone()).record(leases as f64);
+    }
+
+    /// Reports an endpoint inserted into a balanced channel if `inserted`, or removed from it.
+    #[inline]
+    pub(crate) fn endpoint_changed(&self, inserted: bool) {
+        let change = if inserted { "insert" } else { "remove" };
+        metrics::counter!(self.0.endpoint_changes.clone(), "change" => change).increment(1);
+    }
+
+    /// Reports the fraction `usage` of the quota used by the database.
+    #[inline]
+    pub(crate) fn quota_usage(&self, usage: f64) {
+        metrics::gauge!(self.0.db_quota_usage.clone()).set(usage);
+    }
+
+    /// Reports the fraction `utilization` of the burst of the rate limit of `class` in use.
+    #[inline]
+    pub(crate) fn rate_limit_utilization(&self, class: &'static str, utilization: f64) {
+        let gauge = self.0.rate_limit_utilization.clone();
+        metrics::gauge!(gauge, "class" => class).set(utilization);
+    }
+}
+
+impl Default for Metrics {
+    #[inline]
+    fn default() -> Self {
+        Self::new(DEFAULT_METRICS_PREFIX)
+    }
+}
+
+/// An attempt of a RPC in flight, no longer counted as such when dropped.
+pub(crate) struct InFlight {
+    metrics: Metrics,
+    rpc: &'static str,
+    gauge: Gauge,
+    start: Instant,
+}
+
+impl InFlight {
+    /// Reports the completion of the attempt, failed with `error` if any.
+    pub(crate) fn finish<E: ObservedError>(self, error: Option<&E>) {
+        let names = &self.metrics.0;
+        let code = code_label(error.map_or(Some(Code::Ok), E::code));
+        let labels = [("rpc", self.rpc), ("code", code)];
+        metrics::counter!(names.requests.clone(), &labels).increment(1);
+        metrics::histogram!(names.request_duration.clone(), &labels).record(self.start.elapsed());
+        if error.is_some_and(E::is_transport) {
+            metrics::counter!(names.reconnects.clone(), "rpc" => self.rpc).increment(1);
+        }
+    }
+}
+
+impl Drop for InFlight {
+    #[inline]
+    fn drop(&mut self) {
+        self.gauge.decrement(1.0);
+    }
+}
+
+/// The label of the gRPC code `code`, `Unknown` for errors without a code.
+fn code_label(code: Option<Code>) -> &'static str {
+    match code {
+        Some(Code::Ok) => "Ok",
+        Some(Code::Cancelled) => "Cancelled",
+        Some(Code::Unknown) | None => "Unknown",
+        Some(Code::InvalidArgument) => "InvalidArgument",
+        Some(Code::DeadlineExceeded) => "DeadlineExceeded",
+        Some(Code::NotFound) => "NotFound",
+        Some(Code::AlreadyExists) => "AlreadyExists",
+        Some(Code::PermissionDenied) => "PermissionDenied",
+        Some(Code::ResourceExhausted) => "ResourceExhausted",
+        Some(Code::FailedPrecondition) => "FailedPrecondition",
+        Some(Code::Aborted) => "Aborted",
+        Some(Code::OutOfRange) => "OutOfRange",
+        Some(Code::Unimplemented) => "Unimplemented",
+        Some(Code::Internal) => "Internal",
+        Some(Code::Unavailable) => "Unavailable",
+        Some(Code::DataLoss) => "DataLoss",
+        Some(Code::Unauthenticated) => "Unauthenticated",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::channel::{BalancedChannelBuilder, Change, Channel, Tonic};
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::lock::MutexExt;
+    use crate::observe::{observe_call, Observer};
+    use crate::rpc::kv::KvClient;
+    use crate::rpc::pb::etcdserverpb::{PutResponse as PbPutResponse, ResponseHeader};
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+    use metrics::{Counter, CounterFn, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata};
+    use metrics::{Recorder, Unit};
+    use prost::Message;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tonic::codegen::Bytes;
+    use tonic::transport::Endpoint;
+    use tower::util::BoxCloneService;
+    use tower::ServiceExt;
+
+    type Values = Arc<Mutex<HashMap<String, f64>>>;
+
+    /// A recorder keeping the value of every counter and gauge, and the number of samples of
+    /// every histogram, by name and labels, e.g. `test_requests_total{rpc=Put,code=Ok}`.
+    #[derive(Default)]
+    struct TestRecorder {
+        values: Values,
+    }
+
+    struct Handle {
+        key: String,
+        values: Values,
+    }
+
+    impl Handle {
+        fn update(&self, f: impl FnOnce(f64) -> f64) {
+            let mut values = self.values.lock_unpoisoned();
+            let value = values.entry(self.key.clone()).or_default();
+            *value = f(*value);
+        }
+    }
+
+    impl CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            self.update(|v| v + value as f64);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.update(|_| value as f64);
+        }
+    }
+
+    impl GaugeFn for Handle {
+        fn increment(&self, value: f64) {
+            self.update(|v| v + value);
+        }
+
+        fn decrement(&self, value: f64) {
+            self.update(|v| v - value);
+        }
+
+        fn set(&self, value: f64) {
+            self.update(|_| value);
+        }
+    }
+
+    impl HistogramFn for Handle {
+        fn record(&self, _value: f64) {
+            self.update(|v| v + 1.0);
+        }
+    }
+
+    impl TestRecorder {
+        fn handle(&self, key: &Key) -> Arc<Handle> {
+            let labels: Vec<_> = key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect();
+            let key = match labels.is_empty() {
+                true => key.name().to_string(),
+                false => format!("{}{{{}}}", key.name(), labels.join(",")),
+            };
+            Arc::new(Handle {
+                key,
+                values: self.values.clone(),
+            })
+        }
+
+        fn value(&self, key: &str) -> f64 {
+            self.values
+                .lock_unpoisoned()
+                .get(key)
+                .copied()
+                .unwrap_or_default()
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.handle(key))
+        }
+
+        fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.handle(key))
+        }
+
+        fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.handle(key))
+        }
+    }
+
+    /// A client whose puts succeed, whose ranges fail and whose compactions lose the
+    /// connection.
+    fn kv_client(observer: Observer) -> KvClient {
+        let service = tower::service_fn(|req: http::Request<tonic::body::Body>| async move {
+            if req.uri().path().ends_with("/Range") {
+                let status = tonic::Status::unavailable("etcdserver: no leader");
+                return Ok::<_, tower::BoxError>(status.into_http());
+            }
+            if req.uri().path().ends_with("/Compact") {
+                return Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset).into());
+            }
+            let msg = PbPutResponse {
+                header: Some(ResponseHeader::default()),
+                prev_kv: None,
+            };
+            let mut buf = vec![0];
+            buf.extend_from_slice(&(msg.encoded_len() as u32).to_be_bytes());
+            msg.encode(&mut buf).unwrap();
+            let mut trailers = http::HeaderMap::new();
+            tonic::Status::ok("").add_header(&mut trailers).unwrap();
+            let frames = [
+                Ok::<_, tower::BoxError>(Frame::data(Bytes::from(buf))),
+                Ok(Frame::trailers(trailers)),
+            ];
+            let body = tonic::body::Body::new(StreamBody::new(tokio_stream::iter(frames)));
+            Ok(http::Response::builder()
+                .header("content-type", "application/grpc")
+                .body(body)
+                .unwrap())
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor::default(),
+        );
+        KvClient::new(channel, AuthToken::default())
+            .with_read_retries(0)
+            .with_observer(observer)
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let recorder = TestRecorder::default();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+        let observer = Observer::default().with_metrics_prefix("test");
+        let mut client = kv_client(observer.clone());
+
+        client.put("foo", "bar", None).await.unwrap();
+        client.put("foo", "baz", None).await.unwrap();
+        assert!(client.get("foo", None).await.is_err());
+        assert!(client.compact(1, None).await.is_err());
+        assert_eq!(recorder.value("test_requests_total{rpc=Put,code=Ok}"), 2.0);
+        assert_eq!(
+            recorder.value("test_request_duration_seconds{rpc=Put,code=Ok}"),
+            2.0
+        );
+        assert_eq!(
+            recorder.value("test_requests_total{rpc=Range,code=Unavailable}"),
+            1.0
+        );
+        assert_eq!(recorder.value("test_requests_in_flight{rpc=Put}"), 0.0);
+        assert_eq!(recorder.value("test_reconnects_total{rpc=Compact}"), 1.0);
+        assert_eq!(recorder.value("test_reconnects_total{rpc=Range}"), 0.0);
+
+        let call = observe_call!(observer, "Watch");
+        call.watch_events(3);
+        call.keep_alive(true);
+        call.keep_alive(false);
+        assert_eq!(recorder.value("test_watch_events_total"), 3.0);
+        assert_eq!(
+            recorder.value("test_lease_keep_alives_total{result=success}"),
+            1.0
+        );
+        assert_eq!(
+            recorder.value("test_lease_keep_alives_total{result=failure}"),
+            1.0
+        );
+        observer.keep_alive_batch(3);
+        observer.keep_alive_batch(5);
+        assert_eq!(recorder.value("test_lease_keep_alive_wakeups_total"), 2.0);
+        assert_eq!(recorder.value("test_lease_keep_alive_batch_size"), 2.0);
+
+        let (_channel, tx) = observer.scope(|| Tonic.balanced_channel(8)).unwrap();
+        let uri = http::Uri::from_static("http://127.0.0.1:2379");
+        let endpoint = Endpoint::from(uri.clone());
+        tx.send(Change::Insert(uri.clone(), endpoint))
+            .await
+            .unwrap();
+        tx.send(Change::Remove(uri)).await.unwrap();
+        while recorder.value("test_endpoint_changes_total{change=remove}") == 0.0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            recorder.value("test_endpoint_changes_total{change=insert}"),
+            1.0
+        );
+
+        // The metrics of another client do not collide.
+        let mut other = kv_client(Observer::default());
+        other.put("foo", "bar", None).await.unwrap();
+        assert_eq!(recorder.value("test_requests_total{rpc=Put,code=Ok}"), 2.0);
+        assert_eq!(
+            recorder.value("etcd_client_requests_total{rpc=Put,code=Ok}"),
+            1.0
+        );
+    }
+}
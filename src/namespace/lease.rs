@@ -1,5 +1,5 @@
 use crate::error::Result;
-use crate::{LeaseClient, LeaseTimeToLiveOptions, LeaseTimeToLiveResponse};
+use crate::{LeaseClient, LeaseId, LeaseTimeToLiveOptions, LeaseTimeToLiveResponse};
 
 pub struct LeaseClientPrefix {
     pfx: Vec<u8>,
@@ -15,7 +15,7 @@ impl LeaseClientPrefix {
 
     pub async fn time_to_live(
         &mut self,
-        id: i64,
+        id: impl Into<LeaseId>,
         options: Option<LeaseTimeToLiveOptions>,
     ) -> Result<LeaseTimeToLiveResponse> {
         let mut resp = self.lease.time_to_live(id, options).await?;
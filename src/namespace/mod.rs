@@ -1,5 +1,9 @@
+#[cfg(feature = "kv")]
 mod kv;
+#[cfg(feature = "lease")]
 mod lease;
 
+#[cfg(feature = "kv")]
 pub use kv::KvClientPrefix;
+#[cfg(feature = "lease")]
 pub use lease::LeaseClientPrefix;
@@ -0,0 +1,418 @@
+//! Observation of RPCs, streams and endpoints.
+//!
+//! The instrumentation points of the crate report to the [`Observer`] of the client, which
+//! forwards them to the backends enabled by features:
+//!
+//! - `tracing`: spans and trace events, see `crate::trace`,
+//! - `metrics`: counters, gauges and histograms, see `crate::metric`.
+//!
+//! The features `gzip` and `zstd` observe the errors of RPCs too, to detect servers rejecting
+//! compressed requests, see `crate::compression`. Without any of these features, an observed
+//! RPC compiles down to the RPC itself.
+
+use crate::channel::Change;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::compression::Rejected;
+use crate::logging::log_event;
+#[cfg(feature = "metrics")]
+use crate::metric::Metrics;
+use crate::trace::RpcSpan;
+#[cfg(feature = "tracing")]
+use crate::trace::TraceKeys;
+use std::fmt::Display;
+
+/// Runs the RPC future `$fut` of the method `$rpc` observed by `$observer`, recording the
+/// key `$key` if given, or as the call `$call` made by [`observe_call`].
+macro_rules! observed {
+    (call = $call:expr, $fut:expr) => {
+        $call.run($fut)
+    };
+    ($observer:expr, $rpc:literal, $fut:expr) => {
+        $crate::observe::observe_call!($observer, $rpc).run($fut)
+    };
+    ($observer:expr, $rpc:literal, key = $key:expr, $fut:expr) => {
+        $crate::observe::observe_call!($observer, $rpc, key = $key).run($fut)
+    };
+}
+
+/// A call of the method `$rpc` observed by `$observer`, recording the key `$key` if given,
+/// kept by the stream the RPC opens.
+macro_rules! observe_call {
+    ($observer:expr, $rpc:literal) => {
+        $observer.call($rpc, $crate::trace::rpc_span!($rpc))
+    };
+    ($observer:expr, $rpc:literal, key = $key:expr) => {
+        $observer.call_with_key($rpc, $crate::trace::rpc_span!($rpc), $key)
+    };
+}
+
+pub(crate) use {observe_call, observed};
+
+tokio::task_local! {
+    static CURRENT: Observer;
+}
+
+/// Observes the RPCs, streams and endpoints of a client.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Observer {
+    #[cfg(feature = "tracing")]
+    trace_keys: TraceKeys,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    rejected: Rejected,
+}
+
+impl Observer {
+    /// Records the keys of requests in their spans according to `keys`.
+    #[cfg(feature = "tracing")]
+    #[inline]
+    pub(crate) fn with_trace_keys(mut self, keys: TraceKeys) -> Self {
+        self.trace_keys = keys;
+        self
+    }
+
+    /// Prefixes the names of the metrics with `prefix`.
+    #[cfg(feature = "metrics")]
+    #[inline]
+    pub(crate) fn with_metrics_prefix(mut self, prefix: &str) -> Self {
+        self.metrics = Metrics::new(prefix);
+        self
+    }
+
+    /// Records the rejections of compressed requests in `rejected`.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    #[inline]
+    pub(crate) fn with_compression(mut self, rejected: Rejected) -> Self {
+        self.rejected = rejected;
+        self
+    }
+
+    /// The observer of the client being connected, see [`Observer::scope`], or a default
+    /// one.
+    #[inline]
+    pub(crate) fn current() -> Self {
+        CURRENT.try_with(Clone::clone).unwrap_or_default()
+    }
+
+    /// Runs `f`, observing the endpoints of the balanced channels it builds with `self`.
+    ///
+    /// Used for the bridges of balanced channels, which are spawned by builders knowing
+    /// nothing of the client.
+    #[inline]
+    pub(crate) fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        CURRENT.sync_scope(self.clone(), f)
+    }
+
+    /// A call of the method `rpc` whose attempt runs in `span`.
+    #[cfg_attr(
+        not(all(feature = "tracing", feature = "metrics")),
+        allow(unused_variables)
+    )]
+    #[inline]
+    pub(crate) fn call(&self, rpc: &'static str, span: RpcSpan) -> Call {
+        Call {
+            #[cfg(feature = "tracing")]
+            span,
+            #[cfg(feature = "metrics")]
+            rpc,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.clone(),
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            rejected: self.rejected.clone(),
+        }
+    }
+
+    /// A call of the method `rpc` requesting `key`, whose attempt runs in `span`.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    #[inline]
+    pub(crate) fn call_with_key(&self, rpc: &'static str, span: RpcSpan, key: &[u8]) -> Call {
+        #[cfg(feature = "tracing")]
+        crate::trace::record_key(&span, self.trace_keys, key);
+        self.call(rpc, span)
+    }
+
+    /// Observes a change of the endpoints of a balanced channel.
+    #[inline]
+    pub(crate) fn endpoint_changed<K: Display, V>(&self, change: &Change<K, V>) {
+        match change {
+            Change::Insert(endpoint, _) => {
+                log_event!(Info, "etcd endpoint added", endpoint = endpoint)
+            }
+            Change::Remove(endpoint) => {
+                log_event!(Info, "etcd endpoint removed", endpoint = endpoint)
+            }
+        }
+        #[cfg(feature = "metrics")]
+        self.metrics
+            .endpoint_changed(matches!(change, Change::Insert(..)));
+    }
+
+    /// Observes a wakeup of the keep-alive timer of sessions, keeping `leases` leases alive.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    #[inline]
+    pub(crate) fn keep_alive_batch(&self, leases: usize) {
+        #[cfg(feature = "metrics")]
+        self.metrics.keep_alive_batch(leases);
+    }
+
+    /// Observes the fraction `usage` of the quota used by the database.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    #[inline]
+    pub(crate) fn quota_usage(&self, usage: f64) {
+        #[cfg(feature = "metrics")]
+        self.metrics.quota_usage(usage);
+    }
+
+    /// Observes the fraction `utilization` of the burst of the rate limit of `class` in use.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    #[inline]
+    pub(crate) fn rate_limit_utilization(&self, class: &'static str, utilization: f64) {
+        #[cfg(feature = "metrics")]
+        self.metrics.rate_limit_utilization(class, utilization);
+    }
+}
+
+/// An observed attempt of a RPC, kept by the stream the RPC opens.
+#[derive(Debug, Clone)]
+pub struct Call {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "metrics")]
+    rpc: &'static str,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    rejected: Rejected,
+}
+
+impl Call {
+    /// Runs the RPC future `fut`.
+    #[cfg(not(any(
+        feature = "tracing",
+        feature = "metrics",
+        feature = "gzip",
+        feature = "zstd"
+    )))]
+    #[inline(always)]
+    pub(crate) fn run<F>(self, fut: F) -> F {
+        fut
+    }
+
+    /// Runs the RPC future `fut`, reporting the attempt and its outcome.
+    #[cfg(any(
+        feature = "tracing",
+        feature = "metrics",
+        feature = "gzip",
+        feature = "zstd"
+    ))]
+    pub(crate) async fn run<F, T, E>(self, fut: F) -> Result<T, E>
+    where
+        F: std::future::Future<Output = Result<T, E>>,
+        T: ObservedResponse,
+        E: ObservedError,
+    {
+        #[cfg(feature = "tracing")]
+        crate::trace::start(&self.span);
+        #[cfg(feature = "tracing")]
+        let fut = tracing::Instrument::instrument(fut, self.span.clone());
+        #[cfg(feature = "metrics")]
+        let in_flight = self.metrics.start(self.rpc);
+        let result = fut.await;
+        #[cfg(feature = "tracing")]
+        crate::trace::finish(&self.span, &result);
+        #[cfg(feature = "metrics")]
+        in_flight.finish(result.as_ref().err());
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Err(e) = &result {
+            self.rejected.check(e.code(), e.message());
+        }
+        result
+    }
+
+    /// The span of the attempt.
+    #[cfg(feature = "tracing")]
+    #[inline]
+    pub(crate) fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    /// Observes `events` events received on the watch stream opened by the call.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    #[inline]
+    pub(crate) fn watch_events(&self, events: usize) {
+        #[cfg(feature = "metrics")]
+        self.metrics.watch_events(events);
+    }
+
+    /// Observes a keep-alive on the stream opened by the call, which renewed the lease if
+    /// `renewed`.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    #[inline]
+    pub(crate) fn keep_alive(&self, renewed: bool) {
+        #[cfg(feature = "metrics")]
+        self.metrics.keep_alive(renewed);
+    }
+}
+
+#[cfg(any(
+    feature = "tracing",
+    feature = "metrics",
+    feature = "gzip",
+    feature = "zstd"
+))]
+pub(crate) use backend::*;
+
+#[cfg(any(
+    feature = "tracing",
+    feature = "metrics",
+    feature = "gzip",
+    feature = "zstd"
+))]
+mod backend {
+    use crate::rpc::pb::etcdserverpb::{
+        AlarmResponse, AuthDisableResponse, AuthEnableResponse, AuthRoleAddResponse,
+        AuthRoleDeleteResponse, AuthRoleGetResponse, AuthRoleGrantPermissionResponse,
+        AuthRoleListResponse, AuthRoleRevokePermissionResponse, AuthUserAddResponse,
+        AuthUserChangePasswordResponse, AuthUserDeleteResponse, AuthUserGetResponse,
+        AuthUserGrantRoleResponse, AuthUserListResponse, AuthUserRevokeRoleResponse,
+        AuthenticateResponse, CompactionResponse, DefragmentResponse, DeleteRangeResponse,
+        DowngradeResponse, HashKvResponse, HashResponse, LeaseGrantResponse, LeaseLeasesResponse,
+        LeaseRevokeResponse, LeaseTimeToLiveResponse, MemberAddResponse, MemberListResponse,
+        MemberPromoteResponse, MemberRemoveResponse, MemberUpdateResponse, MoveLeaderResponse,
+        PutResponse, RangeResponse, ResponseHeader, StatusResponse, TxnResponse,
+    };
+    use crate::rpc::pb::v3electionpb::{
+        CampaignResponse, LeaderResponse, ProclaimResponse, ResignResponse,
+    };
+    use crate::rpc::pb::v3lockpb::{LockResponse, UnlockResponse};
+
+    /// Responses of RPCs, whose revision is recorded.
+    pub(crate) trait ObservedResponse {
+        /// The revision of the response header.
+        #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+        fn revision(&self) -> Option<i64>;
+    }
+
+    impl<T: ObservedResponse> ObservedResponse for tonic::Response<T> {
+        #[inline]
+        fn revision(&self) -> Option<i64> {
+            self.get_ref().revision()
+        }
+    }
+
+    impl<T> ObservedResponse for tonic::Streaming<T> {
+        #[inline]
+        fn revision(&self) -> Option<i64> {
+            None
+        }
+    }
+
+    macro_rules! impl_observed_response {
+        ($($response:ty,)*) => {
+            $(
+                impl ObservedResponse for $response {
+                    #[inline]
+                    fn revision(&self) -> Option<i64> {
+                        self.header.as_ref().map(|header: &ResponseHeader| header.revision)
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_observed_response!(
+        AlarmResponse,
+        AuthDisableResponse,
+        AuthEnableResponse,
+        AuthRoleAddResponse,
+        AuthRoleDeleteResponse,
+        AuthRoleGetResponse,
+        AuthRoleGrantPermissionResponse,
+        AuthRoleListResponse,
+        AuthRoleRevokePermissionResponse,
+        AuthUserAddResponse,
+        AuthUserChangePasswordResponse,
+        AuthUserDeleteResponse,
+        AuthUserGetResponse,
+        AuthUserGrantRoleResponse,
+        AuthUserListResponse,
+        AuthUserRevokeRoleResponse,
+        AuthenticateResponse,
+        CampaignResponse,
+        CompactionResponse,
+        DefragmentResponse,
+        DeleteRangeResponse,
+        DowngradeResponse,
+        HashKvResponse,
+        HashResponse,
+        LeaderResponse,
+        LeaseGrantResponse,
+        LeaseLeasesResponse,
+        LeaseRevokeResponse,
+        LeaseTimeToLiveResponse,
+        LockResponse,
+        MemberAddResponse,
+        MemberListResponse,
+        MemberPromoteResponse,
+        MemberRemoveResponse,
+        MemberUpdateResponse,
+        MoveLeaderResponse,
+        ProclaimResponse,
+        PutResponse,
+        RangeResponse,
+        ResignResponse,
+        StatusResponse,
+        TxnResponse,
+        UnlockResponse,
+    );
+
+    /// Errors of RPCs, whose code is recorded.
+    pub(crate) trait ObservedError {
+        /// The gRPC code of the error.
+        fn code(&self) -> Option<tonic::Code>;
+
+        /// Returns `true` if the error is caused by the transport.
+        #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+        fn is_transport(&self) -> bool;
+
+        /// The gRPC message of the error.
+        #[cfg_attr(not(any(feature = "gzip", feature = "zstd")), allow(dead_code))]
+        fn message(&self) -> &str;
+    }
+
+    impl ObservedError for tonic::Status {
+        #[inline]
+        fn code(&self) -> Option<tonic::Code> {
+            Some(tonic::Status::code(self))
+        }
+
+        #[inline]
+        fn is_transport(&self) -> bool {
+            // Tonic keeps the transport error as the source of the status.
+            std::error::Error::source(self).is_some()
+        }
+
+        #[inline]
+        fn message(&self) -> &str {
+            tonic::Status::message(self)
+        }
+    }
+
+    impl ObservedError for crate::Error {
+        #[inline]
+        fn code(&self) -> Option<tonic::Code> {
+            crate::Error::code(self)
+        }
+
+        #[inline]
+        fn is_transport(&self) -> bool {
+            crate::Error::is_transport(self)
+        }
+
+        #[inline]
+        fn message(&self) -> &str {
+            crate::Error::message(self)
+        }
+    }
+}
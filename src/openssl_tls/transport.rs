@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use super::backoff::{BackOffStatus, BackOffWhenFail};
+use crate::circuit_breaker::{CircuitBreakerLayer, CircuitBreakerOptions};
 use crate::error::Result;
 use http::{Request, Uri};
 use hyper_openssl::client::legacy::HttpsConnector;
@@ -13,22 +14,19 @@ use openssl::{
 };
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
 use tonic::codegen::BoxFuture;
 use tonic::{
     body::Body,
     transport::{Channel, Endpoint},
 };
-use tower::{balance::p2c::Balance, buffer::Buffer, discover::Change, ServiceExt};
+use tower::{balance::p2c::Balance, buffer::Buffer, discover::Change, Layer, ServiceExt};
 
 pub type SslConnectorBuilder = openssl::ssl::SslConnectorBuilder;
 pub type OpenSslResult<T> = std::result::Result<T, ErrorStack>;
 // Below are some type alias for make clearer types.
 pub type TonicRequest = Request<Body>;
 pub type OpenSslChannel = Buffer<TonicRequest, BoxFuture<http::Response<Body>, tower::BoxError>>;
-/// OpenSslDiscover is the backend for balanced channel based on OpenSSL transports.
-/// Because `Channel::balance` doesn't allow us to provide custom connector, we must implement ourselves' balancer...
-pub type OpenSslDiscover<K> = ReceiverStream<Result<Change<K, BackOffWhenFail<Channel>>>>;
-
 #[derive(Clone)]
 pub struct OpenSslConnector(HttpsConnector<HttpConnector>);
 
@@ -53,13 +51,23 @@ compile_error!(concat!(
     "we may create TLS tunnels over TLS tunnels or directly fail because of some sorts of misconfiguration.")
 );
 
-/// Create a balanced channel using the OpenSSL config.
+/// Create a balanced channel using the OpenSSL config, wrapping every endpoint in a circuit
+/// breaker if given, updated through a channel of `buffer_size` changes.
 pub fn balanced_channel(
     connector: OpenSslConnector,
+    circuit_breaker: Option<CircuitBreakerOptions>,
+    buffer_size: usize,
 ) -> Result<(OpenSslChannel, Sender<Change<Uri, Endpoint>>)> {
-    let (tx, rx) = tokio::sync::mpsc::channel(16);
-    let tls_conn = create_openssl_discover(connector, rx);
-    let balance = Balance::new(tls_conn).boxed();
+    let (tx, rx) = tokio::sync::mpsc::channel(buffer_size);
+    let balance = match circuit_breaker {
+        Some(options) => {
+            let tls_conn = create_openssl_discover(connector, rx, move |uri: &Uri, chan| {
+                CircuitBreakerLayer::new(options.clone(), uri.clone()).layer(chan)
+            });
+            Balance::new(tls_conn).boxed()
+        }
+        None => Balance::new(create_openssl_discover(connector, rx, |_: &Uri, chan| chan)).boxed(),
+    };
     // Note: the buffer should already be configured when creating the internal channels,
     // we wrap this in the buffer is just for making them `Clone`.
     let buffered = Buffer::new(balance, 1024);
@@ -67,6 +75,15 @@ pub fn balanced_channel(
     Ok((buffered, tx))
 }
 
+/// Create a channel to a single endpoint using the OpenSSL config, connecting lazily.
+pub fn channel(connector: OpenSslConnector, endpoint: Endpoint) -> OpenSslChannel {
+    let chan = endpoint
+        .connect_with_connector_lazy(connector.0)
+        .map_err(tower::BoxError::from)
+        .boxed();
+    Buffer::new(chan, 1024)
+}
+
 /// Create a connector which dials TLS connections by openssl.
 fn create_openssl_connector(builder: SslConnectorBuilder) -> OpenSslResult<OpenSslConnector> {
     let mut http = HttpConnector::new();
@@ -75,42 +92,34 @@ fn create_openssl_connector(builder: SslConnectorBuilder) -> OpenSslResult<OpenS
     Ok(OpenSslConnector(https))
 }
 
-/// Create a discover which mapping Endpoints into SSL connections.
+/// Create a discover which mapping Endpoints into SSL connections, the backend of the
+/// balanced channel based on OpenSSL transports: `Channel::balance` doesn't allow us to
+/// provide custom connector, so we must implement ourselves' balancer.
 /// Because this would fully take over the transport layer by a security channel,
 /// you should NOT enable `tonic/ssl` feature (or tonic may try to create SSL session over the security transport...).
-fn create_openssl_discover<K: Send + 'static>(
+fn create_openssl_discover<K: Send + 'static, S: Send + 'static>(
     connector: OpenSslConnector,
-    mut incoming: Receiver<Change<K, Endpoint>>,
-) -> OpenSslDiscover<K> {
-    let (tx, rx) = tokio::sync::mpsc::channel(16);
-    let fut = async move {
-        while let Some(x) = incoming.recv().await {
-            let r = async {
-                match x {
-                    Change::Insert(name, e) => {
-                        let chan = e.connect_with_connector_lazy(connector.clone().0);
-                        Ok(Change::Insert(
-                            name,
-                            BackOffWhenFail::new(
-                                chan,
-                                BackOffStatus::new(
-                                    /*initial*/ Duration::from_secs(1),
-                                    /*max*/ Duration::from_secs(256),
-                                ),
-                            ),
-                        ))
-                    }
-                    Change::Remove(name) => Ok(Change::Remove(name)),
-                }
-            }
-            .await;
-            if tx.send(r).await.is_err() {
-                return;
-            }
+    incoming: Receiver<Change<K, Endpoint>>,
+    wrap: impl Fn(&K, Channel) -> S + Send + Sync + 'static,
+) -> impl Stream<Item = Result<Change<K, BackOffWhenFail<S>>>> + Send + Unpin + 'static {
+    // The endpoints are connected as the balancer discovers them.
+    ReceiverStream::new(incoming).map(move |change| match change {
+        Change::Insert(name, e) => {
+            let chan = e.connect_with_connector_lazy(connector.clone().0);
+            let chan = wrap(&name, chan);
+            Ok(Change::Insert(
+                name,
+                BackOffWhenFail::new(
+                    chan,
+                    BackOffStatus::new(
+                        /*initial*/ Duration::from_secs(1),
+                        /*max*/ Duration::from_secs(256),
+                    ),
+                ),
+            ))
         }
-    };
-    tokio::task::spawn(fut);
-    ReceiverStream::new(rx)
+        Change::Remove(name) => Ok(Change::Remove(name)),
+    })
 }
 
 /// The configuration type for a openssl connection.
@@ -0,0 +1,288 @@
+//! Reads whose revisions never go back, across failovers to lagging members.
+
+use crate::error::{Error, Result};
+use crate::ids::Revision;
+use crate::rpc::kv::{
+    DeleteOptions, DeleteResponse, GetOptions, GetResponse, KvClient, PutOptions, PutResponse, Txn,
+    TxnResponse,
+};
+use crate::rpc::HasResponseHeader;
+use http::Uri;
+use std::future::Future;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+/// The number of endpoints a stale read is retried against at most, by default.
+pub const DEFAULT_ORDERING_RETRIES: u32 = 3;
+
+type ListEndpoints = Arc<dyn Fn() -> Vec<Uri> + Send + Sync>;
+type ConnectEndpoint = Arc<dyn Fn(&Uri) -> Result<KvClient> + Send + Sync>;
+
+/// A client of the key-value store whose reads never observe an older revision than it
+/// observed before, see [`Client::with_ordering_guard`](crate::Client::with_ordering_guard).
+///
+/// The clones of the client share the highest revision observed.
+#[derive(Clone)]
+pub struct OrderedKvClient {
+    kv: KvClient,
+    endpoints: ListEndpoints,
+    connect: ConnectEndpoint,
+    /// The highest revision observed, in the header of any response.
+    revision: Arc<AtomicI64>,
+    retries: u32,
+}
+
+impl OrderedKvClient {
+    /// Creates a client sending its requests with `kv`, and retrying the stale reads against
+    /// the `endpoints`, reached by `connect` on their own.
+    pub(crate) fn new(
+        kv: KvClient,
+        endpoints: impl Fn() -> Vec<Uri> + Send + Sync + 'static,
+        connect: impl Fn(&Uri) -> Result<KvClient> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            kv,
+            endpoints: Arc::new(endpoints),
+            connect: Arc::new(connect),
+            revision: Arc::default(),
+            retries: DEFAULT_ORDERING_RETRIES,
+        }
+    }
+
+    /// Sets the number of endpoints a stale read is retried against at most,
+    /// [`DEFAULT_ORDERING_RETRIES`] by default.
+    #[inline]
+    pub fn with_max_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// The highest revision observed so far, `0` until a request succeeded.
+    #[inline]
+    pub fn revision(&self) -> Revision {
+        Revision::new(self.revision.load(Ordering::Acquire))
+    }
+
+    /// Puts the given key into the key-value store, see [`KvClient::put`].
+    #[inline]
+    pub async fn put(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: impl Into<Vec<u8>>,
+        options: Option<PutOptions>,
+    ) -> Result<PutResponse> {
+        let resp = self.kv.put(key, value, options).await?;
+        self.observe(revision_of(&resp));
+        Ok(resp)
+    }
+
+    /// Gets the key from the key-value store, see [`KvClient::get`], retrying against
+    /// the other endpoints if the response is older than the revision observed before.
+    #[inline]
+    pub async fn get(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<GetOptions>,
+    ) -> Result<GetResponse> {
+        let key = key.into();
+        self.guarded(|mut kv| {
+            let key = key.clone();
+            let options = options.clone();
+            async move { kv.get(key, options).await }
+        })
+        .await
+    }
+
+    /// Deletes the given key from the key-value store, see [`KvClient::delete`].
+    #[inline]
+    pub async fn delete(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResponse> {
+        let resp = self.kv.delete(key, options).await?;
+        self.observe(revision_of(&resp));
+        Ok(resp)
+    }
+
+    /// Processes multiple operations in a single transaction, see [`KvClient::txn`],
+    /// retrying against the other endpoints if the response is older than the revision
+    /// observed before, which only a read-only txn served by a lagging member can be.
+    #[inline]
+    pub async fn txn(&mut self, txn: Txn) -> Result<TxnResponse> {
+        self.guarded(|mut kv| {
+            let txn = txn.clone();
+            async move { kv.txn(txn).await }
+        })
+        .await
+    }
+
+    /// Sends `request` with the client, then against the endpoints in turn while its
+    /// response is older than the revision observed before.
+    ///
+    /// etcd has no field requiring a minimum revision of the member serving a request at
+    /// the latest revision, so the header of the response is checked instead.
+    async fn guarded<R, F, Fut>(&self, mut request: F) -> Result<R>
+    where
+        R: HasResponseHeader,
+        F: FnMut(KvClient) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let required = self.revision();
+        let resp = request(self.kv.clone()).await?;
+        let mut seen = revision_of(&resp);
+        if seen >= required {
+            self.observe(seen);
+            return Ok(resp);
+        }
+
+        let endpoints = (self.endpoints)();
+        for uri in endpoints.iter().take(self.retries as usize) {
+            // An endpoint which can not be reached is as good as a stale one.
+            let Ok(kv) = (self.connect)(uri) else {
+                continue;
+            };
+            match request(kv).await {
+                Ok(resp) if revision_of(&resp) >= required => {
+                    self.observe(revision_of(&resp));
+                    return Ok(resp);
+                }
+                Ok(resp) => seen = seen.max(revision_of(&resp)),
+                Err(e) if e.is_retryable() => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Error::StaleRead {
+            seen: Revision::new(seen),
+            required,
+        })
+    }
+
+    /// Records the revision `revision` observed.
+    #[inline]
+    fn observe(&self, revision: i64) {
+        self.revision.fetch_max(revision, Ordering::AcqRel);
+    }
+}
+
+/// The revision of the store when `resp` was served, `0` without a header.
+#[inline]
+fn revision_of(resp: &impl HasResponseHeader) -> i64 {
+    resp.header().map_or(0, |header| header.revision().get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::channel::Channel;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::rpc::pb::etcdserverpb::{
+        RangeRequest as PbRangeRequest, RangeResponse as PbRangeResponse,
+        ResponseHeader as PbResponseHeader,
+    };
+    use std::sync::atomic::AtomicUsize;
+    use tonic::codec::ProstCodec;
+    use tonic::server::Grpc;
+    use tower::util::BoxCloneService;
+
+    /// A member serving range requests at its revision.
+    #[derive(Clone, Default)]
+    struct Member {
+        revision: Arc<AtomicI64>,
+        ranges: Arc<AtomicUsize>,
+    }
+
+    impl Member {
+        fn at(revision: i64) -> Self {
+            let member = Self::default();
+            member.set_revision(revision);
+            member
+        }
+
+        fn set_revision(&self, revision: i64) {
+            self.revision.store(revision, Ordering::SeqCst);
+        }
+
+        fn ranges(&self) -> usize {
+            self.ranges.load(Ordering::SeqCst)
+        }
+
+        fn client(&self) -> KvClient {
+            let member = self.clone();
+            let service = tower::service_fn(move |req: http::Request<tonic::body::Body>| {
+                let member = member.clone();
+                async move {
+                    let service = tower::service_fn(|_: tonic::Request<PbRangeRequest>| {
+                        member.ranges.fetch_add(1, Ordering::SeqCst);
+                        let header = PbResponseHeader {
+                            revision: member.revision.load(Ordering::SeqCst),
+                            ..Default::default()
+                        };
+                        let resp = PbRangeResponse {
+                            header: Some(header),
+                            ..Default::default()
+                        };
+                        async move { Ok(tonic::Response::new(resp)) }
+                    });
+                    let resp = Grpc::new(ProstCodec::default()).unary(service, req).await;
+                    Ok::<_, tower::BoxError>(resp)
+                }
+            });
+            let channel = InterceptedChannel::new(
+                Channel::Custom(BoxCloneService::new(service)),
+                Interceptor::default(),
+            );
+            KvClient::new(channel, AuthToken::default())
+        }
+    }
+
+    /// A client over the balanced `primary`, whose endpoints are `a` and `b`.
+    fn ordered_client(primary: &Member, a: &Member, b: &Member) -> OrderedKvClient {
+        let (a, b) = (a.clone(), b.clone());
+        OrderedKvClient::new(
+            primary.client(),
+            || vec![Uri::from_static("http://a"), Uri::from_static("http://b")],
+            move |uri| match uri.host() {
+                Some("a") => Ok(a.client()),
+                _ => Ok(b.client()),
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_stale_read_retried() {
+        let (primary, a, b) = (Member::at(10), Member::at(5), Member::at(12));
+        let mut client = ordered_client(&primary, &a, &b);
+        client.get("key", None).await.unwrap();
+        assert_eq!(client.revision(), 10);
+        assert_eq!(a.ranges() + b.ranges(), 0);
+
+        // The balanced channel failed over to a lagging member.
+        primary.set_revision(5);
+        let resp = client.get("key", None).await.unwrap();
+        assert_eq!(revision_of(&resp), 12);
+        assert_eq!(client.revision(), 12);
+        assert_eq!((a.ranges(), b.ranges()), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_stale_read_failed() {
+        let (primary, a, b) = (Member::at(10), Member::at(7), Member::at(8));
+        let mut client = ordered_client(&primary, &a, &b).with_max_retries(1);
+        client.get("key", None).await.unwrap();
+
+        primary.set_revision(6);
+        let err = client.get("key", None).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::StaleRead { seen, required } if seen == 7 && required == 10
+            ),
+            "{:?}",
+            err
+        );
+        assert_eq!((a.ranges(), b.ranges()), (1, 0));
+        assert_eq!(client.revision(), 10);
+    }
+}
@@ -0,0 +1,509 @@
+//! Client-side rate limiting of the reads and writes initiated by a client.
+//!
+//! With [`ConnectOptions::with_rate_limit`](crate::ConnectOptions::with_rate_limit) the
+//! channels of the client go through a [`RateLimitGate`], which classifies the RPCs by their
+//! path: ranges and the opening of watch streams are reads; puts, deletes, txns and lease
+//! grants are writes. The other RPCs, and the messages of open streams, are not limited.
+//!
+//! Each class has a budget of `burst` RPCs, refilled at its rate. It is kept as the
+//! theoretical arrival time of the next RPC of the class, which is pushed one interval
+//! further by every RPC: an RPC arriving while that time is more than `burst - 1` intervals
+//! ahead of now exceeds the budget, and waits until it is not, or fails at once with
+//! [`Error::RateLimited`](crate::Error::RateLimited).
+
+use crate::channel::Channel;
+use crate::lock::MutexExt;
+use crate::observe::Observer;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tonic::metadata::MetadataValue;
+use tonic::Status;
+use tower::util::BoxCloneService;
+use tower::Service;
+
+type Request = http::Request<tonic::body::Body>;
+type Response = http::Response<tonic::body::Body>;
+
+/// The gRPC message of the statuses of the requests rejected by the client.
+pub(crate) const RATE_LIMITED_MESSAGE: &str = "etcd-client: rate limited";
+
+/// The metadata key of the statuses of the requests rejected by the client, the
+/// milliseconds until the budget allows them.
+pub(crate) const RETRY_AFTER_KEY: &str = "etcd-client-retry-after-ms";
+
+/// The paths of the RPCs limited as reads.
+const READ_PATHS: &[&str] = &["/etcdserverpb.KV/Range", "/etcdserverpb.Watch/Watch"];
+
+/// The paths of the RPCs limited as writes.
+const WRITE_PATHS: &[&str] = &[
+    "/etcdserverpb.KV/Put",
+    "/etcdserverpb.KV/DeleteRange",
+    "/etcdserverpb.KV/Txn",
+    "/etcdserverpb.Lease/LeaseGrant",
+];
+
+/// What the client does with the RPCs exceeding the budget of their class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RateLimitMode {
+    /// The RPCs wait until the budget allows them, in the order they were initiated.
+    #[default]
+    Delay,
+    /// The RPCs fail at once with [`Error::RateLimited`](crate::Error::RateLimited), telling
+    /// how long until the budget allows them.
+    Reject,
+}
+
+/// The rate limit of the reads and writes of a client,
+/// see [`ConnectOptions::with_rate_limit`](crate::ConnectOptions::with_rate_limit).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RateLimit {
+    reads_per_sec: u32,
+    writes_per_sec: u32,
+    burst: u32,
+    mode: RateLimitMode,
+}
+
+impl RateLimit {
+    /// Creates a `RateLimit` of `reads_per_sec` reads and `writes_per_sec` writes per
+    /// second, without bursts, delaying the RPCs exceeding it.
+    #[inline]
+    pub const fn new(reads_per_sec: u32, writes_per_sec: u32) -> Self {
+        Self {
+            reads_per_sec,
+            writes_per_sec,
+            burst: 1,
+            mode: RateLimitMode::Delay,
+        }
+    }
+
+    /// Sets the number of reads, and of writes, which can be initiated at once after the
+    /// client was idle, 1 by default.
+    #[inline]
+    pub const fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    /// Sets what the client does with the RPCs exceeding the limit.
+    #[inline]
+    pub const fn with_mode(mut self, mode: RateLimitMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// The reads per second.
+    #[inline]
+    pub const fn reads_per_sec(&self) -> u32 {
+        self.reads_per_sec
+    }
+
+    /// The writes per second.
+    #[inline]
+    pub const fn writes_per_sec(&self) -> u32 {
+        self.writes_per_sec
+    }
+
+    /// The number of reads, and of writes, which can be initiated at once.
+    #[inline]
+    pub const fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// What the client does with the RPCs exceeding the limit.
+    #[inline]
+    pub const fn mode(&self) -> RateLimitMode {
+        self.mode
+    }
+
+    /// The settings of the rate limit which can not be honoured, e.g. a rate of zero.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.reads_per_sec == 0 {
+            problems.push(String::from("rate limit of reads is zero"));
+        }
+        if self.writes_per_sec == 0 {
+            problems.push(String::from("rate limit of writes is zero"));
+        }
+        if self.burst == 0 {
+            problems.push(String::from("rate limit burst is zero"));
+        }
+        problems
+    }
+}
+
+/// The classes of the limited RPCs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Read,
+    Write,
+}
+
+impl Class {
+    /// The class of the RPC of `path`, `None` if it is not limited.
+    fn of(path: &str) -> Option<Self> {
+        if READ_PATHS.contains(&path) {
+            Some(Class::Read)
+        } else if WRITE_PATHS.contains(&path) {
+            Some(Class::Write)
+        } else {
+            None
+        }
+    }
+
+    /// The label of the class in metrics.
+    #[inline]
+    const fn label(self) -> &'static str {
+        match self {
+            Class::Read => "read",
+            Class::Write => "write",
+        }
+    }
+}
+
+/// The budget of a class of RPCs.
+#[derive(Debug)]
+struct Budget {
+    /// The interval between two RPCs at the rate of the class.
+    interval: Duration,
+    /// How far ahead of now the next RPC may be, `burst - 1` intervals.
+    tolerance: Duration,
+    /// The theoretical arrival time of the next RPC.
+    next: Instant,
+}
+
+impl Budget {
+    fn new(per_sec: u32, burst: u32) -> Self {
+        let interval = Duration::from_secs(1) / per_sec.max(1);
+        Self {
+            interval,
+            tolerance: interval * (burst.max(1) - 1),
+            next: Instant::now(),
+        }
+    }
+
+    /// Takes the budget of an RPC initiated `now`, returning how long it has to wait. In
+    /// [`RateLimitMode::Reject`], an RPC which has to wait takes nothing.
+    fn take(&mut self, now: Instant, mode: RateLimitMode) -> Duration {
+        let next = self.next.max(now);
+        let wait = (next - now).saturating_sub(self.tolerance);
+        if wait.is_zero() || mode == RateLimitMode::Delay {
+            self.next = next + self.interval;
+        }
+        wait
+    }
+
+    /// The fraction of the burst in use at `now`, above 1 while RPCs are delayed.
+    fn utilization(&self, now: Instant) -> f64 {
+        let used = self.next.saturating_duration_since(now);
+        used.as_secs_f64() / (self.tolerance + self.interval).as_secs_f64()
+    }
+}
+
+/// The budgets of the reads and writes of a client, shared by its channels.
+#[derive(Clone)]
+pub(crate) struct RateLimiter(Arc<Limiter>);
+
+struct Limiter {
+    mode: RateLimitMode,
+    reads: Mutex<Budget>,
+    writes: Mutex<Budget>,
+    observer: Observer,
+}
+
+impl RateLimiter {
+    /// Creates the budgets of `limit`, reporting their utilization to `observer`.
+    #[inline]
+    pub(crate) fn new(limit: &RateLimit, observer: Observer) -> Self {
+        Self(Arc::new(Limiter {
+            mode: limit.mode,
+            reads: Mutex::new(Budget::new(limit.reads_per_sec, limit.burst)),
+            writes: Mutex::new(Budget::new(limit.writes_per_sec, limit.burst)),
+            observer,
+        }))
+    }
+
+    /// Takes the budget of an RPC of `class` initiated now, returning how long it has to
+    /// wait.
+    fn take(&self, class: Class) -> Duration {
+        let budget = match class {
+            Class::Read => &self.0.reads,
+            Class::Write => &self.0.writes,
+        };
+        let now = Instant::now();
+        let (wait, utilization) = {
+            let mut budget = budget.lock_unpoisoned();
+            let wait = budget.take(now, self.0.mode);
+            (wait, budget.utilization(now))
+        };
+        self.0
+            .observer
+            .rate_limit_utilization(class.label(), utilization);
+        wait
+    }
+}
+
+/// The channel pacing or rejecting the reads and writes exceeding the budgets of a
+/// [`RateLimiter`].
+#[derive(Clone)]
+pub(crate) struct RateLimitGate {
+    inner: Channel,
+    limiter: RateLimiter,
+}
+
+impl RateLimitGate {
+    /// Limits the requests sent over `inner` with `limiter`.
+    #[inline]
+    pub(crate) fn new(inner: Channel, limiter: RateLimiter) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+impl Service<Request> for RateLimitGate {
+    type Response = Response;
+    type Error = tower::BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, tower::BoxError>> + Send>>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let wait = match Class::of(req.uri().path()) {
+            Some(class) => self.limiter.take(class),
+            None => Duration::ZERO,
+        };
+        if wait.is_zero() {
+            return Box::pin(self.inner.call(req));
+        }
+        if self.limiter.0.mode == RateLimitMode::Reject {
+            return Box::pin(std::future::ready(Ok(rate_limited(wait))));
+        }
+        // The ready inner channel is taken, its clone is not ready yet.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            tokio::time::sleep(wait).await;
+            inner.call(req).await
+        })
+    }
+}
+
+/// Limits `channel` with `limiter`, if any.
+#[inline]
+pub(crate) fn limit(channel: Channel, limiter: Option<&RateLimiter>) -> Channel {
+    match limiter {
+        Some(limiter) => Channel::Custom(BoxCloneService::new(RateLimitGate::new(
+            channel,
+            limiter.clone(),
+        ))),
+        None => channel,
+    }
+}
+
+/// The response rejecting a request which the budget allows after `retry_after`.
+fn rate_limited(retry_after: Duration) -> Response {
+    let mut status = Status::resource_exhausted(RATE_LIMITED_MESSAGE);
+    // Rounded up, so that the request is allowed once retried after that many millis.
+    let millis = retry_after.as_nanos().div_ceil(1_000_000).to_string();
+    if let Ok(millis) = MetadataValue::try_from(millis) {
+        status.metadata_mut().insert(RETRY_AFTER_KEY, millis);
+    }
+    status.into_http()
+}
+
+#[cfg(all(test, feature = "kv", feature = "lease"))]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::error::Error;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::rpc::kv::KvClient;
+    use crate::rpc::lease::LeaseClient;
+    use crate::rpc::pb::etcdserverpb::{
+        LeaseRevokeRequest as PbLeaseRevokeRequest, LeaseRevokeResponse as PbLeaseRevokeResponse,
+        PutRequest as PbPutRequest, PutResponse as PbPutResponse, RangeRequest as PbRangeRequest,
+        RangeResponse as PbRangeResponse,
+    };
+    use tonic::codec::ProstCodec;
+    use tonic::server::Grpc;
+
+    /// A member recording when the requests reached it.
+    #[derive(Clone, Default)]
+    struct Member {
+        received: Arc<Mutex<Vec<(&'static str, Instant)>>>,
+    }
+
+    impl Member {
+        fn channel(&self) -> Channel {
+            let member = self.clone();
+            let service = tower::service_fn(move |req: Request| {
+                let member = member.clone();
+                async move { Ok::<_, tower::BoxError>(member.serve(req).await) }
+            });
+            Channel::Custom(BoxCloneService::new(service))
+        }
+
+        /// The clients of the member, limited by `limit`.
+        fn clients(&self, limit: RateLimit) -> (KvClient, LeaseClient) {
+            let limiter = RateLimiter::new(&limit, Observer::default());
+            let channel = InterceptedChannel::new(
+                super::limit(self.channel(), Some(&limiter)),
+                Interceptor::default(),
+            );
+            (
+                KvClient::new(channel.clone(), AuthToken::default()),
+                LeaseClient::new(channel, AuthToken::default()),
+            )
+        }
+
+        /// The milliseconds elapsed since `start` when the requests of `rpc` were received.
+        fn received(&self, rpc: &str, start: Instant) -> Vec<u128> {
+            let received = self.received.lock_unpoisoned();
+            received
+                .iter()
+                .filter(|(r, _)| *r == rpc)
+                .map(|(_, at)| (*at - start).as_millis())
+                .collect()
+        }
+
+        async fn serve(self, req: Request) -> Response {
+            let rpc = match req.uri().path() {
+                "/etcdserverpb.KV/Range" => "Range",
+                "/etcdserverpb.KV/Put" => "Put",
+                "/etcdserverpb.Lease/LeaseRevoke" => "LeaseRevoke",
+                path => return Status::unimplemented(path.to_string()).into_http(),
+            };
+            self.received.lock_unpoisoned().push((rpc, Instant::now()));
+            match rpc {
+                "Range" => {
+                    let service = tower::service_fn(|_: tonic::Request<PbRangeRequest>| async {
+                        Ok(tonic::Response::new(PbRangeResponse::default()))
+                    });
+                    Grpc::new(ProstCodec::default()).unary(service, req).await
+                }
+                "Put" => {
+                    let service = tower::service_fn(|_: tonic::Request<PbPutRequest>| async {
+                        Ok(tonic::Response::new(PbPutResponse::default()))
+                    });
+                    Grpc::new(ProstCodec::default()).unary(service, req).await
+                }
+                _ => {
+                    let service =
+                        tower::service_fn(|_: tonic::Request<PbLeaseRevokeRequest>| async {
+                            Ok(tonic::Response::new(PbLeaseRevokeResponse::default()))
+                        });
+                    Grpc::new(ProstCodec::default()).unary(service, req).await
+                }
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_delay_paces() {
+        let member = Member::default();
+        let (kv, _) = member.clients(RateLimit::new(10, 4));
+        let start = Instant::now();
+
+        // Concurrent reads are sent one interval apart.
+        let reads = (0..4).map(|_| {
+            let mut kv = kv.clone();
+            tokio::spawn(async move { kv.get("key", None).await.unwrap() })
+        });
+        for read in reads.collect::<Vec<_>>() {
+            read.await.unwrap();
+        }
+        assert_eq!(member.received("Range", start), [0, 100, 200, 300]);
+
+        // The writes have a budget of their own.
+        let mut kv = kv;
+        for _ in 0..3 {
+            kv.put("key", "value", None).await.unwrap();
+        }
+        assert_eq!(member.received("Put", start), [300, 550, 800]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_delay_bursts() {
+        let member = Member::default();
+        let (mut kv, _) = member.clients(RateLimit::new(10, 10).with_burst(3));
+        let start = Instant::now();
+
+        for _ in 0..5 {
+            kv.get("key", None).await.unwrap();
+        }
+        assert_eq!(member.received("Range", start), [0, 0, 0, 100, 200]);
+
+        // The budget is refilled at the rate, up to the burst.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        for _ in 0..4 {
+            kv.get("key", None).await.unwrap();
+        }
+        assert_eq!(
+            member.received("Range", start),
+            [0, 0, 0, 100, 200, 1200, 1200, 1200, 1300]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reject() {
+        let member = Member::default();
+        let limit = RateLimit::new(4, 10)
+            .with_burst(2)
+            .with_mode(RateLimitMode::Reject);
+        let (mut kv, mut lease) = member.clients(limit);
+        let start = Instant::now();
+
+        kv.get("key", None).await.unwrap();
+        kv.get("key", None).await.unwrap();
+        let err = kv.get("key", None).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::RateLimited { retry_after, .. } if retry_after == Duration::from_millis(250)
+            ),
+            "{:?}",
+            err
+        );
+        assert!(!err.is_retryable());
+        assert_eq!(member.received("Range", start), [0, 0]);
+
+        // A rejected read takes nothing from the budget.
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        kv.get("key", None).await.unwrap();
+        kv.get("key", None).await.unwrap_err();
+        assert_eq!(member.received("Range", start), [0, 0, 250]);
+
+        // The RPCs which are neither reads nor writes are not limited.
+        for _ in 0..5 {
+            lease.revoke(1).await.unwrap();
+        }
+        assert_eq!(member.received("LeaseRevoke", start).len(), 5);
+    }
+
+    #[test]
+    fn test_classes() {
+        assert_eq!(Class::of("/etcdserverpb.KV/Range"), Some(Class::Read));
+        assert_eq!(Class::of("/etcdserverpb.Watch/Watch"), Some(Class::Read));
+        assert_eq!(Class::of("/etcdserverpb.KV/Txn"), Some(Class::Write));
+        assert_eq!(
+            Class::of("/etcdserverpb.Lease/LeaseGrant"),
+            Some(Class::Write)
+        );
+        assert_eq!(Class::of("/etcdserverpb.Lease/LeaseKeepAlive"), None);
+        assert_eq!(Class::of("/etcdserverpb.Maintenance/Status"), None);
+    }
+
+    #[test]
+    fn test_problems() {
+        assert!(RateLimit::new(1, 1).problems().is_empty());
+        assert_eq!(
+            RateLimit::new(0, 1).with_burst(0).problems(),
+            ["rate limit of reads is zero", "rate limit burst is zero"]
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! The protobuf messages and gRPC clients generated from the etcd API.
+//!
+//! The wrappers of the crate convert from and into the messages they wrap, e.g.
+//! `GetResponse::from(raw::etcdserverpb::RangeResponse { .. })`, and the request options
+//! convert from their requests.
+//!
+//! The generated clients returned by e.g. [`Client::kv_raw`] call any RPC of the proto files
+//! over the [`RawChannel`] of a client, which balances the requests over its endpoints and
+//! sends its auth token and metadata. They bypass the retries, timeouts, compression,
+//! tracing and metrics of the wrappers.
+//!
+//! # Stability
+//!
+//! The generated types follow the proto files of etcd rather than the semver of this crate:
+//! a minor release may add fields to them as etcd does, which breaks struct literals not
+//! ending with `..Default::default()`, or rename them when the proto files do.
+//!
+//! Some `bytes` fields are [`Bytes`] rather than `Vec<u8>`, shared by the clones of a
+//! request instead of copied: the keys, range ends and values of the `RangeRequest`,
+//! `PutRequest`, `DeleteRangeRequest` and `Compare` messages, and the blob of the
+//! `SnapshotResponse`. They are built with `.into()` from a `Vec<u8>`, or with
+//! [`Bytes::from_static`] without copying a static key.
+//!
+//! [`Client::kv_raw`]: crate::Client::kv_raw
+
+pub use crate::rpc::pb::{authpb, etcdserverpb, mvccpb, v3electionpb, v3lockpb};
+pub use prost::bytes::Bytes;
+
+use crate::auth::AuthService;
+use crate::intercept::InterceptedChannel;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+type Request = http::Request<tonic::body::Body>;
+
+/// The channel of the generated clients of a [`Client`](crate::Client), sending the
+/// requests over its balanced channel along with its auth token and metadata.
+#[derive(Clone)]
+pub struct RawChannel(AuthService<InterceptedChannel>);
+
+impl RawChannel {
+    #[inline]
+    pub(crate) const fn new(channel: AuthService<InterceptedChannel>) -> Self {
+        Self(channel)
+    }
+}
+
+impl Service<Request> for RawChannel {
+    type Response = <AuthService<InterceptedChannel> as Service<Request>>::Response;
+    type Error = <AuthService<InterceptedChannel> as Service<Request>>::Error;
+    type Future = <AuthService<InterceptedChannel> as Service<Request>>::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&mut self, request: Request) -> Self::Future {
+        self.0.call(request)
+    }
+}
@@ -0,0 +1,94 @@
+//! A barrier blocking waiters until it is released.
+
+use super::{next_revision, wait_event};
+use crate::error::{Error, Result};
+use crate::ids::LeaseId;
+use crate::rpc::kv::{Compare, CompareOp, KvClient, PutOptions, Txn, TxnOp};
+use crate::rpc::watch::{WatchClient, WatchFilterType, WatchOptions};
+use crate::session::Session;
+use crate::Client;
+use std::future::Future;
+
+/// A barrier on a key: while the key exists, [`Barrier::wait`] blocks until it is deleted.
+///
+/// The barrier is held by creating the key and released by deleting it, e.g. to hold
+/// workers back until some setup is done. Holding it bound to a [`Session`] releases it
+/// once the session lease is gone, so a crashed holder does not block the waiters forever.
+#[derive(Clone)]
+pub struct Barrier {
+    kv: KvClient,
+    watch: WatchClient,
+    key: Vec<u8>,
+}
+
+impl Barrier {
+    /// Creates a barrier on `key`.
+    #[inline]
+    pub fn new(client: &Client, key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            kv: client.kv_client(),
+            watch: client.watch_client(),
+            key: key.into(),
+        }
+    }
+
+    /// The key of the barrier.
+    #[inline]
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Holds the barrier, creating its key, attached to the lease of `session` if given.
+    ///
+    /// Fails with an [`Error::KeyExists`] if the barrier is held already.
+    pub fn hold<'a>(
+        &'a mut self,
+        session: Option<&Session>,
+    ) -> impl Future<Output = Result<()>> + Send + 'a {
+        // Only the lease is kept by the future, which would not be `Send` with the session.
+        let lease = session.map(|session| session.check().map(|()| session.lease_id()));
+        async move { self.hold_lease(lease.transpose()?).await }
+    }
+
+    /// Holds the barrier with the lease `lease`, if any, see [`Barrier::hold`].
+    async fn hold_lease(&mut self, lease: Option<LeaseId>) -> Result<()> {
+        let mut options = PutOptions::new();
+        if let Some(lease) = lease {
+            options = options.with_lease(lease);
+        }
+        let txn = Txn::new()
+            .when([Compare::version(self.key.clone(), CompareOp::Equal, 0)])
+            .and_then([TxnOp::put(self.key.clone(), "", Some(options))]);
+        if !self.kv.txn(txn).await?.succeeded() {
+            return Err(Error::KeyExists(self.key.clone()));
+        }
+        Ok(())
+    }
+
+    /// Releases the barrier, deleting its key, which unblocks all the waiters.
+    ///
+    /// Releasing a barrier which is not held does nothing.
+    #[inline]
+    pub async fn release(&mut self) -> Result<()> {
+        self.kv.delete(self.key.clone(), None).await?;
+        Ok(())
+    }
+
+    /// Waits until the barrier is released, returning immediately if it is not held.
+    pub async fn wait(&mut self) -> Result<()> {
+        loop {
+            let resp = self.kv.get(self.key.clone(), None).await?;
+            if resp.kvs().is_empty() {
+                return Ok(());
+            }
+            // Watching from the revision after the get, a release between the get and the
+            // watch still shows up as an event.
+            let options = WatchOptions::new()
+                .with_start_revision(next_revision(&resp))
+                .with_filters([WatchFilterType::NoPut]);
+            if wait_event(&mut self.watch, self.key.clone(), options).await? {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! A local cache of a prefix, kept up to date by watching it.
+
+use super::prefix_watch::{PrefixUpdate, PrefixWatch, RELIST_BACKOFF};
+use crate::error::Result;
+use crate::ids::Revision;
+use crate::lock::RwLockExt;
+use crate::logging::log_event;
+use crate::rpc::watch::EventType;
+use crate::rpc::KeyValue;
+use crate::task::Task;
+use crate::Client;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+
+/// The name of the task keeping a cache up to date.
+const CACHE_TASK: &str = "cache update";
+
+/// The number of changes buffered for the subscribers of a cache, by default.
+pub const DEFAULT_CACHE_EVENT_CAPACITY: usize = 1024;
+
+/// Options for creating a [`Cache`].
+#[derive(Debug, Clone)]
+pub struct CacheOptions {
+    event_capacity: usize,
+}
+
+impl CacheOptions {
+    /// Creates a `CacheOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            event_capacity: DEFAULT_CACHE_EVENT_CAPACITY,
+        }
+    }
+
+    /// Sets the number of changes buffered for the subscribers, a subscriber falling
+    /// further behind gets a [`CacheEvent::Resynced`] instead of the changes it missed.
+    #[inline]
+    pub const fn with_event_capacity(mut self, capacity: usize) -> Self {
+        self.event_capacity = capacity;
+        self
+    }
+}
+
+impl Default for CacheOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A change to a [`Cache`], see [`Cache::subscribe`].
+#[derive(Debug, Clone)]
+pub enum CacheEvent {
+    /// A key has been put.
+    Put(KeyValue),
+    /// A key has been deleted, with its last cached key-value.
+    Delete(KeyValue),
+    /// The prefix has been listed again, e.g. after its watch revision was compacted, or
+    /// the subscriber fell behind: the changes since the last event are unknown, the whole
+    /// cache is to be read again.
+    Resynced,
+}
+
+/// The entries of a cache and the revision they are up to date with.
+struct State {
+    kvs: HashMap<Vec<u8>, KeyValue>,
+    revision: i64,
+}
+
+/// A cache and its subscribers, shared with its update task.
+struct Shared {
+    state: RwLock<State>,
+    events: broadcast::Sender<CacheEvent>,
+}
+
+/// A local cache of all the keys under a prefix, kept up to date by watching them.
+///
+/// The prefix is listed, then watched from the revision it was listed at, so that no
+/// change is missed between the two. The watch survives reconnects, resuming from the last
+/// revision seen, and compactions, after which the prefix is listed again and a
+/// [`CacheEvent::Resynced`] sent to the subscribers.
+///
+/// The cache is updated in the background until dropped.
+pub struct Cache {
+    shared: Arc<Shared>,
+    updater: Task,
+}
+
+impl Cache {
+    /// Creates the cache of the keys under `prefix`, listing them before returning.
+    pub async fn new(
+        client: &Client,
+        prefix: impl Into<Vec<u8>>,
+        options: Option<CacheOptions>,
+    ) -> Result<Self> {
+        let options = options.unwrap_or_default();
+        let mut watch = PrefixWatch::new(client.kv_client(), client.watch_client(), prefix.into());
+        let mut state = State {
+            kvs: HashMap::new(),
+            revision: 0,
+        };
+        if let PrefixUpdate::Listed(kvs) = watch.next().await? {
+            state.kvs = index(kvs);
+            state.revision = watch.revision();
+        }
+
+        // At least one, broadcast channels can not be empty.
+        let (events, _) = broadcast::channel(options.event_capacity.max(1));
+        let shared = Arc::new(Shared {
+            state: RwLock::new(state),
+            events,
+        });
+        let updater = client
+            .lease_client()
+            .tasks()
+            .spawn(CACHE_TASK, update(shared.clone(), watch));
+        Ok(Self { shared, updater })
+    }
+
+    /// The cached key-value of `key`, if it exists.
+    #[inline]
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<KeyValue> {
+        let state = self.shared.state.read_unpoisoned();
+        state.kvs.get(key.as_ref()).cloned()
+    }
+
+    /// All the cached key-values, in no particular order, as of now.
+    pub fn iter_snapshot(&self) -> impl Iterator<Item = KeyValue> {
+        let state = self.shared.state.read_unpoisoned();
+        let kvs: Vec<_> = state.kvs.values().cloned().collect();
+        kvs.into_iter()
+    }
+
+    /// The number of cached keys.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.shared.state.read_unpoisoned().kvs.len()
+    }
+
+    /// Returns `true` if no key is cached.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The revision the cache is up to date with.
+    #[inline]
+    pub fn revision(&self) -> Revision {
+        Revision::new(self.shared.state.read_unpoisoned().revision)
+    }
+
+    /// Subscribes to the changes applied to the cache from now on.
+    pub fn subscribe(&self) -> CacheSubscriber {
+        // Under the lock, not to receive a change already read from the cache.
+        let _state = self.shared.state.read_unpoisoned();
+        CacheSubscriber {
+            events: self.shared.events.subscribe(),
+        }
+    }
+}
+
+impl Drop for Cache {
+    #[inline]
+    fn drop(&mut self) {
+        self.updater.abort();
+    }
+}
+
+/// The changes to a [`Cache`], see [`Cache::subscribe`].
+pub struct CacheSubscriber {
+    events: broadcast::Receiver<CacheEvent>,
+}
+
+impl CacheSubscriber {
+    /// Fetches the next change to the cache, or `None` once the cache is dropped.
+    pub async fn message(&mut self) -> Option<CacheEvent> {
+        match self.events.recv().await {
+            Ok(event) => Some(event),
+            Err(broadcast::error::RecvError::Lagged(_)) => Some(CacheEvent::Resynced),
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    }
+}
+
+/// Indexes `kvs` by key.
+fn index(kvs: Vec<KeyValue>) -> HashMap<Vec<u8>, KeyValue> {
+    kvs.into_iter().map(|kv| (kv.key().to_vec(), kv)).collect()
+}
+
+/// Applies the changes of the watched prefix to the cache.
+async fn update(shared: Arc<Shared>, mut watch: PrefixWatch) {
+    loop {
+        let update = match watch.next().await {
+            Ok(update) => update,
+            Err(e) => {
+                log_event!(
+                    Warn,
+                    "etcd cache failed to watch its prefix",
+                    reason = e.to_string(),
+                );
+                tokio::time::sleep(RELIST_BACKOFF).await;
+                continue;
+            }
+        };
+
+        let mut state = shared.state.write_unpoisoned();
+        match update {
+            PrefixUpdate::Listed(kvs) => {
+                state.kvs = index(kvs);
+                let _ = shared.events.send(CacheEvent::Resynced);
+            }
+            PrefixUpdate::Changed(resp) => {
+                for event in resp.events() {
+                    let Some(kv) = event.kv() else {
+                        continue;
+                    };
+                    let event = match event.event_type() {
+                        EventType::Put => {
+                            state.kvs.insert(kv.key().to_vec(), kv.clone());
+                            CacheEvent::Put(kv.clone())
+                        }
+                        EventType::Delete => {
+                            let cached = state.kvs.remove(kv.key());
+                            CacheEvent::Delete(cached.unwrap_or_else(|| kv.clone()))
+                        }
+                    };
+                    let _ = shared.events.send(event);
+                }
+            }
+        }
+        state.revision = watch.revision();
+    }
+}
@@ -0,0 +1,150 @@
+//! A double barrier, for a group of participants to enter and leave a computation together.
+
+use super::{next_revision, wait_event};
+use crate::error::{Error, Result};
+use crate::ids::LeaseId;
+use crate::rpc::kv::{GetOptions, KvClient, PutOptions};
+use crate::rpc::watch::{WatchClient, WatchFilterType, WatchOptions};
+use crate::session::Session;
+use crate::Client;
+use std::future::Future;
+
+/// A double barrier under a prefix, which `count` participants enter and leave together.
+///
+/// [`DoubleBarrier::enter`] registers the participant under `{prefix}/waiters/` and blocks
+/// until `count` participants are registered, [`DoubleBarrier::leave`] unregisters it and
+/// blocks until all of them are gone. The participants are registered with the lease of
+/// their session: a participant which crashes is unregistered once its lease expires, so
+/// that the others do not wait for it forever.
+#[derive(Clone)]
+pub struct DoubleBarrier {
+    kv: KvClient,
+    watch: WatchClient,
+    prefix: Vec<u8>,
+    count: usize,
+    /// The key the participant is registered under, once it entered.
+    key: Option<Vec<u8>>,
+}
+
+impl DoubleBarrier {
+    /// Creates a double barrier under `prefix` for `count` participants.
+    #[inline]
+    pub fn new(client: &Client, prefix: impl Into<Vec<u8>>, count: usize) -> Self {
+        Self {
+            kv: client.kv_client(),
+            watch: client.watch_client(),
+            prefix: prefix.into(),
+            count,
+            key: None,
+        }
+    }
+
+    /// The prefix of the double barrier.
+    #[inline]
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// The key under which the participants are registered.
+    fn waiters(&self) -> Vec<u8> {
+        [self.prefix.as_slice(), b"/waiters/"].concat()
+    }
+
+    /// The key put once all the participants entered.
+    fn ready(&self) -> Vec<u8> {
+        [self.prefix.as_slice(), b"/ready"].concat()
+    }
+
+    /// Enters the double barrier, registering the participant with the lease of `session`,
+    /// and waits until `count` participants entered.
+    ///
+    /// Fails with an [`Error::InvalidArgs`] if more than `count` participants entered, the
+    /// participant is unregistered then.
+    pub fn enter<'a>(
+        &'a mut self,
+        session: &Session,
+    ) -> impl Future<Output = Result<()>> + Send + 'a {
+        // Only the lease is kept by the future, which would not be `Send` with the session.
+        let lease = session.check().map(|()| session.lease_id());
+        async move { self.enter_lease(lease?).await }
+    }
+
+    /// Enters the double barrier with the lease `lease`, see [`DoubleBarrier::enter`].
+    async fn enter_lease(&mut self, lease: LeaseId) -> Result<()> {
+        let key = [self.waiters(), format!("{:016x}", lease).into_bytes()].concat();
+        let options = PutOptions::new().with_lease(lease);
+        self.kv.put(key.clone(), "", Some(options)).await?;
+        self.key = Some(key);
+
+        let (waiters, ready) = (self.waiters(), self.ready());
+        let prefix = [self.prefix.as_slice(), b"/"].concat();
+        loop {
+            let options = GetOptions::new().with_prefix().with_keys_only();
+            let resp = self.kv.get(prefix.clone(), Some(options)).await?;
+            if resp.kvs().iter().any(|kv| kv.key() == ready.as_slice()) {
+                return Ok(());
+            }
+            let entered = resp
+                .kvs()
+                .iter()
+                .filter(|kv| kv.key().starts_with(&waiters))
+                .count();
+            if entered > self.count {
+                self.leave_key().await?;
+                return Err(Error::InvalidArgs(format!(
+                    "more than {} participants entered the double barrier",
+                    self.count
+                )));
+            }
+            if entered == self.count {
+                // Bound to the lease as well, not to be left behind by a crash.
+                let options = PutOptions::new().with_lease(lease);
+                self.kv.put(ready, "", Some(options)).await?;
+                return Ok(());
+            }
+
+            // Any change, i.e. a participant entering, leaving by crashing or the ready key
+            // being put, is worth checking again.
+            let options = WatchOptions::new()
+                .with_prefix()
+                .with_start_revision(next_revision(&resp));
+            wait_event(&mut self.watch, prefix.clone(), options).await?;
+        }
+    }
+
+    /// Leaves the double barrier, unregistering the participant, and waits until all the
+    /// participants left.
+    ///
+    /// Fails with an [`Error::InvalidArgs`] if the participant did not enter.
+    pub async fn leave(&mut self) -> Result<()> {
+        if self.key.is_none() {
+            return Err(Error::InvalidArgs(String::from(
+                "the double barrier was not entered",
+            )));
+        }
+        self.leave_key().await?;
+
+        let waiters = self.waiters();
+        loop {
+            let options = GetOptions::new().with_prefix().with_count_only();
+            let resp = self.kv.get(waiters.clone(), Some(options)).await?;
+            if resp.count() == 0 {
+                self.kv.delete(self.ready(), None).await?;
+                return Ok(());
+            }
+            let options = WatchOptions::new()
+                .with_prefix()
+                .with_start_revision(next_revision(&resp))
+                .with_filters([WatchFilterType::NoPut]);
+            wait_event(&mut self.watch, waiters.clone(), options).await?;
+        }
+    }
+
+    /// Unregisters the participant.
+    async fn leave_key(&mut self) -> Result<()> {
+        if let Some(key) = self.key.take() {
+            self.kv.delete(key, None).await?;
+        }
+        Ok(())
+    }
+}
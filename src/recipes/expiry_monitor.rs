@@ -0,0 +1,340 @@
+//! A monitor telling the keys deleted because their lease expired from the keys deleted
+//! explicitly.
+
+use super::prefix_watch::RELIST_BACKOFF;
+use crate::error::{Error, Result};
+use crate::ids::LeaseId;
+use crate::rpc::lease::LeaseClient;
+use crate::rpc::watch::{
+    EventType, WatchClient, WatchFilterType, WatchOptions, WatchStream, Watcher,
+};
+use crate::task::Task;
+use crate::Client;
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// The name of the task monitoring a prefix.
+const EXPIRY_MONITOR_TASK: &str = "expiry-monitor";
+
+/// The number of leases looked up at once, so that the expiry of many leases does not
+/// flood the cluster with TimeToLive requests.
+const EXPIRY_LOOKUPS: usize = 8;
+
+/// The number of expired leases remembered, so that the keys of a lease are classified by
+/// a single lookup.
+const EXPIRED_LEASES_CAPACITY: usize = 1024;
+
+/// Why a key has been deleted, see [`ExpiryMonitor::message`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpiryEvent {
+    /// The key was deleted along with its lease, which expired or was revoked.
+    Expired {
+        /// The key deleted.
+        key: Vec<u8>,
+        /// The ID of the lease the key was attached to.
+        lease: LeaseId,
+    },
+    /// The key was deleted explicitly, while its lease, if any, was still alive.
+    Deleted {
+        /// The key deleted.
+        key: Vec<u8>,
+    },
+}
+
+/// The handle of a monitor of the deletions under a prefix, see [`expiry_monitor`].
+///
+/// Dropping the handle stops the monitor in the background, see [`ExpiryMonitor::stop`] to
+/// wait for it.
+pub struct ExpiryMonitor {
+    shutdown: CancellationToken,
+    events: mpsc::UnboundedReceiver<ExpiryEvent>,
+    result: Option<oneshot::Receiver<Result<()>>>,
+    runner: Option<Task>,
+}
+
+impl ExpiryMonitor {
+    /// Fetches the next deletion, or `None` once the monitor stopped, see
+    /// [`ExpiryMonitor::stop`] for why.
+    #[inline]
+    pub async fn message(&mut self) -> Option<ExpiryEvent> {
+        self.events.recv().await
+    }
+
+    /// Stops the monitor, and returns the error the monitor stopped with on its own if any,
+    /// e.g. because the revision to resume watching from has been compacted.
+    pub async fn stop(mut self) -> Result<()> {
+        self.shutdown.cancel();
+        if let Some(runner) = self.runner.take() {
+            runner.join().await?;
+        }
+        match self.result.take() {
+            Some(result) => result.await.unwrap_or(Ok(())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ExpiryMonitor {
+    #[inline]
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// Monitors the keys deleted under `prefix`, telling the keys deleted because their lease
+/// expired from the keys deleted explicitly.
+///
+/// etcd does not tell why a key is deleted: the deletions are watched with the previous
+/// key-values, and the lease of a deleted key is looked up. A key whose lease no longer
+/// exists is reported as [`ExpiryEvent::Expired`], as etcd deletes the keys of a lease
+/// along with it; a key without a lease, or whose lease is still alive, as
+/// [`ExpiryEvent::Deleted`]. The leases are looked up a few at a time, and the expired
+/// ones remembered, so that the keys of many expired leases are classified without
+/// stampeding the cluster.
+///
+/// A lease revoked explicitly is not told from an expired one, and a key deleted
+/// explicitly right before its lease expired may be reported as expired.
+pub fn expiry_monitor(client: &Client, prefix: impl Into<Vec<u8>>) -> ExpiryMonitor {
+    let shutdown = CancellationToken::new();
+    let (tx, events) = mpsc::unbounded_channel();
+    let (result_tx, result) = oneshot::channel();
+    let mut runner = Runner {
+        watch: client.watch_client(),
+        lease: client.lease_client(),
+        prefix: prefix.into(),
+        expired: ExpiredLeases::default(),
+        shutdown: shutdown.clone(),
+        tx,
+    };
+    let runner = client
+        .lease_client()
+        .tasks()
+        .spawn(EXPIRY_MONITOR_TASK, async move {
+            let _ = result_tx.send(runner.run().await);
+        });
+    ExpiryMonitor {
+        shutdown,
+        events,
+        result: Some(result),
+        runner: Some(runner),
+    }
+}
+
+/// What a monitor runs with.
+struct Runner {
+    watch: WatchClient,
+    lease: LeaseClient,
+    prefix: Vec<u8>,
+    expired: ExpiredLeases,
+    shutdown: CancellationToken,
+    tx: mpsc::UnboundedSender<ExpiryEvent>,
+}
+
+impl Runner {
+    /// Watches the deletions, and classifies them until shut down.
+    async fn run(&mut self) -> Result<()> {
+        // The revision the deletions are known up to, `0` until watched.
+        let mut revision = 0;
+        let mut stream: Option<(Watcher, WatchStream)> = None;
+        loop {
+            let Some((_, watch)) = &mut stream else {
+                let mut options = WatchOptions::new()
+                    .with_prefix()
+                    .with_prev_key()
+                    .with_filters([WatchFilterType::NoPut]);
+                if revision > 0 {
+                    options = options.with_start_revision(revision + 1);
+                }
+                let watch = self.watch.watch(self.prefix.clone(), Some(options));
+                stream = tokio::select! {
+                    _ = self.shutdown.cancelled() => return Ok(()),
+                    watch = watch => match watch {
+                        Ok(watch) => Some(watch),
+                        Err(e) if e.is_retryable() => {
+                            tokio::time::sleep(RELIST_BACKOFF).await;
+                            None
+                        }
+                        Err(e) => return Err(e),
+                    },
+                };
+                continue;
+            };
+            let resp = tokio::select! {
+                _ = self.shutdown.cancelled() => return Ok(()),
+                resp = watch.message() => resp,
+            };
+            let resp = match resp {
+                Ok(Some(resp)) if resp.canceled() => {
+                    // The deletions made meanwhile can not be found anymore.
+                    return Err(Error::WatchError(format!(
+                        "expiry monitor watch canceled after revision {}, compacted at {}: {}",
+                        revision,
+                        resp.compact_revision(),
+                        resp.cancel_reason()
+                    )));
+                }
+                Ok(Some(resp)) => resp,
+                Ok(None) => {
+                    stream = None;
+                    continue;
+                }
+                Err(e) if e.is_retryable() => {
+                    stream = None;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if revision == 0 && resp.created() {
+                // A broken watch resumes after the revision it was created at.
+                revision = resp.header().map_or(0, |header| header.revision().get());
+            }
+
+            let mut deletions = Vec::new();
+            for event in resp.events() {
+                let Some(kv) = event.kv() else {
+                    continue;
+                };
+                revision = kv.mod_revision().get();
+                if event.event_type() != EventType::Delete {
+                    continue;
+                }
+                let lease = event.prev_kv().map_or(0, |prev| prev.lease().get());
+                deletions.push((kv.key().to_vec(), lease));
+            }
+            if deletions.is_empty() {
+                continue;
+            }
+            // The token is cloned as the lookups borrow the task mutably.
+            let shutdown = self.shutdown.clone();
+            let events = tokio::select! {
+                _ = shutdown.cancelled() => return Ok(()),
+                events = self.classify(deletions) => events?,
+            };
+            for event in events {
+                let _ = self.tx.send(event);
+            }
+        }
+    }
+
+    /// Classifies the deleted keys `deletions`, paired with the ID of their lease, `0` if
+    /// without one.
+    async fn classify(&mut self, deletions: Vec<(Vec<u8>, i64)>) -> Result<Vec<ExpiryEvent>> {
+        let leases: HashSet<i64> = deletions
+            .iter()
+            .map(|(_, lease)| *lease)
+            .filter(|lease| *lease != 0 && !self.expired.contains(*lease))
+            .collect();
+        let mut alive = HashMap::new();
+        let mut lookups = JoinSet::new();
+        for lease in leases {
+            if lookups.len() >= EXPIRY_LOOKUPS {
+                self.collect(&mut lookups, &mut alive).await?;
+            }
+            let client = self.lease.clone();
+            lookups.spawn(async move { (lease, is_alive(client, lease).await) });
+        }
+        while !lookups.is_empty() {
+            self.collect(&mut lookups, &mut alive).await?;
+        }
+
+        Ok(deletions
+            .into_iter()
+            .map(|(key, lease)| match alive.get(&lease) {
+                Some(false) => ExpiryEvent::Expired {
+                    key,
+                    lease: LeaseId::new(lease),
+                },
+                _ if self.expired.contains(lease) => ExpiryEvent::Expired {
+                    key,
+                    lease: LeaseId::new(lease),
+                },
+                _ => ExpiryEvent::Deleted { key },
+            })
+            .collect())
+    }
+
+    /// Collects the result of the next lookup of `lookups` into `alive`, remembering the
+    /// lease if expired.
+    async fn collect(
+        &mut self,
+        lookups: &mut JoinSet<(i64, Result<bool>)>,
+        alive: &mut HashMap<i64, bool>,
+    ) -> Result<()> {
+        let (lease, result) = match lookups.join_next().await {
+            Some(Ok(lookup)) => lookup,
+            Some(Err(e)) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            _ => return Ok(()),
+        };
+        let is_alive = result?;
+        if !is_alive {
+            self.expired.insert(lease);
+        }
+        alive.insert(lease, is_alive);
+        Ok(())
+    }
+}
+
+/// Returns `true` if the lease `id` is still alive, retrying while the cluster is
+/// unreachable.
+async fn is_alive(mut client: LeaseClient, id: i64) -> Result<bool> {
+    loop {
+        match client.check_lease(id).await {
+            Ok(()) => return Ok(true),
+            Err(Error::LeaseNotFound { .. } | Error::LeaseExpired { .. }) => return Ok(false),
+            Err(e) if e.is_retryable() => tokio::time::sleep(RELIST_BACKOFF).await,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The leases found expired, up to [`EXPIRED_LEASES_CAPACITY`] of the latest ones.
+#[derive(Default)]
+struct ExpiredLeases {
+    leases: HashSet<i64>,
+    order: VecDeque<i64>,
+}
+
+impl ExpiredLeases {
+    /// Returns `true` if the lease `id` has been found expired.
+    #[inline]
+    fn contains(&self, id: i64) -> bool {
+        self.leases.contains(&id)
+    }
+
+    /// Remembers that the lease `id` expired, forgetting the oldest lease if full.
+    fn insert(&mut self, id: i64) {
+        if !self.leases.insert(id) {
+            return;
+        }
+        self.order.push_back(id);
+        if self.order.len() > EXPIRED_LEASES_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.leases.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_leases() {
+        let mut expired = ExpiredLeases::default();
+        for id in 1..=EXPIRED_LEASES_CAPACITY as i64 {
+            expired.insert(id);
+        }
+        expired.insert(1);
+        assert!(expired.contains(1));
+        assert_eq!(expired.order.len(), EXPIRED_LEASES_CAPACITY);
+
+        expired.insert(0x1_0000);
+        assert!(!expired.contains(1));
+        assert!(expired.contains(2));
+        assert!(expired.contains(0x1_0000));
+        assert_eq!(expired.leases.len(), EXPIRED_LEASES_CAPACITY);
+    }
+}
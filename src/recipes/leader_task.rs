@@ -0,0 +1,235 @@
+//! A task run only while holding the leadership of an election.
+
+use crate::error::{Error, Result};
+use crate::ids::Revision;
+use crate::rpc::election::{
+    ElectionClient, LeaderKey, ProclaimOptions, ProclaimResponse, RECAMPAIGN_BACKOFF,
+};
+use crate::task::{panic_message, Task};
+use crate::Client;
+use std::future::Future;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// The name of the task campaigning and running the task of a [`LeaderTask`].
+const LEADER_TASK: &str = "leader task";
+
+/// The leadership a task of [`leader_task`] runs with.
+#[derive(Clone)]
+pub struct LeadershipContext {
+    election: ElectionClient,
+    leader: LeaderKey,
+    epoch: u64,
+    cancel: CancellationToken,
+}
+
+impl LeadershipContext {
+    /// The token cancelled once the leadership is lost, or the runner shut down, e.g. to
+    /// stop the work the task spawned.
+    #[inline]
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// Number of the leadership, increasing with each won leadership.
+    #[inline]
+    pub const fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// The leader key of the won election.
+    #[inline]
+    pub fn leader(&self) -> &LeaderKey {
+        &self.leader
+    }
+
+    /// A fencing token of the leadership, see [`LeaderKey::rev`].
+    #[inline]
+    pub fn fencing_token(&self) -> Revision {
+        self.leader.rev()
+    }
+
+    /// Lets the leader announce a new value without another election.
+    pub async fn proclaim(&mut self, value: impl Into<Vec<u8>>) -> Result<ProclaimResponse> {
+        let options = ProclaimOptions::new().with_leader(self.leader.clone());
+        self.election.proclaim(value, Some(options)).await
+    }
+}
+
+/// What happened to a [`LeaderTask`], see [`LeaderTask::message`].
+#[derive(Debug)]
+pub enum LeaderTaskEvent {
+    /// The leadership has been won, the task is started.
+    Elected {
+        /// Number of the leadership, increasing with each won leadership.
+        epoch: u64,
+        /// The fencing token of the leadership.
+        fencing_token: Revision,
+    },
+    /// The leadership of the given epoch has been lost, the task is stopped.
+    Deposed {
+        /// Number of the lost leadership.
+        epoch: u64,
+    },
+    /// The task returned while holding the leadership, which is resigned to run the task
+    /// again once elected again.
+    Finished {
+        /// Number of the leadership the task ran with.
+        epoch: u64,
+    },
+    /// The task panicked while holding the leadership, which is resigned to run the task
+    /// again once elected again.
+    Panicked {
+        /// Number of the leadership the task ran with.
+        epoch: u64,
+        /// The message of the panic.
+        panic_message: String,
+    },
+    /// Campaigning failed, the runner campaigns again after a delay.
+    CampaignFailed(Error),
+}
+
+/// The handle of a task run while holding the leadership of an election, see
+/// [`leader_task`].
+///
+/// Dropping the handle shuts the runner down in the background, see
+/// [`LeaderTask::shutdown`] to wait for it.
+pub struct LeaderTask {
+    shutdown: CancellationToken,
+    events: mpsc::UnboundedReceiver<LeaderTaskEvent>,
+    runner: Option<Task>,
+}
+
+impl LeaderTask {
+    /// Fetches the next event of the runner, or `None` once it is shut down.
+    #[inline]
+    pub async fn message(&mut self) -> Option<LeaderTaskEvent> {
+        self.events.recv().await
+    }
+
+    /// Shuts the runner down: stops the task if running, resigns the leadership if held,
+    /// and waits for both to be done.
+    ///
+    /// Fails with an [`Error::InternalTaskFailed`] if the runner itself panicked.
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.shutdown.cancel();
+        match self.runner.take() {
+            Some(runner) => runner.join().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for LeaderTask {
+    #[inline]
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// Runs the task made by `make_task` while holding the leadership of the election `name`,
+/// campaigning again whenever it is lost.
+///
+/// The task is cancelled as soon as the leadership is lost: the token of its
+/// [`LeadershipContext`] is cancelled and its future is dropped, before campaigning again.
+/// A task which returns or panics while holding the leadership resigns it, a panic being
+/// reported as a [`LeaderTaskEvent::Panicked`] event rather than stopping the runner.
+///
+/// The leadership is campaigned for with an empty value, see
+/// [`LeadershipContext::proclaim`] to announce one.
+pub fn leader_task<F, Fut>(client: &Client, name: impl Into<Vec<u8>>, make_task: F) -> LeaderTask
+where
+    F: FnMut(LeadershipContext) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let shutdown = CancellationToken::new();
+    let (tx, events) = mpsc::unbounded_channel();
+    let election = client.election_client();
+    let runner = client.lease_client().tasks().spawn(
+        LEADER_TASK,
+        run(election, name.into(), make_task, shutdown.clone(), tx),
+    );
+    LeaderTask {
+        shutdown,
+        events,
+        runner: Some(runner),
+    }
+}
+
+/// Campaigns and runs the task until `shutdown` is cancelled.
+async fn run<F, Fut>(
+    mut election: ElectionClient,
+    name: Vec<u8>,
+    mut make_task: F,
+    shutdown: CancellationToken,
+    tx: mpsc::UnboundedSender<LeaderTaskEvent>,
+) where
+    F: FnMut(LeadershipContext) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut epoch = 0;
+    loop {
+        let campaign = election.campaign_guarded(name.clone(), Vec::new(), None);
+        let guard = tokio::select! {
+            _ = shutdown.cancelled() => return,
+            guard = campaign => guard,
+        };
+        let guard = match guard {
+            Ok(guard) => guard,
+            Err(e) => {
+                let _ = tx.send(LeaderTaskEvent::CampaignFailed(e));
+                tokio::select! {
+                    _ = shutdown.cancelled() => return,
+                    _ = tokio::time::sleep(RECAMPAIGN_BACKOFF) => continue,
+                }
+            }
+        };
+
+        epoch += 1;
+        let _ = tx.send(LeaderTaskEvent::Elected {
+            epoch,
+            fencing_token: guard.fencing_token(),
+        });
+        let cancel = shutdown.child_token();
+        let context = LeadershipContext {
+            election: election.clone(),
+            leader: guard.leader().clone(),
+            epoch,
+            cancel: cancel.clone(),
+        };
+        // Spawned to catch its panic.
+        let mut task = tokio::spawn(make_task(context));
+        let ended = tokio::select! {
+            _ = guard.lost() => None,
+            _ = shutdown.cancelled() => None,
+            ended = &mut task => Some(ended),
+        };
+
+        match ended {
+            Some(ended) => {
+                let _ = tx.send(match ended {
+                    Err(e) if e.is_panic() => LeaderTaskEvent::Panicked {
+                        epoch,
+                        panic_message: panic_message(e.into_panic().as_ref()),
+                    },
+                    _ => LeaderTaskEvent::Finished { epoch },
+                });
+                cancel.cancel();
+                let _ = guard.resign().await;
+            }
+            None => {
+                // The task is done with before anything else, not to run alongside the
+                // next leader.
+                cancel.cancel();
+                task.abort();
+                let _ = task.await;
+                if shutdown.is_cancelled() {
+                    let _ = guard.resign().await;
+                    return;
+                }
+                drop(guard);
+                let _ = tx.send(LeaderTaskEvent::Deposed { epoch });
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! A mirror of a prefix from a cluster to another, like `etcdctl make-mirror`.
+
+use super::prefix_watch::RELIST_BACKOFF;
+use crate::error::{Error, Result};
+use crate::ids::Revision;
+use crate::rpc::get_prefix;
+use crate::rpc::kv::{GetOptions, KvClient, Txn, TxnOp};
+use crate::rpc::watch::{EventType, WatchClient, WatchOptions, WatchStream, Watcher};
+use crate::task::Task;
+use crate::Client;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+
+/// The name of the task mirroring a prefix.
+const MIRROR_TASK: &str = "mirror";
+
+/// The number of keys copied per page by a mirror, by default.
+pub const DEFAULT_MIRROR_BATCH_SIZE: i64 = 1000;
+
+/// The number of operations of the txns applying the changes to the destination, the
+/// default `--max-txn-ops` of etcd.
+const MIRROR_TXN_OPS: usize = 128;
+
+/// Options for [`mirror`].
+#[derive(Debug, Clone)]
+pub struct MirrorOptions {
+    src_prefix: Vec<u8>,
+    dst_prefix: Option<Vec<u8>>,
+    batch_size: i64,
+}
+
+impl MirrorOptions {
+    /// Creates a `MirrorOptions` mirroring the keys under `src_prefix`.
+    #[inline]
+    pub fn new(src_prefix: impl Into<Vec<u8>>) -> Self {
+        Self {
+            src_prefix: src_prefix.into(),
+            dst_prefix: None,
+            batch_size: DEFAULT_MIRROR_BATCH_SIZE,
+        }
+    }
+
+    /// Replaces the source prefix of the keys by `dst_prefix` on the destination, instead
+    /// of mirroring them under the same keys.
+    #[inline]
+    pub fn with_dst_prefix(mut self, dst_prefix: impl Into<Vec<u8>>) -> Self {
+        self.dst_prefix = Some(dst_prefix.into());
+        self
+    }
+
+    /// Sets the number of keys copied per page.
+    #[inline]
+    pub const fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// The key on the destination of the source key `key`.
+    fn dst_key(&self, key: &[u8]) -> Vec<u8> {
+        match &self.dst_prefix {
+            Some(prefix) => [prefix, &key[self.src_prefix.len()..]].concat(),
+            None => key.to_vec(),
+        }
+    }
+}
+
+/// The progress of a [`Mirror`], see [`Mirror::message`].
+#[derive(Debug)]
+pub enum MirrorEvent {
+    /// A page of the initial copy has been applied to the destination.
+    Copied {
+        /// The number of keys copied so far.
+        keys: u64,
+    },
+    /// The initial copy is done, the changes made since are applied from now on.
+    Synced {
+        /// The number of keys copied.
+        keys: u64,
+        /// The revision of the source the keys were copied at.
+        revision: Revision,
+    },
+    /// The changes of the source up to a revision have been applied to the destination.
+    Applied {
+        /// The revision of the source applied.
+        revision: Revision,
+        /// The number of revisions the source was ahead of the applied one.
+        lag: i64,
+    },
+    /// A key could not be put or deleted on the destination, and has been skipped.
+    Failed {
+        /// The key on the destination.
+        key: Vec<u8>,
+        /// Why the key could not be applied.
+        error: Box<Error>,
+    },
+}
+
+/// The handle of a mirror between two clusters, see [`mirror`].
+///
+/// Dropping the handle stops the mirror in the background, see [`Mirror::stop`] to wait
+/// for it.
+pub struct Mirror {
+    shutdown: CancellationToken,
+    events: mpsc::UnboundedReceiver<MirrorEvent>,
+    result: Option<oneshot::Receiver<Result<()>>>,
+    runner: Option<Task>,
+}
+
+impl Mirror {
+    /// Fetches the next event of the mirror, or `None` once it stopped, see
+    /// [`Mirror::stop`] for why.
+    #[inline]
+    pub async fn message(&mut self) -> Option<MirrorEvent> {
+        self.events.recv().await
+    }
+
+    /// Stops the mirror once the changes being applied are, and returns the error the
+    /// mirror stopped with on its own if any, e.g. because the source revision has been
+    /// compacted before it could be applied.
+    pub async fn stop(mut self) -> Result<()> {
+        self.shutdown.cancel();
+        if let Some(runner) = self.runner.take() {
+            runner.join().await?;
+        }
+        match self.result.take() {
+            Some(result) => result.await.unwrap_or(Ok(())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Mirror {
+    #[inline]
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+/// Mirrors the keys under a prefix from the cluster of `src` to the one of `dst`, like
+/// `etcdctl make-mirror`.
+///
+/// The keys are copied as of a single revision by pages, then the changes made since are
+/// watched and applied, until the mirror is stopped. The changes of a revision are applied
+/// together. A key which can not be applied is reported as a
+/// [`MirrorEvent::Failed`] event and skipped, rather than stopping the mirror.
+///
+/// Only the keys and values are mirrored, not their leases: a key bound to a lease on the
+/// source is not on the destination, and is only deleted there once the source deletes it.
+pub fn mirror(src: &Client, dst: &Client, options: MirrorOptions) -> Mirror {
+    let shutdown = CancellationToken::new();
+    let (tx, events) = mpsc::unbounded_channel();
+    let (result_tx, result) = oneshot::channel();
+    let mut runner = Runner {
+        src: src.kv_client(),
+        watch: src.watch_client(),
+        dst: dst.kv_client(),
+        options,
+        shutdown: shutdown.clone(),
+        tx,
+    };
+    let runner = src.lease_client().tasks().spawn(MIRROR_TASK, async move {
+        let _ = result_tx.send(runner.run().await);
+    });
+    Mirror {
+        shutdown,
+        events,
+        result: Some(result),
+        runner: Some(runner),
+    }
+}
+
+/// What a mirror runs with.
+struct Runner {
+    src: KvClient,
+    watch: WatchClient,
+    dst: KvClient,
+    options: MirrorOptions,
+    shutdown: CancellationToken,
+    tx: mpsc::UnboundedSender<MirrorEvent>,
+}
+
+impl Runner {
+    /// Copies, then applies the changes until shut down.
+    async fn run(&mut self) -> Result<()> {
+        let Some(revision) = self.copy().await? else {
+            return Ok(());
+        };
+        self.tail(revision).await
+    }
+
+    /// Copies the keys as of a single revision, and returns the revision, or `None` if
+    /// shut down meanwhile.
+    async fn copy(&mut self) -> Result<Option<Revision>> {
+        let end = get_prefix(&self.options.src_prefix);
+        let (mut key, mut revision, mut keys) =
+            (self.options.src_prefix.clone(), Revision::current(), 0);
+        loop {
+            let options = GetOptions::new()
+                .with_range(end.clone())
+                .with_revision(revision)
+                .with_limit(self.options.batch_size);
+            let mut resp = self.src.get(key.clone(), Some(options)).await?;
+            if revision.is_current() {
+                revision = resp.header().map_or(revision, |header| header.revision());
+            }
+            let more = resp.more();
+            let kvs = resp.take_kvs();
+            if let Some(last) = kvs.last() {
+                // The next page starts right after the last key.
+                key = [last.key(), b"\0"].concat();
+            }
+            keys += kvs.len() as u64;
+            let ops = kvs
+                .into_iter()
+                .map(|kv| {
+                    let (key, value) = kv.into_key_value();
+                    (self.options.dst_key(&key), Some(value))
+                })
+                .collect();
+            self.apply(ops).await;
+            let _ = self.tx.send(MirrorEvent::Copied { keys });
+
+            if !more {
+                let _ = self.tx.send(MirrorEvent::Synced { keys, revision });
+                return Ok(Some(revision));
+            }
+            if self.shutdown.is_cancelled() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Applies the changes made after `revision` until shut down.
+    async fn tail(&mut self, mut revision: Revision) -> Result<()> {
+        let mut stream: Option<(Watcher, WatchStream)> = None;
+        loop {
+            let Some((_, watch)) = &mut stream else {
+                let options = WatchOptions::new()
+                    .with_prefix()
+                    .with_start_revision(revision + 1);
+                let watch = self
+                    .watch
+                    .watch(self.options.src_prefix.clone(), Some(options));
+                stream = tokio::select! {
+                    _ = self.shutdown.cancelled() => return Ok(()),
+                    watch = watch => match watch {
+                        Ok(watch) => Some(watch),
+                        Err(e) if e.is_retryable() => {
+                            tokio::time::sleep(RELIST_BACKOFF).await;
+                            None
+                        }
+                        Err(e) => return Err(e),
+                    },
+                };
+                continue;
+            };
+            let resp = tokio::select! {
+                _ = self.shutdown.cancelled() => return Ok(()),
+                resp = watch.message() => resp,
+            };
+            let resp = match resp {
+                Ok(Some(resp)) if resp.canceled() => {
+                    // What was deleted meanwhile can not be found by copying again.
+                    return Err(Error::WatchError(format!(
+                        "mirror watch canceled after revision {}, compacted at {}: {}",
+                        revision,
+                        resp.compact_revision(),
+                        resp.cancel_reason()
+                    )));
+                }
+                Ok(Some(resp)) => resp,
+                Ok(None) => {
+                    stream = None;
+                    continue;
+                }
+                Err(e) if e.is_retryable() => {
+                    stream = None;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            // The changes of a revision, i.e. of a txn on the source, are applied together.
+            let mut ops = Vec::new();
+            for event in resp.events() {
+                let Some(kv) = event.kv() else {
+                    continue;
+                };
+                if kv.mod_revision() != revision && !ops.is_empty() {
+                    self.apply(std::mem::take(&mut ops)).await;
+                }
+                revision = kv.mod_revision();
+                let value = match event.event_type() {
+                    EventType::Put => Some(kv.value().to_vec()),
+                    EventType::Delete => None,
+                };
+                ops.push((self.options.dst_key(kv.key()), value));
+            }
+            if ops.is_empty() {
+                continue;
+            }
+            self.apply(ops).await;
+            let lag = resp
+                .header()
+                .map_or(0, |header| header.revision() - revision)
+                .max(0);
+            let _ = self.tx.send(MirrorEvent::Applied { revision, lag });
+        }
+    }
+
+    /// Puts, or deletes if without a value, the keys `ops` on the destination, by txns of
+    /// [`MIRROR_TXN_OPS`] operations, one by one if a txn fails to report the keys failing.
+    async fn apply(&mut self, ops: Vec<(Vec<u8>, Option<Vec<u8>>)>) {
+        for chunk in ops.chunks(MIRROR_TXN_OPS) {
+            let txn_ops: Vec<_> = chunk
+                .iter()
+                .map(|(key, value)| txn_op(key, value))
+                .collect();
+            if self.dst.txn(Txn::new().and_then(txn_ops)).await.is_ok() {
+                continue;
+            }
+            for (key, value) in chunk {
+                let result = match value {
+                    Some(value) => self
+                        .dst
+                        .put(key.clone(), value.clone(), None)
+                        .await
+                        .map(drop),
+                    None => self.dst.delete(key.clone(), None).await.map(drop),
+                };
+                if let Err(e) = result {
+                    let _ = self.tx.send(MirrorEvent::Failed {
+                        key: key.clone(),
+                        error: Box::new(e),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// The operation putting `value` to `key`, or deleting `key` if without a value.
+fn txn_op(key: &[u8], value: &Option<Vec<u8>>) -> TxnOp {
+    match value {
+        Some(value) => TxnOp::put(key, value.clone(), None),
+        None => TxnOp::delete(key, None),
+    }
+}
@@ -0,0 +1,132 @@
+//! Distributed synchronization recipes built on top of the etcd primitives, like the
+//! `recipes` package of the Go client.
+
+mod barrier;
+mod cache;
+mod double_barrier;
+mod expiry_monitor;
+#[cfg(feature = "election")]
+mod leader_task;
+mod mirror;
+mod prefix_watch;
+mod priority_queue;
+mod queue;
+mod rw_lock;
+mod semaphore;
+mod service_registry;
+
+pub use barrier::Barrier;
+pub use cache::{Cache, CacheEvent, CacheOptions, CacheSubscriber, DEFAULT_CACHE_EVENT_CAPACITY};
+pub use double_barrier::DoubleBarrier;
+pub use expiry_monitor::{expiry_monitor, ExpiryEvent, ExpiryMonitor};
+#[cfg(feature = "election")]
+#[cfg_attr(docsrs, doc(cfg(feature = "election")))]
+pub use leader_task::{leader_task, LeaderTask, LeaderTaskEvent, LeadershipContext};
+pub use mirror::{mirror, Mirror, MirrorEvent, MirrorOptions, DEFAULT_MIRROR_BATCH_SIZE};
+pub use priority_queue::PriorityQueue;
+pub use queue::Queue;
+pub use rw_lock::{RwLock, RwLockGuard};
+pub use semaphore::Semaphore;
+pub use service_registry::{
+    Registration, ServiceEvent, ServiceInstance, ServiceRegistry, ServiceWatch,
+};
+
+use crate::error::{Error, Result};
+use crate::rpc::kv::{
+    Compare, CompareOp, GetOptions, GetResponse, KvClient, PutOptions, SortOrder, SortTarget, Txn,
+    TxnOp,
+};
+use crate::rpc::watch::{WatchClient, WatchFilterType, WatchOptions};
+use crate::rpc::KeyValue;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The revision after the one `resp` was served at, to watch from so that no change made
+/// between a get and a watch is missed.
+#[inline]
+fn next_revision(resp: &GetResponse) -> i64 {
+    resp.header().map_or(0, |header| header.revision().get()) + 1
+}
+
+/// Waits for the first event of the watch of `key` with `options`, returning `false` if
+/// the watch was canceled, e.g. because its start revision has been compacted meanwhile.
+async fn wait_event(
+    watch: &mut WatchClient,
+    key: impl Into<Vec<u8>>,
+    options: WatchOptions,
+) -> Result<bool> {
+    let (_watcher, mut stream) = watch.watch(key, Some(options)).await?;
+    while let Some(resp) = stream.message().await? {
+        if !resp.events().is_empty() {
+            return Ok(true);
+        }
+        if resp.canceled() {
+            return Ok(false);
+        }
+    }
+    Err(Error::WatchError(String::from("watch stream closed")))
+}
+
+/// Puts `value` under a new key made of `prefix` and the current time in nanoseconds,
+/// retrying with another time if the key exists, and returns the key and its create
+/// revision.
+///
+/// The keys are unique but not ordered across clients, whose clocks differ: the recipes
+/// order them by their create revision.
+async fn put_unique(
+    kv: &mut KvClient,
+    prefix: &[u8],
+    value: Vec<u8>,
+    options: Option<PutOptions>,
+) -> Result<(Vec<u8>, i64)> {
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+        let key = [prefix, format!("{:020}", nanos).as_bytes()].concat();
+        let txn = Txn::new()
+            .when([Compare::version(key.clone(), CompareOp::Equal, 0)])
+            .and_then([TxnOp::put(key.clone(), value.clone(), options.clone())]);
+        let resp = kv.txn(txn).await?;
+        if resp.succeeded() {
+            return Ok((
+                key,
+                resp.header().map_or(0, |header| header.revision().get()),
+            ));
+        }
+    }
+}
+
+/// The key-value created first under `prefix`, if any, and the revision after the one it
+/// was read at.
+async fn first_created(kv: &mut KvClient, prefix: Vec<u8>) -> Result<(Option<KeyValue>, i64)> {
+    let options = GetOptions::new()
+        .with_prefix()
+        .with_sort(SortTarget::Create, SortOrder::Ascend)
+        .with_limit(1);
+    let mut resp = kv.get(prefix, Some(options)).await?;
+    let revision = next_revision(&resp);
+    Ok((resp.take_kvs().into_iter().next(), revision))
+}
+
+/// Claims `item` by deleting it, unless it was modified meanwhile, e.g. deleted by another
+/// consumer claiming it first.
+async fn claim(kv: &mut KvClient, item: &KeyValue) -> Result<bool> {
+    let txn = Txn::new()
+        .when([Compare::mod_revision(
+            item.key(),
+            CompareOp::Equal,
+            item.mod_revision(),
+        )])
+        .and_then([TxnOp::delete(item.key(), None)]);
+    Ok(kv.txn(txn).await?.succeeded())
+}
+
+/// Waits for a key to be put under `prefix` from `revision` on.
+async fn wait_put(watch: &mut WatchClient, prefix: Vec<u8>, revision: i64) -> Result<()> {
+    let options = WatchOptions::new()
+        .with_prefix()
+        .with_start_revision(revision)
+        .with_filters([WatchFilterType::NoDelete]);
+    wait_event(watch, prefix, options).await?;
+    Ok(())
+}
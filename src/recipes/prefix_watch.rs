@@ -0,0 +1,142 @@
+//! A watch of a prefix surviving reconnects and compactions, shared by the recipes
+//! mirroring a prefix.
+
+use crate::error::Result;
+use crate::rpc::kv::{GetOptions, KvClient};
+use crate::rpc::watch::{WatchClient, WatchOptions, WatchResponse, WatchStream, Watcher};
+use crate::rpc::KeyValue;
+use std::time::Duration;
+
+/// The delay before listing or watching the prefix again after failing to, e.g. while the
+/// cluster is unreachable.
+pub(super) const RELIST_BACKOFF: Duration = Duration::from_secs(1);
+
+/// What happened to a watched prefix, see [`PrefixWatch::next`].
+pub(super) enum PrefixUpdate {
+    /// The prefix has been listed, the key-values replace all the ones known so far.
+    Listed(Vec<KeyValue>),
+    /// The prefix changed.
+    Changed(WatchResponse),
+}
+
+/// A watch of a prefix, listing it first, then watching it from the revision it was
+/// listed at.
+///
+/// A broken watch is resumed from the revision after the last one seen, and the prefix is
+/// listed again if the revision has been compacted meanwhile.
+pub(super) struct PrefixWatch {
+    kv: KvClient,
+    watch: WatchClient,
+    prefix: Vec<u8>,
+    /// The revision the prefix is known up to, `0` until listed.
+    revision: i64,
+    stream: Option<(Watcher, WatchStream)>,
+}
+
+impl PrefixWatch {
+    /// Creates the watch of `prefix`, which is listed by the first [`PrefixWatch::next`].
+    pub(super) fn new(kv: KvClient, watch: WatchClient, prefix: Vec<u8>) -> Self {
+        Self {
+            kv,
+            watch,
+            prefix,
+            revision: 0,
+            stream: None,
+        }
+    }
+
+    /// The watched prefix.
+    #[inline]
+    pub(super) fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// The revision the prefix is known up to, `0` until listed.
+    #[inline]
+    pub(super) fn revision(&self) -> i64 {
+        self.revision
+    }
+
+    /// Fetches the next listing or change of the prefix.
+    ///
+    /// Errors while the cluster is unreachable are retried, other errors are returned, the
+    /// prefix being listed again by the next call.
+    pub(super) async fn next(&mut self) -> Result<PrefixUpdate> {
+        loop {
+            let Some((_, stream)) = &mut self.stream else {
+                if self.revision > 0 && self.resume().await? {
+                    continue;
+                }
+                return self.list().await.map(PrefixUpdate::Listed);
+            };
+            match stream.message().await {
+                Ok(Some(resp)) if resp.canceled() => {
+                    // The revision is compacted, or the watch is canceled by the server:
+                    // what was missed is found by listing again.
+                    self.stream = None;
+                    self.revision = 0;
+                }
+                Ok(Some(resp)) => {
+                    if let Some(kv) = resp.events().last().and_then(|event| event.kv()) {
+                        self.revision = kv.mod_revision().get();
+                        return Ok(PrefixUpdate::Changed(resp));
+                    }
+                    // Only a progress notification tells that every event up to its revision
+                    // has been sent, the header of the others may be ahead of their events.
+                    if !resp.created() {
+                        if let Some(header) = resp.header() {
+                            self.revision = self.revision.max(header.revision().get());
+                        }
+                    }
+                }
+                Ok(None) => self.stream = None,
+                Err(e) => {
+                    self.stream = None;
+                    if !e.is_retryable() {
+                        self.revision = 0;
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Watches the prefix from the revision after the last one seen, and returns whether it
+    /// did, or `false` if the revision has been compacted.
+    async fn resume(&mut self) -> Result<bool> {
+        loop {
+            let options = WatchOptions::new()
+                .with_prefix()
+                .with_start_revision(self.revision + 1);
+            match self.watch.watch(self.prefix.clone(), Some(options)).await {
+                Ok(stream) => {
+                    self.stream = Some(stream);
+                    return Ok(true);
+                }
+                Err(e) if e.is_compacted() => return Ok(false),
+                Err(e) if e.is_retryable() => tokio::time::sleep(RELIST_BACKOFF).await,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Lists the prefix, and watches it from then on.
+    async fn list(&mut self) -> Result<Vec<KeyValue>> {
+        loop {
+            let options = GetOptions::new().with_prefix();
+            let mut resp = match self.kv.get(self.prefix.clone(), Some(options)).await {
+                Ok(resp) => resp,
+                Err(e) if e.is_retryable() => {
+                    tokio::time::sleep(RELIST_BACKOFF).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            self.revision = resp.header().map_or(0, |header| header.revision().get());
+            // Compacted right after the listing, which is then listed again.
+            if self.resume().await? {
+                return Ok(resp.take_kvs());
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! A queue of values popped by priority.
+
+use super::{claim, first_created, next_revision, put_unique, wait_put};
+use crate::error::Result;
+use crate::rpc::kv::{GetOptions, KvClient, SortOrder, SortTarget};
+use crate::rpc::watch::WatchClient;
+use crate::rpc::KeyValue;
+use crate::Client;
+
+/// The length of a priority in keys, i.e. of the largest `u16` in decimal.
+const PRIORITY_LEN: usize = 5;
+
+/// A queue of values under a prefix, popped by priority, lower priorities first.
+///
+/// Every value is put under a key of its own below `{prefix}/{priority}/`, the priority
+/// zero-padded for the keys to sort by it. The values of the lowest priority are popped
+/// first, the ones of equal priority in the order their pushes were applied, like in a
+/// [`Queue`](super::Queue). Every value is popped by exactly one consumer.
+///
+/// Values of a priority are only popped once no value of a lower priority is left: they
+/// starve as long as values of lower priorities are pushed at least as fast as they are
+/// popped.
+#[derive(Clone)]
+pub struct PriorityQueue {
+    kv: KvClient,
+    watch: WatchClient,
+    prefix: Vec<u8>,
+}
+
+impl PriorityQueue {
+    /// Creates a priority queue under `prefix`.
+    #[inline]
+    pub fn new(client: &Client, prefix: impl Into<Vec<u8>>) -> Self {
+        let mut prefix = prefix.into();
+        prefix.push(b'/');
+        Self {
+            kv: client.kv_client(),
+            watch: client.watch_client(),
+            prefix,
+        }
+    }
+
+    /// Pushes `value` with the priority `priority`, popped before the values of higher
+    /// priorities.
+    #[inline]
+    pub async fn push(&mut self, value: impl Into<Vec<u8>>, priority: u16) -> Result<()> {
+        let prefix = [
+            self.prefix.as_slice(),
+            format!("{:0width$}/", priority, width = PRIORITY_LEN).as_bytes(),
+        ]
+        .concat();
+        put_unique(&mut self.kv, &prefix, value.into(), None).await?;
+        Ok(())
+    }
+
+    /// Pops the value of the lowest priority pushed first, waiting for one to be pushed if
+    /// the queue is empty.
+    pub async fn pop(&mut self) -> Result<Vec<u8>> {
+        loop {
+            match self.front().await? {
+                (Some(front), _) => {
+                    if claim(&mut self.kv, &front).await? {
+                        return Ok(front.into_key_value().1);
+                    }
+                }
+                (None, revision) => {
+                    wait_put(&mut self.watch, self.prefix.clone(), revision).await?;
+                }
+            }
+        }
+    }
+
+    /// Pops the value of the lowest priority pushed first, or returns `None` if the queue
+    /// is empty.
+    pub async fn try_pop(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            let Some(front) = self.front().await?.0 else {
+                return Ok(None);
+            };
+            if claim(&mut self.kv, &front).await? {
+                return Ok(Some(front.into_key_value().1));
+            }
+        }
+    }
+
+    /// Returns the value of the lowest priority pushed first without popping it, or `None`
+    /// if the queue is empty.
+    #[inline]
+    pub async fn peek(&mut self) -> Result<Option<Vec<u8>>> {
+        let (front, _) = self.front().await?;
+        Ok(front.map(|kv| kv.into_key_value().1))
+    }
+
+    /// The key-value of the lowest priority created first, if any, and the revision after
+    /// the one the queue was found empty at.
+    async fn front(&mut self) -> Result<(Option<KeyValue>, i64)> {
+        loop {
+            // The first key is of the lowest priority, although not necessarily the first
+            // created of it, the keys of a priority are not ordered across clients.
+            let options = GetOptions::new()
+                .with_prefix()
+                .with_sort(SortTarget::Key, SortOrder::Ascend)
+                .with_keys_only()
+                .with_limit(1);
+            let resp = self.kv.get(self.prefix.clone(), Some(options)).await?;
+            let Some(first) = resp.kvs().first() else {
+                return Ok((None, next_revision(&resp)));
+            };
+            let len = first.key().len().min(self.prefix.len() + PRIORITY_LEN + 1);
+            let priority = first.key()[..len].to_vec();
+            // Everything of the priority may have been popped meanwhile.
+            if let (Some(front), revision) = first_created(&mut self.kv, priority).await? {
+                return Ok((Some(front), revision));
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! A FIFO queue of values.
+
+use super::{claim, first_created, put_unique, wait_put};
+use crate::error::Result;
+use crate::rpc::kv::KvClient;
+use crate::rpc::watch::WatchClient;
+use crate::Client;
+
+/// A FIFO queue of values under a prefix, shared by producers and consumers.
+///
+/// Every value is put under a key of its own below `{prefix}/`, and the values are
+/// consumed in the order of the create revisions of their keys, i.e. in the order their
+/// pushes were applied, even when pushed by clients on different machines. Every value is
+/// popped by exactly one consumer.
+#[derive(Clone)]
+pub struct Queue {
+    kv: KvClient,
+    watch: WatchClient,
+    prefix: Vec<u8>,
+}
+
+impl Queue {
+    /// Creates a queue under `prefix`.
+    #[inline]
+    pub fn new(client: &Client, prefix: impl Into<Vec<u8>>) -> Self {
+        let mut prefix = prefix.into();
+        prefix.push(b'/');
+        Self {
+            kv: client.kv_client(),
+            watch: client.watch_client(),
+            prefix,
+        }
+    }
+
+    /// Pushes `value` at the back of the queue.
+    #[inline]
+    pub async fn push(&mut self, value: impl Into<Vec<u8>>) -> Result<()> {
+        put_unique(&mut self.kv, &self.prefix, value.into(), None).await?;
+        Ok(())
+    }
+
+    /// Pops the value at the front of the queue, waiting for one to be pushed if the queue
+    /// is empty.
+    pub async fn pop(&mut self) -> Result<Vec<u8>> {
+        loop {
+            match first_created(&mut self.kv, self.prefix.clone()).await? {
+                (Some(front), _) => {
+                    if claim(&mut self.kv, &front).await? {
+                        return Ok(front.into_key_value().1);
+                    }
+                }
+                (None, revision) => {
+                    wait_put(&mut self.watch, self.prefix.clone(), revision).await?;
+                }
+            }
+        }
+    }
+
+    /// Pops the value at the front of the queue, or returns `None` if the queue is empty.
+    pub async fn try_pop(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            let Some(front) = first_created(&mut self.kv, self.prefix.clone()).await?.0 else {
+                return Ok(None);
+            };
+            if claim(&mut self.kv, &front).await? {
+                return Ok(Some(front.into_key_value().1));
+            }
+        }
+    }
+
+    /// Returns the value at the front of the queue without popping it, or `None` if the
+    /// queue is empty.
+    #[inline]
+    pub async fn peek(&mut self) -> Result<Option<Vec<u8>>> {
+        let (front, _) = first_created(&mut self.kv, self.prefix.clone()).await?;
+        Ok(front.map(|kv| kv.into_key_value().1))
+    }
+}
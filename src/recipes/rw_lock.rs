@@ -0,0 +1,148 @@
+//! A read-write lock, held by many readers or a single writer.
+
+use super::{next_revision, put_unique, wait_event};
+use crate::error::Result;
+use crate::ids::LeaseId;
+use crate::rpc::kv::{GetOptions, KvClient, PutOptions, SortOrder, SortTarget};
+use crate::rpc::watch::{WatchClient, WatchFilterType, WatchOptions};
+use crate::session::Session;
+use crate::Client;
+use std::future::Future;
+
+/// A read-write lock under a prefix, held by any number of readers or by a single writer.
+///
+/// Readers put a key of their own under `{prefix}/read/`, writers under
+/// `{prefix}/write/`, bound to the lease of their session. The lock is granted in the
+/// order the keys were created: a reader waits for the writers before it, a writer for
+/// everyone before it, so that neither readers nor writers starve. Every contender only
+/// watches the key it waits for, the last one before it, rather than waking up on any
+/// change. A holder which crashes releases the lock once its lease expires.
+#[derive(Clone)]
+pub struct RwLock {
+    kv: KvClient,
+    watch: WatchClient,
+    prefix: Vec<u8>,
+}
+
+impl RwLock {
+    /// Creates a read-write lock under `prefix`.
+    #[inline]
+    pub fn new(client: &Client, prefix: impl Into<Vec<u8>>) -> Self {
+        let mut prefix = prefix.into();
+        prefix.push(b'/');
+        Self {
+            kv: client.kv_client(),
+            watch: client.watch_client(),
+            prefix,
+        }
+    }
+
+    /// Locks for reading with the lease of `session`, waiting for the writers which
+    /// contended before.
+    #[inline]
+    pub fn read<'a>(
+        &'a mut self,
+        session: &Session,
+    ) -> impl Future<Output = Result<RwLockGuard>> + Send + 'a {
+        // Only the lease is kept by the future, which would not be `Send` with the session.
+        let lease = session.check().map(|()| session.lease_id());
+        async move { self.lock(lease?, false).await }
+    }
+
+    /// Locks for writing with the lease of `session`, waiting for the readers and writers
+    /// which contended before.
+    #[inline]
+    pub fn write<'a>(
+        &'a mut self,
+        session: &Session,
+    ) -> impl Future<Output = Result<RwLockGuard>> + Send + 'a {
+        let lease = session.check().map(|()| session.lease_id());
+        async move { self.lock(lease?, true).await }
+    }
+
+    /// Locks with the lease `lease`, for writing if `write`.
+    async fn lock(&mut self, lease: LeaseId, write: bool) -> Result<RwLockGuard> {
+        let kind: &[u8] = if write { b"write/" } else { b"read/" };
+        let options = PutOptions::new().with_lease(lease);
+        let prefix = [self.prefix.as_slice(), kind].concat();
+        let (key, revision) = put_unique(&mut self.kv, &prefix, Vec::new(), Some(options)).await?;
+        // Made right away to delete the key if failing or cancelled while waiting.
+        let guard = RwLockGuard {
+            kv: self.kv.clone(),
+            key: Some(key),
+            write,
+        };
+
+        let blockers = if write {
+            self.prefix.clone()
+        } else {
+            [self.prefix.as_slice(), b"write/"].concat()
+        };
+        loop {
+            let options = GetOptions::new()
+                .with_prefix()
+                .with_max_create_revision(revision - 1)
+                .with_sort(SortTarget::Create, SortOrder::Descend)
+                .with_keys_only()
+                .with_limit(1);
+            let resp = self.kv.get(blockers.clone(), Some(options)).await?;
+            let Some(last) = resp.kvs().first() else {
+                return Ok(guard);
+            };
+            // The ones before the last have been waited for by it already, or are readers
+            // holding the lock alongside it.
+            let options = WatchOptions::new()
+                .with_start_revision(next_revision(&resp))
+                .with_filters([WatchFilterType::NoPut]);
+            wait_event(&mut self.watch, last.key(), options).await?;
+        }
+    }
+}
+
+/// A held read-write lock, see [`RwLock::read`] and [`RwLock::write`].
+///
+/// Dropping the guard releases the lock in the background, use [`RwLockGuard::unlock`]
+/// to observe the result.
+pub struct RwLockGuard {
+    kv: KvClient,
+    key: Option<Vec<u8>>,
+    write: bool,
+}
+
+impl RwLockGuard {
+    /// The key that exists on etcd for as long as the lock is held.
+    #[inline]
+    pub fn key(&self) -> &[u8] {
+        // The key is only taken when the guard is consumed.
+        self.key.as_deref().unwrap()
+    }
+
+    /// Whether the lock is held for writing.
+    #[inline]
+    pub const fn is_write(&self) -> bool {
+        self.write
+    }
+
+    /// Releases the lock.
+    pub async fn unlock(mut self) -> Result<()> {
+        let key = self.key.take().unwrap();
+        self.kv.delete(key, None).await?;
+        Ok(())
+    }
+}
+
+impl Drop for RwLockGuard {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let mut kv = self.kv.clone();
+        handle.spawn(async move {
+            let _ = kv.delete(key, None).await;
+        });
+    }
+}
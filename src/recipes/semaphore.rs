@@ -0,0 +1,188 @@
+//! A counting semaphore, for at most a number of holders to hold it together.
+
+use super::{next_revision, put_unique, wait_event};
+use crate::error::{Error, Result};
+use crate::ids::LeaseId;
+use crate::rpc::kv::{GetOptions, KvClient, PutOptions, SortOrder, SortTarget};
+use crate::rpc::watch::{WatchClient, WatchFilterType, WatchOptions};
+use crate::session::Session;
+use crate::Client;
+use std::future::Future;
+use std::time::Duration;
+
+/// A counting semaphore under a prefix, held by at most `permits` holders at a time.
+///
+/// Every contender puts a key of its own under the prefix, bound to the lease of its
+/// session, and holds the semaphore once its key is among the `permits` keys created
+/// first. Holders are served in the order their keys were created, and a holder which
+/// crashes releases the semaphore once its lease expires.
+#[derive(Clone)]
+pub struct Semaphore {
+    kv: KvClient,
+    watch: WatchClient,
+    prefix: Vec<u8>,
+    permits: usize,
+    /// The key of the contender, once it acquired the semaphore.
+    key: Option<Vec<u8>>,
+}
+
+impl Semaphore {
+    /// Creates a semaphore under `prefix` with `permits` permits.
+    #[inline]
+    pub fn new(client: &Client, prefix: impl Into<Vec<u8>>, permits: usize) -> Self {
+        let mut prefix = prefix.into();
+        prefix.push(b'/');
+        Self {
+            kv: client.kv_client(),
+            watch: client.watch_client(),
+            prefix,
+            permits,
+            key: None,
+        }
+    }
+
+    /// The key of the holder, once it acquired the semaphore.
+    #[inline]
+    pub fn key(&self) -> Option<&[u8]> {
+        self.key.as_deref()
+    }
+
+    /// Acquires the semaphore with the lease of `session`, waiting until fewer than
+    /// `permits` holders created before it are left.
+    ///
+    /// Fails with an [`Error::InvalidArgs`] if the semaphore is already acquired by this
+    /// `Semaphore`, or with an [`Error::LeaseKeepAliveError`] if its key is deleted while
+    /// waiting, e.g. because the lease expired.
+    pub fn acquire<'a>(
+        &'a mut self,
+        session: &Session,
+    ) -> impl Future<Output = Result<()>> + Send + 'a {
+        // Only the lease is kept by the future, which would not be `Send` with the session.
+        let lease = session.check().map(|()| session.lease_id());
+        async move {
+            let key = self.put_key(lease?).await?;
+            match self.wait(&key).await {
+                Ok(()) => {
+                    self.key = Some(key);
+                    Ok(())
+                }
+                Err(e) => {
+                    let _ = self.kv.delete(key, None).await;
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// Acquires the semaphore with the lease of `session` if fewer than `permits` holders
+    /// hold it, and returns whether it did, see [`Semaphore::acquire`].
+    pub fn try_acquire<'a>(
+        &'a mut self,
+        session: &Session,
+    ) -> impl Future<Output = Result<bool>> + Send + 'a {
+        let lease = session.check().map(|()| session.lease_id());
+        async move {
+            let key = self.put_key(lease?).await?;
+            let acquired = match self.rank(&key).await {
+                Ok((Some(rank), _)) => rank < self.permits,
+                Ok((None, _)) => false,
+                Err(e) => {
+                    let _ = self.kv.delete(key, None).await;
+                    return Err(e);
+                }
+            };
+            if acquired {
+                self.key = Some(key);
+            } else {
+                self.kv.delete(key, None).await?;
+            }
+            Ok(acquired)
+        }
+    }
+
+    /// Acquires the semaphore with the lease of `session`, waiting for up to `timeout`, and
+    /// returns whether it did, see [`Semaphore::acquire`].
+    ///
+    /// The key of the contender is put before the timeout starts, and deleted when giving
+    /// up, so that a contender which timed out never holds a permit unknowingly.
+    pub fn acquire_timeout<'a>(
+        &'a mut self,
+        session: &Session,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<bool>> + Send + 'a {
+        let lease = session.check().map(|()| session.lease_id());
+        async move {
+            let key = self.put_key(lease?).await?;
+            let result = match tokio::time::timeout(timeout, self.wait(&key)).await {
+                Ok(Ok(())) => {
+                    self.key = Some(key);
+                    return Ok(true);
+                }
+                Ok(Err(e)) => Err(e),
+                Err(_elapsed) => Ok(false),
+            };
+            // Giving up after being granted a permit in the meantime releases it as well.
+            self.kv.delete(key, None).await?;
+            result
+        }
+    }
+
+    /// Releases the semaphore.
+    ///
+    /// Fails with an [`Error::InvalidArgs`] if the semaphore was not acquired.
+    pub async fn release(&mut self) -> Result<()> {
+        let Some(key) = self.key.take() else {
+            return Err(Error::InvalidArgs(String::from(
+                "the semaphore was not acquired",
+            )));
+        };
+        self.kv.delete(key, None).await?;
+        Ok(())
+    }
+
+    /// Puts a new key of the contender bound to the lease `lease`.
+    async fn put_key(&mut self, lease: LeaseId) -> Result<Vec<u8>> {
+        if self.key.is_some() {
+            return Err(Error::InvalidArgs(String::from(
+                "the semaphore is already acquired",
+            )));
+        }
+        let options = PutOptions::new().with_lease(lease);
+        let (key, _) = put_unique(&mut self.kv, &self.prefix, Vec::new(), Some(options)).await?;
+        Ok(key)
+    }
+
+    /// Waits until `key` is among the `permits` keys created first.
+    async fn wait(&mut self, key: &[u8]) -> Result<()> {
+        loop {
+            let (rank, revision) = self.rank(key).await?;
+            match rank {
+                Some(rank) if rank < self.permits => return Ok(()),
+                Some(_) => {}
+                None => {
+                    return Err(Error::LeaseKeepAliveError(String::from(
+                        "the semaphore key is deleted, e.g. its lease expired",
+                    )))
+                }
+            }
+            // Only a holder leaving frees a permit.
+            let options = WatchOptions::new()
+                .with_prefix()
+                .with_start_revision(revision)
+                .with_filters([WatchFilterType::NoPut]);
+            wait_event(&mut self.watch, self.prefix.clone(), options).await?;
+        }
+    }
+
+    /// The number of keys created before `key`, or `None` if `key` does not exist, and the
+    /// revision after the one they were read at.
+    async fn rank(&mut self, key: &[u8]) -> Result<(Option<usize>, i64)> {
+        let options = GetOptions::new()
+            .with_prefix()
+            .with_sort(SortTarget::Create, SortOrder::Ascend)
+            .with_keys_only();
+        let resp = self.kv.get(self.prefix.clone(), Some(options)).await?;
+        let rank = resp.kvs().iter().position(|kv| kv.key() == key);
+        Ok((rank, next_revision(&resp)))
+    }
+}
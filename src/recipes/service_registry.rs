@@ -0,0 +1,334 @@
+//! A registry of service instances, registered with leases and discovered by watching.
+
+use super::prefix_watch::{PrefixUpdate, PrefixWatch};
+use crate::bytes::DebugBytes;
+use crate::error::{Error, Result};
+use crate::ids::LeaseId;
+use crate::rpc::kv::{KvClient, PutOptions};
+use crate::rpc::lease::LeaseClient;
+use crate::rpc::watch::{EventType, WatchClient};
+use crate::session::{Session, SessionOptions};
+use crate::Client;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Debug, Formatter};
+
+/// A registry of service instances under a prefix.
+///
+/// Every instance is put under `{prefix}/{service}/{instance_id}` with its metadata as
+/// value, bound to a lease kept alive for as long as it is registered: an instance which
+/// crashes is deregistered once its lease expires. The metadata is opaque to the registry,
+/// e.g. serialized with `serde_json`.
+#[derive(Clone)]
+pub struct ServiceRegistry {
+    kv: KvClient,
+    watch: WatchClient,
+    lease: LeaseClient,
+    prefix: Vec<u8>,
+}
+
+impl ServiceRegistry {
+    /// Creates a service registry under `prefix`.
+    #[inline]
+    pub fn new(client: &Client, prefix: impl Into<Vec<u8>>) -> Self {
+        let mut prefix = prefix.into();
+        prefix.push(b'/');
+        Self {
+            kv: client.kv_client(),
+            watch: client.watch_client(),
+            lease: client.lease_client(),
+            prefix,
+        }
+    }
+
+    /// The prefix of the instances of `service`.
+    fn service_prefix(&self, service: &[u8]) -> Result<Vec<u8>> {
+        check_name("service", service)?;
+        Ok([self.prefix.as_slice(), service, b"/"].concat())
+    }
+
+    /// Registers the instance `instance_id` of `service` with `metadata`, bound to a lease
+    /// of `ttl` seconds kept alive until the returned [`Registration`] is closed or dropped.
+    ///
+    /// Registering an instance again replaces its metadata and lease. Fails with an
+    /// [`Error::InvalidArgs`] if `service` or `instance_id` is empty or contains a `/`.
+    pub async fn register(
+        &mut self,
+        service: impl Into<Vec<u8>>,
+        instance_id: impl Into<Vec<u8>>,
+        metadata: impl Into<Vec<u8>>,
+        ttl: i64,
+    ) -> Result<Registration> {
+        let instance_id = instance_id.into();
+        check_name("instance id", &instance_id)?;
+        let key = [self.service_prefix(&service.into())?, instance_id].concat();
+
+        let options = SessionOptions::new().with_ttl(ttl);
+        let session = Session::new(self.lease.clone(), Some(options)).await?;
+        let options = PutOptions::new().with_lease(session.lease_id());
+        if let Err(e) = self.kv.put(key.clone(), metadata, Some(options)).await {
+            let _ = session.close().await;
+            return Err(e);
+        }
+        Ok(Registration {
+            key,
+            session: Some(session),
+        })
+    }
+
+    /// Discovers the instances of `service`: the returned [`ServiceWatch`] yields all the
+    /// registered instances first, then the changes to them.
+    ///
+    /// Fails with an [`Error::InvalidArgs`] if `service` is empty or contains a `/`.
+    pub async fn discover(&mut self, service: impl Into<Vec<u8>>) -> Result<ServiceWatch> {
+        let prefix = self.service_prefix(&service.into())?;
+        let mut watch = ServiceWatch {
+            watch: PrefixWatch::new(self.kv.clone(), self.watch.clone(), prefix),
+            instances: None,
+            events: VecDeque::new(),
+        };
+        let update = watch.watch.next().await?;
+        watch.apply(update);
+        Ok(watch)
+    }
+}
+
+/// Fails if `name` is not a valid segment of the keys of a registry.
+fn check_name(what: &str, name: &[u8]) -> Result<()> {
+    if name.is_empty() || name.contains(&b'/') {
+        return Err(Error::InvalidArgs(format!(
+            "{} {:?} is empty or contains a '/'",
+            what,
+            DebugBytes(name)
+        )));
+    }
+    Ok(())
+}
+
+/// The registration of a service instance, see [`ServiceRegistry::register`].
+///
+/// Dropping the registration deregisters the instance in the background, see
+/// [`Registration::close`] to wait for it.
+pub struct Registration {
+    key: Vec<u8>,
+    session: Option<Session>,
+}
+
+impl Registration {
+    /// The key the instance is registered under.
+    #[inline]
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// The lease the instance is registered with.
+    #[inline]
+    pub fn lease_id(&self) -> LeaseId {
+        self.session
+            .as_ref()
+            .map_or(LeaseId::none(), Session::lease_id)
+    }
+
+    /// Returns `true` if the lease is no longer being kept alive, the instance is
+    /// deregistered once it expires.
+    #[inline]
+    pub fn is_lost(&self) -> bool {
+        self.session.as_ref().map_or(true, Session::is_done)
+    }
+
+    /// Deregisters the instance by revoking its lease.
+    pub async fn close(mut self) -> Result<()> {
+        match self.session.take() {
+            Some(session) => session.close().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let Some(session) = self.session.take() else {
+            return;
+        };
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        handle.spawn(async move {
+            let _ = session.close().await;
+        });
+    }
+}
+
+/// A registered service instance.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ServiceInstance {
+    id: Vec<u8>,
+    metadata: Vec<u8>,
+}
+
+impl ServiceInstance {
+    /// The ID of the instance.
+    #[inline]
+    pub fn id(&self) -> &[u8] {
+        &self.id
+    }
+
+    /// The ID of the instance in string.
+    #[inline]
+    pub fn id_str(&self) -> Result<&str> {
+        std::str::from_utf8(&self.id).map_err(From::from)
+    }
+
+    /// The metadata the instance is registered with.
+    #[inline]
+    pub fn metadata(&self) -> &[u8] {
+        &self.metadata
+    }
+
+    /// Converts the instance into its ID and metadata.
+    #[inline]
+    pub fn into_parts(self) -> (Vec<u8>, Vec<u8>) {
+        (self.id, self.metadata)
+    }
+}
+
+impl Debug for ServiceInstance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceInstance")
+            .field("id", &DebugBytes(&self.id))
+            .field("metadata", &DebugBytes(&self.metadata))
+            .finish()
+    }
+}
+
+/// A change to the instances of a service, see [`ServiceWatch::message`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceEvent {
+    /// All the instances registered when the discovery started, always the first event.
+    Instances(Vec<ServiceInstance>),
+    /// An instance has been registered.
+    Added(ServiceInstance),
+    /// An instance has been registered again with another metadata.
+    Updated(ServiceInstance),
+    /// An instance has been deregistered, with the metadata it was registered with.
+    Removed(ServiceInstance),
+}
+
+/// The instances of a service and their changes, see [`ServiceRegistry::discover`].
+///
+/// The instances are listed, then watched from the revision they were listed at. The
+/// watch survives reconnects, resuming from the last revision seen, and compactions: the
+/// instances are listed again, and the differences with the ones known so far are yielded
+/// as changes.
+pub struct ServiceWatch {
+    watch: PrefixWatch,
+    /// The instances by ID, once listed.
+    instances: Option<BTreeMap<Vec<u8>, Vec<u8>>>,
+    /// The changes not yielded yet.
+    events: VecDeque<ServiceEvent>,
+}
+
+impl ServiceWatch {
+    /// The instances registered as of the last change yielded, or listed.
+    #[inline]
+    pub fn instances(&self) -> impl Iterator<Item = ServiceInstance> + '_ {
+        self.instances
+            .iter()
+            .flatten()
+            .map(|(id, metadata)| instance(id, metadata))
+    }
+
+    /// Fetches the next change to the instances, waiting for one if none is pending.
+    ///
+    /// Errors while the cluster is unreachable are retried, other errors are returned, the
+    /// instances being listed again by the next call.
+    pub async fn message(&mut self) -> Result<ServiceEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            let update = self.watch.next().await?;
+            self.apply(update);
+        }
+    }
+
+    /// Queues the changes of `update`: the differences with the instances known so far if
+    /// listed, or all of them if listed for the first time.
+    fn apply(&mut self, update: PrefixUpdate) {
+        let prefix_len = self.watch.prefix().len();
+        let listed: BTreeMap<_, _> = match update {
+            PrefixUpdate::Listed(kvs) => kvs
+                .into_iter()
+                .map(|kv| {
+                    let (key, metadata) = kv.into_key_value();
+                    (key[prefix_len..].to_vec(), metadata)
+                })
+                .collect(),
+            PrefixUpdate::Changed(resp) => {
+                for event in resp.events() {
+                    let Some(kv) = event.kv() else {
+                        continue;
+                    };
+                    let id = kv.key()[prefix_len..].to_vec();
+                    match event.event_type() {
+                        EventType::Put => self.put(id, kv.value().to_vec()),
+                        EventType::Delete => self.remove(id),
+                    }
+                }
+                return;
+            }
+        };
+        match self.instances.take() {
+            None => {
+                let instances = listed.iter().map(|(id, metadata)| instance(id, metadata));
+                self.events
+                    .push_back(ServiceEvent::Instances(instances.collect()));
+                self.instances = Some(listed);
+            }
+            Some(known) => {
+                let removed: Vec<_> = known
+                    .keys()
+                    .filter(|id| !listed.contains_key(*id))
+                    .cloned()
+                    .collect();
+                self.instances = Some(known);
+                for id in removed {
+                    self.remove(id);
+                }
+                for (id, metadata) in listed {
+                    self.put(id, metadata);
+                }
+            }
+        }
+    }
+
+    /// Records the registration of the instance `id`, queuing the change if any.
+    fn put(&mut self, id: Vec<u8>, metadata: Vec<u8>) {
+        let instances = self.instances.get_or_insert_with(BTreeMap::new);
+        let event = match instances.get(&id) {
+            Some(known) if *known == metadata => return,
+            Some(_) => ServiceEvent::Updated(instance(&id, &metadata)),
+            None => ServiceEvent::Added(instance(&id, &metadata)),
+        };
+        instances.insert(id, metadata);
+        self.events.push_back(event);
+    }
+
+    /// Records the deregistration of the instance `id`, queuing the change if it was known.
+    fn remove(&mut self, id: Vec<u8>) {
+        let instances = self.instances.get_or_insert_with(BTreeMap::new);
+        if let Some(metadata) = instances.remove(&id) {
+            self.events
+                .push_back(ServiceEvent::Removed(instance(&id, &metadata)));
+        }
+    }
+}
+
+/// The instance `id` registered with `metadata`.
+#[inline]
+fn instance(id: &[u8], metadata: &[u8]) -> ServiceInstance {
+    ServiceInstance {
+        id: id.to_vec(),
+        metadata: metadata.to_vec(),
+    }
+}
@@ -0,0 +1,210 @@
+//! Renaming and swapping keys atomically.
+
+use crate::error::{Error, Result};
+use crate::ids::Revision;
+use crate::rpc::kv::{
+    Compare, CompareOp, GetOptions, KvClient, PutOptions, Txn, TxnOp, TxnOpResponse, TxnResponse,
+};
+use crate::rpc::KeyValue;
+
+/// Options for [`KvClient::rename`].
+#[derive(Debug, Default, Clone)]
+pub struct RenameOptions {
+    overwrite: bool,
+    keep_lease: bool,
+}
+
+impl RenameOptions {
+    /// Creates a `RenameOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            overwrite: false,
+            keep_lease: false,
+        }
+    }
+
+    /// Replaces the destination key if it exists, instead of leaving both keys as they are.
+    #[inline]
+    pub const fn with_overwrite(mut self) -> Self {
+        self.overwrite = true;
+        self
+    }
+
+    /// Binds the destination key to the lease of the source key, instead of to no lease.
+    #[inline]
+    pub const fn with_keep_lease(mut self) -> Self {
+        self.keep_lease = true;
+        self
+    }
+}
+
+/// What [`KvClient::rename`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameResult {
+    /// The source key has been moved to the destination key.
+    Renamed {
+        /// The revision of the rename.
+        revision: Revision,
+    },
+    /// The source key does not exist, nothing has been changed.
+    SourceMissing,
+    /// The destination key exists and is not to be overwritten, nothing has been changed.
+    DestinationExists,
+}
+
+/// What [`KvClient::swap`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwapResult {
+    /// The values of the keys have been swapped.
+    Swapped {
+        /// The revision of the swap.
+        revision: Revision,
+    },
+    /// A key does not exist, nothing has been changed.
+    Missing(Vec<u8>),
+}
+
+impl KvClient {
+    /// Moves the value of `from` to `to` and deletes `from`, in a single txn.
+    ///
+    /// Nothing is changed if `from` does not exist, or if `to` exists unless
+    /// [`RenameOptions::with_overwrite`]. The txn only applies if `from` did not change
+    /// since it was read, it is read again otherwise, e.g. if its lease expired meanwhile.
+    pub async fn rename(
+        &mut self,
+        from: impl Into<Vec<u8>>,
+        to: impl Into<Vec<u8>>,
+        options: Option<RenameOptions>,
+    ) -> Result<RenameResult> {
+        let (from, to) = (from.into(), to.into());
+        let options = options.unwrap_or_default();
+        if from == to {
+            return Err(Error::InvalidArgs(String::from(
+                "a key can not be renamed to itself",
+            )));
+        }
+
+        loop {
+            let Some(source) = self.get(from.clone(), None).await?.take_kvs().pop() else {
+                return Ok(RenameResult::SourceMissing);
+            };
+            let mut compares = vec![unchanged(&source)];
+            if !options.overwrite {
+                compares.push(Compare::version(to.clone(), CompareOp::Equal, 0));
+            }
+            let put_options = match source.lease() {
+                lease if options.keep_lease && !lease.is_none() => {
+                    Some(PutOptions::new().with_lease(lease))
+                }
+                _ => None,
+            };
+            let txn = Txn::new()
+                .when(compares)
+                .and_then([
+                    TxnOp::put(to.clone(), source.value(), put_options),
+                    TxnOp::delete(from.clone(), None),
+                ])
+                .or_else([
+                    TxnOp::get(from.clone(), Some(GetOptions::new().with_keys_only())),
+                    TxnOp::get(to.clone(), Some(GetOptions::new().with_keys_only())),
+                ]);
+            let Some(resp) = self.try_txn(txn).await? else {
+                continue;
+            };
+            if resp.succeeded() {
+                return Ok(RenameResult::Renamed {
+                    revision: revision(&resp),
+                });
+            }
+            // Only the destination existing is final, the source changing is retried.
+            if let [from_resp, to_resp] = get_responses(&resp).as_slice() {
+                let current = from_resp.first().map(KeyValue::mod_revision);
+                if current == Some(source.mod_revision()) && !to_resp.is_empty() {
+                    return Ok(RenameResult::DestinationExists);
+                }
+            }
+        }
+    }
+
+    /// Swaps the values of `a` and `b` in a single txn, each key keeping its lease.
+    ///
+    /// Nothing is changed if a key does not exist. The txn only applies if neither key
+    /// changed since they were read, they are read again otherwise.
+    pub async fn swap(
+        &mut self,
+        a: impl Into<Vec<u8>>,
+        b: impl Into<Vec<u8>>,
+    ) -> Result<SwapResult> {
+        let (a, b) = (a.into(), b.into());
+        if a == b {
+            return Err(Error::InvalidArgs(String::from(
+                "a key can not be swapped with itself",
+            )));
+        }
+
+        loop {
+            let txn =
+                Txn::new().and_then([TxnOp::get(a.clone(), None), TxnOp::get(b.clone(), None)]);
+            let resp = self.txn(txn).await?;
+            let (a_kv, b_kv) = match <[_; 2]>::try_from(get_responses(&resp)) {
+                Ok([mut a_kvs, mut b_kvs]) => (a_kvs.pop(), b_kvs.pop()),
+                Err(_) => (None, None),
+            };
+            let (a_kv, b_kv) = match (a_kv, b_kv) {
+                (Some(a_kv), Some(b_kv)) => (a_kv, b_kv),
+                (None, _) => return Ok(SwapResult::Missing(a)),
+                (_, None) => return Ok(SwapResult::Missing(b)),
+            };
+
+            let options = || Some(PutOptions::new().with_ignore_lease());
+            let txn = Txn::new()
+                .when([unchanged(&a_kv), unchanged(&b_kv)])
+                .and_then([
+                    TxnOp::put(a.clone(), b_kv.value(), options()),
+                    TxnOp::put(b.clone(), a_kv.value(), options()),
+                ]);
+            if let Some(resp) = self.try_txn(txn).await? {
+                if resp.succeeded() {
+                    return Ok(SwapResult::Swapped {
+                        revision: revision(&resp),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Sends `txn`, returning `None` if it failed because of a lease not found, e.g.
+    /// expired since the keys were read.
+    async fn try_txn(&mut self, txn: Txn) -> Result<Option<TxnResponse>> {
+        match self.txn(txn).await {
+            Ok(resp) => Ok(Some(resp)),
+            Err(Error::LeaseNotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The compare that `kv` did not change since read.
+#[inline]
+fn unchanged(kv: &KeyValue) -> Compare {
+    Compare::mod_revision(kv.key(), CompareOp::Equal, kv.mod_revision())
+}
+
+/// The revision of the txn `resp`.
+#[inline]
+fn revision(resp: &TxnResponse) -> Revision {
+    resp.header()
+        .map_or(Revision::current(), |header| header.revision())
+}
+
+/// The key-values of the get operations of the txn `resp`.
+fn get_responses(resp: &TxnResponse) -> Vec<Vec<KeyValue>> {
+    resp.op_responses()
+        .into_iter()
+        .filter_map(|resp| match resp {
+            TxnOpResponse::Get(mut resp) => Some(resp.take_kvs()),
+            _ => None,
+        })
+        .collect()
+}
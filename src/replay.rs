@@ -0,0 +1,865 @@
+//! Recording of the gRPC exchanges of a client, and their replay in hermetic tests.
+//!
+//! [`RecordingChannel::wrap`] records the exchanges sent over a [`Channel`] into a sink,
+//! frame by frame: the method and the metadata of every call, the messages of its requests,
+//! then the status, the chunks and the trailers of its response, each with the time elapsed
+//! since the recording started. The records are length-delimited protobuf messages appended
+//! to the sink as the frames go through, so that the recording of a process which crashed
+//! still holds the exchanges up to the crash.
+//!
+//! A [`ReplayChannel`] serves a recording back. A call is answered by the first recorded
+//! call not replayed yet of the same method and with the same first request message. The
+//! chunks of its response are sent once the requests they followed in the recording were
+//! sent again, so that the replay of the streams is deterministic whatever their timing.
+//! A call diverging from the recording fails with a status telling how, and the first
+//! divergence is reported by [`ReplayChannel::finish`], see [`verify`].
+//!
+//! The recordings leave out the `authorization` metadata, but hold the request messages as
+//! they are, the passwords of the `Authenticate` requests included: they are to be kept as
+//! safe as the credentials of the cluster.
+//!
+//! ```no_run
+//! use etcd_client::replay::{self, RecordingChannel};
+//! use etcd_client::{Channel, Client, Error};
+//!
+//! async fn session(mut client: Client) -> Result<i64, Error> {
+//!     client.put("key", "value", None).await?;
+//!     Ok(client.get("key", None).await?.count())
+//! }
+//!
+//! # async fn record(channel: Channel) -> Result<(), Error> {
+//! let sink = std::fs::File::create("session.etcd")?;
+//! let client = Client::from_channel(RecordingChannel::wrap(channel, sink), None).await?;
+//! session(client).await?;
+//!
+//! // Later, in a test without a cluster.
+//! assert_eq!(replay::verify(session, "session.etcd").await?, 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::bytes::DebugBytes;
+use crate::channel::Channel;
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::lock::MutexExt;
+use crate::logging::log_event;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use http_body::{Body, Frame, SizeHint};
+use prost::bytes::{Buf, Bytes, BytesMut};
+use prost::Message;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+use tonic::Status;
+use tower::util::BoxCloneService;
+use tower::Service;
+
+type Request = http::Request<tonic::body::Body>;
+type Response = http::Response<tonic::body::Body>;
+type ResponseFuture =
+    Pin<Box<dyn Future<Output = std::result::Result<Response, tower::BoxError>> + Send>>;
+
+/// The metadata left out of the recordings.
+const REDACTED_METADATA: &[&str] = &["authorization"];
+
+/// The length of the prefix of a gRPC message, its compression flag and its length.
+const MESSAGE_PREFIX_LEN: usize = 5;
+
+/// A record of a recording, an event of a call.
+#[derive(Clone, PartialEq, prost::Message)]
+struct PbRecord {
+    /// The call, numbered in the order the calls started.
+    #[prost(uint64, tag = "1")]
+    call: u64,
+    /// The microseconds elapsed since the recording started.
+    #[prost(uint64, tag = "2")]
+    micros: u64,
+    #[prost(oneof = "PbEvent", tags = "3, 4, 5, 6, 7, 8, 9")]
+    event: Option<PbEvent>,
+}
+
+/// An event of a call.
+#[derive(Clone, PartialEq, prost::Oneof)]
+enum PbEvent {
+    /// The call started.
+    #[prost(message, tag = "3")]
+    Start(PbStart),
+    /// A request message, with its prefix.
+    #[prost(bytes = "vec", tag = "4")]
+    Request(Vec<u8>),
+    /// The head of the response.
+    #[prost(message, tag = "5")]
+    Response(PbHead),
+    /// A chunk of the body of the response.
+    #[prost(bytes = "vec", tag = "6")]
+    Data(Vec<u8>),
+    /// The trailers of the response, ending it.
+    #[prost(message, tag = "7")]
+    Trailers(PbHead),
+    /// The end of the response without trailers.
+    #[prost(bool, tag = "8")]
+    End(bool),
+    /// The failure of the call, or of its response once started.
+    #[prost(string, tag = "9")]
+    Error(String),
+}
+
+/// The start of a call.
+#[derive(Clone, PartialEq, prost::Message)]
+struct PbStart {
+    #[prost(string, tag = "1")]
+    method: String,
+    #[prost(message, repeated, tag = "2")]
+    metadata: Vec<PbHeader>,
+}
+
+/// The HTTP status and the headers of a response, or its trailers.
+#[derive(Clone, PartialEq, prost::Message)]
+struct PbHead {
+    #[prost(uint32, tag = "1")]
+    status: u32,
+    #[prost(message, repeated, tag = "2")]
+    headers: Vec<PbHeader>,
+}
+
+/// A header, or an entry of the metadata.
+#[derive(Clone, PartialEq, prost::Message)]
+struct PbHeader {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(bytes = "vec", tag = "2")]
+    value: Vec<u8>,
+}
+
+/// The recorded headers of `headers`.
+fn pb_headers(headers: &HeaderMap) -> Vec<PbHeader> {
+    headers
+        .iter()
+        .filter(|(name, _)| !REDACTED_METADATA.contains(&name.as_str()))
+        .map(|(name, value)| PbHeader {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_vec(),
+        })
+        .collect()
+}
+
+/// The headers recorded as `headers`.
+fn header_map(headers: &[PbHeader]) -> Result<HeaderMap> {
+    let mut map = HeaderMap::with_capacity(headers.len());
+    for header in headers {
+        let name = HeaderName::from_bytes(header.name.as_bytes()).map_err(invalid)?;
+        let value = HeaderValue::from_bytes(&header.value).map_err(invalid)?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+/// The error of a recording which can not be read.
+#[inline]
+fn invalid(e: impl Display) -> Error {
+    Error::InvalidArgs(format!("invalid recording: {}", e))
+}
+
+/// The gRPC messages carried by the chunks of a body.
+#[derive(Default)]
+struct Messages(BytesMut);
+
+impl Messages {
+    #[inline]
+    fn push(&mut self, chunk: &[u8]) {
+        self.0.extend_from_slice(chunk);
+    }
+
+    /// The next complete message, with its prefix.
+    fn next(&mut self) -> Option<Bytes> {
+        let len = self.0.get(1..MESSAGE_PREFIX_LEN)?;
+        let len = MESSAGE_PREFIX_LEN + u32::from_be_bytes(len.try_into().ok()?) as usize;
+        (self.0.len() >= len).then(|| self.0.split_to(len).freeze())
+    }
+}
+
+/// The sink of a recording, shared by the clones of its channel.
+struct Recorder {
+    sink: Mutex<Box<dyn Write + Send>>,
+    started: Instant,
+    /// The number of calls started.
+    calls: AtomicU64,
+}
+
+impl Recorder {
+    /// Records `event` of the call `call`.
+    fn record(&self, call: u64, event: PbEvent) {
+        let mut sink = self.sink.lock_unpoisoned();
+        let record = PbRecord {
+            call,
+            micros: self.started.elapsed().as_micros() as u64,
+            event: Some(event),
+        };
+        let written = sink
+            .write_all(&record.encode_length_delimited_to_vec())
+            .and_then(|_| sink.flush());
+        if let Err(e) = written {
+            log_event!(Warn, "etcd recording failed", error = e);
+        }
+    }
+}
+
+/// A channel recording the exchanges sent over another one, see the [module](self) docs.
+#[derive(Clone)]
+pub struct RecordingChannel {
+    inner: Channel,
+    recorder: Arc<Recorder>,
+}
+
+impl RecordingChannel {
+    /// Wraps `channel` into a channel recording the exchanges sent over it into `sink`,
+    /// e.g. a file.
+    ///
+    /// Every record is written to the sink and flushed while the frame it records waits, so
+    /// a sink which may block for long, e.g. over the network, is better buffered apart.
+    pub fn wrap(channel: Channel, sink: impl Write + Send + 'static) -> Channel {
+        let recording = Self {
+            inner: channel,
+            recorder: Arc::new(Recorder {
+                sink: Mutex::new(Box::new(sink)),
+                started: Instant::now(),
+                calls: AtomicU64::new(0),
+            }),
+        };
+        Channel::Custom(BoxCloneService::new(recording))
+    }
+}
+
+impl Service<Request> for RecordingChannel {
+    type Response = Response;
+    type Error = tower::BoxError;
+    type Future = ResponseFuture;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let recorder = self.recorder.clone();
+        let call = recorder.calls.fetch_add(1, Ordering::Relaxed);
+        let start = PbStart {
+            method: req.uri().path().to_owned(),
+            metadata: pb_headers(req.headers()),
+        };
+        recorder.record(call, PbEvent::Start(start));
+        let req = req.map(|body| {
+            tonic::body::Body::new(Recorded {
+                inner: body,
+                recorder: recorder.clone(),
+                call,
+                requests: Some(Messages::default()),
+            })
+        });
+        let resp = self.inner.call(req);
+        Box::pin(async move {
+            let resp = match resp.await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    recorder.record(call, PbEvent::Error(e.to_string()));
+                    return Err(e);
+                }
+            };
+            let head = PbHead {
+                status: resp.status().as_u16().into(),
+                headers: pb_headers(resp.headers()),
+            };
+            recorder.record(call, PbEvent::Response(head));
+            Ok(resp.map(|body| {
+                tonic::body::Body::new(Recorded {
+                    inner: body,
+                    recorder,
+                    call,
+                    requests: None,
+                })
+            }))
+        })
+    }
+}
+
+/// A body recording its frames as they go through.
+struct Recorded {
+    inner: tonic::body::Body,
+    recorder: Arc<Recorder>,
+    call: u64,
+    /// The messages of the body of the requests, `None` for the body of the response.
+    requests: Option<Messages>,
+}
+
+impl Body for Recorded {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Frame<Bytes>, Status>>> {
+        let this = &mut *self;
+        let frame = ready!(Pin::new(&mut this.inner).poll_frame(cx));
+        let (recorder, call) = (&this.recorder, this.call);
+        match (&frame, &mut this.requests) {
+            (Some(Ok(frame)), Some(messages)) => {
+                if let Some(chunk) = frame.data_ref() {
+                    messages.push(chunk);
+                    while let Some(message) = messages.next() {
+                        recorder.record(call, PbEvent::Request(message.to_vec()));
+                    }
+                }
+            }
+            (Some(Ok(frame)), None) => {
+                if let Some(chunk) = frame.data_ref() {
+                    recorder.record(call, PbEvent::Data(chunk.to_vec()));
+                } else if let Some(trailers) = frame.trailers_ref() {
+                    let trailers = PbHead {
+                        status: 0,
+                        headers: pb_headers(trailers),
+                    };
+                    recorder.record(call, PbEvent::Trailers(trailers));
+                }
+            }
+            (Some(Err(status)), None) => {
+                recorder.record(call, PbEvent::Error(status.message().to_owned()));
+            }
+            (None, None) => recorder.record(call, PbEvent::End(true)),
+            _ => {}
+        }
+        Poll::Ready(frame)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// An event of the response of a recorded call.
+enum ReplayEvent {
+    Data(Bytes),
+    Trailers(HeaderMap),
+    Error(String),
+}
+
+/// A call of a recording.
+struct RecordedCall {
+    method: String,
+    /// The request messages, with their prefix.
+    requests: Vec<Bytes>,
+    /// The status and the headers of the response, or the failure of the call, `None` if
+    /// the recording ends before either.
+    response: Option<std::result::Result<(StatusCode, HeaderMap), String>>,
+    /// The events of the response, each with the number of request messages before it.
+    events: Vec<(usize, ReplayEvent)>,
+    /// Whether the response ends within the recording.
+    ended: bool,
+}
+
+impl RecordedCall {
+    #[inline]
+    fn new(method: String) -> Self {
+        Self {
+            method,
+            requests: Vec::new(),
+            response: None,
+            events: Vec::new(),
+            ended: false,
+        }
+    }
+
+    /// Adds the recorded `event` to the call.
+    fn push(&mut self, event: PbEvent) -> Result<()> {
+        if self.ended {
+            return Ok(());
+        }
+        let before = self.requests.len();
+        match event {
+            PbEvent::Start(_) => return Err(invalid("a call started twice")),
+            PbEvent::Request(message) => self.requests.push(message.into()),
+            PbEvent::Response(head) => {
+                let status = u16::try_from(head.status).map_err(invalid)?;
+                let status = StatusCode::from_u16(status).map_err(invalid)?;
+                let headers = header_map(&head.headers)?;
+                // A trailers-only response has no body.
+                self.ended = headers.contains_key("grpc-status");
+                self.response = Some(Ok((status, headers)));
+            }
+            PbEvent::Data(chunk) => {
+                self.events.push((before, ReplayEvent::Data(chunk.into())));
+            }
+            PbEvent::Trailers(trailers) => {
+                let trailers = header_map(&trailers.headers)?;
+                self.events.push((before, ReplayEvent::Trailers(trailers)));
+                self.ended = true;
+            }
+            PbEvent::End(_) => self.ended = true,
+            PbEvent::Error(e) => {
+                match self.response {
+                    Some(_) => self.events.push((before, ReplayEvent::Error(e))),
+                    None => self.response = Some(Err(e)),
+                }
+                self.ended = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The calls of a recording and the progress of their replay.
+struct ReplayState {
+    calls: Vec<Arc<RecordedCall>>,
+    replayed: Vec<bool>,
+    /// The first divergence of the replay from the recording.
+    diverged: Option<String>,
+}
+
+impl ReplayState {
+    /// Takes the first call not replayed yet of `method` whose first request message is
+    /// `first`, or fails with the divergence.
+    fn take(
+        &mut self,
+        method: &str,
+        first: Option<&[u8]>,
+    ) -> std::result::Result<Arc<RecordedCall>, String> {
+        let found = self
+            .calls
+            .iter()
+            .zip(&self.replayed)
+            .position(|(call, replayed)| {
+                !replayed && call.method == method && call.requests.first().map(|m| &m[..]) == first
+            });
+        match found {
+            Some(index) => {
+                self.replayed[index] = true;
+                Ok(self.calls[index].clone())
+            }
+            None => Err(self.diverge(match first {
+                Some(message) => format!(
+                    "no call of {} with the request {:?} left to replay",
+                    method,
+                    DebugBytes(&message[MESSAGE_PREFIX_LEN..])
+                ),
+                None => format!("no call of {} without requests left to replay", method),
+            })),
+        }
+    }
+
+    /// Records the divergence `message` unless the replay diverged before, returning it.
+    fn diverge(&mut self, message: String) -> String {
+        self.diverged.get_or_insert_with(|| message.clone());
+        message
+    }
+}
+
+/// A channel serving the calls recorded by a [`RecordingChannel`], see the [module](self)
+/// docs.
+///
+/// The clones of the channel replay the same recording, each recorded call once.
+#[derive(Clone)]
+pub struct ReplayChannel {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl ReplayChannel {
+    /// Reads the recording in the file at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Reads the recording `recording`, e.g. embedded with `include_bytes!`.
+    pub fn from_bytes(mut recording: &[u8]) -> Result<Self> {
+        let mut calls = Vec::new();
+        let mut started = HashMap::new();
+        while recording.has_remaining() {
+            let record = PbRecord::decode_length_delimited(&mut recording).map_err(invalid)?;
+            match record.event {
+                Some(PbEvent::Start(start)) => {
+                    started.insert(record.call, calls.len());
+                    calls.push(RecordedCall::new(start.method));
+                }
+                Some(event) => match started.get(&record.call) {
+                    Some(index) => calls[*index].push(event)?,
+                    None => return Err(invalid(format!("call {} never started", record.call))),
+                },
+                None => {}
+            }
+        }
+        Ok(Self {
+            state: Arc::new(Mutex::new(ReplayState {
+                replayed: vec![false; calls.len()],
+                calls: calls.into_iter().map(Arc::new).collect(),
+                diverged: None,
+            })),
+        })
+    }
+
+    /// The channel serving the recorded calls, e.g. to [`Client::from_channel`].
+    #[inline]
+    pub fn channel(&self) -> Channel {
+        Channel::Custom(BoxCloneService::new(self.clone()))
+    }
+
+    /// Fails with an [`Error::ReplayDiverged`] if a call diverged from the recording, or if
+    /// recorded calls were not replayed.
+    pub fn finish(&self) -> Result<()> {
+        let state = self.state.lock_unpoisoned();
+        if let Some(diverged) = &state.diverged {
+            return Err(Error::ReplayDiverged(diverged.clone()));
+        }
+        let mut left = state
+            .calls
+            .iter()
+            .zip(&state.replayed)
+            .filter(|(_, replayed)| !**replayed);
+        match left.next() {
+            Some((call, _)) => Err(Error::ReplayDiverged(format!(
+                "{} recorded calls not replayed, the first of {}",
+                left.count() + 1,
+                call.method
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Service<Request> for ReplayChannel {
+    type Response = Response;
+    type Error = tower::BoxError;
+    type Future = ResponseFuture;
+
+    #[inline]
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let state = self.state.clone();
+        Box::pin(async move {
+            let method = req.uri().path().to_owned();
+            let mut requests = Requests::new(req.into_body());
+            let first = std::future::poll_fn(|cx| requests.poll_next(cx)).await;
+            let call = match state.lock_unpoisoned().take(&method, first.as_deref()) {
+                Ok(call) => call,
+                Err(diverged) => return Ok(Status::internal(diverged).into_http()),
+            };
+            let (status, headers) = match &call.response {
+                Some(Ok(head)) => head.clone(),
+                Some(Err(e)) => return Err(e.clone().into()),
+                None => {
+                    let message = format!("the recording ends before the response of {}", method);
+                    let diverged = state.lock_unpoisoned().diverge(message);
+                    return Ok(Status::internal(diverged).into_http());
+                }
+            };
+            let body = Replayed {
+                requests,
+                replayed: usize::from(first.is_some()),
+                next: 0,
+                call,
+                state,
+            };
+            let mut resp = http::Response::new(tonic::body::Body::new(body));
+            *resp.status_mut() = status;
+            *resp.headers_mut() = headers;
+            Ok(resp)
+        })
+    }
+}
+
+/// The request messages of a replayed call, as the client sends them.
+struct Requests {
+    body: tonic::body::Body,
+    messages: Messages,
+    ended: bool,
+}
+
+impl Requests {
+    #[inline]
+    fn new(body: tonic::body::Body) -> Self {
+        Self {
+            body,
+            messages: Messages::default(),
+            ended: false,
+        }
+    }
+
+    /// The next request message, `None` once the requests ended.
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Bytes>> {
+        loop {
+            if let Some(message) = self.messages.next() {
+                return Poll::Ready(Some(message));
+            }
+            if self.ended {
+                return Poll::Ready(None);
+            }
+            match ready!(Pin::new(&mut self.body).poll_frame(cx)) {
+                Some(Ok(frame)) => {
+                    if let Some(chunk) = frame.data_ref() {
+                        self.messages.push(chunk);
+                    }
+                }
+                // The requests end with the body, or with its failure.
+                _ => self.ended = true,
+            }
+        }
+    }
+}
+
+/// The body of the response of a replayed call.
+struct Replayed {
+    requests: Requests,
+    /// The number of request messages replayed.
+    replayed: usize,
+    /// The index of the next event of the response.
+    next: usize,
+    call: Arc<RecordedCall>,
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl Replayed {
+    /// Receives the next request message, `false` once the requests ended, failing if it
+    /// is not the next one recorded.
+    fn poll_request(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<bool, Status>> {
+        let Some(message) = ready!(self.requests.poll_next(cx)) else {
+            return Poll::Ready(Ok(false));
+        };
+        let diverged = match self.call.requests.get(self.replayed) {
+            Some(recorded) if *recorded == message => {
+                self.replayed += 1;
+                return Poll::Ready(Ok(true));
+            }
+            Some(_) => "differs from the recording",
+            None => "was not recorded",
+        };
+        let message = format!(
+            "request {} of {} {}: {:?}",
+            self.replayed,
+            self.call.method,
+            diverged,
+            DebugBytes(&message[MESSAGE_PREFIX_LEN..])
+        );
+        Poll::Ready(Err(self.diverge(message)))
+    }
+
+    /// Records the divergence `message`, returning the status failing the response.
+    fn diverge(&self, message: String) -> Status {
+        Status::internal(self.state.lock_unpoisoned().diverge(message))
+    }
+}
+
+impl Body for Replayed {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Frame<Bytes>, Status>>> {
+        let this = &mut *self;
+        let call = this.call.clone();
+        loop {
+            let Some((before, event)) = call.events.get(this.next) else {
+                if call.ended {
+                    return Poll::Ready(None);
+                }
+                // The response was still open when the recording ended, like it stays.
+                match ready!(this.poll_request(cx)) {
+                    Ok(true) => continue,
+                    Ok(false) => return Poll::Pending,
+                    Err(status) => return Poll::Ready(Some(Err(status))),
+                }
+            };
+            if this.replayed < *before {
+                match ready!(this.poll_request(cx)) {
+                    Ok(true) => continue,
+                    Ok(false) => {
+                        let message = format!(
+                            "{} ended its requests after {} of them, {} were recorded",
+                            call.method, this.replayed, before
+                        );
+                        return Poll::Ready(Some(Err(this.diverge(message))));
+                    }
+                    Err(status) => return Poll::Ready(Some(Err(status))),
+                }
+            }
+            this.next += 1;
+            return Poll::Ready(Some(match event {
+                ReplayEvent::Data(chunk) => Ok(Frame::data(chunk.clone())),
+                ReplayEvent::Trailers(trailers) => Ok(Frame::trailers(trailers.clone())),
+                ReplayEvent::Error(e) => Err(Status::unknown(e.clone())),
+            }));
+        }
+    }
+}
+
+/// Replays the recording in the file at `path` to a client built over it, handed to
+/// `client_fn`, returning the output of `client_fn`.
+///
+/// Fails with an [`Error::ReplayDiverged`] if the calls of `client_fn` diverge from the
+/// recording, or if recorded calls are left once it returns. The client is built without
+/// options, so the client recorded is expected to send no call of its own besides the
+/// ones of `client_fn`, e.g. to authenticate.
+pub async fn verify<F, Fut, T>(client_fn: F, path: impl AsRef<Path>) -> Result<T>
+where
+    F: FnOnce(Client) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let replay = ReplayChannel::from_file(path)?;
+    let client = Client::from_channel(replay.channel(), None).await?;
+    let output = client_fn(client).await;
+    // A divergence tells more than the error it caused.
+    replay.finish()?;
+    output
+}
+
+#[cfg(all(test, feature = "kv", feature = "watch"))]
+mod tests {
+    use super::*;
+    use crate::rpc::pb::etcdserverpb::{
+        RangeResponse as PbRangeResponse, WatchResponse as PbWatchResponse,
+    };
+    use http_body_util::StreamBody;
+    use tokio_stream::wrappers::ReceiverStream;
+
+    /// Encodes `msg` as a gRPC message.
+    fn frame(msg: &impl Message) -> Bytes {
+        let mut buf = vec![0];
+        buf.extend_from_slice(&(msg.encoded_len() as u32).to_be_bytes());
+        msg.encode(&mut buf).unwrap();
+        Bytes::from(buf)
+    }
+
+    /// A sink whose bytes can be read while it is written.
+    #[derive(Clone, Default)]
+    struct Sink(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock_unpoisoned().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A server answering each request message with a response numbered from 0, the count
+    /// of a range or the ID of a watch, and ending the response with the requests.
+    fn server() -> Channel {
+        let service = tower::service_fn(|req: Request| async move {
+            let path = req.uri().path().to_owned();
+            let (tx, rx) = tokio::sync::mpsc::channel(8);
+            tokio::spawn(async move {
+                let mut requests = Requests::new(req.into_body());
+                let mut id = 0;
+                while std::future::poll_fn(|cx| requests.poll_next(cx))
+                    .await
+                    .is_some()
+                {
+                    let chunk = match path.as_str() {
+                        "/etcdserverpb.Watch/Watch" => frame(&PbWatchResponse {
+                            created: true,
+                            watch_id: id,
+                            ..Default::default()
+                        }),
+                        _ => frame(&PbRangeResponse {
+                            count: id,
+                            ..Default::default()
+                        }),
+                    };
+                    id += 1;
+                    if tx.send(Ok::<_, Status>(Frame::data(chunk))).await.is_err() {
+                        return;
+                    }
+                }
+                let mut trailers = HeaderMap::new();
+                Status::ok("").add_header(&mut trailers).unwrap();
+                let _ = tx.send(Ok(Frame::trailers(trailers))).await;
+            });
+            let body = tonic::body::Body::new(StreamBody::new(ReceiverStream::new(rx)));
+            let resp = http::Response::builder()
+                .header("content-type", "application/grpc")
+                .body(body)
+                .unwrap();
+            Ok::<_, tower::BoxError>(resp)
+        });
+        Channel::Custom(BoxCloneService::new(service))
+    }
+
+    /// Gets `key`, then watches it and another key on the same stream, returning the count
+    /// of the get and the ID of the second watch.
+    async fn session(mut client: Client, key: &'static str) -> Result<(i64, i64)> {
+        let count = client.get(key, None).await?.count();
+        let (mut watcher, mut stream) = client.watch(key, None).await?;
+        watcher.watch("other", None).await?;
+        let resp = stream.message().await?.unwrap();
+        Ok((count, resp.watch_id()))
+    }
+
+    #[tokio::test]
+    async fn test_record_replay() {
+        let sink = Sink::default();
+        let channel = RecordingChannel::wrap(server(), sink.clone());
+        let client = Client::from_channel(channel, None).await.unwrap();
+        assert_eq!(session(client, "key").await.unwrap(), (0, 1));
+
+        let recording = sink.0.lock_unpoisoned().clone();
+        let replay = ReplayChannel::from_bytes(&recording).unwrap();
+        let client = Client::from_channel(replay.channel(), None).await.unwrap();
+        assert_eq!(session(client, "key").await.unwrap(), (0, 1));
+        replay.finish().unwrap();
+
+        // The recorded calls are replayed once.
+        let client = Client::from_channel(replay.channel(), None).await.unwrap();
+        let err = session(client, "key").await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("no call of /etcdserverpb.KV/Range"));
+    }
+
+    #[tokio::test]
+    async fn test_replay_diverged() {
+        let sink = Sink::default();
+        let channel = RecordingChannel::wrap(server(), sink.clone());
+        let client = Client::from_channel(channel, None).await.unwrap();
+        session(client, "key").await.unwrap();
+        let recording = sink.0.lock_unpoisoned().clone();
+
+        let replay = ReplayChannel::from_bytes(&recording).unwrap();
+        let client = Client::from_channel(replay.channel(), None).await.unwrap();
+        session(client, "other").await.unwrap_err();
+        let err = replay.finish().unwrap_err();
+        assert!(
+            matches!(&err, Error::ReplayDiverged(message) if message.contains("other")),
+            "{:?}",
+            err
+        );
+
+        // Calls left out are divergences too.
+        let replay = ReplayChannel::from_bytes(&recording).unwrap();
+        let mut client = Client::from_channel(replay.channel(), None).await.unwrap();
+        client.get("key", None).await.unwrap();
+        let err = replay.finish().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "replay diverged: 1 recorded calls not replayed, the first of /etcdserverpb.Watch/Watch"
+        );
+    }
+}
@@ -0,0 +1,366 @@
+//! Retrying of RPCs failing with transient errors.
+//!
+//! A [`RetryPolicy`] set with [`ConnectOptions::with_retry`](crate::ConnectOptions::with_retry)
+//! makes the sub-clients retry safe-to-retry RPCs which failed because the server was
+//! unavailable, had no leader, or timed out. Every attempt re-attaches the current auth token.
+//!
+//! Independent of the policy, read-only RPCs, i.e. Range, MemberList, LeaseTimeToLive and
+//! Status, are retried [`DEFAULT_READ_RETRIES`] times by default if they failed because the
+//! leader changed or the request timed out, which happens during rolling restarts. This is
+//! set with [`ConnectOptions::with_read_retries`](crate::ConnectOptions::with_read_retries).
+//!
+//! Retried requests are reported by the `etcd_retry` tracing span, which records the number
+//! of attempts and the total elapsed time, and by a debug event per retry.
+
+use crate::error::{Error, Result};
+use crate::logging::log_event;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::Instrument;
+
+/// The default number of retries of read-only RPCs which failed because the leader changed
+/// or the request timed out.
+pub const DEFAULT_READ_RETRIES: u32 = 2;
+
+/// The backoff of read retries.
+const READ_RETRY_BACKOFF: RetryPolicy = RetryPolicy::new();
+
+/// Policy of retrying RPCs failing with transient errors.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: f64,
+    retry_non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    /// Creates a `RetryPolicy` of 3 attempts, with a backoff starting at 50ms and doubled up
+    /// to 1s, randomized by 20%.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+            jitter: 0.2,
+            retry_non_idempotent: false,
+        }
+    }
+
+    /// Sets the maximum number of attempts, including the first one.
+    #[inline]
+    pub const fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets the backoff before the first retry, it is doubled for every further retry.
+    #[inline]
+    pub const fn with_initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Sets the maximum backoff between two attempts.
+    #[inline]
+    pub const fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets the fraction between 0 and 1 by which the backoff is randomly shortened, so that
+    /// clients failing at the same time do not retry at the same time.
+    #[inline]
+    pub const fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Whether to retry RPCs which are not idempotent, i.e. Put, DeleteRange and Txn,
+    /// even if the request may have reached the server.
+    ///
+    /// They are only retried if the connection was refused otherwise.
+    #[inline]
+    pub const fn with_retry_non_idempotent(mut self, retry_non_idempotent: bool) -> Self {
+        self.retry_non_idempotent = retry_non_idempotent;
+        self
+    }
+
+    /// The backoff before the retry after `attempt` attempts.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << (attempt - 1).min(31))
+            .min(self.max_backoff);
+        // A fresh random state is seeded randomly, which is good enough for a jitter.
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        backoff.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * random)
+    }
+
+    /// Returns `true` if the request failed with `err` may be retried.
+    fn should_retry(&self, err: &Error, idempotent: bool) -> bool {
+        let Some(status) = err.as_status() else {
+            return false;
+        };
+        // No leader, leader changed and request timed out are unavailable as well.
+        if status.code() != tonic::Code::Unavailable {
+            return false;
+        }
+        idempotent || self.retry_non_idempotent || is_connection_refused(status)
+    }
+
+    /// The settings of the policy which can not be honoured, e.g. a jitter above 1.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.max_attempts == 0 {
+            problems.push(String::from("retry policy has no attempts"));
+        }
+        if self.initial_backoff > self.max_backoff {
+            problems.push(String::from(
+                "retry policy initial backoff exceeds the maximum backoff",
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            problems.push(format!(
+                "retry policy jitter {} is not between 0 and 1",
+                self.jitter
+            ));
+        }
+        problems
+    }
+}
+
+impl Default for RetryPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns `true` if the request failed because the connection was refused, in which case
+/// it never reached the server.
+fn is_connection_refused(status: &tonic::Status) -> bool {
+    let mut source = std::error::Error::source(status);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<std::io::Error>() {
+            if err.kind() == std::io::ErrorKind::ConnectionRefused {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Returns `true` if a read failed with `err` because the leader changed or the request
+/// timed out, which is always safe to retry.
+fn is_read_retryable(err: &Error) -> bool {
+    matches!(err, Error::LeaderChanged { .. } | Error::Timeout { .. })
+}
+
+/// Runs the RPC made by `f`, retrying it according to `policy` if given.
+///
+/// Read-only RPCs pass their number of `read_retries`, other RPCs pass 0.
+pub(crate) async fn retry<T, F, Fut>(
+    policy: Option<&RetryPolicy>,
+    read_retries: u32,
+    rpc: &'static str,
+    idempotent: bool,
+    mut f: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    if policy.is_none() && read_retries == 0 {
+        return match f().await {
+            // The client sends uncompressed requests from now on.
+            Err(e) if e.is_compression_rejected() => f().await,
+            result => result,
+        };
+    }
+
+    let span = tracing::debug_span!(
+        "etcd_retry",
+        rpc,
+        attempts = tracing::field::Empty,
+        elapsed_ms = tracing::field::Empty,
+    );
+    let start = Instant::now();
+    let mut attempt = 1;
+    let mut read_retry = 0;
+    let mut resent = false;
+    let result = async {
+        loop {
+            let e = match crate::trace::attempt(attempt, f()).await {
+                Err(e) => e,
+                result => break result,
+            };
+            if !resent && e.is_compression_rejected() {
+                tracing::debug!(attempt, error = %e, "resending etcd request uncompressed");
+                resent = true;
+                continue;
+            }
+            let backoff = match policy {
+                Some(policy)
+                    if attempt < policy.max_attempts && policy.should_retry(&e, idempotent) =>
+                {
+                    policy.backoff(attempt)
+                }
+                _ if read_retry < read_retries && is_read_retryable(&e) => {
+                    read_retry += 1;
+                    READ_RETRY_BACKOFF.backoff(read_retry)
+                }
+                _ => {
+                    if attempt > 1 {
+                        log_event!(
+                            Error,
+                            "giving up etcd request",
+                            rpc = rpc,
+                            attempts = attempt,
+                            error = &e,
+                        );
+                    }
+                    break Err(e);
+                }
+            };
+            tracing::debug!(attempt, error = %e, ?backoff, "retrying etcd request");
+            log_event!(
+                Warn,
+                "retrying etcd request",
+                rpc = rpc,
+                attempt = attempt,
+                delay_ms = backoff.as_millis(),
+                error = &e,
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+    .instrument(span.clone())
+    .await;
+
+    span.record("attempts", attempt);
+    span.record("elapsed_ms", start.elapsed().as_millis() as u64);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new()
+            .with_initial_backoff(Duration::from_millis(100))
+            .with_max_backoff(Duration::from_millis(300))
+            .with_jitter(0.0);
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(300));
+        assert_eq!(policy.backoff(100), Duration::from_millis(300));
+
+        let policy = policy.with_jitter(0.5);
+        for _ in 0..100 {
+            let backoff = policy.backoff(1);
+            assert!(backoff >= Duration::from_millis(50) && backoff <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new();
+        let no_leader = Error::from(tonic::Status::unavailable("etcdserver: no leader"));
+        assert!(policy.should_retry(&no_leader, true));
+        assert!(!policy.should_retry(&no_leader, false));
+        assert!(policy
+            .clone()
+            .with_retry_non_idempotent(true)
+            .should_retry(&no_leader, false));
+
+        let mut refused = tonic::Status::unavailable("error trying to connect");
+        refused.set_source(Arc::new(std::io::Error::from(
+            std::io::ErrorKind::ConnectionRefused,
+        )));
+        assert!(policy.should_retry(&Error::from(refused), false));
+
+        let compacted = Error::from(tonic::Status::out_of_range(
+            "etcdserver: mvcc: required revision has been compacted",
+        ));
+        assert!(!policy.should_retry(&compacted, true));
+        assert!(!policy.should_retry(&Error::EndpointsNotManaged, true));
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let policy = RetryPolicy::new()
+            .with_max_attempts(3)
+            .with_initial_backoff(Duration::from_millis(1));
+        let attempts = AtomicU32::new(0);
+        let result = retry(Some(&policy), 0, "Range", true, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(Error::from(tonic::Status::unavailable(
+                "etcdserver: request timed out",
+            )))
+        })
+        .await;
+        assert!(matches!(result, Err(Error::Timeout { .. })));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let attempts = AtomicU32::new(0);
+        let result = retry(Some(&policy), 0, "Put", false, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(Error::from(tonic::Status::unavailable(
+                "etcdserver: request timed out",
+            )))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let attempts = AtomicU32::new(0);
+        let result = retry(Some(&policy), 0, "Range", true, || async {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(Error::from(tonic::Status::unavailable(
+                    "etcdserver: no leader",
+                ))),
+                n => Ok(n),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_read_retry() {
+        let attempts = AtomicU32::new(0);
+        let result = retry(None, 2, "Range", true, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(Error::from(tonic::Status::unavailable(
+                "etcdserver: leader changed",
+            )))
+        })
+        .await;
+        assert!(matches!(result, Err(Error::LeaderChanged { .. })));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // Other errors are not retried without a policy.
+        let attempts = AtomicU32::new(0);
+        let result = retry(None, 2, "Range", true, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(Error::from(tonic::Status::unavailable(
+                "etcdserver: no leader",
+            )))
+        })
+        .await;
+        assert!(matches!(result, Err(Error::NoLeader { .. })));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,356 @@
+//! Routing of calls to the leader or to a given member, rather than to any member.
+//!
+//! A routed call is sent over a channel to its member alone, as the member-wise operations
+//! of the client are. The leader is found from the status of the members and kept until a
+//! routed call observes that it changed: the member answers that it is not the leader, or
+//! reports a higher raft term than it did when found.
+
+use crate::auth::AuthToken;
+use crate::compression::Compression;
+use crate::error::{Error, Result};
+use crate::intercept::InterceptedChannel;
+use crate::lock::MutexExt;
+use crate::logging::log_event;
+use crate::rpc::HasResponseHeader;
+use http::Uri;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+/// The member a call is sent to, see the `with_route` methods of the options of the KV
+/// calls, and `MaintenanceClient::routed`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum RouteTo {
+    /// Any member, as chosen by the balanced channel of the client.
+    #[default]
+    Any,
+    /// The leader of the cluster, e.g. to save the forwarding of a write by a follower.
+    Leader,
+    /// The member of the given ID, e.g. to read its local state with a serializable get.
+    Member(u64),
+}
+
+impl Display for RouteTo {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteTo::Any => write!(f, "any member"),
+            RouteTo::Leader => write!(f, "leader"),
+            RouteTo::Member(id) => write!(f, "member {:x}", id),
+        }
+    }
+}
+
+/// The member found for a route.
+#[derive(Debug, Clone)]
+pub(crate) struct Target {
+    /// The first client URL of the member.
+    pub(crate) uri: Uri,
+    /// The raft term the member was found at.
+    pub(crate) raft_term: u64,
+}
+
+type Resolve =
+    Box<dyn Fn(RouteTo) -> Pin<Box<dyn Future<Output = Result<Target>> + Send>> + Send + Sync>;
+type Connect = Box<dyn Fn(&Uri) -> Result<InterceptedChannel> + Send + Sync>;
+
+/// Finds the members of routes, and connects to them, shared by the clones of a client.
+pub(crate) struct Router {
+    resolve: Resolve,
+    connect: Connect,
+    auth_token: AuthToken,
+    compression: Compression,
+    /// Whether the calls fail rather than being sent to any member if their member can not
+    /// be reached.
+    strict: bool,
+    /// The leader found last, until a routed call observes that it changed.
+    leader: Mutex<Option<Target>>,
+}
+
+impl Router {
+    /// Creates a router finding the member of a route with `resolve`, and connecting to it
+    /// with `connect`, the calls being authenticated with `auth_token` and compressed
+    /// according to `compression`.
+    pub(crate) fn new<F, Fut>(
+        resolve: F,
+        connect: impl Fn(&Uri) -> Result<InterceptedChannel> + Send + Sync + 'static,
+        auth_token: AuthToken,
+        compression: Compression,
+    ) -> Self
+    where
+        F: Fn(RouteTo) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Target>> + Send + 'static,
+    {
+        Self {
+            resolve: Box::new(move |route| Box::pin(resolve(route))),
+            connect: Box::new(connect),
+            auth_token,
+            compression,
+            strict: false,
+            leader: Mutex::new(None),
+        }
+    }
+
+    /// Fails the calls whose member can not be reached if `strict`, rather than sending them
+    /// to any member.
+    #[inline]
+    pub(crate) fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The auth token the routed calls are sent with.
+    #[inline]
+    pub(crate) fn auth_token(&self) -> &AuthToken {
+        &self.auth_token
+    }
+
+    /// The compression of the routed calls.
+    #[inline]
+    pub(crate) fn compression(&self) -> &Compression {
+        &self.compression
+    }
+
+    /// The channel to the member of `route`, finding the leader unless known.
+    pub(crate) async fn channel(&self, route: RouteTo) -> Result<InterceptedChannel> {
+        let cached = match route {
+            RouteTo::Leader => self.leader.lock_unpoisoned().clone(),
+            _ => None,
+        };
+        let target = match cached {
+            Some(target) => target,
+            None => {
+                let target = (self.resolve)(route).await?;
+                if route == RouteTo::Leader {
+                    *self.leader.lock_unpoisoned() = Some(target.clone());
+                }
+                target
+            }
+        };
+        (self.connect)(&target.uri)
+    }
+
+    /// Observes the result of a call routed to `route`, forgetting the leader if the result
+    /// tells that it changed.
+    pub(crate) fn observe<R: HasResponseHeader>(&self, route: RouteTo, result: &Result<R>) {
+        if route != RouteTo::Leader {
+            return;
+        }
+        let mut leader = self.leader.lock_unpoisoned();
+        let Some(target) = leader.as_ref() else {
+            return;
+        };
+        let changed = match result {
+            Ok(resp) => resp
+                .raft_term()
+                .is_some_and(|raft_term| raft_term > target.raft_term),
+            Err(e) => e.is_not_leader() || matches!(e, Error::LeaderChanged { .. }),
+        };
+        if changed {
+            *leader = None;
+        }
+    }
+
+    /// Handles the member of `route` being unreachable because of `error`: fails with an
+    /// [`Error::RouteUnreachable`] if strict, or lets the call be sent to any member.
+    pub(crate) fn unreachable(&self, route: RouteTo, error: Error) -> Result<()> {
+        if route == RouteTo::Leader {
+            *self.leader.lock_unpoisoned() = None;
+        }
+        if self.strict {
+            return Err(Error::RouteUnreachable {
+                route,
+                source: Box::new(error),
+            });
+        }
+        log_event!(
+            Warn,
+            "etcd route unreachable, sending to any member",
+            route = route,
+            error = error,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "kv"))]
+mod tests {
+    use super::*;
+    use crate::channel::Channel;
+    use crate::intercept::Interceptor;
+    use crate::rpc::kv::{GetOptions, KvClient};
+    use crate::rpc::pb::etcdserverpb::{
+        RangeRequest as PbRangeRequest, RangeResponse as PbRangeResponse,
+        ResponseHeader as PbResponseHeader,
+    };
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tonic::codec::ProstCodec;
+    use tonic::server::Grpc;
+    use tonic::Status;
+    use tower::util::BoxCloneService;
+
+    /// The members `a`, `b` and `c` of IDs 1 to 3, and the endpoints which served the calls.
+    #[derive(Clone, Default)]
+    struct Cluster {
+        served: Arc<Mutex<Vec<&'static str>>>,
+        leader: Arc<AtomicU64>,
+        raft_term: Arc<AtomicU64>,
+        /// The member answering that it is not the leader, `0` for none.
+        deposed: Arc<AtomicU64>,
+        resolved: Arc<AtomicUsize>,
+    }
+
+    const MEMBERS: [(u64, &str); 3] = [(1, "a"), (2, "b"), (3, "c")];
+
+    impl Cluster {
+        fn new(leader: u64) -> Self {
+            let cluster = Self::default();
+            cluster.leader.store(leader, Ordering::SeqCst);
+            cluster.raft_term.store(2, Ordering::SeqCst);
+            cluster
+        }
+
+        fn served(&self) -> Vec<&'static str> {
+            std::mem::take(&mut *self.served.lock_unpoisoned())
+        }
+
+        /// The channel to the endpoint `name` of the member `id`, `0` for the balanced one.
+        fn channel(&self, id: u64, name: &'static str) -> InterceptedChannel {
+            let cluster = self.clone();
+            let service = tower::service_fn(move |req: http::Request<tonic::body::Body>| {
+                let cluster = cluster.clone();
+                async move {
+                    let service = tower::service_fn(|_: tonic::Request<PbRangeRequest>| {
+                        cluster.served.lock_unpoisoned().push(name);
+                        let resp = if id != 0 && cluster.deposed.load(Ordering::SeqCst) == id {
+                            Err(Status::failed_precondition("etcdserver: not leader"))
+                        } else {
+                            let header = PbResponseHeader {
+                                member_id: id,
+                                raft_term: cluster.raft_term.load(Ordering::SeqCst),
+                                ..Default::default()
+                            };
+                            Ok(tonic::Response::new(PbRangeResponse {
+                                header: Some(header),
+                                ..Default::default()
+                            }))
+                        };
+                        async move { resp }
+                    });
+                    let resp = Grpc::new(ProstCodec::default()).unary(service, req).await;
+                    Ok::<_, tower::BoxError>(resp)
+                }
+            });
+            InterceptedChannel::new(
+                Channel::Custom(BoxCloneService::new(service)),
+                Interceptor::default(),
+            )
+        }
+
+        /// A client over the balanced endpoint, routing the calls to the members.
+        fn client(&self, strict: bool) -> KvClient {
+            let (resolving, connecting) = (self.clone(), self.clone());
+            let router = Router::new(
+                move |route| {
+                    let cluster = resolving.clone();
+                    async move {
+                        cluster.resolved.fetch_add(1, Ordering::SeqCst);
+                        let id = match route {
+                            RouteTo::Member(id) => id,
+                            _ => cluster.leader.load(Ordering::SeqCst),
+                        };
+                        let (_, name) = MEMBERS
+                            .into_iter()
+                            .find(|(member, _)| *member == id)
+                            .ok_or(Error::MemberNotFound(id))?;
+                        Ok(Target {
+                            uri: format!("http://{}", name).parse()?,
+                            raft_term: cluster.raft_term.load(Ordering::SeqCst),
+                        })
+                    }
+                },
+                move |uri| {
+                    let (id, name) = MEMBERS
+                        .into_iter()
+                        .find(|(_, name)| uri.host() == Some(name))
+                        .unwrap();
+                    Ok(connecting.channel(id, name))
+                },
+                AuthToken::default(),
+                Compression::new(),
+            )
+            .with_strict(strict);
+            KvClient::new(self.channel(0, "balanced"), AuthToken::default())
+                .with_router(Arc::new(router))
+        }
+    }
+
+    fn routed(route: RouteTo) -> Option<GetOptions> {
+        Some(GetOptions::new().with_route(route))
+    }
+
+    #[tokio::test]
+    async fn test_route_member() {
+        let cluster = Cluster::new(1);
+        let mut client = cluster.client(false);
+        client.get("key", routed(RouteTo::Member(2))).await.unwrap();
+        client.get("key", routed(RouteTo::Member(3))).await.unwrap();
+        client.get("key", routed(RouteTo::Any)).await.unwrap();
+        client.get("key", None).await.unwrap();
+        assert_eq!(cluster.served(), ["b", "c", "balanced", "balanced"]);
+
+        // An unknown member falls back to any member, unless strict.
+        client.get("key", routed(RouteTo::Member(9))).await.unwrap();
+        assert_eq!(cluster.served(), ["balanced"]);
+        let mut client = cluster.client(true);
+        let err = client
+            .get("key", routed(RouteTo::Member(9)))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                Error::RouteUnreachable {
+                    route: RouteTo::Member(9),
+                    source,
+                } if matches!(**source, Error::MemberNotFound(9))
+            ),
+            "{:?}",
+            err
+        );
+        assert!(cluster.served().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_route_leader() {
+        let cluster = Cluster::new(1);
+        let mut client = cluster.client(false);
+        client.get("key", routed(RouteTo::Leader)).await.unwrap();
+        client.get("key", routed(RouteTo::Leader)).await.unwrap();
+        assert_eq!(cluster.served(), ["a", "a"]);
+        assert_eq!(cluster.resolved.load(Ordering::SeqCst), 1);
+
+        // A higher raft term tells that the leader may have changed.
+        cluster.leader.store(2, Ordering::SeqCst);
+        cluster.raft_term.store(3, Ordering::SeqCst);
+        client.get("key", routed(RouteTo::Leader)).await.unwrap();
+        client.get("key", routed(RouteTo::Leader)).await.unwrap();
+        client.get("key", routed(RouteTo::Leader)).await.unwrap();
+        assert_eq!(cluster.served(), ["a", "b", "b"]);
+        assert_eq!(cluster.resolved.load(Ordering::SeqCst), 2);
+
+        // So does a member answering that it is not the leader.
+        cluster.leader.store(3, Ordering::SeqCst);
+        cluster.deposed.store(2, Ordering::SeqCst);
+        let err = client
+            .get("key", routed(RouteTo::Leader))
+            .await
+            .unwrap_err();
+        assert!(err.is_not_leader(), "{:?}", err);
+        client.get("key", routed(RouteTo::Leader)).await.unwrap();
+        assert_eq!(cluster.served(), ["b", "c"]);
+        assert_eq!(cluster.resolved.load(Ordering::SeqCst), 3);
+    }
+}
@@ -2,10 +2,11 @@
 
 pub use crate::rpc::pb::authpb::permission::Type as PermissionType;
 
-use crate::auth::AuthService;
-use crate::error::Result;
+use crate::auth::{refresh_token, AuthService, AuthToken};
+use crate::compression::{Compressing, Compression};
+use crate::error::{Result, RpcResultExt};
 use crate::intercept::InterceptedChannel;
-use crate::lock::RwLockExt;
+use crate::observe::{observed, Observer};
 use crate::rpc::pb::authpb::{Permission as PbPermission, UserAddOptions as PbUserAddOptions};
 use crate::rpc::pb::etcdserverpb::auth_client::AuthClient as PbAuthClient;
 use crate::rpc::pb::etcdserverpb::{
@@ -35,62 +36,93 @@ use crate::rpc::pb::etcdserverpb::{
 };
 use crate::rpc::ResponseHeader;
 use crate::rpc::{get_prefix, KeyRange};
-use http::HeaderValue;
-use std::sync::RwLock;
-use std::{string::String, sync::Arc};
+use std::string::String;
 use tonic::{IntoRequest, Request};
 
 /// Client for Auth operations.
 #[derive(Clone)]
 pub struct AuthClient {
-    inner: PbAuthClient<AuthService<InterceptedChannel>>,
-    auth_token: Arc<RwLock<Option<HeaderValue>>>,
+    inner: Compressing<PbAuthClient<AuthService<InterceptedChannel>>>,
+    auth_token: AuthToken,
+    observer: Observer,
 }
 
 impl AuthClient {
     /// Creates an auth client.
     #[inline]
-    pub(crate) fn new(
-        channel: InterceptedChannel,
-        auth_token: Arc<RwLock<Option<HeaderValue>>>,
-    ) -> Self {
-        let inner = PbAuthClient::new(AuthService::new(channel, auth_token.clone()));
-        Self { inner, auth_token }
+    pub(crate) fn new(channel: InterceptedChannel, auth_token: AuthToken) -> Self {
+        let inner = Compressing::new(PbAuthClient::new(AuthService::new(
+            channel,
+            auth_token.clone(),
+        )));
+        Self {
+            inner,
+            auth_token,
+            observer: Observer::default(),
+        }
+    }
+
+    /// Observes requests with `observer`.
+    #[inline]
+    pub(crate) fn with_observer(mut self, observer: Observer) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Compresses the messages according to `compression`.
+    #[inline]
+    pub(crate) fn with_compression(mut self, compression: &Compression) -> Self {
+        self.inner = self.inner.with_compression(compression);
+        self
+    }
+
+    /// The auth token shared by the clients of a [`Client`](crate::Client).
+    #[inline]
+    pub(crate) fn auth_token(&self) -> &AuthToken {
+        &self.auth_token
     }
 
     /// Sets client-side authentication.
+    ///
+    /// Setting it concurrently, e.g. refreshing an expired token from several tasks, keeps
+    /// the token of the first to authenticate.
     pub async fn set_client_auth(&mut self, name: String, password: String) -> Result<()> {
+        let current = self.auth_token.load_full();
         let resp = self.authenticate(name, password).await?;
-        self.auth_token
-            .write_unpoisoned()
-            .replace(resp.token().parse()?);
+        refresh_token(&self.auth_token, &current, resp.token().parse()?);
         Ok(())
     }
 
     /// Removes client-side authentication.
     pub fn remove_client_auth(&mut self) {
-        self.auth_token.write_unpoisoned().take();
+        self.auth_token.store(None);
     }
 
     /// Enables authentication for the etcd cluster.
     #[inline]
     pub async fn auth_enable(&mut self) -> Result<AuthEnableResponse> {
-        let resp = self
-            .inner
-            .auth_enable(AuthEnableOptions::new())
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "AuthEnable",
+            self.inner.auth_enable(AuthEnableOptions::new())
+        )
+        .await
+        .for_rpc("AuthEnable")?
+        .into_inner();
         Ok(AuthEnableResponse::new(resp))
     }
 
     /// Disables authentication for the etcd cluster.
     #[inline]
     pub async fn auth_disable(&mut self) -> Result<AuthDisableResponse> {
-        let resp = self
-            .inner
-            .auth_disable(AuthDisableOptions::new())
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "AuthDisable",
+            self.inner.auth_disable(AuthDisableOptions::new())
+        )
+        .await
+        .for_rpc("AuthDisable")?
+        .into_inner();
         Ok(AuthDisableResponse::new(resp))
     }
 
@@ -103,55 +135,71 @@ impl AuthClient {
         name: String,
         password: String,
     ) -> Result<AuthenticateResponse> {
-        let resp = self
-            .inner
-            .authenticate(AuthenticateOptions::new().with_user(name, password))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "Authenticate",
+            self.inner
+                .authenticate(AuthenticateOptions::new().with_user(name, password))
+        )
+        .await
+        .for_rpc("Authenticate")?
+        .into_inner();
         Ok(AuthenticateResponse::new(resp))
     }
 
     /// Adds role
     #[inline]
     pub async fn role_add(&mut self, name: impl Into<String>) -> Result<RoleAddResponse> {
-        let resp = self
-            .inner
-            .role_add(RoleAddOptions::new(name.into()))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "RoleAdd",
+            self.inner.role_add(RoleAddOptions::new(name.into()))
+        )
+        .await
+        .for_rpc("RoleAdd")?
+        .into_inner();
         Ok(RoleAddResponse::new(resp))
     }
 
     /// Deletes role
     #[inline]
     pub async fn role_delete(&mut self, name: impl Into<String>) -> Result<RoleDeleteResponse> {
-        let resp = self
-            .inner
-            .role_delete(RoleDeleteOptions::new(name.into()))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "RoleDelete",
+            self.inner.role_delete(RoleDeleteOptions::new(name.into()))
+        )
+        .await
+        .for_rpc("RoleDelete")?
+        .into_inner();
         Ok(RoleDeleteResponse::new(resp))
     }
 
     /// Gets role
     #[inline]
     pub async fn role_get(&mut self, name: impl Into<String>) -> Result<RoleGetResponse> {
-        let resp = self
-            .inner
-            .role_get(RoleGetOptions::new(name.into()))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "RoleGet",
+            self.inner.role_get(RoleGetOptions::new(name.into()))
+        )
+        .await
+        .for_rpc("RoleGet")?
+        .into_inner();
         Ok(RoleGetResponse::new(resp))
     }
 
     /// Lists role
     #[inline]
     pub async fn role_list(&mut self) -> Result<RoleListResponse> {
-        let resp = self
-            .inner
-            .role_list(AuthRoleListOptions {})
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "RoleList",
+            self.inner.role_list(AuthRoleListOptions {})
+        )
+        .await
+        .for_rpc("RoleList")?
+        .into_inner();
         Ok(RoleListResponse::new(resp))
     }
 
@@ -162,11 +210,15 @@ impl AuthClient {
         name: impl Into<String>,
         perm: Permission,
     ) -> Result<RoleGrantPermissionResponse> {
-        let resp = self
-            .inner
-            .role_grant_permission(RoleGrantPermissionOptions::new(name.into(), perm))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "RoleGrantPermission",
+            self.inner
+                .role_grant_permission(RoleGrantPermissionOptions::new(name.into(), perm))
+        )
+        .await
+        .for_rpc("RoleGrantPermission")?
+        .into_inner();
         Ok(RoleGrantPermissionResponse::new(resp))
     }
 
@@ -178,16 +230,19 @@ impl AuthClient {
         key: impl Into<Vec<u8>>,
         options: Option<RoleRevokePermissionOptions>,
     ) -> Result<RoleRevokePermissionResponse> {
-        let resp = self
-            .inner
-            .role_revoke_permission(
+        let resp = observed!(
+            self.observer,
+            "RoleRevokePermission",
+            self.inner.role_revoke_permission(
                 options
                     .unwrap_or_default()
                     .with_name(name.into())
                     .with_key(key.into()),
             )
-            .await?
-            .into_inner();
+        )
+        .await
+        .for_rpc("RoleRevokePermission")?
+        .into_inner();
         Ok(RoleRevokePermissionResponse::new(resp))
     }
 
@@ -199,49 +254,61 @@ impl AuthClient {
         password: impl Into<String>,
         options: Option<UserAddOptions>,
     ) -> Result<UserAddResponse> {
-        let resp = self
-            .inner
-            .user_add(
+        let resp = observed!(
+            self.observer,
+            "UserAdd",
+            self.inner.user_add(
                 options
                     .unwrap_or_default()
                     .with_name(name.into())
                     .with_pwd(password.into()),
             )
-            .await?
-            .into_inner();
+        )
+        .await
+        .for_rpc("UserAdd")?
+        .into_inner();
         Ok(UserAddResponse::new(resp))
     }
 
     /// Gets user
     #[inline]
     pub async fn user_get(&mut self, name: impl Into<String>) -> Result<UserGetResponse> {
-        let resp = self
-            .inner
-            .user_get(UserGetOptions::new(name.into()))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "UserGet",
+            self.inner.user_get(UserGetOptions::new(name.into()))
+        )
+        .await
+        .for_rpc("UserGet")?
+        .into_inner();
         Ok(UserGetResponse::new(resp))
     }
 
     /// Lists user
     #[inline]
     pub async fn user_list(&mut self) -> Result<UserListResponse> {
-        let resp = self
-            .inner
-            .user_list(AuthUserListOptions {})
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "UserList",
+            self.inner.user_list(AuthUserListOptions {})
+        )
+        .await
+        .for_rpc("UserList")?
+        .into_inner();
         Ok(UserListResponse::new(resp))
     }
 
     /// Deletes user
     #[inline]
     pub async fn user_delete(&mut self, name: impl Into<String>) -> Result<UserDeleteResponse> {
-        let resp = self
-            .inner
-            .user_delete(UserDeleteOptions::new(name.into()))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "UserDelete",
+            self.inner.user_delete(UserDeleteOptions::new(name.into()))
+        )
+        .await
+        .for_rpc("UserDelete")?
+        .into_inner();
         Ok(UserDeleteResponse::new(resp))
     }
 
@@ -252,11 +319,15 @@ impl AuthClient {
         name: impl Into<String>,
         password: impl Into<String>,
     ) -> Result<UserChangePasswordResponse> {
-        let resp = self
-            .inner
-            .user_change_password(UserChangePasswordOptions::new(name.into(), password.into()))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "UserChangePassword",
+            self.inner
+                .user_change_password(UserChangePasswordOptions::new(name.into(), password.into()))
+        )
+        .await
+        .for_rpc("UserChangePassword")?
+        .into_inner();
         Ok(UserChangePasswordResponse::new(resp))
     }
 
@@ -267,11 +338,15 @@ impl AuthClient {
         name: impl Into<String>,
         role: impl Into<String>,
     ) -> Result<UserGrantRoleResponse> {
-        let resp = self
-            .inner
-            .user_grant_role(UserGrantRoleOptions::new(name.into(), role.into()))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "UserGrantRole",
+            self.inner
+                .user_grant_role(UserGrantRoleOptions::new(name.into(), role.into()))
+        )
+        .await
+        .for_rpc("UserGrantRole")?
+        .into_inner();
         Ok(UserGrantRoleResponse::new(resp))
     }
 
@@ -282,17 +357,26 @@ impl AuthClient {
         name: impl Into<String>,
         role: impl Into<String>,
     ) -> Result<UserRevokeRoleResponse> {
-        let resp = self
-            .inner
-            .user_revoke_role(UserRevokeRoleOptions::new(name.into(), role.into()))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "UserRevokeRole",
+            self.inner
+                .user_revoke_role(UserRevokeRoleOptions::new(name.into(), role.into()))
+        )
+        .await
+        .for_rpc("UserRevokeRole")?
+        .into_inner();
         Ok(UserRevokeRoleResponse::new(resp))
     }
 }
 
 /// Options for `AuthEnable` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct AuthEnableOptions(PbAuthEnableRequest);
 
 impl AuthEnableOptions {
@@ -320,6 +404,11 @@ impl IntoRequest<PbAuthEnableRequest> for AuthEnableOptions {
 /// Response for `AuthEnable` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct AuthEnableResponse(PbAuthEnableResponse);
 
@@ -345,6 +434,11 @@ impl AuthEnableResponse {
 
 /// Options for `AuthDisable` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct AuthDisableOptions(PbAuthDisableRequest);
 
 impl AuthDisableOptions {
@@ -372,6 +466,11 @@ impl IntoRequest<PbAuthDisableRequest> for AuthDisableOptions {
 /// Response for `AuthDisable` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct AuthDisableResponse(PbAuthDisableResponse);
 
@@ -397,6 +496,11 @@ impl AuthDisableResponse {
 
 /// Options for `Authenticate` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct AuthenticateOptions(PbAuthenticateRequest);
 
@@ -436,6 +540,11 @@ impl IntoRequest<PbAuthenticateRequest> for AuthenticateOptions {
 /// Response for `Authenticate` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct AuthenticateResponse(PbAuthenticateResponse);
 
@@ -469,6 +578,11 @@ impl AuthenticateResponse {
 
 /// Options for `RoleAddOptions` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct RoleAddOptions(PbAuthRoleAddRequest);
 
@@ -497,6 +611,11 @@ impl IntoRequest<PbAuthRoleAddRequest> for RoleAddOptions {
 /// Response for role add operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct RoleAddResponse(PbAuthRoleAddResponse);
 
@@ -522,6 +641,11 @@ impl RoleAddResponse {
 
 /// Options for delete role operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct RoleDeleteOptions(PbAuthRoleDeleteRequest);
 
@@ -550,6 +674,11 @@ impl IntoRequest<PbAuthRoleDeleteRequest> for RoleDeleteOptions {
 /// Response for delete role operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct RoleDeleteResponse(PbAuthRoleDeleteResponse);
 
@@ -575,6 +704,11 @@ impl RoleDeleteResponse {
 
 /// Options for get role operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct RoleGetOptions(PbAuthRoleGetRequest);
 
@@ -803,6 +937,11 @@ impl From<Permission> for PbPermission {
 /// Response for get role operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct RoleGetResponse(PbAuthRoleGetResponse);
 
@@ -842,6 +981,11 @@ use PbAuthRoleListRequest as AuthRoleListOptions;
 /// Response for list role operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct RoleListResponse(PbAuthRoleListResponse);
 
@@ -873,6 +1017,11 @@ impl RoleListResponse {
 
 /// Options for grant role permission operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct RoleGrantPermissionOptions(PbAuthRoleGrantPermissionRequest);
 
@@ -904,6 +1053,11 @@ impl IntoRequest<PbAuthRoleGrantPermissionRequest> for RoleGrantPermissionOption
 /// Response for grant role permission operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct RoleGrantPermissionResponse(PbAuthRoleGrantPermissionResponse);
 
@@ -1012,6 +1166,11 @@ impl IntoRequest<PbAuthRoleRevokePermissionRequest> for RoleRevokePermissionOpti
 /// Response for revoke role permission operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct RoleRevokePermissionResponse(PbAuthRoleRevokePermissionResponse);
 
@@ -1037,6 +1196,11 @@ impl RoleRevokePermissionResponse {
 
 /// Options for `UserAdd` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserAddOptions(PbAuthUserAddRequest);
 
@@ -1090,6 +1254,11 @@ impl IntoRequest<PbAuthUserAddRequest> for UserAddOptions {
 /// Response for use add operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserAddResponse(PbAuthUserAddResponse);
 
@@ -1115,6 +1284,11 @@ impl UserAddResponse {
 
 /// Options for get user operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserGetOptions(PbAuthUserGetRequest);
 
@@ -1143,6 +1317,11 @@ impl IntoRequest<PbAuthUserGetRequest> for UserGetOptions {
 /// Response for get user operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserGetResponse(PbAuthUserGetResponse);
 
@@ -1178,6 +1357,11 @@ use PbAuthUserListRequest as AuthUserListOptions;
 /// Response for list user operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserListResponse(PbAuthUserListResponse);
 
@@ -1209,6 +1393,11 @@ impl UserListResponse {
 
 /// Options for delete user operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserDeleteOptions(PbAuthUserDeleteRequest);
 
@@ -1237,6 +1426,11 @@ impl IntoRequest<PbAuthUserDeleteRequest> for UserDeleteOptions {
 /// Response for delete user operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserDeleteResponse(PbAuthUserDeleteResponse);
 
@@ -1262,6 +1456,11 @@ impl UserDeleteResponse {
 
 /// Options for change user's password operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserChangePasswordOptions(PbAuthUserChangePasswordRequest);
 
@@ -1293,6 +1492,11 @@ impl IntoRequest<PbAuthUserChangePasswordRequest> for UserChangePasswordOptions
 /// Response for change user's password operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserChangePasswordResponse(PbAuthUserChangePasswordResponse);
 
@@ -1318,6 +1522,11 @@ impl UserChangePasswordResponse {
 
 /// Options for grant role for an user operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserGrantRoleOptions(PbAuthUserGrantRoleRequest);
 
@@ -1346,6 +1555,11 @@ impl IntoRequest<PbAuthUserGrantRoleRequest> for UserGrantRoleOptions {
 /// Response for grant role for an user operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserGrantRoleResponse(PbAuthUserGrantRoleResponse);
 
@@ -1371,6 +1585,11 @@ impl UserGrantRoleResponse {
 
 /// Options for revoke role for an user operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserRevokeRoleOptions(PbAuthUserRevokeRoleRequest);
 
@@ -1399,6 +1618,11 @@ impl IntoRequest<PbAuthUserRevokeRoleRequest> for UserRevokeRoleOptions {
 /// Response for revoke role for an user operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UserRevokeRoleResponse(PbAuthUserRevokeRoleResponse);
 
@@ -1421,3 +1645,39 @@ impl UserRevokeRoleResponse {
         self.0.header.take().map(ResponseHeader::new)
     }
 }
+
+impl_raw_conversions!(
+    AuthEnableResponse(PbAuthEnableResponse),
+    AuthDisableResponse(PbAuthDisableResponse),
+    AuthenticateResponse(PbAuthenticateResponse),
+    RoleAddResponse(PbAuthRoleAddResponse),
+    RoleDeleteResponse(PbAuthRoleDeleteResponse),
+    RoleGetResponse(PbAuthRoleGetResponse),
+    RoleListResponse(PbAuthRoleListResponse),
+    RoleGrantPermissionResponse(PbAuthRoleGrantPermissionResponse),
+    RoleRevokePermissionResponse(PbAuthRoleRevokePermissionResponse),
+    UserAddResponse(PbAuthUserAddResponse),
+    UserGetResponse(PbAuthUserGetResponse),
+    UserListResponse(PbAuthUserListResponse),
+    UserDeleteResponse(PbAuthUserDeleteResponse),
+    UserChangePasswordResponse(PbAuthUserChangePasswordResponse),
+    UserGrantRoleResponse(PbAuthUserGrantRoleResponse),
+    UserRevokeRoleResponse(PbAuthUserRevokeRoleResponse),
+);
+
+impl_raw_conversions!(
+    options:
+    AuthEnableOptions(PbAuthEnableRequest),
+    AuthDisableOptions(PbAuthDisableRequest),
+    AuthenticateOptions(PbAuthenticateRequest),
+    RoleAddOptions(PbAuthRoleAddRequest),
+    RoleDeleteOptions(PbAuthRoleDeleteRequest),
+    RoleGetOptions(PbAuthRoleGetRequest),
+    RoleGrantPermissionOptions(PbAuthRoleGrantPermissionRequest),
+    UserAddOptions(PbAuthUserAddRequest),
+    UserGetOptions(PbAuthUserGetRequest),
+    UserDeleteOptions(PbAuthUserDeleteRequest),
+    UserChangePasswordOptions(PbAuthUserChangePasswordRequest),
+    UserGrantRoleOptions(PbAuthUserGrantRoleRequest),
+    UserRevokeRoleOptions(PbAuthUserRevokeRoleRequest),
+);
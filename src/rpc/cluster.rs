@@ -1,8 +1,15 @@
 //! Etcd Cluster RPC.
 
-use crate::auth::AuthService;
-use crate::error::Result;
+use crate::auth::{AuthService, AuthToken};
+use crate::client::Connector;
+use crate::compression::{Compressing, Compression};
+use crate::deadline::CallOptions;
+use crate::error::{Error, Result, RpcResultExt};
 use crate::intercept::InterceptedChannel;
+use crate::observe::{observed, Observer};
+use crate::retry::{retry, RetryPolicy, DEFAULT_READ_RETRIES};
+#[cfg(feature = "maintenance")]
+use crate::rpc::maintenance::{MaintenanceClient, StatusResponse};
 use crate::rpc::pb::etcdserverpb::cluster_client::ClusterClient as PbClusterClient;
 use crate::rpc::pb::etcdserverpb::{
     Member as PbMember, MemberAddRequest as PbMemberAddRequest,
@@ -13,27 +20,101 @@ use crate::rpc::pb::etcdserverpb::{
     MemberUpdateResponse as PbMemberUpdateResponse,
 };
 use crate::rpc::ResponseHeader;
-use http::HeaderValue;
-use std::sync::RwLock;
+use std::mem::ManuallyDrop;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use std::{string::String, sync::Arc};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 use tonic::{IntoRequest, Request};
 
+/// The last member list, shared by all clones of a client.
+#[derive(Default)]
+struct MembersCache {
+    /// The list and when it was fetched. The lock is held while refreshing, so that
+    /// concurrent callers wait for a single request.
+    members: Mutex<Option<(Instant, u64, MemberListResponse)>>,
+    /// Bumped on invalidation, a list fetched at an older generation is stale.
+    generation: AtomicU64,
+}
+
 /// Client for Cluster operations.
-#[repr(transparent)]
 #[derive(Clone)]
 pub struct ClusterClient {
-    inner: PbClusterClient<AuthService<InterceptedChannel>>,
+    inner: Compressing<PbClusterClient<AuthService<InterceptedChannel>>>,
+    channel: InterceptedChannel,
+    auth_token: AuthToken,
+    connector: Option<Connector>,
+    members_cache: Arc<MembersCache>,
+    retry: Option<RetryPolicy>,
+    read_retries: u32,
+    default_deadline: Option<Duration>,
+    observer: Observer,
 }
 
 impl ClusterClient {
     /// Creates an Cluster client.
     #[inline]
-    pub(crate) fn new(
-        channel: InterceptedChannel,
-        auth_token: Arc<RwLock<Option<HeaderValue>>>,
-    ) -> Self {
-        let inner = PbClusterClient::new(AuthService::new(channel, auth_token));
-        Self { inner }
+    pub(crate) fn new(channel: InterceptedChannel, auth_token: AuthToken) -> Self {
+        let inner = Compressing::new(PbClusterClient::new(AuthService::new(
+            channel.clone(),
+            auth_token.clone(),
+        )));
+        Self {
+            inner,
+            channel,
+            auth_token,
+            connector: None,
+            members_cache: Arc::default(),
+            retry: None,
+            read_retries: DEFAULT_READ_RETRIES,
+            default_deadline: None,
+            observer: Observer::default(),
+        }
+    }
+
+    /// Retries requests failing with transient errors according to `policy`.
+    #[inline]
+    pub(crate) fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Retries read-only requests failing because the leader changed or the request timed
+    /// out up to `read_retries` times.
+    #[inline]
+    pub(crate) fn with_read_retries(mut self, read_retries: u32) -> Self {
+        self.read_retries = read_retries;
+        self
+    }
+
+    /// Aborts requests which have no deadline of their own after `deadline`.
+    #[inline]
+    pub(crate) fn with_default_deadline(mut self, deadline: Duration) -> Self {
+        self.default_deadline = Some(deadline);
+        self
+    }
+
+    /// Observes requests with `observer`.
+    #[inline]
+    pub(crate) fn with_observer(mut self, observer: Observer) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Compresses the messages according to `compression`.
+    #[inline]
+    pub(crate) fn with_compression(mut self, compression: &Compression) -> Self {
+        self.inner = self.inner.with_compression(compression);
+        self
+    }
+
+    /// Allows the client to connect to single members, used to check the learner progress.
+    #[inline]
+    pub(crate) fn with_connector(mut self, connector: Connector) -> Self {
+        self.connector = Some(connector);
+        self
     }
 
     /// Adds a new member into the cluster.
@@ -43,11 +124,16 @@ impl ClusterClient {
         urls: impl Into<Vec<String>>,
         options: Option<MemberAddOptions>,
     ) -> Result<MemberAddResponse> {
-        let resp = self
-            .inner
-            .member_add(options.unwrap_or_default().with_urls(urls))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "MemberAdd",
+            self.inner
+                .member_add(options.unwrap_or_default().with_urls(urls))
+        )
+        .await
+        .for_rpc("MemberAdd")?
+        .into_inner();
+        self.invalidate_members_cache();
 
         Ok(MemberAddResponse::new(resp))
     }
@@ -55,11 +141,16 @@ impl ClusterClient {
     /// Removes an existing member from the cluster.
     #[inline]
     pub async fn member_remove(&mut self, id: u64) -> Result<MemberRemoveResponse> {
-        let resp = self
-            .inner
-            .member_remove(MemberRemoveOptions::new().with_id(id))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "MemberRemove",
+            self.inner
+                .member_remove(MemberRemoveOptions::new().with_id(id))
+        )
+        .await
+        .for_rpc("MemberRemove")?
+        .into_inner();
+        self.invalidate_members_cache();
         Ok(MemberRemoveResponse::new(resp))
     }
 
@@ -70,39 +161,243 @@ impl ClusterClient {
         id: u64,
         url: impl Into<Vec<String>>,
     ) -> Result<MemberUpdateResponse> {
-        let resp = self
-            .inner
-            .member_update(MemberUpdateOptions::new().with_option(id, url))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "MemberUpdate",
+            self.inner
+                .member_update(MemberUpdateOptions::new().with_option(id, url))
+        )
+        .await
+        .for_rpc("MemberUpdate")?
+        .into_inner();
+        self.invalidate_members_cache();
         Ok(MemberUpdateResponse::new(resp))
     }
 
     /// Lists all the members in the cluster.
     #[inline]
     pub async fn member_list(&mut self) -> Result<MemberListResponse> {
-        let resp = self
-            .inner
-            .member_list(PbMemberListRequest {})
-            .await?
-            .into_inner();
+        self.member_list_with_options(None).await
+    }
+
+    /// Lists all the members in the cluster with options.
+    #[inline]
+    pub async fn member_list_with_options(
+        &mut self,
+        options: Option<MemberListOptions>,
+    ) -> Result<MemberListResponse> {
+        let mut options = options.unwrap_or_default();
+        let call = std::mem::take(&mut options.1);
+        let inner = self.inner.clone();
+        let observer = self.observer.clone();
+        let resp = call
+            .run(
+                "MemberList",
+                self.default_deadline,
+                retry(
+                    self.retry.as_ref(),
+                    self.read_retries,
+                    "MemberList",
+                    true,
+                    move || {
+                        let mut inner = inner.clone();
+                        let observer = observer.clone();
+                        let options = options.clone();
+                        async move {
+                            Ok(
+                                observed!(observer, "MemberList", inner.member_list(options))
+                                    .await
+                                    .for_rpc("MemberList")?
+                                    .into_inner(),
+                            )
+                        }
+                    },
+                ),
+            )
+            .await?;
         Ok(MemberListResponse::new(resp))
     }
 
+    /// Lists all the members in the cluster, reusing the last list if it is not older than
+    /// `max_age`.
+    ///
+    /// The cache is shared by all clones of the client, and concurrent callers finding it
+    /// stale wait for a single refresh. Membership changes made through this client
+    /// invalidate the cache.
+    pub async fn members_cached(&mut self, max_age: Duration) -> Result<MemberListResponse> {
+        let cache = self.members_cache.clone();
+        // A cancelled caller drops the guard before the cache is written, leaving it as it was.
+        let mut members = cache.members.lock().await;
+        let generation = cache.generation.load(Ordering::Acquire);
+        if let Some((fetched, fetched_generation, resp)) = members.as_ref() {
+            if *fetched_generation == generation && fetched.elapsed() <= max_age {
+                return Ok(resp.clone());
+            }
+        }
+
+        let resp = self.member_list().await?;
+        *members = Some((Instant::now(), generation, resp.clone()));
+        Ok(resp)
+    }
+
+    /// Drops the cached member list, so that the next `members_cached` call refreshes it.
+    #[inline]
+    pub fn invalidate_members_cache(&self) {
+        self.members_cache.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
     /// Promotes a member from raft learner (non-voting) to raft voting member.
     #[inline]
     pub async fn member_promote(&mut self, id: u64) -> Result<MemberPromoteResponse> {
-        let resp = self
-            .inner
-            .member_promote(MemberPromoteOptions::new().with_id(id))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "MemberPromote",
+            self.inner
+                .member_promote(MemberPromoteOptions::new().with_id(id))
+        )
+        .await
+        .for_rpc("MemberPromote")?
+        .into_inner();
+        self.invalidate_members_cache();
         Ok(MemberPromoteResponse::new(resp))
     }
+
+    /// Adds a new member as a learner and promotes it once it is in sync with the leader.
+    ///
+    /// The progress of the learner is checked by comparing its raft index with the one of the
+    /// leader, which requires the learner to be started with its client URLs. If the learner
+    /// is not promoted within the timeout, it is left in the cluster as a learner.
+    pub async fn add_and_promote(
+        &mut self,
+        urls: impl Into<Vec<String>>,
+        options: Option<PromoteOptions>,
+    ) -> Result<MemberPromoteResponse> {
+        let options = options.unwrap_or_default();
+        let resp = self
+            .member_add(urls, Some(MemberAddOptions::new().with_learner()))
+            .await?;
+        let Some(id) = resp.member().map(Member::id) else {
+            return Err(Error::InvalidArgs(String::from(
+                "added member is missing in response",
+            )));
+        };
+
+        let deadline = Instant::now() + options.timeout;
+        loop {
+            if self.is_learner_in_sync(id).await {
+                match self.member_promote(id).await {
+                    Err(e) if e.is_learner_not_ready() => {}
+                    resp => return resp,
+                }
+            }
+
+            if Instant::now() + options.poll_interval > deadline {
+                return Err(Error::GRpcStatus(tonic::Status::deadline_exceeded(
+                    format!("learner {:x} is not in sync with the leader", id),
+                )));
+            }
+            tokio::time::sleep(options.poll_interval).await;
+        }
+    }
+
+    /// Checks whether the learner has applied most of the leader's raft log,
+    /// the same ratio etcd requires for promotion.
+    #[cfg(feature = "maintenance")]
+    async fn is_learner_in_sync(&self, id: u64) -> bool {
+        let Some(connector) = self.connector.clone() else {
+            // Without access to single members, leave the check to the server.
+            return true;
+        };
+
+        let mut maintenance = MaintenanceClient::new(self.channel.clone(), self.auth_token.clone())
+            .with_connector(connector);
+        let statuses: Vec<StatusResponse> = match maintenance.status_all().await {
+            Ok(statuses) => statuses
+                .into_iter()
+                .filter_map(|(_, status)| status.ok())
+                .collect(),
+            Err(_) => return false,
+        };
+
+        let member_id = |status: &StatusResponse| status.header().map(|h| h.member_id());
+        let learner = statuses.iter().find(|s| member_id(s) == Some(id));
+        let leader = learner.and_then(|learner| {
+            statuses
+                .iter()
+                .find(|s| member_id(s) == Some(learner.leader()))
+        });
+        match (learner, leader) {
+            (Some(learner), Some(leader)) => {
+                learner.raft_applied_index() as f64
+                    >= leader.raft_index() as f64 * LEARNER_READY_PERCENT
+            }
+            _ => false,
+        }
+    }
+
+    /// Without the maintenance service to query the members' status, leaves the
+    /// check to the server.
+    #[cfg(not(feature = "maintenance"))]
+    async fn is_learner_in_sync(&self, _id: u64) -> bool {
+        true
+    }
+}
+
+/// The ratio of the leader's raft log a learner must have applied to be promoted.
+const LEARNER_READY_PERCENT: f64 = 0.9;
+
+/// The default interval of checking the progress of a learner.
+pub const DEFAULT_PROMOTE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The default timeout of waiting for a learner to be in sync.
+pub const DEFAULT_PROMOTE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Options for `add_and_promote` operation.
+#[derive(Debug, Clone)]
+pub struct PromoteOptions {
+    poll_interval: Duration,
+    timeout: Duration,
+}
+
+impl PromoteOptions {
+    /// Creates a `PromoteOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            poll_interval: DEFAULT_PROMOTE_POLL_INTERVAL,
+            timeout: DEFAULT_PROMOTE_TIMEOUT,
+        }
+    }
+
+    /// Sets the interval of checking the progress of the learner.
+    #[inline]
+    pub const fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the timeout of waiting for the learner to be in sync.
+    #[inline]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Default for PromoteOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Options for `MemberAdd` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct MemberAddOptions(PbMemberAddRequest);
 
@@ -128,6 +423,12 @@ impl MemberAddOptions {
         self.0.is_learner = true;
         self
     }
+
+    /// Adds the member as a non-voting learner, the same as [`MemberAddOptions::with_is_learner`].
+    #[inline]
+    pub const fn with_learner(self) -> Self {
+        self.with_is_learner()
+    }
 }
 
 impl From<MemberAddOptions> for PbMemberAddRequest {
@@ -147,6 +448,11 @@ impl IntoRequest<PbMemberAddRequest> for MemberAddOptions {
 /// Response for `MemberAdd` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct MemberAddResponse(PbMemberAddResponse);
 
@@ -184,6 +490,11 @@ impl MemberAddResponse {
 
 /// Options for `MemberRemove` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 // #[repr(transparent)]
 pub struct MemberRemoveOptions(PbMemberRemoveRequest);
 
@@ -219,6 +530,11 @@ impl IntoRequest<PbMemberRemoveRequest> for MemberRemoveOptions {
 /// Response for `MemberRemove` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct MemberRemoveResponse(PbMemberRemoveResponse);
 
@@ -250,6 +566,11 @@ impl MemberRemoveResponse {
 
 /// Options for `MemberUpdate` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 // #[repr(transparent)]
 pub struct MemberUpdateOptions(PbMemberUpdateRequest);
 
@@ -288,6 +609,11 @@ impl IntoRequest<PbMemberUpdateRequest> for MemberUpdateOptions {
 /// Response for `MemberUpdate` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct MemberUpdateResponse(PbMemberUpdateResponse);
 
@@ -317,9 +643,85 @@ impl MemberUpdateResponse {
     }
 }
 
+/// Options for `MemberList` operation.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct MemberListOptions(
+    PbMemberListRequest,
+    #[cfg_attr(feature = "serde", serde(skip))] CallOptions,
+);
+
+impl MemberListOptions {
+    /// Creates a `MemberListOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self(
+            PbMemberListRequest {
+                linearizable: false,
+            },
+            CallOptions::new(),
+        )
+    }
+
+    /// Aborts the request if it has not completed by `deadline`, including its retries.
+    #[inline]
+    pub fn with_deadline(mut self, deadline: std::time::Instant) -> Self {
+        self.1.set_deadline(deadline);
+        self
+    }
+
+    /// Aborts the request when `token` is cancelled.
+    #[inline]
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.1.set_cancel(token);
+        self
+    }
+
+    /// Sends the metadata `key: value` with the request, replacing the metadata of the client
+    /// under the same key, see
+    /// [`ConnectOptions::with_metadata`](crate::ConnectOptions::with_metadata).
+    #[inline]
+    pub fn with_metadata(mut self, key: &str, value: impl AsRef<[u8]>) -> Result<Self> {
+        self.1.append_metadata(key, value.as_ref())?;
+        Ok(self)
+    }
+
+    /// Lists the committed membership of the cluster instead of the view of the local member.
+    ///
+    /// Supported since etcd 3.5.
+    #[inline]
+    pub const fn with_linearizable(mut self) -> Self {
+        self.0.linearizable = true;
+        self
+    }
+}
+
+impl From<MemberListOptions> for PbMemberListRequest {
+    #[inline]
+    fn from(options: MemberListOptions) -> Self {
+        options.0
+    }
+}
+
+impl IntoRequest<PbMemberListRequest> for MemberListOptions {
+    #[inline]
+    fn into_request(self) -> Request<PbMemberListRequest> {
+        Request::new(self.into())
+    }
+}
+
 /// Response for `MemberList` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct MemberListResponse(PbMemberListResponse);
 
@@ -347,11 +749,41 @@ impl MemberListResponse {
     pub fn members(&self) -> &[Member] {
         unsafe { &*(self.0.members.as_slice() as *const _ as *const [Member]) }
     }
+
+    /// Takes the members out of the response, leaving an empty vector in its place.
+    #[inline]
+    pub fn take_members(&mut self) -> Vec<Member> {
+        let members = ManuallyDrop::new(std::mem::take(&mut self.0.members));
+        unsafe {
+            Vec::from_raw_parts(
+                members.as_ptr() as *mut Member,
+                members.len(),
+                members.capacity(),
+            )
+        }
+    }
+
+    /// The voting members of the cluster.
+    #[inline]
+    pub fn voting_members(&self) -> impl Iterator<Item = &Member> {
+        self.members().iter().filter(|member| !member.is_learner())
+    }
+
+    /// The learner members of the cluster.
+    #[inline]
+    pub fn learners(&self) -> impl Iterator<Item = &Member> {
+        self.members().iter().filter(|member| member.is_learner())
+    }
 }
 
 /// Cluster member.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct Member(PbMember);
 
@@ -385,6 +817,24 @@ impl Member {
     pub const fn is_learner(&self) -> bool {
         self.0.is_learner
     }
+
+    /// Takes the name of the member, leaving an empty string in its place.
+    #[inline]
+    pub fn take_name(&mut self) -> String {
+        std::mem::take(&mut self.0.name)
+    }
+
+    /// Takes the peer URLs of the member, leaving an empty vector in its place.
+    #[inline]
+    pub fn take_peer_urls(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.0.peer_ur_ls)
+    }
+
+    /// Takes the client URLs of the member, leaving an empty vector in its place.
+    #[inline]
+    pub fn take_client_urls(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.0.client_ur_ls)
+    }
 }
 
 impl From<&PbMember> for &Member {
@@ -396,6 +846,11 @@ impl From<&PbMember> for &Member {
 
 /// Options for `MemberPromote` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct MemberPromoteOptions(PbMemberPromoteRequest);
 
@@ -431,6 +886,11 @@ impl IntoRequest<PbMemberPromoteRequest> for MemberPromoteOptions {
 /// Response for `MemberPromote` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct MemberPromoteResponse(PbMemberPromoteResponse);
 
@@ -459,3 +919,96 @@ impl MemberPromoteResponse {
         unsafe { &*(self.0.members.as_slice() as *const _ as *const [Member]) }
     }
 }
+
+impl_raw_conversions!(
+    MemberAddResponse(PbMemberAddResponse),
+    MemberRemoveResponse(PbMemberRemoveResponse),
+    MemberUpdateResponse(PbMemberUpdateResponse),
+    MemberListResponse(PbMemberListResponse),
+    Member(PbMember),
+    MemberPromoteResponse(PbMemberPromoteResponse),
+);
+
+impl_raw_conversions!(
+    options:
+    MemberAddOptions(PbMemberAddRequest),
+    MemberRemoveOptions(PbMemberRemoveRequest),
+    MemberUpdateOptions(PbMemberUpdateRequest),
+    MemberListOptions(PbMemberListRequest, CallOptions),
+    MemberPromoteOptions(PbMemberPromoteRequest),
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::Channel;
+    use crate::intercept::Interceptor;
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+    use prost::Message;
+    use std::sync::atomic::AtomicUsize;
+    use tonic::codegen::Bytes;
+    use tower::util::BoxCloneService;
+    use tower::ServiceExt;
+
+    /// A client answering every member list with an empty list, counting the requests.
+    fn counting_client(requests: Arc<AtomicUsize>) -> ClusterClient {
+        let service = tower::service_fn(move |_req: http::Request<tonic::body::Body>| {
+            requests.fetch_add(1, Ordering::SeqCst);
+            async move {
+                // Keep the request in flight for a while, so that callers overlap.
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let msg = PbMemberListResponse::default();
+                let mut buf = vec![0];
+                buf.extend_from_slice(&(msg.encoded_len() as u32).to_be_bytes());
+                msg.encode(&mut buf).unwrap();
+                let mut trailers = http::HeaderMap::new();
+                tonic::Status::ok("").add_header(&mut trailers).unwrap();
+                let frames = [
+                    Ok::<_, tower::BoxError>(Frame::data(Bytes::from(buf))),
+                    Ok(Frame::trailers(trailers)),
+                ];
+
+                let body = tonic::body::Body::new(StreamBody::new(tokio_stream::iter(frames)));
+                let resp = http::Response::builder()
+                    .header("content-type", "application/grpc")
+                    .body(body)
+                    .unwrap();
+                Ok::<_, tower::BoxError>(resp)
+            }
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor::default(),
+        );
+        ClusterClient::new(channel, AuthToken::default())
+    }
+
+    #[tokio::test]
+    async fn test_members_cached() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let client = counting_client(requests.clone());
+        let max_age = Duration::from_secs(60);
+
+        let callers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut client = client.clone();
+                tokio::spawn(async move { client.members_cached(max_age).await })
+            })
+            .collect();
+        for caller in callers {
+            caller.await.unwrap().unwrap();
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        client.clone().members_cached(max_age).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        client.invalidate_members_cache();
+        client.clone().members_cached(max_age).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        client.clone().members_cached(Duration::ZERO).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+}
@@ -1,8 +1,16 @@
 //! Etcd Election RPC.
 
-use crate::auth::AuthService;
-use crate::error::Result;
+use crate::auth::{AuthService, AuthToken};
+use crate::compression::{Compressing, Compression};
+#[cfg(feature = "kv")]
+use crate::contender::Contender;
+use crate::error::{Error, Result, RpcResultExt};
+use crate::ids::{LeaseId, Revision};
 use crate::intercept::InterceptedChannel;
+use crate::observe::{observe_call, observed, Call, Observer};
+#[cfg(feature = "kv")]
+use crate::rpc::kv::KvClient;
+use crate::rpc::lease::LeaseClient;
 use crate::rpc::pb::v3electionpb::election_client::ElectionClient as PbElectionClient;
 use crate::rpc::pb::v3electionpb::{
     CampaignRequest as PbCampaignRequest, CampaignResponse as PbCampaignResponse,
@@ -10,23 +18,38 @@ use crate::rpc::pb::v3electionpb::{
     ProclaimRequest as PbProclaimRequest, ProclaimResponse as PbProclaimResponse,
     ResignRequest as PbResignRequest, ResignResponse as PbResignResponse,
 };
+use crate::rpc::watch::{EventType, WatchClient, WatchFilterType, WatchOptions};
 use crate::rpc::{KeyValue, ResponseHeader};
-use http::HeaderValue;
-use std::sync::RwLock;
-use std::task::{Context, Poll};
-use std::{pin::Pin, sync::Arc};
+use crate::session::{Session, SessionOptions};
+use crate::task::{Task, Tasks};
+use crate::trace::stream_event;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
 use tokio_stream::Stream;
 use tonic::{IntoRequest, Request, Streaming};
 
 /// Client for Elect operations.
-#[repr(transparent)]
 #[derive(Clone)]
 pub struct ElectionClient {
-    inner: PbElectionClient<AuthService<InterceptedChannel>>,
+    inner: Compressing<PbElectionClient<AuthService<InterceptedChannel>>>,
+    lease: LeaseClient,
+    watch: WatchClient,
+    /// The client listing the candidates of the elections.
+    #[cfg(feature = "kv")]
+    kv: KvClient,
+    observer: Observer,
 }
 
 /// Options for `campaign` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct CampaignOptions(PbCampaignRequest);
 
@@ -78,6 +101,11 @@ impl IntoRequest<PbCampaignRequest> for CampaignOptions {
 
 /// Options for `proclaim` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct ProclaimOptions(PbProclaimRequest);
 
@@ -121,6 +149,11 @@ impl IntoRequest<PbProclaimRequest> for ProclaimOptions {
 
 /// Options for `leader` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LeaderOptions(PbLeaderRequest);
 
@@ -154,6 +187,11 @@ impl IntoRequest<PbLeaderRequest> for LeaderOptions {
 
 /// Options for `resign` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct ResignOptions(PbResignRequest);
 
@@ -188,6 +226,11 @@ impl IntoRequest<PbResignRequest> for ResignOptions {
 /// Response for `Campaign` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct CampaignResponse(PbCampaignResponse);
 
@@ -225,6 +268,11 @@ impl CampaignResponse {
 /// Response for `Proclaim` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct ProclaimResponse(PbProclaimResponse);
 
@@ -250,6 +298,11 @@ impl ProclaimResponse {
 /// Response for `Leader` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LeaderResponse(PbLeaderResponse);
 
@@ -289,19 +342,23 @@ impl LeaderResponse {
 #[derive(Debug)]
 pub struct ObserveStream {
     stream: Streaming<PbLeaderResponse>,
+    call: Call,
 }
 
 impl ObserveStream {
     #[inline]
-    const fn new(stream: Streaming<PbLeaderResponse>) -> Self {
-        Self { stream }
+    const fn new(stream: Streaming<PbLeaderResponse>, call: Call) -> Self {
+        Self { stream, call }
     }
 
     /// Fetches the next message from this stream.
     #[inline]
     pub async fn message(&mut self) -> Result<Option<LeaderResponse>> {
-        match self.stream.message().await? {
-            Some(resp) => Ok(Some(LeaderResponse::new(resp))),
+        match self.stream.message().await.for_rpc("Observe")? {
+            Some(resp) => {
+                stream_event!(self.call, "observe response");
+                Ok(Some(LeaderResponse::new(resp)))
+            }
             None => Ok(None),
         }
     }
@@ -312,19 +369,26 @@ impl Stream for ObserveStream {
 
     #[inline]
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.get_mut().stream)
-            .poll_next(cx)
-            .map(|t| match t {
-                Some(Ok(resp)) => Some(Ok(LeaderResponse::new(resp))),
-                Some(Err(e)) => Some(Err(From::from(e))),
-                None => None,
-            })
+        let this = self.get_mut();
+        Pin::new(&mut this.stream).poll_next(cx).map(|t| match t {
+            Some(Ok(resp)) => {
+                stream_event!(this.call, "observe response");
+                Some(Ok(LeaderResponse::new(resp)))
+            }
+            Some(Err(e)) => Some(Err(Error::from(e).with_rpc("Observe"))),
+            None => None,
+        })
     }
 }
 
 /// Response for `Resign` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct ResignResponse(PbResignResponse);
 
@@ -347,8 +411,263 @@ impl ResignResponse {
     }
 }
 
+/// Options for guarded `campaign` operations.
+#[derive(Debug, Default, Clone)]
+pub struct ElectionOptions {
+    session: SessionOptions,
+    auto_recampaign: bool,
+}
+
+impl ElectionOptions {
+    /// Creates an `ElectionOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            session: SessionOptions::new(),
+            auto_recampaign: false,
+        }
+    }
+
+    /// Sets the options of the session backing the leadership.
+    #[inline]
+    pub fn with_session(mut self, session: SessionOptions) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Campaigns again with a fresh session after the leadership has been lost,
+    /// only used by [`ElectionClient::campaign_events`].
+    #[inline]
+    pub const fn with_auto_recampaign(mut self, auto_recampaign: bool) -> Self {
+        self.auto_recampaign = auto_recampaign;
+        self
+    }
+}
+
+/// Leadership of an election won by [`ElectionClient::campaign_guarded`].
+///
+/// The leadership is lost once the session lease is no longer kept alive or the leader key
+/// is deleted, e.g. by someone else resigning the leadership. Dropping the guard resigns
+/// the leadership on a best-effort basis.
+pub struct LeadershipGuard {
+    election: ElectionClient,
+    leader: LeaderKey,
+    session: Option<Session>,
+    lost: watch::Receiver<bool>,
+    monitor: Task,
+}
+
+impl LeadershipGuard {
+    fn new(
+        election: ElectionClient,
+        watch: WatchClient,
+        leader: LeaderKey,
+        session: Session,
+    ) -> Self {
+        let (lost_tx, lost) = watch::channel(false);
+        let monitor = election.lease.tasks().spawn(
+            MONITOR_TASK,
+            Self::monitor(
+                watch,
+                leader.key().to_vec(),
+                leader.rev(),
+                session.subscribe(),
+                lost_tx,
+            ),
+        );
+
+        Self {
+            election,
+            leader,
+            session: Some(session),
+            lost,
+            monitor,
+        }
+    }
+
+    async fn monitor(
+        mut watch: WatchClient,
+        key: Vec<u8>,
+        rev: Revision,
+        mut session_done: watch::Receiver<bool>,
+        lost: watch::Sender<bool>,
+    ) {
+        let options = WatchOptions::new()
+            .with_start_revision(rev)
+            .with_filters([WatchFilterType::NoPut]);
+        if let Ok((_watcher, mut stream)) = watch.watch(key, Some(options)).await {
+            loop {
+                tokio::select! {
+                    _ = session_done.wait_for(|done| *done) => break,
+                    resp = stream.message() => match resp {
+                        Ok(Some(resp)) => {
+                            if resp.canceled()
+                                || resp
+                                    .events()
+                                    .iter()
+                                    .any(|event| event.event_type() == EventType::Delete)
+                            {
+                                break;
+                            }
+                        }
+                        // Leadership can not be confirmed anymore.
+                        _ => break,
+                    }
+                }
+            }
+        }
+        lost.send_replace(true);
+    }
+
+    /// The leader key of the won election.
+    #[inline]
+    pub fn leader(&self) -> &LeaderKey {
+        &self.leader
+    }
+
+    /// A fencing token of the leadership, see [`LeaderKey::rev`].
+    #[inline]
+    pub fn fencing_token(&self) -> Revision {
+        self.leader.rev()
+    }
+
+    /// The session holding the leadership.
+    #[inline]
+    pub fn session(&self) -> &Session {
+        // The session is only taken when the guard is consumed.
+        self.session.as_ref().unwrap()
+    }
+
+    /// Returns `true` if the leadership has been lost, or can not be confirmed anymore
+    /// because the monitor of the leadership panicked.
+    #[inline]
+    pub fn is_lost(&self) -> bool {
+        *self.lost.borrow() || self.monitor.is_failed()
+    }
+
+    /// Resolves once the leadership has been lost.
+    ///
+    /// The returned future does not borrow the guard, so it can be awaited while using it.
+    pub fn lost(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut lost = self.lost.clone();
+        async move {
+            // An error means the monitor has been stopped, which only happens on drop or
+            // if it panicked.
+            let _ = lost.wait_for(|lost| *lost).await;
+        }
+    }
+
+    /// Lets the leader announce a new value without another election.
+    pub async fn proclaim(&mut self, value: impl Into<Vec<u8>>) -> Result<ProclaimResponse> {
+        self.monitor.check()?;
+        self.session().check()?;
+        let options = ProclaimOptions::new().with_leader(self.leader.clone());
+        self.election.proclaim(value, Some(options)).await
+    }
+
+    /// Resigns the leadership and revokes the session lease.
+    pub async fn resign(mut self) -> Result<ResignResponse> {
+        self.monitor.abort();
+        let options = ResignOptions::new().with_leader(self.leader.clone());
+        let resp = self.election.resign(Some(options)).await;
+        if let Some(session) = self.session.take() {
+            session.close().await?;
+        }
+        resp
+    }
+}
+
+impl Drop for LeadershipGuard {
+    fn drop(&mut self) {
+        self.monitor.abort();
+        let Some(session) = self.session.take() else {
+            return;
+        };
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let mut election = self.election.clone();
+        let options = ResignOptions::new().with_leader(self.leader.clone());
+        handle.spawn(async move {
+            let _ = election.resign(Some(options)).await;
+            drop(session);
+        });
+    }
+}
+
+/// The name of the task monitoring a leadership.
+const MONITOR_TASK: &str = "leadership monitor";
+
+/// The name of the task of a background campaign.
+const CAMPAIGN_TASK: &str = "campaign";
+
+/// Delay before campaigning again after a failed campaign.
+pub(crate) const RECAMPAIGN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Leadership change reported by [`ElectionClient::campaign_events`].
+#[derive(Debug, Clone)]
+pub enum LeadershipEvent {
+    /// The leadership has been won.
+    Elected {
+        /// Number of the leadership, increasing with each won leadership.
+        epoch: u64,
+        /// The leader key of the won election.
+        leader: LeaderKey,
+    },
+    /// The leadership of the given epoch has been lost, work done as leader must stop.
+    Deposed {
+        /// Number of the lost leadership.
+        epoch: u64,
+    },
+}
+
+/// Stream of [`LeadershipEvent`]s.
+#[derive(Debug)]
+pub struct LeadershipEvents {
+    rx: mpsc::Receiver<Result<LeadershipEvent>>,
+    task: Option<Task>,
+}
+
+impl LeadershipEvents {
+    /// Fetches the next event from this stream.
+    ///
+    /// If the campaign panicked, the stream ends with [`Error::InternalTaskFailed`].
+    #[inline]
+    pub async fn message(&mut self) -> Option<Result<LeadershipEvent>> {
+        match self.rx.recv().await {
+            Some(event) => Some(event),
+            None => self.failure(),
+        }
+    }
+
+    /// The failure of the campaign task, reported once at the end of the stream.
+    #[inline]
+    fn failure(&mut self) -> Option<Result<LeadershipEvent>> {
+        self.task.take()?.check().err().map(Err)
+    }
+}
+
+impl Stream for LeadershipEvents {
+    type Item = Result<LeadershipEvent>;
+
+    #[inline]
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match ready!(this.rx.poll_recv(cx)) {
+            Some(event) => Poll::Ready(Some(event)),
+            None => Poll::Ready(this.failure()),
+        }
+    }
+}
+
 /// Leader key of election
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LeaderKey(PbLeaderKey);
 
@@ -380,15 +699,15 @@ impl LeaderKey {
 
     /// The creation revision of the key
     #[inline]
-    pub const fn with_rev(mut self, rev: i64) -> Self {
-        self.0.rev = rev;
+    pub fn with_rev(mut self, rev: impl Into<Revision>) -> Self {
+        self.0.rev = rev.into().get();
         self
     }
 
     /// The lease ID of the election leader.
     #[inline]
-    pub const fn with_lease(mut self, lease: i64) -> Self {
-        self.0.lease = lease;
+    pub fn with_lease(mut self, lease: impl Into<LeaseId>) -> Self {
+        self.0.lease = lease.into().get();
         self
     }
 
@@ -444,15 +763,18 @@ impl LeaderKey {
     /// The creation revision of the key.  It can be used to test for ownership
     /// of an election during transactions by testing the key's creation revision
     /// matches rev.
+    ///
+    /// It also serves as a fencing token, which is monotonic across successive
+    /// holders of the same election.
     #[inline]
-    pub const fn rev(&self) -> i64 {
-        self.0.rev
+    pub const fn rev(&self) -> Revision {
+        Revision::new(self.0.rev)
     }
 
     /// The lease ID of the election leader.
     #[inline]
-    pub const fn lease(&self) -> i64 {
-        self.0.lease
+    pub const fn lease(&self) -> LeaseId {
+        LeaseId::new(self.0.lease)
     }
 }
 
@@ -487,12 +809,59 @@ impl From<&PbLeaderKey> for &LeaderKey {
 impl ElectionClient {
     /// Creates a election
     #[inline]
-    pub(crate) fn new(
-        channel: InterceptedChannel,
-        auth_token: Arc<RwLock<Option<HeaderValue>>>,
-    ) -> Self {
-        let inner = PbElectionClient::new(AuthService::new(channel, auth_token));
-        Self { inner }
+    pub(crate) fn new(channel: InterceptedChannel, auth_token: AuthToken) -> Self {
+        let lease = LeaseClient::new(channel.clone(), auth_token.clone());
+        let watch = WatchClient::new(channel.clone(), auth_token.clone());
+        #[cfg(feature = "kv")]
+        let kv = KvClient::new(channel.clone(), auth_token.clone());
+        let inner = Compressing::new(PbElectionClient::new(AuthService::new(channel, auth_token)));
+        Self {
+            inner,
+            lease,
+            watch,
+            #[cfg(feature = "kv")]
+            kv,
+            observer: Observer::default(),
+        }
+    }
+
+    /// Spawns the background tasks of campaigns with `tasks`.
+    #[inline]
+    pub(crate) fn with_tasks(mut self, tasks: Tasks) -> Self {
+        self.lease = self.lease.with_tasks(tasks);
+        self
+    }
+
+    /// Observes requests with `observer`.
+    #[inline]
+    pub(crate) fn with_observer(mut self, observer: Observer) -> Self {
+        self.lease = self.lease.with_observer(observer.clone());
+        self.watch = self.watch.with_observer(observer.clone());
+        #[cfg(feature = "kv")]
+        {
+            self.kv = self.kv.with_observer(observer.clone());
+        }
+        self.observer = observer;
+        self
+    }
+
+    /// Compresses the messages according to `compression`.
+    #[inline]
+    pub(crate) fn with_compression(mut self, compression: &Compression) -> Self {
+        #[cfg(feature = "kv")]
+        {
+            self.kv = self.kv.with_compression(compression);
+        }
+        self.inner = self.inner.with_compression(compression);
+        self
+    }
+
+    /// The candidates of the election `name`, the leader first and the others in the order
+    /// they will lead, with the values they campaigned with.
+    #[cfg(feature = "kv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+    pub async fn candidates(&mut self, name: impl Into<Vec<u8>>) -> Result<Vec<Contender>> {
+        self.kv.contenders(name.into()).await
     }
 
     /// Puts a value as eligible for the election on the prefix key.
@@ -503,21 +872,176 @@ impl ElectionClient {
         &mut self,
         name: impl Into<Vec<u8>>,
         value: impl Into<Vec<u8>>,
-        lease: i64,
+        lease: impl Into<LeaseId>,
     ) -> Result<CampaignResponse> {
-        let resp = self
-            .inner
-            .campaign(
+        let lease = lease.into().get();
+        let resp = observed!(
+            self.observer,
+            "Campaign",
+            self.inner.campaign(
                 CampaignOptions::new()
                     .with_name(name)
                     .with_value(value)
                     .with_lease(lease),
             )
-            .await?
-            .into_inner();
+        )
+        .await
+        .for_rpc("Campaign")?
+        .into_inner();
         Ok(CampaignResponse::new(resp))
     }
 
+    /// Campaigns with a new session and wraps the won leadership in a [`LeadershipGuard`].
+    ///
+    /// The leadership is bound to the session lease, see [`LeadershipGuard`] for when it
+    /// is considered lost. Fails with an [`Error::ElectError`] if the server answers without
+    /// the leader key, the session is closed then.
+    pub async fn campaign_guarded(
+        &mut self,
+        name: impl Into<Vec<u8>>,
+        value: impl Into<Vec<u8>>,
+        options: Option<ElectionOptions>,
+    ) -> Result<LeadershipGuard> {
+        let options = options.unwrap_or_default();
+        let session = Session::new(self.lease.clone(), Some(options.session)).await?;
+        let resp = self.campaign(name, value, session.lease_id()).await;
+        self.guard(resp, session).await
+    }
+
+    /// Wraps the leadership won by `resp` with the lease of `session` in a
+    /// [`LeadershipGuard`], closing the session if the campaign failed.
+    async fn guard(
+        &self,
+        resp: Result<CampaignResponse>,
+        session: Session,
+    ) -> Result<LeadershipGuard> {
+        let mut resp = match resp {
+            Ok(resp) => resp,
+            Err(e) => {
+                let _ = session.close().await;
+                return Err(e);
+            }
+        };
+        // The campaign is won, revoking the lease gives the leadership up.
+        let Some(leader) = resp.take_leader() else {
+            let _ = session.close().await;
+            return Err(Error::ElectError(String::from(
+                "campaign response without a leader key",
+            )));
+        };
+        Ok(LeadershipGuard::new(
+            self.clone(),
+            self.watch.clone(),
+            leader,
+            session,
+        ))
+    }
+
+    /// Campaigns in the background and reports the leadership changes as a stream of
+    /// [`LeadershipEvent`]s.
+    ///
+    /// Each won leadership is numbered by an epoch. Once it is lost, a `Deposed` event is
+    /// emitted, and with [`ElectionOptions::with_auto_recampaign`] the campaign restarts
+    /// with a fresh session only after the event has been received. Otherwise the stream
+    /// ends after the first `Deposed` event. Dropping the stream resigns the leadership.
+    pub fn campaign_events(
+        &self,
+        name: impl Into<Vec<u8>>,
+        value: impl Into<Vec<u8>>,
+        options: Option<ElectionOptions>,
+    ) -> LeadershipEvents {
+        let (tx, rx) = mpsc::channel(1);
+        let task = self.lease.tasks().spawn(
+            CAMPAIGN_TASK,
+            Self::campaign_loop(
+                self.clone(),
+                name.into(),
+                value.into(),
+                options.unwrap_or_default(),
+                tx,
+            ),
+        );
+        LeadershipEvents {
+            rx,
+            task: Some(task),
+        }
+    }
+
+    async fn campaign_loop(
+        mut self,
+        name: Vec<u8>,
+        value: Vec<u8>,
+        options: ElectionOptions,
+        tx: mpsc::Sender<Result<LeadershipEvent>>,
+    ) {
+        let auto_recampaign = options.auto_recampaign;
+        let mut epoch = 0;
+        loop {
+            let session = Session::new(self.lease.clone(), Some(options.session.clone()));
+            let session = tokio::select! {
+                _ = tx.closed() => return,
+                session = session => session,
+            };
+            let guard = match session {
+                Ok(session) => {
+                    let campaign = self.campaign(name.clone(), value.clone(), session.lease_id());
+                    let resp = tokio::select! {
+                        _ = tx.closed() => {
+                            // Revoking the lease deletes the candidate key, rather than
+                            // leaving it in the queue until the lease expires.
+                            let _ = session.close().await;
+                            return;
+                        }
+                        resp = campaign => resp,
+                    };
+                    self.guard(resp, session).await
+                }
+                Err(e) => Err(e),
+            };
+            let guard = match guard {
+                Ok(guard) => guard,
+                Err(e) => {
+                    if tx.send(Err(e)).await.is_err() || !auto_recampaign {
+                        return;
+                    }
+                    tokio::time::sleep(RECAMPAIGN_BACKOFF).await;
+                    continue;
+                }
+            };
+
+            epoch += 1;
+            let elected = LeadershipEvent::Elected {
+                epoch,
+                leader: guard.leader().clone(),
+            };
+            if tx.send(Ok(elected)).await.is_err() {
+                let _ = guard.resign().await;
+                return;
+            }
+            tokio::select! {
+                _ = tx.closed() => {
+                    let _ = guard.resign().await;
+                    return;
+                }
+                _ = guard.lost() => {}
+            }
+            drop(guard);
+
+            if tx
+                .send(Ok(LeadershipEvent::Deposed { epoch }))
+                .await
+                .is_err()
+                || !auto_recampaign
+            {
+                return;
+            }
+            // The channel holds a single event, so a free slot means `Deposed` has been received.
+            if tx.reserve().await.is_err() {
+                return;
+            }
+        }
+    }
+
     /// Lets the leader announce a new value without another election.
     #[inline]
     pub async fn proclaim(
@@ -525,22 +1049,29 @@ impl ElectionClient {
         value: impl Into<Vec<u8>>,
         options: Option<ProclaimOptions>,
     ) -> Result<ProclaimResponse> {
-        let resp = self
-            .inner
-            .proclaim(options.unwrap_or_default().with_value(value))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "Proclaim",
+            self.inner
+                .proclaim(options.unwrap_or_default().with_value(value))
+        )
+        .await
+        .for_rpc("Proclaim")?
+        .into_inner();
         Ok(ProclaimResponse::new(resp))
     }
 
     /// Returns the leader value for the current election.
     #[inline]
     pub async fn leader(&mut self, name: impl Into<Vec<u8>>) -> Result<LeaderResponse> {
-        let resp = self
-            .inner
-            .leader(LeaderOptions::new().with_name(name))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "Leader",
+            self.inner.leader(LeaderOptions::new().with_name(name))
+        )
+        .await
+        .for_rpc("Leader")?
+        .into_inner();
         Ok(LeaderResponse::new(resp))
     }
 
@@ -548,23 +1079,44 @@ impl ElectionClient {
     /// as GetResponse values on every current elected leader key.
     #[inline]
     pub async fn observe(&mut self, name: impl Into<Vec<u8>>) -> Result<ObserveStream> {
-        let resp = self
-            .inner
-            .observe(LeaderOptions::new().with_name(name))
-            .await?
-            .into_inner();
-
-        Ok(ObserveStream::new(resp))
+        let call = observe_call!(self.observer, "Observe");
+        let resp = observed!(
+            call = call.clone(),
+            self.inner.observe(LeaderOptions::new().with_name(name))
+        )
+        .await
+        .for_rpc("Observe")?
+        .into_inner();
+
+        Ok(ObserveStream::new(resp, call))
     }
 
     /// Releases election leadership and then start a new election
     #[inline]
     pub async fn resign(&mut self, option: Option<ResignOptions>) -> Result<ResignResponse> {
-        let resp = self
-            .inner
-            .resign(option.unwrap_or_default())
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "Resign",
+            self.inner.resign(option.unwrap_or_default())
+        )
+        .await
+        .for_rpc("Resign")?
+        .into_inner();
         Ok(ResignResponse::new(resp))
     }
 }
+
+impl_raw_conversions!(
+    CampaignResponse(PbCampaignResponse),
+    ProclaimResponse(PbProclaimResponse),
+    LeaderResponse(PbLeaderResponse),
+    ResignResponse(PbResignResponse),
+);
+
+impl_raw_conversions!(
+    options:
+    CampaignOptions(PbCampaignRequest),
+    ProclaimOptions(PbProclaimRequest),
+    LeaderOptions(PbLeaderRequest),
+    ResignOptions(PbResignRequest),
+);
@@ -3,9 +3,18 @@
 pub use crate::rpc::pb::etcdserverpb::compare::CompareResult as CompareOp;
 pub use crate::rpc::pb::etcdserverpb::range_request::{SortOrder, SortTarget};
 
-use crate::auth::AuthService;
-use crate::error::Result;
+use crate::auth::{AuthService, AuthToken};
+use crate::compression::{Compressing, Compression};
+use crate::deadline::CallOptions;
+use crate::error::{Error, Result, RpcResultExt};
+use crate::hedge::{Hedger, ReadHedging};
+use crate::ids::{LeaseId, Revision};
 use crate::intercept::InterceptedChannel;
+use crate::observe::{observed, Observer};
+use crate::retry::{retry, RetryPolicy, DEFAULT_READ_RETRIES};
+use crate::route::{RouteTo, Router};
+#[cfg(feature = "lease")]
+use crate::rpc::lease::{LeaseClient, LeaseTimeToLiveOptions};
 use crate::rpc::pb::etcdserverpb::compare::{CompareTarget, TargetUnion};
 use crate::rpc::pb::etcdserverpb::kv_client::KvClient as PbKvClient;
 use crate::rpc::pb::etcdserverpb::request_op::Request as PbTxnOp;
@@ -18,36 +27,247 @@ use crate::rpc::pb::etcdserverpb::{
     PutResponse as PbPutResponse, RangeRequest as PbRangeRequest, RangeResponse as PbRangeResponse,
     RequestOp as PbTxnRequestOp, TxnRequest as PbTxnRequest, TxnResponse as PbTxnResponse,
 };
-use crate::rpc::{get_prefix, KeyRange, KeyValue, ResponseHeader};
+use crate::rpc::pb::mvccpb::KeyValue as PbKeyValue;
+use crate::rpc::{get_prefix, shared_bytes, HasResponseHeader, KeyRange, KeyValue, ResponseHeader};
+use crate::size_accounting::SizeAccounting;
+use crate::trace::{self, BULK_LANE};
 use crate::vec::VecExt;
-use http::HeaderValue;
+use http::Uri;
+use prost::bytes::Bytes;
+use prost::Message;
+use std::future::Future;
 use std::mem::ManuallyDrop;
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 use tonic::{IntoRequest, Request};
 
+/// Hedger of Range requests.
+pub(crate) type KvHedger = Hedger<Compressing<PbKvClient<AuthService<InterceptedChannel>>>>;
+
 /// Client for KV operations.
-#[repr(transparent)]
 #[derive(Clone)]
 pub struct KvClient {
-    inner: PbKvClient<AuthService<InterceptedChannel>>,
+    inner: Compressing<PbKvClient<AuthService<InterceptedChannel>>>,
+    retry: Option<RetryPolicy>,
+    read_retries: u32,
+    /// The deadline of the requests without one of their own.
+    pub(crate) default_deadline: Option<Duration>,
+    hedger: Option<Arc<KvHedger>>,
+    /// The accounting of the bytes written, if enabled.
+    size: Option<Arc<SizeAccounting>>,
+    /// The client of the leases checked by puts.
+    #[cfg(feature = "lease")]
+    pub(crate) lease: LeaseClient,
+    /// The client of the bulk lane, if any.
+    bulk: Option<Compressing<PbKvClient<AuthService<InterceptedChannel>>>>,
+    /// The lane the requests are sent over, `None` for the default one.
+    lane: Option<&'static str>,
+    /// The router of the calls sent to the leader or a given member, if any.
+    router: Option<Arc<Router>>,
+    observer: Observer,
 }
 
 impl KvClient {
     /// Creates a kv client.
     #[inline]
-    pub(crate) fn new(
+    pub(crate) fn new(channel: InterceptedChannel, auth_token: AuthToken) -> Self {
+        #[cfg(feature = "lease")]
+        let lease = LeaseClient::new(channel.clone(), auth_token.clone());
+        let inner = Compressing::new(PbKvClient::new(AuthService::new(channel, auth_token)));
+        Self {
+            inner,
+            retry: None,
+            read_retries: DEFAULT_READ_RETRIES,
+            default_deadline: None,
+            hedger: None,
+            size: None,
+            #[cfg(feature = "lease")]
+            lease,
+            bulk: None,
+            lane: None,
+            router: None,
+            observer: Observer::default(),
+        }
+    }
+
+    /// Retries requests failing with transient errors according to `policy`.
+    #[inline]
+    pub(crate) fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        #[cfg(feature = "lease")]
+        {
+            self.lease = self.lease.with_retry(policy.clone());
+        }
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Retries read-only requests failing because the leader changed or the request timed
+    /// out up to `read_retries` times.
+    #[inline]
+    pub(crate) fn with_read_retries(mut self, read_retries: u32) -> Self {
+        #[cfg(feature = "lease")]
+        {
+            self.lease = self.lease.with_read_retries(read_retries);
+        }
+        self.read_retries = read_retries;
+        self
+    }
+
+    /// Creates a hedger of Range requests across `endpoints`, connected by `connect` and
+    /// compressed according to `compression`.
+    pub(crate) fn hedger(
+        options: ReadHedging,
+        endpoints: Vec<Uri>,
+        auth_token: AuthToken,
+        compression: Compression,
+        connect: impl Fn(&Uri) -> Result<InterceptedChannel> + Send + Sync + 'static,
+    ) -> KvHedger {
+        Hedger::new(options, endpoints, move |uri| {
+            let channel = connect(uri)?;
+            let inner = PbKvClient::new(AuthService::new(channel, auth_token.clone()));
+            Ok(Compressing::new(inner).with_compression(&compression))
+        })
+    }
+
+    /// Hedges Range requests across endpoints with `hedger`.
+    #[inline]
+    pub(crate) fn with_hedger(mut self, hedger: Arc<KvHedger>) -> Self {
+        self.hedger = Some(hedger);
+        self
+    }
+
+    /// Accounts the bytes written with `size`.
+    #[inline]
+    pub(crate) fn with_size_accounting(mut self, size: Arc<SizeAccounting>) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Routes the calls sent to the leader or a given member with `router`.
+    #[inline]
+    pub(crate) fn with_router(mut self, router: Arc<Router>) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    /// Sends the low priority reads over `channel`, the balanced channel of the bulk lane.
+    #[inline]
+    pub(crate) fn with_bulk_lane(
+        mut self,
         channel: InterceptedChannel,
-        auth_token: Arc<RwLock<Option<HeaderValue>>>,
+        auth_token: AuthToken,
     ) -> Self {
-        let inner = PbKvClient::new(AuthService::new(channel, auth_token));
-        Self { inner }
+        self.bulk = Some(Compressing::new(PbKvClient::new(AuthService::new(
+            channel, auth_token,
+        ))));
+        self
+    }
+
+    /// The client sending its requests over the bulk lane, unhedged, or a clone of this
+    /// client if there is no bulk lane.
+    #[inline]
+    pub(crate) fn over_bulk_lane(&self) -> Self {
+        match &self.bulk {
+            Some(bulk) => Self {
+                inner: bulk.clone(),
+                hedger: None,
+                bulk: None,
+                lane: Some(BULK_LANE),
+                ..self.clone()
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// The router and the route of a call with the options `call`, taking its route, or
+    /// `None` if the call is sent to any member.
+    #[inline]
+    fn router_of(&self, call: &mut CallOptions) -> Option<(Arc<Router>, RouteTo)> {
+        let route = call.take_route();
+        let router = self.router.clone()?;
+        (route != RouteTo::Any).then_some((router, route))
+    }
+
+    /// Sends a call with `send` over the channel to the member of `route`, or over the
+    /// balanced channel if that member can not be reached and `router` is not strict.
+    async fn routed<R, F, Fut>(&self, router: &Router, route: RouteTo, mut send: F) -> Result<R>
+    where
+        R: HasResponseHeader,
+        F: FnMut(KvClient) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let channel = match router.channel(route).await {
+            Ok(channel) => channel,
+            Err(e) => {
+                router.unreachable(route, e)?;
+                return send(self.clone()).await;
+            }
+        };
+        let inner = PbKvClient::new(AuthService::new(channel, router.auth_token().clone()));
+        let client = Self {
+            inner: Compressing::new(inner).with_compression(router.compression()),
+            hedger: None,
+            bulk: None,
+            lane: None,
+            router: None,
+            ..self.clone()
+        };
+        let result = send(client).await;
+        router.observe(route, &result);
+        match result {
+            Err(e) if e.is_transport() => {
+                router.unreachable(route, e)?;
+                send(self.clone()).await
+            }
+            result => result,
+        }
+    }
+
+    /// Observes requests with `observer`.
+    #[inline]
+    pub(crate) fn with_observer(mut self, observer: Observer) -> Self {
+        #[cfg(feature = "lease")]
+        {
+            self.lease = self.lease.with_observer(observer.clone());
+        }
+        self.observer = observer;
+        self
+    }
+
+    /// Compresses the messages according to `compression`.
+    #[inline]
+    pub(crate) fn with_compression(mut self, compression: &Compression) -> Self {
+        #[cfg(feature = "lease")]
+        {
+            self.lease = self.lease.with_compression(compression);
+        }
+        self.inner = self.inner.with_compression(compression);
+        self.bulk = self.bulk.map(|bulk| bulk.with_compression(compression));
+        self
+    }
+
+    /// Aborts requests which have no deadline of their own after `deadline`.
+    #[inline]
+    pub(crate) fn with_default_deadline(mut self, deadline: Duration) -> Self {
+        #[cfg(feature = "lease")]
+        {
+            self.lease = self.lease.with_default_deadline(deadline);
+        }
+        self.default_deadline = Some(deadline);
+        self
     }
 
     /// Limits the maximum size of a decoded message.
     ///
     /// Default: `4MB`
     pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
-        self.inner = self.inner.max_decoding_message_size(limit);
+        self.inner = self
+            .inner
+            .map(|inner| inner.max_decoding_message_size(limit));
+        self.bulk = self
+            .bulk
+            .map(|bulk| bulk.map(|bulk| bulk.max_decoding_message_size(limit)));
         self
     }
 
@@ -55,7 +275,12 @@ impl KvClient {
     ///
     /// Default: `usize::MAX`
     pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
-        self.inner = self.inner.max_encoding_message_size(limit);
+        self.inner = self
+            .inner
+            .map(|inner| inner.max_encoding_message_size(limit));
+        self.bulk = self
+            .bulk
+            .map(|bulk| bulk.map(|bulk| bulk.max_encoding_message_size(limit)));
         self
     }
 
@@ -69,14 +294,83 @@ impl KvClient {
         value: impl Into<Vec<u8>>,
         options: Option<PutOptions>,
     ) -> Result<PutResponse> {
-        let resp = self
-            .inner
-            .put(options.unwrap_or_default().with_kv(key, value))
-            .await?
-            .into_inner();
+        let mut options = options.unwrap_or_default().with_kv(key, value);
+        if let Some((router, route)) = self.router_of(&mut options.1) {
+            return self
+                .routed(&router, route, |mut client| {
+                    let options = options.clone();
+                    async move { client.send_put(options).await }
+                })
+                .await;
+        }
+        self.send_put(options).await
+    }
+
+    /// Sends the Put request of `options`.
+    async fn send_put(&mut self, mut options: PutOptions) -> Result<PutResponse> {
+        let call = std::mem::take(&mut options.1);
+        if let Some(size) = &self.size {
+            size.check_request(options.0.encoded_len())?;
+        }
+        let written = options.0.key.len() + options.0.value.len();
+        let lease = options.0.lease;
+        #[cfg(feature = "lease")]
+        let checked = options.2.then(|| self.lease.clone());
+        let inner = self.inner.clone();
+        let observer = self.observer.clone();
+        let put = retry(self.retry.as_ref(), 0, "Put", false, move || {
+            let mut inner = inner.clone();
+            let observer = observer.clone();
+            let options = options.clone();
+            async move {
+                Ok(
+                    observed!(observer, "Put", key = &options.0.key, inner.put(options))
+                        .await
+                        .for_rpc("Put")?
+                        .into_inner(),
+                )
+            }
+        });
+        let resp = call
+            .run("Put", self.default_deadline, async move {
+                #[cfg(feature = "lease")]
+                if let Some(mut client) = checked {
+                    client.check_lease(lease).await?;
+                    // The lease was alive when checked.
+                    return put.await.map_err(|e| e.with_lease_expired(lease));
+                }
+                put.await.map_err(|e| e.with_lease_id(lease))
+            })
+            .await?;
+        if let Some(size) = &self.size {
+            size.record_write(written as u64);
+        }
         Ok(PutResponse::new(resp))
     }
 
+    /// The keys attached to the lease `lease`, the reverse lookup of
+    /// [`PutOptions::with_lease`].
+    ///
+    /// Fails with [`Error::LeaseNotFound`] if the server does not know the lease, and with
+    /// [`Error::LeaseExpired`] if the lease expired.
+    #[cfg(feature = "lease")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "lease")))]
+    pub async fn keys_of_lease(&mut self, lease: impl Into<LeaseId>) -> Result<Vec<Vec<u8>>> {
+        let lease = lease.into();
+        let mut resp = self
+            .lease
+            .time_to_live(lease, Some(LeaseTimeToLiveOptions::new().with_keys()))
+            .await?;
+        // Servers before 3.3 report an expired lease as a TTL of -1.
+        if resp.ttl() < 0 {
+            return Err(Error::LeaseExpired {
+                id: lease,
+                status: None,
+            });
+        }
+        Ok(resp.take_keys())
+    }
+
     /// Gets the key or a range of keys from the store.
     #[inline]
     pub async fn get(
@@ -84,14 +378,115 @@ impl KvClient {
         key: impl Into<Vec<u8>>,
         options: Option<GetOptions>,
     ) -> Result<GetResponse> {
-        let resp = self
-            .inner
-            .range(options.unwrap_or_default().with_key(key.into()))
-            .await?
-            .into_inner();
+        let mut options = options.unwrap_or_default().with_key(key.into());
+        if let Some((router, route)) = self.router_of(&mut options.call) {
+            return self
+                .routed(&router, route, |mut client| {
+                    let options = options.clone();
+                    async move { client.send_get(options).await }
+                })
+                .await;
+        }
+        self.send_get(options).await
+    }
+
+    /// Sends the Range request of `options`, paginating it if needed.
+    async fn send_get(&mut self, mut options: GetOptions) -> Result<GetResponse> {
+        let call = std::mem::take(&mut options.call);
+        let auto_paginate = options.auto_paginate.take();
+        let client = match options.low_priority {
+            true => self.over_bulk_lane(),
+            false => self.clone(),
+        };
+        let req = PbRangeRequest::from(options);
+        let resp = call
+            .run("Range", self.default_deadline, async move {
+                match client.clone().range(req.clone()).await {
+                    // Pages of another order than by ascending keys can not be resumed.
+                    Err(e)
+                        if is_response_too_large(&e)
+                            && req.sort_order == SortOrder::None as i32 =>
+                    {
+                        match auto_paginate {
+                            Some(batch) => client.paginate(req, batch).await,
+                            None => Err(e),
+                        }
+                    }
+                    resp => resp,
+                }
+            })
+            .await?;
         Ok(GetResponse::new(resp))
     }
 
+    /// Sends the Range request `req`, retrying and hedging it.
+    async fn range(self, req: PbRangeRequest) -> Result<PbRangeResponse> {
+        let inner = self.inner;
+        let hedger = self.hedger;
+        let observer = self.observer.clone();
+        let attempts = retry(
+            self.retry.as_ref(),
+            self.read_retries,
+            "Range",
+            true,
+            move || {
+                let inner = inner.clone();
+                let hedger = hedger.clone();
+                let req = req.clone();
+                let observer = observer.clone();
+                let range = move |mut inner: Compressing<PbKvClient<_>>| {
+                    let observer = observer.clone();
+                    let req = req.clone();
+                    async move {
+                        let key = req.key.clone();
+                        let req = crate::leader::serializable(req.serializable, req);
+                        Ok(observed!(observer, "Range", key = &key, inner.range(req))
+                            .await
+                            .for_rpc("Range")?
+                            .into_inner())
+                    }
+                };
+                async move {
+                    match hedger {
+                        Some(hedger) => hedger.run(inner, range).await,
+                        None => range(inner).await,
+                    }
+                }
+            },
+        );
+        trace::lane(self.lane, attempts).await
+    }
+
+    /// Sends the Range request `req` as pages of up to `batch` keys at the revision of the
+    /// first page, and assembles them into a single response.
+    async fn paginate(self, mut req: PbRangeRequest, batch: i64) -> Result<PbRangeResponse> {
+        let limit = req.limit;
+        req.limit = batch.max(1);
+        if limit > 0 {
+            req.limit = req.limit.min(limit);
+        }
+        let mut resp = self.clone().range(req.clone()).await?;
+        req.revision = resp
+            .header
+            .as_ref()
+            .map_or(req.revision, |header| header.revision);
+        while resp.more && (limit == 0 || (resp.kvs.len() as i64) < limit) {
+            let Some(last) = resp.kvs.last() else {
+                break;
+            };
+            let mut key = last.key.clone();
+            key.push(0);
+            req.key = key.into();
+            if limit > 0 {
+                req.limit = batch.max(1).min(limit - resp.kvs.len() as i64);
+            }
+            let page = self.clone().range(req.clone()).await?;
+            resp.kvs.extend(page.kvs);
+            resp.more = page.more;
+        }
+        Ok(resp)
+    }
+
     /// Deletes the given key or a range of keys from the key-value store.
     #[inline]
     pub async fn delete(
@@ -99,11 +494,50 @@ impl KvClient {
         key: impl Into<Vec<u8>>,
         options: Option<DeleteOptions>,
     ) -> Result<DeleteResponse> {
-        let resp = self
-            .inner
-            .delete_range(options.unwrap_or_default().with_key(key.into()))
-            .await?
-            .into_inner();
+        let mut options = options.unwrap_or_default().with_key(key.into());
+        if let Some((router, route)) = self.router_of(&mut options.call) {
+            return self
+                .routed(&router, route, |mut client| {
+                    let options = options.clone();
+                    async move { client.send_delete(options).await }
+                })
+                .await;
+        }
+        self.send_delete(options).await
+    }
+
+    /// Sends the DeleteRange request of `options`, or the guarded delete it asks for.
+    async fn send_delete(&mut self, mut options: DeleteOptions) -> Result<DeleteResponse> {
+        if options.is_guarded() {
+            // Boxed, not to embed the get and the txn of the guard in every delete.
+            return Box::pin(self.delete_guarded(options)).await;
+        }
+        let call = std::mem::take(&mut options.call);
+        let req = PbDeleteRequest::from(options);
+        let inner = self.inner.clone();
+        let observer = self.observer.clone();
+        let resp = call
+            .run(
+                "DeleteRange",
+                self.default_deadline,
+                retry(self.retry.as_ref(), 0, "DeleteRange", false, move || {
+                    let mut inner = inner.clone();
+                    let observer = observer.clone();
+                    let req = req.clone();
+                    async move {
+                        Ok(observed!(
+                            observer,
+                            "DeleteRange",
+                            key = &req.key,
+                            inner.delete_range(req)
+                        )
+                        .await
+                        .for_rpc("DeleteRange")?
+                        .into_inner())
+                    }
+                }),
+            )
+            .await?;
         Ok(DeleteResponse::new(resp))
     }
 
@@ -113,14 +547,21 @@ impl KvClient {
     #[inline]
     pub async fn compact(
         &mut self,
-        revision: i64,
+        revision: impl Into<Revision>,
         options: Option<CompactionOptions>,
     ) -> Result<CompactionResponse> {
-        let resp = self
-            .inner
-            .compact(options.unwrap_or_default().with_revision(revision))
-            .await?
-            .into_inner();
+        let revision = revision.into().get();
+        let mut inner = self.inner.clone();
+        let observer = self.observer.clone();
+        let resp = CallOptions::new()
+            .run("Compact", self.default_deadline, async move {
+                let options = options.unwrap_or_default().with_revision(revision);
+                Ok(observed!(observer, "Compact", inner.compact(options))
+                    .await
+                    .for_rpc("Compact")?
+                    .into_inner())
+            })
+            .await?;
         Ok(CompactionResponse::new(resp))
     }
 
@@ -129,44 +570,169 @@ impl KvClient {
     /// and generates events with the same revision for every completed operation.
     /// It is not allowed to modify the same key several times within one txn.
     #[inline]
-    pub async fn txn(&mut self, txn: Txn) -> Result<TxnResponse> {
-        let resp = self.inner.txn(txn).await?.into_inner();
+    pub async fn txn(&mut self, mut txn: Txn) -> Result<TxnResponse> {
+        if let Some((router, route)) = self.router_of(&mut txn.call) {
+            return self
+                .routed(&router, route, |mut client| {
+                    let txn = txn.clone();
+                    async move { client.send_txn(txn).await }
+                })
+                .await;
+        }
+        self.send_txn(txn).await
+    }
+
+    /// Sends the Txn request `txn`.
+    async fn send_txn(&mut self, mut txn: Txn) -> Result<TxnResponse> {
+        let call = std::mem::take(&mut txn.call);
+        // The request is kept to account the puts of the branch applied.
+        let accounted = match &self.size {
+            Some(size) => {
+                size.check_request(txn.req.encoded_len())?;
+                Some((size.clone(), txn.req.clone()))
+            }
+            None => None,
+        };
+        let inner = self.inner.clone();
+        let observer = self.observer.clone();
+        let resp = call
+            .run(
+                "Txn",
+                self.default_deadline,
+                retry(self.retry.as_ref(), 0, "Txn", false, move || {
+                    let mut inner = inner.clone();
+                    let observer = observer.clone();
+                    let txn = txn.clone();
+                    async move {
+                        Ok(observed!(observer, "Txn", inner.txn(txn))
+                            .await
+                            .for_rpc("Txn")?
+                            .into_inner())
+                    }
+                }),
+            )
+            .await?;
+        if let Some((size, req)) = accounted {
+            size.record_write(put_bytes(&req, &resp) as u64);
+        }
         Ok(TxnResponse::new(resp))
     }
 }
 
+/// The sum of the sizes of the keys and values put by the branch of `req` applied, as
+/// told by `resp`, nested txns included.
+fn put_bytes(req: &PbTxnRequest, resp: &PbTxnResponse) -> usize {
+    let branch = if resp.succeeded {
+        &req.success
+    } else {
+        &req.failure
+    };
+    branch
+        .iter()
+        .zip(&resp.responses)
+        .map(|(op, op_resp)| match (&op.request, &op_resp.response) {
+            (Some(PbTxnOp::RequestPut(put)), _) => put.key.len() + put.value.len(),
+            (Some(PbTxnOp::RequestTxn(txn)), Some(PbTxnOpResponse::ResponseTxn(txn_resp))) => {
+                put_bytes(txn, txn_resp)
+            }
+            _ => 0,
+        })
+        .sum()
+}
+
 /// Options for `Put` operation.
 #[derive(Debug, Default, Clone)]
-#[repr(transparent)]
-pub struct PutOptions(PbPutRequest);
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct PutOptions(
+    PbPutRequest,
+    #[cfg_attr(feature = "serde", serde(skip))] CallOptions,
+    /// Whether the lease is checked before the put is sent.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(not(feature = "lease"), allow(dead_code))]
+    bool,
+);
 
 impl PutOptions {
     /// Set key-value pair.
     #[inline]
     fn with_kv(mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Self {
-        self.0.key = key.into();
-        self.0.value = value.into();
+        self.0.key = shared_bytes(key);
+        self.0.value = shared_bytes(value);
         self
     }
 
     /// Creates a `PutOptions`.
     #[inline]
     pub const fn new() -> Self {
-        Self(PbPutRequest {
-            key: Vec::new(),
-            value: Vec::new(),
-            lease: 0,
-            prev_kv: false,
-            ignore_value: false,
-            ignore_lease: false,
-        })
+        Self(
+            PbPutRequest {
+                key: Bytes::new(),
+                value: Bytes::new(),
+                lease: 0,
+                prev_kv: false,
+                ignore_value: false,
+                ignore_lease: false,
+            },
+            CallOptions::new(),
+            false,
+        )
+    }
+
+    /// Aborts the request if it has not completed by `deadline`, including its retries.
+    #[inline]
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.1.set_deadline(deadline);
+        self
+    }
+
+    /// Aborts the request when `token` is cancelled.
+    #[inline]
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.1.set_cancel(token);
+        self
+    }
+
+    /// Sends the metadata `key: value` with the request, replacing the metadata of the client
+    /// under the same key, see
+    /// [`ConnectOptions::with_metadata`](crate::ConnectOptions::with_metadata).
+    #[inline]
+    pub fn with_metadata(mut self, key: &str, value: impl AsRef<[u8]>) -> Result<Self> {
+        self.1.append_metadata(key, value.as_ref())?;
+        Ok(self)
+    }
+
+    /// Sends the request to the member of `route` rather than to any member, see
+    /// [`RouteTo`].
+    #[inline]
+    pub fn with_route(mut self, route: RouteTo) -> Self {
+        self.1.set_route(route);
+        self
     }
 
     /// Lease is the lease ID to associate with the key in the key-value store. A lease
     /// value of 0 indicates no lease.
     #[inline]
-    pub const fn with_lease(mut self, lease: i64) -> Self {
-        self.0.lease = lease;
+    pub fn with_lease(mut self, lease: impl Into<LeaseId>) -> Self {
+        self.0.lease = lease.into().get();
+        self
+    }
+
+    /// Associates the key with the lease `lease` as [`PutOptions::with_lease`] does, checking
+    /// first that the lease is alive.
+    ///
+    /// The put fails with [`Error::LeaseNotFound`] if the server does not know the lease,
+    /// and with [`Error::LeaseExpired`] if the lease expired, including between the check
+    /// and the put. Ignored in transactions.
+    #[cfg(feature = "lease")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "lease")))]
+    #[inline]
+    pub fn with_lease_checked(mut self, lease: impl Into<LeaseId>) -> Self {
+        self.0.lease = lease.into().get();
+        self.2 = true;
         self
     }
 
@@ -212,6 +778,11 @@ impl IntoRequest<PbPutRequest> for PutOptions {
 /// Response for `Put` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct PutResponse(PbPutResponse);
 
@@ -252,6 +823,15 @@ impl PutResponse {
             kv.key.strip_key_prefix(prefix);
         }
     }
+
+    /// Calls `f` on the key-value pairs of the response, up to the first error.
+    #[inline]
+    pub(crate) fn try_for_each_kv(
+        &mut self,
+        f: &mut impl FnMut(&mut PbKeyValue) -> Result<()>,
+    ) -> Result<()> {
+        self.0.prev_kv.iter_mut().try_for_each(f)
+    }
 }
 
 /// Options for `Get` operation.
@@ -259,6 +839,9 @@ impl PutResponse {
 pub struct GetOptions {
     req: PbRangeRequest,
     key_range: KeyRange,
+    call: CallOptions,
+    auto_paginate: Option<i64>,
+    low_priority: bool,
 }
 
 impl GetOptions {
@@ -274,8 +857,8 @@ impl GetOptions {
     pub const fn new() -> Self {
         Self {
             req: PbRangeRequest {
-                key: Vec::new(),
-                range_end: Vec::new(),
+                key: Bytes::new(),
+                range_end: Bytes::new(),
                 limit: 0,
                 revision: 0,
                 sort_order: 0,
@@ -289,9 +872,43 @@ impl GetOptions {
                 max_create_revision: 0,
             },
             key_range: KeyRange::new(),
+            call: CallOptions::new(),
+            auto_paginate: None,
+            low_priority: false,
         }
     }
 
+    /// Aborts the request if it has not completed by `deadline`, including its retries.
+    #[inline]
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.call.set_deadline(deadline);
+        self
+    }
+
+    /// Aborts the request when `token` is cancelled.
+    #[inline]
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.call.set_cancel(token);
+        self
+    }
+
+    /// Sends the metadata `key: value` with the request, replacing the metadata of the client
+    /// under the same key, see
+    /// [`ConnectOptions::with_metadata`](crate::ConnectOptions::with_metadata).
+    #[inline]
+    pub fn with_metadata(mut self, key: &str, value: impl AsRef<[u8]>) -> Result<Self> {
+        self.call.append_metadata(key, value.as_ref())?;
+        Ok(self)
+    }
+
+    /// Sends the request to the member of `route` rather than to any member, see
+    /// [`RouteTo`].
+    #[inline]
+    pub fn with_route(mut self, route: RouteTo) -> Self {
+        self.call.set_route(route);
+        self
+    }
+
     /// Specifies the range of 'Get'.
     /// Returns the keys in the range [key, end_key).
     /// `end_key` must be lexicographically greater than start key.
@@ -334,8 +951,8 @@ impl GetOptions {
     /// If revision is less or equal to zero, the range is over the newest key-value store.
     /// If the revision has been compacted, ErrCompacted is returned as a response.
     #[inline]
-    pub const fn with_revision(mut self, revision: i64) -> Self {
-        self.req.revision = revision;
+    pub fn with_revision(mut self, revision: impl Into<Revision>) -> Self {
+        self.req.revision = revision.into().get();
         self
     }
 
@@ -386,32 +1003,55 @@ impl GetOptions {
     /// Sets the lower bound for returned key mod revisions; all keys with
     /// lesser mod revisions will be filtered away.
     #[inline]
-    pub const fn with_min_mod_revision(mut self, revision: i64) -> Self {
-        self.req.min_mod_revision = revision;
+    pub fn with_min_mod_revision(mut self, revision: impl Into<Revision>) -> Self {
+        self.req.min_mod_revision = revision.into().get();
         self
     }
 
     /// Sets the upper bound for returned key mod revisions; all keys with
     /// greater mod revisions will be filtered away.
     #[inline]
-    pub const fn with_max_mod_revision(mut self, revision: i64) -> Self {
-        self.req.max_mod_revision = revision;
+    pub fn with_max_mod_revision(mut self, revision: impl Into<Revision>) -> Self {
+        self.req.max_mod_revision = revision.into().get();
         self
     }
 
     /// Sets the lower bound for returned key create revisions; all keys with
     /// lesser create revisions will be filtered away.
     #[inline]
-    pub const fn with_min_create_revision(mut self, revision: i64) -> Self {
-        self.req.min_create_revision = revision;
+    pub fn with_min_create_revision(mut self, revision: impl Into<Revision>) -> Self {
+        self.req.min_create_revision = revision.into().get();
         self
     }
 
     /// `max_create_revision` is the upper bound for returned key create revisions; all keys with
     /// greater create revisions will be filtered away.
     #[inline]
-    pub const fn with_max_create_revision(mut self, revision: i64) -> Self {
-        self.req.max_create_revision = revision;
+    pub fn with_max_create_revision(mut self, revision: impl Into<Revision>) -> Self {
+        self.req.max_create_revision = revision.into().get();
+        self
+    }
+
+    /// Gets the range again as pages of `batch` keys if its response is larger than the
+    /// maximum message size.
+    ///
+    /// All pages are read at the revision of the first one, and are assembled into a single
+    /// response, whose `count` is the one of the first page. The limit of the request is
+    /// honored. A range sorted in another order than by ascending keys is not paginated.
+    /// Ignored in transactions.
+    #[inline]
+    pub const fn with_auto_paginate(mut self, batch: i64) -> Self {
+        self.auto_paginate = Some(batch);
+        self
+    }
+
+    /// Sends the request over the bulk lane, unhedged, if the client was connected with
+    /// [`ConnectOptions::with_priority_lanes`](crate::ConnectOptions::with_priority_lanes),
+    /// so that a large read does not delay the latency-critical requests.
+    /// Ignored in transactions.
+    #[inline]
+    pub const fn with_low_priority(mut self) -> Self {
+        self.low_priority = true;
         self
     }
 
@@ -419,14 +1059,21 @@ impl GetOptions {
     pub(crate) fn key_range_end_mut(&mut self) -> &mut Vec<u8> {
         &mut self.key_range.range_end
     }
+
+    /// The key range read.
+    #[cfg(feature = "watch")]
+    #[inline]
+    pub(super) fn key_range(&self) -> &KeyRange {
+        &self.key_range
+    }
 }
 
 impl From<GetOptions> for PbRangeRequest {
     #[inline]
     fn from(mut options: GetOptions) -> Self {
         let (key, rang_end) = options.key_range.build();
-        options.req.key = key;
-        options.req.range_end = rang_end;
+        options.req.key = key.into();
+        options.req.range_end = rang_end.into();
         options.req
     }
 }
@@ -438,9 +1085,34 @@ impl IntoRequest<PbRangeRequest> for GetOptions {
     }
 }
 
+/// Returns `true` if the response of a request was larger than the maximum message size of
+/// the server or of the client.
+fn is_response_too_large(err: &Error) -> bool {
+    match err {
+        Error::RequestTooLarge {
+            status: Some(status),
+            ..
+        } => status
+            .message()
+            .starts_with("grpc: trying to send message larger than max"),
+        Error::GRpcStatus(status) => {
+            status.code() == tonic::Code::OutOfRange
+                && status
+                    .message()
+                    .contains("decoded message length too large")
+        }
+        _ => false,
+    }
+}
+
 /// Response for `Get` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct GetResponse(PbRangeResponse);
 
@@ -484,6 +1156,15 @@ impl GetResponse {
         }
     }
 
+    /// Calls `f` on the key-value pairs of the response, up to the first error.
+    #[inline]
+    pub(crate) fn try_for_each_kv(
+        &mut self,
+        f: &mut impl FnMut(&mut PbKeyValue) -> Result<()>,
+    ) -> Result<()> {
+        self.0.kvs.iter_mut().try_for_each(f)
+    }
+
     /// Indicates if there are more keys to return in the requested range.
     #[inline]
     pub const fn more(&self) -> bool {
@@ -502,12 +1183,15 @@ impl GetResponse {
 pub struct DeleteOptions {
     req: PbDeleteRequest,
     key_range: KeyRange,
+    call: CallOptions,
+    dry_run: Option<i64>,
+    max_deletions: Option<i64>,
 }
 
 impl DeleteOptions {
     /// Sets key.
     #[inline]
-    fn with_key(mut self, key: impl Into<Vec<u8>>) -> Self {
+    pub(crate) fn with_key(mut self, key: impl Into<Vec<u8>>) -> Self {
         self.key_range.with_key(key);
         self
     }
@@ -517,14 +1201,111 @@ impl DeleteOptions {
     pub const fn new() -> Self {
         Self {
             req: PbDeleteRequest {
-                key: Vec::new(),
-                range_end: Vec::new(),
+                key: Bytes::new(),
+                range_end: Bytes::new(),
                 prev_kv: false,
             },
             key_range: KeyRange::new(),
+            call: CallOptions::new(),
+            dry_run: None,
+            max_deletions: None,
         }
     }
 
+    /// Aborts the request if it has not completed by `deadline`, including its retries.
+    #[inline]
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.call.set_deadline(deadline);
+        self
+    }
+
+    /// Aborts the request when `token` is cancelled.
+    #[inline]
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.call.set_cancel(token);
+        self
+    }
+
+    /// Sends the metadata `key: value` with the request, replacing the metadata of the client
+    /// under the same key, see
+    /// [`ConnectOptions::with_metadata`](crate::ConnectOptions::with_metadata).
+    #[inline]
+    pub fn with_metadata(mut self, key: &str, value: impl AsRef<[u8]>) -> Result<Self> {
+        self.call.append_metadata(key, value.as_ref())?;
+        Ok(self)
+    }
+
+    /// Sends the request to the member of `route` rather than to any member, see
+    /// [`RouteTo`].
+    #[inline]
+    pub fn with_route(mut self, route: RouteTo) -> Self {
+        self.call.set_route(route);
+        self
+    }
+
+    /// Counts the keys the delete would remove instead of deleting them, see
+    /// [`KvClient::delete_dry_run`]. [`KvClient::delete`] then returns the count as
+    /// [`DeleteResponse::deleted`], and no previous key-value.
+    ///
+    /// Only honored by [`KvClient::delete`], not by [`TxnOp::delete`].
+    #[inline]
+    pub const fn with_dry_run(mut self) -> Self {
+        self.dry_run = Some(0);
+        self
+    }
+
+    /// Counts the keys the delete would remove instead of deleting them, and lists up to
+    /// `limit` of them, without their values, as [`DeleteResponse::prev_kvs`]. See
+    /// [`DeleteOptions::with_dry_run`].
+    #[inline]
+    pub const fn with_dry_run_keys(mut self, limit: i64) -> Self {
+        self.dry_run = Some(if limit > 0 { limit } else { 0 });
+        self
+    }
+
+    /// Refuses to delete more than `limit` keys: the keys of the range are counted first,
+    /// and the delete fails with an [`Error::DeletionLimitExceeded`] if there are more.
+    ///
+    /// The delete is then sent in a txn comparing that no key of the range has been put since
+    /// the count, so that keys created in between are not deleted unchecked; the keys are
+    /// counted again and the txn sent again otherwise, until the deadline of the request.
+    /// Only honored by [`KvClient::delete`], not by [`TxnOp::delete`].
+    #[inline]
+    pub const fn with_max_deletions(mut self, limit: i64) -> Self {
+        self.max_deletions = Some(limit);
+        self
+    }
+
+    /// The number of keys to list in a dry run, if the delete is one.
+    #[inline]
+    pub(crate) const fn dry_run(&self) -> Option<i64> {
+        self.dry_run
+    }
+
+    /// The maximum number of keys to delete, if limited.
+    #[inline]
+    pub(crate) const fn max_deletions(&self) -> Option<i64> {
+        self.max_deletions
+    }
+
+    /// Returns `true` if the delete is a dry run or limited, and not a plain DeleteRange.
+    #[inline]
+    pub(crate) const fn is_guarded(&self) -> bool {
+        self.dry_run.is_some() || self.max_deletions.is_some()
+    }
+
+    /// Takes the deadline, cancellation and metadata of the request.
+    #[inline]
+    pub(crate) fn take_call(&mut self) -> CallOptions {
+        std::mem::take(&mut self.call)
+    }
+
+    /// The key and the range end to delete.
+    #[inline]
+    pub(crate) fn range(&self) -> (Vec<u8>, Vec<u8>) {
+        self.key_range.clone().build()
+    }
+
     /// `end_key` is the key following the last key to delete for the range [key, end_key).
     #[inline]
     pub fn with_range(mut self, end_key: impl Into<Vec<u8>>) -> Self {
@@ -571,8 +1352,8 @@ impl From<DeleteOptions> for PbDeleteRequest {
     #[inline]
     fn from(mut options: DeleteOptions) -> Self {
         let (key, rang_end) = options.key_range.build();
-        options.req.key = key;
-        options.req.range_end = rang_end;
+        options.req.key = key.into();
+        options.req.range_end = rang_end.into();
         options.req
     }
 }
@@ -587,6 +1368,11 @@ impl IntoRequest<PbDeleteRequest> for DeleteOptions {
 /// Response for `Delete` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct DeleteResponse(PbDeleteResponse);
 
@@ -597,6 +1383,33 @@ impl DeleteResponse {
         Self(resp)
     }
 
+    /// The response of a dry run, reporting the keys of `get` as deleted.
+    #[inline]
+    pub(crate) fn from_dry_run(get: GetResponse) -> Self {
+        Self(PbDeleteResponse {
+            header: get.0.header,
+            deleted: get.0.count,
+            prev_kvs: get.0.kvs,
+        })
+    }
+
+    /// The response of the delete of the txn `txn`, with the header of the txn.
+    pub(crate) fn from_txn(txn: TxnResponse) -> Self {
+        let delete = txn
+            .0
+            .responses
+            .into_iter()
+            .find_map(|resp| match resp.response {
+                Some(PbTxnOpResponse::ResponseDeleteRange(delete)) => Some(delete),
+                _ => None,
+            })
+            .unwrap_or_default();
+        Self(PbDeleteResponse {
+            header: txn.0.header,
+            ..delete
+        })
+    }
+
     /// Delete response header.
     #[inline]
     pub fn header(&self) -> Option<&ResponseHeader> {
@@ -634,10 +1447,24 @@ impl DeleteResponse {
             kv.key.strip_key_prefix(prefix);
         }
     }
+
+    /// Calls `f` on the key-value pairs of the response, up to the first error.
+    #[inline]
+    pub(crate) fn try_for_each_kv(
+        &mut self,
+        f: &mut impl FnMut(&mut PbKeyValue) -> Result<()>,
+    ) -> Result<()> {
+        self.0.prev_kvs.iter_mut().try_for_each(f)
+    }
 }
 
 /// Options for `Compact` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct CompactionOptions(PbCompactionRequest);
 
@@ -678,6 +1505,11 @@ impl IntoRequest<PbCompactionRequest> for CompactionOptions {
 /// Response for `Compact` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct CompactionResponse(PbCompactionResponse);
 
@@ -703,6 +1535,11 @@ impl CompactionResponse {
 
 /// Transaction comparison.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct Compare(PbCompare);
 
@@ -718,8 +1555,8 @@ impl Compare {
         Self(PbCompare {
             result: cmp as i32,
             target: target as i32,
-            key: key.into(),
-            range_end: Vec::new(),
+            key: shared_bytes(key),
+            range_end: Bytes::new(),
             target_union: Some(target_union),
         })
     }
@@ -737,7 +1574,12 @@ impl Compare {
 
     /// Compares the creation revision of the given key.
     #[inline]
-    pub fn create_revision(key: impl Into<Vec<u8>>, cmp: CompareOp, revision: i64) -> Self {
+    pub fn create_revision(
+        key: impl Into<Vec<u8>>,
+        cmp: CompareOp,
+        revision: impl Into<Revision>,
+    ) -> Self {
+        let revision = revision.into().get();
         Self::new(
             key,
             cmp,
@@ -748,7 +1590,12 @@ impl Compare {
 
     /// Compares the last modified revision of the given key.
     #[inline]
-    pub fn mod_revision(key: impl Into<Vec<u8>>, cmp: CompareOp, revision: i64) -> Self {
+    pub fn mod_revision(
+        key: impl Into<Vec<u8>>,
+        cmp: CompareOp,
+        revision: impl Into<Revision>,
+    ) -> Self {
+        let revision = revision.into().get();
         Self::new(
             key,
             cmp,
@@ -764,33 +1611,39 @@ impl Compare {
             key,
             cmp,
             CompareTarget::Value,
-            TargetUnion::Value(value.into()),
+            TargetUnion::Value(shared_bytes(value)),
         )
     }
 
     /// Compares the lease id of the given key.
     #[inline]
-    pub fn lease(key: impl Into<Vec<u8>>, cmp: CompareOp, lease: i64) -> Self {
+    pub fn lease(key: impl Into<Vec<u8>>, cmp: CompareOp, lease: impl Into<LeaseId>) -> Self {
+        let lease = lease.into().get();
         Self::new(key, cmp, CompareTarget::Lease, TargetUnion::Lease(lease))
     }
 
     /// Sets the comparison to scan the range [key, end).
     #[inline]
     pub fn with_range(mut self, end: impl Into<Vec<u8>>) -> Self {
-        self.0.range_end = end.into();
+        self.0.range_end = shared_bytes(end);
         self
     }
 
     /// Sets the comparison to scan all keys prefixed by the key.
     #[inline]
     pub fn with_prefix(mut self) -> Self {
-        self.0.range_end = get_prefix(&self.0.key);
+        self.0.range_end = get_prefix(&self.0.key).into();
         self
     }
 }
 
 /// Transaction operation.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct TxnOp(PbTxnOp);
 
@@ -828,6 +1681,17 @@ impl TxnOp {
     pub fn txn(txn: Txn) -> Self {
         TxnOp(PbTxnOp::RequestTxn(txn.into()))
     }
+
+    /// Returns `true` if the operation is a txn with compares, at any depth.
+    pub(crate) fn has_compares(&self) -> bool {
+        fn has_compares(txn: &PbTxnRequest) -> bool {
+            !txn.compare.is_empty()
+                || txn.success.iter().chain(&txn.failure).any(
+                    |op| matches!(&op.request, Some(PbTxnOp::RequestTxn(txn)) if has_compares(txn)),
+                )
+        }
+        matches!(&self.0, PbTxnOp::RequestTxn(txn) if has_compares(txn))
+    }
 }
 
 impl From<TxnOp> for PbTxnOp {
@@ -844,6 +1708,7 @@ pub struct Txn {
     c_when: bool,
     c_then: bool,
     c_else: bool,
+    call: CallOptions,
 }
 
 impl Txn {
@@ -859,9 +1724,41 @@ impl Txn {
             c_when: false,
             c_then: false,
             c_else: false,
+            call: CallOptions::new(),
         }
     }
 
+    /// Aborts the transaction if it has not completed by `deadline`, including its retries.
+    #[inline]
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.call.set_deadline(deadline);
+        self
+    }
+
+    /// Aborts the transaction when `token` is cancelled.
+    #[inline]
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.call.set_cancel(token);
+        self
+    }
+
+    /// Sends the metadata `key: value` with the transaction, replacing the metadata of the
+    /// client under the same key, see
+    /// [`ConnectOptions::with_metadata`](crate::ConnectOptions::with_metadata).
+    #[inline]
+    pub fn with_metadata(mut self, key: &str, value: impl AsRef<[u8]>) -> Result<Self> {
+        self.call.append_metadata(key, value.as_ref())?;
+        Ok(self)
+    }
+
+    /// Sends the request to the member of `route` rather than to any member, see
+    /// [`RouteTo`].
+    #[inline]
+    pub fn with_route(mut self, route: RouteTo) -> Self {
+        self.call.set_route(route);
+        self
+    }
+
     /// Takes a list of comparison. If all comparisons passed in succeed,
     /// the operations passed into `and_then()` will be executed. Or the operations
     /// passed into `or_else()` will be executed.
@@ -924,6 +1821,16 @@ impl Txn {
     pub(crate) fn prefix_with(&mut self, prefix: &[u8]) {
         self.req.prefix_with(prefix);
     }
+
+    /// Calls `f` on the values of the puts and of the compares of the transaction, at any
+    /// depth, up to the first error.
+    #[inline]
+    pub(crate) fn try_for_each_value(
+        &mut self,
+        f: &mut impl FnMut(&mut Bytes) -> Result<()>,
+    ) -> Result<()> {
+        self.req.try_for_each_value(f)
+    }
 }
 
 impl PbTxnRequest {
@@ -956,6 +1863,22 @@ impl PbTxnRequest {
         self.success.iter_mut().for_each(prefix_op);
         self.failure.iter_mut().for_each(prefix_op);
     }
+
+    fn try_for_each_value(&mut self, f: &mut impl FnMut(&mut Bytes) -> Result<()>) -> Result<()> {
+        for cmp in self.compare.iter_mut() {
+            if let Some(TargetUnion::Value(value)) = &mut cmp.target_union {
+                f(value)?;
+            }
+        }
+        for op in self.success.iter_mut().chain(self.failure.iter_mut()) {
+            match &mut op.request {
+                Some(PbTxnOp::RequestPut(req)) => f(&mut req.value)?,
+                Some(PbTxnOp::RequestTxn(req)) => req.try_for_each_value(&mut *f)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
 }
 
 impl From<Txn> for PbTxnRequest {
@@ -985,6 +1908,11 @@ pub enum TxnOpResponse {
 /// Response for `Txn` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct TxnResponse(PbTxnResponse);
 
@@ -1040,6 +1968,16 @@ impl TxnResponse {
     pub(crate) fn strip_key_prefix(&mut self, prefix: &[u8]) {
         self.0.strip_key_prefix(prefix);
     }
+
+    /// Calls `f` on the key-value pairs of the responses of the operations, at any depth,
+    /// up to the first error.
+    #[inline]
+    pub(crate) fn try_for_each_kv(
+        &mut self,
+        f: &mut impl FnMut(&mut PbKeyValue) -> Result<()>,
+    ) -> Result<()> {
+        self.0.try_for_each_kv(f)
+    }
 }
 
 impl PbTxnResponse {
@@ -1069,4 +2007,338 @@ impl PbTxnResponse {
             }
         });
     }
+
+    fn try_for_each_kv(&mut self, f: &mut impl FnMut(&mut PbKeyValue) -> Result<()>) -> Result<()> {
+        for op in self.responses.iter_mut() {
+            match &mut op.response {
+                Some(PbTxnOpResponse::ResponseRange(r)) => {
+                    r.kvs.iter_mut().try_for_each(&mut *f)?
+                }
+                Some(PbTxnOpResponse::ResponsePut(r)) => {
+                    r.prev_kv.iter_mut().try_for_each(&mut *f)?
+                }
+                Some(PbTxnOpResponse::ResponseDeleteRange(r)) => {
+                    r.prev_kvs.iter_mut().try_for_each(&mut *f)?
+                }
+                Some(PbTxnOpResponse::ResponseTxn(r)) => r.try_for_each_kv(&mut *f)?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl_raw_conversions!(
+    PutResponse(PbPutResponse),
+    GetResponse(PbRangeResponse),
+    DeleteResponse(PbDeleteResponse),
+    CompactionResponse(PbCompactionResponse),
+    Compare(PbCompare),
+    TxnResponse(PbTxnResponse),
+);
+
+impl_raw_conversions!(
+    options:
+    PutOptions(PbPutRequest, CallOptions, bool),
+    CompactionOptions(PbCompactionRequest),
+    TxnOp(PbTxnOp),
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::Channel;
+    use crate::intercept::Interceptor;
+    use crate::rpc::pb::etcdserverpb::ResponseHeader as PbResponseHeader;
+    use crate::rpc::pb::mvccpb::KeyValue as PbKeyValue;
+    use http_body::Frame;
+    use http_body_util::{BodyExt, StreamBody};
+    use prost::Message;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Mutex;
+    use tonic::codegen::Bytes;
+    use tower::util::BoxCloneService;
+    use tower::ServiceExt;
+
+    /// A successful gRPC response with the message `msg`.
+    fn grpc_response(msg: &impl Message) -> http::Response<tonic::body::Body> {
+        let mut buf = vec![0];
+        buf.extend_from_slice(&(msg.encoded_len() as u32).to_be_bytes());
+        msg.encode(&mut buf).unwrap();
+        let mut trailers = http::HeaderMap::new();
+        tonic::Status::ok("").add_header(&mut trailers).unwrap();
+        let frames = [
+            Ok::<_, tower::BoxError>(Frame::data(Bytes::from(buf))),
+            Ok(Frame::trailers(trailers)),
+        ];
+        let body = tonic::body::Body::new(StreamBody::new(tokio_stream::iter(frames)));
+        http::Response::builder()
+            .header("content-type", "application/grpc")
+            .body(body)
+            .unwrap()
+    }
+
+    /// A client whose range requests fail with `message` `failures` times, then succeed.
+    fn flaky_client(message: &'static str, failures: u32) -> (KvClient, Arc<AtomicU32>) {
+        let requests = Arc::new(AtomicU32::new(0));
+        let service = tower::service_fn({
+            let requests = requests.clone();
+            move |_req: http::Request<tonic::body::Body>| {
+                let request = requests.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if request < failures {
+                        let status = tonic::Status::unavailable(message);
+                        return Ok::<_, tower::BoxError>(status.into_http());
+                    }
+                    let msg = PbRangeResponse {
+                        kvs: vec![PbKeyValue {
+                            key: b"key".to_vec(),
+                            value: b"value".to_vec(),
+                            ..Default::default()
+                        }],
+                        count: 1,
+                        ..Default::default()
+                    };
+                    Ok(grpc_response(&msg))
+                }
+            }
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor::default(),
+        );
+        let client = KvClient::new(channel, AuthToken::default());
+        (client, requests)
+    }
+
+    #[tokio::test]
+    async fn test_range_read_retries() {
+        for message in [
+            "etcdserver: leader changed",
+            "etcdserver: request timed out",
+        ] {
+            let (mut client, requests) = flaky_client(message, 2);
+            let resp = client.get("key", None).await.unwrap();
+            assert_eq!(resp.kvs()[0].value(), b"value");
+            assert_eq!(requests.load(Ordering::SeqCst), 3);
+        }
+
+        let (client, requests) = flaky_client("etcdserver: leader changed", 2);
+        let err = client
+            .with_read_retries(0)
+            .get("key", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::LeaderChanged { .. }));
+        assert_eq!(err.rpc(), Some("Range"));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    /// A client of a server holding the lease `0x10` of 1 second, whose puts take 1.5 seconds
+    /// to reach the server.
+    #[cfg(feature = "lease")]
+    fn lease_client() -> KvClient {
+        use crate::rpc::pb::etcdserverpb::LeaseTimeToLiveResponse as PbLeaseTimeToLiveResponse;
+        use tokio::time::Instant;
+
+        let expiry = Instant::now() + Duration::from_secs(1);
+        let service = tower::service_fn(move |req: http::Request<tonic::body::Body>| async move {
+            let not_found = tonic::Status::not_found("etcdserver: requested lease not found");
+            match req.uri().path() {
+                "/etcdserverpb.Lease/LeaseTimeToLive" => {
+                    let remaining = expiry.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok::<_, tower::BoxError>(not_found.into_http());
+                    }
+                    let msg = PbLeaseTimeToLiveResponse {
+                        id: 0x10,
+                        ttl: remaining.as_secs() as i64,
+                        granted_ttl: 1,
+                        ..Default::default()
+                    };
+                    Ok(grpc_response(&msg))
+                }
+                "/etcdserverpb.KV/Put" => {
+                    tokio::time::sleep(Duration::from_millis(1500)).await;
+                    if Instant::now() >= expiry {
+                        return Ok(not_found.into_http());
+                    }
+                    Ok(grpc_response(&PbPutResponse::default()))
+                }
+                path => panic!("unexpected request: {}", path),
+            }
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor::default(),
+        );
+        KvClient::new(channel, AuthToken::default())
+    }
+
+    #[cfg(feature = "lease")]
+    #[tokio::test(start_paused = true)]
+    async fn test_put_lease_checked() {
+        // The lease expires while the put is in flight.
+        let mut client = lease_client();
+        let options = PutOptions::new().with_lease_checked(0x10);
+        let err = client
+            .put("key", "value", Some(options.clone()))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::LeaseExpired {
+                    id,
+                    status: Some(_)
+                } if id == 0x10
+            ),
+            "{:?}",
+            err
+        );
+        assert_eq!(err.rpc(), Some("Put"));
+
+        // The lease is known to have expired before the put is sent.
+        let err = client.put("key", "value", Some(options)).await.unwrap_err();
+        assert!(
+            matches!(err, Error::LeaseNotFound { id: Some(id), .. } if id == 0x10),
+            "{:?}",
+            err
+        );
+        assert_eq!(err.rpc(), Some("LeaseTimeToLive"));
+
+        // Unchecked, the put is sent and fails.
+        let options = PutOptions::new().with_lease(0x10);
+        let err = client.put("key", "value", Some(options)).await.unwrap_err();
+        assert!(
+            matches!(err, Error::LeaseNotFound { id: Some(id), .. } if id == 0x10),
+            "{:?}",
+            err
+        );
+        assert_eq!(err.rpc(), Some("Put"));
+    }
+
+    #[tokio::test]
+    async fn test_low_priority_bulk_lane() {
+        let (client, requests) = flaky_client("", 0);
+        let (bulk, bulk_requests) = flaky_client("", 0);
+        let options = GetOptions::new().with_low_priority();
+
+        // Without a bulk lane, the low priority reads are sent as any other.
+        client
+            .clone()
+            .get("key", Some(options.clone()))
+            .await
+            .unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let mut client = KvClient {
+            bulk: Some(bulk.inner),
+            ..client
+        };
+        client.get("key", None).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(bulk_requests.load(Ordering::SeqCst), 0);
+        let resp = client.get("key", Some(options)).await.unwrap();
+        assert_eq!(resp.kvs()[0].value(), b"value");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(bulk_requests.load(Ordering::SeqCst), 1);
+    }
+
+    /// A client over the keys `a` to `e` at revision 10, whose range requests without a limit
+    /// fail because the response is too large, and the requests it received.
+    fn paged_client() -> (KvClient, Arc<Mutex<Vec<PbRangeRequest>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let service = tower::service_fn({
+            let requests = requests.clone();
+            move |req: http::Request<tonic::body::Body>| {
+                let requests = requests.clone();
+                async move {
+                    let body = req.into_body().collect().await.unwrap().to_bytes();
+                    let req = PbRangeRequest::decode(&body[5..]).unwrap();
+                    requests.lock().unwrap().push(req.clone());
+                    if req.limit == 0 {
+                        let status = tonic::Status::resource_exhausted(
+                            "grpc: trying to send message larger than max (5000000 vs. 4194304)",
+                        );
+                        return Ok::<_, tower::BoxError>(status.into_http());
+                    }
+                    let keys = [b"a", b"b", b"c", b"d", b"e"];
+                    let matched: Vec<_> = keys
+                        .iter()
+                        .filter(|key| key.as_slice() >= &req.key[..])
+                        .collect();
+                    let msg = PbRangeResponse {
+                        header: Some(PbResponseHeader {
+                            revision: 10,
+                            ..Default::default()
+                        }),
+                        kvs: matched
+                            .iter()
+                            .take(req.limit as usize)
+                            .map(|key| PbKeyValue {
+                                key: key.to_vec(),
+                                ..Default::default()
+                            })
+                            .collect(),
+                        more: matched.len() > req.limit as usize,
+                        count: matched.len() as i64,
+                    };
+                    Ok(grpc_response(&msg))
+                }
+            }
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor::default(),
+        );
+        let client = KvClient::new(channel, AuthToken::default());
+        (client, requests)
+    }
+
+    #[tokio::test]
+    async fn test_auto_paginate() {
+        let (mut client, requests) = paged_client();
+        let options = GetOptions::new().with_all_keys().with_auto_paginate(2);
+        let resp = client.get("", Some(options)).await.unwrap();
+        let keys: Vec<_> = resp.kvs().iter().map(|kv| kv.key()).collect();
+        assert_eq!(keys, [b"a", b"b", b"c", b"d", b"e"]);
+        assert_eq!(resp.count(), 5);
+        assert!(!resp.more());
+        let requests = std::mem::take(&mut *requests.lock().unwrap());
+        assert_eq!(requests.len(), 4);
+        // The pages after the first one are pinned to its revision.
+        assert_eq!(requests[1].revision, 0);
+        assert_eq!(requests[2].key, &b"b\0"[..]);
+        assert_eq!(requests[2].revision, 10);
+        assert_eq!(requests[3].key, &b"d\0"[..]);
+
+        // The limit of the request is honored.
+        let (mut client, _) = paged_client();
+        let options = GetOptions::new()
+            .with_all_keys()
+            .with_limit(3)
+            .with_auto_paginate(2);
+        let resp = client.get("", Some(options)).await.unwrap();
+        assert_eq!(resp.kvs().len(), 3);
+        assert_eq!(resp.count(), 5);
+        assert!(resp.more());
+
+        let (mut client, requests) = paged_client();
+        let err = client
+            .get("", Some(GetOptions::new().with_all_keys()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::RequestTooLarge { .. }));
+
+        // Keys sorted in descending order can not be paginated.
+        requests.lock().unwrap().clear();
+        let options = GetOptions::new()
+            .with_all_keys()
+            .with_sort(SortTarget::Key, SortOrder::Descend)
+            .with_auto_paginate(2);
+        let err = client.get("", Some(options)).await.unwrap_err();
+        assert!(matches!(err, Error::RequestTooLarge { .. }));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
 }
@@ -1,8 +1,14 @@
 //! Etcd Lease RPC.
 
-use crate::auth::AuthService;
-use crate::error::Result;
+use crate::auth::{AuthService, AuthToken};
+use crate::compression::{Compressing, Compression};
+use crate::deadline::CallOptions;
+use crate::error::{Result, RpcResultExt};
+use crate::ids::LeaseId;
 use crate::intercept::InterceptedChannel;
+use crate::keep_alive::{KeepAlivePool, KeptAlive, DEFAULT_LEASE_KEEP_ALIVE_GRANULARITY};
+use crate::observe::{observe_call, observed, Call, Observer};
+use crate::retry::{retry, RetryPolicy, DEFAULT_READ_RETRIES};
 use crate::rpc::pb::etcdserverpb::lease_client::LeaseClient as PbLeaseClient;
 use crate::rpc::pb::etcdserverpb::{
     LeaseGrantRequest as PbLeaseGrantRequest, LeaseGrantResponse as PbLeaseGrantResponse,
@@ -14,33 +20,123 @@ use crate::rpc::pb::etcdserverpb::{
     LeaseTimeToLiveResponse as PbLeaseTimeToLiveResponse,
 };
 use crate::rpc::ResponseHeader;
+use crate::task::Tasks;
+use crate::trace::stream_event;
 use crate::vec::VecExt;
 use crate::Error;
-use http::HeaderValue;
 use std::pin::Pin;
-use std::sync::{Arc, RwLock};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{channel, Sender};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::Stream;
+use tokio_util::sync::CancellationToken;
 use tonic::{IntoRequest, Request, Streaming};
 
 /// Client for lease operations.
-#[repr(transparent)]
 #[derive(Clone)]
 pub struct LeaseClient {
-    inner: PbLeaseClient<AuthService<InterceptedChannel>>,
+    inner: Compressing<PbLeaseClient<AuthService<InterceptedChannel>>>,
+    retry: Option<RetryPolicy>,
+    read_retries: u32,
+    default_deadline: Option<Duration>,
+    create_timeout: Option<Duration>,
+    tasks: Tasks,
+    observer: Observer,
+    pool: KeepAlivePool,
 }
 
 impl LeaseClient {
     /// Creates a `LeaseClient`.
     #[inline]
-    pub(crate) fn new(
-        channel: InterceptedChannel,
-        auth_token: Arc<RwLock<Option<HeaderValue>>>,
-    ) -> Self {
-        let inner = PbLeaseClient::new(AuthService::new(channel, auth_token));
-        Self { inner }
+    pub(crate) fn new(channel: InterceptedChannel, auth_token: AuthToken) -> Self {
+        let inner = Compressing::new(PbLeaseClient::new(AuthService::new(channel, auth_token)));
+        Self {
+            inner,
+            retry: None,
+            read_retries: DEFAULT_READ_RETRIES,
+            default_deadline: None,
+            create_timeout: None,
+            tasks: Tasks::default(),
+            observer: Observer::default(),
+            pool: KeepAlivePool::new(DEFAULT_LEASE_KEEP_ALIVE_GRANULARITY),
+        }
+    }
+
+    /// Retries requests failing with transient errors according to `policy`.
+    #[inline]
+    pub(crate) fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Retries read-only requests failing because the leader changed or the request timed
+    /// out up to `read_retries` times.
+    #[inline]
+    pub(crate) fn with_read_retries(mut self, read_retries: u32) -> Self {
+        self.read_retries = read_retries;
+        self
+    }
+
+    /// Aborts requests which have no deadline of their own after `deadline`.
+    #[inline]
+    pub(crate) fn with_default_deadline(mut self, deadline: Duration) -> Self {
+        self.default_deadline = Some(deadline);
+        self
+    }
+
+    /// Aborts opening keep alive streams after `timeout`.
+    #[inline]
+    pub(crate) fn with_create_timeout(mut self, timeout: Duration) -> Self {
+        self.create_timeout = Some(timeout);
+        self
+    }
+
+    /// Spawns the keep alive tasks of sessions with `tasks`.
+    #[inline]
+    pub(crate) fn with_tasks(mut self, tasks: Tasks) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    /// Wakes the keep alives of sessions up at most once per `granularity`.
+    #[inline]
+    pub(crate) fn with_keep_alive_granularity(mut self, granularity: Duration) -> Self {
+        self.pool = KeepAlivePool::new(granularity);
+        self
+    }
+
+    /// Observes requests with `observer`.
+    #[inline]
+    pub(crate) fn with_observer(mut self, observer: Observer) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Compresses the messages according to `compression`.
+    #[inline]
+    pub(crate) fn with_compression(mut self, compression: &Compression) -> Self {
+        self.inner = self.inner.with_compression(compression);
+        self
+    }
+
+    /// The background tasks of the client.
+    #[inline]
+    pub(crate) fn tasks(&self) -> &Tasks {
+        &self.tasks
+    }
+
+    /// The observer of the requests of the client.
+    #[inline]
+    pub(crate) fn observer(&self) -> &Observer {
+        &self.observer
+    }
+
+    /// Keeps the lease `id` of `ttl` seconds alive along with the leases of the other
+    /// sessions of the client, until the returned handle is dropped.
+    #[inline]
+    pub(crate) async fn keep_alive_shared(&mut self, id: i64, ttl: i64) -> Result<KeptAlive> {
+        self.pool.clone().register(self, id, ttl).await
     }
 
     /// Creates a lease which expires if the server does not receive a keepAlive
@@ -52,29 +148,68 @@ impl LeaseClient {
         ttl: i64,
         options: Option<LeaseGrantOptions>,
     ) -> Result<LeaseGrantResponse> {
-        let resp = self
-            .inner
-            .lease_grant(options.unwrap_or_default().with_ttl(ttl))
-            .await?
-            .into_inner();
+        let mut options = options.unwrap_or_default().with_ttl(ttl);
+        let call = std::mem::take(&mut options.1);
+        let mut inner = self.inner.clone();
+        let observer = self.observer.clone();
+        let resp = call
+            .run("LeaseGrant", self.default_deadline, async move {
+                Ok(
+                    observed!(observer, "LeaseGrant", inner.lease_grant(options))
+                        .await
+                        .for_rpc("LeaseGrant")?
+                        .into_inner(),
+                )
+            })
+            .await?;
         Ok(LeaseGrantResponse::new(resp))
     }
 
     /// Revokes a lease. All keys attached to the lease will expire and be deleted.
     #[inline]
-    pub async fn revoke(&mut self, id: i64) -> Result<LeaseRevokeResponse> {
-        let resp = self
-            .inner
-            .lease_revoke(LeaseRevokeOptions::new().with_id(id))
-            .await?
-            .into_inner();
+    pub async fn revoke(&mut self, id: impl Into<LeaseId>) -> Result<LeaseRevokeResponse> {
+        let id = id.into().get();
+        let mut inner = self.inner.clone();
+        let observer = self.observer.clone();
+        let resp = CallOptions::new()
+            .run("LeaseRevoke", self.default_deadline, async move {
+                let resp = observed!(
+                    observer,
+                    "LeaseRevoke",
+                    inner.lease_revoke(LeaseRevokeOptions::new().with_id(id))
+                )
+                .await
+                .for_rpc("LeaseRevoke")
+                .map_err(|e| e.with_lease_id(id))?;
+                Ok(resp.into_inner())
+            })
+            .await?;
         Ok(LeaseRevokeResponse::new(resp))
     }
 
     /// Keeps the lease alive by streaming keep alive requests from the client
     /// to the server and streaming keep alive responses from the server to the client.
     #[inline]
-    pub async fn keep_alive(&mut self, id: i64) -> Result<(LeaseKeeper, LeaseKeepAliveStream)> {
+    pub async fn keep_alive(
+        &mut self,
+        id: impl Into<LeaseId>,
+    ) -> Result<(LeaseKeeper, LeaseKeepAliveStream)> {
+        let id = id.into().get();
+        CallOptions::new()
+            .run(
+                "LeaseKeepAlive",
+                self.create_timeout,
+                Self::open_keep_alive(self.inner.clone(), self.observer.clone(), id),
+            )
+            .await
+    }
+
+    /// Opens a keep alive stream and sends the first keep alive of the lease `id` on it.
+    async fn open_keep_alive(
+        mut inner: Compressing<PbLeaseClient<AuthService<InterceptedChannel>>>,
+        observer: Observer,
+        id: i64,
+    ) -> Result<(LeaseKeeper, LeaseKeepAliveStream)> {
         let (sender, receiver) = channel::<PbLeaseKeepAliveRequest>(100);
         sender
             .send(LeaseKeepAliveOptions::new().with_id(id).into())
@@ -83,10 +218,15 @@ impl LeaseClient {
 
         let receiver = ReceiverStream::new(receiver);
 
-        let mut stream = self.inner.lease_keep_alive(receiver).await?.into_inner();
+        let call = observe_call!(observer, "LeaseKeepAlive");
+        let mut stream = observed!(call = call.clone(), inner.lease_keep_alive(receiver))
+            .await
+            .for_rpc("LeaseKeepAlive")?
+            .into_inner();
 
-        let id = match stream.message().await? {
+        let id = match stream.message().await.for_rpc("LeaseKeepAlive")? {
             Some(resp) => {
+                call.keep_alive(resp.ttl > 0);
                 if resp.ttl <= 0 {
                     return Err(Error::LeaseKeepAliveError("lease not found".to_string()));
                 }
@@ -101,7 +241,7 @@ impl LeaseClient {
 
         Ok((
             LeaseKeeper::new(id, sender),
-            LeaseKeepAliveStream::new(stream),
+            LeaseKeepAliveStream::new(stream, call),
         ))
     }
 
@@ -109,33 +249,91 @@ impl LeaseClient {
     #[inline]
     pub async fn time_to_live(
         &mut self,
-        id: i64,
+        id: impl Into<LeaseId>,
         options: Option<LeaseTimeToLiveOptions>,
     ) -> Result<LeaseTimeToLiveResponse> {
-        let resp = self
-            .inner
-            .lease_time_to_live(options.unwrap_or_default().with_id(id))
-            .await?
-            .into_inner();
+        let id = id.into().get();
+        let mut options = options.unwrap_or_default().with_id(id);
+        let call = std::mem::take(&mut options.1);
+        let inner = self.inner.clone();
+        let observer = self.observer.clone();
+        let resp = call
+            .run(
+                "LeaseTimeToLive",
+                self.default_deadline,
+                retry(
+                    self.retry.as_ref(),
+                    self.read_retries,
+                    "LeaseTimeToLive",
+                    true,
+                    move || {
+                        let mut inner = inner.clone();
+                        let observer = observer.clone();
+                        let options = options.clone();
+                        async move {
+                            Ok(observed!(
+                                observer,
+                                "LeaseTimeToLive",
+                                inner.lease_time_to_live(options)
+                            )
+                            .await
+                            .for_rpc("LeaseTimeToLive")
+                            .map_err(|e| e.with_lease_id(id))?
+                            .into_inner())
+                        }
+                    },
+                ),
+            )
+            .await?;
         Ok(LeaseTimeToLiveResponse::new(resp))
     }
 
+    /// Fails unless the lease `id` is alive, with [`Error::LeaseNotFound`] if the server does
+    /// not know it and with [`Error::LeaseExpired`] if it expired.
+    #[cfg(feature = "kv")]
+    pub(crate) async fn check_lease(&mut self, id: i64) -> Result<()> {
+        // Servers before 3.3 report an expired lease as a TTL of -1.
+        if self.time_to_live(id, None).await?.ttl() < 0 {
+            return Err(Error::LeaseExpired {
+                id: LeaseId::new(id),
+                status: None,
+            });
+        }
+        Ok(())
+    }
+
     /// Lists all existing leases.
     #[inline]
     pub async fn leases(&mut self) -> Result<LeaseLeasesResponse> {
-        let resp = self
-            .inner
-            .lease_leases(PbLeaseLeasesRequest {})
-            .await?
-            .into_inner();
+        let mut inner = self.inner.clone();
+        let observer = self.observer.clone();
+        let resp = CallOptions::new()
+            .run("LeaseLeases", self.default_deadline, async move {
+                Ok(observed!(
+                    observer,
+                    "LeaseLeases",
+                    inner.lease_leases(PbLeaseLeasesRequest {})
+                )
+                .await
+                .for_rpc("LeaseLeases")?
+                .into_inner())
+            })
+            .await?;
         Ok(LeaseLeasesResponse::new(resp))
     }
 }
 
 /// Options for `Grant` operation.
 #[derive(Debug, Default, Clone)]
-#[repr(transparent)]
-pub struct LeaseGrantOptions(PbLeaseGrantRequest);
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct LeaseGrantOptions(
+    PbLeaseGrantRequest,
+    #[cfg_attr(feature = "serde", serde(skip))] CallOptions,
+);
 
 impl LeaseGrantOptions {
     /// Set ttl
@@ -147,15 +345,38 @@ impl LeaseGrantOptions {
 
     /// Set id
     #[inline]
-    pub const fn with_id(mut self, id: i64) -> Self {
-        self.0.id = id;
+    pub fn with_id(mut self, id: impl Into<LeaseId>) -> Self {
+        self.0.id = id.into().get();
         self
     }
 
     /// Creates a `LeaseGrantOptions`.
     #[inline]
     pub const fn new() -> Self {
-        Self(PbLeaseGrantRequest { ttl: 0, id: 0 })
+        Self(PbLeaseGrantRequest { ttl: 0, id: 0 }, CallOptions::new())
+    }
+
+    /// Aborts the request if it has not completed by `deadline`, including its retries.
+    #[inline]
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.1.set_deadline(deadline);
+        self
+    }
+
+    /// Aborts the request when `token` is cancelled.
+    #[inline]
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.1.set_cancel(token);
+        self
+    }
+
+    /// Sends the metadata `key: value` with the request, replacing the metadata of the client
+    /// under the same key, see
+    /// [`ConnectOptions::with_metadata`](crate::ConnectOptions::with_metadata).
+    #[inline]
+    pub fn with_metadata(mut self, key: &str, value: impl AsRef<[u8]>) -> Result<Self> {
+        self.1.append_metadata(key, value.as_ref())?;
+        Ok(self)
     }
 }
 
@@ -176,6 +397,11 @@ impl IntoRequest<PbLeaseGrantRequest> for LeaseGrantOptions {
 /// Response for `Grant` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LeaseGrantResponse(PbLeaseGrantResponse);
 
@@ -206,8 +432,8 @@ impl LeaseGrantResponse {
 
     /// ID is the lease ID for the granted lease.
     #[inline]
-    pub const fn id(&self) -> i64 {
-        self.0.id
+    pub const fn id(&self) -> LeaseId {
+        LeaseId::new(self.0.id)
     }
 
     /// Error message if return error.
@@ -254,6 +480,11 @@ impl IntoRequest<PbLeaseRevokeRequest> for LeaseRevokeOptions {
 /// Response for `Revoke` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LeaseRevokeResponse(PbLeaseRevokeResponse);
 
@@ -314,6 +545,11 @@ impl IntoRequest<PbLeaseKeepAliveRequest> for LeaseKeepAliveOptions {
 /// Response for `KeepAlive` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LeaseKeepAliveResponse(PbLeaseKeepAliveResponse);
 
@@ -344,15 +580,22 @@ impl LeaseKeepAliveResponse {
 
     /// ID is the lease ID for the keep alive request.
     #[inline]
-    pub const fn id(&self) -> i64 {
-        self.0.id
+    pub const fn id(&self) -> LeaseId {
+        LeaseId::new(self.0.id)
     }
 }
 
 /// Options for `TimeToLive` operation.
 #[derive(Debug, Default, Clone)]
-#[repr(transparent)]
-pub struct LeaseTimeToLiveOptions(PbLeaseTimeToLiveRequest);
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct LeaseTimeToLiveOptions(
+    PbLeaseTimeToLiveRequest,
+    #[cfg_attr(feature = "serde", serde(skip))] CallOptions,
+);
 
 impl LeaseTimeToLiveOptions {
     /// ID is the lease ID for the lease.
@@ -372,7 +615,33 @@ impl LeaseTimeToLiveOptions {
     /// Creates a `LeaseTimeToLiveOptions`.
     #[inline]
     pub const fn new() -> Self {
-        Self(PbLeaseTimeToLiveRequest { id: 0, keys: false })
+        Self(
+            PbLeaseTimeToLiveRequest { id: 0, keys: false },
+            CallOptions::new(),
+        )
+    }
+
+    /// Aborts the request if it has not completed by `deadline`, including its retries.
+    #[inline]
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.1.set_deadline(deadline);
+        self
+    }
+
+    /// Aborts the request when `token` is cancelled.
+    #[inline]
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.1.set_cancel(token);
+        self
+    }
+
+    /// Sends the metadata `key: value` with the request, replacing the metadata of the client
+    /// under the same key, see
+    /// [`ConnectOptions::with_metadata`](crate::ConnectOptions::with_metadata).
+    #[inline]
+    pub fn with_metadata(mut self, key: &str, value: impl AsRef<[u8]>) -> Result<Self> {
+        self.1.append_metadata(key, value.as_ref())?;
+        Ok(self)
     }
 }
 
@@ -393,6 +662,11 @@ impl IntoRequest<PbLeaseTimeToLiveRequest> for LeaseTimeToLiveOptions {
 /// Response for `TimeToLive` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LeaseTimeToLiveResponse(PbLeaseTimeToLiveResponse);
 
@@ -423,8 +697,8 @@ impl LeaseTimeToLiveResponse {
 
     /// ID is the lease ID from the keep alive request.
     #[inline]
-    pub const fn id(&self) -> i64 {
-        self.0.id
+    pub const fn id(&self) -> LeaseId {
+        LeaseId::new(self.0.id)
     }
 
     /// GrantedTTL is the initial granted time in seconds upon lease creation/renewal.
@@ -439,6 +713,12 @@ impl LeaseTimeToLiveResponse {
         &self.0.keys
     }
 
+    /// Takes the keys attached to the lease, leaving an empty vector in their place.
+    #[inline]
+    pub fn take_keys(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.0.keys)
+    }
+
     #[inline]
     pub(crate) fn strip_keys_prefix(&mut self, prefix: &[u8]) {
         self.0.keys.iter_mut().for_each(|key| {
@@ -450,6 +730,11 @@ impl LeaseTimeToLiveResponse {
 /// Response for `Leases` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LeaseLeasesResponse(PbLeaseLeasesResponse);
 
@@ -482,14 +767,19 @@ impl LeaseLeasesResponse {
 /// Lease status.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LeaseStatus(PbLeaseStatus);
 
 impl LeaseStatus {
     /// Lease id.
     #[inline]
-    pub const fn id(&self) -> i64 {
-        self.0.id
+    pub const fn id(&self) -> LeaseId {
+        LeaseId::new(self.0.id)
     }
 }
 
@@ -517,55 +807,113 @@ impl LeaseKeeper {
 
     /// The lease id which user want to keep alive.
     #[inline]
-    pub const fn id(&self) -> i64 {
-        self.id
+    pub const fn id(&self) -> LeaseId {
+        LeaseId::new(self.id)
     }
 
     /// Sends a keep alive request and receive response
     #[inline]
     pub async fn keep_alive(&mut self) -> Result<()> {
+        self.send(self.id).await
+    }
+
+    /// Sends a keep alive request of the lease `id` on the stream of the keeper.
+    #[inline]
+    pub(crate) async fn send(&mut self, id: i64) -> Result<()> {
         self.sender
-            .send(LeaseKeepAliveOptions::new().with_id(self.id).into())
+            .send(LeaseKeepAliveOptions::new().with_id(id).into())
             .await
             .map_err(|e| Error::LeaseKeepAliveError(e.to_string()))
     }
 }
 
 /// The lease keep alive response stream.
+///
+/// The stream is a [`Stream`] of the responses, to compose with the adapters of
+/// `tokio_stream` or `futures`, e.g. to merge the streams of several leases:
+///
+/// ```no_run
+/// use etcd_client::{Client, Error};
+/// use tokio_stream::{StreamExt, StreamMap};
+///
+/// # async fn keep_alive(client: &mut Client, leases: &[i64]) -> Result<(), Error> {
+/// let (mut keepers, mut streams) = (Vec::new(), StreamMap::new());
+/// for &id in leases {
+///     let (keeper, stream) = client.lease_keep_alive(id).await?;
+///     keepers.push(keeper);
+///     streams.insert(id, stream);
+/// }
+/// while let Some((id, resp)) = streams.next().await {
+///     println!("lease {:x} kept alive for {}s", id, resp?.ttl());
+/// }
+/// # Ok(())
+/// # }
+/// ```
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug)]
 pub struct LeaseKeepAliveStream {
     stream: Streaming<PbLeaseKeepAliveResponse>,
+    call: Call,
 }
 
 impl LeaseKeepAliveStream {
     /// Creates a new `LeaseKeepAliveStream`.
     #[inline]
-    const fn new(stream: Streaming<PbLeaseKeepAliveResponse>) -> Self {
-        Self { stream }
+    const fn new(stream: Streaming<PbLeaseKeepAliveResponse>, call: Call) -> Self {
+        Self { stream, call }
     }
 
     /// Fetches the next message from this stream.
+    ///
+    /// The future is cancel safe: dropped before it completes, e.g. by a branch of
+    /// `tokio::select!` completing first, it loses no message, the next call fetching it.
     #[inline]
     pub async fn message(&mut self) -> Result<Option<LeaseKeepAliveResponse>> {
-        match self.stream.message().await? {
-            Some(resp) => Ok(Some(LeaseKeepAliveResponse::new(resp))),
-            None => Ok(None),
-        }
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+            .await
+            .transpose()
     }
 }
 
+/// A message is only taken out of the underlying stream by the poll returning it, so a
+/// pending poll abandoned loses nothing.
 impl Stream for LeaseKeepAliveStream {
     type Item = Result<LeaseKeepAliveResponse>;
 
     #[inline]
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.get_mut().stream)
-            .poll_next(cx)
-            .map(|t| match t {
-                Some(Ok(resp)) => Some(Ok(LeaseKeepAliveResponse::new(resp))),
-                Some(Err(e)) => Some(Err(From::from(e))),
-                None => None,
-            })
+        let this = self.get_mut();
+        Pin::new(&mut this.stream).poll_next(cx).map(|t| match t {
+            Some(Ok(resp)) => {
+                this.call.keep_alive(resp.ttl > 0);
+                stream_event!(
+                    this.call,
+                    id = resp.id,
+                    ttl = resp.ttl,
+                    "keep alive response"
+                );
+                Some(Ok(LeaseKeepAliveResponse::new(resp)))
+            }
+            Some(Err(e)) => {
+                this.call.keep_alive(false);
+                Some(Err(Error::from(e).with_rpc("LeaseKeepAlive")))
+            }
+            None => None,
+        })
     }
 }
+
+impl_raw_conversions!(
+    LeaseGrantResponse(PbLeaseGrantResponse),
+    LeaseRevokeResponse(PbLeaseRevokeResponse),
+    LeaseKeepAliveResponse(PbLeaseKeepAliveResponse),
+    LeaseTimeToLiveResponse(PbLeaseTimeToLiveResponse),
+    LeaseLeasesResponse(PbLeaseLeasesResponse),
+    LeaseStatus(PbLeaseStatus),
+);
+
+impl_raw_conversions!(
+    options:
+    LeaseGrantOptions(PbLeaseGrantRequest, CallOptions),
+    LeaseTimeToLiveOptions(PbLeaseTimeToLiveRequest, CallOptions),
+);
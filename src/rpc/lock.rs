@@ -1,12 +1,19 @@
 //! Etcd Lock RPC.
 
 use super::pb::v3lockpb;
-use crate::auth::AuthService;
-use crate::error::Result;
+use crate::auth::{AuthService, AuthToken};
+use crate::compression::{Compressing, Compression};
+#[cfg(feature = "kv")]
+use crate::contender::Contender;
+#[cfg(feature = "kv")]
+use crate::error::Error;
+use crate::error::{Result, RpcResultExt};
+use crate::ids::{LeaseId, Revision};
 use crate::intercept::InterceptedChannel;
+use crate::observe::{observed, Observer};
+#[cfg(feature = "kv")]
+use crate::rpc::kv::{Compare, CompareOp, KvClient, Txn, TxnOp};
 use crate::rpc::ResponseHeader;
-use http::HeaderValue;
-use std::sync::{Arc, RwLock};
 use tonic::{IntoRequest, Request};
 use v3lockpb::lock_client::LockClient as PbLockClient;
 use v3lockpb::{
@@ -15,21 +22,50 @@ use v3lockpb::{
 };
 
 /// Client for Lock operations.
-#[repr(transparent)]
 #[derive(Clone)]
 pub struct LockClient {
-    inner: PbLockClient<AuthService<InterceptedChannel>>,
+    inner: Compressing<PbLockClient<AuthService<InterceptedChannel>>>,
+    /// The client listing the contenders of the locks.
+    #[cfg(feature = "kv")]
+    kv: KvClient,
+    observer: Observer,
 }
 
 impl LockClient {
     /// Creates a lock client.
     #[inline]
-    pub(crate) fn new(
-        channel: InterceptedChannel,
-        auth_token: Arc<RwLock<Option<HeaderValue>>>,
-    ) -> Self {
-        let inner = PbLockClient::new(AuthService::new(channel, auth_token));
-        Self { inner }
+    pub(crate) fn new(channel: InterceptedChannel, auth_token: AuthToken) -> Self {
+        #[cfg(feature = "kv")]
+        let kv = KvClient::new(channel.clone(), auth_token.clone());
+        let inner = Compressing::new(PbLockClient::new(AuthService::new(channel, auth_token)));
+        Self {
+            inner,
+            #[cfg(feature = "kv")]
+            kv,
+            observer: Observer::default(),
+        }
+    }
+
+    /// Observes requests with `observer`.
+    #[inline]
+    pub(crate) fn with_observer(mut self, observer: Observer) -> Self {
+        #[cfg(feature = "kv")]
+        {
+            self.kv = self.kv.with_observer(observer.clone());
+        }
+        self.observer = observer;
+        self
+    }
+
+    /// Compresses the messages according to `compression`.
+    #[inline]
+    pub(crate) fn with_compression(mut self, compression: &Compression) -> Self {
+        #[cfg(feature = "kv")]
+        {
+            self.kv = self.kv.with_compression(compression);
+        }
+        self.inner = self.inner.with_compression(compression);
+        self
     }
 
     /// Acquires a distributed shared lock on a given named lock.
@@ -44,11 +80,14 @@ impl LockClient {
         name: impl Into<Vec<u8>>,
         options: Option<LockOptions>,
     ) -> Result<LockResponse> {
-        let resp = self
-            .inner
-            .lock(options.unwrap_or_default().with_name(name))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "Lock",
+            self.inner.lock(options.unwrap_or_default().with_name(name))
+        )
+        .await
+        .for_rpc("Lock")?
+        .into_inner();
         Ok(LockResponse::new(resp))
     }
 
@@ -57,17 +96,131 @@ impl LockClient {
     /// ownership of the lock.
     #[inline]
     pub async fn unlock(&mut self, key: impl Into<Vec<u8>>) -> Result<UnlockResponse> {
-        let resp = self
-            .inner
-            .unlock(UnlockOptions::new().with_key(key))
-            .await?
-            .into_inner();
+        let resp = observed!(
+            self.observer,
+            "Unlock",
+            self.inner.unlock(UnlockOptions::new().with_key(key))
+        )
+        .await
+        .for_rpc("Unlock")?
+        .into_inner();
         Ok(UnlockResponse::new(resp))
     }
+
+    /// Acquires a lock like [`LockClient::lock`] and wraps it in a [`LockGuard`],
+    /// which releases the lock once dropped.
+    #[inline]
+    pub async fn lock_guarded(
+        &mut self,
+        name: impl Into<Vec<u8>>,
+        options: Option<LockOptions>,
+    ) -> Result<LockGuard> {
+        let resp = self.lock(name, options).await?;
+        Ok(LockGuard {
+            client: self.clone(),
+            resp: Some(resp),
+        })
+    }
+
+    /// The holder of the lock `name`, the contender whose key was created first, none if
+    /// the lock is free.
+    ///
+    /// Locks are exclusive, there is at most one holder: the contenders locking with the
+    /// lease of the holder share its key.
+    #[cfg(feature = "kv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+    pub async fn holders(&mut self, name: impl Into<Vec<u8>>) -> Result<Vec<Contender>> {
+        let mut contenders = self.kv.contenders(name.into()).await?;
+        contenders.truncate(1);
+        Ok(contenders)
+    }
+
+    /// The contenders waiting for the lock `name` behind its holder, in the order they
+    /// will hold it.
+    #[cfg(feature = "kv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+    pub async fn waiters(&mut self, name: impl Into<Vec<u8>>) -> Result<Vec<Contender>> {
+        let mut contenders = self.kv.contenders(name.into()).await?;
+        if !contenders.is_empty() {
+            contenders.remove(0);
+        }
+        Ok(contenders)
+    }
+
+    /// Breaks the lock `name` by deleting the key of its holder, and returns the evicted
+    /// holder, or `None` if the lock was free.
+    ///
+    /// An escape hatch for a lock whose holder is stuck while its lease is kept alive. The
+    /// next waiter, if any, holds the lock once the key is deleted, while the evicted holder
+    /// is not told and may still act as if it held it: the resources guarded by the lock
+    /// should check its [`fencing_token`](LockResponse::fencing_token).
+    ///
+    /// Fails with [`Error::InvalidArgs`] unless forced by [`BreakOptions::with_force`].
+    #[cfg(feature = "kv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+    pub async fn break_lock(
+        &mut self,
+        name: impl Into<Vec<u8>>,
+        options: BreakOptions,
+    ) -> Result<Option<Contender>> {
+        if !options.force {
+            return Err(Error::InvalidArgs(
+                "breaking a lock must be forced by BreakOptions::with_force".to_string(),
+            ));
+        }
+        let name = name.into();
+        loop {
+            let contenders = self.kv.contenders(name.clone()).await?;
+            let Some(holder) = contenders.into_iter().next() else {
+                return Ok(None);
+            };
+            // The key is only deleted if it is still the one of the holder listed, the
+            // holder may have released the lock, and a new one taken it, meanwhile.
+            let txn = Txn::new()
+                .when([Compare::create_revision(
+                    holder.key(),
+                    CompareOp::Equal,
+                    holder.create_revision(),
+                )])
+                .and_then([TxnOp::delete(holder.key(), None)]);
+            if self.kv.txn(txn).await?.succeeded() {
+                return Ok(Some(holder));
+            }
+        }
+    }
+}
+
+/// Options for [`LockClient::break_lock`].
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BreakOptions {
+    force: bool,
+}
+
+#[cfg(feature = "kv")]
+impl BreakOptions {
+    /// Creates a `BreakOptions`, which does not break the lock unless forced.
+    #[inline]
+    pub const fn new() -> Self {
+        Self { force: false }
+    }
+
+    /// Breaks the lock, acknowledging that its holder is evicted without being told.
+    #[inline]
+    pub const fn with_force(mut self) -> Self {
+        self.force = true;
+        self
+    }
 }
 
 /// Options for `Lock` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LockOptions(PbLockRequest);
 
@@ -94,8 +247,8 @@ impl LockOptions {
     /// be treated as a single acquisition; locking twice with the same lease is a
     /// no-op.
     #[inline]
-    pub const fn with_lease(mut self, lease: i64) -> Self {
-        self.0.lease = lease;
+    pub fn with_lease(mut self, lease: impl Into<LeaseId>) -> Self {
+        self.0.lease = lease.into().get();
         self
     }
 }
@@ -117,6 +270,11 @@ impl IntoRequest<PbLockRequest> for LockOptions {
 /// Response for `Lock` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct LockResponse(PbLockResponse);
 
@@ -146,10 +304,78 @@ impl LockResponse {
     pub fn key(&self) -> &[u8] {
         &self.0.key
     }
+
+    /// A fencing token of the lock ownership, the revision of the response, which is the
+    /// revision the lock key was created at when the lock is acquired without waiting.
+    ///
+    /// It is monotonic across successive holders of the same lock, so a resource guarded
+    /// by the lock can reject requests carrying a token lower than one it has seen.
+    #[inline]
+    pub fn fencing_token(&self) -> Revision {
+        Revision::new(self.0.header.as_ref().map_or(0, |header| header.revision))
+    }
+}
+
+/// A held lock acquired by [`LockClient::lock_guarded`].
+///
+/// Dropping the guard releases the lock on a best-effort basis, use [`LockGuard::unlock`]
+/// to observe the result.
+pub struct LockGuard {
+    client: LockClient,
+    resp: Option<LockResponse>,
+}
+
+impl LockGuard {
+    /// The response of the `Lock` operation.
+    #[inline]
+    pub fn response(&self) -> &LockResponse {
+        // The response is only taken when the guard is consumed.
+        self.resp.as_ref().unwrap()
+    }
+
+    /// The key that exists on etcd for as long as the lock is held.
+    #[inline]
+    pub fn key(&self) -> &[u8] {
+        self.response().key()
+    }
+
+    /// A fencing token of the lock ownership, see [`LockResponse::fencing_token`].
+    #[inline]
+    pub fn fencing_token(&self) -> Revision {
+        self.response().fencing_token()
+    }
+
+    /// Releases the lock.
+    #[inline]
+    pub async fn unlock(mut self) -> Result<UnlockResponse> {
+        let resp = self.resp.take().unwrap();
+        self.client.unlock(resp.key()).await
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let Some(resp) = self.resp.take() else {
+            return;
+        };
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let mut client = self.client.clone();
+        handle.spawn(async move {
+            let _ = client.unlock(resp.key()).await;
+        });
+    }
 }
 
 /// Options for `Unlock` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UnlockOptions(PbUnlockRequest);
 
@@ -185,6 +411,11 @@ impl IntoRequest<PbUnlockRequest> for UnlockOptions {
 /// Response for `Unlock` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct UnlockResponse(PbUnlockResponse);
 
@@ -207,3 +438,14 @@ impl UnlockResponse {
         self.0.header.take().map(ResponseHeader::new)
     }
 }
+
+impl_raw_conversions!(
+    LockResponse(PbLockResponse),
+    UnlockResponse(PbUnlockResponse),
+);
+
+impl_raw_conversions!(
+    options:
+    LockOptions(PbLockRequest),
+    UnlockOptions(PbUnlockRequest),
+);
@@ -4,12 +4,23 @@ pub use crate::rpc::pb::etcdserverpb::alarm_request::AlarmAction;
 pub use crate::rpc::pb::etcdserverpb::AlarmType;
 
 use super::pb::etcdserverpb;
-use crate::auth::AuthService;
-use crate::error::Result;
+use crate::auth::{AuthService, AuthToken};
+use crate::client::Connector;
+use crate::compression::{Compressing, Compression};
+use crate::deadline::CallOptions;
+use crate::error::{Error, Result, RpcResultExt};
+use crate::ids::Revision;
 use crate::intercept::InterceptedChannel;
+use crate::logging::log_event;
+use crate::observe::{observe_call, observed, Call, Observer};
+use crate::retry::{retry, RetryPolicy, DEFAULT_READ_RETRIES};
+use crate::route::{RouteTo, Router};
+use crate::rpc::cluster::{ClusterClient, Member};
+use crate::rpc::kv::{CompactionOptions, KvClient};
 use crate::rpc::pb::etcdserverpb::{
     AlarmRequest as PbAlarmRequest, AlarmResponse as PbAlarmResponse,
     DefragmentRequest as PbDefragmentRequest, DefragmentResponse as PbDefragmentResponse,
+    DowngradeRequest as PbDowngradeRequest, DowngradeResponse as PbDowngradeResponse,
     HashKvRequest as PbHashKvRequest, HashKvResponse as PbHashKvResponse,
     HashRequest as PbHashRequest, HashResponse as PbHashResponse,
     MoveLeaderRequest as PbMoveLeaderRequest, MoveLeaderResponse as PbMoveLeaderResponse,
@@ -17,22 +28,51 @@ use crate::rpc::pb::etcdserverpb::{
     StatusRequest as PbStatusRequest, StatusResponse as PbStatusResponse,
 };
 use crate::rpc::ResponseHeader;
+use crate::trace::{self, stream_event, BULK_LANE};
+use etcdserverpb::downgrade_request::DowngradeAction;
 use etcdserverpb::maintenance_client::MaintenanceClient as PbMaintenanceClient;
 use etcdserverpb::AlarmMember as PbAlarmMember;
-use http::HeaderValue;
-use std::sync::{Arc, RwLock};
+use http::Uri;
+use prost::bytes::{Buf, Bytes};
+use sha2::{Digest, Sha256};
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio_stream::Stream;
 use tonic::codec::Streaming as PbStreaming;
 use tonic::{IntoRequest, Request};
 
 /// Client for maintenance operations.
-#[repr(transparent)]
 #[derive(Clone)]
 pub struct MaintenanceClient {
-    inner: PbMaintenanceClient<AuthService<InterceptedChannel>>,
+    inner: Compressing<PbMaintenanceClient<AuthService<InterceptedChannel>>>,
+    /// The client of the bulk lane, if any, sending the snapshots and defragmentations.
+    bulk: Option<Compressing<PbMaintenanceClient<AuthService<InterceptedChannel>>>>,
+    kv: KvClient,
+    cluster: ClusterClient,
+    pub(crate) connector: Option<Connector>,
+    /// The router of the clients sending their requests to the leader or a given member.
+    router: Option<Arc<Router>>,
+    retry: Option<RetryPolicy>,
+    read_retries: u32,
+    default_deadline: Option<Duration>,
+    create_timeout: Option<Duration>,
+    observer: Observer,
 }
 
 /// Options for `alarm` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct AlarmOptions(PbAlarmRequest);
 
@@ -181,21 +221,78 @@ impl IntoRequest<PbHashKvRequest> for HashKvOptions {
     }
 }
 
+/// Callback invoked with the number of snapshot bytes received so far.
+type OnChunk = Arc<dyn Fn(u64) + Send + Sync>;
+
 /// Options for `snapshot` operation.
-#[derive(Debug, Default, Clone)]
-struct SnapshotOptions(PbSnapshotRequest);
+#[derive(Clone)]
+pub struct SnapshotOptions {
+    req: PbSnapshotRequest,
+    on_chunk: Option<OnChunk>,
+    max_retries: u32,
+    verify: bool,
+}
 
 impl SnapshotOptions {
+    /// Creates a new `SnapshotOptions`.
     #[inline]
-    const fn new() -> Self {
-        Self(PbSnapshotRequest {})
+    pub const fn new() -> Self {
+        Self {
+            req: PbSnapshotRequest {},
+            on_chunk: None,
+            max_retries: 0,
+            verify: true,
+        }
+    }
+
+    /// Sets a progress callback of [`MaintenanceClient::snapshot_to`], called after each
+    /// received chunk with the total number of bytes received so far.
+    ///
+    /// When the download is retried, the count starts over for each attempt.
+    #[inline]
+    pub fn with_on_chunk(mut self, on_chunk: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.on_chunk = Some(Arc::new(on_chunk));
+        self
+    }
+
+    /// Sets how many times [`MaintenanceClient::snapshot_to_file`] restarts a broken
+    /// snapshot stream, 0 by default.
+    #[inline]
+    pub const fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Whether to verify the trailing sha256 checksum of the snapshot, enabled by default.
+    #[inline]
+    pub const fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+}
+
+impl Default for SnapshotOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for SnapshotOptions {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SnapshotOptions")
+            .field("on_chunk", &self.on_chunk.is_some())
+            .field("max_retries", &self.max_retries)
+            .field("verify", &self.verify)
+            .finish()
     }
 }
 
 impl From<SnapshotOptions> for PbSnapshotRequest {
     #[inline]
     fn from(snapshot: SnapshotOptions) -> Self {
-        snapshot.0
+        snapshot.req
     }
 }
 
@@ -209,12 +306,17 @@ impl IntoRequest<PbSnapshotRequest> for SnapshotOptions {
 /// Response for `alarm` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct AlarmResponse(PbAlarmResponse);
 
 /// Alarm member of respond.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AlarmMember {
     /// memberID is the ID of the member associated with the raised alarm.
     member_id: u64,
@@ -265,6 +367,11 @@ impl AlarmResponse {
 /// Response for `status` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct StatusResponse(PbStatusResponse);
 
@@ -335,6 +442,12 @@ impl StatusResponse {
         self.0.db_size_in_use
     }
 
+    /// Get the size of the backend database logically in use, in bytes.
+    #[inline]
+    pub fn db_size_in_use(&self) -> i64 {
+        self.0.db_size_in_use
+    }
+
     /// Indicate if the member is raft learner.
     #[inline]
     pub fn is_learner(&self) -> bool {
@@ -345,6 +458,11 @@ impl StatusResponse {
 /// Response for `defragment` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct DefragmentResponse(PbDefragmentResponse);
 
@@ -371,6 +489,11 @@ impl DefragmentResponse {
 /// Response for `hash` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct HashResponse(PbHashResponse);
 
@@ -403,6 +526,11 @@ impl HashResponse {
 /// Response for `hash_kv` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct HashKvResponse(PbHashKvResponse);
 
@@ -436,11 +564,22 @@ impl HashKvResponse {
     pub fn compact_version(&self) -> i64 {
         self.0.compact_revision
     }
+
+    /// Gets compacted revision of key-value store when hash begins.
+    #[inline]
+    pub fn compact_revision(&self) -> Revision {
+        Revision::new(self.0.compact_revision)
+    }
 }
 
 /// Response for `snapshot` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct SnapshotResponse(PbSnapshotResponse);
 
@@ -474,28 +613,638 @@ impl SnapshotResponse {
     pub fn blob(&self) -> &[u8] {
         &self.0.blob
     }
+
+    /// Takes the chunk out of the response, leaving an empty one in its place.
+    ///
+    /// The chunk shares the buffer it was received in, no bytes are copied.
+    #[inline]
+    pub fn take_blob(&mut self) -> Bytes {
+        std::mem::take(&mut self.0.blob)
+    }
 }
 
 /// Response for `snapshot` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug)]
-#[repr(transparent)]
-pub struct SnapshotStreaming(PbStreaming<PbSnapshotResponse>);
+pub struct SnapshotStreaming(PbStreaming<PbSnapshotResponse>, Call);
 
 impl SnapshotStreaming {
     /// Fetches the next message from this stream.
     #[inline]
     pub async fn message(&mut self) -> Result<Option<SnapshotResponse>> {
-        let ret = self.0.message().await?;
+        let ret = self.0.message().await.for_rpc("Snapshot")?;
         match ret {
-            Some(rsp) => Ok(Some(SnapshotResponse::new(rsp))),
+            Some(rsp) => {
+                stream_event!(self.1, bytes = rsp.blob.len(), "snapshot chunk");
+                Ok(Some(SnapshotResponse::new(rsp)))
+            }
             None => Ok(None),
         }
     }
 }
 
+impl Stream for SnapshotStreaming {
+    type Item = Result<SnapshotResponse>;
+
+    #[inline]
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        Pin::new(&mut this.0).poll_next(cx).map(|t| match t {
+            Some(Ok(resp)) => {
+                stream_event!(this.1, bytes = resp.blob.len(), "snapshot chunk");
+                Some(Ok(SnapshotResponse::new(resp)))
+            }
+            Some(Err(e)) => Some(Err(Error::from(e).with_rpc("Snapshot"))),
+            None => None,
+        })
+    }
+}
+
+/// The length of the sha256 checksum etcd appends to a snapshot.
+const SNAPSHOT_CHECKSUM_LEN: usize = 32;
+
+/// Summary of a snapshot written by [`MaintenanceClient::snapshot_to`].
+#[derive(Debug, Clone)]
+pub struct SnapshotSummary {
+    bytes: u64,
+    sha256: [u8; SNAPSHOT_CHECKSUM_LEN],
+    revision: i64,
+    member_id: u64,
+}
+
+impl SnapshotSummary {
+    /// The number of bytes written, including the trailing checksum.
+    #[inline]
+    pub const fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// The verified sha256 checksum of the snapshot.
+    #[inline]
+    pub const fn sha256(&self) -> &[u8; SNAPSHOT_CHECKSUM_LEN] {
+        &self.sha256
+    }
+
+    /// The revision of the key-value store at the time of the snapshot.
+    #[inline]
+    pub const fn revision(&self) -> Revision {
+        Revision::new(self.revision)
+    }
+
+    /// The ID of the member the snapshot was taken from.
+    #[inline]
+    pub const fn member_id(&self) -> u64 {
+        self.member_id
+    }
+}
+
+/// Hashes a snapshot stream, holding back the trailing checksum.
+#[derive(Default)]
+struct SnapshotHasher {
+    hasher: Sha256,
+    trailer: Vec<u8>,
+}
+
+impl SnapshotHasher {
+    fn update(&mut self, blob: &[u8]) {
+        if blob.len() >= SNAPSHOT_CHECKSUM_LEN {
+            let (data, trailer) = blob.split_at(blob.len() - SNAPSHOT_CHECKSUM_LEN);
+            self.hasher.update(&self.trailer);
+            self.hasher.update(data);
+            self.trailer.clear();
+            self.trailer.extend_from_slice(trailer);
+        } else {
+            self.trailer.extend_from_slice(blob);
+            let excess = self.trailer.len().saturating_sub(SNAPSHOT_CHECKSUM_LEN);
+            self.hasher.update(&self.trailer[..excess]);
+            self.trailer.drain(..excess);
+        }
+    }
+
+    fn verify(self) -> Result<[u8; SNAPSHOT_CHECKSUM_LEN]> {
+        let actual: [u8; SNAPSHOT_CHECKSUM_LEN] = self.hasher.finalize().into();
+        if self.trailer != actual {
+            return Err(Error::SnapshotChecksumMismatch {
+                expected: self.trailer,
+                actual: actual.to_vec(),
+            });
+        }
+        Ok(actual)
+    }
+
+    /// Returns the checksum of the data, verifying it against the trailer if `verify` is set.
+    fn finish(self, verify: bool) -> Result<[u8; SNAPSHOT_CHECKSUM_LEN]> {
+        if verify {
+            return self.verify();
+        }
+        Ok(self.hasher.finalize().into())
+    }
+}
+
+/// State of a snapshot download kept across attempts.
+#[derive(Default)]
+struct SnapshotDownload {
+    /// The revision of the snapshot on disk.
+    revision: Option<i64>,
+    /// The number of bytes on disk which belong to the snapshot.
+    valid: u64,
+}
+
+/// Returns the first error if none of the members could be reached.
+fn check_reachable(statuses: &mut Vec<MemberResult<StatusResponse>>) -> Result<()> {
+    if statuses.is_empty() || statuses.iter().any(|result| result.result.is_ok()) {
+        return Ok(());
+    }
+    statuses.swap_remove(0).result.map(|_| ())
+}
+
+/// Returns the leader and the raft term reported by the member with the highest term.
+fn leader_of(statuses: &mut Vec<MemberResult<StatusResponse>>) -> Result<Option<(u64, u64)>> {
+    check_reachable(statuses)?;
+    Ok(statuses
+        .iter()
+        .filter_map(|result| result.result.as_ref().ok())
+        .filter(|status| status.leader() != 0)
+        .max_by_key(|status| status.raft_term())
+        .map(|status| (status.leader(), status.raft_term())))
+}
+
+/// The delay before restarting a broken snapshot stream.
+const SNAPSHOT_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Returns `true` if a snapshot stream failed with an error worth restarting it for.
+fn is_snapshot_retryable(err: &Error) -> bool {
+    match err {
+        Error::TransportError(_) => true,
+        _ => matches!(
+            err.as_status().map(tonic::Status::code),
+            Some(
+                tonic::Code::Unavailable
+                    | tonic::Code::Unknown
+                    | tonic::Code::Internal
+                    | tonic::Code::Cancelled
+                    | tonic::Code::DeadlineExceeded
+                    | tonic::Code::Aborted
+            )
+        ),
+    }
+}
+
+/// The timeout of querying the status of a single member for `cluster_health` operation.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default timeout of defragmenting a single member.
+pub const DEFAULT_DEFRAG_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Options for `defragment_all` operation.
+#[derive(Debug, Clone)]
+pub struct DefragOptions {
+    timeout: Duration,
+    skip_leader: bool,
+    leader_last: bool,
+}
+
+impl DefragOptions {
+    /// Creates a new `DefragOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            timeout: DEFAULT_DEFRAG_TIMEOUT,
+            skip_leader: false,
+            leader_last: false,
+        }
+    }
+
+    /// Sets the timeout of defragmenting a single member.
+    #[inline]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Does not defragment the current leader.
+    #[inline]
+    pub const fn with_skip_leader(mut self) -> Self {
+        self.skip_leader = true;
+        self
+    }
+
+    /// Defragments the current leader after all the other members,
+    /// to minimize disruption.
+    #[inline]
+    pub const fn with_leader_last(mut self) -> Self {
+        self.leader_last = true;
+        self
+    }
+}
+
+impl Default for DefragOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Result of an operation on a single member in member-wise operations.
+#[derive(Debug)]
+pub struct MemberResult<T> {
+    member: Member,
+    endpoint: Option<String>,
+    result: Result<T>,
+}
+
+impl<T> MemberResult<T> {
+    /// The member the operation was run on.
+    #[inline]
+    pub fn member(&self) -> &Member {
+        &self.member
+    }
+
+    /// The client URL the member was connected through.
+    #[inline]
+    pub fn endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref()
+    }
+
+    /// The result of the operation on the member.
+    #[inline]
+    pub fn result(&self) -> &Result<T> {
+        &self.result
+    }
+
+    /// Takes the result of the operation on the member.
+    #[inline]
+    pub fn into_result(self) -> Result<T> {
+        self.result
+    }
+}
+
+/// Result of defragmenting a single member in `defragment_all` operation.
+pub type MemberDefragmentResult = MemberResult<DefragmentResponse>;
+
+/// Result of hashing a single member in `hash_kv_all` operation.
+pub type MemberHashKvResult = MemberResult<HashKvResponse>;
+
+/// Report of `hash_kv_all` operation, comparing the hashes of all members at a revision.
+#[derive(Debug)]
+pub struct ConsistencyReport {
+    revision: Revision,
+    members: Vec<MemberHashKvResult>,
+}
+
+impl ConsistencyReport {
+    /// The revision the members were hashed at.
+    #[inline]
+    pub const fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    /// The results of all members.
+    #[inline]
+    pub fn members(&self) -> &[MemberHashKvResult] {
+        &self.members
+    }
+
+    /// The hash and compact revision reported by most of the members.
+    pub fn majority(&self) -> Option<(u32, Revision)> {
+        let mut counts: Vec<((u32, Revision), usize)> = Vec::new();
+        for resp in self.members.iter().filter_map(|m| m.result.as_ref().ok()) {
+            let key = (resp.hash(), resp.compact_revision());
+            match counts.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((key, 1)),
+            }
+        }
+        // The first one wins on ties, so the result only depends on the member order.
+        counts
+            .into_iter()
+            .fold(
+                None,
+                |max: Option<((u32, Revision), usize)>, entry| match max {
+                    Some(max) if max.1 >= entry.1 => Some(max),
+                    _ => Some(entry),
+                },
+            )
+            .map(|(key, _)| key)
+    }
+
+    /// The members whose hash differs from the majority at the same compact revision.
+    ///
+    /// Hashes of members with different compact revisions cover different ranges of
+    /// revisions and are not comparable.
+    pub fn mismatched(&self) -> Vec<&MemberHashKvResult> {
+        let Some((hash, compact_revision)) = self.majority() else {
+            return Vec::new();
+        };
+        self.members
+            .iter()
+            .filter(|m| match &m.result {
+                Ok(resp) => resp.compact_revision() == compact_revision && resp.hash() != hash,
+                Err(_) => false,
+            })
+            .collect()
+    }
+
+    /// The members which failed to be hashed.
+    #[inline]
+    pub fn failed(&self) -> Vec<&MemberHashKvResult> {
+        self.members.iter().filter(|m| m.result.is_err()).collect()
+    }
+
+    /// Returns `true` if no mismatch was detected.
+    #[inline]
+    pub fn is_consistent(&self) -> bool {
+        self.mismatched().is_empty()
+    }
+}
+
+/// Options for `verify_snapshot` operation.
+#[derive(Debug, Default, Clone)]
+pub struct VerifySnapshotOptions {
+    all_members: bool,
+}
+
+impl VerifySnapshotOptions {
+    /// Creates a new `VerifySnapshotOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self { all_members: false }
+    }
+
+    /// Also hashes all the other members at the revision of the snapshot, and compares
+    /// their hashes with the one of the member the snapshot was taken from.
+    #[inline]
+    pub const fn with_all_members(mut self) -> Self {
+        self.all_members = true;
+        self
+    }
+}
+
+/// Outcome of hashing a member at the revision of a snapshot in `verify_snapshot` operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotHashCheck {
+    /// The member hashed the revision, and the same as the member the snapshot was taken from.
+    Match,
+    /// The member hashed the revision differently from the member the snapshot was taken from.
+    Mismatch,
+    /// The hashes cover different ranges of revisions, as the members were compacted at
+    /// different revisions, or the member the snapshot was taken from failed.
+    Incomparable,
+    /// The revision has been compacted on the member, it can no longer be verified.
+    Compacted,
+    /// The member failed to be hashed.
+    Failed,
+}
+
+/// Report of `verify_snapshot` operation, hashing the members at the revision of a snapshot.
+#[derive(Debug)]
+pub struct SnapshotVerification {
+    revision: Revision,
+    source: MemberHashKvResult,
+    members: Vec<MemberHashKvResult>,
+}
+
+impl SnapshotVerification {
+    /// The revision of the snapshot the members were hashed at.
+    #[inline]
+    pub const fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    /// The result of the member the snapshot was taken from.
+    #[inline]
+    pub fn source(&self) -> &MemberHashKvResult {
+        &self.source
+    }
+
+    /// The results of the other members, empty unless
+    /// [`VerifySnapshotOptions::with_all_members`] is set.
+    #[inline]
+    pub fn members(&self) -> &[MemberHashKvResult] {
+        &self.members
+    }
+
+    /// Checks the result of `member`, either the source or one of the other members.
+    ///
+    /// The source matches as long as it can still hash the revision of the snapshot.
+    pub fn check(&self, member: &MemberHashKvResult) -> SnapshotHashCheck {
+        let resp = match &member.result {
+            Ok(resp) => resp,
+            Err(e) if e.is_compacted() => return SnapshotHashCheck::Compacted,
+            Err(_) => return SnapshotHashCheck::Failed,
+        };
+        match &self.source.result {
+            Ok(source) if source.compact_revision() != resp.compact_revision() => {
+                SnapshotHashCheck::Incomparable
+            }
+            Ok(source) if source.hash() != resp.hash() => SnapshotHashCheck::Mismatch,
+            Ok(_) => SnapshotHashCheck::Match,
+            Err(_) => SnapshotHashCheck::Incomparable,
+        }
+    }
+
+    /// Returns `true` if the revision of the snapshot has been compacted on its source.
+    #[inline]
+    pub fn is_compacted(&self) -> bool {
+        self.check(&self.source) == SnapshotHashCheck::Compacted
+    }
+
+    /// The other members whose hash differs from the one of the source.
+    pub fn mismatched(&self) -> Vec<&MemberHashKvResult> {
+        self.members
+            .iter()
+            .filter(|m| self.check(m) == SnapshotHashCheck::Mismatch)
+            .collect()
+    }
+
+    /// Returns `true` if the source hashed the revision of the snapshot, and every other
+    /// member hashed matches it.
+    #[inline]
+    pub fn is_verified(&self) -> bool {
+        self.check(&self.source) == SnapshotHashCheck::Match
+            && self
+                .members
+                .iter()
+                .all(|m| self.check(m) == SnapshotHashCheck::Match)
+    }
+}
+
+/// Health of a single member in `cluster_health` operation.
+#[derive(Debug)]
+pub struct MemberHealth {
+    member: Member,
+    endpoint: Option<String>,
+    status: Result<StatusResponse>,
+    alarms: Vec<AlarmType>,
+}
+
+impl MemberHealth {
+    /// The member.
+    #[inline]
+    pub fn member(&self) -> &Member {
+        &self.member
+    }
+
+    /// The endpoint the member was queried through, if it has any client URLs.
+    #[inline]
+    pub fn endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref()
+    }
+
+    /// The status of the member, or the error of reaching it.
+    #[inline]
+    pub fn status(&self) -> &Result<StatusResponse> {
+        &self.status
+    }
+
+    /// Returns `true` if the status of the member was retrieved.
+    #[inline]
+    pub fn is_reachable(&self) -> bool {
+        self.status.is_ok()
+    }
+
+    /// The leader the member knows of, `None` if unreachable or without a leader.
+    #[inline]
+    pub fn leader(&self) -> Option<u64> {
+        self.status
+            .as_ref()
+            .ok()
+            .map(StatusResponse::leader)
+            .filter(|leader| *leader != 0)
+    }
+
+    /// Returns `true` if the member is the leader.
+    #[inline]
+    pub fn is_leader(&self) -> bool {
+        self.leader() == Some(self.member.id())
+    }
+
+    /// The size of the backend database of the member in bytes, `None` if unreachable.
+    #[inline]
+    pub fn db_size(&self) -> Option<i64> {
+        self.status.as_ref().ok().map(StatusResponse::db_size)
+    }
+
+    /// The raft term of the member, `None` if unreachable.
+    #[inline]
+    pub fn raft_term(&self) -> Option<u64> {
+        self.status.as_ref().ok().map(StatusResponse::raft_term)
+    }
+
+    /// Returns `true` if the member is a raft learner.
+    #[inline]
+    pub const fn is_learner(&self) -> bool {
+        self.member.is_learner()
+    }
+
+    /// The alarms raised on the member.
+    #[inline]
+    pub fn alarms(&self) -> &[AlarmType] {
+        &self.alarms
+    }
+
+    /// Returns `true` if the member is reachable, knows a leader, and reports neither
+    /// alarms nor errors.
+    pub fn is_healthy(&self) -> bool {
+        match &self.status {
+            Ok(status) => {
+                status.leader() != 0 && status.errors().is_empty() && self.alarms.is_empty()
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Report of `cluster_health` operation.
+#[derive(Debug)]
+pub struct ClusterHealth {
+    members: Vec<MemberHealth>,
+}
+
+impl ClusterHealth {
+    /// Creates a `ClusterHealth` from the status of every member and the raised alarms.
+    fn new(statuses: Vec<MemberResult<StatusResponse>>, alarms: &[AlarmMember]) -> Self {
+        let members = statuses
+            .into_iter()
+            .map(|result| {
+                let alarms = alarms
+                    .iter()
+                    .filter(|alarm| alarm.member_id() == result.member.id())
+                    .map(AlarmMember::alarm)
+                    .filter(|alarm| *alarm != AlarmType::None)
+                    .collect();
+                MemberHealth {
+                    member: result.member,
+                    endpoint: result.endpoint,
+                    status: result.result,
+                    alarms,
+                }
+            })
+            .collect();
+        Self { members }
+    }
+
+    /// The health of all members.
+    #[inline]
+    pub fn members(&self) -> &[MemberHealth] {
+        &self.members
+    }
+
+    /// The leader known by the reachable member with the highest raft term.
+    pub fn leader(&self) -> Option<u64> {
+        self.members
+            .iter()
+            .filter(|m| m.leader().is_some())
+            .max_by_key(|m| m.raft_term())
+            .and_then(MemberHealth::leader)
+    }
+
+    /// Returns `true` if any reachable member knows a leader.
+    #[inline]
+    pub fn has_leader(&self) -> bool {
+        self.leader().is_some()
+    }
+
+    /// Returns `true` if a majority of the voting members is reachable and knows a leader.
+    pub fn quorum_available(&self) -> bool {
+        let voting = self.members.iter().filter(|m| !m.is_learner());
+        let (total, available) = voting.fold((0, 0), |(total, available), m| {
+            (total + 1, available + m.leader().is_some() as usize)
+        });
+        total > 0 && available > total / 2
+    }
+
+    /// The difference between the highest and the lowest raft term of the reachable members.
+    ///
+    /// A skew usually means some members are partitioned from the leader.
+    pub fn term_skew(&self) -> u64 {
+        let terms = self.members.iter().filter_map(MemberHealth::raft_term);
+        let (min, max) = terms.fold((u64::MAX, 0), |(min, max), term| {
+            (min.min(term), max.max(term))
+        });
+        max.saturating_sub(min)
+    }
+
+    /// The number of members in the cluster.
+    #[inline]
+    pub fn members_total(&self) -> usize {
+        self.members.len()
+    }
+
+    /// The number of healthy members, see [`MemberHealth::is_healthy`].
+    #[inline]
+    pub fn members_healthy(&self) -> usize {
+        self.members.iter().filter(|m| m.is_healthy()).count()
+    }
+}
+
 /// Options for `MoveLeader` operation.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct MoveLeaderOptions(PbMoveLeaderRequest);
 
@@ -531,6 +1280,11 @@ impl IntoRequest<PbMoveLeaderRequest> for MoveLeaderOptions {
 /// Response for `MoveLeader` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct MoveLeaderResponse(PbMoveLeaderResponse);
 
@@ -553,15 +1307,215 @@ impl MoveLeaderResponse {
     }
 }
 
+/// Options for `Downgrade` operation.
+#[derive(Debug, Default, Clone)]
+#[repr(transparent)]
+struct DowngradeOptions(PbDowngradeRequest);
+
+impl DowngradeOptions {
+    #[inline]
+    fn new(action: DowngradeAction, version: impl Into<String>) -> Self {
+        Self(PbDowngradeRequest {
+            action: action as i32,
+            version: version.into(),
+        })
+    }
+}
+
+impl From<DowngradeOptions> for PbDowngradeRequest {
+    #[inline]
+    fn from(options: DowngradeOptions) -> Self {
+        options.0
+    }
+}
+
+impl IntoRequest<PbDowngradeRequest> for DowngradeOptions {
+    #[inline]
+    fn into_request(self) -> Request<PbDowngradeRequest> {
+        Request::new(self.into())
+    }
+}
+
+/// Response for `Downgrade` operation.
+#[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+#[repr(transparent)]
+pub struct DowngradeResponse(PbDowngradeResponse);
+
+impl DowngradeResponse {
+    #[inline]
+    const fn new(resp: PbDowngradeResponse) -> Self {
+        Self(resp)
+    }
+
+    /// Get response header.
+    #[inline]
+    pub fn header(&self) -> Option<&ResponseHeader> {
+        self.0.header.as_ref().map(From::from)
+    }
+
+    /// Takes the header out of the response, leaving a [`None`] in its place.
+    #[inline]
+    pub fn take_header(&mut self) -> Option<ResponseHeader> {
+        self.0.header.take().map(ResponseHeader::new)
+    }
+
+    /// The current cluster version.
+    #[inline]
+    pub fn version(&self) -> &str {
+        &self.0.version
+    }
+}
+
 impl MaintenanceClient {
     /// Creates a maintenance client.
     #[inline]
-    pub(crate) fn new(
+    pub(crate) fn new(channel: InterceptedChannel, auth_token: AuthToken) -> Self {
+        let kv = KvClient::new(channel.clone(), auth_token.clone());
+        let cluster = ClusterClient::new(channel.clone(), auth_token.clone());
+        let inner = Compressing::new(PbMaintenanceClient::new(AuthService::new(
+            channel, auth_token,
+        )));
+        Self {
+            inner,
+            bulk: None,
+            kv,
+            cluster,
+            connector: None,
+            router: None,
+            retry: None,
+            read_retries: DEFAULT_READ_RETRIES,
+            default_deadline: None,
+            create_timeout: None,
+            observer: Observer::default(),
+        }
+    }
+
+    /// Retries requests failing with transient errors according to `policy`.
+    #[inline]
+    pub(crate) fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.kv = self.kv.with_retry(policy.clone());
+        self.cluster = self.cluster.with_retry(policy.clone());
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Retries read-only requests failing because the leader changed or the request timed
+    /// out up to `read_retries` times.
+    #[inline]
+    pub(crate) fn with_read_retries(mut self, read_retries: u32) -> Self {
+        self.kv = self.kv.with_read_retries(read_retries);
+        self.cluster = self.cluster.with_read_retries(read_retries);
+        self.read_retries = read_retries;
+        self
+    }
+
+    /// Aborts requests which have no deadline of their own after `deadline`.
+    #[inline]
+    pub(crate) fn with_default_deadline(mut self, deadline: Duration) -> Self {
+        self.kv = self.kv.with_default_deadline(deadline);
+        self.cluster = self.cluster.with_default_deadline(deadline);
+        self.default_deadline = Some(deadline);
+        self
+    }
+
+    /// Aborts opening snapshot streams after `timeout`.
+    #[inline]
+    pub(crate) fn with_create_timeout(mut self, timeout: Duration) -> Self {
+        self.create_timeout = Some(timeout);
+        self
+    }
+
+    /// Observes requests with `observer`.
+    #[inline]
+    pub(crate) fn with_observer(mut self, observer: Observer) -> Self {
+        self.kv = self.kv.with_observer(observer.clone());
+        self.cluster = self.cluster.with_observer(observer.clone());
+        self.observer = observer;
+        self
+    }
+
+    /// Compresses the messages according to `compression`.
+    #[inline]
+    pub(crate) fn with_compression(mut self, compression: &Compression) -> Self {
+        self.inner = self.inner.with_compression(compression);
+        self.bulk = self.bulk.map(|bulk| bulk.with_compression(compression));
+        self
+    }
+
+    /// Sends the snapshots and defragmentations over `channel`, the balanced channel of the
+    /// bulk lane.
+    #[inline]
+    pub(crate) fn with_bulk_lane(
+        mut self,
         channel: InterceptedChannel,
-        auth_token: Arc<RwLock<Option<HeaderValue>>>,
+        auth_token: AuthToken,
     ) -> Self {
-        let inner = PbMaintenanceClient::new(AuthService::new(channel, auth_token));
-        Self { inner }
+        self.bulk = Some(Compressing::new(PbMaintenanceClient::new(
+            AuthService::new(channel, auth_token),
+        )));
+        self
+    }
+
+    /// The client of the bulk requests, with the lane it sends them over.
+    #[inline]
+    fn bulk_lane(
+        &self,
+    ) -> (
+        Compressing<PbMaintenanceClient<AuthService<InterceptedChannel>>>,
+        Option<&'static str>,
+    ) {
+        match &self.bulk {
+            Some(bulk) => (bulk.clone(), Some(BULK_LANE)),
+            None => (self.inner.clone(), None),
+        }
+    }
+
+    /// Allows the client to connect to single members, used by member-wise operations.
+    #[inline]
+    pub(crate) fn with_connector(mut self, connector: Connector) -> Self {
+        self.connector = Some(connector);
+        self
+    }
+
+    /// Routes the clients sending their requests to the leader or a given member with
+    /// `router`.
+    #[inline]
+    pub(crate) fn with_router(mut self, router: Arc<Router>) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    /// A client sending its requests to the member of `route`, e.g. to read the status of
+    /// the leader.
+    ///
+    /// The client sends its requests to any member if the member of `route` can not be
+    /// reached, unless
+    /// [`ConnectOptions::with_strict_routing`](crate::ConnectOptions::with_strict_routing)
+    /// is set, in which case this fails with an [`Error::RouteUnreachable`].
+    pub async fn routed(&self, route: RouteTo) -> Result<MaintenanceClient> {
+        let Some(router) = self.router.as_ref().filter(|_| route != RouteTo::Any) else {
+            return Ok(self.clone());
+        };
+        let channel = match router.channel(route).await {
+            Ok(channel) => channel,
+            Err(e) => {
+                router.unreachable(route, e)?;
+                return Ok(self.clone());
+            }
+        };
+        let inner =
+            PbMaintenanceClient::new(AuthService::new(channel, router.auth_token().clone()));
+        Ok(Self {
+            inner: Compressing::new(inner).with_compression(router.compression()),
+            bulk: None,
+            ..self.clone()
+        })
     }
 
     /// Get or active or inactive alarm.
@@ -572,76 +1526,1123 @@ impl MaintenanceClient {
         alarm_type: AlarmType,
         options: Option<AlarmOptions>,
     ) -> Result<AlarmResponse> {
-        let resp = self
-            .inner
-            .alarm(
+        let resp = observed!(
+            self.observer,
+            "Alarm",
+            self.inner.alarm(
                 options
                     .unwrap_or_default()
                     .with_action_and_type(alarm_action, alarm_type),
             )
-            .await?
-            .into_inner();
+        )
+        .await
+        .for_rpc("Alarm")?
+        .into_inner();
         Ok(AlarmResponse::new(resp))
     }
 
+    /// Lists the alarms raised on all members.
+    #[inline]
+    pub async fn alarm_list(&mut self) -> Result<AlarmResponse> {
+        self.alarm(AlarmAction::Get, AlarmType::None, None).await
+    }
+
+    /// Disarms an alarm raised on a member.
+    #[inline]
+    pub async fn alarm_disarm(
+        &mut self,
+        member_id: u64,
+        alarm_type: AlarmType,
+    ) -> Result<AlarmResponse> {
+        let mut options = AlarmOptions::new();
+        options.with_member(member_id);
+        self.alarm(AlarmAction::Deactivate, alarm_type, Some(options))
+            .await
+    }
+
+    /// Disarms all the alarms raised on all members, returning the disarmed alarms.
+    pub async fn alarm_disarm_all(&mut self) -> Result<Vec<AlarmMember>> {
+        let alarms = self.alarm_list().await?.alarms().to_vec();
+        for alarm in &alarms {
+            self.alarm_disarm(alarm.member_id(), alarm.alarm()).await?;
+        }
+        Ok(alarms)
+    }
+
+    /// Recovers the cluster from a `NOSPACE` alarm, returning the disarmed alarms.
+    ///
+    /// This compacts the key-value store at `revision`, or the current revision if `0`,
+    /// defragments every member with [`MaintenanceClient::defragment_all`] and then disarms
+    /// the `NOSPACE` alarms. The alarms are left untouched if any member fails to be
+    /// defragmented, as they would be raised again right away.
+    pub async fn recover_nospace(
+        &mut self,
+        revision: impl Into<Revision>,
+    ) -> Result<Vec<AlarmMember>> {
+        let mut revision = revision.into();
+        if revision.is_current() {
+            revision = self
+                .status()
+                .await?
+                .header()
+                .map(|header| header.revision())
+                .unwrap_or_default();
+        }
+
+        let options = CompactionOptions::new().with_physical();
+        match self.kv.compact(revision, Some(options)).await {
+            Err(e) if !e.is_compacted() => return Err(e),
+            _ => {}
+        }
+
+        for result in self.defragment_all(None).await? {
+            result.into_result()?;
+        }
+
+        let mut alarms = self.alarm_list().await?.alarms().to_vec();
+        alarms.retain(|alarm| alarm.alarm() == AlarmType::Nospace);
+        for alarm in &alarms {
+            self.alarm_disarm(alarm.member_id(), alarm.alarm()).await?;
+        }
+        Ok(alarms)
+    }
+
     /// Get status of a member.
     #[inline]
     pub async fn status(&mut self) -> Result<StatusResponse> {
-        let resp = self.inner.status(StatusOptions::new()).await?.into_inner();
+        let inner = self.inner.clone();
+        let observer = self.observer.clone();
+        let resp = CallOptions::new()
+            .run(
+                "Status",
+                self.default_deadline,
+                retry(
+                    self.retry.as_ref(),
+                    self.read_retries,
+                    "Status",
+                    true,
+                    move || {
+                        let mut inner = inner.clone();
+                        let observer = observer.clone();
+                        async move {
+                            Ok(
+                                observed!(observer, "Status", inner.status(StatusOptions::new()))
+                                    .await
+                                    .for_rpc("Status")?
+                                    .into_inner(),
+                            )
+                        }
+                    },
+                ),
+            )
+            .await?;
         Ok(StatusResponse::new(resp))
     }
 
+    /// Gets the status of every member of the cluster, through its first client URL.
+    ///
+    /// The status of each member is queried directly, so a member being down does not
+    /// prevent the status of the others from being returned. Members without client URLs,
+    /// e.g. ones that have not been started yet, are skipped.
+    pub async fn status_all(&mut self) -> Result<Vec<(Uri, Result<StatusResponse>)>> {
+        let members = self.cluster.member_list().await?.take_members();
+        let results = self.status_members(members, None).await?;
+        Ok(results
+            .into_iter()
+            .filter_map(|result| {
+                let uri = result.endpoint()?.parse().ok()?;
+                Some((uri, result.into_result()))
+            })
+            .collect())
+    }
+
+    /// Gets the status of the given members, each through its first client URL.
+    pub(crate) async fn status_members(
+        &self,
+        members: Vec<Member>,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<MemberResult<StatusResponse>>> {
+        let Some(connector) = self.connector.clone() else {
+            return Err(Error::EndpointsNotManaged);
+        };
+
+        let mut results = Vec::with_capacity(members.len());
+        for member in members {
+            let result = Self::on_member(&connector, member, |mut client, endpoint| async move {
+                let Some(timeout) = timeout else {
+                    return client.status().await;
+                };
+                match tokio::time::timeout(timeout, client.status()).await {
+                    Ok(resp) => resp,
+                    Err(_) => Err(Error::GRpcStatus(tonic::Status::deadline_exceeded(
+                        format!("status of {} timed out", endpoint),
+                    ))),
+                }
+            })
+            .await;
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    /// Gets the status of every member of the cluster, giving up on members which do not
+    /// answer in time.
+    async fn member_statuses(&mut self) -> Result<Vec<MemberResult<StatusResponse>>> {
+        let members = self.cluster.member_list().await?.take_members();
+        self.status_members(members, Some(HEALTH_CHECK_TIMEOUT))
+            .await
+    }
+
+    /// Finds the current leader from the status of every member, returning its ID, its
+    /// first client URL and the raft term, or `None` if no reachable member knows a leader.
+    ///
+    /// The leader reported by the member with the highest raft term wins, unreachable
+    /// members are ignored unless no member is reachable at all.
+    pub async fn cluster_leader(&mut self) -> Result<Option<(u64, Uri, u64)>> {
+        let mut statuses = self.member_statuses().await?;
+        let (leader, raft_term) = match leader_of(&mut statuses)? {
+            Some(leader) => leader,
+            None => return Ok(None),
+        };
+        let endpoint = statuses
+            .iter()
+            .find(|result| result.member.id() == leader)
+            .and_then(|result| result.member.client_urls().first());
+        let Some(endpoint) = endpoint else {
+            return Err(Error::MemberNotFound(leader));
+        };
+        Ok(Some((leader, endpoint.parse()?, raft_term)))
+    }
+
+    /// Gets the highest raft term observed by the members of the cluster.
+    ///
+    /// Unreachable members are ignored unless no member is reachable at all.
+    pub async fn raft_term(&mut self) -> Result<u64> {
+        let mut statuses = self.member_statuses().await?;
+        check_reachable(&mut statuses)?;
+        Ok(statuses
+            .iter()
+            .filter_map(|result| result.result.as_ref().ok())
+            .map(StatusResponse::raft_term)
+            .max()
+            .unwrap_or_default())
+    }
+
+    /// Summarizes the health of the cluster from the member list, the status of every
+    /// member and the raised alarms.
+    ///
+    /// Members which can not be reached are reported as such instead of failing the call.
+    pub async fn cluster_health(&mut self) -> Result<ClusterHealth> {
+        let statuses = self.member_statuses().await?;
+        // Alarms are reported on a best-effort basis, the list requires a reachable member.
+        let alarms = match self.alarm_list().await {
+            Ok(resp) => resp.alarms().to_vec(),
+            Err(_) => Vec::new(),
+        };
+        Ok(ClusterHealth::new(statuses, &alarms))
+    }
+
     /// Defragment a member's backend database to recover storage space.
     #[inline]
     pub async fn defragment(&mut self) -> Result<DefragmentResponse> {
-        let resp = self
-            .inner
-            .defragment(DefragmentOptions::new())
-            .await?
-            .into_inner();
+        let (mut inner, lane) = self.bulk_lane();
+        let resp = trace::lane(
+            lane,
+            observed!(
+                self.observer,
+                "Defragment",
+                inner.defragment(DefragmentOptions::new())
+            ),
+        )
+        .await
+        .for_rpc("Defragment")?
+        .into_inner();
         Ok(DefragmentResponse::new(resp))
     }
 
+    /// Defragments every member of the cluster one after another.
+    ///
+    /// Defragmentation is local to a member, so each member is connected to directly
+    /// through its first client URL, with the TLS and auth configuration of the client.
+    /// A failure of one member does not stop the run, the result of every defragmented
+    /// member is returned instead.
+    pub async fn defragment_all(
+        &mut self,
+        options: Option<DefragOptions>,
+    ) -> Result<Vec<MemberDefragmentResult>> {
+        let Some(connector) = self.connector.clone() else {
+            return Err(Error::EndpointsNotManaged);
+        };
+        let options = options.unwrap_or_default();
+
+        let mut members = self.cluster.member_list().await?.members().to_vec();
+        if options.skip_leader || options.leader_last {
+            let leader = self.status().await?.leader();
+            if options.skip_leader {
+                members.retain(|member| member.id() != leader);
+            } else {
+                // Stable sort, so the other members keep their order.
+                members.sort_by_key(|member| member.id() == leader);
+            }
+        }
+
+        let mut results = Vec::with_capacity(members.len());
+        for member in members {
+            let timeout = options.timeout;
+            let result = Self::on_member(&connector, member, |mut client, endpoint| async move {
+                match tokio::time::timeout(timeout, client.defragment()).await {
+                    Ok(resp) => resp,
+                    Err(_) => Err(Error::GRpcStatus(tonic::Status::deadline_exceeded(
+                        format!("defragment of {} timed out", endpoint),
+                    ))),
+                }
+            })
+            .await;
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    /// Runs an operation on a single member, connected through its first client URL.
+    async fn on_member<T, F, Fut>(connector: &Connector, member: Member, f: F) -> MemberResult<T>
+    where
+        F: FnOnce(MaintenanceClient, String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let Some(endpoint) = member.client_urls().first().cloned() else {
+            let result = Err(Error::InvalidArgs(format!(
+                "member {:x} has no client urls",
+                member.id()
+            )));
+            return MemberResult {
+                member,
+                endpoint: None,
+                result,
+            };
+        };
+
+        let client = endpoint
+            .parse()
+            .map_err(Error::from)
+            .and_then(|uri| connector.client(&uri));
+        let result = match client {
+            Ok(client) => f(client.maintenance_client(), endpoint.clone()).await,
+            Err(e) => Err(e),
+        };
+        MemberResult {
+            member,
+            endpoint: Some(endpoint),
+            result,
+        }
+    }
+
     /// Computes the hash of whole backend keyspace.
     /// including key, lease, and other buckets in storage.
     /// This is designed for testing ONLY!
     #[inline]
     pub async fn hash(&mut self) -> Result<HashResponse> {
-        let resp = self.inner.hash(HashOptions::new()).await?.into_inner();
+        let resp = observed!(self.observer, "Hash", self.inner.hash(HashOptions::new()))
+            .await
+            .for_rpc("Hash")?
+            .into_inner();
         Ok(HashResponse::new(resp))
     }
 
     /// Computes the hash of all MVCC keys up to a given revision.
     /// It only iterates \"key\" bucket in backend storage.
     #[inline]
-    pub async fn hash_kv(&mut self, revision: i64) -> Result<HashKvResponse> {
-        let resp = self
-            .inner
-            .hash_kv(HashKvOptions::new(revision))
-            .await?
-            .into_inner();
+    pub async fn hash_kv(&mut self, revision: impl Into<Revision>) -> Result<HashKvResponse> {
+        let resp = observed!(
+            self.observer,
+            "HashKV",
+            self.inner
+                .hash_kv(HashKvOptions::new(revision.into().get()))
+        )
+        .await
+        .for_rpc("HashKV")?
+        .into_inner();
         Ok(HashKvResponse::new(resp))
     }
 
+    /// Computes the hash of all MVCC keys up to `revision` on every member of the cluster,
+    /// reporting the members whose hashes diverge.
+    ///
+    /// A `revision` of `0` uses the current revision, so that all members hash the same
+    /// range. A member failing with a compacted revision can be detected with
+    /// [`Error::is_compacted`].
+    pub async fn hash_kv_all(
+        &mut self,
+        revision: impl Into<Revision>,
+    ) -> Result<ConsistencyReport> {
+        let Some(connector) = self.connector.clone() else {
+            return Err(Error::EndpointsNotManaged);
+        };
+
+        let mut revision = revision.into();
+        if revision.is_current() {
+            revision = self
+                .status()
+                .await?
+                .header()
+                .map(|header| header.revision())
+                .unwrap_or_default();
+        }
+
+        let members = self.cluster.member_list().await?.members().to_vec();
+        let mut results = Vec::with_capacity(members.len());
+        for member in members {
+            let result = Self::on_member(&connector, member, |mut client, _| async move {
+                client.hash_kv(revision).await
+            })
+            .await;
+            results.push(result);
+        }
+
+        Ok(ConsistencyReport {
+            revision,
+            members: results,
+        })
+    }
+
+    /// Verifies that a snapshot corresponds to a consistent state of the cluster, by hashing
+    /// all MVCC keys up to its revision on the member it was taken from, and on the other
+    /// members if [`VerifySnapshotOptions::with_all_members`] is set.
+    ///
+    /// Fails with [`Error::MemberNotFound`] if the member the snapshot was taken from left
+    /// the cluster. Once the revision of the snapshot is compacted, the members report
+    /// [`SnapshotHashCheck::Compacted`].
+    pub async fn verify_snapshot(
+        &mut self,
+        summary: &SnapshotSummary,
+        options: Option<VerifySnapshotOptions>,
+    ) -> Result<SnapshotVerification> {
+        let Some(connector) = self.connector.clone() else {
+            return Err(Error::EndpointsNotManaged);
+        };
+        let options = options.unwrap_or_default();
+        let revision = summary.revision();
+        let hash_kv = |mut client: MaintenanceClient, _: String| async move {
+            client.hash_kv(revision).await
+        };
+
+        let mut members = self.cluster.member_list().await?.members().to_vec();
+        let Some(index) = members.iter().position(|m| m.id() == summary.member_id()) else {
+            return Err(Error::MemberNotFound(summary.member_id()));
+        };
+        let source = Self::on_member(&connector, members.remove(index), hash_kv).await;
+
+        let mut results = Vec::new();
+        if options.all_members {
+            for member in members {
+                results.push(Self::on_member(&connector, member, hash_kv).await);
+            }
+        }
+
+        Ok(SnapshotVerification {
+            revision,
+            source,
+            members: results,
+        })
+    }
+
     /// Gets a snapshot of the entire backend from a member over a stream to a client.
     #[inline]
     pub async fn snapshot(&mut self) -> Result<SnapshotStreaming> {
+        self.snapshot_stream().await
+    }
+
+    /// Gets the raw chunk stream of a snapshot of the entire backend.
+    ///
+    /// The last 32 bytes of the stream are the sha256 checksum of the preceding bytes,
+    /// use [`MaintenanceClient::snapshot_to`] to have it verified.
+    #[inline]
+    pub async fn snapshot_stream(&mut self) -> Result<SnapshotStreaming> {
+        let call = observe_call!(self.observer, "Snapshot");
         let resp = self
-            .inner
-            .snapshot(SnapshotOptions::new())
-            .await?
-            .into_inner();
-        Ok(SnapshotStreaming(resp))
+            .open_snapshot(SnapshotOptions::new(), call.clone())
+            .await?;
+        Ok(SnapshotStreaming(resp, call))
+    }
+
+    /// Opens a snapshot stream as the observed call `call`.
+    async fn open_snapshot(
+        &mut self,
+        options: SnapshotOptions,
+        call: Call,
+    ) -> Result<PbStreaming<PbSnapshotResponse>> {
+        let (mut inner, lane) = self.bulk_lane();
+        let fut = trace::lane(lane, observed!(call = call, inner.snapshot(options)));
+        let resp = CallOptions::new()
+            .run("Snapshot", self.create_timeout, async {
+                fut.await.for_rpc("Snapshot")
+            })
+            .await?;
+        Ok(resp.into_inner())
+    }
+
+    /// Streams a snapshot of the entire backend into `writer` and verifies its checksum.
+    ///
+    /// The chunks are written as they arrive, including the trailing checksum, so the
+    /// output is the same as `etcdctl snapshot save`. On a checksum mismatch the written
+    /// data is left as is and [`Error::SnapshotChecksumMismatch`] is returned.
+    pub async fn snapshot_to<W>(
+        &mut self,
+        mut writer: W,
+        options: Option<SnapshotOptions>,
+    ) -> Result<SnapshotSummary>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let options = options.unwrap_or_default();
+        let on_chunk = options.on_chunk.clone();
+        let verify = options.verify;
+        let call = observe_call!(self.observer, "Snapshot");
+        let mut stream = self.open_snapshot(options, call).await?;
+
+        let mut hasher = SnapshotHasher::default();
+        let mut bytes = 0;
+        let mut header = None;
+        while let Some(mut resp) = stream.message().await.for_rpc("Snapshot")? {
+            if header.is_none() {
+                header = resp.header.take();
+            }
+            hasher.update(&resp.blob);
+            bytes += resp.blob.len() as u64;
+            writer.write_all_buf(&mut resp.blob).await?;
+            if let Some(on_chunk) = &on_chunk {
+                on_chunk(bytes);
+            }
+        }
+        writer.flush().await?;
+
+        Ok(SnapshotSummary {
+            bytes,
+            sha256: hasher.finish(verify)?,
+            revision: header.as_ref().map(|h| h.revision).unwrap_or_default(),
+            member_id: header.as_ref().map(|h| h.member_id).unwrap_or_default(),
+        })
+    }
+
+    /// Downloads a snapshot of the entire backend into the file at `path`.
+    ///
+    /// The snapshot is written to `path` with a `.part` suffix first, and only renamed to
+    /// `path` once complete and verified. The Snapshot RPC can not seek, so a broken stream
+    /// is restarted up to [`SnapshotOptions::with_max_retries`] times from the beginning,
+    /// and the part already on disk is compared with the new stream instead of being written
+    /// again. If the revision changed in the meantime, or the data differs, the file is
+    /// overwritten from there on.
+    pub async fn snapshot_to_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        options: Option<SnapshotOptions>,
+    ) -> Result<SnapshotSummary> {
+        let path = path.as_ref();
+        let options = options.unwrap_or_default();
+        let mut part = path.as_os_str().to_owned();
+        part.push(".part");
+        let part = PathBuf::from(part);
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&part)
+            .await?;
+        let mut download = SnapshotDownload::default();
+        let mut attempt = 0;
+        let result = loop {
+            match self
+                .snapshot_attempt(&mut file, &mut download, &options)
+                .await
+            {
+                Ok(summary) => break Ok(summary),
+                Err(e) if attempt < options.max_retries && is_snapshot_retryable(&e) => {
+                    attempt += 1;
+                    log_event!(
+                        Warn,
+                        "resuming etcd snapshot",
+                        attempt = attempt,
+                        revision = download.revision.unwrap_or_default(),
+                        offset = download.valid,
+                        error = &e,
+                    );
+                    tokio::time::sleep(SNAPSHOT_RETRY_BACKOFF).await;
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        drop(file);
+
+        match result {
+            Ok(summary) => {
+                tokio::fs::rename(&part, path).await?;
+                Ok(summary)
+            }
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&part).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Streams a snapshot into `file` once, skipping over the matching part already on disk.
+    async fn snapshot_attempt(
+        &mut self,
+        file: &mut tokio::fs::File,
+        download: &mut SnapshotDownload,
+        options: &SnapshotOptions,
+    ) -> Result<SnapshotSummary> {
+        let call = observe_call!(self.observer, "Snapshot");
+        let mut stream = self.open_snapshot(options.clone(), call).await?;
+        file.seek(SeekFrom::Start(0)).await?;
+
+        let mut hasher = SnapshotHasher::default();
+        let mut bytes = 0;
+        let mut first = true;
+        let mut member_id = 0;
+        let mut buf = Vec::new();
+        while let Some(resp) = stream.message().await.for_rpc("Snapshot")? {
+            if first {
+                first = false;
+                member_id = resp.header.as_ref().map_or(0, |header| header.member_id);
+                let revision = resp.header.as_ref().map(|header| header.revision);
+                if revision != download.revision {
+                    // A snapshot of another revision, nothing on disk can be reused.
+                    download.valid = 0;
+                    download.revision = revision;
+                }
+            }
+            hasher.update(&resp.blob);
+
+            let mut blob = resp.blob;
+            if bytes < download.valid {
+                let overlap = blob.len().min((download.valid - bytes) as usize);
+                buf.resize(overlap, 0);
+                file.read_exact(&mut buf).await?;
+                let matched = buf.iter().zip(&blob).take_while(|(a, b)| a == b).count();
+                bytes += matched as u64;
+                blob.advance(matched);
+                if matched < overlap {
+                    // Diverged from the previous attempt, overwrite from here on.
+                    download.valid = bytes;
+                    file.seek(SeekFrom::Start(bytes)).await?;
+                }
+            }
+            if !blob.is_empty() {
+                bytes += blob.len() as u64;
+                file.write_all_buf(&mut blob).await?;
+                download.valid = bytes;
+            }
+
+            if let Some(on_chunk) = &options.on_chunk {
+                on_chunk(bytes);
+            }
+        }
+
+        // The previous attempt may have written a longer snapshot.
+        file.set_len(bytes).await?;
+        file.sync_all().await?;
+
+        Ok(SnapshotSummary {
+            bytes,
+            sha256: hasher.finish(options.verify)?,
+            revision: download.revision.unwrap_or_default(),
+            member_id,
+        })
     }
 
     /// Moves the current leader node to target node.
-    #[inline]
+    ///
+    /// The request must be served by the leader, so unless the client was created from a raw
+    /// channel, it is sent to the current leader directly, retrying once if the leadership
+    /// changed in the meantime. [`Error::MemberNotFound`] is returned if the target is not
+    /// a member of the cluster.
     pub async fn move_leader(&mut self, target_id: u64) -> Result<MoveLeaderResponse> {
-        let resp = self
-            .inner
-            .move_leader(MoveLeaderOptions::new().with_target_id(target_id))
-            .await?
-            .into_inner();
+        let Some(connector) = self.connector.clone() else {
+            return Self::move_leader_on(self, target_id).await;
+        };
+
+        let members = self.cluster.member_list().await?.members().to_vec();
+        if !members.iter().any(|member| member.id() == target_id) {
+            return Err(Error::MemberNotFound(target_id));
+        }
+
+        match self.move_leader_once(&connector, &members, target_id).await {
+            Err(e) if e.is_not_leader() => {
+                self.move_leader_once(&connector, &members, target_id).await
+            }
+            resp => resp,
+        }
+    }
+
+    async fn move_leader_once(
+        &mut self,
+        connector: &Connector,
+        members: &[Member],
+        target_id: u64,
+    ) -> Result<MoveLeaderResponse> {
+        let leader_id = self.status().await?.leader();
+        let Some(leader) = members.iter().find(|member| member.id() == leader_id) else {
+            return Err(Error::MemberNotFound(leader_id));
+        };
+
+        Self::on_member(connector, leader.clone(), |mut client, _| async move {
+            Self::move_leader_on(&mut client, target_id).await
+        })
+        .await
+        .into_result()
+    }
+
+    #[inline]
+    async fn move_leader_on(client: &mut Self, target_id: u64) -> Result<MoveLeaderResponse> {
+        let resp = observed!(
+            client.observer,
+            "MoveLeader",
+            client
+                .inner
+                .move_leader(MoveLeaderOptions::new().with_target_id(target_id))
+        )
+        .await
+        .for_rpc("MoveLeader")?
+        .into_inner();
         Ok(MoveLeaderResponse::new(resp))
     }
+
+    /// Validates whether the cluster can be downgraded to `target_version`.
+    ///
+    /// Supported since etcd 3.5, [`Error::UnsupportedByServer`] is returned by older servers.
+    #[inline]
+    pub async fn downgrade_validate(
+        &mut self,
+        target_version: impl Into<String>,
+    ) -> Result<DowngradeResponse> {
+        self.downgrade(DowngradeAction::Validate, target_version.into())
+            .await
+    }
+
+    /// Enables downgrading the cluster to `target_version`.
+    ///
+    /// Supported since etcd 3.5, [`Error::UnsupportedByServer`] is returned by older servers.
+    #[inline]
+    pub async fn downgrade_enable(
+        &mut self,
+        target_version: impl Into<String>,
+    ) -> Result<DowngradeResponse> {
+        self.downgrade(DowngradeAction::Enable, target_version.into())
+            .await
+    }
+
+    /// Cancels the ongoing downgrade of the cluster.
+    ///
+    /// Supported since etcd 3.5, [`Error::UnsupportedByServer`] is returned by older servers.
+    #[inline]
+    pub async fn downgrade_cancel(&mut self) -> Result<DowngradeResponse> {
+        self.downgrade(DowngradeAction::Cancel, String::new()).await
+    }
+
+    async fn downgrade(
+        &mut self,
+        action: DowngradeAction,
+        version: String,
+    ) -> Result<DowngradeResponse> {
+        match observed!(
+            self.observer,
+            "Downgrade",
+            self.inner.downgrade(DowngradeOptions::new(action, version))
+        )
+        .await
+        {
+            Ok(resp) => Ok(DowngradeResponse::new(resp.into_inner())),
+            Err(status) if status.code() == tonic::Code::Unimplemented => {
+                let server_version = self
+                    .status()
+                    .await
+                    .ok()
+                    .map(|status| status.version().to_owned());
+                Err(Error::UnsupportedByServer {
+                    rpc: "Downgrade",
+                    server_version,
+                })
+            }
+            Err(status) => Err(Error::from(status).with_rpc("Downgrade")),
+        }
+    }
+}
+
+impl_raw_conversions!(
+    AlarmResponse(PbAlarmResponse),
+    StatusResponse(PbStatusResponse),
+    DefragmentResponse(PbDefragmentResponse),
+    HashResponse(PbHashResponse),
+    HashKvResponse(PbHashKvResponse),
+    SnapshotResponse(PbSnapshotResponse),
+    MoveLeaderResponse(PbMoveLeaderResponse),
+    DowngradeResponse(PbDowngradeResponse),
+);
+
+impl_raw_conversions!(
+    options:
+    AlarmOptions(PbAlarmRequest),
+    MoveLeaderOptions(PbMoveLeaderRequest),
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::Channel;
+    use crate::intercept::Interceptor;
+    use crate::rpc::pb::etcdserverpb::Member as PbMember;
+    use crate::rpc::pb::etcdserverpb::ResponseHeader as PbResponseHeader;
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+    use prost::Message;
+    use tonic::codegen::Bytes;
+    use tower::util::BoxCloneService;
+    use tower::ServiceExt;
+
+    fn snapshot(data: &[u8]) -> Vec<u8> {
+        let mut snapshot = data.to_vec();
+        snapshot.extend_from_slice(&Sha256::digest(data));
+        snapshot
+    }
+
+    #[test]
+    fn test_snapshot_hasher() {
+        let snapshot = snapshot(b"etcd snapshot data, long enough to span several chunks");
+        for chunk in [1, 7, 31, 32, 33, 64, snapshot.len()] {
+            let mut hasher = SnapshotHasher::default();
+            snapshot.chunks(chunk).for_each(|blob| hasher.update(blob));
+            let sha256 = hasher.verify().unwrap();
+            assert_eq!(&snapshot[snapshot.len() - 32..], sha256);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_hasher_mismatch() {
+        let mut snapshot = snapshot(b"etcd snapshot data");
+        snapshot[0] ^= 1;
+
+        let mut hasher = SnapshotHasher::default();
+        hasher.update(&snapshot);
+        match hasher.verify() {
+            Err(Error::SnapshotChecksumMismatch { expected, actual }) => {
+                assert_eq!(expected, &snapshot[snapshot.len() - 32..]);
+                assert_ne!(expected, actual);
+            }
+            _ => panic!("expected a checksum mismatch"),
+        }
+    }
+
+    fn member_hash(id: u64, hash: u32, compact_revision: i64) -> MemberHashKvResult {
+        let member = PbMember {
+            id,
+            ..Default::default()
+        };
+        MemberResult {
+            member: <&Member>::from(&member).clone(),
+            endpoint: None,
+            result: Ok(HashKvResponse::new(PbHashKvResponse {
+                header: None,
+                hash,
+                compact_revision,
+            })),
+        }
+    }
+
+    #[test]
+    fn test_consistency_report() {
+        let report = ConsistencyReport {
+            revision: Revision::new(10),
+            members: vec![
+                member_hash(1, 100, 5),
+                member_hash(2, 200, 5),
+                member_hash(3, 100, 5),
+                member_hash(4, 300, 6),
+                MemberResult {
+                    member: member_hash(5, 0, 0).member,
+                    endpoint: None,
+                    result: Err(Error::EndpointsNotManaged),
+                },
+            ],
+        };
+
+        assert_eq!(report.majority(), Some((100, Revision::new(5))));
+        let mismatched: Vec<u64> = report
+            .mismatched()
+            .iter()
+            .map(|m| m.member().id())
+            .collect();
+        assert_eq!(mismatched, [2]);
+        assert_eq!(report.failed().len(), 1);
+        assert!(!report.is_consistent());
+    }
+
+    #[test]
+    fn test_snapshot_verification() {
+        let mut verification = SnapshotVerification {
+            revision: Revision::new(10),
+            source: member_hash(1, 100, 5),
+            members: vec![
+                member_hash(2, 100, 5),
+                member_hash(3, 200, 5),
+                member_hash(4, 300, 6),
+            ],
+        };
+        let checks: Vec<SnapshotHashCheck> = verification
+            .members()
+            .iter()
+            .map(|m| verification.check(m))
+            .collect();
+        assert_eq!(
+            checks,
+            [
+                SnapshotHashCheck::Match,
+                SnapshotHashCheck::Mismatch,
+                SnapshotHashCheck::Incomparable
+            ]
+        );
+        assert_eq!(verification.mismatched()[0].member().id(), 3);
+        assert!(!verification.is_verified());
+
+        verification.members.truncate(1);
+        assert!(verification.is_verified());
+
+        verification.source.result = Err(Error::Compacted {
+            status: tonic::Status::out_of_range(
+                "etcdserver: mvcc: required revision has been compacted",
+            ),
+        });
+        assert!(verification.is_compacted());
+        assert_eq!(
+            verification.check(&verification.members[0]),
+            SnapshotHashCheck::Incomparable
+        );
+        assert!(!verification.is_verified());
+    }
+
+    fn member_status(id: u64, leader: u64, raft_term: u64) -> MemberResult<StatusResponse> {
+        let member = PbMember {
+            id,
+            ..Default::default()
+        };
+        MemberResult {
+            member: <&Member>::from(&member).clone(),
+            endpoint: None,
+            result: Ok(StatusResponse::new(PbStatusResponse {
+                leader,
+                raft_term,
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn test_cluster_health() {
+        let alarms = AlarmResponse::new(PbAlarmResponse {
+            header: None,
+            alarms: vec![PbAlarmMember {
+                member_id: 2,
+                alarm: AlarmType::Nospace as i32,
+            }],
+        });
+        let health = ClusterHealth::new(
+            vec![
+                member_status(1, 1, 5),
+                member_status(2, 1, 5),
+                MemberResult {
+                    member: member_status(3, 0, 0).member,
+                    endpoint: None,
+                    result: Err(Error::EndpointsNotManaged),
+                },
+            ],
+            alarms.alarms(),
+        );
+
+        assert_eq!(health.members_total(), 3);
+        assert_eq!(health.members_healthy(), 1);
+        assert_eq!(health.leader(), Some(1));
+        assert!(health.members()[0].is_leader());
+        assert_eq!(health.members()[1].alarms(), [AlarmType::Nospace]);
+        assert!(!health.members()[2].is_reachable());
+        assert!(health.quorum_available());
+        assert_eq!(health.term_skew(), 0);
+
+        let health = ClusterHealth::new(
+            vec![
+                member_status(1, 0, 7),
+                member_status(2, 1, 5),
+                MemberResult {
+                    member: member_status(3, 0, 0).member,
+                    endpoint: None,
+                    result: Err(Error::EndpointsNotManaged),
+                },
+            ],
+            &[],
+        );
+        assert!(health.has_leader());
+        assert!(!health.quorum_available());
+        assert_eq!(health.term_skew(), 2);
+    }
+
+    #[test]
+    fn test_leader_of() {
+        let mut statuses = vec![
+            member_status(1, 1, 5),
+            member_status(2, 3, 6),
+            member_status(3, 0, 7),
+        ];
+        assert_eq!(leader_of(&mut statuses).unwrap(), Some((3, 6)));
+
+        let mut statuses = vec![member_status(1, 0, 5)];
+        assert_eq!(leader_of(&mut statuses).unwrap(), None);
+
+        let unreachable = || MemberResult {
+            member: member_status(1, 0, 0).member,
+            endpoint: None,
+            result: Err(Error::EndpointsNotManaged),
+        };
+        let mut statuses = vec![unreachable(), member_status(2, 2, 4)];
+        assert_eq!(leader_of(&mut statuses).unwrap(), Some((2, 4)));
+        let mut statuses = vec![unreachable(), unreachable()];
+        assert!(matches!(
+            leader_of(&mut statuses),
+            Err(Error::EndpointsNotManaged)
+        ));
+    }
+
+    /// A client whose every request fails with `code`.
+    fn failing_client(code: tonic::Code) -> MaintenanceClient {
+        let service = tower::service_fn(move |_req: http::Request<tonic::body::Body>| async move {
+            let resp = tonic::Status::new(code, "mock").into_http::<tonic::body::Body>();
+            Ok::<_, tower::BoxError>(resp)
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor::default(),
+        );
+        MaintenanceClient::new(channel, AuthToken::default())
+    }
+
+    #[tokio::test]
+    async fn test_downgrade_unimplemented() {
+        let mut client = failing_client(tonic::Code::Unimplemented);
+        match client.downgrade_validate("3.4.0").await {
+            Err(Error::UnsupportedByServer {
+                rpc,
+                server_version,
+            }) => {
+                assert_eq!(rpc, "Downgrade");
+                assert_eq!(server_version, None);
+            }
+            other => panic!("expected unsupported by server, got {:?}", other),
+        }
+
+        let mut client = failing_client(tonic::Code::FailedPrecondition);
+        let err = client.downgrade_cancel().await.unwrap_err();
+        assert!(
+            matches!(err, Error::GRpcStatus(status) if status.code() == tonic::Code::FailedPrecondition)
+        );
+    }
+
+    /// One response of the mock snapshot service: the revision, the snapshot, and the
+    /// number of chunks after which the stream breaks.
+    type SnapshotAttempt = (i64, Vec<u8>, Option<usize>);
+
+    /// A client serving the given snapshot attempts in turn, in chunks of 16 bytes.
+    fn snapshot_client(attempts: Vec<SnapshotAttempt>) -> MaintenanceClient {
+        let attempts = Arc::new(std::sync::Mutex::new(attempts.into_iter()));
+        let service = tower::service_fn(move |_req: http::Request<tonic::body::Body>| {
+            let (revision, snapshot, broken_after) = attempts.lock().unwrap().next().unwrap();
+            async move {
+                let mut frames = Vec::new();
+                for blob in snapshot.chunks(16).take(broken_after.unwrap_or(usize::MAX)) {
+                    let msg = PbSnapshotResponse {
+                        header: Some(PbResponseHeader {
+                            revision,
+                            ..Default::default()
+                        }),
+                        remaining_bytes: 0,
+                        blob: Bytes::copy_from_slice(blob),
+                    };
+                    let mut buf = vec![0];
+                    buf.extend_from_slice(&(msg.encoded_len() as u32).to_be_bytes());
+                    msg.encode(&mut buf).unwrap();
+                    frames.push(Ok::<_, tower::BoxError>(Frame::data(Bytes::from(buf))));
+                }
+                let status = match broken_after {
+                    Some(_) => tonic::Status::unavailable("stream broken"),
+                    None => tonic::Status::ok(""),
+                };
+                let mut trailers = http::HeaderMap::new();
+                status.add_header(&mut trailers).unwrap();
+                frames.push(Ok(Frame::trailers(trailers)));
+
+                let body = tonic::body::Body::new(StreamBody::new(tokio_stream::iter(frames)));
+                let resp = http::Response::builder()
+                    .header("content-type", "application/grpc")
+                    .body(body)
+                    .unwrap();
+                Ok::<_, tower::BoxError>(resp)
+            }
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor::default(),
+        );
+        MaintenanceClient::new(channel, AuthToken::default())
+    }
+
+    fn snapshot_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("etcd-client-{}-{}.db", std::process::id(), name))
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_to_file_resume() {
+        let data = snapshot(&[7; 100]);
+        let mut diverged = snapshot(&[7; 120]);
+        diverged[40] = 8;
+
+        // Broken, resumed at the same revision but diverged, then at a new revision.
+        let mut client = snapshot_client(vec![
+            (5, data.clone(), Some(3)),
+            (5, diverged, Some(5)),
+            (6, data.clone(), None),
+        ]);
+        let path = snapshot_path("resume");
+        let options = SnapshotOptions::new().with_max_retries(2);
+        let summary = client.snapshot_to_file(&path, Some(options)).await.unwrap();
+        assert_eq!(summary.bytes(), data.len() as u64);
+        assert_eq!(summary.revision(), 6);
+        assert_eq!(&summary.sha256()[..], &data[data.len() - 32..]);
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), data);
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        // Resumed at the same revision with the same data.
+        let mut client = snapshot_client(vec![(5, data.clone(), Some(2)), (5, data.clone(), None)]);
+        let options = SnapshotOptions::new().with_max_retries(1);
+        let summary = client.snapshot_to_file(&path, Some(options)).await.unwrap();
+        assert_eq!(summary.revision(), 5);
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), data);
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_to_file_exhausted() {
+        let data = snapshot(&[7; 100]);
+        let mut client = snapshot_client(vec![(5, data.clone(), Some(1)), (5, data, Some(2))]);
+        let path = snapshot_path("exhausted");
+        let options = SnapshotOptions::new().with_max_retries(1);
+        let err = client
+            .snapshot_to_file(&path, Some(options))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::GRpcStatus(status) if status.code() == tonic::Code::Unavailable)
+        );
+        assert!(!path.exists());
+        let mut part = path.into_os_string();
+        part.push(".part");
+        assert!(!PathBuf::from(part).exists());
+    }
 }
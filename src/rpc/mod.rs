@@ -1,27 +1,91 @@
 //! Etcd RPC interfaces.
 
-#[cfg(feature = "pub-response-field")]
 pub(crate) mod pb;
 
-#[cfg(not(feature = "pub-response-field"))]
-mod pb;
+/// Implements the conversions between wrappers and the protobuf messages they wrap, both
+/// ways for `Wrapper(Pb)`, and from the message for `options: Wrapper(Pb[, CallOptions])`
+/// whose conversion into the request already exists.
+macro_rules! impl_raw_conversions {
+    (options: $($wrapper:ident($pb:ty $(, $field:ty)*),)*) => {
+        $(
+            #[cfg(feature = "raw-proto")]
+            #[cfg_attr(docsrs, doc(cfg(feature = "raw-proto")))]
+            impl From<$pb> for $wrapper {
+                #[inline]
+                fn from(pb: $pb) -> Self {
+                    Self(pb $(, <$field>::default())*)
+                }
+            }
+        )*
+    };
+    ($($wrapper:ident($pb:ty),)*) => {
+        $(
+            #[cfg(feature = "raw-proto")]
+            #[cfg_attr(docsrs, doc(cfg(feature = "raw-proto")))]
+            impl From<$pb> for $wrapper {
+                #[inline]
+                fn from(pb: $pb) -> Self {
+                    Self(pb)
+                }
+            }
+
+            #[cfg(feature = "raw-proto")]
+            #[cfg_attr(docsrs, doc(cfg(feature = "raw-proto")))]
+            impl From<$wrapper> for $pb {
+                #[inline]
+                fn from(wrapper: $wrapper) -> Self {
+                    wrapper.0
+                }
+            }
+        )*
+    };
+}
 
+#[cfg(feature = "auth")]
+#[cfg_attr(docsrs, doc(cfg(feature = "auth")))]
 pub mod auth;
+#[cfg(feature = "cluster")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cluster")))]
 pub mod cluster;
+#[cfg(feature = "election")]
+#[cfg_attr(docsrs, doc(cfg(feature = "election")))]
 pub mod election;
+#[cfg(feature = "kv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
 pub mod kv;
+#[cfg(feature = "lease")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lease")))]
 pub mod lease;
+#[cfg(feature = "lock")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lock")))]
 pub mod lock;
+#[cfg(feature = "maintenance")]
+#[cfg_attr(docsrs, doc(cfg(feature = "maintenance")))]
 pub mod maintenance;
+#[cfg(feature = "watch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
 pub mod watch;
 
+use crate::bytes::{self, DebugBytes};
 use crate::error::Result;
+use crate::ids::{LeaseId, Revision};
 use pb::etcdserverpb::ResponseHeader as PbResponseHeader;
 use pb::mvccpb::KeyValue as PbKeyValue;
+use prost::bytes::Bytes;
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Formatter};
 
 /// General `etcd` response header.
+///
+/// The header is a few integers, copied rather than shared: keeping it out of a response
+/// allocates nothing, unlike sharing it behind an `Arc`.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct ResponseHeader(PbResponseHeader);
 
@@ -49,8 +113,8 @@ impl ResponseHeader {
     /// received in this stream are guaranteed to have a higher revision number than the
     /// header.revision() number.
     #[inline]
-    pub const fn revision(&self) -> i64 {
-        self.0.revision
+    pub const fn revision(&self) -> Revision {
+        Revision::new(self.0.revision)
     }
 
     /// The raft term when the request was applied.
@@ -67,9 +131,106 @@ impl From<&PbResponseHeader> for &ResponseHeader {
     }
 }
 
+/// Responses carrying a [`ResponseHeader`], e.g. to detect raft term changes from the
+/// responses of any RPC.
+pub trait HasResponseHeader {
+    /// Get response header.
+    fn header(&self) -> Option<&ResponseHeader>;
+
+    /// The raft term when the request was applied.
+    #[inline]
+    fn raft_term(&self) -> Option<u64> {
+        self.header().map(ResponseHeader::raft_term)
+    }
+}
+
+macro_rules! impl_has_response_header {
+    ($($feature:literal => [$($response:ty,)*])*) => {
+        $($(
+            #[cfg(feature = $feature)]
+            impl HasResponseHeader for $response {
+                #[inline]
+                fn header(&self) -> Option<&ResponseHeader> {
+                    <$response>::header(self)
+                }
+            }
+        )*)*
+    };
+}
+
+impl_has_response_header! {
+    "auth" => [
+        auth::AuthEnableResponse,
+        auth::AuthDisableResponse,
+        auth::AuthenticateResponse,
+        auth::RoleAddResponse,
+        auth::RoleDeleteResponse,
+        auth::RoleGetResponse,
+        auth::RoleListResponse,
+        auth::RoleGrantPermissionResponse,
+        auth::RoleRevokePermissionResponse,
+        auth::UserAddResponse,
+        auth::UserGetResponse,
+        auth::UserListResponse,
+        auth::UserDeleteResponse,
+        auth::UserChangePasswordResponse,
+        auth::UserGrantRoleResponse,
+        auth::UserRevokeRoleResponse,
+    ]
+    "cluster" => [
+        cluster::MemberAddResponse,
+        cluster::MemberRemoveResponse,
+        cluster::MemberUpdateResponse,
+        cluster::MemberListResponse,
+        cluster::MemberPromoteResponse,
+    ]
+    "election" => [
+        election::CampaignResponse,
+        election::ProclaimResponse,
+        election::LeaderResponse,
+        election::ResignResponse,
+    ]
+    "kv" => [
+        kv::PutResponse,
+        kv::GetResponse,
+        kv::DeleteResponse,
+        kv::CompactionResponse,
+        kv::TxnResponse,
+    ]
+    "lease" => [
+        lease::LeaseGrantResponse,
+        lease::LeaseRevokeResponse,
+        lease::LeaseKeepAliveResponse,
+        lease::LeaseTimeToLiveResponse,
+        lease::LeaseLeasesResponse,
+    ]
+    "lock" => [
+        lock::LockResponse,
+        lock::UnlockResponse,
+    ]
+    "maintenance" => [
+        maintenance::AlarmResponse,
+        maintenance::StatusResponse,
+        maintenance::DefragmentResponse,
+        maintenance::HashResponse,
+        maintenance::HashKvResponse,
+        maintenance::SnapshotResponse,
+        maintenance::MoveLeaderResponse,
+        maintenance::DowngradeResponse,
+    ]
+    "watch" => [
+        watch::WatchResponse,
+    ]
+}
+
 /// Key-value pair.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct KeyValue(PbKeyValue);
 
@@ -103,6 +264,18 @@ impl KeyValue {
         std::str::from_utf8_unchecked(self.key())
     }
 
+    /// The key in string, with invalid UTF-8 sequences replaced by `U+FFFD`.
+    #[inline]
+    pub fn key_str_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.key())
+    }
+
+    /// The key in lowercase hex.
+    #[inline]
+    pub fn key_hex(&self) -> String {
+        bytes::hex(self.key())
+    }
+
     /// The value held by the key, in bytes.
     #[inline]
     pub fn value(&self) -> &[u8] {
@@ -126,6 +299,19 @@ impl KeyValue {
         std::str::from_utf8_unchecked(self.value())
     }
 
+    /// The value held by the key, in string, with invalid UTF-8 sequences replaced by
+    /// `U+FFFD`.
+    #[inline]
+    pub fn value_str_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.value())
+    }
+
+    /// The value held by the key, in lowercase hex.
+    #[inline]
+    pub fn value_hex(&self) -> String {
+        bytes::hex(self.value())
+    }
+
     /// Convert to key-value pair.
     pub fn into_key_value(self) -> (Vec<u8>, Vec<u8>) {
         (self.0.key, self.0.value)
@@ -133,14 +319,14 @@ impl KeyValue {
 
     /// The revision of last creation on this key.
     #[inline]
-    pub const fn create_revision(&self) -> i64 {
-        self.0.create_revision
+    pub const fn create_revision(&self) -> Revision {
+        Revision::new(self.0.create_revision)
     }
 
     /// The revision of last modification on this key.
     #[inline]
-    pub const fn mod_revision(&self) -> i64 {
-        self.0.mod_revision
+    pub const fn mod_revision(&self) -> Revision {
+        Revision::new(self.0.mod_revision)
     }
 
     /// The version of the key. A deletion resets
@@ -155,8 +341,23 @@ impl KeyValue {
     /// When the attached lease expires, the key will be deleted.
     /// If lease is 0, then no lease is attached to the key.
     #[inline]
-    pub const fn lease(&self) -> i64 {
-        self.0.lease
+    pub const fn lease(&self) -> LeaseId {
+        LeaseId::new(self.0.lease)
+    }
+}
+
+/// Prints the key and value as strings if printable, truncated to
+/// [`DEBUG_BYTES_LIMIT`](crate::DEBUG_BYTES_LIMIT) bytes or the precision of the format.
+impl Debug for PbKeyValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyValue")
+            .field("key", &DebugBytes(&self.key))
+            .field("create_revision", &self.create_revision)
+            .field("mod_revision", &self.mod_revision)
+            .field("version", &self.version)
+            .field("value", &DebugBytes(&self.value))
+            .field("lease", &self.lease)
+            .finish()
     }
 }
 
@@ -174,9 +375,21 @@ impl From<&mut PbKeyValue> for &mut KeyValue {
     }
 }
 
+/// Converts `bytes` into the bytes of a request, sharing them without copying them if
+/// already owned.
+///
+/// A borrowed key is copied once here, when the request is built, rather than when it is
+/// sent: the options own their requests, deferring the copy to the send would need them to
+/// borrow the key, and a lifetime on every option type.
+#[inline]
+pub(crate) fn shared_bytes(bytes: impl Into<Vec<u8>>) -> Bytes {
+    let bytes: Vec<u8> = bytes.into();
+    Bytes::from(bytes)
+}
+
 /// Get prefix end key of `key`.
 #[inline]
-fn get_prefix(key: &[u8]) -> Vec<u8> {
+pub(crate) fn get_prefix(key: &[u8]) -> Vec<u8> {
     for (i, v) in key.iter().enumerate().rev() {
         if *v < 0xFF {
             let mut end = Vec::from(&key[..=i]);
@@ -251,6 +464,13 @@ impl KeyRange {
         self.with_from_key = false;
     }
 
+    /// Returns `true` if the range is the key alone, no range end nor option set.
+    #[cfg(all(feature = "kv", feature = "watch"))]
+    #[inline]
+    pub fn is_single_key(&self) -> bool {
+        self.range_end.is_empty() && !self.with_prefix && !self.with_from_key && !self.with_all_keys
+    }
+
     /// Build the key and range end.
     #[inline]
     pub fn build(mut self) -> (Vec<u8>, Vec<u8>) {
@@ -275,6 +495,8 @@ impl KeyRange {
     }
 }
 
+impl_raw_conversions!(ResponseHeader(PbResponseHeader), KeyValue(PbKeyValue),);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -285,4 +507,83 @@ mod tests {
         assert_eq!(get_prefix(b"\xFF").as_slice(), b"\0");
         assert_eq!(get_prefix(b"foo\xFF").as_slice(), b"fop");
     }
+
+    #[test]
+    fn test_key_value_debug() {
+        use pb::etcdserverpb::{response_op, RangeResponse, ResponseOp, TxnResponse};
+        use pb::mvccpb::Event;
+
+        let kv = KeyValue::new(PbKeyValue {
+            key: b"foo".to_vec(),
+            value: b"\x00\x01".repeat(200),
+            mod_revision: 7,
+            ..Default::default()
+        });
+        assert_eq!(kv.key_str_lossy(), "foo");
+        assert_eq!(kv.key_hex(), "666f6f");
+        assert_eq!(kv.value_hex().len(), 800);
+        let invalid = KeyValue::new(PbKeyValue {
+            value: b"a\xffb".to_vec(),
+            ..Default::default()
+        });
+        assert_eq!(invalid.value_str_lossy(), "a\u{FFFD}b");
+
+        let debug = format!("{:.4?}", kv);
+        assert_eq!(
+            debug,
+            r#"KeyValue(KeyValue { key: "foo", create_revision: 0, mod_revision: 7, version: 0, value: b"\x00\x01\x00\x01"... (400 bytes), lease: 0 })"#
+        );
+        assert!(format!("{:?}", kv).contains("... (400 bytes)"));
+
+        // Events and responses holding key-values print them the same, the precision of
+        // the format applies to the nested key-values.
+        let event = Event {
+            kv: Some(kv.0.clone()),
+            prev_kv: Some(invalid.0),
+            ..Default::default()
+        };
+        let debug = format!("{:.4?}", event);
+        assert!(debug.contains(r#"key: "foo""#), "{}", debug);
+        assert!(debug.contains(r#"value: b"a\xffb""#), "{}", debug);
+        let txn = TxnResponse {
+            responses: vec![ResponseOp {
+                response: Some(response_op::Response::ResponseRange(RangeResponse {
+                    kvs: vec![kv.0],
+                    count: 1,
+                    ..Default::default()
+                })),
+            }],
+            ..Default::default()
+        };
+        let debug = format!("{:#.4?}", txn);
+        assert!(debug.contains(r#"key: "foo""#), "{}", debug);
+        assert!(debug.contains("... (400 bytes)"), "{}", debug);
+    }
+
+    #[cfg(feature = "raw-proto")]
+    #[test]
+    fn test_raw_conversions() {
+        use crate::raw::{etcdserverpb, mvccpb};
+
+        let kv = mvccpb::KeyValue {
+            key: b"foo".to_vec(),
+            value: b"bar".to_vec(),
+            ..Default::default()
+        };
+        let resp = kv::GetResponse::from(etcdserverpb::RangeResponse {
+            kvs: vec![kv.clone()],
+            count: 1,
+            ..Default::default()
+        });
+        assert_eq!(resp.kvs()[0].key(), b"foo");
+        let resp = etcdserverpb::RangeResponse::from(resp);
+        assert_eq!(resp.kvs, std::slice::from_ref(&kv));
+        assert_eq!(mvccpb::KeyValue::from(KeyValue::from(kv.clone())), kv);
+
+        let options = kv::PutOptions::from(etcdserverpb::PutRequest {
+            lease: 7,
+            ..Default::default()
+        });
+        assert_eq!(etcdserverpb::PutRequest::from(options).lease, 7);
+    }
 }
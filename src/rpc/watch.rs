@@ -2,9 +2,16 @@
 
 pub use crate::rpc::pb::mvccpb::event::EventType;
 
-use crate::auth::AuthService;
-use crate::error::{Error, Result};
+use crate::auth::{AuthService, AuthToken};
+use crate::compression::{Compressing, Compression};
+use crate::deadline::CallOptions;
+use crate::error::{Error, Result, RpcResultExt};
+use crate::ids::Revision;
 use crate::intercept::InterceptedChannel;
+use crate::observe::{observe_call, observed, Call, Observer};
+use crate::retry::{retry, RetryPolicy};
+#[cfg(feature = "kv")]
+use crate::rpc::kv::{GetOptions, KvClient};
 use crate::rpc::pb::etcdserverpb::watch_client::WatchClient as PbWatchClient;
 use crate::rpc::pb::etcdserverpb::watch_request::RequestUnion as WatchRequestUnion;
 use crate::rpc::pb::etcdserverpb::{
@@ -12,38 +19,109 @@ use crate::rpc::pb::etcdserverpb::{
     WatchResponse as PbWatchResponse,
 };
 use crate::rpc::pb::mvccpb::Event as PbEvent;
+#[cfg(feature = "kv")]
+use crate::rpc::pb::mvccpb::KeyValue as PbKeyValue;
 use crate::rpc::{KeyRange, KeyValue, ResponseHeader};
-use http::HeaderValue;
+use crate::shared_watch::SharedWatches;
+use crate::task::Tasks;
+use crate::trace::stream_event;
+use std::future::Future;
 use std::pin::Pin;
-use std::sync::{Arc, RwLock};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{channel, Sender};
 use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
 use tonic::Streaming;
 
 /// Client for watch operations.
-#[repr(transparent)]
 #[derive(Clone)]
 pub struct WatchClient {
-    inner: PbWatchClient<AuthService<InterceptedChannel>>,
+    inner: Compressing<PbWatchClient<AuthService<InterceptedChannel>>>,
+    /// Reads the keys observed, see [`WatchClient::observe_key`].
+    #[cfg(feature = "kv")]
+    pub(crate) kv: KvClient,
+    retry: Option<RetryPolicy>,
+    create_timeout: Option<Duration>,
+    observer: Observer,
+    pub(crate) tasks: Tasks,
+    /// The watches shared by subscribers, see [`WatchClient::shared_subscribe`].
+    pub(crate) shared: SharedWatches,
 }
 
 impl WatchClient {
     /// Creates a watch client.
     #[inline]
-    pub(crate) fn new(
-        channel: InterceptedChannel,
-        auth_token: Arc<RwLock<Option<HeaderValue>>>,
-    ) -> Self {
-        let inner = PbWatchClient::new(AuthService::new(channel, auth_token));
-        Self { inner }
+    pub(crate) fn new(channel: InterceptedChannel, auth_token: AuthToken) -> Self {
+        #[cfg(feature = "kv")]
+        let kv = KvClient::new(channel.clone(), auth_token.clone());
+        let inner = Compressing::new(PbWatchClient::new(AuthService::new(channel, auth_token)));
+        Self {
+            inner,
+            #[cfg(feature = "kv")]
+            kv,
+            retry: None,
+            create_timeout: None,
+            observer: Observer::default(),
+            tasks: Tasks::default(),
+            shared: SharedWatches::default(),
+        }
+    }
+
+    /// Retries creating watches failing with transient errors according to `policy`.
+    #[inline]
+    pub(crate) fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        #[cfg(feature = "kv")]
+        {
+            self.kv = self.kv.with_retry(policy.clone());
+        }
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Spawns the background tasks of observed keys with `tasks`.
+    #[inline]
+    pub(crate) fn with_tasks(mut self, tasks: Tasks) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    /// Aborts creating watches which have no deadline of their own after `timeout`.
+    #[inline]
+    pub(crate) fn with_create_timeout(mut self, timeout: Duration) -> Self {
+        self.create_timeout = Some(timeout);
+        self
+    }
+
+    /// Observes watches with `observer`.
+    #[inline]
+    pub(crate) fn with_observer(mut self, observer: Observer) -> Self {
+        #[cfg(feature = "kv")]
+        {
+            self.kv = self.kv.with_observer(observer.clone());
+        }
+        self.observer = observer;
+        self
+    }
+
+    /// Compresses the messages according to `compression`.
+    #[inline]
+    pub(crate) fn with_compression(mut self, compression: &Compression) -> Self {
+        #[cfg(feature = "kv")]
+        {
+            self.kv = self.kv.with_compression(compression);
+        }
+        self.inner = self.inner.with_compression(compression);
+        self
     }
 
     /// Limits the maximum size of a decoded message.
     ///
     /// Default: `4MB`
     pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
-        self.inner = self.inner.max_decoding_message_size(limit);
+        self.inner = self
+            .inner
+            .map(|inner| inner.max_decoding_message_size(limit));
         self
     }
 
@@ -57,28 +135,75 @@ impl WatchClient {
         key: impl Into<Vec<u8>>,
         options: Option<WatchOptions>,
     ) -> Result<(Watcher, WatchStream)> {
+        let (watcher, stream, _) = self.create_watch(key, options).await?;
+        Ok((watcher, stream))
+    }
+
+    /// Watches like [`WatchClient::watch`], also returning the revision the watch was created
+    /// at.
+    pub(crate) async fn create_watch(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<WatchOptions>,
+    ) -> Result<(Watcher, WatchStream, i64)> {
+        let mut options = options.unwrap_or_default().with_key(key);
+        let call = std::mem::take(&mut options.call);
+        let (observer, key) = (self.observer.clone(), options.req.key.clone());
+        let request: WatchRequest = options.into();
+        let inner = self.inner.clone();
+        let (watcher, mut stream, revision) = call
+            .run(
+                "Watch",
+                self.create_timeout,
+                retry(self.retry.as_ref(), 0, "Watch", true, move || {
+                    let call = observe_call!(observer, "Watch", key = &key);
+                    Self::create(inner.clone(), request.clone(), call)
+                }),
+            )
+            .await?;
+        if let Some(token) = call.cancel() {
+            stream.cancel_on(token.clone());
+        }
+        Ok((watcher, stream, revision))
+    }
+
+    /// Opens a watch stream and creates the watch with `request` on it, returning the
+    /// revision of the response creating it.
+    async fn create(
+        mut inner: Compressing<PbWatchClient<AuthService<InterceptedChannel>>>,
+        request: WatchRequest,
+        call: Call,
+    ) -> Result<(Watcher, WatchStream, i64)> {
         let (request_sender, request_receiver) = channel::<WatchRequest>(100);
         let request_stream = ReceiverStream::new(request_receiver);
 
         request_sender
-            .send(options.unwrap_or_default().with_key(key).into())
+            .send(request)
             .await
             .map_err(|e| Error::WatchError(e.to_string()))?;
 
-        let response_stream = self.inner.watch(request_stream).await?.into_inner();
-        let mut watch_stream = WatchStream::new(response_stream);
+        let response_stream = observed!(call = call.clone(), inner.watch(request_stream))
+            .await
+            .for_rpc("Watch")?
+            .into_inner();
+        let mut watch_stream = WatchStream::new(response_stream, call);
 
-        let watch_id = match watch_stream.message().await? {
+        let (watch_id, revision) = match watch_stream.message().await? {
             Some(resp) => {
                 assert!(resp.created(), "not a create watch response");
-                resp.watch_id()
+                let revision = resp.header().map_or(0, |header| header.revision().get());
+                (resp.watch_id(), revision)
             }
             None => {
                 return Err(Error::WatchError("failed to create watch".to_string()));
             }
         };
 
-        Ok((Watcher::new(watch_id, request_sender), watch_stream))
+        Ok((
+            Watcher::new(watch_id, request_sender),
+            watch_stream,
+            revision,
+        ))
     }
 }
 
@@ -87,16 +212,27 @@ impl WatchClient {
 pub struct WatchOptions {
     req: WatchCreateRequest,
     key_range: KeyRange,
+    call: CallOptions,
 }
 
 impl WatchOptions {
     /// Sets key.
     #[inline]
-    fn with_key(mut self, key: impl Into<Vec<u8>>) -> Self {
+    pub(crate) fn with_key(mut self, key: impl Into<Vec<u8>>) -> Self {
         self.key_range.with_key(key);
         self
     }
 
+    /// Watches the range of `options`, unless a range of its own is set.
+    #[cfg(feature = "kv")]
+    #[inline]
+    pub(crate) fn or_range_of(mut self, options: &GetOptions) -> Self {
+        if self.key_range.is_single_key() {
+            self.key_range = options.key_range().clone();
+        }
+        self
+    }
+
     /// Creates a new `WatchOptions`.
     #[inline]
     pub const fn new() -> Self {
@@ -112,9 +248,41 @@ impl WatchOptions {
                 fragment: false,
             },
             key_range: KeyRange::new(),
+            call: CallOptions::new(),
         }
     }
 
+    /// Aborts creating the watch if it has not been created by `deadline`, including its
+    /// retries. The created watch is not affected.
+    #[inline]
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.call.set_deadline(deadline);
+        self
+    }
+
+    /// Aborts creating the watch when `token` is cancelled, and closes the watch stream
+    /// if it has been created.
+    #[inline]
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.call.set_cancel(token);
+        self
+    }
+
+    /// Takes the token set by [`WatchOptions::with_cancel`] out of the options.
+    #[inline]
+    pub(crate) fn take_cancel(&mut self) -> Option<CancellationToken> {
+        self.call.take_cancel()
+    }
+
+    /// Sends the metadata `key: value` with the request opening the watch stream, replacing
+    /// the metadata of the client under the same key, see
+    /// [`ConnectOptions::with_metadata`](crate::ConnectOptions::with_metadata).
+    #[inline]
+    pub fn with_metadata(mut self, key: &str, value: impl AsRef<[u8]>) -> Result<Self> {
+        self.call.append_metadata(key, value.as_ref())?;
+        Ok(self)
+    }
+
     /// Sets the end of the range [key, end) to watch. If `end` is not given,
     /// only the key argument is watched. If `end` is equal to '\0', all keys greater than
     /// or equal to the key argument are watched.
@@ -147,8 +315,8 @@ impl WatchOptions {
 
     /// Sets the revision to watch from (inclusive). No `start_revision` is "now".
     #[inline]
-    pub const fn with_start_revision(mut self, revision: i64) -> Self {
-        self.req.start_revision = revision;
+    pub fn with_start_revision(mut self, revision: impl Into<Revision>) -> Self {
+        self.req.start_revision = revision.into().get();
         self
     }
 
@@ -246,6 +414,11 @@ pub enum WatchFilterType {
 /// Response for `Watch` operation.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct WatchResponse(PbWatchResponse);
 
@@ -299,8 +472,8 @@ impl WatchResponse {
     /// The client should treat the watcher as canceled and should not try to create any
     /// watcher with the same start_revision again.
     #[inline]
-    pub const fn compact_revision(&self) -> i64 {
-        self.0.compact_revision
+    pub const fn compact_revision(&self) -> Revision {
+        Revision::new(self.0.compact_revision)
     }
 
     /// Indicates the reason for canceling the watcher.
@@ -314,11 +487,33 @@ impl WatchResponse {
     pub fn events(&self) -> &[Event] {
         unsafe { &*(self.0.events.as_slice() as *const _ as *const [Event]) }
     }
+
+    /// Calls `f` on the key-value pairs of the events, and on their previous ones, up to
+    /// the first error.
+    #[cfg(feature = "kv")]
+    pub(crate) fn try_for_each_kv(
+        &mut self,
+        f: &mut impl FnMut(&mut PbKeyValue) -> Result<()>,
+    ) -> Result<()> {
+        for event in self.0.events.iter_mut() {
+            event
+                .kv
+                .iter_mut()
+                .chain(event.prev_kv.iter_mut())
+                .try_for_each(&mut *f)?;
+        }
+        Ok(())
+    }
 }
 
 /// Watching event.
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 #[repr(transparent)]
 pub struct Event(PbEvent);
 
@@ -421,40 +616,101 @@ impl Watcher {
 }
 
 /// The watch response stream.
+///
+/// The stream is a [`Stream`] of the responses, to compose with the adapters of
+/// `tokio_stream` or `futures`, e.g. to merge the streams of several watches:
+///
+/// ```no_run
+/// use etcd_client::{Client, Error};
+/// use tokio_stream::{StreamExt, StreamMap};
+///
+/// # async fn watch(client: &mut Client) -> Result<(), Error> {
+/// let (mut watchers, mut streams) = (Vec::new(), StreamMap::new());
+/// for key in ["a", "b", "c"] {
+///     let (watcher, stream) = client.watch(key, None).await?;
+///     watchers.push(watcher);
+///     streams.insert(key, stream);
+/// }
+/// while let Some((key, resp)) = streams.next().await {
+///     println!("{}: {} events", key, resp?.events().len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
 #[cfg_attr(feature = "pub-response-field", visible::StructFields(pub))]
 #[derive(Debug)]
 pub struct WatchStream {
-    stream: Streaming<PbWatchResponse>,
+    stream: Option<Streaming<PbWatchResponse>>,
+    cancel: Option<Pin<Box<WaitForCancellationFutureOwned>>>,
+    /// The observed Watch RPC, which every message is reported to.
+    call: Call,
 }
 
 impl WatchStream {
     /// Creates a new `WatchStream`.
     #[inline]
-    const fn new(stream: Streaming<PbWatchResponse>) -> Self {
-        Self { stream }
+    fn new(stream: Streaming<PbWatchResponse>, call: Call) -> Self {
+        Self {
+            stream: Some(stream),
+            cancel: None,
+            call,
+        }
+    }
+
+    /// Closes the stream when `token` is cancelled.
+    #[inline]
+    fn cancel_on(&mut self, token: CancellationToken) {
+        self.cancel = Some(Box::pin(token.cancelled_owned()));
     }
 
     /// Fetch the next message from this stream.
+    ///
+    /// The future is cancel safe: dropped before it completes, e.g. by a branch of
+    /// `tokio::select!` completing first, it loses no event, the next call fetching it.
     #[inline]
     pub async fn message(&mut self) -> Result<Option<WatchResponse>> {
-        match self.stream.message().await? {
-            Some(resp) => Ok(Some(WatchResponse::new(resp))),
-            None => Ok(None),
-        }
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+            .await
+            .transpose()
     }
 }
 
+/// A message is only taken out of the underlying stream by the poll returning it, so a
+/// pending poll abandoned loses nothing.
 impl Stream for WatchStream {
     type Item = Result<WatchResponse>;
 
     #[inline]
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.get_mut().stream)
-            .poll_next(cx)
-            .map(|t| match t {
-                Some(Ok(resp)) => Some(Ok(WatchResponse::new(resp))),
-                Some(Err(e)) => Some(Err(From::from(e))),
-                None => None,
-            })
+        let this = self.get_mut();
+        if let Some(cancel) = &mut this.cancel {
+            if cancel.as_mut().poll(cx).is_ready() {
+                // Dropping the stream resets it, which cancels the watch on the server.
+                this.stream = None;
+                this.cancel = None;
+                return Poll::Ready(Some(Err(Error::Cancelled { rpc: "Watch" })));
+            }
+        }
+        let Some(stream) = &mut this.stream else {
+            return Poll::Ready(None);
+        };
+        Pin::new(stream).poll_next(cx).map(|t| match t {
+            Some(Ok(resp)) => {
+                this.call.watch_events(resp.events.len());
+                stream_event!(
+                    this.call,
+                    watch_id = resp.watch_id,
+                    revision = resp.header.as_ref().map(|header| header.revision),
+                    events = resp.events.len(),
+                    canceled = resp.canceled,
+                    "watch response"
+                );
+                Some(Ok(WatchResponse::new(resp)))
+            }
+            Some(Err(e)) => Some(Err(Error::from(e).with_rpc("Watch"))),
+            None => None,
+        })
     }
 }
+
+impl_raw_conversions!(WatchResponse(PbWatchResponse), Event(PbEvent),);
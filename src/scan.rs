@@ -0,0 +1,772 @@
+//! Scanning the keys under a prefix by concurrent paginated scans of its sub-ranges.
+
+use crate::error::{Error, Result};
+use crate::ids::Revision;
+use crate::lock::MutexExt;
+use crate::rpc::get_prefix;
+use crate::rpc::kv::{GetOptions, KvClient};
+use crate::rpc::KeyValue;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// The name of the tasks scanning the shards.
+const SCAN_TASK: &str = "scan shard";
+
+/// The number of concurrent scans of a parallel scan, by default.
+pub const DEFAULT_SCAN_SHARDS: usize = 4;
+
+/// The number of keys per page of a parallel scan, by default.
+pub const DEFAULT_SCAN_BATCH_SIZE: i64 = 1000;
+
+/// The number of pages a shard of a parallel scan reads ahead of the stream, by default.
+pub const DEFAULT_SCAN_PREFETCH: usize = 16;
+
+/// The number of counts searching each boundary between shards.
+const MAX_PROBES: usize = 32;
+
+/// How the key-values of a parallel scan are ordered, see
+/// [`ParallelScanOptions::with_order`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScanOrder {
+    /// The pages are returned by ascending keys, the later shards reading ahead while the
+    /// earlier ones are returned, up to [`ParallelScanOptions::with_prefetch`] pages each.
+    #[default]
+    KeyOrdered,
+    /// The pages are returned as the shards read them, each shard's by ascending keys.
+    Interleaved,
+}
+
+/// Options for [`KvClient::get_stream_parallel`].
+#[derive(Debug, Clone)]
+pub struct ParallelScanOptions {
+    shards: usize,
+    batch_size: i64,
+    revision: i64,
+    order: ScanOrder,
+    prefetch: usize,
+    low_priority: bool,
+}
+
+impl Default for ParallelScanOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParallelScanOptions {
+    /// Creates a `ParallelScanOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            shards: DEFAULT_SCAN_SHARDS,
+            batch_size: DEFAULT_SCAN_BATCH_SIZE,
+            revision: 0,
+            order: ScanOrder::KeyOrdered,
+            prefetch: DEFAULT_SCAN_PREFETCH,
+            low_priority: false,
+        }
+    }
+
+    /// Sets the number of sub-ranges scanned concurrently.
+    #[inline]
+    pub const fn with_shards(mut self, shards: usize) -> Self {
+        self.shards = shards;
+        self
+    }
+
+    /// Sets the number of keys per page.
+    #[inline]
+    pub const fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Scans the keys as of `revision`, instead of the revision of the first request.
+    #[inline]
+    pub fn with_revision(mut self, revision: impl Into<Revision>) -> Self {
+        self.revision = revision.into().get();
+        self
+    }
+
+    /// Sets the order of the key-values, [`ScanOrder::KeyOrdered`] by default.
+    #[inline]
+    pub const fn with_order(mut self, order: ScanOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Sets the number of pages every shard reads ahead of the stream, bounding the memory
+    /// of the scan. The key-ordered shards after the one being returned are paused once
+    /// as many pages are buffered.
+    #[inline]
+    pub const fn with_prefetch(mut self, pages: usize) -> Self {
+        self.prefetch = pages;
+        self
+    }
+
+    /// Sends the requests of the scan over the bulk lane, see
+    /// [`GetOptions::with_low_priority`].
+    #[inline]
+    pub const fn with_low_priority(mut self) -> Self {
+        self.low_priority = true;
+        self
+    }
+}
+
+/// The pages of key-values of a parallel scan, see [`KvClient::get_stream_parallel`].
+///
+/// Dropping the stream stops the scans.
+pub struct ScanStream {
+    revision: i64,
+    order: ScanOrder,
+    /// The pages of the shards, by ascending keys, the ones of the shards exhausted first
+    /// popped out if key-ordered, a single channel shared by the shards if interleaved.
+    pages: Vec<mpsc::Receiver<Option<Vec<KeyValue>>>>,
+    /// The number of shards not exhausted yet.
+    pending: usize,
+    abort: Arc<Abort>,
+    shards: JoinSet<()>,
+}
+
+impl ScanStream {
+    /// The revision the keys are scanned at.
+    #[inline]
+    pub const fn revision(&self) -> Revision {
+        Revision::new(self.revision)
+    }
+
+    /// Fetches the next page of key-values, `None` once all the keys have been.
+    ///
+    /// Fails with the error of the first shard failing, which aborts all the others, e.g.
+    /// an [`Error::Compacted`] if the revision is compacted meanwhile.
+    pub async fn message(&mut self) -> Result<Option<Vec<KeyValue>>> {
+        while self.pending > 0 {
+            let Some(pages) = self.pages.first_mut() else {
+                break;
+            };
+            let page = tokio::select! {
+                biased;
+                _ = self.abort.token.cancelled() => return Err(self.abort.error()),
+                page = pages.recv() => page,
+            };
+            match page {
+                Some(Some(kvs)) => return Ok(Some(kvs)),
+                // The shard is exhausted.
+                Some(None) => {
+                    self.pending -= 1;
+                    if self.order == ScanOrder::KeyOrdered {
+                        self.pages.remove(0);
+                    }
+                }
+                // A shard stopped without being exhausted nor failing.
+                None => {
+                    self.check_shards().await;
+                    return Err(Error::InternalTaskFailed {
+                        task: SCAN_TASK,
+                        panic_message: String::from("scan shard stopped"),
+                    });
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Resumes the panic of a shard.
+    async fn check_shards(&mut self) {
+        while let Some(joined) = self.shards.join_next().await {
+            if let Err(e) = joined {
+                if e.is_panic() {
+                    std::panic::resume_unwind(e.into_panic());
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ScanStream {
+    #[inline]
+    fn drop(&mut self) {
+        self.abort.token.cancel();
+    }
+}
+
+/// The first error of the shards, which aborts them all.
+#[derive(Default)]
+struct Abort {
+    token: CancellationToken,
+    error: Mutex<Option<Error>>,
+}
+
+impl Abort {
+    /// Aborts the shards because of `err`, unless aborted already.
+    fn fail(&self, err: Error) {
+        let mut error = self.error.lock_unpoisoned();
+        if error.is_none() {
+            *error = Some(err);
+        }
+        self.token.cancel();
+    }
+
+    /// Takes the error the shards are aborted with.
+    fn error(&self) -> Error {
+        self.error
+            .lock_unpoisoned()
+            .take()
+            .unwrap_or(Error::Cancelled { rpc: "Range" })
+    }
+}
+
+impl KvClient {
+    /// Scans the keys under `prefix` as of a single revision, by concurrent paginated scans
+    /// of disjoint sub-ranges.
+    ///
+    /// The boundaries of the sub-ranges are searched first, concurrently, by counting the
+    /// keys before candidate keys, so the shards have about as many keys each. A single
+    /// sequential scan is made of a prefix with no more keys than a page.
+    pub async fn get_stream_parallel(
+        &mut self,
+        prefix: impl Into<Vec<u8>>,
+        options: Option<ParallelScanOptions>,
+    ) -> Result<ScanStream> {
+        let options = options.unwrap_or_default();
+        if options.shards == 0 || options.batch_size <= 0 || options.prefetch == 0 {
+            return Err(Error::InvalidArgs(String::from(
+                "a parallel scan needs shards and pages of keys",
+            )));
+        }
+        let mut kv = match options.low_priority {
+            true => self.over_bulk_lane(),
+            false => self.clone(),
+        };
+        let mut prefix = prefix.into();
+        let end = get_prefix(&prefix);
+        // The empty prefix, of all the keys, starts at the least key.
+        if prefix.is_empty() {
+            prefix.push(0);
+        }
+
+        let count = GetOptions::new()
+            .with_range(end.clone())
+            .with_revision(options.revision)
+            .with_count_only();
+        // The requests are boxed, keeping the futures of the scan and of its shards small.
+        let resp = Box::pin(kv.get(prefix.clone(), Some(count))).await?;
+        let revision = match options.revision {
+            0 => resp.header().map_or(0, |header| header.revision().get()),
+            revision => revision,
+        };
+        let ranges = kv
+            .split(
+                Range {
+                    start: prefix,
+                    end,
+                    count: resp.count(),
+                },
+                revision,
+                options
+                    .shards
+                    .min((resp.count() / options.batch_size) as usize + 1),
+            )
+            .await?;
+
+        let abort = Arc::new(Abort::default());
+        let mut shards = JoinSet::new();
+        let mut pages = Vec::new();
+        let interleaved = match options.order {
+            ScanOrder::Interleaved => {
+                let (tx, rx) = mpsc::channel(options.prefetch * ranges.len());
+                pages.push(rx);
+                Some(tx)
+            }
+            ScanOrder::KeyOrdered => None,
+        };
+        let pending = ranges.len();
+        for range in ranges {
+            let tx = match &interleaved {
+                Some(tx) => tx.clone(),
+                None => {
+                    let (tx, rx) = mpsc::channel(options.prefetch);
+                    pages.push(rx);
+                    tx
+                }
+            };
+            let shard = Shard {
+                kv: kv.clone(),
+                range,
+                revision,
+                batch_size: options.batch_size,
+                tx,
+            };
+            let abort = abort.clone();
+            shards.spawn(async move {
+                let result = tokio::select! {
+                    _ = abort.token.cancelled() => Ok(()),
+                    result = shard.scan() => result,
+                };
+                if let Err(e) = result {
+                    abort.fail(e);
+                }
+            });
+        }
+        Ok(ScanStream {
+            revision,
+            order: options.order,
+            pages,
+            pending,
+            abort,
+            shards,
+        })
+    }
+
+    /// Splits `range` into `parts` sub-ranges of about as many keys as of `revision`, their
+    /// boundaries searched concurrently.
+    async fn split(&self, range: Range, revision: i64, parts: usize) -> Result<Vec<Range>> {
+        if parts <= 1 || range.count <= 1 {
+            return Ok(vec![range]);
+        }
+        let range = Arc::new(range);
+        let mut searches = JoinSet::new();
+        for part in 1..parts {
+            let target = range.count * part as i64 / parts as i64;
+            let kv = self.clone();
+            let range = range.clone();
+            searches.spawn(async move { kv.boundary(&range, revision, target).await });
+        }
+        let mut boundaries = Vec::with_capacity(parts - 1);
+        while let Some(joined) = searches.join_next().await {
+            match joined {
+                Ok(boundary) => boundaries.extend(boundary?),
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(e) => {
+                    return Err(Error::InternalTaskFailed {
+                        task: SCAN_TASK,
+                        panic_message: e.to_string(),
+                    })
+                }
+            }
+        }
+        boundaries.sort();
+        boundaries.dedup();
+
+        let mut ranges = Vec::with_capacity(boundaries.len() + 1);
+        let (mut start, mut below) = (range.start.clone(), 0);
+        for (key, count) in boundaries {
+            ranges.push(Range {
+                start,
+                end: key.clone(),
+                count: count - below,
+            });
+            (start, below) = (key, count);
+        }
+        ranges.push(Range {
+            start,
+            end: range.end.clone(),
+            count: range.count - below,
+        });
+        Ok(ranges)
+    }
+
+    /// Searches a key of `range` with about `target` of its keys as of `revision` before
+    /// it, returning it with their number, or `None` if no key splits the range.
+    ///
+    /// The key is found by growing the prefix of the key with `target` keys before it: by
+    /// the bytes it shares with the first key after the prefix, found by bisecting their
+    /// number, then by its next byte, found by bisection. Keys sharing long prefixes thus
+    /// take a few probes, not one per bit of the prefix.
+    async fn boundary(
+        mut self,
+        range: &Range,
+        revision: i64,
+        target: i64,
+    ) -> Result<Option<(Vec<u8>, i64)>> {
+        let tolerance = (target / 4).max(1);
+        let mut best = None;
+        // Every key of the range is prefixed by the common prefix of its bounds.
+        let mut prefix = match range.end.as_slice() {
+            [0] => Vec::new(),
+            end => common_prefix(&range.start, end).to_vec(),
+        };
+        let (mut count, mut first) = self.probe(range, &prefix, revision).await?;
+        let mut probes = 1;
+        while probes < MAX_PROBES {
+            if let Some(found) = closest(&mut best, &prefix, count, range, target, tolerance) {
+                return Ok(Some(found));
+            }
+            let Some(after) = first.take() else {
+                break;
+            };
+
+            // The target key shares `after[..shared]`, not `after[..unshared]`.
+            let (mut shared, mut unshared) = (prefix.len(), after.len() + 1);
+            while unshared - shared > 1 && probes < MAX_PROBES {
+                let mid = (shared + unshared) / 2;
+                let key = get_prefix(&after[..mid]);
+                let (count, _) = self.probe(range, &key, revision).await?;
+                probes += 1;
+                if let Some(found) = closest(&mut best, &key, count, range, target, tolerance) {
+                    return Ok(Some(found));
+                }
+                match count > target {
+                    true => shared = mid,
+                    false => unshared = mid,
+                }
+            }
+            if unshared - shared > 1 {
+                break;
+            }
+            prefix = after[..shared].to_vec();
+
+            // The next byte of the target key is after the one of the first key.
+            let mut low = after.get(shared).map_or(0, |&byte| u16::from(byte) + 1);
+            let mut high = 256;
+            let mut next = None;
+            while high - low > 1 && probes < MAX_PROBES {
+                let mid = (low + high) / 2;
+                let key = [prefix.as_slice(), &[mid as u8]].concat();
+                let probe = self.probe(range, &key, revision).await?;
+                probes += 1;
+                if let Some(found) = closest(&mut best, &key, probe.0, range, target, tolerance) {
+                    return Ok(Some(found));
+                }
+                match probe.0 <= target {
+                    true => (low, next) = (mid, Some(probe)),
+                    false => high = mid,
+                }
+            }
+            if high - low > 1 {
+                break;
+            }
+            prefix.push(low as u8);
+            (count, first) = match next {
+                Some(probe) => probe,
+                None => {
+                    probes += 1;
+                    self.probe(range, &prefix, revision).await?
+                }
+            };
+        }
+        Ok(best)
+    }
+
+    /// Counts the keys of `range` before `key` as of `revision`, returning them with the
+    /// first key at or after it.
+    async fn probe(
+        &mut self,
+        range: &Range,
+        key: &[u8],
+        revision: i64,
+    ) -> Result<(i64, Option<Vec<u8>>)> {
+        let key = key.max(range.start.as_slice());
+        let options = GetOptions::new()
+            .with_range(range.end.clone())
+            .with_revision(revision)
+            .with_limit(1)
+            .with_keys_only();
+        let resp = Box::pin(self.get(key, Some(options))).await?;
+        let first = resp.kvs().first().map(|kv| kv.key().to_vec());
+        Ok((range.count - resp.count(), first))
+    }
+}
+
+/// Keeps `key` in `best` if it splits `range` closer to `target` keys before it, returning
+/// it if within `tolerance` of `target`.
+fn closest(
+    best: &mut Option<(Vec<u8>, i64)>,
+    key: &[u8],
+    count: i64,
+    range: &Range,
+    target: i64,
+    tolerance: i64,
+) -> Option<(Vec<u8>, i64)> {
+    if count <= 0 || count >= range.count {
+        return None;
+    }
+    if best.as_ref().map_or(true, |(_, best)| {
+        (count - target).abs() < (best - target).abs()
+    }) {
+        *best = Some((key.to_vec(), count));
+    }
+    match (count - target).abs() <= tolerance {
+        true => best.clone(),
+        false => None,
+    }
+}
+
+/// The longest common prefix of `a` and `b`.
+fn common_prefix<'a>(a: &'a [u8], b: &[u8]) -> &'a [u8] {
+    let len = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    &a[..len]
+}
+
+/// A range of keys, with the number of keys in it.
+#[derive(Debug)]
+struct Range {
+    start: Vec<u8>,
+    /// The end of the range, excluded, `[0]` for the end of the key space.
+    end: Vec<u8>,
+    count: i64,
+}
+
+/// The scan of a sub-range of a parallel scan.
+struct Shard {
+    kv: KvClient,
+    range: Range,
+    revision: i64,
+    batch_size: i64,
+    tx: mpsc::Sender<Option<Vec<KeyValue>>>,
+}
+
+impl Shard {
+    /// Sends the pages of the range, then `None`, until the stream is dropped.
+    async fn scan(mut self) -> Result<()> {
+        let mut key = self.range.start.clone();
+        loop {
+            let options = GetOptions::new()
+                .with_range(self.range.end.clone())
+                .with_revision(self.revision)
+                .with_limit(self.batch_size);
+            let mut resp = Box::pin(self.kv.get(key.clone(), Some(options))).await?;
+            let more = resp.more();
+            let kvs = resp.take_kvs();
+            if let Some(last) = kvs.last() {
+                // The next page starts right after the last key.
+                key = [last.key(), b"\0"].concat();
+            }
+            if !kvs.is_empty() && self.tx.send(Some(kvs)).await.is_err() {
+                return Ok(());
+            }
+            if !more {
+                let _ = self.tx.send(None).await;
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::channel::Channel;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::rpc::pb::etcdserverpb::{
+        RangeRequest as PbRangeRequest, RangeResponse as PbRangeResponse,
+        ResponseHeader as PbResponseHeader,
+    };
+    use crate::rpc::pb::mvccpb::KeyValue as PbKeyValue;
+    use http_body::Frame;
+    use http_body_util::{BodyExt, StreamBody};
+    use prost::Message;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tonic::codegen::Bytes;
+    use tower::util::BoxCloneService;
+    use tower::ServiceExt;
+
+    /// The revision of the keys of [`scan_client`].
+    const REVISION: i64 = 10;
+
+    /// A gRPC response with the message `msg`, or with `status` if not ok.
+    fn grpc_response(
+        msg: &impl Message,
+        status: tonic::Status,
+    ) -> http::Response<tonic::body::Body> {
+        let mut buf = vec![0];
+        buf.extend_from_slice(&(msg.encoded_len() as u32).to_be_bytes());
+        msg.encode(&mut buf).unwrap();
+        let mut trailers = http::HeaderMap::new();
+        let ok = status.code() == tonic::Code::Ok;
+        status.add_header(&mut trailers).unwrap();
+        let mut frames = vec![Ok::<_, tower::BoxError>(Frame::data(Bytes::from(buf)))];
+        if !ok {
+            frames.clear();
+        }
+        frames.push(Ok(Frame::trailers(trailers)));
+        let body = tonic::body::Body::new(StreamBody::new(tokio_stream::iter(frames)));
+        http::Response::builder()
+            .header("content-type", "application/grpc")
+            .body(body)
+            .unwrap()
+    }
+
+    /// A client of a store of the `keys` at [`REVISION`], whose range requests fail as
+    /// compacted once `pages` pages have been read.
+    fn scan_client(keys: Vec<Vec<u8>>, pages: usize) -> KvClient {
+        let keys = Arc::new(keys);
+        let read = Arc::new(AtomicUsize::new(0));
+        let service = tower::service_fn(move |req: http::Request<tonic::body::Body>| {
+            let keys = keys.clone();
+            let read = read.clone();
+            async move {
+                let body = req.into_body().collect().await.unwrap().to_bytes();
+                let req = PbRangeRequest::decode(&body[5..]).unwrap();
+                // Only the first count is not at the revision of the scan.
+                assert!(req.revision == REVISION || req.count_only && req.revision == 0);
+                let matched: Vec<_> = keys
+                    .iter()
+                    .filter(|key| {
+                        key.as_slice() >= &req.key[..]
+                            && (&req.range_end[..] == b"\0" || key.as_slice() < &req.range_end[..])
+                    })
+                    .collect();
+                let mut msg = PbRangeResponse {
+                    header: Some(PbResponseHeader {
+                        revision: REVISION,
+                        ..Default::default()
+                    }),
+                    count: matched.len() as i64,
+                    ..Default::default()
+                };
+                if !req.count_only {
+                    // Only the pages count, not the keys probed for the boundaries of shards.
+                    if !req.keys_only && read.fetch_add(1, Ordering::SeqCst) >= pages {
+                        let status = tonic::Status::out_of_range(
+                            "etcdserver: mvcc: required revision has been compacted",
+                        );
+                        return Ok::<_, tower::BoxError>(grpc_response(&msg, status));
+                    }
+                    let limit = req.limit as usize;
+                    msg.more = matched.len() > limit;
+                    msg.kvs = matched
+                        .into_iter()
+                        .take(limit)
+                        .map(|key| PbKeyValue {
+                            key: key.clone(),
+                            ..Default::default()
+                        })
+                        .collect();
+                }
+                Ok(grpc_response(&msg, tonic::Status::ok("")))
+            }
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor::default(),
+        );
+        KvClient::new(channel, AuthToken::default())
+    }
+
+    /// The keys `prefix/0000` to `prefix/{n - 1}` and a key after them.
+    fn keys(prefix: &str, n: usize) -> Vec<Vec<u8>> {
+        let mut keys: Vec<_> = (0..n)
+            .map(|i| format!("{}/{:04}", prefix, i).into_bytes())
+            .collect();
+        keys.push(format!("{}0", prefix).into_bytes());
+        keys
+    }
+
+    /// Reads all the pages of `stream`.
+    async fn read_all(stream: &mut ScanStream) -> Result<Vec<Vec<KeyValue>>> {
+        let mut pages = Vec::new();
+        while let Some(page) = stream.message().await? {
+            pages.push(page);
+        }
+        Ok(pages)
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        assert_eq!(common_prefix(b"key/", b"key0"), b"key");
+        assert_eq!(common_prefix(b"key", b"key/a"), b"key");
+        assert_eq!(common_prefix(b"a", b"b"), b"");
+    }
+
+    #[tokio::test]
+    async fn test_get_stream_parallel() {
+        let mut client = scan_client(keys("key", 1000), usize::MAX);
+        let expected: Vec<_> = keys("key", 1000).into_iter().take(1000).collect();
+
+        for shards in [1, 3, 8] {
+            let options = ParallelScanOptions::new()
+                .with_shards(shards)
+                .with_batch_size(50)
+                .with_revision(REVISION);
+            let mut stream = client
+                .get_stream_parallel("key/", Some(options.clone()))
+                .await
+                .unwrap();
+            assert_eq!(stream.revision(), REVISION);
+            let pages = read_all(&mut stream).await.unwrap();
+            assert!(pages.iter().all(|page| page.len() <= 50));
+            let keys: Vec<_> = pages.iter().flatten().map(|kv| kv.key().to_vec()).collect();
+            assert_eq!(keys, expected, "{} shards", shards);
+
+            // Interleaved, the keys are the same ones in another order.
+            let options = options.with_order(ScanOrder::Interleaved);
+            let mut stream = client
+                .get_stream_parallel("key/", Some(options))
+                .await
+                .unwrap();
+            let mut keys: Vec<_> = read_all(&mut stream)
+                .await
+                .unwrap()
+                .iter()
+                .flatten()
+                .map(|kv| kv.key().to_vec())
+                .collect();
+            keys.sort();
+            assert_eq!(keys, expected, "{} shards interleaved", shards);
+        }
+
+        // The revision of the first request is pinned.
+        let mut stream = client.get_stream_parallel("key/", None).await.unwrap();
+        assert_eq!(stream.revision(), REVISION);
+        assert_eq!(read_all(&mut stream).await.unwrap().concat().len(), 1000);
+
+        let mut stream = client.get_stream_parallel("none/", None).await.unwrap();
+        assert!(stream.message().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_split() {
+        // Keys sharing long prefixes after the one scanned too.
+        for prefix in ["key", "key/0000000000"] {
+            let mut keys = keys(prefix, 1000);
+            keys.pop();
+            let client = scan_client(keys.clone(), usize::MAX);
+            let range = Range {
+                start: b"key/".to_vec(),
+                end: b"key0".to_vec(),
+                count: keys.iter().filter(|key| key.starts_with(b"key/")).count() as i64,
+            };
+            let ranges = client.split(range, REVISION, 4).await.unwrap();
+            // The sub-ranges are contiguous, of about as many keys each.
+            assert_eq!(ranges.len(), 4);
+            assert_eq!(ranges[0].start, b"key/");
+            assert_eq!(ranges[3].end, b"key0");
+            for pair in ranges.windows(2) {
+                assert_eq!(pair[0].end, pair[1].start);
+            }
+            assert_eq!(ranges.iter().map(|range| range.count).sum::<i64>(), 1000);
+            for range in &ranges {
+                assert!((190..=310).contains(&range.count), "{:?}", range);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_stream_parallel_compacted() {
+        for order in [ScanOrder::KeyOrdered, ScanOrder::Interleaved] {
+            let mut client = scan_client(keys("key", 1000), 5);
+            let options = ParallelScanOptions::new()
+                .with_shards(4)
+                .with_batch_size(50)
+                .with_order(order);
+            let mut stream = client
+                .get_stream_parallel("key/", Some(options))
+                .await
+                .unwrap();
+            let err = read_all(&mut stream).await.unwrap_err();
+            assert!(matches!(err, Error::Compacted { .. }), "{:?}", err);
+        }
+
+        let mut client = scan_client(Vec::new(), 0);
+        let options = ParallelScanOptions::new().with_shards(0);
+        assert!(matches!(
+            client.get_stream_parallel("key/", Some(options)).await,
+            Err(Error::InvalidArgs(_))
+        ));
+    }
+}
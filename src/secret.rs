@@ -0,0 +1,58 @@
+//! Secrets zeroed when dropped.
+
+use std::fmt::{self, Debug, Formatter};
+use zeroize::Zeroizing;
+
+/// A secret string, e.g. an auth token, zeroed in memory when dropped and redacted from
+/// `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    /// Creates a `Secret` holding `secret`.
+    #[inline]
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(Zeroizing::new(secret.into()))
+    }
+
+    /// The secret itself, to hand it over, e.g. to another process. Copies of it are not
+    /// zeroed.
+    #[inline]
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Secret {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+impl From<String> for Secret {
+    #[inline]
+    fn from(secret: String) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl From<&str> for Secret {
+    #[inline]
+    fn from(secret: &str) -> Self {
+        Self::new(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret() {
+        let secret = Secret::from("token.42");
+        assert_eq!(secret.expose(), "token.42");
+        assert_eq!(format!("{:?}", secret), "Secret(..)");
+        assert_eq!(secret.clone(), Secret::new(String::from("token.42")));
+    }
+}
@@ -0,0 +1,393 @@
+//! Lease-backed client session.
+//!
+//! A [`Session`] grants a lease and keeps it alive in the background for as long as the
+//! session lives. Higher level primitives such as guarded elections attach their keys to
+//! the session lease, so that they are released automatically if the process goes away.
+//!
+//! A session can be handed off to another process, e.g. the next generation of a service
+//! deployed without downtime, with its lease and the keys attached to it: the old process
+//! releases it as a [`SessionHandle`], the new one adopts it, see [`Session::adopt`].
+
+#[cfg(feature = "kv")]
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::ids::LeaseId;
+use crate::keep_alive::KeptAlive;
+#[cfg(feature = "kv")]
+use crate::rpc::kv::{Compare, CompareOp, PutOptions, Txn, TxnOp};
+use crate::rpc::lease::{LeaseClient, LeaseGrantOptions};
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use tokio::sync::watch;
+
+/// The default session TTL in seconds, the same as the Go client.
+pub const DEFAULT_SESSION_TTL: i64 = 60;
+
+/// Options for creating a [`Session`].
+#[derive(Debug, Clone)]
+pub struct SessionOptions {
+    ttl: i64,
+    lease: i64,
+}
+
+impl SessionOptions {
+    /// Creates a `SessionOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            ttl: DEFAULT_SESSION_TTL,
+            lease: 0,
+        }
+    }
+
+    /// Sets the lease TTL of the session in seconds.
+    #[inline]
+    pub const fn with_ttl(mut self, ttl: i64) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Uses an existing lease instead of granting a new one, the TTL of the session is then
+    /// the one the lease was granted with.
+    #[inline]
+    pub fn with_lease(mut self, lease: impl Into<LeaseId>) -> Self {
+        self.lease = lease.into().get();
+        self
+    }
+}
+
+impl Default for SessionOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A lease kept alive in the background.
+///
+/// The leases of all the sessions of a client are kept alive together, by a single task
+/// sending their keep-alives in batches, see
+/// [`ConnectOptions::with_lease_keep_alive_granularity`](crate::ConnectOptions::with_lease_keep_alive_granularity).
+///
+/// Dropping the session stops the keep alive, the lease then expires after its TTL.
+/// Use [`Session::close`] to revoke the lease immediately.
+pub struct Session {
+    lease: LeaseClient,
+    id: i64,
+    ttl: i64,
+    /// The number of times the session was adopted, see [`Session::adopt`].
+    generation: u64,
+    kept_alive: KeptAlive,
+}
+
+impl Session {
+    /// Creates a session, granting a new lease unless one is given in `options`.
+    ///
+    /// Fails with [`Error::LeaseNotFound`] or [`Error::LeaseExpired`] if the lease given
+    /// expired or was revoked.
+    pub async fn new(mut lease: LeaseClient, options: Option<SessionOptions>) -> Result<Self> {
+        let options = options.unwrap_or_default();
+        let (id, ttl) = if options.lease != 0 {
+            let id = options.lease;
+            // The lease is kept alive at the pace of the TTL it was granted with.
+            let resp = lease
+                .time_to_live(id, None)
+                .await
+                .map_err(|e| e.with_lease_id(id))?;
+            // Servers before 3.3 report an expired lease as a TTL of -1.
+            if resp.ttl() < 0 {
+                return Err(Error::LeaseExpired {
+                    id: LeaseId::new(id),
+                    status: None,
+                });
+            }
+            (id, resp.granted_ttl())
+        } else {
+            let resp = lease
+                .grant(options.ttl, Some(LeaseGrantOptions::new()))
+                .await?;
+            (resp.id().get(), resp.ttl())
+        };
+
+        let kept_alive = lease.keep_alive_shared(id, ttl).await?;
+        Ok(Self {
+            lease,
+            id,
+            ttl,
+            generation: 0,
+            kept_alive,
+        })
+    }
+
+    /// Adopts the session of `handle`, released by another process, keeping its lease alive
+    /// from now on.
+    ///
+    /// Fails with [`Error::LeaseNotFound`] if the lease expired or was revoked meanwhile, or
+    /// [`Error::LeaseExpired`] from servers before 3.3, and with [`Error::LeaseTooShort`] if
+    /// less than a third of its TTL is left, the time between two keep-alives: the lease
+    /// could expire before the first one.
+    ///
+    /// A lease must be kept alive by at most one process, or the keys attached to it outlive
+    /// the process which should own them. The old process is expected to release the session
+    /// with [`Session::release_without_revoke`] before the handle is adopted, which cannot be
+    /// verified. Adopting the same handle twice is detected though, by the fencing key
+    /// `etcd-client/session/<lease id in hex>`, attached to the lease, holding the number of
+    /// times the session was adopted: the second adoption fails with
+    /// [`Error::SessionAdopted`].
+    #[cfg(feature = "kv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "kv")))]
+    pub async fn adopt(client: &Client, handle: &SessionHandle) -> Result<Self> {
+        let id = handle.id;
+        let mut lease = client.lease_client();
+        let resp = lease
+            .time_to_live(id, None)
+            .await
+            .map_err(|e| e.with_lease_id(id))?;
+        // Servers before 3.3 report an expired lease as a TTL of -1.
+        if resp.ttl() < 0 {
+            return Err(Error::LeaseExpired {
+                id: LeaseId::new(id),
+                status: None,
+            });
+        }
+        let ttl = resp.granted_ttl();
+        let min = (ttl / 3).max(1);
+        if resp.ttl() < min {
+            return Err(Error::LeaseTooShort {
+                id: LeaseId::new(id),
+                ttl: resp.ttl(),
+                min,
+            });
+        }
+
+        let key = format!("etcd-client/session/{:x}", id);
+        let compare = match handle.generation {
+            0 => Compare::create_revision(key.as_str(), CompareOp::Equal, 0),
+            generation => Compare::value(key.as_str(), CompareOp::Equal, generation.to_string()),
+        };
+        let generation = handle.generation + 1;
+        let put = TxnOp::put(
+            key.as_str(),
+            generation.to_string(),
+            Some(PutOptions::new().with_lease(id)),
+        );
+        let txn = Txn::new().when([compare]).and_then([put]);
+        let resp = client
+            .kv_client()
+            .txn(txn)
+            .await
+            .map_err(|e| e.with_lease_id(id))?;
+        if !resp.succeeded() {
+            return Err(Error::SessionAdopted {
+                id: LeaseId::new(id),
+                generation: handle.generation,
+            });
+        }
+
+        let kept_alive = lease.keep_alive_shared(id, ttl).await?;
+        Ok(Self {
+            lease,
+            id,
+            ttl,
+            generation,
+            kept_alive,
+        })
+    }
+
+    /// The handle of the session, for another process to adopt it with [`Session::adopt`].
+    ///
+    /// The session is still kept alive, see [`Session::release_without_revoke`] to hand it
+    /// off.
+    #[inline]
+    pub const fn export(&self) -> SessionHandle {
+        SessionHandle {
+            id: self.id,
+            ttl: self.ttl,
+            generation: self.generation,
+        }
+    }
+
+    /// Stops the keep alive without revoking the lease, and returns the handle of the
+    /// session for another process to adopt it before the lease expires.
+    ///
+    /// Like dropping the session, but it makes the hand-off explicit, and the handle is the
+    /// one of the session as it is released.
+    #[inline]
+    pub fn release_without_revoke(self) -> SessionHandle {
+        self.export()
+    }
+
+    /// The lease ID of the session.
+    #[inline]
+    pub const fn lease_id(&self) -> LeaseId {
+        LeaseId::new(self.id)
+    }
+
+    /// The lease TTL of the session in seconds.
+    #[inline]
+    pub const fn ttl(&self) -> i64 {
+        self.ttl
+    }
+
+    /// Returns `true` if the lease is no longer being kept alive.
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.kept_alive.is_done()
+    }
+
+    /// Returns [`Error::InternalTaskFailed`](crate::Error::InternalTaskFailed) if the keep
+    /// alive task panicked.
+    #[inline]
+    pub(crate) fn check(&self) -> Result<()> {
+        self.kept_alive.check()
+    }
+
+    /// Resolves once the lease is no longer being kept alive, e.g. the lease expired,
+    /// was revoked, the keep alive stream broke, or the keep alive task panicked.
+    pub fn done(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut done = self.kept_alive.subscribe();
+        async move {
+            // An error means the keeper has been stopped, which is done as well.
+            let _ = done.wait_for(|done| *done).await;
+        }
+    }
+
+    /// Subscribes to the done state of the session.
+    #[inline]
+    pub(crate) fn subscribe(&self) -> watch::Receiver<bool> {
+        self.kept_alive.subscribe()
+    }
+
+    /// The number of times the session was adopted, 0 for a session created by this process.
+    #[inline]
+    pub const fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Stops the keep alive and revokes the session lease, deleting all the keys attached to it.
+    pub async fn close(self) -> Result<()> {
+        let Self {
+            mut lease,
+            id,
+            kept_alive,
+            ..
+        } = self;
+        drop(kept_alive);
+        lease.revoke(id).await?;
+        Ok(())
+    }
+}
+
+impl Debug for Session {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("id", &self.id)
+            .field("ttl", &self.ttl)
+            .field("generation", &self.generation)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The version of the encoding of [`SessionHandle`].
+const HANDLE_VERSION: u8 = 1;
+
+/// The length of an encoded [`SessionHandle`]: the version, then the lease ID, the TTL and
+/// the generation, big-endian.
+const HANDLE_LEN: usize = 1 + 8 + 8 + 8;
+
+/// The handle of a session handed off to another process, see [`Session::export`] and
+/// [`Session::adopt`].
+///
+/// The handle is passed along as bytes, e.g. through a file or the environment of the new
+/// process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionHandle {
+    id: i64,
+    ttl: i64,
+    generation: u64,
+}
+
+impl SessionHandle {
+    /// The lease ID of the session.
+    #[inline]
+    pub const fn lease_id(&self) -> LeaseId {
+        LeaseId::new(self.id)
+    }
+
+    /// The lease TTL of the session in seconds.
+    #[inline]
+    pub const fn ttl(&self) -> i64 {
+        self.ttl
+    }
+
+    /// The number of times the session was adopted before it was exported.
+    #[inline]
+    pub const fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Encodes the handle.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HANDLE_LEN);
+        bytes.push(HANDLE_VERSION);
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        bytes.extend_from_slice(&self.ttl.to_be_bytes());
+        bytes.extend_from_slice(&self.generation.to_be_bytes());
+        bytes
+    }
+
+    /// Decodes a handle encoded by [`SessionHandle::to_bytes`].
+    ///
+    /// Fails with [`Error::InvalidArgs`] if `bytes` are not a handle of this version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let field = |i: usize| {
+            let start = 1 + 8 * i;
+            <[u8; 8]>::try_from(&bytes[start..start + 8]).unwrap()
+        };
+        match bytes {
+            [HANDLE_VERSION, ..] if bytes.len() == HANDLE_LEN => Ok(Self {
+                id: i64::from_be_bytes(field(0)),
+                ttl: i64::from_be_bytes(field(1)),
+                generation: u64::from_be_bytes(field(2)),
+            }),
+            [HANDLE_VERSION, ..] | [] => Err(Error::InvalidArgs(format!(
+                "invalid session handle of {} bytes",
+                bytes.len()
+            ))),
+            [version, ..] => Err(Error::InvalidArgs(format!(
+                "unsupported session handle version {}",
+                version
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_bytes() {
+        let handle = SessionHandle {
+            id: 0x694d77aa9e38260f,
+            ttl: 60,
+            generation: 2,
+        };
+        let bytes = handle.to_bytes();
+        assert_eq!(bytes.len(), HANDLE_LEN);
+        assert_eq!(SessionHandle::from_bytes(&bytes).unwrap(), handle);
+
+        let err = SessionHandle::from_bytes(&bytes[..HANDLE_LEN - 1]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid arguments: invalid session handle of 24 bytes"
+        );
+        let mut other = bytes;
+        other[0] = HANDLE_VERSION + 1;
+        let err = SessionHandle::from_bytes(&other).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid arguments: unsupported session handle version 2"
+        );
+    }
+}
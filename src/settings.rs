@@ -0,0 +1,156 @@
+//! Settings shared by the environment variables and the config files, in the formats of
+//! `etcdctl` and of the Go client.
+
+#[cfg(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl"))]
+use crate::ConnectOptions;
+#[cfg(feature = "config")]
+use std::fmt::Write;
+use std::time::Duration;
+
+/// The problem of TLS settings without a TLS implementation to use them.
+#[cfg(not(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl")))]
+pub(crate) const TLS_REQUIRED: &str =
+    "requires the `tls-ring`, `tls-aws-lc` or `tls-openssl` feature";
+
+/// Parses a duration in the format of Go, i.e. of `etcdctl`, a sequence of decimal numbers
+/// each followed by a unit, e.g. `5s`, `1.5s`, `1m30s` or `300ms`.
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    if s == "0" {
+        return Some(Duration::ZERO);
+    }
+    if s.is_empty() {
+        return None;
+    }
+
+    let mut secs = 0.0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let is_number = |c: char| c.is_ascii_digit() || c == '.';
+        let (number, tail) = rest.split_at(rest.find(|c| !is_number(c)).unwrap_or(rest.len()));
+        let (unit, tail) = tail.split_at(tail.find(is_number).unwrap_or(tail.len()));
+        let number: f64 = number.parse().ok()?;
+        let scale = match unit {
+            "ns" => 1e-9,
+            "us" | "µs" | "μs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return None,
+        };
+        secs += number * scale;
+        rest = tail;
+    }
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+/// Formats `duration` as Go does, e.g. `1h0m0s`, `1m30s`, `1.5s` or `300ms`, which
+/// [`parse_duration`] parses back.
+#[cfg(feature = "config")]
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let nanos = duration.subsec_nanos();
+    if secs == 0 {
+        return match nanos {
+            0 => String::from("0s"),
+            _ if nanos % 1_000_000 == 0 => format!("{}ms", nanos / 1_000_000),
+            _ if nanos % 1_000 == 0 => format!("{}us", nanos / 1_000),
+            _ => format!("{}ns", nanos),
+        };
+    }
+
+    let mut formatted = String::new();
+    let (hours, minutes) = (secs / 3600, secs / 60 % 60);
+    // Writing to a string never fails.
+    if hours > 0 {
+        let _ = write!(formatted, "{}h", hours);
+    }
+    if hours > 0 || minutes > 0 {
+        let _ = write!(formatted, "{}m", minutes);
+    }
+    let _ = write!(formatted, "{}", secs % 60);
+    if nanos > 0 {
+        let fraction = format!("{:09}", nanos);
+        let _ = write!(formatted, ".{}", fraction.trim_end_matches('0'));
+    }
+    formatted.push('s');
+    formatted
+}
+
+/// Sets the TLS options of `options` to trust the PEM encoded CA certificate `ca` and to
+/// authenticate with the PEM encoded certificate and private key `identity`.
+///
+/// Without a CA certificate the system roots are trusted, if the `tls-roots` feature is on.
+#[cfg(any(feature = "tls-ring", feature = "tls-aws-lc", feature = "tls-openssl"))]
+pub(crate) fn with_tls_pem(
+    options: ConnectOptions,
+    ca: Option<Vec<u8>>,
+    identity: Option<(Vec<u8>, Vec<u8>)>,
+) -> ConnectOptions {
+    #[cfg(feature = "tls-openssl")]
+    {
+        let (cert, key) = identity.unwrap_or_default();
+        let tls = crate::OpenSslClientConfig::default()
+            .ca_cert_pem(&ca.unwrap_or_default())
+            .client_cert_pem_and_key(&cert, &key);
+        options.with_openssl_tls(tls)
+    }
+    #[cfg(not(feature = "tls-openssl"))]
+    {
+        let mut tls = crate::TlsOptions::new();
+        match ca {
+            Some(ca) => tls = tls.with_ca_certificate(ca),
+            #[cfg(feature = "tls-roots")]
+            None => tls = tls.with_native_roots(),
+            #[cfg(not(feature = "tls-roots"))]
+            None => {}
+        }
+        if let Some((cert, key)) = identity {
+            tls = tls.identity(crate::Identity::from_pem(cert, key));
+        }
+        options.with_tls(tls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        let ms = Duration::from_millis;
+        assert_eq!(parse_duration("0"), Some(Duration::ZERO));
+        assert_eq!(parse_duration("5s"), Some(ms(5000)));
+        assert_eq!(parse_duration("1.5s"), Some(ms(1500)));
+        assert_eq!(parse_duration("300ms"), Some(ms(300)));
+        assert_eq!(parse_duration("1m30s"), Some(ms(90_000)));
+        assert_eq!(parse_duration("2h"), Some(ms(7_200_000)));
+        assert_eq!(parse_duration("10us"), Some(Duration::from_micros(10)));
+        assert_eq!(parse_duration("10µs"), Some(Duration::from_micros(10)));
+        assert_eq!(parse_duration("1500ns"), Some(Duration::from_nanos(1500)));
+
+        for invalid in ["", "5", "s", "-5s", "5 s", "1.2.3s", "5sec", ".s"] {
+            assert_eq!(parse_duration(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_format_duration() {
+        for (duration, formatted) in [
+            (Duration::ZERO, "0s"),
+            (Duration::from_nanos(1500), "1500ns"),
+            (Duration::from_micros(10), "10us"),
+            (Duration::from_millis(300), "300ms"),
+            (Duration::from_millis(1500), "1.5s"),
+            (Duration::from_secs(90), "1m30s"),
+            (Duration::from_secs(3600), "1h0m0s"),
+            (Duration::new(3661, 1), "1h1m1.000000001s"),
+        ] {
+            assert_eq!(format_duration(duration), formatted);
+            if duration.subsec_nanos() % 1000 == 0 {
+                assert_eq!(parse_duration(formatted), Some(duration));
+            }
+        }
+    }
+}
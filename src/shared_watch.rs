@@ -0,0 +1,428 @@
+//! Watches shared by the subscribers of the same keys, see [`WatchClient::shared_subscribe`].
+
+use crate::error::{Error, Result};
+use crate::ids::Revision;
+use crate::lock::MutexExt;
+use crate::logging::log_event;
+use crate::rpc::pb::etcdserverpb::WatchCreateRequest;
+use crate::rpc::watch::{WatchClient, WatchOptions, WatchResponse, WatchStream, Watcher};
+use crate::task::Task;
+use prost::Message;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::broadcast;
+
+/// The name of the task forwarding the responses of a shared watch to its subscribers.
+const SHARED_WATCH_TASK: &str = "shared watch";
+
+/// The number of responses buffered for the subscribers of a shared watch.
+const SHARED_WATCH_CAPACITY: usize = 1024;
+
+/// What a [`SharedWatchStream`] receives.
+#[derive(Debug, Clone)]
+pub enum SharedWatchEvent {
+    /// A response of the shared watch.
+    Response(WatchResponse),
+    /// The subscriber fell behind and missed this number of responses, which other
+    /// subscribers received: the keys are to be read again.
+    Lagged(u64),
+}
+
+/// The watches of a watch client shared by subscribers, by their create request, shared by
+/// the clones of the client.
+#[derive(Clone, Default)]
+pub(crate) struct SharedWatches(Arc<Mutex<HashMap<Vec<u8>, Weak<Upstream>>>>);
+
+/// The state of a shared watch, shared with the task forwarding its responses.
+struct State {
+    /// The revision the next events are from.
+    resume_revision: i64,
+    /// The responses sent to the subscribers, `None` once the watch stopped.
+    events: Option<broadcast::Sender<WatchResponse>>,
+    /// Why the watch stopped, if it failed.
+    error: Option<String>,
+}
+
+/// A watch shared by subscribers, canceled when the last of them is dropped.
+struct Upstream {
+    id: Vec<u8>,
+    watches: SharedWatches,
+    state: Arc<Mutex<State>>,
+    task: Task,
+}
+
+impl Upstream {
+    /// Returns `true` unless the watch stopped.
+    #[inline]
+    fn is_live(&self) -> bool {
+        self.state.lock_unpoisoned().events.is_some()
+    }
+
+    /// Subscribes to the responses of the watch from now on.
+    fn subscribe(self: &Arc<Self>) -> SharedWatchStream {
+        // Under the lock, not to miss a response between the revision and the subscription.
+        let state = self.state.lock_unpoisoned();
+        let events = match &state.events {
+            Some(events) => events.subscribe(),
+            None => broadcast::channel(1).1,
+        };
+        SharedWatchStream {
+            events,
+            resume_revision: state.resume_revision,
+            upstream: self.clone(),
+        }
+    }
+}
+
+impl Drop for Upstream {
+    fn drop(&mut self) {
+        // Dropping the watch stream cancels the watch on the server.
+        self.task.abort();
+        let mut watches = self.watches.0.lock_unpoisoned();
+        if watches
+            .get(&self.id)
+            .is_some_and(|upstream| upstream.strong_count() == 0)
+        {
+            watches.remove(&self.id);
+        }
+    }
+}
+
+/// The responses of a watch shared by subscribers, see [`WatchClient::shared_subscribe`].
+///
+/// The shared watch is canceled once all its subscribers are dropped.
+pub struct SharedWatchStream {
+    events: broadcast::Receiver<WatchResponse>,
+    resume_revision: i64,
+    upstream: Arc<Upstream>,
+}
+
+impl SharedWatchStream {
+    /// The revision the events received by the stream start from.
+    ///
+    /// A subscriber joining a shared watch late does not receive the events the others
+    /// received before, it reads the keys at the revision before this one instead.
+    #[inline]
+    pub const fn resume_revision(&self) -> Revision {
+        Revision::new(self.resume_revision)
+    }
+
+    /// Fetches the next response of the shared watch, or `None` once it is canceled by the
+    /// server, e.g. because its start revision has been compacted.
+    ///
+    /// Fails with an [`Error::WatchError`] if the watch stream failed, the keys are to be
+    /// subscribed to again.
+    pub async fn message(&mut self) -> Result<Option<SharedWatchEvent>> {
+        match self.events.recv().await {
+            Ok(resp) => Ok(Some(SharedWatchEvent::Response(resp))),
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                Ok(Some(SharedWatchEvent::Lagged(missed)))
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                self.upstream.task.check()?;
+                match &self.upstream.state.lock_unpoisoned().error {
+                    Some(reason) => Err(Error::WatchError(reason.clone())),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+}
+
+impl WatchClient {
+    /// Subscribes to the events of `key` with `options`, sharing the watch with the other
+    /// subscribers to the same key with the same options, through this client or its clones.
+    ///
+    /// The first subscriber creates the watch, the others receive its responses from when
+    /// they subscribe on, starting from their [`SharedWatchStream::resume_revision`]. The
+    /// watch is canceled when the last subscriber is dropped. The responses are buffered
+    /// for every subscriber, one falling too far behind gets a [`SharedWatchEvent::Lagged`]
+    /// instead of the responses it missed, without holding up the others.
+    ///
+    /// The deadline and the token set by [`WatchOptions::with_cancel`] only abort creating
+    /// the watch, which is not canceled along with a subscriber.
+    pub async fn shared_subscribe(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<WatchOptions>,
+    ) -> Result<SharedWatchStream> {
+        let key = key.into();
+        let mut options = options.unwrap_or_default();
+        let create = WatchCreateRequest::from(options.clone().with_key(key.clone()));
+        let id = create.encode_to_vec();
+        let joined = self
+            .shared
+            .0
+            .lock_unpoisoned()
+            .get(&id)
+            .and_then(Weak::upgrade);
+        if let Some(upstream) = joined.filter(|upstream| upstream.is_live()) {
+            return Ok(upstream.subscribe());
+        }
+
+        let created = match options.take_cancel() {
+            Some(token) => token
+                .run_until_cancelled(self.create_watch(key, Some(options)))
+                .await
+                .unwrap_or(Err(Error::Cancelled { rpc: "Watch" }))?,
+            None => self.create_watch(key, Some(options)).await?,
+        };
+        let (watcher, stream, revision) = created;
+        let resume_revision = match create.start_revision {
+            0 => revision + 1,
+            start => start,
+        };
+        let (events, _) = broadcast::channel(SHARED_WATCH_CAPACITY);
+        let state = Arc::new(Mutex::new(State {
+            resume_revision,
+            events: Some(events),
+            error: None,
+        }));
+        let task = self
+            .tasks
+            .spawn(SHARED_WATCH_TASK, forward(state.clone(), watcher, stream));
+        let upstream = Arc::new(Upstream {
+            id: id.clone(),
+            watches: self.shared.clone(),
+            state,
+            task,
+        });
+
+        // Another subscriber may have created the same watch meanwhile, which is joined
+        // instead, this one being canceled. Dropped out of the lock, which their drop takes.
+        let raced = {
+            let mut watches = self.shared.0.lock_unpoisoned();
+            let raced = watches.get(&id).and_then(Weak::upgrade);
+            if !raced.as_ref().is_some_and(|raced| raced.is_live()) {
+                watches.insert(id, Arc::downgrade(&upstream));
+            }
+            raced
+        };
+        match raced.filter(|raced| raced.is_live()) {
+            Some(raced) => Ok(raced.subscribe()),
+            None => Ok(upstream.subscribe()),
+        }
+    }
+}
+
+/// Forwards the responses of a shared watch to its subscribers, until it stops.
+async fn forward(state: Arc<Mutex<State>>, _watcher: Watcher, mut stream: WatchStream) {
+    let error = loop {
+        let resp = match stream.message().await {
+            Ok(Some(resp)) => resp,
+            Ok(None) => break Some(String::from("watch stream closed")),
+            Err(e) => break Some(e.to_string()),
+        };
+        let canceled = resp.canceled();
+        let revision = match resp.events().last().and_then(|event| event.kv()) {
+            Some(kv) => kv.mod_revision().get() + 1,
+            // A progress notification, the events up to its revision have been received.
+            None => resp
+                .header()
+                .map_or(0, |header| header.revision().get() + 1),
+        };
+        let mut state = state.lock_unpoisoned();
+        if !canceled {
+            state.resume_revision = state.resume_revision.max(revision);
+        }
+        if let Some(events) = &state.events {
+            let _ = events.send(resp);
+        }
+        if canceled {
+            break None;
+        }
+    };
+
+    if let Some(reason) = &error {
+        log_event!(Warn, "etcd shared watch stopped", reason = reason);
+    }
+    let mut state = state.lock_unpoisoned();
+    state.events = None;
+    state.error = error;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::channel::Channel;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::rpc::pb::etcdserverpb::watch_request::RequestUnion as WatchRequestUnion;
+    use crate::rpc::pb::etcdserverpb::{
+        ResponseHeader as PbResponseHeader, WatchRequest as PbWatchRequest,
+        WatchResponse as PbWatchResponse,
+    };
+    use crate::rpc::pb::mvccpb::{Event as PbEvent, KeyValue as PbKeyValue};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tokio_stream::StreamExt;
+    use tonic::codec::ProstCodec;
+    use tonic::server::Grpc;
+    use tonic::{Status, Streaming};
+    use tower::util::BoxCloneService;
+
+    type WatchSender = UnboundedSender<std::result::Result<PbWatchResponse, Status>>;
+
+    /// A watch service counting the watches created, and the watch streams still open.
+    #[derive(Clone, Default)]
+    struct Counting {
+        creates: Arc<AtomicUsize>,
+        open: Arc<AtomicUsize>,
+        streams: Arc<Mutex<Vec<WatchSender>>>,
+    }
+
+    impl Counting {
+        fn client(&self) -> WatchClient {
+            let counting = self.clone();
+            let service = tower::service_fn(move |req: http::Request<tonic::body::Body>| {
+                let counting = counting.clone();
+                async move {
+                    let service =
+                        tower::service_fn(move |req: tonic::Request<Streaming<PbWatchRequest>>| {
+                            let resp = counting.serve(req.into_inner());
+                            async move { Ok::<_, Status>(tonic::Response::new(resp)) }
+                        });
+                    Ok::<_, tower::BoxError>(
+                        Grpc::new(ProstCodec::default())
+                            .streaming(service, req)
+                            .await,
+                    )
+                }
+            });
+            let channel = InterceptedChannel::new(
+                Channel::Custom(BoxCloneService::new(service)),
+                Interceptor::default(),
+            );
+            WatchClient::new(channel, AuthToken::default())
+        }
+
+        fn serve(
+            &self,
+            mut requests: Streaming<PbWatchRequest>,
+        ) -> UnboundedReceiverStream<std::result::Result<PbWatchResponse, Status>> {
+            let (sender, receiver) = unbounded_channel();
+            let counting = self.clone();
+            counting.open.fetch_add(1, Ordering::SeqCst);
+            counting.streams.lock_unpoisoned().push(sender.clone());
+            tokio::spawn(async move {
+                while let Some(Ok(req)) = requests.next().await {
+                    if let Some(WatchRequestUnion::CreateRequest(_)) = req.request_union {
+                        counting.creates.fetch_add(1, Ordering::SeqCst);
+                        let resp = PbWatchResponse {
+                            header: Some(PbResponseHeader {
+                                revision: 5,
+                                ..Default::default()
+                            }),
+                            watch_id: 1,
+                            created: true,
+                            ..Default::default()
+                        };
+                        let _ = sender.send(Ok(resp));
+                    }
+                }
+                counting.open.fetch_sub(1, Ordering::SeqCst);
+            });
+            UnboundedReceiverStream::new(receiver)
+        }
+
+        /// Sends the put of `key` at `revision` on every watch stream.
+        fn put(&self, key: &[u8], revision: i64) {
+            let resp = PbWatchResponse {
+                header: Some(PbResponseHeader {
+                    revision,
+                    ..Default::default()
+                }),
+                watch_id: 1,
+                events: vec![PbEvent {
+                    kv: Some(PbKeyValue {
+                        key: key.to_vec(),
+                        mod_revision: revision,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            for stream in self.streams.lock_unpoisoned().iter() {
+                let _ = stream.send(Ok(resp.clone()));
+            }
+        }
+    }
+
+    async fn next_revision(stream: &mut SharedWatchStream) -> i64 {
+        match stream.message().await.unwrap() {
+            Some(SharedWatchEvent::Response(resp)) => {
+                resp.events()[0].kv().unwrap().mod_revision().get()
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shared_subscribe() {
+        let counting = Counting::default();
+        let client = counting.client();
+        let options = WatchOptions::new().with_prefix();
+        let mut streams = Vec::new();
+        for _ in 0..3 {
+            let stream = client
+                .clone()
+                .shared_subscribe("prefix/", Some(options.clone()))
+                .await
+                .unwrap();
+            assert_eq!(stream.resume_revision(), 6);
+            streams.push(stream);
+        }
+        assert_eq!(counting.creates.load(Ordering::SeqCst), 1);
+
+        // Other options are another watch.
+        let other = client
+            .clone()
+            .shared_subscribe("prefix/", None)
+            .await
+            .unwrap();
+        assert_eq!(counting.creates.load(Ordering::SeqCst), 2);
+        drop(other);
+
+        counting.put(b"prefix/a", 7);
+        for stream in &mut streams {
+            assert_eq!(next_revision(stream).await, 7);
+        }
+
+        // A late subscriber resumes after the events already received.
+        let mut late = client
+            .clone()
+            .shared_subscribe("prefix/", Some(options.clone()))
+            .await
+            .unwrap();
+        assert_eq!(late.resume_revision(), 8);
+        counting.put(b"prefix/b", 8);
+        assert_eq!(next_revision(&mut late).await, 8);
+        assert_eq!(counting.creates.load(Ordering::SeqCst), 2);
+
+        // The watch is canceled once its last subscriber is dropped, not before.
+        drop(late);
+        streams.truncate(1);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(counting.open.load(Ordering::SeqCst), 1);
+        drop(streams);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while counting.open.load(Ordering::SeqCst) > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the watch must be canceled");
+        assert!(client.shared.0.lock_unpoisoned().is_empty());
+
+        // The next subscriber creates the watch again.
+        client
+            .clone()
+            .shared_subscribe("prefix/", Some(options))
+            .await
+            .unwrap();
+        assert_eq!(counting.creates.load(Ordering::SeqCst), 3);
+    }
+}
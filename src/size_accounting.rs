@@ -0,0 +1,352 @@
+//! Accounting of the bytes written by a client, and warnings as the database of the cluster
+//! nears its quota, see
+//! [`ConnectOptions::with_size_accounting`](crate::ConnectOptions::with_size_accounting).
+
+use crate::error::{Error, Result};
+use crate::logging::log_event;
+use crate::observe::Observer;
+use crate::task::Tasks;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+/// The interval between two checks of the size of the database against the quota, by
+/// default.
+pub const DEFAULT_QUOTA_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The fraction of the quota over which the size of the database is warned about, by
+/// default.
+pub const DEFAULT_QUOTA_WARN_FRACTION: f64 = 0.8;
+
+/// The name of the task checking the size of the database against the quota.
+const QUOTA_CHECK_TASK: &str = "quota check";
+
+type WarningHook = Arc<dyn Fn(QuotaWarning) + Send + Sync>;
+
+/// Options for the size accounting of a client, see
+/// [`ConnectOptions::with_size_accounting_options`](crate::ConnectOptions::with_size_accounting_options).
+#[derive(Clone)]
+pub struct SizeAccountingOptions {
+    quota_bytes: Option<u64>,
+    warn_fraction: f64,
+    check_interval: Duration,
+    max_request_bytes: Option<usize>,
+    on_warning: Option<WarningHook>,
+}
+
+impl SizeAccountingOptions {
+    /// Creates a `SizeAccountingOptions` accounting the bytes written only.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            quota_bytes: None,
+            warn_fraction: DEFAULT_QUOTA_WARN_FRACTION,
+            check_interval: DEFAULT_QUOTA_CHECK_INTERVAL,
+            max_request_bytes: None,
+            on_warning: None,
+        }
+    }
+
+    /// Checks the size of the database against `quota_bytes`, the `--quota-backend-bytes`
+    /// of the cluster, and warns once it reaches `warn_fraction` of it, e.g. `0.8`.
+    ///
+    /// The size is requested from a member every
+    /// [`SizeAccountingOptions::with_check_interval`]. A warning is raised each time the size
+    /// crosses the threshold upwards: the hook set by
+    /// [`SizeAccountingOptions::with_on_warning`] is called, and the `metrics` feature
+    /// reports the used fraction of the quota as the `<prefix>_db_quota_usage` gauge.
+    #[inline]
+    pub fn with_quota(mut self, quota_bytes: u64, warn_fraction: f64) -> Self {
+        self.quota_bytes = Some(quota_bytes);
+        self.warn_fraction = warn_fraction;
+        self
+    }
+
+    /// Sets the interval between two checks of the size of the database, 30 seconds by
+    /// default.
+    #[inline]
+    pub const fn with_check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+
+    /// Refuses to send the puts and txns larger than `max_request_bytes`, the
+    /// `--max-request-bytes` of the cluster, failing them with an
+    /// [`Error::RequestTooLarge`] without sending them.
+    #[inline]
+    pub const fn with_max_request_bytes(mut self, max_request_bytes: usize) -> Self {
+        self.max_request_bytes = Some(max_request_bytes);
+        self
+    }
+
+    /// Calls `hook` each time the size of the database crosses the warning threshold of
+    /// the quota, see [`SizeAccountingOptions::with_quota`].
+    #[inline]
+    pub fn with_on_warning(mut self, hook: impl Fn(QuotaWarning) + Send + Sync + 'static) -> Self {
+        self.on_warning = Some(Arc::new(hook));
+        self
+    }
+}
+
+impl Default for SizeAccountingOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for SizeAccountingOptions {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SizeAccountingOptions")
+            .field("quota_bytes", &self.quota_bytes)
+            .field("warn_fraction", &self.warn_fraction)
+            .field("check_interval", &self.check_interval)
+            .field("max_request_bytes", &self.max_request_bytes)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The size of the database crossed the warning threshold of the quota, see
+/// [`SizeAccountingOptions::with_on_warning`].
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaWarning {
+    db_size: i64,
+    quota_bytes: u64,
+}
+
+impl QuotaWarning {
+    /// The size of the database in bytes, as reported by a member.
+    #[inline]
+    pub const fn db_size(&self) -> i64 {
+        self.db_size
+    }
+
+    /// The quota of the database in bytes.
+    #[inline]
+    pub const fn quota_bytes(&self) -> u64 {
+        self.quota_bytes
+    }
+
+    /// The fraction of the quota used.
+    #[inline]
+    pub fn usage(&self) -> f64 {
+        self.db_size as f64 / self.quota_bytes as f64
+    }
+}
+
+/// The bytes written by a client, see [`Client::write_stats`](crate::Client::write_stats).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteStats {
+    bytes_written: u64,
+    writes: u64,
+    db_size: Option<i64>,
+    quota_bytes: Option<u64>,
+}
+
+impl WriteStats {
+    /// The sum of the sizes of the keys and values of the successful puts, including the
+    /// puts of the applied branch of txns.
+    #[inline]
+    pub const fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// The number of successful puts and txns.
+    #[inline]
+    pub const fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// The size of the database in bytes at the last check, if checked.
+    #[inline]
+    pub const fn db_size(&self) -> Option<i64> {
+        self.db_size
+    }
+
+    /// The quota of the database in bytes, if configured.
+    #[inline]
+    pub const fn quota_bytes(&self) -> Option<u64> {
+        self.quota_bytes
+    }
+}
+
+/// The size accounting shared by the clones of a client.
+#[derive(Debug)]
+pub(crate) struct SizeAccounting {
+    bytes_written: AtomicU64,
+    writes: AtomicU64,
+    /// The size of the database at the last check, `-1` until checked.
+    db_size: AtomicI64,
+    /// Whether the size is over the warning threshold.
+    warned: AtomicBool,
+    options: SizeAccountingOptions,
+}
+
+impl SizeAccounting {
+    /// Creates the accounting of a client.
+    #[inline]
+    pub(crate) fn new(options: SizeAccountingOptions) -> Arc<Self> {
+        Arc::new(Self {
+            bytes_written: AtomicU64::new(0),
+            writes: AtomicU64::new(0),
+            db_size: AtomicI64::new(-1),
+            warned: AtomicBool::new(false),
+            options,
+        })
+    }
+
+    /// Fails with an [`Error::RequestTooLarge`] if a request of `size` bytes is over the
+    /// configured maximum.
+    #[inline]
+    pub(crate) fn check_request(&self, size: usize) -> Result<()> {
+        match self.options.max_request_bytes {
+            Some(limit) if size > limit => Err(Error::RequestTooLarge {
+                size: Some(size),
+                limit: Some(limit),
+                status: None,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records a successful write of `bytes` bytes.
+    #[inline]
+    pub(crate) fn record_write(&self, bytes: u64) {
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+        self.writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The bytes written so far.
+    pub(crate) fn stats(&self) -> WriteStats {
+        let db_size = self.db_size.load(Ordering::Relaxed);
+        WriteStats {
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            db_size: (db_size >= 0).then_some(db_size),
+            quota_bytes: self.options.quota_bytes,
+        }
+    }
+
+    /// Records the size `db_size` of the database, warning if it crossed the threshold.
+    #[cfg_attr(not(feature = "maintenance"), allow(dead_code))]
+    fn record_db_size(&self, db_size: i64, observer: &Observer) {
+        self.db_size.store(db_size, Ordering::Relaxed);
+        let Some(quota_bytes) = self.options.quota_bytes.filter(|quota| *quota > 0) else {
+            return;
+        };
+        let warning = QuotaWarning {
+            db_size,
+            quota_bytes,
+        };
+        observer.quota_usage(warning.usage());
+        let over = warning.usage() >= self.options.warn_fraction;
+        if self.warned.swap(over, Ordering::Relaxed) || !over {
+            return;
+        }
+        log_event!(
+            Warn,
+            "etcd database nearing its quota",
+            db_size = db_size,
+            quota_bytes = quota_bytes,
+        );
+        if let Some(hook) = &self.options.on_warning {
+            hook(warning);
+        }
+    }
+
+    /// Checks the size of the database returned by `status` against the quota, every
+    /// check interval, until `status` returns `None` once the client is dropped.
+    #[cfg_attr(not(feature = "maintenance"), allow(dead_code))]
+    pub(crate) fn check_quota<F, Fut>(
+        self: &Arc<Self>,
+        tasks: &Tasks,
+        observer: Observer,
+        mut status: F,
+    ) where
+        F: FnMut() -> Option<Fut> + Send + 'static,
+        Fut: Future<Output = Result<i64>> + Send + 'static,
+    {
+        if self.options.quota_bytes.is_none() {
+            return;
+        }
+        let accounting: Weak<Self> = Arc::downgrade(self);
+        let interval = self.options.check_interval;
+        tasks.spawn(QUOTA_CHECK_TASK, async move {
+            loop {
+                let Some(status) = status() else {
+                    return;
+                };
+                // Failed checks are tried again at the next interval.
+                let db_size = status.await;
+                let Some(accounting) = accounting.upgrade() else {
+                    return;
+                };
+                if let Ok(db_size) = db_size {
+                    accounting.record_db_size(db_size, &observer);
+                }
+                drop(accounting);
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_check_request() {
+        let accounting = SizeAccounting::new(SizeAccountingOptions::new());
+        assert!(accounting.check_request(usize::MAX).is_ok());
+
+        let options = SizeAccountingOptions::new().with_max_request_bytes(100);
+        let accounting = SizeAccounting::new(options);
+        assert!(accounting.check_request(100).is_ok());
+        let err = accounting.check_request(101).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RequestTooLarge {
+                size: Some(101),
+                limit: Some(100),
+                status: None
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "request of 101 bytes is larger than 100 bytes"
+        );
+    }
+
+    #[test]
+    fn test_quota_warning() {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let options = SizeAccountingOptions::new()
+            .with_quota(1000, 0.8)
+            .with_on_warning({
+                let warnings = warnings.clone();
+                move |warning| warnings.lock().unwrap().push(warning.db_size())
+            });
+        let accounting = SizeAccounting::new(options);
+        let observer = Observer::default();
+        assert_eq!(accounting.stats().db_size(), None);
+
+        // Warned when crossing the threshold upwards only.
+        for db_size in [500, 800, 900, 700, 850] {
+            accounting.record_db_size(db_size, &observer);
+        }
+        assert_eq!(*warnings.lock().unwrap(), [800, 850]);
+
+        accounting.record_write(10);
+        accounting.record_write(5);
+        let stats = accounting.stats();
+        assert_eq!(stats.bytes_written(), 15);
+        assert_eq!(stats.writes(), 2);
+        assert_eq!(stats.db_size(), Some(850));
+        assert_eq!(stats.quota_bytes(), Some(1000));
+    }
+}
@@ -0,0 +1,205 @@
+//! Decoding of the `google.rpc.Status` details attached to gRPC statuses.
+//!
+//! The details are sent in the `grpc-status-details-bin` metadata, their raw bytes are
+//! returned by [`Error::status_details`](crate::Error::status_details).
+
+use std::collections::HashMap;
+
+/// The type URL of `google.rpc.ErrorInfo`.
+const ERROR_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.ErrorInfo";
+
+/// The type URL of `google.rpc.QuotaFailure`.
+const QUOTA_FAILURE_TYPE_URL: &str = "type.googleapis.com/google.rpc.QuotaFailure";
+
+/// The `google.rpc.Status` message.
+#[derive(Clone, PartialEq, prost::Message)]
+struct PbRpcStatus {
+    #[prost(int32, tag = "1")]
+    code: i32,
+    #[prost(string, tag = "2")]
+    message: String,
+    #[prost(message, repeated, tag = "3")]
+    details: Vec<prost_types::Any>,
+}
+
+/// The `google.rpc.ErrorInfo` message.
+#[derive(Clone, PartialEq, prost::Message)]
+struct PbErrorInfo {
+    #[prost(string, tag = "1")]
+    reason: String,
+    #[prost(string, tag = "2")]
+    domain: String,
+    #[prost(map = "string, string", tag = "3")]
+    metadata: HashMap<String, String>,
+}
+
+/// The `google.rpc.QuotaFailure` message.
+#[derive(Clone, PartialEq, prost::Message)]
+struct PbQuotaFailure {
+    #[prost(message, repeated, tag = "1")]
+    violations: Vec<PbQuotaViolation>,
+}
+
+/// The `google.rpc.QuotaFailure.Violation` message.
+#[derive(Clone, PartialEq, prost::Message)]
+struct PbQuotaViolation {
+    #[prost(string, tag = "1")]
+    subject: String,
+    #[prost(string, tag = "2")]
+    description: String,
+}
+
+/// Decodes the first detail of type `type_url` from the encoded `google.rpc.Status`.
+fn decode<T: prost::Message + Default>(details: &[u8], type_url: &str) -> Option<T> {
+    let status = <PbRpcStatus as prost::Message>::decode(details).ok()?;
+    let detail = status.details.iter().find(|any| any.type_url == type_url)?;
+    T::decode(detail.value.as_slice()).ok()
+}
+
+/// The reason of an error, see `google.rpc.ErrorInfo`.
+#[derive(Debug, Clone)]
+#[repr(transparent)]
+pub struct ErrorInfo(PbErrorInfo);
+
+impl ErrorInfo {
+    /// Decodes the error info from the encoded `google.rpc.Status`.
+    #[inline]
+    pub(crate) fn decode(details: &[u8]) -> Option<Self> {
+        decode(details, ERROR_INFO_TYPE_URL).map(Self)
+    }
+
+    /// The reason of the error, e.g. `QUOTA_EXCEEDED`.
+    #[inline]
+    pub fn reason(&self) -> &str {
+        &self.0.reason
+    }
+
+    /// The logical grouping of the reason, e.g. `etcd.io`.
+    #[inline]
+    pub fn domain(&self) -> &str {
+        &self.0.domain
+    }
+
+    /// Additional structured details of the error.
+    #[inline]
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.0.metadata
+    }
+}
+
+/// The quota checks which failed, see `google.rpc.QuotaFailure`.
+#[derive(Debug, Clone)]
+#[repr(transparent)]
+pub struct QuotaFailure(PbQuotaFailure);
+
+impl QuotaFailure {
+    /// Decodes the quota failure from the encoded `google.rpc.Status`.
+    #[inline]
+    pub(crate) fn decode(details: &[u8]) -> Option<Self> {
+        decode(details, QUOTA_FAILURE_TYPE_URL).map(Self)
+    }
+
+    /// The violated quotas.
+    #[inline]
+    pub fn violations(&self) -> &[QuotaViolation] {
+        unsafe { &*(self.0.violations.as_slice() as *const _ as *const [QuotaViolation]) }
+    }
+}
+
+/// A violated quota.
+#[derive(Debug, Clone)]
+#[repr(transparent)]
+pub struct QuotaViolation(PbQuotaViolation);
+
+impl QuotaViolation {
+    /// The subject of the quota, e.g. a client or a key.
+    #[inline]
+    pub fn subject(&self) -> &str {
+        &self.0.subject
+    }
+
+    /// How the quota was violated.
+    #[inline]
+    pub fn description(&self) -> &str {
+        &self.0.description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::channel::Channel;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::rpc::kv::KvClient;
+    use crate::Error;
+    use prost::Message;
+    use tower::util::BoxCloneService;
+    use tower::ServiceExt;
+
+    fn any(type_url: &str, msg: &impl Message) -> prost_types::Any {
+        prost_types::Any {
+            type_url: type_url.to_string(),
+            value: msg.encode_to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_details() {
+        let details = PbRpcStatus {
+            code: tonic::Code::ResourceExhausted as i32,
+            message: "quota exceeded".to_string(),
+            details: vec![
+                any(
+                    ERROR_INFO_TYPE_URL,
+                    &PbErrorInfo {
+                        reason: "QUOTA_EXCEEDED".to_string(),
+                        domain: "etcd.io".to_string(),
+                        metadata: HashMap::from([("limit".to_string(), "100".to_string())]),
+                    },
+                ),
+                any(
+                    QUOTA_FAILURE_TYPE_URL,
+                    &PbQuotaFailure {
+                        violations: vec![PbQuotaViolation {
+                            subject: "client:app".to_string(),
+                            description: "too many requests per second".to_string(),
+                        }],
+                    },
+                ),
+            ],
+        }
+        .encode_to_vec();
+
+        let service = tower::service_fn(move |_req: http::Request<tonic::body::Body>| {
+            let details = details.clone();
+            async move {
+                let status = tonic::Status::with_details(
+                    tonic::Code::ResourceExhausted,
+                    "quota exceeded",
+                    details.into(),
+                );
+                Ok::<_, tower::BoxError>(status.into_http::<tonic::body::Body>())
+            }
+        });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor::default(),
+        );
+        let mut client = KvClient::new(channel, AuthToken::default());
+
+        let err = client.get("key", None).await.unwrap_err();
+        assert!(err.status_details().is_some());
+        let info = err.error_info().unwrap();
+        assert_eq!(info.reason(), "QUOTA_EXCEEDED");
+        assert_eq!(info.domain(), "etcd.io");
+        assert_eq!(info.metadata()["limit"], "100");
+        let quota = err.quota_failure().unwrap();
+        assert_eq!(quota.violations().len(), 1);
+        assert_eq!(quota.violations()[0].subject(), "client:app");
+
+        let err = Error::from(tonic::Status::resource_exhausted("other"));
+        assert!(err.status_details().is_none());
+        assert!(err.error_info().is_none());
+    }
+}
@@ -0,0 +1,312 @@
+//! Software transactional memory over the key-value store, like `concurrency.STM` of the
+//! Go client.
+//!
+//! A [`Stm`] transaction is a function reading and writing keys through the [`Stm`] it is
+//! given. The writes are applied by a single txn, only if none of the keys read changed
+//! meanwhile, otherwise the function runs again with fresh reads.
+
+use crate::error::{Error, Result};
+use crate::rpc::kv::{Compare, CompareOp, GetOptions, KvClient, Txn, TxnOp, TxnOpResponse};
+use crate::rpc::KeyValue;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The number of times a STM transaction is run again after conflicting, by default.
+pub const DEFAULT_STM_RETRIES: u32 = 10;
+
+/// The isolation of a STM transaction from the concurrent writes, from the strongest to
+/// the weakest.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// All the keys are read at the revision of the first read, and the transaction
+    /// conflicts if any key read or written changed since then.
+    #[default]
+    SerializableSnapshot,
+    /// All the keys are read at the revision of the first read, and the transaction
+    /// conflicts if any key read changed since then.
+    Serializable,
+    /// Every key is read at the latest revision, and the transaction conflicts if any key
+    /// read changed since it was read.
+    RepeatableReads,
+    /// Every key is read at the latest revision, and the transaction never conflicts.
+    ReadCommitted,
+}
+
+/// The reads and writes of a STM transaction, see [`KvClient::stm`].
+///
+/// The keys are read before the transaction function runs: a key read for the first time
+/// is `None` until the function runs again with the key fetched. The function then runs
+/// several times, it must be side-effect free and only depend on what it reads.
+#[derive(Debug)]
+pub struct Stm {
+    isolation: IsolationLevel,
+    /// The revision of the snapshot read, once the first keys are fetched.
+    revision: i64,
+    /// The fetched keys, `None` for the keys which do not exist.
+    reads: BTreeMap<Vec<u8>, Option<KeyValue>>,
+    /// The keys read by the current run which are not fetched yet.
+    misses: BTreeSet<Vec<u8>>,
+    /// The writes of the current run, `None` for the deletes.
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    retries: u32,
+}
+
+impl Stm {
+    /// Creates the `Stm` of a transaction.
+    fn new(isolation: IsolationLevel) -> Self {
+        Self {
+            isolation,
+            revision: 0,
+            reads: BTreeMap::new(),
+            misses: BTreeSet::new(),
+            writes: BTreeMap::new(),
+            retries: 0,
+        }
+    }
+
+    /// Reads the value of `key`, or the one written by the transaction, or `None` if the
+    /// key does not exist.
+    pub fn get(&mut self, key: impl Into<Vec<u8>>) -> Option<Vec<u8>> {
+        let key = key.into();
+        if let Some(write) = self.writes.get(&key) {
+            return write.clone();
+        }
+        match self.reads.get(&key) {
+            Some(kv) => kv.as_ref().map(|kv| kv.value().to_vec()),
+            None => {
+                self.misses.insert(key);
+                None
+            }
+        }
+    }
+
+    /// Writes `value` to `key`.
+    #[inline]
+    pub fn put(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) {
+        self.writes.insert(key.into(), Some(value.into()));
+    }
+
+    /// Deletes `key`.
+    #[inline]
+    pub fn delete(&mut self, key: impl Into<Vec<u8>>) {
+        self.writes.insert(key.into(), None);
+    }
+
+    /// The number of times the transaction conflicted so far.
+    #[inline]
+    pub const fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Whether the keys are read at the revision of the first read.
+    #[inline]
+    const fn is_snapshot(&self) -> bool {
+        matches!(
+            self.isolation,
+            IsolationLevel::SerializableSnapshot | IsolationLevel::Serializable
+        )
+    }
+
+    /// Fetches the keys read for the first time by the last run, in a single txn.
+    async fn fetch(&mut self, kv: &mut KvClient) -> Result<()> {
+        let misses = std::mem::take(&mut self.misses);
+        let options = || {
+            let options = GetOptions::new();
+            match self.revision {
+                revision if revision > 0 && self.is_snapshot() => options.with_revision(revision),
+                _ => options,
+            }
+        };
+        let ops: Vec<_> = misses
+            .iter()
+            .map(|key| TxnOp::get(key.clone(), Some(options())))
+            .collect();
+        let resp = kv.txn(Txn::new().and_then(ops)).await?;
+        if self.revision == 0 {
+            self.revision = resp.header().map_or(0, |header| header.revision().get());
+        }
+        for (key, resp) in misses.into_iter().zip(resp.op_responses()) {
+            let TxnOpResponse::Get(mut resp) = resp else {
+                continue;
+            };
+            self.reads.insert(key, resp.take_kvs().into_iter().next());
+        }
+        Ok(())
+    }
+
+    /// The txn applying the writes if the keys did not change, per the isolation level.
+    fn commit_txn(&self) -> Txn {
+        let mut compares = Vec::new();
+        if self.isolation != IsolationLevel::ReadCommitted {
+            for (key, kv) in &self.reads {
+                let revision = kv.as_ref().map_or(0, |kv| kv.mod_revision().get());
+                compares.push(Compare::mod_revision(
+                    key.clone(),
+                    CompareOp::Equal,
+                    revision,
+                ));
+            }
+        }
+        if self.isolation == IsolationLevel::SerializableSnapshot {
+            for key in self
+                .writes
+                .keys()
+                .filter(|key| !self.reads.contains_key(*key))
+            {
+                compares.push(Compare::mod_revision(
+                    key.clone(),
+                    CompareOp::Less,
+                    self.revision + 1,
+                ));
+            }
+        }
+        let ops: Vec<_> = self
+            .writes
+            .iter()
+            .map(|(key, value)| match value {
+                Some(value) => TxnOp::put(key.clone(), value.clone(), None),
+                None => TxnOp::delete(key.clone(), None),
+            })
+            .collect();
+        Txn::new().when(compares).and_then(ops)
+    }
+
+    /// Forgets the reads, for the transaction to run again with fresh ones.
+    fn reset(&mut self) {
+        self.revision = 0;
+        self.reads.clear();
+        self.retries += 1;
+    }
+}
+
+impl KvClient {
+    /// Runs the STM transaction `f` at the isolation level `isolation`, retrying it
+    /// [`DEFAULT_STM_RETRIES`] times on conflicts, see [`KvClient::stm_with_retries`].
+    #[inline]
+    pub async fn stm<R>(
+        &mut self,
+        isolation: IsolationLevel,
+        f: impl FnMut(&mut Stm) -> Result<R>,
+    ) -> Result<R> {
+        self.stm_with_retries(isolation, DEFAULT_STM_RETRIES, f)
+            .await
+    }
+
+    /// Runs the STM transaction `f` at the isolation level `isolation`, and returns what it
+    /// returns once its writes are applied.
+    ///
+    /// `f` runs again with fresh reads whenever the keys changed before the writes could be
+    /// applied, up to `retries` times, after which an [`Error::StmRetriesExhausted`] is
+    /// returned. `f` also runs again, not counting as a retry, when it reads keys it did not
+    /// read before, see [`Stm`]. An error returned by `f` once all its reads are fetched
+    /// aborts the transaction.
+    ///
+    /// The keys first read by a run are fetched by a single txn, whose number of
+    /// operations is limited by `--max-txn-ops`, as is the number of keys read and written.
+    pub async fn stm_with_retries<R>(
+        &mut self,
+        isolation: IsolationLevel,
+        retries: u32,
+        mut f: impl FnMut(&mut Stm) -> Result<R>,
+    ) -> Result<R> {
+        let mut stm = Stm::new(isolation);
+        loop {
+            stm.writes.clear();
+            let result = f(&mut stm);
+            if !stm.misses.is_empty() {
+                stm.fetch(self).await?;
+                continue;
+            }
+            let value = result?;
+            if self.txn(stm.commit_txn()).await?.succeeded() {
+                return Ok(value);
+            }
+            if stm.retries >= retries {
+                return Err(Error::StmRetriesExhausted {
+                    retries: stm.retries,
+                });
+            }
+            stm.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::pb::etcdserverpb::compare::TargetUnion;
+    use crate::rpc::pb::etcdserverpb::TxnRequest as PbTxnRequest;
+    use crate::rpc::pb::mvccpb::KeyValue as PbKeyValue;
+
+    fn kv(key: &str, value: &str, mod_revision: i64) -> Option<KeyValue> {
+        Some(KeyValue::new(PbKeyValue {
+            key: key.into(),
+            value: value.into(),
+            mod_revision,
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn test_stm_reads_and_writes() {
+        let mut stm = Stm::new(IsolationLevel::SerializableSnapshot);
+        assert_eq!(stm.get("a"), None);
+        assert_eq!(stm.misses, BTreeSet::from([b"a".to_vec()]));
+
+        stm.misses.clear();
+        stm.reads.insert(b"a".to_vec(), kv("a", "1", 5));
+        stm.reads.insert(b"b".to_vec(), None);
+        assert_eq!(stm.get("a"), Some(b"1".to_vec()));
+        assert_eq!(stm.get("b"), None);
+        assert!(stm.misses.is_empty());
+
+        // the transaction reads its own writes
+        stm.put("a", "2");
+        stm.delete("c");
+        assert_eq!(stm.get("a"), Some(b"2".to_vec()));
+        assert_eq!(stm.get("c"), None);
+        assert!(stm.misses.is_empty());
+    }
+
+    #[test]
+    fn test_stm_commit_txn() {
+        let compares = |isolation| {
+            let mut stm = Stm::new(isolation);
+            stm.revision = 7;
+            stm.reads.insert(b"a".to_vec(), kv("a", "1", 5));
+            stm.reads.insert(b"b".to_vec(), None);
+            stm.put("a", "2");
+            stm.put("c", "3");
+            let req = PbTxnRequest::from(stm.commit_txn());
+            let compares: Vec<_> = req
+                .compare
+                .into_iter()
+                .map(|compare| match compare.target_union {
+                    Some(TargetUnion::ModRevision(revision)) => {
+                        (compare.key.to_vec(), compare.result, revision)
+                    }
+                    target => panic!("unexpected compare target {:?}", target),
+                })
+                .collect();
+            compares
+        };
+        let (equal, less) = (CompareOp::Equal as i32, CompareOp::Less as i32);
+        assert_eq!(
+            compares(IsolationLevel::SerializableSnapshot),
+            vec![
+                (b"a".to_vec(), equal, 5),
+                (b"b".to_vec(), equal, 0),
+                (b"c".to_vec(), less, 8),
+            ]
+        );
+        for isolation in [
+            IsolationLevel::Serializable,
+            IsolationLevel::RepeatableReads,
+        ] {
+            assert_eq!(
+                compares(isolation),
+                vec![(b"a".to_vec(), equal, 5), (b"b".to_vec(), equal, 0),]
+            );
+        }
+        assert!(compares(IsolationLevel::ReadCommitted).is_empty());
+    }
+}
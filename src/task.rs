@@ -0,0 +1,256 @@
+//! Background tasks spawned by the crate.
+//!
+//! A task which panics does not leave the client limping along silently: the panic is
+//! caught, the task is recorded as failed, and the calls relying on it return
+//! [`Error::InternalTaskFailed`]. The failure is also reported to the hook set by
+//! [`ConnectOptions::with_task_failure_hook`](crate::ConnectOptions::with_task_failure_hook).
+
+use crate::error::{Error, Result};
+use crate::lock::MutexExt;
+use crate::logging::log_event;
+use std::any::Any;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use tokio::task::JoinHandle;
+
+type HookFn = dyn Fn(&str, &str) + Send + Sync;
+
+/// The hook called with the name of a failed task and its panic message.
+#[derive(Clone)]
+pub(crate) struct TaskFailureHook(Arc<HookFn>);
+
+impl TaskFailureHook {
+    #[inline]
+    pub(crate) fn new(hook: impl Fn(&str, &str) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+}
+
+impl Debug for TaskFailureHook {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("TaskFailureHook")
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: Tasks;
+}
+
+/// Spawns the background tasks of a client, and records their failures.
+#[derive(Clone, Default)]
+pub(crate) struct Tasks {
+    hook: Option<TaskFailureHook>,
+    failures: Arc<Mutex<Vec<(&'static str, String)>>>,
+}
+
+impl Tasks {
+    /// Creates a `Tasks` reporting failures to `hook`.
+    #[inline]
+    pub(crate) fn new(hook: Option<TaskFailureHook>) -> Self {
+        Self {
+            hook,
+            failures: Arc::default(),
+        }
+    }
+
+    /// The tasks of the client being connected, see [`Tasks::scope`], or detached ones whose
+    /// failures are only logged.
+    ///
+    /// The builders of the crate spawn none, only the ones of tests forwarding the endpoint
+    /// changes by a task do.
+    #[cfg_attr(not(test), allow(dead_code))]
+    #[inline]
+    pub(crate) fn current() -> Self {
+        CURRENT.try_with(Clone::clone).unwrap_or_default()
+    }
+
+    /// Runs `f`, spawning the tasks of [`Tasks::current`] with `self`.
+    ///
+    /// Used for the tasks of balanced channels, which would be spawned by builders
+    /// knowing nothing of the client.
+    #[inline]
+    pub(crate) fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        CURRENT.sync_scope(self.clone(), f)
+    }
+
+    /// Spawns the task `task` running `fut`, catching its panic.
+    pub(crate) fn spawn<F>(&self, task: &'static str, fut: F) -> Task
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let failure = Arc::new(OnceLock::new());
+        let handle = tokio::spawn({
+            let tasks = self.clone();
+            let failure = failure.clone();
+            async move {
+                if let Err(payload) = CatchUnwind(Box::pin(fut)).await {
+                    let panic_message = panic_message(payload.as_ref());
+                    tracing::error!(task, panic_message, "etcd client task panicked");
+                    log_event!(
+                        Error,
+                        "etcd client task panicked",
+                        task = task,
+                        panic = &panic_message,
+                    );
+                    tasks
+                        .failures
+                        .lock_unpoisoned()
+                        .push((task, panic_message.clone()));
+                    if let Some(hook) = &tasks.hook {
+                        (hook.0)(task, &panic_message);
+                    }
+                    let _ = failure.set(panic_message);
+                }
+            }
+        });
+        Task {
+            task,
+            handle,
+            failure,
+        }
+    }
+
+    /// Returns [`Error::InternalTaskFailed`] if a task named `task` failed.
+    pub(crate) fn check(&self, task: &'static str) -> Result<()> {
+        let failures = self.failures.lock_unpoisoned();
+        match failures.iter().find(|(name, _)| *name == task) {
+            Some((task, panic_message)) => Err(Error::InternalTaskFailed {
+                task,
+                panic_message: panic_message.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A background task spawned by [`Tasks::spawn`].
+///
+/// Dropping the task does not stop it.
+#[derive(Debug)]
+pub(crate) struct Task {
+    task: &'static str,
+    handle: JoinHandle<()>,
+    failure: Arc<OnceLock<String>>,
+}
+
+impl Task {
+    /// Returns [`Error::InternalTaskFailed`] if the task panicked.
+    #[inline]
+    pub(crate) fn check(&self) -> Result<()> {
+        match self.failure.get() {
+            Some(panic_message) => Err(Error::InternalTaskFailed {
+                task: self.task,
+                panic_message: panic_message.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns `true` if the task panicked.
+    #[inline]
+    pub(crate) fn is_failed(&self) -> bool {
+        self.failure.get().is_some()
+    }
+
+    /// Returns `true` if the task finished, panicked or was aborted.
+    #[inline]
+    pub(crate) fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stops the task.
+    #[inline]
+    pub(crate) fn abort(&self) {
+        self.handle.abort();
+    }
+
+    /// Waits for the task to finish, and returns [`Error::InternalTaskFailed`] if it
+    /// panicked.
+    pub(crate) async fn join(mut self) -> Result<()> {
+        let _ = (&mut self.handle).await;
+        self.check()
+    }
+}
+
+/// The message of a panic with `payload`.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+/// Catches the panic of the future.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = std::thread::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let fut = self.0.as_mut();
+        match std::panic::catch_unwind(AssertUnwindSafe(|| fut.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_task_panic() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let hook = TaskFailureHook::new({
+            let reported = reported.clone();
+            move |task, message| {
+                reported
+                    .lock_unpoisoned()
+                    .push((task.to_string(), message.to_string()))
+            }
+        });
+        let tasks = Tasks::new(Some(hook));
+
+        let task = tasks.scope(|| Tasks::current().spawn("bridge", async { panic!("hook bug") }));
+        while !task.is_finished() {
+            tokio::task::yield_now().await;
+        }
+        assert!(task.is_failed());
+        match task.check() {
+            Err(Error::InternalTaskFailed {
+                task,
+                panic_message,
+            }) => {
+                assert_eq!(task, "bridge");
+                assert_eq!(panic_message, "hook bug");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(matches!(
+            tasks.check("bridge"),
+            Err(Error::InternalTaskFailed { .. })
+        ));
+        assert!(tasks.check("sync").is_ok());
+        assert_eq!(
+            *reported.lock_unpoisoned(),
+            [("bridge".to_string(), "hook bug".to_string())]
+        );
+
+        let task = tasks.spawn("sync", async {});
+        while !task.is_finished() {
+            tokio::task::yield_now().await;
+        }
+        assert!(task.check().is_ok());
+        assert!(tasks.check("sync").is_ok());
+    }
+}
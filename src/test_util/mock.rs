@@ -0,0 +1,416 @@
+//! An in-process mock of etcd, serving the clients connected to it without any network.
+
+use crate::channel::{BalancedChannelBuilder, Change, Channel, EndpointUpdater};
+use crate::client::{Client, ConnectOptions};
+use crate::error::Result;
+use crate::ids::Revision;
+use crate::lock::MutexExt;
+use crate::rpc::pb::etcdserverpb::watch_request::RequestUnion as WatchRequestUnion;
+use crate::rpc::pb::etcdserverpb::{
+    PutRequest as PbPutRequest, PutResponse as PbPutResponse, RangeRequest as PbRangeRequest,
+    RangeResponse as PbRangeResponse, ResponseHeader as PbResponseHeader,
+    WatchRequest as PbWatchRequest, WatchResponse as PbWatchResponse,
+};
+use crate::rpc::pb::mvccpb::{Event as PbEvent, KeyValue as PbKeyValue};
+use http::Uri;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::StreamExt;
+use tonic::codec::ProstCodec;
+use tonic::server::Grpc;
+
+use tonic::{Status, Streaming};
+use tower::util::BoxCloneService;
+
+type Request = http::Request<tonic::body::Body>;
+type Response = http::Response<tonic::body::Body>;
+
+/// The events of a watch stream.
+type WatchSender = UnboundedSender<std::result::Result<PbWatchResponse, Status>>;
+
+/// An in-process mock of etcd, serving the KV `Range` and `Put` RPCs and the `Watch` RPC from
+/// a store in memory, for tests and benchmarks which do not need a real cluster.
+///
+/// The clients connected to it send their requests to the mock through a custom
+/// [`Channel`], so no network is involved. The mock keeps the latest revision of every key
+/// only: ranges at past revisions read the latest one and watches only report the puts
+/// made after they are created. The other RPCs fail as unimplemented, as do the requests
+/// sent to a single endpoint, e.g. through [`Client::endpoint_client`], which are not
+/// served by the mock.
+///
+/// ```
+/// use etcd_client::test_util::MockEtcd;
+///
+/// # async fn test() -> Result<(), etcd_client::Error> {
+/// let etcd = MockEtcd::new();
+/// let mut client = etcd.client().await?;
+/// client.put("foo", "bar", None).await?;
+/// let resp = client.get("foo", None).await?;
+/// assert_eq!(resp.kvs()[0].value(), b"bar");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MockEtcd {
+    state: Arc<Mutex<State>>,
+}
+
+/// The store of a [`MockEtcd`] and the watches on it.
+#[derive(Debug, Default)]
+struct State {
+    revision: i64,
+    kvs: BTreeMap<Vec<u8>, PbKeyValue>,
+    watches: Vec<Watch>,
+    next_watch_id: i64,
+    endpoints: Vec<Uri>,
+}
+
+/// A watch created by a client of a [`MockEtcd`].
+#[derive(Debug)]
+struct Watch {
+    id: i64,
+    key: Vec<u8>,
+    range_end: Vec<u8>,
+    prev_kv: bool,
+    sender: WatchSender,
+}
+
+impl MockEtcd {
+    /// Creates a mock of an empty etcd store.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects a client to the mock, as an endpoint named `mock:2379`.
+    #[inline]
+    pub async fn client(&self) -> Result<Client> {
+        self.client_with(None).await
+    }
+
+    /// Connects a client to the mock with `options`. The options must not authenticate, auth
+    /// is not mocked.
+    pub async fn client_with(&self, options: Option<ConnectOptions>) -> Result<Client> {
+        Client::connect_with_balanced_channel(["http://mock:2379"], options, self.clone()).await
+    }
+
+    /// The channel of the clients of the mock, serving their requests in the calling task.
+    pub fn channel(&self) -> Channel {
+        let mock = self.clone();
+        let service = tower::service_fn(move |req: Request| {
+            let mock = mock.clone();
+            async move { Ok::<_, tower::BoxError>(mock.serve(req).await) }
+        });
+        Channel::Custom(BoxCloneService::new(service))
+    }
+
+    /// The revision of the store, of its last put.
+    #[inline]
+    pub fn revision(&self) -> Revision {
+        Revision::new(self.state.lock_unpoisoned().revision)
+    }
+
+    /// The endpoints of the balanced channels of the clients, as they were added and
+    /// removed.
+    #[inline]
+    pub fn endpoints(&self) -> Vec<Uri> {
+        self.state.lock_unpoisoned().endpoints.clone()
+    }
+
+    /// The number of watches created and not canceled yet.
+    #[inline]
+    pub fn watches(&self) -> usize {
+        let mut state = self.state.lock_unpoisoned();
+        state.watches.retain(|watch| !watch.sender.is_closed());
+        state.watches.len()
+    }
+
+    /// Serves `req`, by its path.
+    async fn serve(self, req: Request) -> Response {
+        match req.uri().path() {
+            "/etcdserverpb.KV/Range" => {
+                let service = tower::service_fn(|req: tonic::Request<PbRangeRequest>| {
+                    let resp = self.range(req.into_inner());
+                    async move { Ok::<_, Status>(tonic::Response::new(resp)) }
+                });
+                Grpc::new(ProstCodec::default()).unary(service, req).await
+            }
+            "/etcdserverpb.KV/Put" => {
+                let service = tower::service_fn(|req: tonic::Request<PbPutRequest>| {
+                    let resp = self.put(req.into_inner());
+                    async move { Ok::<_, Status>(tonic::Response::new(resp)) }
+                });
+                Grpc::new(ProstCodec::default()).unary(service, req).await
+            }
+            "/etcdserverpb.Watch/Watch" => {
+                let service =
+                    tower::service_fn(|req: tonic::Request<Streaming<PbWatchRequest>>| {
+                        let events = self.watch(req.into_inner());
+                        async move { Ok::<_, Status>(tonic::Response::new(events)) }
+                    });
+                Grpc::new(ProstCodec::default())
+                    .streaming(service, req)
+                    .await
+            }
+            path => Status::unimplemented(format!("{} is not mocked", path)).into_http(),
+        }
+    }
+
+    /// The header of the responses at the revision of `state`.
+    fn header(state: &State) -> Option<PbResponseHeader> {
+        Some(PbResponseHeader {
+            revision: state.revision,
+            ..Default::default()
+        })
+    }
+
+    fn range(&self, req: PbRangeRequest) -> PbRangeResponse {
+        let state = self.state.lock_unpoisoned();
+        let kvs: Vec<&PbKeyValue> = state
+            .kvs
+            .range(req.key.to_vec()..)
+            .take_while(|(key, _)| in_range(key, &req.key, &req.range_end))
+            .map(|(_, kv)| kv)
+            .collect();
+        let limit = match req.limit {
+            limit if limit > 0 => kvs.len().min(limit as usize),
+            _ => kvs.len(),
+        };
+        let page = match req.count_only {
+            true => Vec::new(),
+            false => kvs[..limit]
+                .iter()
+                .map(|&kv| match req.keys_only {
+                    true => PbKeyValue {
+                        value: Vec::new(),
+                        ..kv.clone()
+                    },
+                    false => kv.clone(),
+                })
+                .collect(),
+        };
+        PbRangeResponse {
+            header: Self::header(&state),
+            more: limit < kvs.len(),
+            count: kvs.len() as i64,
+            kvs: page,
+        }
+    }
+
+    fn put(&self, req: PbPutRequest) -> PbPutResponse {
+        let mut state = self.state.lock_unpoisoned();
+        state.revision += 1;
+        let revision = state.revision;
+        let prev = state.kvs.get(&req.key[..]).cloned();
+        let kv = PbKeyValue {
+            key: req.key.to_vec(),
+            create_revision: prev.as_ref().map_or(revision, |prev| prev.create_revision),
+            mod_revision: revision,
+            version: prev.as_ref().map_or(0, |prev| prev.version) + 1,
+            value: req.value.to_vec(),
+            lease: req.lease,
+        };
+        state.kvs.insert(kv.key.clone(), kv.clone());
+
+        let header = Self::header(&state);
+        state.watches.retain(|watch| {
+            if !in_range(&kv.key, &watch.key, &watch.range_end) {
+                return !watch.sender.is_closed();
+            }
+            let event = PbEvent {
+                r#type: 0,
+                kv: Some(kv.clone()),
+                prev_kv: prev.clone().filter(|_| watch.prev_kv),
+            };
+            let resp = PbWatchResponse {
+                header,
+                watch_id: watch.id,
+                events: vec![event],
+                ..Default::default()
+            };
+            watch.sender.send(Ok(resp)).is_ok()
+        });
+
+        PbPutResponse {
+            header,
+            prev_kv: prev.filter(|_| req.prev_kv),
+        }
+    }
+
+    /// Serves the watch stream of `requests`, its events are sent as the keys are put.
+    fn watch(
+        &self,
+        mut requests: Streaming<PbWatchRequest>,
+    ) -> UnboundedReceiverStream<std::result::Result<PbWatchResponse, Status>> {
+        let (sender, receiver) = unbounded_channel();
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(req)) = requests.next().await {
+                let mut state = state.lock_unpoisoned();
+                let header = Self::header(&state);
+                let resp = match req.request_union {
+                    Some(WatchRequestUnion::CreateRequest(create)) => {
+                        let id = match create.watch_id {
+                            0 => {
+                                state.next_watch_id += 1;
+                                state.next_watch_id
+                            }
+                            id => id,
+                        };
+                        state.watches.push(Watch {
+                            id,
+                            key: create.key,
+                            range_end: create.range_end,
+                            prev_kv: create.prev_kv,
+                            sender: sender.clone(),
+                        });
+                        PbWatchResponse {
+                            header,
+                            watch_id: id,
+                            created: true,
+                            ..Default::default()
+                        }
+                    }
+                    Some(WatchRequestUnion::CancelRequest(cancel)) => {
+                        state.watches.retain(|watch| {
+                            watch.id != cancel.watch_id || !watch.sender.same_channel(&sender)
+                        });
+                        PbWatchResponse {
+                            header,
+                            watch_id: cancel.watch_id,
+                            canceled: true,
+                            ..Default::default()
+                        }
+                    }
+                    Some(WatchRequestUnion::ProgressRequest(_)) => PbWatchResponse {
+                        header,
+                        watch_id: -1,
+                        ..Default::default()
+                    },
+                    None => continue,
+                };
+                if sender.send(Ok(resp)).is_err() {
+                    break;
+                }
+            }
+            // The watches of the stream end along with it.
+            state
+                .lock_unpoisoned()
+                .watches
+                .retain(|watch| !watch.sender.same_channel(&sender));
+        });
+        UnboundedReceiverStream::new(receiver)
+    }
+}
+
+impl BalancedChannelBuilder for MockEtcd {
+    type Error = crate::error::Error;
+
+    /// Makes the channel of the mock, recording the endpoints the client adds and removes.
+    fn balanced_channel(
+        self,
+        buffer_size: usize,
+    ) -> std::result::Result<(Channel, EndpointUpdater), Self::Error> {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(buffer_size);
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            while let Some(change) = receiver.recv().await {
+                let endpoints = &mut state.lock_unpoisoned().endpoints;
+                match change {
+                    Change::Insert(uri, _) if !endpoints.contains(&uri) => endpoints.push(uri),
+                    Change::Insert(..) => {}
+                    Change::Remove(uri) => endpoints.retain(|e| *e != uri),
+                }
+            }
+        });
+        Ok((self.channel(), EndpointUpdater::from(sender)))
+    }
+}
+
+/// Returns `true` if `key` is in the range of `start` and `range_end`, as etcd interprets
+/// them.
+fn in_range(key: &[u8], start: &[u8], range_end: &[u8]) -> bool {
+    match range_end {
+        [] => key == start,
+        [0] => key >= start,
+        range_end => key >= start && key < range_end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GetOptions, PutOptions, WatchOptions};
+
+    #[tokio::test]
+    async fn test_mock_kv() {
+        let etcd = MockEtcd::new();
+        let mut client = etcd.client().await.unwrap();
+        for key in ["a", "b/1", "b/2", "c"] {
+            client.put(key, "1", None).await.unwrap();
+        }
+        let resp = client
+            .put("b/1", "2", Some(PutOptions::new().with_prev_key()))
+            .await
+            .unwrap();
+        assert_eq!(resp.prev_key().unwrap().value(), b"1");
+        assert_eq!(etcd.revision(), 5);
+
+        let resp = client
+            .get("b/", Some(GetOptions::new().with_prefix().with_limit(1)))
+            .await
+            .unwrap();
+        assert_eq!(resp.count(), 2);
+        assert!(resp.more());
+        let kv = &resp.kvs()[0];
+        assert_eq!((kv.key(), kv.value()), (&b"b/1"[..], &b"2"[..]));
+        assert_eq!(
+            (kv.create_revision().get(), kv.mod_revision().get()),
+            (2, 5)
+        );
+        assert_eq!(kv.version(), 2);
+
+        let resp = client
+            .get(
+                "",
+                Some(GetOptions::new().with_all_keys().with_count_only()),
+            )
+            .await
+            .unwrap();
+        assert_eq!((resp.count(), resp.kvs().len()), (4, 0));
+
+        let err = client.delete("a", None).await.unwrap_err();
+        assert!(err.to_string().contains("not mocked"), "{}", err);
+
+        client.add_endpoint("http://mock-1:2379").await.unwrap();
+        client.remove_endpoint("http://mock:2379").await.unwrap();
+        tokio::task::yield_now().await;
+        assert_eq!(etcd.endpoints(), [Uri::from_static("http://mock-1:2379")]);
+    }
+
+    #[tokio::test]
+    async fn test_mock_watch() {
+        let etcd = MockEtcd::new();
+        let client = etcd.client().await.unwrap();
+        let (mut watcher, mut stream) = client
+            .watch_client()
+            .watch("key", Some(WatchOptions::new().with_prefix()))
+            .await
+            .unwrap();
+        assert_eq!(etcd.watches(), 1);
+
+        client.kv_client().put("other", "1", None).await.unwrap();
+        client.kv_client().put("key/1", "1", None).await.unwrap();
+        let resp = stream.message().await.unwrap().unwrap();
+        assert_eq!(resp.watch_id(), watcher.watch_id());
+        assert_eq!(resp.events()[0].kv().unwrap().key(), b"key/1");
+        assert_eq!(resp.header().unwrap().revision(), 2);
+
+        watcher.cancel().await.unwrap();
+        let resp = stream.message().await.unwrap().unwrap();
+        assert!(resp.canceled());
+        assert_eq!(etcd.watches(), 0);
+    }
+}
@@ -0,0 +1,591 @@
+//! etcd clusters for integration tests.
+//!
+//! An [`EtcdCluster`] launches its members with a [`Backend`]: the [`Process`] backend runs
+//! the `etcd` binary found on the `PATH`, the [`Docker`] backend runs containers of an etcd
+//! image. Members can be stopped, started again and partitioned from the others for chaos
+//! tests, and the cluster is torn down once dropped.
+//!
+//! ```no_run
+//! use etcd_client::test_util::EtcdCluster;
+//!
+//! # async fn test() -> Result<(), etcd_client::Error> {
+//! let cluster = EtcdCluster::start(3).await?;
+//! let mut client = cluster.client().await?;
+//! client.put("foo", "bar", None).await?;
+//!
+//! // The cluster keeps its quorum without one member.
+//! cluster.stop_member(0)?;
+//! client.get("foo", None).await?;
+//! cluster.start_member(0).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Tests and benchmarks which need no real cluster can use a [`MockEtcd`] instead, serving
+//! the KV and Watch RPCs in process.
+
+#[cfg(all(feature = "kv", feature = "watch"))]
+mod mock;
+
+#[cfg(all(feature = "kv", feature = "watch"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "kv", feature = "watch"))))]
+pub use self::mock::MockEtcd;
+
+use crate::client::{Client, ConnectOptions};
+use crate::error::{Error, Result};
+use crate::lock::MutexExt;
+use std::io;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+
+/// The image of the containers of the [`Docker`] backend by default.
+pub const DEFAULT_ETCD_IMAGE: &str = "quay.io/coreos/etcd:v3.5.17";
+
+/// The time a member may take to report itself healthy once launched.
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The interval of polling the health endpoint of a member.
+const HEALTH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Distinguishes the clusters of a process.
+static CLUSTERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Launches the members of an [`EtcdCluster`].
+pub trait Backend: Send + Sync {
+    /// Launches the member `spec`, without waiting for it to be ready.
+    fn launch(&self, spec: &MemberSpec) -> io::Result<Box<dyn Member>>;
+}
+
+/// A launched member of an [`EtcdCluster`], stopped and removed along with its data once
+/// dropped.
+pub trait Member: Send {
+    /// Kills the member, keeping its data.
+    fn kill(&mut self) -> io::Result<()>;
+
+    /// Starts the killed member again on its data.
+    fn restart(&mut self) -> io::Result<()>;
+
+    /// Suspends the member, which stops answering its peers and clients.
+    fn pause(&mut self) -> io::Result<()>;
+
+    /// Resumes the suspended member.
+    fn resume(&mut self) -> io::Result<()>;
+}
+
+/// The settings of a member of an [`EtcdCluster`], listening on the loopback interface.
+#[derive(Debug, Clone)]
+pub struct MemberSpec {
+    name: String,
+    client_port: u16,
+    peer_port: u16,
+    initial_cluster: String,
+    cluster_token: String,
+}
+
+impl MemberSpec {
+    /// The name of the member, unique in its cluster.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The URL the member serves its clients on.
+    #[inline]
+    pub fn client_url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.client_port)
+    }
+
+    /// The URL the member serves its peers on.
+    #[inline]
+    pub fn peer_url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.peer_port)
+    }
+
+    /// The token of the cluster, unique to the process and the cluster.
+    #[inline]
+    pub fn cluster_token(&self) -> &str {
+        &self.cluster_token
+    }
+
+    /// The arguments of `etcd` running the member on the data directory `data_dir`.
+    pub fn args(&self, data_dir: &str) -> Vec<String> {
+        let client_url = self.client_url();
+        let peer_url = self.peer_url();
+        [
+            "--name",
+            &self.name,
+            "--data-dir",
+            data_dir,
+            "--listen-client-urls",
+            &client_url,
+            "--advertise-client-urls",
+            &client_url,
+            "--listen-peer-urls",
+            &peer_url,
+            "--initial-advertise-peer-urls",
+            &peer_url,
+            "--initial-cluster",
+            &self.initial_cluster,
+            "--initial-cluster-token",
+            &self.cluster_token,
+            "--initial-cluster-state",
+            "new",
+            "--log-level",
+            "error",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect()
+    }
+}
+
+/// Runs the members as processes of a local `etcd` binary, storing their data in the
+/// temporary directory.
+#[derive(Debug, Clone)]
+pub struct Process {
+    binary: PathBuf,
+}
+
+impl Process {
+    /// Creates a `Process` running the binary `binary`.
+    #[inline]
+    pub fn new(binary: impl Into<PathBuf>) -> Self {
+        Self {
+            binary: binary.into(),
+        }
+    }
+
+    /// Creates a `Process` running the `etcd` binary found on the `PATH`, if any.
+    pub fn find() -> Option<Self> {
+        let paths = std::env::var_os("PATH")?;
+        std::env::split_paths(&paths)
+            .map(|path| path.join("etcd"))
+            .find(|binary| binary.is_file())
+            .map(Self::new)
+    }
+}
+
+impl Backend for Process {
+    fn launch(&self, spec: &MemberSpec) -> io::Result<Box<dyn Member>> {
+        let data_dir = std::env::temp_dir().join(format!("{}-{}", spec.cluster_token, spec.name));
+        let mut command = Command::new(&self.binary);
+        command
+            .args(spec.args(&data_dir.to_string_lossy()))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        let mut member = ProcessMember {
+            command,
+            child: None,
+            data_dir,
+        };
+        member.restart()?;
+        Ok(Box::new(member))
+    }
+}
+
+struct ProcessMember {
+    command: Command,
+    child: Option<Child>,
+    data_dir: PathBuf,
+}
+
+impl ProcessMember {
+    /// Sends `signal` to the running process.
+    fn signal(&self, signal: &str) -> io::Result<()> {
+        let Some(child) = &self.child else {
+            return Err(io::Error::other("the member is not running"));
+        };
+        run(Command::new("kill").args([signal, &child.id().to_string()]))
+    }
+}
+
+impl Member for ProcessMember {
+    fn kill(&mut self) -> io::Result<()> {
+        if let Some(mut child) = self.child.take() {
+            // A paused process is killed too.
+            child.kill()?;
+            child.wait()?;
+        }
+        Ok(())
+    }
+
+    fn restart(&mut self) -> io::Result<()> {
+        if self.child.is_none() {
+            self.child = Some(self.command.spawn()?);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn pause(&mut self) -> io::Result<()> {
+        self.signal("-STOP")
+    }
+
+    #[inline]
+    fn resume(&mut self) -> io::Result<()> {
+        self.signal("-CONT")
+    }
+}
+
+impl Drop for ProcessMember {
+    fn drop(&mut self) {
+        let _ = self.kill();
+        let _ = std::fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+/// Runs the members as containers of an etcd image on the host network, with the `docker`
+/// command.
+#[derive(Debug, Clone)]
+pub struct Docker {
+    image: String,
+}
+
+impl Docker {
+    /// Creates a `Docker` running containers of the image `image`, which must provide
+    /// `/usr/local/bin/etcd` like the official images.
+    #[inline]
+    pub fn new(image: impl Into<String>) -> Self {
+        Self {
+            image: image.into(),
+        }
+    }
+}
+
+impl Default for Docker {
+    #[inline]
+    fn default() -> Self {
+        Self::new(DEFAULT_ETCD_IMAGE)
+    }
+}
+
+impl Backend for Docker {
+    fn launch(&self, spec: &MemberSpec) -> io::Result<Box<dyn Member>> {
+        let container = format!("{}-{}", spec.cluster_token, spec.name);
+        run(Command::new("docker")
+            .args(["run", "--detach", "--network", "host", "--name", &container])
+            .args([&self.image, "/usr/local/bin/etcd"])
+            .args(spec.args("/etcd-data")))?;
+        Ok(Box::new(DockerMember { container }))
+    }
+}
+
+struct DockerMember {
+    container: String,
+}
+
+impl DockerMember {
+    /// Runs the docker command `command` on the container.
+    #[inline]
+    fn docker(&self, command: &str) -> io::Result<()> {
+        run(Command::new("docker").args([command, &self.container]))
+    }
+}
+
+impl Member for DockerMember {
+    #[inline]
+    fn kill(&mut self) -> io::Result<()> {
+        self.docker("kill")
+    }
+
+    #[inline]
+    fn restart(&mut self) -> io::Result<()> {
+        self.docker("start")
+    }
+
+    #[inline]
+    fn pause(&mut self) -> io::Result<()> {
+        self.docker("pause")
+    }
+
+    #[inline]
+    fn resume(&mut self) -> io::Result<()> {
+        self.docker("unpause")
+    }
+}
+
+impl Drop for DockerMember {
+    fn drop(&mut self) {
+        let _ = run(Command::new("docker").args(["rm", "--force", "--volumes", &self.container]));
+    }
+}
+
+/// Runs `command` to completion, failing if it exits unsuccessfully.
+fn run(command: &mut Command) -> io::Result<()> {
+    let output = command.stdin(Stdio::null()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{:?} failed with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// A cluster of etcd members listening on the loopback interface, torn down once dropped.
+///
+/// Launching and stopping members blocks the current thread, e.g. while docker pulls the
+/// image.
+pub struct EtcdCluster {
+    members: Vec<Mutex<Box<dyn Member>>>,
+    specs: Vec<MemberSpec>,
+    ready_timeout: Duration,
+}
+
+impl EtcdCluster {
+    /// Starts a cluster of `members` members with the [`Process`] backend if `etcd` is found
+    /// on the `PATH`, or with the [`Docker`] backend otherwise, and waits until all of them
+    /// are healthy.
+    pub async fn start(members: usize) -> Result<Self> {
+        match Process::find() {
+            Some(process) => Self::start_with(members, &process).await,
+            None => Self::start_with(members, &Docker::default()).await,
+        }
+    }
+
+    /// Starts a cluster of `members` members with `backend`, and waits until all of them
+    /// are healthy.
+    pub async fn start_with(members: usize, backend: &dyn Backend) -> Result<Self> {
+        if members == 0 {
+            return Err(Error::InvalidArgs(String::from(
+                "a cluster needs at least one member",
+            )));
+        }
+
+        let cluster_token = format!(
+            "etcd-client-test-{}-{}",
+            std::process::id(),
+            CLUSTERS.fetch_add(1, Ordering::Relaxed)
+        );
+        // Keeps the ports bound until all of them are picked, so that they are distinct.
+        let listeners = (0..members * 2)
+            .map(|_| TcpListener::bind("127.0.0.1:0"))
+            .collect::<io::Result<Vec<_>>>()?;
+        let ports = listeners
+            .iter()
+            .map(|listener| Ok(listener.local_addr()?.port()))
+            .collect::<io::Result<Vec<_>>>()?;
+        drop(listeners);
+
+        let initial_cluster = (0..members)
+            .map(|i| format!("member-{}=http://127.0.0.1:{}", i, ports[i * 2 + 1]))
+            .collect::<Vec<_>>()
+            .join(",");
+        let specs: Vec<_> = (0..members)
+            .map(|i| MemberSpec {
+                name: format!("member-{}", i),
+                client_port: ports[i * 2],
+                peer_port: ports[i * 2 + 1],
+                initial_cluster: initial_cluster.clone(),
+                cluster_token: cluster_token.clone(),
+            })
+            .collect();
+
+        let mut cluster = Self {
+            members: Vec::with_capacity(members),
+            specs,
+            ready_timeout: DEFAULT_READY_TIMEOUT,
+        };
+        // Members launched so far are torn down if a later one fails.
+        for spec in &cluster.specs {
+            cluster.members.push(Mutex::new(backend.launch(spec)?));
+        }
+        for i in 0..members {
+            cluster.wait_healthy(i).await?;
+        }
+        Ok(cluster)
+    }
+
+    /// The number of members of the cluster.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.specs.len()
+    }
+
+    /// Whether the cluster has no member, which is never the case.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+
+    /// The settings of the member `i`.
+    #[inline]
+    pub fn member(&self, i: usize) -> &MemberSpec {
+        &self.specs[i]
+    }
+
+    /// The client URLs of all the members, including the stopped ones.
+    pub fn endpoints(&self) -> Vec<String> {
+        self.specs.iter().map(MemberSpec::client_url).collect()
+    }
+
+    /// Connects a client to all the members.
+    #[inline]
+    pub async fn client(&self) -> Result<Client> {
+        self.client_with(None).await
+    }
+
+    /// Connects a client with `options` to all the members.
+    #[inline]
+    pub async fn client_with(&self, options: Option<ConnectOptions>) -> Result<Client> {
+        Client::connect(self.endpoints(), options).await
+    }
+
+    /// Kills the member `i`, keeping its data.
+    pub fn stop_member(&self, i: usize) -> Result<()> {
+        self.members[i].lock_unpoisoned().kill()?;
+        Ok(())
+    }
+
+    /// Starts the stopped member `i` again, and waits until it is healthy.
+    pub async fn start_member(&self, i: usize) -> Result<()> {
+        self.members[i].lock_unpoisoned().restart()?;
+        self.wait_healthy(i).await
+    }
+
+    /// Partitions the member `i` from its peers and clients by suspending it, until
+    /// [`EtcdCluster::heal`] resumes it.
+    pub fn partition(&self, i: usize) -> Result<()> {
+        self.members[i].lock_unpoisoned().pause()?;
+        Ok(())
+    }
+
+    /// Resumes the partitioned member `i`, without waiting for it to catch up.
+    pub fn heal(&self, i: usize) -> Result<()> {
+        self.members[i].lock_unpoisoned().resume()?;
+        Ok(())
+    }
+
+    /// Waits until the health endpoint of the member `i` reports it healthy.
+    async fn wait_healthy(&self, i: usize) -> Result<()> {
+        let spec = &self.specs[i];
+        let deadline = Instant::now() + self.ready_timeout;
+        while !is_healthy(spec.client_port).await {
+            if Instant::now() >= deadline {
+                return Err(Error::IoError(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "{} is not healthy after {:?}",
+                        spec.name, self.ready_timeout
+                    ),
+                )));
+            }
+            tokio::time::sleep(HEALTH_POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if the member serving clients on `port` reports itself healthy, which
+/// requires a leader.
+async fn is_healthy(port: u16) -> bool {
+    let check = async {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+        let request = format!(
+            "GET /health HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nConnection: close\r\n\r\n",
+            port
+        );
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        io::Result::Ok(String::from_utf8_lossy(&response).contains(r#""health":"true""#))
+    };
+    tokio::time::timeout(HEALTH_POLL_INTERVAL * 10, check)
+        .await
+        .is_ok_and(|healthy| healthy.unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    /// Serves the health endpoint of every member, healthy while running.
+    struct Fake;
+
+    impl Backend for Fake {
+        fn launch(&self, spec: &MemberSpec) -> io::Result<Box<dyn Member>> {
+            let listener = TcpListener::bind(("127.0.0.1", spec.client_port))?;
+            listener.set_nonblocking(true)?;
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            let running = Arc::new(AtomicBool::new(true));
+            let healthy = running.clone();
+            tokio::spawn(async move {
+                while let Ok((mut socket, _)) = listener.accept().await {
+                    let mut request = [0; 1024];
+                    let _ = socket.read(&mut request).await;
+                    if healthy.load(Ordering::Relaxed) {
+                        let body = r#"{"health":"true","reason":""}"#;
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                            body.len(),
+                            body
+                        );
+                        let _ = socket.write_all(response.as_bytes()).await;
+                    }
+                }
+            });
+            Ok(Box::new(FakeMember(running)))
+        }
+    }
+
+    struct FakeMember(Arc<AtomicBool>);
+
+    impl Member for FakeMember {
+        fn kill(&mut self) -> io::Result<()> {
+            self.0.store(false, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn restart(&mut self) -> io::Result<()> {
+            self.0.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn pause(&mut self) -> io::Result<()> {
+            self.kill()
+        }
+
+        fn resume(&mut self) -> io::Result<()> {
+            self.restart()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cluster() {
+        let mut cluster = EtcdCluster::start_with(3, &Fake).await.unwrap();
+        assert_eq!(cluster.len(), 3);
+        let endpoints = cluster.endpoints();
+        assert_eq!(endpoints[0], cluster.member(0).client_url());
+        assert!(cluster.member(0).args("/data").contains(&endpoints[0]));
+        assert!(cluster.member(2).args("/data")[13].contains(&cluster.member(1).peer_url()));
+
+        cluster.stop_member(1).unwrap();
+        assert!(!is_healthy(cluster.member(1).client_port).await);
+        cluster.start_member(1).await.unwrap();
+        cluster.partition(2).unwrap();
+        assert!(!is_healthy(cluster.member(2).client_port).await);
+        cluster.heal(2).unwrap();
+        assert!(is_healthy(cluster.member(2).client_port).await);
+
+        // A member which does not recover times out.
+        cluster.ready_timeout = Duration::from_millis(200);
+        cluster.stop_member(0).unwrap();
+        cluster.members[0] = Mutex::new(Box::new(FakeMember(Default::default())));
+        let err = cluster.start_member(0).await.unwrap_err();
+        assert!(matches!(err, Error::IoError(e) if e.kind() == io::ErrorKind::TimedOut));
+
+        assert!(EtcdCluster::start_with(0, &Fake).await.is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! TLS options of the `rustls`-based connections.
+
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+/// TLS options of the connections to the etcd servers, see
+/// [`ConnectOptions::with_tls`](crate::ConnectOptions::with_tls).
+///
+/// The servers are verified against the union of the chosen roots: the system trust store,
+/// see [`TlsOptions::with_native_roots`], the bundled webpki roots, see
+/// [`TlsOptions::with_webpki_roots`], and the CA certificates, unless the CA certificates
+/// replace the other roots, see [`TlsOptions::replace_roots`]. The roots apply to every
+/// endpoint, including the ones added later, e.g. by endpoint sync.
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    domain_name: Option<String>,
+    ca_certificates: Vec<Certificate>,
+    identity: Option<Identity>,
+    #[cfg(feature = "tls-roots")]
+    native_roots: bool,
+    #[cfg(feature = "tls-webpki-roots")]
+    webpki_roots: bool,
+    replace_roots: bool,
+    assume_http2: bool,
+    use_key_log: bool,
+}
+
+impl TlsOptions {
+    /// Creates a `TlsOptions` without any root, the servers can not be verified until roots
+    /// or CA certificates are added.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            domain_name: None,
+            ca_certificates: Vec::new(),
+            identity: None,
+            #[cfg(feature = "tls-roots")]
+            native_roots: false,
+            #[cfg(feature = "tls-webpki-roots")]
+            webpki_roots: false,
+            replace_roots: false,
+            assume_http2: false,
+            use_key_log: false,
+        }
+    }
+
+    /// Sets the domain name the certificates of the servers are verified against, instead
+    /// of the hosts of their URLs.
+    #[inline]
+    pub fn domain_name(mut self, domain_name: impl Into<String>) -> Self {
+        self.domain_name = Some(domain_name.into());
+        self
+    }
+
+    /// Adds the CA certificate `ca_certificate` to the roots.
+    #[inline]
+    pub fn ca_certificate(mut self, ca_certificate: Certificate) -> Self {
+        self.ca_certificates.push(ca_certificate);
+        self
+    }
+
+    /// Adds the CA certificates `ca_certificates` to the roots.
+    #[inline]
+    pub fn ca_certificates(
+        mut self,
+        ca_certificates: impl IntoIterator<Item = Certificate>,
+    ) -> Self {
+        self.ca_certificates.extend(ca_certificates);
+        self
+    }
+
+    /// Adds the PEM encoded CA certificates `pem` to the roots.
+    #[inline]
+    pub fn with_ca_certificate(self, pem: impl AsRef<[u8]>) -> Self {
+        self.ca_certificate(Certificate::from_pem(pem))
+    }
+
+    /// Trusts the roots of the system trust store, loaded by the `rustls-native-certs`
+    /// crate. Connecting fails if the store has no certificate.
+    #[cfg(feature = "tls-roots")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls-roots")))]
+    #[inline]
+    pub const fn with_native_roots(mut self) -> Self {
+        self.native_roots = true;
+        self
+    }
+
+    /// Trusts the Mozilla roots bundled by the `webpki-roots` crate.
+    #[cfg(feature = "tls-webpki-roots")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls-webpki-roots")))]
+    #[inline]
+    pub const fn with_webpki_roots(mut self) -> Self {
+        self.webpki_roots = true;
+        self
+    }
+
+    /// Sets whether the CA certificates, if any, replace the native and webpki roots rather
+    /// than adding to them. Defaults to `false`.
+    #[inline]
+    pub const fn replace_roots(mut self, replace: bool) -> Self {
+        self.replace_roots = replace;
+        self
+    }
+
+    /// Sets the certificate and private key the client authenticates with.
+    #[inline]
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Sets whether HTTP/2 is assumed even if the server does not negotiate it by ALPN.
+    #[inline]
+    pub const fn assume_http2(mut self, assume_http2: bool) -> Self {
+        self.assume_http2 = assume_http2;
+        self
+    }
+
+    /// Logs the TLS keys to the file named by the `SSLKEYLOGFILE` environment variable,
+    /// e.g. to decrypt captured traffic.
+    #[inline]
+    pub const fn use_key_log(mut self) -> Self {
+        self.use_key_log = true;
+        self
+    }
+
+    /// Whether the native and webpki roots are trusted, i.e. not replaced by CA
+    /// certificates.
+    #[cfg(any(feature = "tls-roots", feature = "tls-webpki-roots"))]
+    #[inline]
+    fn keeps_roots(&self) -> bool {
+        !self.replace_roots || self.ca_certificates.is_empty()
+    }
+
+    /// The TLS config of the channels.
+    pub(crate) fn config(&self) -> ClientTlsConfig {
+        let mut config = ClientTlsConfig::new()
+            .ca_certificates(self.ca_certificates.iter().cloned())
+            .assume_http2(self.assume_http2);
+        if let Some(domain_name) = &self.domain_name {
+            config = config.domain_name(domain_name);
+        }
+        if let Some(identity) = &self.identity {
+            config = config.identity(identity.clone());
+        }
+        if self.use_key_log {
+            config = config.use_key_log();
+        }
+        #[cfg(feature = "tls-roots")]
+        if self.native_roots && self.keeps_roots() {
+            config = config.with_native_roots();
+        }
+        #[cfg(feature = "tls-webpki-roots")]
+        if self.webpki_roots && self.keeps_roots() {
+            config = config.with_webpki_roots();
+        }
+        config
+    }
+}
+
+#[cfg(all(test, any(feature = "tls-roots", feature = "tls-webpki-roots")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_roots() {
+        let pem = "-----BEGIN CERTIFICATE-----\n-----END CERTIFICATE-----\n";
+        assert!(TlsOptions::new().keeps_roots());
+        // Only CA certificates replace the roots.
+        assert!(TlsOptions::new().replace_roots(true).keeps_roots());
+        assert!(TlsOptions::new().with_ca_certificate(pem).keeps_roots());
+        assert!(!TlsOptions::new()
+            .with_ca_certificate(pem)
+            .replace_roots(true)
+            .keeps_roots());
+    }
+}
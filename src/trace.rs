@@ -0,0 +1,472 @@
+//! Tracing of RPCs, the backend of `crate::observe` enabled by the `tracing` feature.
+//!
+//! Every attempt of an RPC runs in a debug span named after the method, e.g. `etcd.Range`
+//! or `etcd.Put`, recording:
+//!
+//! - `key`: the requested key, redacted or truncated according to [`TraceKeys`],
+//! - `endpoint`: the endpoint the attempt was sent to, if known, i.e. for hedged reads,
+//! - `attempt`: the number of the attempt, if the RPC is retried,
+//! - `lane`: `bulk` if the attempt was sent over the bulk lane, see
+//!   [`ConnectOptions::with_priority_lanes`](crate::ConnectOptions::with_priority_lanes),
+//! - `revision`: the revision of the response header,
+//! - `error_code`: the gRPC code of the error the attempt failed with.
+//!
+//! Watch, keep-alive, observe and snapshot streams keep the span of the RPC which created
+//! them, and report every received message as a trace event of that span.
+//!
+//! With [`TraceOptions::with_traceparent`], the W3C `traceparent` of the span is sent in
+//! the metadata of every request, so that the request can be correlated on the server side.
+//!
+//! Without the feature, none of this is compiled.
+
+#[cfg(feature = "tracing")]
+pub(crate) use enabled::*;
+#[cfg(feature = "tracing")]
+pub use enabled::{TraceKeys, TraceOptions};
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use disabled::*;
+
+/// The lane of the bulk requests, recorded as the field `lane` of their spans.
+pub(crate) const BULK_LANE: &str = "bulk";
+
+/// The span of an attempt of the RPC `$rpc`.
+#[cfg(feature = "tracing")]
+macro_rules! rpc_span {
+    ($rpc:literal) => {
+        tracing::debug_span!(
+            concat!("etcd.", $rpc),
+            key = tracing::field::Empty,
+            endpoint = tracing::field::Empty,
+            attempt = tracing::field::Empty,
+            lane = tracing::field::Empty,
+            revision = tracing::field::Empty,
+            error_code = tracing::field::Empty,
+        )
+    };
+}
+
+/// The span of an attempt of the RPC `$rpc`.
+#[cfg(not(feature = "tracing"))]
+macro_rules! rpc_span {
+    ($rpc:literal) => {
+        ()
+    };
+}
+
+/// Reports a message received on a stream as a trace event of the span of the observed
+/// call `$call` which opened it.
+#[cfg(feature = "tracing")]
+macro_rules! stream_event {
+    ($call:expr, $($fields:tt)*) => {
+        tracing::trace!(parent: $call.span(), $($fields)*)
+    };
+}
+
+/// Reports a message received on a stream as a trace event of the span of the observed
+/// call `$call` which opened it.
+#[cfg(not(feature = "tracing"))]
+macro_rules! stream_event {
+    ($call:expr, $($fields:tt)*) => {
+        let _ = &$call;
+    };
+}
+
+pub(crate) use {rpc_span, stream_event};
+
+#[cfg(feature = "tracing")]
+mod enabled {
+    use crate::observe::{ObservedError, ObservedResponse};
+    use http::Uri;
+    use std::fmt::{self, Debug, Formatter};
+    use std::future::Future;
+    use std::sync::Arc;
+    use tonic::metadata::AsciiMetadataValue;
+    use tracing::{Instrument, Span};
+
+    /// The span of an attempt of a RPC.
+    pub(crate) type RpcSpan = Span;
+
+    /// The metadata key of the W3C trace context.
+    pub(crate) const TRACEPARENT_KEY: &str = "traceparent";
+
+    /// How the keys of requests are recorded in their spans.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum TraceKeys {
+        /// Only the length of keys is recorded.
+        #[default]
+        Redacted,
+        /// Up to this number of leading bytes of keys are recorded.
+        Truncated(usize),
+        /// Keys are recorded in full.
+        Full,
+    }
+
+    impl TraceKeys {
+        /// The representation of `key` recorded in spans.
+        pub(crate) fn format(self, key: &[u8]) -> String {
+            match self {
+                TraceKeys::Redacted => format!("<redacted {} bytes>", key.len()),
+                TraceKeys::Truncated(len) if len < key.len() => {
+                    format!("{}...", String::from_utf8_lossy(&key[..len]))
+                }
+                TraceKeys::Truncated(_) | TraceKeys::Full => {
+                    String::from_utf8_lossy(key).into_owned()
+                }
+            }
+        }
+    }
+
+    type TraceparentFn = dyn Fn(&Span) -> Option<String> + Send + Sync;
+
+    /// Options for [`ConnectOptions::with_tracing`](crate::ConnectOptions::with_tracing).
+    #[derive(Clone, Default)]
+    pub struct TraceOptions {
+        keys: TraceKeys,
+        traceparent: Option<Arc<TraceparentFn>>,
+    }
+
+    impl TraceOptions {
+        /// Creates a `TraceOptions` redacting keys and sending no trace context.
+        #[inline]
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Sets how the keys of requests are recorded in their spans.
+        #[inline]
+        pub fn with_keys(mut self, keys: TraceKeys) -> Self {
+            self.keys = keys;
+            self
+        }
+
+        /// Sets the function returning the W3C `traceparent` of the span of a request, which
+        /// is sent in the metadata of the request.
+        ///
+        /// `tracing` spans have no W3C trace context of their own, it is given by the layer
+        /// exporting them, e.g. the `OpenTelemetrySpanExt::context` of `tracing-opentelemetry`.
+        #[inline]
+        pub fn with_traceparent(
+            mut self,
+            traceparent: impl Fn(&Span) -> Option<String> + Send + Sync + 'static,
+        ) -> Self {
+            self.traceparent = Some(Arc::new(traceparent));
+            self
+        }
+
+        /// How the keys of requests are recorded.
+        #[inline]
+        pub(crate) fn keys(&self) -> TraceKeys {
+            self.keys
+        }
+
+        /// The metadata value of the trace context of the current span.
+        pub(crate) fn traceparent(&self) -> Option<AsciiMetadataValue> {
+            let traceparent = self.traceparent.as_ref()?(&Span::current())?;
+            traceparent.parse().ok()
+        }
+    }
+
+    impl Debug for TraceOptions {
+        #[inline]
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TraceOptions")
+                .field("keys", &self.keys)
+                .field("traceparent", &self.traceparent.is_some())
+                .finish()
+        }
+    }
+
+    /// The attempt of a RPC being run.
+    #[derive(Clone, Default)]
+    struct Attempt {
+        number: Option<u32>,
+        endpoint: Option<Uri>,
+        lane: Option<&'static str>,
+    }
+
+    tokio::task_local! {
+        static ATTEMPT: Attempt;
+    }
+
+    /// Runs `fut` as the attempt `number` of a retried RPC.
+    #[inline]
+    pub(crate) fn attempt<F: Future>(number: u32, fut: F) -> impl Future<Output = F::Output> {
+        let mut attempt = ATTEMPT.try_with(Clone::clone).unwrap_or_default();
+        attempt.number = Some(number);
+        ATTEMPT.scope(attempt, fut)
+    }
+
+    /// Runs `fut` as an attempt sent to `endpoint`, in the current span even if it is spawned.
+    #[inline]
+    pub(crate) fn endpoint<F: Future>(endpoint: &Uri, fut: F) -> impl Future<Output = F::Output> {
+        let mut attempt = ATTEMPT.try_with(Clone::clone).unwrap_or_default();
+        attempt.endpoint = Some(endpoint.clone());
+        ATTEMPT.scope(attempt, fut.instrument(Span::current()))
+    }
+
+    /// Runs `fut` as attempts sent over the lane `lane`, if any.
+    #[inline]
+    pub(crate) fn lane<F: Future>(
+        lane: Option<&'static str>,
+        fut: F,
+    ) -> impl Future<Output = F::Output> {
+        let mut attempt = ATTEMPT.try_with(Clone::clone).unwrap_or_default();
+        attempt.lane = lane.or(attempt.lane);
+        ATTEMPT.scope(attempt, fut)
+    }
+
+    /// Records `key` in `span` according to `keys`.
+    #[inline]
+    pub(crate) fn record_key(span: &Span, keys: TraceKeys, key: &[u8]) {
+        if !span.is_disabled() {
+            span.record("key", keys.format(key));
+        }
+    }
+
+    /// Records the attempt of the RPC starting in `span`.
+    pub(crate) fn start(span: &Span) {
+        if !span.is_disabled() {
+            let _ = ATTEMPT.try_with(|attempt| {
+                if let Some(number) = attempt.number {
+                    span.record("attempt", number);
+                }
+                if let Some(endpoint) = &attempt.endpoint {
+                    span.record("endpoint", tracing::field::display(endpoint));
+                }
+                if let Some(lane) = attempt.lane {
+                    span.record("lane", lane);
+                }
+            });
+        }
+    }
+
+    /// Records the outcome of the RPC which ran in `span`.
+    pub(crate) fn finish<T, E>(span: &Span, result: &Result<T, E>)
+    where
+        T: ObservedResponse,
+        E: ObservedError,
+    {
+        match result {
+            Ok(resp) => {
+                if let Some(revision) = resp.revision() {
+                    span.record("revision", revision);
+                }
+            }
+            Err(e) => {
+                span.record("error_code", tracing::field::debug(e.code()));
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod disabled {
+    use http::Uri;
+
+    /// The span of an attempt of a RPC.
+    pub(crate) type RpcSpan = ();
+
+    /// Runs `fut` as the attempt `number` of a retried RPC.
+    #[inline(always)]
+    pub(crate) fn attempt<F>(_number: u32, fut: F) -> F {
+        fut
+    }
+
+    /// Runs `fut` as an attempt sent to `endpoint`, in the current span even if it is spawned.
+    #[inline(always)]
+    pub(crate) fn endpoint<F>(_endpoint: &Uri, fut: F) -> F {
+        fut
+    }
+
+    /// Runs `fut` as attempts sent over the lane `lane`, if any.
+    #[inline(always)]
+    pub(crate) fn lane<F>(_lane: Option<&'static str>, fut: F) -> F {
+        fut
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::channel::Channel;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::lock::MutexExt;
+    use crate::observe::Observer;
+    use crate::rpc::kv::KvClient;
+    use crate::rpc::pb::etcdserverpb::{PutResponse as PbPutResponse, ResponseHeader};
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+    use prost::Message;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tonic::codegen::Bytes;
+    use tower::util::BoxCloneService;
+    use tower::ServiceExt;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    type Spans = Arc<Mutex<Vec<(&'static str, HashMap<String, String>)>>>;
+
+    /// A subscriber recording the names and fields of spans.
+    #[derive(Default)]
+    struct Recorder {
+        spans: Spans,
+        metadata: Mutex<Vec<&'static Metadata<'static>>>,
+        entered: Mutex<Vec<Id>>,
+    }
+
+    struct Fields<'a>(&'a mut HashMap<String, String>);
+
+    impl Visit for Fields<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl tracing::Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = HashMap::new();
+            span.record(&mut Fields(&mut fields));
+            let mut spans = self.spans.lock_unpoisoned();
+            spans.push((span.metadata().name(), fields));
+            self.metadata.lock_unpoisoned().push(span.metadata());
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.spans.lock_unpoisoned();
+            values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].1));
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, span: &Id) {
+            self.entered.lock_unpoisoned().push(span.clone());
+        }
+
+        fn exit(&self, _span: &Id) {
+            self.entered.lock_unpoisoned().pop();
+        }
+
+        fn current_span(&self) -> tracing_core::span::Current {
+            match self.entered.lock_unpoisoned().last() {
+                Some(span) => {
+                    let metadata = self.metadata.lock_unpoisoned()[span.into_u64() as usize - 1];
+                    tracing_core::span::Current::new(span.clone(), metadata)
+                }
+                None => tracing_core::span::Current::none(),
+            }
+        }
+    }
+
+    /// A client whose puts succeed at revision 42 and whose ranges fail, recording the
+    /// `traceparent` of the requests.
+    fn client() -> (KvClient, Arc<Mutex<Vec<String>>>) {
+        let traceparents = Arc::new(Mutex::new(Vec::new()));
+        let service = tower::service_fn({
+            let traceparents = traceparents.clone();
+            move |req: http::Request<tonic::body::Body>| {
+                if let Some(traceparent) = req.headers().get(TRACEPARENT_KEY) {
+                    traceparents
+                        .lock_unpoisoned()
+                        .push(traceparent.to_str().unwrap().to_string());
+                }
+                async move {
+                    if req.uri().path().ends_with("/Range") {
+                        let status = tonic::Status::unavailable("etcdserver: no leader");
+                        return Ok::<_, tower::BoxError>(status.into_http());
+                    }
+                    let msg = PbPutResponse {
+                        header: Some(ResponseHeader {
+                            revision: 42,
+                            ..Default::default()
+                        }),
+                        prev_kv: None,
+                    };
+                    let mut buf = vec![0];
+                    buf.extend_from_slice(&(msg.encoded_len() as u32).to_be_bytes());
+                    msg.encode(&mut buf).unwrap();
+                    let mut trailers = http::HeaderMap::new();
+                    tonic::Status::ok("").add_header(&mut trailers).unwrap();
+                    let frames = [
+                        Ok::<_, tower::BoxError>(Frame::data(Bytes::from(buf))),
+                        Ok(Frame::trailers(trailers)),
+                    ];
+                    let body = tonic::body::Body::new(StreamBody::new(tokio_stream::iter(frames)));
+                    Ok(http::Response::builder()
+                        .header("content-type", "application/grpc")
+                        .body(body)
+                        .unwrap())
+                }
+            }
+        });
+        let options = TraceOptions::new()
+            .with_keys(TraceKeys::Truncated(3))
+            .with_traceparent(|span| {
+                let name = span.metadata()?.name();
+                name.starts_with("etcd.").then(|| TRACEPARENT.to_string())
+            });
+        let channel = InterceptedChannel::new(
+            Channel::Custom(BoxCloneService::new(service.boxed_clone())),
+            Interceptor {
+                tracing: Some(options.clone()),
+                ..Default::default()
+            },
+        );
+        let observer = Observer::default().with_trace_keys(options.keys());
+        let client = KvClient::new(channel, AuthToken::default()).with_observer(observer);
+        (client, traceparents)
+    }
+
+    #[tokio::test]
+    async fn test_rpc_spans() {
+        let spans = Spans::default();
+        let _guard = tracing::subscriber::set_default(Recorder {
+            spans: spans.clone(),
+            ..Default::default()
+        });
+        let (mut client, traceparents) = client();
+
+        client.put("foobar", "value", None).await.unwrap();
+        assert!(client.get("foo", None).await.is_err());
+
+        let spans = spans.lock_unpoisoned();
+        let (_, put) = spans.iter().find(|(name, _)| *name == "etcd.Put").unwrap();
+        assert_eq!(put["key"], "foo...");
+        assert_eq!(put["revision"], "42");
+        assert!(!put.contains_key("error_code"));
+        let (_, range) = spans
+            .iter()
+            .find(|(name, _)| *name == "etcd.Range")
+            .unwrap();
+        assert_eq!(range["key"], "foo");
+        assert_eq!(range["attempt"], "1");
+        assert_eq!(range["error_code"], "Some(Unavailable)");
+        assert_eq!(*traceparents.lock_unpoisoned(), [TRACEPARENT, TRACEPARENT]);
+    }
+
+    #[test]
+    fn test_trace_keys() {
+        assert_eq!(TraceKeys::Redacted.format(b"secret"), "<redacted 6 bytes>");
+        assert_eq!(TraceKeys::Truncated(3).format(b"secret"), "sec...");
+        assert_eq!(TraceKeys::Truncated(10).format(b"secret"), "secret");
+        assert_eq!(TraceKeys::Full.format(b"secret"), "secret");
+    }
+}
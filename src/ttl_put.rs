@@ -0,0 +1,225 @@
+//! Putting keys which disappear after a time to live.
+
+use crate::bytes::DebugBytes;
+use crate::error::{Error, Result};
+use crate::ids::{LeaseId, Revision};
+use crate::keep_alive::KeptAlive;
+use crate::rpc::kv::{KvClient, PutOptions, Txn, TxnOp};
+use std::fmt::{self, Debug, Formatter};
+use std::time::Duration;
+
+/// Options for [`KvClient::put_with_ttl`].
+#[derive(Debug, Default, Clone)]
+pub struct PutTtlOptions {
+    keep_alive: bool,
+}
+
+impl PutTtlOptions {
+    /// Creates a `PutTtlOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self { keep_alive: false }
+    }
+
+    /// Keeps the lease alive from the start, as [`TtlPut::keep_alive`] does.
+    #[inline]
+    pub const fn with_keep_alive(mut self) -> Self {
+        self.keep_alive = true;
+        self
+    }
+}
+
+/// Keys put with a time to live, see [`KvClient::put_with_ttl`].
+///
+/// The keys are deleted once their lease expires, unless it is kept alive by
+/// [`TtlPut::keep_alive`] or they are made permanent by [`TtlPut::persist`]. Dropping the
+/// handle stops keeping the lease alive, the keys then expire after the TTL.
+pub struct TtlPut {
+    kv: KvClient,
+    id: i64,
+    ttl: i64,
+    revision: i64,
+    keys: Vec<Vec<u8>>,
+    kept_alive: Option<KeptAlive>,
+}
+
+impl TtlPut {
+    /// The ID of the lease the keys are attached to.
+    #[inline]
+    pub const fn lease_id(&self) -> LeaseId {
+        LeaseId::new(self.id)
+    }
+
+    /// The TTL of the lease in seconds, as granted by the server.
+    #[inline]
+    pub const fn ttl(&self) -> i64 {
+        self.ttl
+    }
+
+    /// The revision the keys were put at.
+    #[inline]
+    pub const fn revision(&self) -> Revision {
+        Revision::new(self.revision)
+    }
+
+    /// The keys put.
+    #[inline]
+    pub fn keys(&self) -> &[Vec<u8>] {
+        &self.keys
+    }
+
+    /// Keeps the lease alive in the background, along with the leases of the sessions of the
+    /// client, until the handle is dropped or the keys are persisted.
+    pub async fn keep_alive(&mut self) -> Result<()> {
+        if self.kept_alive.is_none() {
+            let kept_alive = self.kv.lease.keep_alive_shared(self.id, self.ttl).await?;
+            self.kept_alive = Some(kept_alive);
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the lease is being kept alive, see [`TtlPut::keep_alive`].
+    #[inline]
+    pub fn is_kept_alive(&self) -> bool {
+        self.kept_alive.as_ref().is_some_and(|kept| !kept.is_done())
+    }
+
+    /// Detaches the keys from the lease in a single txn, keeping their values, so that they
+    /// are not deleted anymore. Returns the revision of the txn.
+    ///
+    /// The lease, attached to no key then, is left to expire. Fails with an
+    /// [`Error::LeaseExpired`] if a key has been deleted already.
+    pub async fn persist(mut self) -> Result<Revision> {
+        let options = PutOptions::new().with_ignore_value();
+        let puts: Vec<TxnOp> = self
+            .keys
+            .iter()
+            .map(|key| TxnOp::put(key.clone(), Vec::new(), Some(options.clone())))
+            .collect();
+        let resp = match self.kv.txn(Txn::new().and_then(puts)).await {
+            Err(Error::KeyNotFound { status }) => {
+                return Err(Error::LeaseExpired {
+                    id: LeaseId::new(self.id),
+                    status: Some(status),
+                })
+            }
+            resp => resp?,
+        };
+        Ok(resp
+            .header()
+            .map_or(Revision::current(), |header| header.revision()))
+    }
+}
+
+impl Debug for TtlPut {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let keys: Vec<_> = self.keys.iter().map(|key| DebugBytes(key)).collect();
+        f.debug_struct("TtlPut")
+            .field("id", &self.id)
+            .field("ttl", &self.ttl)
+            .field("revision", &self.revision)
+            .field("keys", &keys)
+            .field("kept_alive", &self.is_kept_alive())
+            .finish_non_exhaustive()
+    }
+}
+
+impl KvClient {
+    /// Puts `key` attached to a new lease of `ttl`, so that it is deleted once the TTL
+    /// elapses, unless the returned handle keeps the lease alive or persists the key.
+    ///
+    /// Leases have a granularity of one second: `ttl` is rounded up to whole seconds and
+    /// fails with an [`Error::LeaseTtlTooSmall`] under a second. etcd raises the TTLs under
+    /// its minimum, one and a half times its election timeout, to that minimum, see
+    /// [`TtlPut::ttl`] for the TTL granted.
+    #[inline]
+    pub async fn put_with_ttl(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: impl Into<Vec<u8>>,
+        ttl: Duration,
+        options: Option<PutTtlOptions>,
+    ) -> Result<TtlPut> {
+        self.put_many_with_ttl([(key, value)], ttl, options).await
+    }
+
+    /// Puts `kvs` attached to a single new lease of `ttl` in a single txn, see
+    /// [`KvClient::put_with_ttl`].
+    pub async fn put_many_with_ttl<K, V>(
+        &mut self,
+        kvs: impl IntoIterator<Item = (K, V)>,
+        ttl: Duration,
+        options: Option<PutTtlOptions>,
+    ) -> Result<TtlPut>
+    where
+        K: Into<Vec<u8>>,
+        V: Into<Vec<u8>>,
+    {
+        let kvs: Vec<(Vec<u8>, Vec<u8>)> = kvs
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        if kvs.is_empty() {
+            return Err(Error::InvalidArgs("no key to put".to_string()));
+        }
+        let options = options.unwrap_or_default();
+        let grant = self.lease.grant(ttl_seconds(ttl)?, None).await?;
+        let (id, ttl) = (grant.id().get(), grant.ttl());
+
+        let keys: Vec<Vec<u8>> = kvs.iter().map(|(key, _)| key.clone()).collect();
+        let puts: Vec<TxnOp> = kvs
+            .into_iter()
+            .map(|(key, value)| TxnOp::put(key, value, Some(PutOptions::new().with_lease(id))))
+            .collect();
+        let resp = match self.txn(Txn::new().and_then(puts)).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                // The lease is of no use without the keys, and deletes them had they been put.
+                let _ = self.lease.revoke(id).await;
+                return Err(e.with_lease_id(id));
+            }
+        };
+
+        let mut put = TtlPut {
+            kv: self.clone(),
+            id,
+            ttl,
+            revision: resp.header().map_or(0, |header| header.revision().get()),
+            keys,
+            kept_alive: None,
+        };
+        if options.keep_alive {
+            put.keep_alive().await?;
+        }
+        Ok(put)
+    }
+}
+
+/// The TTL of a lease in seconds for `ttl`, rounded up.
+fn ttl_seconds(ttl: Duration) -> Result<i64> {
+    if ttl < Duration::from_secs(1) {
+        return Err(Error::LeaseTtlTooSmall { ttl });
+    }
+    let seconds = ttl
+        .as_secs()
+        .saturating_add(u64::from(ttl.subsec_nanos() > 0));
+    Ok(i64::try_from(seconds).unwrap_or(i64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl_seconds() {
+        assert_eq!(ttl_seconds(Duration::from_secs(1)).unwrap(), 1);
+        assert_eq!(ttl_seconds(Duration::from_millis(1500)).unwrap(), 2);
+        assert_eq!(ttl_seconds(Duration::from_secs(60)).unwrap(), 60);
+        assert_eq!(ttl_seconds(Duration::MAX).unwrap(), i64::MAX);
+
+        let err = ttl_seconds(Duration::from_millis(999)).unwrap_err();
+        assert!(matches!(err, Error::LeaseTtlTooSmall { ttl } if ttl.as_millis() == 999));
+        assert_eq!(err.to_string(), "lease TTL 999ms is under one second");
+        assert!(ttl_seconds(Duration::ZERO).is_err());
+    }
+}
@@ -0,0 +1,438 @@
+//! Applying more operations than a txn may hold, by chunks of txns.
+
+use crate::error::{Error, Result};
+use crate::rpc::kv::{GetOptions, KvClient, Txn, TxnOp, TxnResponse};
+use std::ops::Range;
+use std::time::Instant;
+use tokio::task::JoinSet;
+
+/// The maximum number of operations of a txn, the default `--max-txn-ops` of etcd.
+pub const DEFAULT_MAX_TXN_OPS: usize = 128;
+
+/// The number of operations of a txn probed at most by [`KvClient::max_txn_ops`].
+const MAX_PROBED_TXN_OPS: usize = 1 << 16;
+
+/// The key counted by the operations of the probes.
+const PROBE_KEY: &[u8] = b"\0";
+
+/// Options for [`KvClient::txn_chunked`].
+#[derive(Debug, Clone)]
+pub struct ChunkedTxnOptions {
+    max_ops: usize,
+    probe: bool,
+    concurrency: usize,
+    deadline: Option<Instant>,
+}
+
+impl Default for ChunkedTxnOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChunkedTxnOptions {
+    /// Creates a `ChunkedTxnOptions`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            max_ops: DEFAULT_MAX_TXN_OPS,
+            probe: false,
+            concurrency: 1,
+            deadline: None,
+        }
+    }
+
+    /// Sets the number of operations per chunk, [`DEFAULT_MAX_TXN_OPS`] by default, which
+    /// must not exceed the `--max-txn-ops` of the server.
+    #[inline]
+    pub const fn with_max_ops(mut self, max_ops: usize) -> Self {
+        self.max_ops = max_ops;
+        self
+    }
+
+    /// Probes the maximum number of operations of the server, see
+    /// [`KvClient::max_txn_ops`], rather than using [`ChunkedTxnOptions::with_max_ops`].
+    #[inline]
+    pub const fn with_probed_max_ops(mut self) -> Self {
+        self.probe = true;
+        self
+    }
+
+    /// Sets the number of chunks applied concurrently, one by default. Concurrent chunks
+    /// are applied in no particular order.
+    #[inline]
+    pub const fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Aborts the chunks, and the probes of the maximum number of operations, which have
+    /// not completed by `deadline`. The chunks not started by then are not sent.
+    #[inline]
+    pub const fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+}
+
+/// The results of the chunks of a [`KvClient::txn_chunked`].
+#[derive(Debug)]
+pub struct ChunkedTxnResponse {
+    max_ops: usize,
+    ops: usize,
+    chunks: Vec<Result<TxnResponse>>,
+}
+
+impl ChunkedTxnResponse {
+    /// The number of operations per chunk.
+    #[inline]
+    pub const fn max_ops(&self) -> usize {
+        self.max_ops
+    }
+
+    /// The results of the chunks sent, in the order of their operations. The chunks are
+    /// not sent anymore once one fails or the deadline passes, see
+    /// [`ChunkedTxnResponse::unsent`].
+    #[inline]
+    pub fn chunks(&self) -> &[Result<TxnResponse>] {
+        &self.chunks
+    }
+
+    /// Takes the results of the chunks sent.
+    #[inline]
+    pub fn into_chunks(self) -> Vec<Result<TxnResponse>> {
+        self.chunks
+    }
+
+    /// The indices of the operations of the chunk `chunk`.
+    #[inline]
+    pub fn chunk_ops(&self, chunk: usize) -> Range<usize> {
+        let start = (chunk * self.max_ops).min(self.ops);
+        start..(start + self.max_ops).min(self.ops)
+    }
+
+    /// The indices of the operations of the chunks not sent.
+    #[inline]
+    pub fn unsent(&self) -> Range<usize> {
+        (self.chunks.len() * self.max_ops).min(self.ops)..self.ops
+    }
+
+    /// Returns `true` if all the chunks were applied.
+    #[inline]
+    pub fn is_applied(&self) -> bool {
+        self.unsent().is_empty() && self.chunks.iter().all(Result::is_ok)
+    }
+}
+
+impl KvClient {
+    /// Applies `ops`, which may be more than a txn holds, by txns of up to the maximum number
+    /// of operations of the server, the chunks.
+    ///
+    /// The operations are atomic within their chunk only: a chunk may be applied while
+    /// another fails, and the other clients may read the changes of the chunks applied so
+    /// far. The chunks are applied one after another by default, and not sent anymore
+    /// once one fails. Dropping the future aborts the chunks in flight, which may have been
+    /// applied or not.
+    ///
+    /// Fails with an [`Error::TxnNotSplittable`] without sending any chunk if an operation
+    /// is a txn with compares, as they would not guard the operations of the other chunks.
+    /// A chunk rejected with an [`Error::TxnTooManyOps`] has the `limit` of the server set,
+    /// for a retry of the operations not applied.
+    pub async fn txn_chunked(
+        &mut self,
+        ops: impl Into<Vec<TxnOp>>,
+        options: Option<ChunkedTxnOptions>,
+    ) -> Result<ChunkedTxnResponse> {
+        let ops = ops.into();
+        if let Some(op) = ops.iter().position(TxnOp::has_compares) {
+            return Err(Error::TxnNotSplittable { op });
+        }
+        let options = options.unwrap_or_default();
+        let max_ops = match options.probe {
+            true => self.probe_max_txn_ops(options.deadline).await?,
+            false => options.max_ops.max(1),
+        };
+
+        let mut chunks = ops.chunks(max_ops).map(<[TxnOp]>::to_vec).enumerate();
+        let mut results: Vec<Option<Result<TxnResponse>>> = Vec::new();
+        results.resize_with(ops.len().div_ceil(max_ops), || None);
+        let mut running = JoinSet::new();
+        let mut failed = false;
+        loop {
+            while running.len() < options.concurrency.max(1)
+                && !failed
+                && options
+                    .deadline
+                    .map_or(true, |deadline| Instant::now() < deadline)
+            {
+                let Some((index, chunk)) = chunks.next() else {
+                    break;
+                };
+                let mut txn = Txn::new().and_then(chunk);
+                if let Some(deadline) = options.deadline {
+                    txn = txn.with_deadline(deadline);
+                }
+                let mut kv = self.clone();
+                running.spawn(async move { (index, kv.txn(txn).await) });
+            }
+            let (index, result) = match running.join_next().await {
+                Some(Ok(chunk)) => chunk,
+                Some(Err(e)) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                _ => break,
+            };
+            let result = match result {
+                Err(Error::TxnTooManyOps {
+                    limit: None,
+                    status,
+                }) => Err(Error::TxnTooManyOps {
+                    limit: self.probe_max_txn_ops(options.deadline).await.ok(),
+                    status,
+                }),
+                result => result,
+            };
+            failed |= result.is_err();
+            results[index] = Some(result);
+        }
+
+        Ok(ChunkedTxnResponse {
+            max_ops,
+            ops: ops.len(),
+            // The chunks sent are the first ones, which all completed.
+            chunks: results.into_iter().map_while(|result| result).collect(),
+        })
+    }
+
+    /// Probes the maximum number of operations of a txn, the `--max-txn-ops` of the
+    /// server, up to 65536.
+    ///
+    /// The probes are txns reading the count of a key, as many as the search of the limit
+    /// takes, about ten for the default limit.
+    #[inline]
+    pub async fn max_txn_ops(&mut self) -> Result<usize> {
+        self.probe_max_txn_ops(None).await
+    }
+
+    /// Probes the maximum number of operations of a txn, the probes aborted by `deadline`.
+    async fn probe_max_txn_ops(&mut self, deadline: Option<Instant>) -> Result<usize> {
+        // The largest number of operations known to fit, and the smallest known not to.
+        let (mut fits, mut over) = (0, None);
+        let mut ops = DEFAULT_MAX_TXN_OPS;
+        loop {
+            let op = TxnOp::get(PROBE_KEY, Some(GetOptions::new().with_count_only()));
+            let mut txn = Txn::new().and_then(vec![op; ops]);
+            if let Some(deadline) = deadline {
+                txn = txn.with_deadline(deadline);
+            }
+            match self.txn(txn).await {
+                Ok(_) => fits = ops,
+                Err(Error::TxnTooManyOps { .. }) => over = Some(ops),
+                Err(e) => return Err(e),
+            }
+            ops = match over {
+                Some(over) if over - fits <= 1 => return Ok(fits.max(1)),
+                Some(over) => fits + (over - fits) / 2,
+                None if fits >= MAX_PROBED_TXN_OPS => return Ok(fits),
+                None => (fits * 2).min(MAX_PROBED_TXN_OPS),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthToken;
+    use crate::channel::Channel;
+    use crate::intercept::{InterceptedChannel, Interceptor};
+    use crate::lock::MutexExt;
+    use crate::rpc::kv::{Compare, CompareOp};
+    use crate::rpc::pb::etcdserverpb::request_op::Request as PbTxnOp;
+    use crate::rpc::pb::etcdserverpb::{
+        ResponseHeader as PbResponseHeader, TxnRequest as PbTxnRequest,
+        TxnResponse as PbTxnResponse,
+    };
+    use std::sync::{Arc, Mutex};
+    use tonic::codec::ProstCodec;
+    use tonic::server::Grpc;
+    use tonic::Status;
+    use tower::util::BoxCloneService;
+
+    /// A store allowing txns of up to `max_ops` operations.
+    #[derive(Clone)]
+    struct Store {
+        max_ops: usize,
+        /// The keys put, in order.
+        keys: Arc<Mutex<Vec<Vec<u8>>>>,
+        /// The number of txns received.
+        txns: Arc<Mutex<usize>>,
+    }
+
+    impl Store {
+        fn new(max_ops: usize) -> Self {
+            Self {
+                max_ops,
+                keys: Arc::default(),
+                txns: Arc::default(),
+            }
+        }
+
+        fn client(&self) -> KvClient {
+            let store = self.clone();
+            let service = tower::service_fn(move |req: http::Request<tonic::body::Body>| {
+                let store = store.clone();
+                async move {
+                    let service = tower::service_fn(|req: tonic::Request<PbTxnRequest>| {
+                        let resp = store.txn(req.into_inner());
+                        async move { resp.map(tonic::Response::new) }
+                    });
+                    let resp = Grpc::new(ProstCodec::default()).unary(service, req).await;
+                    Ok::<_, tower::BoxError>(resp)
+                }
+            });
+            let channel = InterceptedChannel::new(
+                Channel::Custom(BoxCloneService::new(service)),
+                Interceptor::default(),
+            );
+            KvClient::new(channel, AuthToken::default())
+        }
+
+        fn txn(&self, txn: PbTxnRequest) -> std::result::Result<PbTxnResponse, Status> {
+            let mut txns = self.txns.lock_unpoisoned();
+            *txns += 1;
+            if txn.success.len() > self.max_ops {
+                return Err(Status::invalid_argument(
+                    "etcdserver: too many operations in txn request",
+                ));
+            }
+            let mut keys = self.keys.lock_unpoisoned();
+            for op in txn.success {
+                if let Some(PbTxnOp::RequestPut(put)) = op.request {
+                    keys.push(put.key.to_vec());
+                }
+            }
+            Ok(PbTxnResponse {
+                header: Some(PbResponseHeader {
+                    revision: *txns as i64,
+                    ..Default::default()
+                }),
+                succeeded: true,
+                ..Default::default()
+            })
+        }
+    }
+
+    fn puts(n: usize) -> Vec<TxnOp> {
+        (0..n)
+            .map(|i| TxnOp::put(format!("key/{:03}", i), "value", None))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_txn_chunked() {
+        let store = Store::new(DEFAULT_MAX_TXN_OPS);
+        let resp = store.client().txn_chunked(puts(300), None).await.unwrap();
+        assert!(resp.is_applied());
+        assert_eq!(resp.chunks().len(), 3);
+        assert_eq!(resp.chunk_ops(2), 256..300);
+        assert!(resp.unsent().is_empty());
+        let revisions: Vec<i64> = resp
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.as_ref().unwrap().header().unwrap().revision().get())
+            .collect();
+        assert_eq!(revisions, [1, 2, 3]);
+
+        let keys: Vec<Vec<u8>> = (0..300).map(|i| format!("key/{:03}", i).into()).collect();
+        assert_eq!(*store.keys.lock_unpoisoned(), keys);
+    }
+
+    #[tokio::test]
+    async fn test_txn_chunked_concurrently() {
+        let store = Store::new(DEFAULT_MAX_TXN_OPS);
+        let options = ChunkedTxnOptions::new()
+            .with_max_ops(50)
+            .with_concurrency(4);
+        let resp = store
+            .client()
+            .txn_chunked(puts(300), Some(options))
+            .await
+            .unwrap();
+        assert!(resp.is_applied());
+        assert_eq!(resp.chunks().len(), 6);
+
+        let mut keys = store.keys.lock_unpoisoned().clone();
+        keys.sort();
+        assert_eq!(keys.len(), 300);
+        keys.dedup();
+        assert_eq!(keys.len(), 300);
+    }
+
+    #[tokio::test]
+    async fn test_txn_chunked_compares() {
+        let store = Store::new(DEFAULT_MAX_TXN_OPS);
+        let guarded = Txn::new()
+            .when([Compare::version("key", CompareOp::Equal, 0)])
+            .and_then(puts(1));
+        let nested = Txn::new().and_then([TxnOp::txn(guarded)]);
+        let mut ops = puts(2);
+        ops.push(TxnOp::txn(nested));
+        let err = store.client().txn_chunked(ops, None).await.unwrap_err();
+        assert!(
+            matches!(err, Error::TxnNotSplittable { op: 2 }),
+            "{:?}",
+            err
+        );
+        assert_eq!(*store.txns.lock_unpoisoned(), 0);
+
+        // Nested txns without compares are kept whole within their chunk.
+        let ops = [TxnOp::txn(Txn::new().and_then(puts(2)))];
+        let resp = store.client().txn_chunked(ops, None).await.unwrap();
+        assert!(resp.is_applied());
+    }
+
+    #[tokio::test]
+    async fn test_max_txn_ops() {
+        for max_ops in [1, 100, DEFAULT_MAX_TXN_OPS, 1000] {
+            let store = Store::new(max_ops);
+            assert_eq!(store.client().max_txn_ops().await.unwrap(), max_ops);
+        }
+
+        let store = Store::new(100);
+        let options = ChunkedTxnOptions::new().with_probed_max_ops();
+        let resp = store
+            .client()
+            .txn_chunked(puts(300), Some(options))
+            .await
+            .unwrap();
+        assert!(resp.is_applied());
+        assert_eq!(resp.max_ops(), 100);
+        assert_eq!(resp.chunks().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_txn_chunked_too_many_ops() {
+        let store = Store::new(100);
+        let resp = store.client().txn_chunked(puts(300), None).await.unwrap();
+        assert!(!resp.is_applied());
+        assert_eq!(resp.chunks().len(), 1);
+        assert_eq!(resp.unsent(), 128..300);
+        let err = resp.chunks()[0].as_ref().unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::TxnTooManyOps {
+                    limit: Some(100),
+                    ..
+                }
+            ),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            err.to_string(),
+            "Txn failed: txn request has more than 100 operations"
+        );
+        assert!(store.keys.lock_unpoisoned().is_empty());
+    }
+}
@@ -0,0 +1,424 @@
+//! Values encoded by the client, e.g. compressed, and decoded back transparently.
+
+use crate::error::{Error, Result};
+use crate::rpc::kv::{
+    DeleteOptions, DeleteResponse, GetOptions, GetResponse, KvClient, PutOptions, PutResponse, Txn,
+    TxnResponse,
+};
+use crate::rpc::pb::mvccpb::KeyValue as PbKeyValue;
+#[cfg(feature = "watch")]
+use crate::rpc::watch::{WatchClient, WatchOptions, WatchResponse, WatchStream, Watcher};
+use prost::bytes::Bytes;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+#[cfg(feature = "watch")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+#[cfg(feature = "watch")]
+use tokio_stream::Stream;
+
+/// An encoding of the values of keys, see
+/// [`Client::with_value_codec`](crate::Client::with_value_codec).
+pub trait ValueCodec: Send + Sync + 'static {
+    /// The prefix marking the values encoded by the codec, telling them from the values
+    /// written without it.
+    ///
+    /// The prefix must not be empty, and is best a byte string which the values written
+    /// without the codec never start with, e.g. starting with a NUL byte for JSON or text.
+    fn magic(&self) -> &[u8];
+
+    /// Encodes `value`, the prefix left out.
+    fn encode(&self, value: &[u8]) -> std::io::Result<Vec<u8>>;
+
+    /// Decodes `encoded`, a value encoded by [`ValueCodec::encode`] without its prefix.
+    fn decode(&self, encoded: &[u8]) -> std::io::Result<Vec<u8>>;
+}
+
+/// The compression of values with zstd.
+#[cfg(feature = "value-zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "value-zstd")))]
+#[derive(Debug, Clone, Copy)]
+pub struct ZstdCodec {
+    level: i32,
+}
+
+#[cfg(feature = "value-zstd")]
+impl ZstdCodec {
+    /// The prefix of the values compressed, see [`ValueCodec::magic`].
+    pub const MAGIC: &'static [u8] = b"\0ez1";
+
+    /// Creates a codec compressing at the default level of zstd.
+    #[inline]
+    pub const fn new() -> Self {
+        Self { level: 0 }
+    }
+
+    /// Compresses at `level`, from 1 to 22, `0` being the default level of zstd.
+    #[inline]
+    pub const fn with_level(mut self, level: i32) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+#[cfg(feature = "value-zstd")]
+impl Default for ZstdCodec {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "value-zstd")]
+impl ValueCodec for ZstdCodec {
+    #[inline]
+    fn magic(&self) -> &[u8] {
+        Self::MAGIC
+    }
+
+    #[inline]
+    fn encode(&self, value: &[u8]) -> std::io::Result<Vec<u8>> {
+        zstd::bulk::compress(value, self.level)
+    }
+
+    #[inline]
+    fn decode(&self, encoded: &[u8]) -> std::io::Result<Vec<u8>> {
+        zstd::stream::decode_all(encoded)
+    }
+}
+
+/// The codec of a client, and the values it leaves as they are.
+#[derive(Clone)]
+struct Codec {
+    codec: Arc<dyn ValueCodec>,
+    min_size: usize,
+}
+
+impl Codec {
+    /// Encodes `value`, unless it is shorter than the minimum size and can not be mistaken
+    /// for an encoded value.
+    fn encode(&self, value: &[u8]) -> Result<Option<Vec<u8>>> {
+        let magic = self.codec.magic();
+        // An empty value stays empty, e.g. for puts ignoring their value.
+        if value.is_empty() || (value.len() < self.min_size && !value.starts_with(magic)) {
+            return Ok(None);
+        }
+        let encoded = self.codec.encode(value)?;
+        Ok(Some([magic, &encoded].concat()))
+    }
+
+    /// Encodes the value of a request in place.
+    #[inline]
+    fn encode_bytes(&self, value: &mut Bytes) -> Result<()> {
+        if let Some(encoded) = self.encode(value)? {
+            *value = encoded.into();
+        }
+        Ok(())
+    }
+
+    /// Decodes the value of `kv` in place, if encoded.
+    fn decode(&self, kv: &mut PbKeyValue) -> Result<()> {
+        let Some(encoded) = kv.value.strip_prefix(self.codec.magic()) else {
+            return Ok(());
+        };
+        kv.value = self
+            .codec
+            .decode(encoded)
+            .map_err(|source| Error::ValueDecode {
+                key: kv.key.clone(),
+                source,
+            })?;
+        Ok(())
+    }
+}
+
+/// A client of the key-value store encoding the values it writes and decoding the values it
+/// reads, see [`Client::with_value_codec`](crate::Client::with_value_codec).
+#[derive(Clone)]
+pub struct ValueCodecClient {
+    kv: KvClient,
+    #[cfg(feature = "watch")]
+    watch: WatchClient,
+    codec: Codec,
+}
+
+impl ValueCodecClient {
+    /// Creates a client encoding the values of `kv` and `watch` with `codec`.
+    pub(crate) fn new(
+        kv: KvClient,
+        #[cfg(feature = "watch")] watch: WatchClient,
+        codec: impl ValueCodec,
+    ) -> Self {
+        Self {
+            kv,
+            #[cfg(feature = "watch")]
+            watch,
+            codec: Codec {
+                codec: Arc::new(codec),
+                min_size: 0,
+            },
+        }
+    }
+
+    /// Leaves the values shorter than `min_size` bytes as they are, unless they start with
+    /// the prefix of the codec, `0` by default.
+    ///
+    /// Compressing small values saves little, if anything.
+    #[inline]
+    pub fn with_min_size(mut self, min_size: usize) -> Self {
+        self.codec.min_size = min_size;
+        self
+    }
+
+    /// Puts the given key into the key-value store with its value encoded, see
+    /// [`KvClient::put`].
+    pub async fn put(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: impl Into<Vec<u8>>,
+        options: Option<PutOptions>,
+    ) -> Result<PutResponse> {
+        let value = value.into();
+        let value = self.codec.encode(&value)?.unwrap_or(value);
+        let mut resp = self.kv.put(key, value, options).await?;
+        resp.try_for_each_kv(&mut |kv| self.codec.decode(kv))?;
+        Ok(resp)
+    }
+
+    /// Gets the key from the key-value store with its value decoded, see [`KvClient::get`].
+    pub async fn get(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<GetOptions>,
+    ) -> Result<GetResponse> {
+        let mut resp = self.kv.get(key, options).await?;
+        resp.try_for_each_kv(&mut |kv| self.codec.decode(kv))?;
+        Ok(resp)
+    }
+
+    /// Deletes the given key from the key-value store, with the previous values decoded,
+    /// see [`KvClient::delete`].
+    pub async fn delete(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResponse> {
+        let mut resp = self.kv.delete(key, options).await?;
+        resp.try_for_each_kv(&mut |kv| self.codec.decode(kv))?;
+        Ok(resp)
+    }
+
+    /// Processes multiple operations in a single transaction, see [`KvClient::txn`], with
+    /// the values of the puts encoded and the values read decoded.
+    ///
+    /// The values of the compares are encoded too, so a compare on a value only succeeds if
+    /// the value was written by a client with the same codec, compressing to the same bytes.
+    /// A compare on the revision of the key is more reliable.
+    pub async fn txn(&mut self, mut txn: Txn) -> Result<TxnResponse> {
+        txn.try_for_each_value(&mut |value| self.codec.encode_bytes(value))?;
+        let mut resp = self.kv.txn(txn).await?;
+        resp.try_for_each_kv(&mut |kv| self.codec.decode(kv))?;
+        Ok(resp)
+    }
+
+    /// Puts the given key into the key-value store with `value` serialized into JSON, then
+    /// encoded.
+    #[cfg(feature = "serde")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub async fn put_json<T: serde::Serialize + ?Sized>(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: &T,
+        options: Option<PutOptions>,
+    ) -> Result<PutResponse> {
+        let value = serde_json::to_vec(value).map_err(|e| Error::IoError(e.into()))?;
+        self.put(key, value, options).await
+    }
+
+    /// Gets the key from the key-value store with its value decoded, then deserialized from
+    /// JSON, `None` if the key does not exist.
+    ///
+    /// A value which is not valid JSON fails with an [`Error::ValueDecode`].
+    #[cfg(feature = "serde")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub async fn get_json<T: serde::de::DeserializeOwned>(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<GetOptions>,
+    ) -> Result<Option<T>> {
+        let resp = self.get(key, options).await?;
+        let Some(kv) = resp.kvs().first() else {
+            return Ok(None);
+        };
+        serde_json::from_slice(kv.value())
+            .map(Some)
+            .map_err(|e| Error::ValueDecode {
+                key: kv.key().to_vec(),
+                source: e.into(),
+            })
+    }
+
+    /// Watches for events happening or that have happened, with the values of the events
+    /// decoded, see [`WatchClient::watch`].
+    #[cfg(feature = "watch")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+    pub async fn watch(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<WatchOptions>,
+    ) -> Result<(Watcher, ValueCodecWatchStream)> {
+        let (watcher, stream) = self.watch.watch(key, options).await?;
+        let stream = ValueCodecWatchStream {
+            stream,
+            codec: self.codec.clone(),
+        };
+        Ok((watcher, stream))
+    }
+}
+
+impl Debug for ValueCodecClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValueCodecClient")
+            .field("min_size", &self.codec.min_size)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The watch response stream of a [`ValueCodecClient`], with the values of the events
+/// decoded.
+///
+/// A value which can not be decoded fails the message holding it with an
+/// [`Error::ValueDecode`], the stream going on with the next messages.
+#[cfg(feature = "watch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+pub struct ValueCodecWatchStream {
+    stream: WatchStream,
+    codec: Codec,
+}
+
+#[cfg(feature = "watch")]
+impl ValueCodecWatchStream {
+    /// Fetch the next message from this stream.
+    #[inline]
+    pub async fn message(&mut self) -> Result<Option<WatchResponse>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+            .await
+            .transpose()
+    }
+}
+
+#[cfg(feature = "watch")]
+impl Stream for ValueCodecWatchStream {
+    type Item = Result<WatchResponse>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        Pin::new(&mut this.stream).poll_next(cx).map(|resp| {
+            resp.map(|resp| {
+                let mut resp = resp?;
+                resp.try_for_each_kv(&mut |kv| this.codec.decode(kv))?;
+                Ok(resp)
+            })
+        })
+    }
+}
+
+#[cfg(feature = "watch")]
+impl Debug for ValueCodecWatchStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValueCodecWatchStream")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A codec reversing the bytes of the values.
+    struct Reverse;
+
+    impl ValueCodec for Reverse {
+        fn magic(&self) -> &[u8] {
+            b"\0rev"
+        }
+
+        fn encode(&self, value: &[u8]) -> std::io::Result<Vec<u8>> {
+            Ok(value.iter().rev().copied().collect())
+        }
+
+        fn decode(&self, encoded: &[u8]) -> std::io::Result<Vec<u8>> {
+            if encoded.is_empty() {
+                return Err(std::io::ErrorKind::InvalidData.into());
+            }
+            self.encode(encoded)
+        }
+    }
+
+    fn codec(min_size: usize) -> Codec {
+        Codec {
+            codec: Arc::new(Reverse),
+            min_size,
+        }
+    }
+
+    fn kv(value: &[u8]) -> PbKeyValue {
+        PbKeyValue {
+            key: b"key".to_vec(),
+            value: value.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let codec = codec(8);
+        for value in [&b"abcdefgh"[..], b"ab", b"\0rev", b"\0reva", b""] {
+            let encoded = codec.encode(value).unwrap();
+            let mut kv = kv(encoded.as_deref().unwrap_or(value));
+            codec.decode(&mut kv).unwrap();
+            assert_eq!(kv.value, value);
+        }
+        // Short values are left as they are, unless they look encoded.
+        assert_eq!(codec.encode(b"ab").unwrap(), None);
+        assert_eq!(codec.encode(b"\0rev").unwrap().unwrap(), b"\0revver\0");
+        assert_eq!(codec.encode(b"").unwrap(), None);
+    }
+
+    #[test]
+    fn test_legacy_values() {
+        let codec = codec(0);
+        let mut legacy = kv(b"{\"legacy\": true}");
+        codec.decode(&mut legacy).unwrap();
+        assert_eq!(legacy.value, b"{\"legacy\": true}");
+
+        let mut corrupt = kv(b"\0rev");
+        let err = codec.decode(&mut corrupt).unwrap_err();
+        assert!(
+            matches!(&err, Error::ValueDecode { key, .. } if key == b"key"),
+            "{:?}",
+            err
+        );
+        assert_eq!(corrupt.value, b"\0rev");
+    }
+
+    #[cfg(feature = "value-zstd")]
+    #[test]
+    fn test_zstd() {
+        let codec = Codec {
+            codec: Arc::new(ZstdCodec::new().with_level(3)),
+            min_size: 0,
+        };
+        let value = br#"{"items": ["a", "a", "a", "a", "a", "a", "a", "a", "a", "a"]}"#.repeat(64);
+        let encoded = codec.encode(&value).unwrap().unwrap();
+        assert!(encoded.starts_with(ZstdCodec::MAGIC));
+        assert!(encoded.len() < value.len() / 10, "{}", encoded.len());
+
+        let mut kv = kv(&encoded);
+        codec.decode(&mut kv).unwrap();
+        assert_eq!(kv.value, value);
+    }
+}
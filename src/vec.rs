@@ -1,3 +1,5 @@
+use prost::bytes::{Buf, Bytes};
+
 pub trait VecExt {
     // slice has a method named `strip_prefix`
     fn strip_key_prefix(&mut self, prefix: &[u8]);
@@ -69,6 +71,29 @@ impl VecExt for Vec<u8> {
     }
 }
 
+/// The bytes of requests, copied only when prefixed.
+impl VecExt for Bytes {
+    fn strip_key_prefix(&mut self, prefix: &[u8]) {
+        if !prefix.is_empty() && self.starts_with(prefix) {
+            self.advance(prefix.len());
+        }
+    }
+
+    fn prefix_with(&mut self, prefix: &[u8]) {
+        if !prefix.is_empty() {
+            *self = [prefix, self].concat().into();
+        }
+    }
+
+    fn prefix_range_end_with(&mut self, prefix: &[u8]) {
+        if !prefix.is_empty() {
+            let mut range_end = self.to_vec();
+            range_end.prefix_range_end_with(prefix);
+            *self = range_end.into();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
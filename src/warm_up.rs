@@ -0,0 +1,115 @@
+//! Warming up the connections of a client before its first requests.
+
+use crate::error::{ConnectError, Error, Result};
+use crate::intercept::InterceptedChannel;
+use http::uri::PathAndQuery;
+use http::Uri;
+use std::time::Duration;
+use tokio::time::Instant;
+use tonic::codec::ProstCodec;
+
+/// The name of the RPC probing a connection.
+const PROBE_RPC: &str = "Check";
+
+/// The path of the RPC probing a connection, the health check every member serves without
+/// authentication. Any answer proves the connection, even from a server without it.
+const PROBE_PATH: &str = "/grpc.health.v1.Health/Check";
+
+/// The delay before probing a balanced channel again after failing to connect it.
+const PROBE_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The warm-up of the connection to an endpoint, see [`Client::warm_up_all`].
+///
+/// [`Client::warm_up_all`]: crate::Client::warm_up_all
+#[derive(Debug)]
+pub struct EndpointWarmUp {
+    endpoint: Uri,
+    result: Result<Duration>,
+}
+
+impl EndpointWarmUp {
+    #[inline]
+    pub(crate) const fn new(endpoint: Uri, result: Result<Duration>) -> Self {
+        Self { endpoint, result }
+    }
+
+    /// The endpoint connected to.
+    #[inline]
+    pub fn endpoint(&self) -> &Uri {
+        &self.endpoint
+    }
+
+    /// The time it took to connect to the endpoint, or why it could not be.
+    #[inline]
+    pub fn result(&self) -> &Result<Duration> {
+        &self.result
+    }
+
+    /// Takes the result of connecting to the endpoint.
+    #[inline]
+    pub fn into_result(self) -> Result<Duration> {
+        self.result
+    }
+
+    /// Returns `true` if the endpoint has been connected to.
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// Sends probes over `channel` until it is connected, returning the time it took.
+///
+/// A balanced channel may send a probe to an endpoint which can not be connected to, so
+/// the probes failing to connect are sent again after [`PROBE_BACKOFF`] if `retry` is set.
+/// Fails with an [`Error::Connect`] naming `endpoint` if it can not be connected, or an
+/// [`Error::Deadline`] if it is not within `timeout`.
+pub(crate) async fn probe(
+    channel: InterceptedChannel,
+    endpoint: &str,
+    timeout: Duration,
+    retry: bool,
+) -> Result<Duration> {
+    let start = Instant::now();
+    let deadline = start + timeout;
+    let mut last_err = None;
+    loop {
+        let err = match tokio::time::timeout_at(deadline, probe_once(channel.clone())).await {
+            Ok(Ok(())) => return Ok(start.elapsed()),
+            Ok(Err(e)) => ConnectError::new(endpoint.to_owned(), e),
+            Err(_) => break,
+        };
+        if !retry || Instant::now() + PROBE_BACKOFF >= deadline {
+            return Err(err.into());
+        }
+        last_err = Some(err);
+        tokio::time::sleep(PROBE_BACKOFF).await;
+    }
+    Err(last_err.map_or(
+        Error::Deadline {
+            rpc: PROBE_RPC,
+            elapsed: start.elapsed(),
+        },
+        Error::Connect,
+    ))
+}
+
+/// Sends a probe over `channel`, failing if it could not be sent to an endpoint.
+async fn probe_once(channel: InterceptedChannel) -> Result<()> {
+    let mut grpc = tonic::client::Grpc::new(channel);
+    grpc.ready()
+        .await
+        .map_err(|e| Error::from(tonic::Status::from_error(e)))?;
+    let path = PathAndQuery::from_static(PROBE_PATH);
+    // The check of the whole server is an empty message, as is its answer without the
+    // serving status.
+    let codec = ProstCodec::<(), ()>::default();
+    match grpc.unary(tonic::Request::new(()), path, codec).await {
+        Ok(_) => Ok(()),
+        Err(status) => match Error::from(status) {
+            // The server answered, the connection is up.
+            err if !err.is_transport() => Ok(()),
+            err => Err(err),
+        },
+    }
+}
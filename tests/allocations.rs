@@ -0,0 +1,151 @@
+//! Counts the copies of the keys of KV requests, by counting the allocations of their size
+//! with a global allocator, against a local mock server, and the allocations of cloning a
+//! client.
+
+use etcd_client::Client;
+use http_body::Frame;
+use http_body_util::StreamBody;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tonic::codegen::Bytes;
+
+/// The length of the keys, one no other allocation of the client or the server has.
+const KEY_LEN: usize = 7919;
+
+/// The allocator counting the allocations of [`KEY_LEN`] bytes, and all the allocations of
+/// every thread.
+struct Counting;
+
+static KEY_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // The thread may be being torn down, its allocations are not counted then.
+        let _ = THREAD_ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        if layout.size() == KEY_LEN {
+            KEY_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size == KEY_LEN {
+            KEY_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Serves empty responses to KV requests, failing the first `failures` ones as timed out,
+/// which reads are retried for.
+async fn kv_server(failures: usize) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let failures = Arc::new(AtomicUsize::new(failures));
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let failures = failures.clone();
+            let service = hyper::service::service_fn(move |req: http::Request<_>| {
+                assert!(req.uri().path().starts_with("/etcdserverpb.KV/"));
+                let failed = failures
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                    .is_ok();
+                let mut trailers = http::HeaderMap::new();
+                let status = match failed {
+                    true => tonic::Status::unavailable("etcdserver: request timed out"),
+                    false => tonic::Status::ok(""),
+                };
+                status.add_header(&mut trailers).unwrap();
+                // The frame of an empty message, the default response.
+                let mut frames = vec![Frame::data(Bytes::from_static(&[0; 5]))];
+                if failed {
+                    frames.clear();
+                }
+                frames.push(Frame::trailers(trailers));
+                let body = StreamBody::new(tokio_stream::iter(
+                    frames.into_iter().map(Ok::<_, Infallible>),
+                ));
+                let resp = http::Response::builder()
+                    .header("content-type", "application/grpc")
+                    .body(body)
+                    .unwrap();
+                async move { Ok::<_, Infallible>(resp) }
+            });
+            let http2 = hyper::server::conn::http2::Builder::new(TokioExecutor::new());
+            tokio::spawn(http2.serve_connection(TokioIo::new(socket), service));
+        }
+    });
+    addr
+}
+
+/// Counts the allocations of [`KEY_LEN`] bytes made by `f`.
+async fn key_allocations<F: std::future::Future>(f: F) -> usize {
+    KEY_ALLOCATIONS.store(0, Ordering::Relaxed);
+    Box::pin(f).await;
+    KEY_ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// The number of allocations the current thread made.
+fn thread_allocations() -> usize {
+    THREAD_ALLOCATIONS.with(Cell::get)
+}
+
+#[tokio::test]
+async fn test_key_copied_once() {
+    let addr = kv_server(1).await;
+    let mut client = Client::connect([addr.to_string()], None).await.unwrap();
+    let key = "k".repeat(KEY_LEN);
+
+    // Borrowed keys are copied once into the request, a retry shares it.
+    let allocations = key_allocations(async {
+        client.get(key.as_str(), None).await.unwrap();
+    })
+    .await;
+    assert_eq!(allocations, 1);
+
+    let allocations = key_allocations(async {
+        client.put(key.as_str(), "value", None).await.unwrap();
+    })
+    .await;
+    assert_eq!(allocations, 1);
+
+    // Owned keys are moved into the request, not copied.
+    let owned = key.clone().into_bytes();
+    let allocations = key_allocations(async {
+        client.get(owned, None).await.unwrap();
+    })
+    .await;
+    assert_eq!(allocations, 0);
+}
+
+#[tokio::test]
+async fn test_clone_allocations() {
+    let addr = kv_server(0).await;
+    let client = Client::connect([addr.to_string()], None).await.unwrap();
+    client.kv_client();
+
+    // A clone shares the internals of the client, and the clients of the services built by
+    // any of the clones.
+    let before = thread_allocations();
+    let clones: [Client; 16] = std::array::from_fn(|_| client.clone());
+    assert_eq!(thread_allocations() - before, 0);
+
+    let mut clone = clones[0].clone();
+    clone.get("key", None).await.unwrap();
+}
@@ -1,11 +1,16 @@
 mod testing;
 
-use crate::testing::{get_client, Result, DEFAULT_TEST_ENDPOINT};
+use crate::testing::{cluster, endpoint, get_client, Result};
 use etcd_client::{
-    AlarmAction, AlarmOptions, AlarmType, Client, Compare, CompareOp, ConnectOptions,
-    DeleteOptions, EventType, GetOptions, LeaseGrantOptions, MemberAddOptions, Permission,
-    PermissionType, ProclaimOptions, PutOptions, ResignOptions, RoleRevokePermissionOptions, Txn,
-    TxnOp, TxnOpResponse, UserAddOptions,
+    AlarmAction, AlarmOptions, AlarmType, BreakOptions, CancellationToken, ChunkedTxnOptions,
+    Compare, CompareOp, ConnectOptions, DefragOptions, DeleteOptions, ElectionOptions,
+    EndpointSyncOptions, Error, EventType, GetOptions, HasResponseHeader, IsolationLevel,
+    LeadershipEvent, LeaseGrantOptions, LeaseId, LockOptions, MemberAddOptions, MemberListOptions,
+    ObserveOptions, ParallelScanOptions, Permission, PermissionType, ProclaimOptions,
+    PromoteOptions, PutOptions, RenameOptions, RenameResult, ResignOptions, Revision,
+    RoleRevokePermissionOptions, RouteTo, ScanOrder, Session, SessionHandle, SessionOptions,
+    SizeAccountingOptions, SnapshotHashCheck, SnapshotOptions, Stm, SwapResult, Txn, TxnOp,
+    TxnOpResponse, UserAddOptions, VerifySnapshotOptions, WatchOptions,
 };
 
 #[tokio::test]
@@ -141,6 +146,177 @@ async fn test_delete() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_delete_interlock() -> Result<()> {
+    let mut client = get_client().await?;
+    for i in 0..5 {
+        client.put(format!("guard-{}", i), "v", None).await?;
+    }
+
+    // dry run
+    {
+        let options = DeleteOptions::new().with_prefix().with_dry_run_keys(2);
+        let dry_run = client.delete_dry_run("guard-", Some(options)).await?;
+        assert_eq!(dry_run.count(), 5);
+        assert_eq!(dry_run.kvs().len(), 2);
+        assert_eq!(dry_run.kvs()[0].key(), b"guard-0");
+        assert!(dry_run.kvs()[0].value().is_empty());
+        assert!(!dry_run.is_complete());
+
+        let options = DeleteOptions::new().with_prefix().with_dry_run();
+        let resp = client.delete("guard-", Some(options)).await?;
+        assert_eq!(resp.deleted(), 5);
+        assert!(resp.prev_kvs().is_empty());
+        let resp = client
+            .get(
+                "guard-",
+                Some(GetOptions::new().with_prefix().with_count_only()),
+            )
+            .await?;
+        assert_eq!(resp.count(), 5);
+    }
+
+    // limited delete
+    {
+        let options = DeleteOptions::new().with_prefix().with_max_deletions(4);
+        let err = client.delete("guard-", Some(options)).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::DeletionLimitExceeded {
+                    would_delete: 5,
+                    limit: 4
+                }
+            ),
+            "{:?}",
+            err
+        );
+
+        let options = DeleteOptions::new()
+            .with_prefix()
+            .with_prev_key()
+            .with_max_deletions(5);
+        let resp = client.delete("guard-", Some(options)).await?;
+        assert_eq!(resp.deleted(), 5);
+        assert_eq!(resp.prev_kvs().len(), 5);
+        assert!(resp.header().is_some_and(|header| header.revision() > 0));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_size_accounting() -> Result<()> {
+    let (tx, mut warnings) = tokio::sync::mpsc::unbounded_channel();
+    let size = SizeAccountingOptions::new()
+        .with_quota(1, 0.8)
+        .with_max_request_bytes(1024)
+        .with_on_warning(move |warning| {
+            let _ = tx.send(warning);
+        });
+    let options = ConnectOptions::new().with_size_accounting_options(size);
+    let mut client = cluster().await?.client_with(Some(options)).await?;
+    assert_eq!(
+        client.write_stats().map(|stats| stats.bytes_written()),
+        Some(0)
+    );
+
+    client.put("size-put", "value", None).await?;
+    let txn = Txn::new()
+        .when([Compare::version("size-put", CompareOp::Equal, 0)])
+        .and_then([TxnOp::put("size-then", "ignored", None)])
+        .or_else([
+            TxnOp::put("size-else", "v", None),
+            TxnOp::get("size-put", None),
+        ]);
+    client.txn(txn).await?;
+    let stats = client.write_stats().unwrap();
+    assert_eq!(stats.bytes_written(), 13 + 10);
+    assert_eq!(stats.writes(), 2);
+
+    // refused without being sent
+    let err = client
+        .put("size-large", vec![0; 2048], None)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::RequestTooLarge {
+                limit: Some(1024),
+                status: None,
+                ..
+            }
+        ),
+        "{:?}",
+        err
+    );
+    assert_eq!(client.write_stats().unwrap().writes(), 2);
+
+    // any database is over a quota of a byte
+    let warning = warnings.recv().await.unwrap();
+    assert!(warning.db_size() > 0);
+    assert_eq!(warning.quota_bytes(), 1);
+    assert!(client.write_stats().unwrap().db_size().is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ordering_guard() -> Result<()> {
+    let client = get_client().await?;
+    let mut ordered = client.with_ordering_guard();
+    assert_eq!(ordered.revision(), 0);
+
+    let put = ordered.put("ordered", "value", None).await?;
+    let revision = put.header().unwrap().revision();
+    assert_eq!(ordered.revision(), revision);
+
+    let options = GetOptions::new().with_serializable();
+    let resp = ordered.get("ordered", Some(options)).await?;
+    assert_eq!(resp.kvs()[0].value(), b"value");
+    assert!(ordered.revision() >= revision);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_route() -> Result<()> {
+    let client = get_client().await?;
+    let mut kv = client.kv_client();
+    let leader = client.maintenance_client().status().await?.leader();
+
+    let options = PutOptions::new().with_route(RouteTo::Leader);
+    let put = kv.put("routed", "value", Some(options)).await?;
+    assert_eq!(put.header().unwrap().member_id(), leader);
+
+    let options = GetOptions::new().with_route(RouteTo::Member(leader));
+    let resp = kv.get("routed", Some(options)).await?;
+    assert_eq!(resp.header().unwrap().member_id(), leader);
+    assert_eq!(resp.kvs()[0].value(), b"value");
+
+    let status = client
+        .maintenance_client()
+        .routed(RouteTo::Leader)
+        .await?
+        .status()
+        .await?;
+    assert_eq!(status.header().unwrap().member_id(), leader);
+
+    // An unknown member fails the call if the routing is strict.
+    let options = ConnectOptions::new().with_strict_routing(true);
+    let mut kv = cluster()
+        .await?
+        .client_with(Some(options))
+        .await?
+        .kv_client();
+    let options = GetOptions::new().with_route(RouteTo::Member(u64::MAX));
+    let err = kv.get("routed", Some(options)).await.unwrap_err();
+    assert!(matches!(err, Error::RouteUnreachable { .. }), "{:?}", err);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_compact() -> Result<()> {
     let mut client = get_client().await?;
@@ -242,6 +418,223 @@ async fn test_txn() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_stm_transfers() -> Result<()> {
+    let mut client = get_client().await?;
+    client.put("stm-account-a", "100", None).await?;
+    client.put("stm-account-b", "0", None).await?;
+
+    // concurrent transfers between the accounts keep their total
+    let balance = |stm: &mut Stm, key: &str| -> i64 {
+        stm.get(key).map_or(0, |value| {
+            String::from_utf8(value).unwrap().parse().unwrap()
+        })
+    };
+    let tasks: Vec<_> = (0..8)
+        .map(|task: i64| {
+            let mut kv = client.kv_client();
+            tokio::spawn(async move {
+                for round in 0..5 {
+                    let amount = (task * 7 + round) % 30;
+                    let (from, to) = match (task + round) % 2 {
+                        0 => ("stm-account-a", "stm-account-b"),
+                        _ => ("stm-account-b", "stm-account-a"),
+                    };
+                    kv.stm_with_retries(IsolationLevel::SerializableSnapshot, 100, |stm| {
+                        let (from_balance, to_balance) = (balance(stm, from), balance(stm, to));
+                        let amount = amount.min(from_balance);
+                        stm.put(from, (from_balance - amount).to_string());
+                        stm.put(to, (to_balance + amount).to_string());
+                        Ok(())
+                    })
+                    .await?;
+                }
+                Ok::<_, Error>(())
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap()?;
+    }
+    let total = client
+        .stm(IsolationLevel::Serializable, |stm| {
+            Ok(balance(stm, "stm-account-a") + balance(stm, "stm-account-b"))
+        })
+        .await?;
+    assert_eq!(total, 100);
+
+    // a transaction which keeps conflicting exhausts its retries
+    let mut other = client.kv_client();
+    let err = client
+        .kv_client()
+        .stm_with_retries(IsolationLevel::RepeatableReads, 2, |stm| {
+            if stm.get("stm-account-a").is_some() {
+                // a write behind the back of the transaction, to the key it read
+                tokio::task::block_in_place(|| {
+                    let handle = tokio::runtime::Handle::current();
+                    handle.block_on(other.put("stm-account-a", "100", None))
+                })?;
+            }
+            stm.put("stm-account-b", "0");
+            Ok(())
+        })
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, Error::StmRetriesExhausted { retries: 2 }),
+        "{:?}",
+        err
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rename() -> Result<()> {
+    let mut client = get_client().await?;
+    client
+        .delete("rename-", Some(DeleteOptions::new().with_prefix()))
+        .await?;
+
+    // the source exists, the destination does not
+    client.put("rename-a", "a", None).await?;
+    let renamed = client.rename("rename-a", "rename-b", None).await?;
+    assert!(matches!(renamed, RenameResult::Renamed { .. }));
+    let resp = client.get("rename-b", None).await?;
+    assert_eq!(resp.kvs().first().map(|kv| kv.value()), Some(&b"a"[..]));
+    assert_eq!(client.get("rename-a", None).await?.count(), 0);
+
+    // neither exists
+    let renamed = client.rename("rename-a", "rename-c", None).await?;
+    assert_eq!(renamed, RenameResult::SourceMissing);
+
+    // the destination exists, the source does not
+    let renamed = client.rename("rename-a", "rename-b", None).await?;
+    assert_eq!(renamed, RenameResult::SourceMissing);
+
+    // both exist, the destination is only replaced if overwriting
+    client.put("rename-a", "a2", None).await?;
+    let renamed = client.rename("rename-a", "rename-b", None).await?;
+    assert_eq!(renamed, RenameResult::DestinationExists);
+    let resp = client.get("rename-b", None).await?;
+    assert_eq!(resp.kvs().first().map(|kv| kv.value()), Some(&b"a"[..]));
+    let options = RenameOptions::new().with_overwrite();
+    let renamed = client.rename("rename-a", "rename-b", Some(options)).await?;
+    assert!(matches!(renamed, RenameResult::Renamed { .. }));
+    let resp = client.get("rename-b", None).await?;
+    assert_eq!(resp.kvs().first().map(|kv| kv.value()), Some(&b"a2"[..]));
+    assert_eq!(client.get("rename-a", None).await?.count(), 0);
+
+    // the lease is only kept if asked to
+    let lease = client.lease_grant(60, None).await?.id();
+    let options = PutOptions::new().with_lease(lease);
+    client.put("rename-a", "a3", Some(options.clone())).await?;
+    client.rename("rename-a", "rename-c", None).await?;
+    let resp = client.get("rename-c", None).await?;
+    assert_eq!(
+        resp.kvs().first().map(|kv| kv.lease()),
+        Some(LeaseId::none())
+    );
+    client.put("rename-a", "a4", Some(options)).await?;
+    let options = RenameOptions::new().with_keep_lease();
+    client.rename("rename-a", "rename-d", Some(options)).await?;
+    let resp = client.get("rename-d", None).await?;
+    assert_eq!(resp.kvs().first().map(|kv| kv.lease()), Some(lease));
+    client.lease_revoke(lease).await?;
+
+    assert!(client.rename("rename-b", "rename-b", None).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap() -> Result<()> {
+    let mut client = get_client().await?;
+    client
+        .delete("swap-", Some(DeleteOptions::new().with_prefix()))
+        .await?;
+
+    client.put("swap-a", "a", None).await?;
+    let swapped = client.swap("swap-a", "swap-b").await?;
+    assert_eq!(swapped, SwapResult::Missing(b"swap-b".to_vec()));
+
+    client.put("swap-b", "b", None).await?;
+    let swapped = client.swap("swap-a", "swap-b").await?;
+    assert!(matches!(swapped, SwapResult::Swapped { .. }));
+    let resp = client.get("swap-a", None).await?;
+    assert_eq!(resp.kvs().first().map(|kv| kv.value()), Some(&b"b"[..]));
+    let resp = client.get("swap-b", None).await?;
+    assert_eq!(resp.kvs().first().map(|kv| kv.value()), Some(&b"a"[..]));
+
+    let swapped = client.swap("swap-c", "swap-b").await?;
+    assert_eq!(swapped, SwapResult::Missing(b"swap-c".to_vec()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_stream_parallel() -> Result<()> {
+    let mut client = get_client().await?;
+    client
+        .delete("scan/", Some(DeleteOptions::new().with_prefix()))
+        .await?;
+    let keys: Vec<_> = (0..500).map(|i| format!("scan/{:03}", i)).collect();
+    for chunk in keys.chunks(100) {
+        let ops: Vec<_> = chunk
+            .iter()
+            .map(|key| TxnOp::put(key.as_str(), "v", None))
+            .collect();
+        client.txn(Txn::new().and_then(ops)).await?;
+    }
+    let revision = client
+        .get("scan/", None)
+        .await?
+        .header()
+        .unwrap()
+        .revision();
+    // changes made after the scan started are not seen
+    client.put("scan/999", "v", None).await?;
+
+    for order in [ScanOrder::KeyOrdered, ScanOrder::Interleaved] {
+        let options = ParallelScanOptions::new()
+            .with_shards(4)
+            .with_batch_size(25)
+            .with_revision(revision)
+            .with_order(order);
+        let mut stream = client.get_stream_parallel("scan/", Some(options)).await?;
+        let mut scanned = Vec::new();
+        while let Some(page) = stream.message().await? {
+            scanned.extend(page.iter().map(|kv| kv.key_str().unwrap().to_owned()));
+        }
+        if order == ScanOrder::Interleaved {
+            scanned.sort();
+        }
+        assert_eq!(scanned, keys);
+    }
+
+    // a compacted revision aborts the scan
+    let current = client
+        .put("scan/999", "v", None)
+        .await?
+        .header()
+        .unwrap()
+        .revision();
+    client.compact(current, None).await?;
+    let options = ParallelScanOptions::new().with_revision(revision);
+    let err = match client.get_stream_parallel("scan/", Some(options)).await {
+        Ok(mut stream) => loop {
+            match stream.message().await {
+                Ok(Some(_)) => continue,
+                Ok(None) => panic!("compacted revision scanned"),
+                Err(e) => break e,
+            }
+        },
+        Err(e) => e,
+    };
+    assert!(err.is_compacted(), "{:?}", err);
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_watch() -> Result<()> {
     let mut client = get_client().await?;
@@ -268,6 +661,173 @@ async fn test_watch() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_get_and_watch() -> Result<()> {
+    let mut client = get_client().await?;
+    client.put("get_and_watch/a", "1", None).await?;
+
+    let options = GetOptions::new().with_prefix();
+    let (get, _watcher, mut stream) = client
+        .get_and_watch("get_and_watch/", Some(options), None)
+        .await?;
+    assert_eq!(get.kvs()[0].key(), b"get_and_watch/a");
+
+    // Only the changes made after the get are watched.
+    client.delete("get_and_watch/a", None).await?;
+    let resp = stream.message().await?.unwrap();
+    assert_eq!(resp.events().len(), 1);
+    let event = &resp.events()[0];
+    assert_eq!(event.event_type(), EventType::Delete);
+    assert_eq!(event.kv().unwrap().key(), b"get_and_watch/a");
+    assert_eq!(
+        event.kv().unwrap().mod_revision(),
+        get.header().unwrap().revision().next()?
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_observe_key() -> Result<()> {
+    let mut client = get_client().await?;
+    client.put("observe", "1", None).await?;
+
+    let mut observer = client.observe_key("observe", None).await?;
+    assert_eq!(observer.get().unwrap().value(), b"1");
+
+    client.put("observe", "2", None).await?;
+    observer.changed().await?;
+    assert_eq!(observer.get().unwrap().value(), b"2");
+    assert!(observer.is_live());
+
+    client.delete("observe", None).await?;
+    observer.changed().await?;
+    assert!(observer.get().is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_observe_key_debounce() -> Result<()> {
+    let mut client = get_client().await?;
+    let options = ObserveOptions::new().with_debounce(std::time::Duration::from_secs(1));
+    let mut observer = client
+        .observe_key("observe_debounce", Some(options))
+        .await?;
+    let mut values = observer.subscribe();
+
+    // a burst only updates the value once, with its last change
+    for i in 0..5 {
+        client.put("observe_debounce", i.to_string(), None).await?;
+    }
+    observer.changed().await?;
+    assert_eq!(observer.get().unwrap().value(), b"4");
+    assert!(values.has_changed().unwrap());
+    values.borrow_and_update();
+    tokio::time::sleep(std::time::Duration::from_millis(1200)).await;
+    assert!(!values.has_changed().unwrap());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_watch_cancel() -> Result<()> {
+    let mut client = get_client().await?;
+
+    let token = CancellationToken::new();
+    let options = WatchOptions::new().with_cancel(token.clone());
+    let (_watcher, mut stream) = client.watch("watch_cancel", Some(options)).await?;
+
+    token.cancel();
+    assert!(matches!(
+        stream.message().await,
+        Err(Error::Cancelled { rpc: "Watch" })
+    ));
+    assert!(stream.message().await?.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_watch_streams_merged() -> Result<()> {
+    use tokio_stream::{StreamExt, StreamMap};
+
+    let mut client = get_client().await?;
+    let keys = ["merged-a", "merged-b", "merged-c"];
+    let (mut watchers, mut streams) = (Vec::new(), StreamMap::new());
+    for key in keys {
+        let (watcher, stream) = client.watch(key, None).await?;
+        watchers.push(watcher);
+        streams.insert(key, stream);
+    }
+
+    // The streams get ready in turn, some of them at once.
+    let mut writer = get_client().await?;
+    let puts = tokio::spawn(async move {
+        for i in 0..30 {
+            writer.put(keys[i % 3], i.to_string(), None).await?;
+            if i % 4 == 0 {
+                tokio::task::yield_now().await;
+            }
+        }
+        Ok::<_, Error>(())
+    });
+
+    // A ticker winning the select drops the polls of the streams in flight.
+    let mut ticker = tokio::time::interval(std::time::Duration::from_micros(50));
+    let mut values = Vec::new();
+    while values.len() < 30 {
+        tokio::select! {
+            next = streams.next() => {
+                let (key, resp) = next.unwrap();
+                for event in resp?.events() {
+                    let value = event.kv().unwrap().value_str()?.parse::<usize>().unwrap();
+                    values.push((key, value));
+                }
+            }
+            _ = ticker.tick() => {}
+        }
+    }
+    puts.await.unwrap()?;
+
+    // No event was lost, nor reordered within its watch.
+    for (n, key) in keys.into_iter().enumerate() {
+        let seen: Vec<_> = values
+            .iter()
+            .filter(|(k, _)| *k == key)
+            .map(|(_, v)| *v)
+            .collect();
+        let put: Vec<_> = (0..30).filter(|i| i % 3 == n).collect();
+        assert_eq!(seen, put, "{}", key);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_default_deadline() -> Result<()> {
+    let options =
+        ConnectOptions::new().with_default_deadline(std::time::Duration::from_millis(500));
+    let cluster = cluster().await?;
+    let mut client = cluster.client_with(Some(options)).await?;
+    client.put("deadline", "01", None).await?;
+
+    // Without endpoints `Client::get` hangs until the deadline passes.
+    client.remove_endpoint(endpoint(&cluster)).await?;
+    match client.get("deadline", None).await {
+        Err(Error::Deadline { rpc, elapsed }) => {
+            assert_eq!(rpc, "Range");
+            assert!(elapsed >= std::time::Duration::from_millis(500));
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    client.add_endpoint(endpoint(&cluster)).await?;
+
+    let resp = client.get("deadline", None).await?;
+    assert_eq!(resp.kvs()[0].value(), b"01");
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_grant_revoke() -> Result<()> {
     let mut client = get_client().await?;
@@ -318,11 +878,108 @@ async fn test_time_to_live() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_put_lease_checked() -> Result<()> {
+    let mut client = get_client().await?;
+    // the server raises the TTL to its minimum, of about 2 seconds
+    let lease = client.lease_grant(1, None).await?.id();
+    let options = PutOptions::new().with_lease_checked(lease);
+    client
+        .put("lease-checked", "1", Some(options.clone()))
+        .await?;
+    let keys = client.kv_client().keys_of_lease(lease).await?;
+    assert_eq!(keys, vec![b"lease-checked".to_vec()]);
+
+    // the lease expires, and the key with it
+    tokio::time::sleep(std::time::Duration::from_secs(4)).await;
+    let resp = client.get("lease-checked", None).await?;
+    assert!(resp.kvs().is_empty());
+    for options in [options, PutOptions::new().with_lease(lease)] {
+        let err = client
+            .put("lease-checked", "2", Some(options))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::LeaseNotFound { id: Some(id), .. } if id == lease),
+            "{:?}",
+            err
+        );
+    }
+    let err = client.kv_client().keys_of_lease(lease).await.unwrap_err();
+    assert!(err.is_not_found());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_put_with_ttl() -> Result<()> {
+    let mut client = get_client().await?;
+    let ttl = std::time::Duration::from_secs(1);
+    let put = client.put_with_ttl("ttl-put", "1", ttl, None).await?;
+    let keys = client.kv_client().keys_of_lease(put.lease_id()).await?;
+    assert_eq!(keys, vec![b"ttl-put".to_vec()]);
+
+    let mut kv = client.kv_client();
+    let kvs = [("ttl-put/a", "1"), ("ttl-put/b", "2")];
+    let persisted = kv.put_many_with_ttl(kvs, ttl, None).await?;
+    let revision = persisted.persist().await?;
+
+    // the lease expires, and the key with it, the persisted keys remain
+    tokio::time::sleep(std::time::Duration::from_secs(4)).await;
+    let resp = client.get("ttl-put", None).await?;
+    assert!(resp.kvs().is_empty());
+    let resp = client
+        .get("ttl-put/", Some(GetOptions::new().with_prefix()))
+        .await?;
+    assert_eq!(resp.kvs().len(), 2);
+    for kv in resp.kvs() {
+        assert_eq!((kv.lease(), kv.mod_revision()), (LeaseId::none(), revision));
+    }
+    assert_eq!(resp.kvs()[1].value(), b"2");
+
+    let err = put.persist().await.unwrap_err();
+    assert!(matches!(err, Error::LeaseExpired { .. }), "{:?}", err);
+    let err = client
+        .put_with_ttl("ttl-put", "1", std::time::Duration::ZERO, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::LeaseTtlTooSmall { .. }), "{:?}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_txn_chunked() -> Result<()> {
+    let mut client = get_client().await?;
+    let ops: Vec<TxnOp> = (0..300)
+        .map(|i| TxnOp::put(format!("txn-chunked/{:03}", i), i.to_string(), None))
+        .collect();
+    let options = ChunkedTxnOptions::new().with_probed_max_ops();
+    let resp = client.txn_chunked(ops, Some(options)).await?;
+    assert!(resp.is_applied());
+    assert_eq!(resp.max_ops(), 128);
+    assert_eq!(resp.chunks().len(), 3);
+
+    // every chunk is a revision of its own, in the order of the ops
+    let revisions: Vec<Revision> = resp
+        .chunks()
+        .iter()
+        .map(|chunk| chunk.as_ref().unwrap().header().unwrap().revision())
+        .collect();
+    assert!(revisions.windows(2).all(|w| w[0] < w[1]));
+    let resp = client
+        .get("txn-chunked/", Some(GetOptions::new().with_prefix()))
+        .await?;
+    assert_eq!(resp.kvs().len(), 300);
+    assert_eq!(resp.kvs()[0].mod_revision(), revisions[0]);
+    assert_eq!(resp.kvs()[299].mod_revision(), revisions[2]);
+    assert_eq!(resp.kvs()[299].value(), b"299");
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_leases() -> Result<()> {
-    let lease1 = 100;
-    let lease2 = 101;
-    let lease3 = 102;
+    let lease1 = LeaseId::new(100);
+    let lease2 = LeaseId::new(101);
+    let lease3 = LeaseId::new(102);
 
     let mut client = get_client().await?;
     let resp = client
@@ -367,6 +1024,193 @@ async fn test_lock() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_lock_fencing_token() -> Result<()> {
+    let mut client = get_client().await?;
+    let first = client.lock_guarded("lock-fencing-test", None).await?;
+    let first_token = first.fencing_token();
+    let resp = client.get(first.key(), None).await?;
+    assert_eq!(resp.kvs()[0].create_revision(), first_token);
+    first.unlock().await?;
+
+    let second = client.lock_guarded("lock-fencing-test", None).await?;
+    assert!(second.fencing_token() > first_token);
+    second.unlock().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lock_waiters() -> Result<()> {
+    let mut client = get_client().await?;
+    let mut leases = Vec::new();
+    for _ in 0..3 {
+        leases.push(client.lease_grant(60, None).await?.id());
+    }
+    let options = LockOptions::new().with_lease(leases[0]);
+    let first = client.lock("lock-waiters", Some(options)).await?;
+    let mut lock = client.lock_client();
+    let mut waiting = Vec::new();
+    for &lease in &leases[1..] {
+        let mut client = client.clone();
+        let options = LockOptions::new().with_lease(lease);
+        waiting.push(tokio::spawn(async move {
+            client.lock("lock-waiters", Some(options)).await
+        }));
+        // the waiters queue up in the order they lock
+        while lock.waiters("lock-waiters").await?.len() < waiting.len() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+
+    let holders = lock.holders("lock-waiters").await?;
+    assert_eq!(holders.len(), 1);
+    assert_eq!(holders[0].key(), first.key());
+    assert_eq!(holders[0].lease(), leases[0]);
+    let waiters = lock.waiters("lock-waiters").await?;
+    let waiting_leases: Vec<_> = waiters.iter().map(|waiter| waiter.lease()).collect();
+    assert_eq!(waiting_leases, leases[1..]);
+    assert!(waiters[0].create_revision() < waiters[1].create_revision());
+    assert!(waiters[0].age() > waiters[1].age());
+
+    // breaking the lock evicts the holder, and releases the next waiter
+    let err = lock
+        .break_lock("lock-waiters", BreakOptions::new())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidArgs(_)), "{:?}", err);
+    let evicted = lock
+        .break_lock("lock-waiters", BreakOptions::new().with_force())
+        .await?;
+    assert_eq!(evicted.map(|holder| holder.lease()), Some(leases[0]));
+    let mut waiting = waiting.into_iter();
+    let second = waiting.next().unwrap().await.unwrap()?;
+    assert_eq!(lock.holders("lock-waiters").await?[0].key(), second.key());
+    assert_eq!(lock.waiters("lock-waiters").await?.len(), 1);
+    client.unlock(second.key()).await?;
+    let third = waiting.next().unwrap().await.unwrap()?;
+    assert_eq!(lock.holders("lock-waiters").await?[0].lease(), leases[2]);
+    assert!(lock.waiters("lock-waiters").await?.is_empty());
+
+    client.unlock(third.key()).await?;
+    assert!(lock.holders("lock-waiters").await?.is_empty());
+    for lease in leases {
+        client.lease_revoke(lease).await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_election_candidates() -> Result<()> {
+    let mut client = get_client().await?;
+    let mut leases = Vec::new();
+    for _ in 0..3 {
+        leases.push(client.lease_grant(60, None).await?.id());
+    }
+    client
+        .campaign("election-candidates", "0", leases[0])
+        .await?;
+    let mut election = client.election_client();
+    let mut campaigns = Vec::new();
+    for (i, &lease) in leases.iter().enumerate().skip(1) {
+        let mut client = client.clone();
+        campaigns.push(tokio::spawn(async move {
+            client
+                .campaign("election-candidates", i.to_string(), lease)
+                .await
+        }));
+        while election.candidates("election-candidates").await?.len() <= i {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+
+    let candidates = election.candidates("election-candidates").await?;
+    let values: Vec<_> = candidates.iter().map(|c| c.value().to_vec()).collect();
+    assert_eq!(values, [b"0".to_vec(), b"1".to_vec(), b"2".to_vec()]);
+    let candidate_leases: Vec<_> = candidates.iter().map(|c| c.lease()).collect();
+    assert_eq!(candidate_leases, leases);
+
+    for lease in leases {
+        client.lease_revoke(lease).await?;
+    }
+    for campaign in campaigns {
+        let _ = campaign.await.unwrap();
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_session_with_lease() -> Result<()> {
+    let mut client = get_client().await?;
+    let lease = client.lease_grant(40, None).await?.id();
+
+    // the session takes the TTL of the lease, not the one of the options
+    let options = SessionOptions::new().with_ttl(5).with_lease(lease);
+    let session = Session::new(client.lease_client(), Some(options)).await?;
+    assert_eq!((session.lease_id(), session.ttl()), (lease, 40));
+    session.close().await?;
+
+    let options = SessionOptions::new().with_lease(lease);
+    let err = Session::new(client.lease_client(), Some(options))
+        .await
+        .err()
+        .unwrap();
+    assert!(err.is_not_found(), "{:?}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_session_handoff() -> Result<()> {
+    let mut old = get_client().await?;
+    let options = SessionOptions::new().with_ttl(30);
+    let session = Session::new(old.lease_client(), Some(options)).await?;
+    let lease = session.lease_id();
+    let options = PutOptions::new().with_lease(lease);
+    old.put("session-handoff", "v", Some(options)).await?;
+    let bytes = session.release_without_revoke().to_bytes();
+
+    // the new process adopts the lease, and the key with it
+    let mut new = get_client().await?;
+    let handle = SessionHandle::from_bytes(&bytes)?;
+    let adopted = Session::adopt(&new, &handle).await?;
+    assert_eq!((adopted.lease_id(), adopted.ttl()), (lease, 30));
+    assert_eq!(adopted.generation(), 1);
+    let resp = new.get("session-handoff", None).await?;
+    assert_eq!(resp.kvs()[0].lease(), lease);
+
+    // the same handle is only adopted once
+    let err = Session::adopt(&old, &handle).await.unwrap_err();
+    assert!(
+        matches!(err, Error::SessionAdopted { id, generation: 0 } if id == lease),
+        "{:?}",
+        err
+    );
+
+    // the adopted session is handed off in turn, back to the old client
+    let handle = adopted.release_without_revoke();
+    let adopted = Session::adopt(&old, &handle).await?;
+    assert_eq!(adopted.generation(), 2);
+    adopted.close().await?;
+    assert!(new.get("session-handoff", None).await?.kvs().is_empty());
+    let err = Session::adopt(&new, &handle).await.unwrap_err();
+    assert!(err.is_not_found(), "{:?}", err);
+
+    // a lease about to expire is refused
+    let options = SessionOptions::new().with_ttl(6);
+    let session = Session::new(old.lease_client(), Some(options)).await?;
+    let handle = session.release_without_revoke();
+    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    let err = Session::adopt(&new, &handle).await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::LeaseTooShort { min: 2, .. } | Error::LeaseNotFound { .. }
+        ),
+        "{:?}",
+        err
+    );
+    Ok(())
+}
+
 #[ignore]
 #[tokio::test]
 async fn test_auth() -> Result<()> {
@@ -381,7 +1225,7 @@ async fn test_auth() -> Result<()> {
         "root",    // user name
         "rootpwd", // password
     ));
-    let mut client_auth = Client::connect(["localhost:2379"], options).await?;
+    let mut client_auth = cluster().await?.client_with(options).await?;
     client_auth.put("auth-test", "value", None).await?;
 
     client_auth.auth_disable().await?;
@@ -393,6 +1237,33 @@ async fn test_auth() -> Result<()> {
     Ok(())
 }
 
+#[ignore]
+#[tokio::test]
+async fn test_auth_token_handoff() -> Result<()> {
+    let mut client = get_client().await?;
+    client.auth_enable().await?;
+
+    // the parent authenticates, and hands its token over to the child
+    let options = Some(ConnectOptions::new().with_user("root", "rootpwd"));
+    let mut parent = cluster().await?.client_with(options).await?;
+    let token = parent
+        .auth_token()
+        .expect("an authenticated client has a token");
+
+    let options = Some(ConnectOptions::new().with_token(token.expose()));
+    let mut child = cluster().await?.client_with(options).await?;
+    child.put("auth-token-test", "value", None).await?;
+    let resp = child.get("auth-token-test", None).await?;
+    assert_eq!(resp.kvs()[0].value(), b"value");
+
+    // the parent can renew its token, the child can not
+    parent.refresh_auth_token().await?;
+    child.refresh_auth_token().await.unwrap_err();
+
+    parent.auth_disable().await?;
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_role() -> Result<()> {
     let mut client = get_client().await?;
@@ -582,6 +1453,30 @@ async fn test_alarm() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_recover_nospace() -> Result<()> {
+    let mut client = get_client().await?;
+    let member_id = client.status().await?.header().unwrap().member_id();
+
+    let mut options = AlarmOptions::new();
+    options.with_member(member_id);
+    client
+        .alarm(AlarmAction::Activate, AlarmType::Nospace, Some(options))
+        .await?;
+    let resp = client.alarm_list().await?;
+    assert!(resp
+        .alarms()
+        .iter()
+        .any(|alarm| alarm.member_id() == member_id && alarm.alarm() == AlarmType::Nospace));
+
+    let disarmed = client.recover_nospace(0).await?;
+    assert_eq!(disarmed.len(), 1);
+    assert_eq!(disarmed[0].member_id(), member_id);
+    assert!(client.alarm_list().await?.alarms().is_empty());
+    assert!(client.alarm_disarm_all().await?.is_empty());
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_status() -> Result<()> {
     let mut client = get_client().await?;
@@ -592,6 +1487,106 @@ async fn test_status() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_status_all() -> Result<()> {
+    let mut client = get_client().await?;
+    let members = client.member_list().await?.members().len();
+
+    let statuses = client.status_all().await?;
+    assert_eq!(statuses.len(), members);
+    for (uri, status) in statuses {
+        let status = status?;
+        assert!(status.db_size_in_use() > 0);
+        assert!(!status.is_learner());
+        assert!(status.errors().is_empty());
+
+        let mut member = client.endpoint_client(uri)?;
+        let resp = member.status().await?;
+        assert_eq!(
+            resp.header().unwrap().member_id(),
+            status.header().unwrap().member_id()
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cluster_health() -> Result<()> {
+    let mut client = get_client().await?;
+    let health = client.cluster_health().await?;
+    assert!(health.has_leader());
+    assert!(health.quorum_available());
+    assert_eq!(health.members_total(), health.members_healthy());
+    assert_eq!(health.members().iter().filter(|m| m.is_leader()).count(), 1);
+    for member in health.members() {
+        assert!(member.is_reachable());
+        assert!(member.db_size().unwrap() > 0);
+    }
+    Ok(())
+}
+
+#[ignore]
+#[tokio::test]
+async fn test_cluster_health_member_down() -> Result<()> {
+    // Requires a 3-node cluster with exactly one member stopped.
+    let mut client = get_client().await?;
+    let health = client.cluster_health().await?;
+    assert_eq!(health.members_total(), 3);
+    assert_eq!(health.members_healthy(), 2);
+    assert!(health.has_leader());
+    assert!(health.quorum_available());
+
+    let down: Vec<_> = health
+        .members()
+        .iter()
+        .filter(|m| !m.is_reachable())
+        .collect();
+    assert_eq!(down.len(), 1);
+    assert!(down[0].raft_term().is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_endpoint_client() -> Result<()> {
+    let client = get_client().await?;
+    let cluster = cluster().await?;
+    let endpoint = endpoint(&cluster);
+    let uri = endpoint.parse().unwrap();
+    let mut member = client.endpoint_client(uri)?;
+    member.put("endpoint-client", "value", None).await?;
+    let resp = member.get("endpoint-client", None).await?;
+    assert_eq!(resp.kvs()[0].value(), b"value");
+
+    // The TLS options of the client are not configured.
+    for scheme in ["ftp://", "https://"] {
+        let uri = endpoint.replace("http://", scheme).parse().unwrap();
+        assert!(matches!(
+            client.endpoint_client(uri),
+            Err(Error::InvalidArgs(_))
+        ));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cluster_leader() -> Result<()> {
+    let mut client = get_client().await?;
+    let (leader, uri, raft_term) = client.cluster_leader().await?.unwrap();
+    assert!(raft_term > 0);
+
+    // Every member agrees on the leader, which is the leader in its own view as well.
+    for (_, status) in client.status_all().await? {
+        assert_eq!(status?.leader(), leader);
+    }
+    let resp = client.endpoint_client(uri)?.status().await?;
+    assert_eq!(resp.header().unwrap().member_id(), leader);
+
+    let resp = client.put("raft-term", "value", None).await?;
+    assert_eq!(HasResponseHeader::raft_term(&resp), Some(raft_term));
+    assert!(client.raft_term().await? >= raft_term);
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_defragment() -> Result<()> {
     let mut client = get_client().await?;
@@ -601,6 +1596,27 @@ async fn test_defragment() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_defragment_all() -> Result<()> {
+    let mut client = get_client().await?;
+    let members = client.member_list().await?.members().len();
+
+    let results = client
+        .defragment_all(Some(DefragOptions::new().with_leader_last()))
+        .await?;
+    assert_eq!(results.len(), members);
+    for result in results {
+        assert!(result.endpoint().is_some());
+        result.into_result()?;
+    }
+
+    let results = client
+        .defragment_all(Some(DefragOptions::new().with_skip_leader()))
+        .await?;
+    assert_eq!(results.len(), members - 1);
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_hash() -> Result<()> {
     let mut client = get_client().await?;
@@ -622,6 +1638,27 @@ async fn test_hash_kv() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_hash_kv_all() -> Result<()> {
+    let mut client = get_client().await?;
+    let report = client.hash_kv_all(0).await?;
+    assert!(report.revision() > 0);
+    assert!(report.failed().is_empty());
+    assert!(report.is_consistent());
+
+    client.put("hash-kv-all", "1", None).await?;
+    let revision = client
+        .put("hash-kv-all", "2", None)
+        .await?
+        .header()
+        .unwrap()
+        .revision();
+    client.compact(revision, None).await?;
+    let err = client.hash_kv(revision.prev()?).await.unwrap_err();
+    assert!(err.is_compacted());
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_snapshot() -> Result<()> {
     let mut client = get_client().await?;
@@ -637,6 +1674,97 @@ async fn test_snapshot() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_snapshot_to() -> Result<()> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    let mut client = get_client().await?;
+    let progress = Arc::new(AtomicU64::new(0));
+    let options = SnapshotOptions::new().with_on_chunk({
+        let progress = progress.clone();
+        move |bytes| progress.store(bytes, Ordering::Relaxed)
+    });
+
+    let mut buf = Vec::new();
+    let summary = client.snapshot_to(&mut buf, Some(options)).await?;
+    assert_eq!(summary.bytes(), buf.len() as u64);
+    assert_eq!(progress.load(Ordering::Relaxed), summary.bytes());
+    assert_eq!(&buf[buf.len() - 32..], summary.sha256());
+    assert!(summary.revision() > 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_snapshot_to_file() -> Result<()> {
+    let mut client = get_client().await?;
+    let path = std::env::temp_dir().join(format!("etcd-snapshot-{}.db", std::process::id()));
+    let options = SnapshotOptions::new().with_max_retries(3);
+    let summary = client.snapshot_to_file(&path, Some(options)).await?;
+
+    let data = tokio::fs::read(&path).await?;
+    assert_eq!(summary.bytes(), data.len() as u64);
+    assert_eq!(&data[data.len() - 32..], summary.sha256());
+    tokio::fs::remove_file(&path).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_verify_snapshot() -> Result<()> {
+    let mut client = get_client().await?;
+    let mut buf = Vec::new();
+    let summary = client.snapshot_to(&mut buf, None).await?;
+    assert_ne!(summary.member_id(), 0);
+
+    // Writes after the snapshot do not change the hash up to its revision.
+    client.put("verify-snapshot", "1", None).await?;
+    let options = VerifySnapshotOptions::new().with_all_members();
+    let verification = client.verify_snapshot(&summary, Some(options)).await?;
+    assert_eq!(verification.revision(), summary.revision());
+    assert_eq!(verification.source().member().id(), summary.member_id());
+    assert!(verification.is_verified(), "{:?}", verification);
+
+    let revision = client
+        .put("verify-snapshot", "2", None)
+        .await?
+        .header()
+        .unwrap()
+        .revision();
+    client.compact(revision, None).await?;
+    let verification = client.verify_snapshot(&summary, None).await?;
+    assert!(verification.is_compacted());
+    assert_eq!(
+        verification.check(verification.source()),
+        SnapshotHashCheck::Compacted
+    );
+    assert!(!verification.is_verified());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sync_endpoints() -> Result<()> {
+    let mut client = get_client().await?;
+    let members = client.member_list().await?.members().len();
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let options = EndpointSyncOptions::new()
+        .with_interval(std::time::Duration::from_millis(200))
+        .with_filter(move |member, url| {
+            let _ = tx.send(member.id());
+            Some(url.to_owned())
+        });
+    let sync = client.sync_endpoints(Some(options))?;
+
+    let mut synced = Vec::new();
+    while synced.len() < members {
+        synced.push(rx.recv().await.unwrap());
+    }
+    assert!(!sync.is_finished());
+    client.put("sync-endpoints", "value", None).await?;
+    sync.stop();
+    Ok(())
+}
+
 #[ignore]
 #[tokio::test]
 async fn test_cluster() -> Result<()> {
@@ -662,6 +1790,49 @@ async fn test_cluster() -> Result<()> {
     Ok(())
 }
 
+#[ignore]
+#[tokio::test]
+async fn test_add_and_promote() -> Result<()> {
+    // Requires a member started with `--initial-cluster-state existing` on this peer URL.
+    let node = "localhost:2550";
+    let mut client = get_client().await?;
+    let options = PromoteOptions::new()
+        .with_poll_interval(std::time::Duration::from_millis(500))
+        .with_timeout(std::time::Duration::from_secs(30));
+    client
+        .add_and_promote([node.to_owned()], Some(options))
+        .await?;
+
+    let resp = client.member_list().await?;
+    assert_eq!(resp.learners().count(), 0);
+    assert!(resp
+        .voting_members()
+        .any(|member| member.peer_urls().iter().any(|url| url == node)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_member_list_linearizable() -> Result<()> {
+    let mut client = get_client().await?;
+    let mut resp = client
+        .member_list_with_options(Some(MemberListOptions::new().with_linearizable()))
+        .await?;
+    let mut members = resp.take_members();
+    assert!(!members.is_empty());
+    assert!(resp.members().is_empty());
+
+    let member = &mut members[0];
+    assert!(!member.take_name().is_empty());
+    assert!(!member.take_client_urls().is_empty());
+    assert!(!member.take_peer_urls().is_empty());
+    assert!(member.peer_urls().is_empty());
+
+    let unknown_id = members.iter().map(|m| m.id()).max().unwrap() + 1;
+    let err = client.member_remove(unknown_id).await.unwrap_err();
+    assert!(err.is_member_not_found());
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_move_leader() -> Result<()> {
     let mut client = get_client().await?;
@@ -692,6 +1863,19 @@ async fn test_move_leader() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_move_leader_not_found() -> Result<()> {
+    let mut client = get_client().await?;
+    let resp = client.member_list().await?;
+    let unknown_id = resp.members().iter().map(|m| m.id()).max().unwrap() + 1;
+
+    match client.move_leader(unknown_id).await {
+        Err(Error::MemberNotFound(id)) => assert_eq!(id, unknown_id),
+        other => panic!("expected member not found, got {:?}", other),
+    }
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_election() -> Result<()> {
     let mut client = get_client().await?;
@@ -742,6 +1926,88 @@ async fn test_election() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_election_guarded() -> Result<()> {
+    let mut client = get_client().await?;
+    let options = ElectionOptions::new().with_session(SessionOptions::new().with_ttl(10));
+    let mut guard = client
+        .campaign_guarded("myGuardedElection", "123", Some(options))
+        .await?;
+    assert_eq!(guard.leader().name(), b"myGuardedElection");
+    assert_eq!(guard.leader().lease(), guard.session().lease_id());
+    assert!(!guard.is_lost());
+
+    guard.proclaim("456").await?;
+    let resp = client.leader("myGuardedElection").await?;
+    assert_eq!(resp.kv().unwrap().value(), b"456");
+
+    // deleting the leader key loses the leadership
+    client.delete(guard.leader().key(), None).await?;
+    let lost = tokio::time::timeout(std::time::Duration::from_secs(5), guard.lost()).await;
+    assert!(lost.is_ok());
+    assert!(guard.is_lost());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_election_auto_recampaign() -> Result<()> {
+    let mut client = get_client().await?;
+    let options = ElectionOptions::new()
+        .with_session(SessionOptions::new().with_ttl(10))
+        .with_auto_recampaign(true);
+    let mut events = client.campaign_events("myRecampaignElection", "123", Some(options));
+
+    let Some(LeadershipEvent::Elected { epoch, leader }) = events.message().await.transpose()?
+    else {
+        panic!("expected an elected event");
+    };
+    assert_eq!(epoch, 1);
+
+    // resigning externally deposes the leader, which then campaigns again
+    client
+        .resign(Some(ResignOptions::new().with_leader(leader.clone())))
+        .await?;
+    let event = events.message().await.transpose()?;
+    assert!(matches!(event, Some(LeadershipEvent::Deposed { epoch: 1 })));
+    let event = events.message().await.transpose()?;
+    let Some(LeadershipEvent::Elected { epoch, leader: new }) = event else {
+        panic!("expected an elected event");
+    };
+    assert_eq!(epoch, 2);
+    assert!(new.rev() > leader.rev());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_election_events_dropped_while_campaigning() -> Result<()> {
+    let mut client = get_client().await?;
+    let lease = client.lease_grant(60, None).await?.id();
+    client.campaign("election-dropped", "0", lease).await?;
+
+    let options = ElectionOptions::new().with_session(SessionOptions::new().with_ttl(60));
+    let events = client.campaign_events("election-dropped", "1", Some(options));
+    let mut election = client.election_client();
+    while election.candidates("election-dropped").await?.len() < 2 {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    // the candidate key is deleted with its session, long before the lease would expire
+    drop(events);
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while election.candidates("election-dropped").await?.len() > 1 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        Ok::<_, Error>(())
+    })
+    .await
+    .expect("candidate key left behind")?;
+
+    client.lease_revoke(lease).await?;
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_remove_and_add_endpoint() -> Result<()> {
     let mut client = get_client().await?;
@@ -758,9 +2024,11 @@ async fn test_remove_and_add_endpoint() -> Result<()> {
     }
 
     // remove endpoint
-    client.remove_endpoint(DEFAULT_TEST_ENDPOINT).await?;
+    let cluster = cluster().await?;
+    let endpoint = endpoint(&cluster);
+    client.remove_endpoint(&endpoint).await?;
     // `Client::get` will hang before adding the endpoint back
-    client.add_endpoint(DEFAULT_TEST_ENDPOINT).await?;
+    client.add_endpoint(&endpoint).await?;
 
     // get key after remove and add endpoint
     {
@@ -774,3 +2042,241 @@ async fn test_remove_and_add_endpoint() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn test_gzip_compression() -> Result<()> {
+    use etcd_client::CompressionEncoding;
+
+    let options = ConnectOptions::new()
+        .with_send_compression(CompressionEncoding::Gzip)
+        .with_accept_compression(&[CompressionEncoding::Gzip]);
+    let cluster = cluster().await?;
+    let mut client = cluster.client_with(Some(options)).await?;
+    client.put("compression", "gzip".repeat(1024), None).await?;
+    let resp = client.get("compression", None).await?;
+    assert_eq!(resp.kvs()[0].value(), "gzip".repeat(1024).as_bytes());
+
+    let (mut watcher, mut stream) = client.watch("compression", None).await?;
+    client.put("compression", "watched", None).await?;
+    let resp = stream.message().await?.unwrap();
+    assert_eq!(resp.events()[0].kv().unwrap().value(), b"watched");
+    watcher.cancel().await?;
+
+    let mut snapshot = client.snapshot().await?;
+    while let Some(resp) = snapshot.message().await? {
+        if resp.remaining_bytes() == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "zstd")]
+#[tokio::test]
+async fn test_zstd_compression_rejected() -> Result<()> {
+    use etcd_client::CompressionEncoding;
+
+    // etcd does not decompress zstd, the client falls back to uncompressed requests.
+    let options = ConnectOptions::new().with_send_compression(CompressionEncoding::Zstd);
+    let cluster = cluster().await?;
+    let mut client = cluster.client_with(Some(options)).await?;
+    client.put("compression", "zstd", None).await?;
+    let resp = client.get("compression", None).await?;
+    assert_eq!(resp.kvs()[0].value(), b"zstd");
+    client.lease_grant(60, None).await?;
+    Ok(())
+}
+
+#[cfg(feature = "raw-proto")]
+#[tokio::test]
+async fn test_kv_raw() -> Result<()> {
+    use etcd_client::raw::etcdserverpb::RangeRequest;
+
+    let mut client = get_client().await?;
+    client.put("raw0", "0", None).await?;
+    client.put("raw1", "1", None).await?;
+
+    let resp = client
+        .get("raw", Some(GetOptions::new().with_prefix()))
+        .await?;
+    let raw = client
+        .kv_raw()
+        .range(RangeRequest {
+            key: b"raw".to_vec().into(),
+            range_end: b"rax".to_vec().into(),
+            ..Default::default()
+        })
+        .await?
+        .into_inner();
+    assert_eq!(raw.count, resp.count());
+    let kvs: Vec<_> = raw
+        .kvs
+        .iter()
+        .map(|kv| (&kv.key[..], &kv.value[..], kv.mod_revision))
+        .collect();
+    let expected: Vec<_> = resp
+        .kvs()
+        .iter()
+        .map(|kv| (kv.key(), kv.value(), kv.mod_revision()))
+        .collect();
+    assert_eq!(kvs, expected);
+    assert_eq!(kvs.len(), 2);
+    Ok(())
+}
+
+#[cfg(feature = "raw-proto")]
+#[tokio::test]
+async fn test_channel() -> Result<()> {
+    use etcd_client::raw::etcdserverpb::maintenance_client::MaintenanceClient;
+    use etcd_client::raw::etcdserverpb::StatusRequest;
+
+    let mut client = get_client().await?;
+    let status = client.status().await?;
+
+    // A client of the Maintenance service built over the connections of the client.
+    let mut maintenance = MaintenanceClient::new(client.channel());
+    let raw = maintenance.status(StatusRequest {}).await?.into_inner();
+    assert_eq!(raw.version, status.version());
+    let mut maintenance = MaintenanceClient::new(client.channel().into_boxed());
+    let raw = maintenance.status(StatusRequest {}).await?.into_inner();
+    assert_eq!(raw.version, status.version());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_value_codec() -> Result<()> {
+    use etcd_client::ValueCodec;
+
+    /// A codec reversing the bytes of the values.
+    struct Reverse;
+
+    impl ValueCodec for Reverse {
+        fn magic(&self) -> &[u8] {
+            b"\0rev"
+        }
+
+        fn encode(&self, value: &[u8]) -> std::io::Result<Vec<u8>> {
+            Ok(value.iter().rev().copied().collect())
+        }
+
+        fn decode(&self, encoded: &[u8]) -> std::io::Result<Vec<u8>> {
+            self.encode(encoded)
+        }
+    }
+
+    let mut client = get_client().await?;
+    let mut codec = client.with_value_codec(Reverse);
+    client.put("codec-legacy", "legacy", None).await?;
+    codec.put("codec-new", "new", None).await?;
+
+    // The values are encoded in the store, and the legacy ones read as they are.
+    let resp = client.get("codec-new", None).await?;
+    assert_eq!(resp.kvs()[0].value(), b"\0revwen");
+    let resp = codec
+        .get("codec-", Some(GetOptions::new().with_prefix()))
+        .await?;
+    let values: Vec<_> = resp.kvs().iter().map(|kv| kv.value()).collect();
+    assert_eq!(values, [&b"legacy"[..], b"new"]);
+
+    let (mut watcher, mut stream) = codec.watch("codec-new", None).await?;
+    let txn = Txn::new().and_then([TxnOp::put(
+        "codec-new",
+        "newer",
+        Some(PutOptions::new().with_prev_key()),
+    )]);
+    let resp = codec.txn(txn).await?;
+    let TxnOpResponse::Put(put) = &resp.op_responses()[0] else {
+        panic!("{:?}", resp);
+    };
+    assert_eq!(put.prev_key().unwrap().value(), b"new");
+    let event = loop {
+        let resp = stream.message().await?.unwrap();
+        if let Some(event) = resp.events().first() {
+            break event.clone();
+        }
+    };
+    assert_eq!(event.kv().unwrap().value(), b"newer");
+    watcher.cancel().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_key_path() -> Result<()> {
+    use etcd_client::keys::{KeyPath, KeyReader};
+
+    let mut client = get_client().await?;
+    let jobs = KeyPath::new("key-path").push_str("a/b");
+    for job in [10, 9, 256] {
+        client.put(jobs.clone().push_u64_be(job), "", None).await?;
+    }
+    client.put(jobs.clone(), "", None).await?;
+    client
+        .put(KeyPath::new("key-path").push_str("a"), "", None)
+        .await?;
+
+    let (key, end) = jobs.range_for_child_prefix();
+    let resp = client
+        .get(key, Some(GetOptions::new().with_range(end)))
+        .await?;
+    let mut ids = Vec::new();
+    for kv in resp.kvs() {
+        let mut key = KeyReader::new(kv.key());
+        assert_eq!(key.next_str()?, "key-path");
+        assert_eq!(key.next_str()?, "a/b");
+        ids.push(key.next_u64_be()?);
+        key.finish()?;
+    }
+    assert_eq!(ids, [9, 10, 256]);
+    Ok(())
+}
+
+#[cfg(feature = "record-replay")]
+#[tokio::test]
+async fn test_record_replay() -> Result<()> {
+    use etcd_client::replay::{self, RecordingChannel};
+    use etcd_client::{Client, Revision};
+
+    async fn session(mut client: Client) -> Result<(Revision, Vec<u8>)> {
+        client.put("replay", "1", None).await?;
+        let (mut watcher, mut stream) = client.watch("replay", None).await?;
+        let resp = client.put("replay", "2", None).await?;
+        let revision = resp.header().unwrap().revision();
+        let value = loop {
+            let resp = stream.message().await?.unwrap();
+            if let Some(event) = resp.events().first() {
+                break event.kv().unwrap().value().to_vec();
+            }
+        };
+        watcher.cancel().await?;
+        Ok((revision, value))
+    }
+
+    let client = get_client().await?;
+    let path = std::env::temp_dir().join(format!("etcd-replay-{}.bin", std::process::id()));
+    let sink = std::fs::File::create(&path)?;
+    let recording = Client::from_channel(RecordingChannel::wrap(client.channel(), sink), None);
+    let recorded = session(recording.await?).await?;
+
+    // The replay needs no cluster, and serves the same responses.
+    assert_eq!(replay::verify(session, &path).await?, recorded);
+    tokio::fs::remove_file(&path).await?;
+    Ok(())
+}
+
+#[cfg(feature = "http-probe")]
+#[tokio::test]
+async fn test_http_probe() -> Result<()> {
+    let client = get_client().await?;
+    let cluster = cluster().await?;
+    let endpoint = endpoint(&cluster);
+
+    let health = client.http_health(&endpoint).await?;
+    assert!(health.is_healthy(), "{:?}", health);
+
+    let version = client.http_version(&endpoint).await?;
+    assert!(version.etcdserver().starts_with("3."), "{:?}", version);
+    assert!(version.etcdcluster().starts_with("3."), "{:?}", version);
+    Ok(())
+}
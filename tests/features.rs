@@ -0,0 +1,47 @@
+//! Checks that the crate builds with each of the features of the etcd services on its own.
+//!
+//! Every build compiles the crate anew, so the test is ignored by default, run it with
+//! `cargo test --test features -- --ignored`.
+
+use std::path::Path;
+use std::process::Command;
+
+/// The features of the etcd services.
+const SERVICES: &[&str] = &[
+    "kv",
+    "watch",
+    "lease",
+    "lock",
+    "election",
+    "maintenance",
+    "cluster",
+    "auth",
+];
+
+/// Checks all the targets of the crate with only `features` enabled, denying warnings.
+fn check(features: &str) {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let status = Command::new(env!("CARGO"))
+        .current_dir(manifest_dir)
+        .args(["check", "--all-targets", "--no-default-features"])
+        .args(["--features", features])
+        // A target directory of its own, not to wait for the lock of the one running the test.
+        .env(
+            "CARGO_TARGET_DIR",
+            manifest_dir.join("target").join("features"),
+        )
+        .env("RUSTFLAGS", "-D warnings")
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "features {:?} failed to build", features);
+}
+
+#[test]
+#[ignore]
+fn test_service_features() {
+    check("");
+    for feature in SERVICES {
+        check(feature);
+    }
+    check(&SERVICES.join(","));
+}
@@ -0,0 +1,643 @@
+mod testing;
+
+use crate::testing::{get_client, Result, TestClient};
+use etcd_client::recipes::{
+    expiry_monitor, leader_task, mirror, Barrier, Cache, CacheEvent, DoubleBarrier, ExpiryEvent,
+    LeaderTaskEvent, MirrorEvent, MirrorOptions, PriorityQueue, Queue, RwLock, Semaphore,
+    ServiceEvent, ServiceRegistry,
+};
+use etcd_client::{Client, DeleteOptions, GetOptions, PutOptions, Session, SessionOptions};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_barrier() -> Result<()> {
+    let client = get_client().await?;
+    let mut barrier = Barrier::new(&client, "test-barrier");
+    let _ = barrier.release().await;
+
+    // not held, waiting returns immediately
+    barrier.wait().await?;
+
+    barrier.hold(None).await?;
+    assert!(barrier.hold(None).await.unwrap_err().is_already_exists());
+
+    let waiters: Vec<_> = (0..5)
+        .map(|_| {
+            let mut barrier = Barrier::new(&client, "test-barrier");
+            tokio::spawn(async move { barrier.wait().await })
+        })
+        .collect();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(waiters.iter().all(|waiter| !waiter.is_finished()));
+
+    // releasing the barrier unblocks all the waiters together
+    barrier.release().await?;
+    for waiter in waiters {
+        tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .expect("waiter not released")
+            .unwrap()?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_barrier_session() -> Result<()> {
+    let client = get_client().await?;
+    let mut barrier = Barrier::new(&client, "test-barrier-session");
+    let _ = barrier.release().await;
+
+    let options = SessionOptions::new().with_ttl(10);
+    let session = Session::new(client.lease_client(), Some(options)).await?;
+    barrier.hold(Some(&session)).await?;
+    let resp = client.kv_client().get("test-barrier-session", None).await?;
+    assert_eq!(resp.kvs()[0].lease(), session.lease_id());
+
+    // closing the session revokes the lease, which releases the barrier
+    let mut waiter = barrier.clone();
+    let waiter = tokio::spawn(async move { waiter.wait().await });
+    session.close().await?;
+    tokio::time::timeout(Duration::from_secs(5), waiter)
+        .await
+        .expect("waiter not released")
+        .unwrap()?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_double_barrier() -> Result<()> {
+    let client = get_client().await?;
+    let prefix = "test-double-barrier";
+    let options = DeleteOptions::new().with_prefix();
+    client.kv_client().delete(prefix, Some(options)).await?;
+
+    // a participant is killed while entering, it is gone once its lease is
+    let session = Session::new(client.lease_client(), None).await?;
+    let lease = session.lease_id();
+    let mut killed = DoubleBarrier::new(&client, prefix, 3);
+    let entering = tokio::spawn(async move { killed.enter(&session).await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!entering.is_finished());
+    entering.abort();
+    // not to wait for the TTL of the lease
+    client.lease_client().revoke(lease).await?;
+
+    let participant = |client: &TestClient| {
+        let mut barrier = DoubleBarrier::new(client, prefix, 3);
+        let lease = client.lease_client();
+        tokio::spawn(async move {
+            let session = Session::new(lease, None).await?;
+            barrier.enter(&session).await?;
+            barrier.leave().await?;
+            session.close().await
+        })
+    };
+    let mut participants: Vec<_> = (0..2).map(|_| participant(&client)).collect();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(participants.iter().all(|p| !p.is_finished()));
+
+    // the last participant releases the others, the killed one not being counted
+    participants.push(participant(&client));
+    for participant in participants {
+        tokio::time::timeout(Duration::from_secs(5), participant)
+            .await
+            .expect("participant blocked")
+            .unwrap()?;
+    }
+    let options = GetOptions::new().with_prefix().with_count_only();
+    let resp = client.kv_client().get(prefix, Some(options)).await?;
+    assert_eq!(resp.count(), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_queue() -> Result<()> {
+    let client = get_client().await?;
+    let prefix = "test-queue";
+    let options = DeleteOptions::new().with_prefix();
+    client.kv_client().delete(prefix, Some(options)).await?;
+
+    let mut queue = Queue::new(&client, prefix);
+    assert_eq!(queue.try_pop().await?, None);
+    assert_eq!(queue.peek().await?, None);
+
+    for value in ["first", "second", "third"] {
+        queue.push(value).await?;
+    }
+    assert_eq!(queue.peek().await?.as_deref(), Some(&b"first"[..]));
+    assert_eq!(queue.pop().await?, b"first");
+    assert_eq!(queue.try_pop().await?.as_deref(), Some(&b"second"[..]));
+    assert_eq!(queue.pop().await?, b"third");
+
+    // popping an empty queue waits for a push
+    let mut consumer = queue.clone();
+    let popping = tokio::spawn(async move { consumer.pop().await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!popping.is_finished());
+    queue.push("fourth").await?;
+    let value = tokio::time::timeout(Duration::from_secs(5), popping)
+        .await
+        .expect("consumer blocked")
+        .unwrap()?;
+    assert_eq!(value, b"fourth");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_queue_concurrent() -> Result<()> {
+    let client = get_client().await?;
+    let prefix = "test-queue-concurrent";
+    let options = DeleteOptions::new().with_prefix();
+    client.kv_client().delete(prefix, Some(options)).await?;
+
+    let (producers, consumers, items) = (3, 4, 20);
+    let mut tasks = Vec::new();
+    for producer in 0..producers {
+        let mut queue = Queue::new(&client, prefix);
+        tasks.push(tokio::spawn(async move {
+            for item in 0..items {
+                queue.push(format!("{}-{}", producer, item)).await?;
+            }
+            Ok(Vec::new())
+        }));
+    }
+    for consumer in 0..consumers {
+        let mut queue = Queue::new(&client, prefix);
+        // the items are split between the consumers, each popping its share
+        let share =
+            producers * items / consumers + usize::from(consumer < producers * items % consumers);
+        tasks.push(tokio::spawn(async move {
+            let mut popped = Vec::new();
+            for _ in 0..share {
+                popped.push(String::from_utf8(queue.pop().await?).unwrap());
+            }
+            Ok(popped)
+        }));
+    }
+
+    // every pushed item is popped exactly once
+    let mut popped = Vec::new();
+    for task in tasks {
+        let items: Result<Vec<String>> = tokio::time::timeout(Duration::from_secs(30), task)
+            .await
+            .expect("queue blocked")
+            .unwrap();
+        popped.extend(items?);
+    }
+    let unique: HashSet<_> = popped.iter().cloned().collect();
+    assert_eq!(popped.len(), producers * items);
+    assert_eq!(unique.len(), producers * items);
+    assert_eq!(Queue::new(&client, prefix).try_pop().await?, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_priority_queue() -> Result<()> {
+    let client = get_client().await?;
+    let prefix = "test-priority-queue";
+    let options = DeleteOptions::new().with_prefix();
+    client.kv_client().delete(prefix, Some(options)).await?;
+
+    let mut queue = PriorityQueue::new(&client, prefix);
+    assert_eq!(queue.try_pop().await?, None);
+    for (value, priority) in [("low", 10), ("high", 1), ("low-2", 10), ("lowest", 65535)] {
+        queue.push(value, priority).await?;
+    }
+    queue.push("high-2", 1).await?;
+
+    // lower priorities first, equal ones in push order
+    assert_eq!(queue.peek().await?.as_deref(), Some(&b"high"[..]));
+    for value in ["high", "high-2", "low", "low-2", "lowest"] {
+        assert_eq!(queue.pop().await?, value.as_bytes());
+    }
+    assert_eq!(queue.try_pop().await?, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_priority_queue_starvation() -> Result<()> {
+    let client = get_client().await?;
+    let prefix = "test-priority-queue-starvation";
+    let options = DeleteOptions::new().with_prefix();
+    client.kv_client().delete(prefix, Some(options)).await?;
+
+    let mut queue = PriorityQueue::new(&client, prefix);
+    queue.push("background", 100).await?;
+
+    // under a steady load of urgent values, the background one starves
+    for round in 0..10 {
+        queue.push(format!("urgent-{}", round), 0).await?;
+        queue.push(format!("urgent-{}-bis", round), 0).await?;
+        let popped = queue.pop().await?;
+        assert!(popped.starts_with(b"urgent-"), "{:?}", popped);
+    }
+    for _ in 0..10 {
+        assert!(queue.pop().await?.starts_with(b"urgent-"));
+    }
+
+    // it is popped once the load drops
+    assert_eq!(queue.pop().await?, b"background");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_leader_task() -> Result<()> {
+    let mut client = get_client().await?;
+    let (started, mut runs) = tokio::sync::mpsc::unbounded_channel();
+    let mut runner = leader_task(&client, "test-leader-task", move |mut context| {
+        let started = started.clone();
+        async move {
+            context.proclaim("leading").await.unwrap();
+            let _ = started.send((context.epoch(), context.leader().clone()));
+            if context.epoch() == 2 {
+                panic!("task bug");
+            }
+            context.cancellation_token().cancelled().await;
+        }
+    });
+
+    let leader = runs.recv().await.unwrap().1;
+    assert!(matches!(
+        runner.message().await,
+        Some(LeaderTaskEvent::Elected { epoch: 1, .. })
+    ));
+    let resp = client.leader("test-leader-task").await?;
+    assert_eq!(resp.kv().unwrap().value(), b"leading");
+
+    // losing the leadership stops the task, which runs again once elected again
+    client.delete(leader.key(), None).await?;
+    assert!(matches!(
+        runner.message().await,
+        Some(LeaderTaskEvent::Deposed { epoch: 1 })
+    ));
+    assert!(matches!(
+        runner.message().await,
+        Some(LeaderTaskEvent::Elected { epoch: 2, .. })
+    ));
+
+    // a panic of the task is reported, the runner goes on
+    match runner.message().await {
+        Some(LeaderTaskEvent::Panicked {
+            epoch: 2,
+            panic_message,
+        }) => assert_eq!(panic_message, "task bug"),
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert!(matches!(
+        runner.message().await,
+        Some(LeaderTaskEvent::Elected { epoch: 3, .. })
+    ));
+    assert_eq!(runs.recv().await.unwrap().0, 2);
+    assert_eq!(runs.recv().await.unwrap().0, 3);
+
+    // shutting down resigns the leadership
+    runner.shutdown().await?;
+    assert!(client.leader("test-leader-task").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_service_registry() -> Result<()> {
+    let client = get_client().await?;
+    let mut registry = ServiceRegistry::new(&client, "test-service-registry");
+
+    let first = registry.register("api", "first", "10.0.0.1", 10).await?;
+    assert_eq!(first.key(), b"test-service-registry/api/first");
+    let mut watch = registry.discover("api").await?;
+    match watch.message().await? {
+        ServiceEvent::Instances(instances) => {
+            assert_eq!(instances.len(), 1);
+            assert_eq!(instances[0].id_str()?, "first");
+            assert_eq!(instances[0].metadata(), b"10.0.0.1");
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    let second = registry.register("api", "second", "10.0.0.2", 10).await?;
+    match watch.message().await? {
+        ServiceEvent::Added(instance) => assert_eq!(instance.id(), b"second"),
+        event => panic!("unexpected event {:?}", event),
+    }
+    let _again = registry.register("api", "second", "10.0.0.3", 10).await?;
+    match watch.message().await? {
+        ServiceEvent::Updated(instance) => assert_eq!(instance.metadata(), b"10.0.0.3"),
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert_eq!(watch.instances().count(), 2);
+
+    // closing revokes the lease, dropping revokes it in the background
+    first.close().await?;
+    match watch.message().await? {
+        ServiceEvent::Removed(instance) => assert_eq!(instance.id(), b"first"),
+        event => panic!("unexpected event {:?}", event),
+    }
+    drop(second);
+    assert!(registry.discover("api/v1").await.is_err());
+    assert!(registry.register("api", "", "", 10).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_semaphore() -> Result<()> {
+    let client = get_client().await?;
+    let session = Session::new(client.lease_client(), None).await?;
+    let mut first = Semaphore::new(&client, "test-semaphore", 2);
+    let mut second = first.clone();
+    let mut third = first.clone();
+
+    first.acquire(&session).await?;
+    assert!(second.try_acquire(&session).await?);
+    assert!(!third.try_acquire(&session).await?);
+    assert!(
+        !third
+            .acquire_timeout(&session, Duration::from_millis(200))
+            .await?
+    );
+    // giving up leaves no key behind
+    let options = GetOptions::new().with_prefix().with_count_only();
+    assert_eq!(
+        client
+            .kv_client()
+            .get("test-semaphore/", Some(options))
+            .await?
+            .count(),
+        2
+    );
+
+    first.release().await?;
+    assert!(first.release().await.is_err());
+    assert!(third.try_acquire(&session).await?);
+    second.release().await?;
+    third.release().await?;
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_semaphore_contenders() -> Result<()> {
+    let client = get_client().await?;
+    let inside = Arc::new(AtomicUsize::new(0));
+    let mut contenders = Vec::new();
+    for contender in 0..5 {
+        let client = Client::clone(&client);
+        let inside = inside.clone();
+        contenders.push(tokio::spawn(async move {
+            let options = SessionOptions::new().with_ttl(2);
+            let session = Session::new(client.lease_client(), Some(options)).await?;
+            let mut semaphore = Semaphore::new(&client, "test-semaphore-contenders", 2);
+            for round in 0..3 {
+                semaphore.acquire(&session).await?;
+                assert!(inside.fetch_add(1, Ordering::SeqCst) < 2);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                inside.fetch_sub(1, Ordering::SeqCst);
+                if contender == 0 && round == 2 {
+                    // crashes while holding, released once its lease expires
+                    drop(session);
+                    return Ok(());
+                }
+                semaphore.release().await?;
+            }
+            Ok::<_, etcd_client::Error>(())
+        }));
+    }
+    for contender in contenders {
+        contender.await.unwrap()?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rw_lock() -> Result<()> {
+    let client = get_client().await?;
+    let session = Session::new(client.lease_client(), None).await?;
+    let mut lock = RwLock::new(&client, "test-rw-lock");
+
+    // readers hold the lock together
+    let first = lock.read(&session).await?;
+    let second = lock.read(&session).await?;
+    assert!(!first.is_write());
+    assert!(first.key().starts_with(b"test-rw-lock/read/"));
+
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let writer = {
+        let client = Client::clone(&client);
+        let log = log.clone();
+        tokio::spawn(async move {
+            let session = Session::new(client.lease_client(), None).await?;
+            let guard = RwLock::new(&client, "test-rw-lock").write(&session).await?;
+            log.lock().unwrap().push("writer");
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            log.lock().unwrap().push("writer done");
+            guard.unlock().await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(log.lock().unwrap().is_empty());
+
+    // a reader after the writer waits for it
+    let reader = {
+        let client = Client::clone(&client);
+        let log = log.clone();
+        tokio::spawn(async move {
+            let session = Session::new(client.lease_client(), None).await?;
+            let guard = RwLock::new(&client, "test-rw-lock").read(&session).await?;
+            log.lock().unwrap().push("reader");
+            guard.unlock().await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(log.lock().unwrap().is_empty());
+
+    first.unlock().await?;
+    drop(second);
+    writer.await.unwrap()?;
+    reader.await.unwrap()?;
+    assert_eq!(*log.lock().unwrap(), ["writer", "writer done", "reader"]);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rw_lock_alternating() -> Result<()> {
+    let client = get_client().await?;
+    let (readers, writers) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let mut contenders = Vec::new();
+    for contender in 0..6 {
+        let client = Client::clone(&client);
+        let (readers, writers) = (readers.clone(), writers.clone());
+        contenders.push(tokio::spawn(async move {
+            let session = Session::new(client.lease_client(), None).await?;
+            let mut lock = RwLock::new(&client, "test-rw-lock-alternating");
+            for _ in 0..3 {
+                if contender % 2 == 0 {
+                    let guard = lock.write(&session).await?;
+                    assert_eq!(writers.fetch_add(1, Ordering::SeqCst), 0);
+                    assert_eq!(readers.load(Ordering::SeqCst), 0);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    writers.fetch_sub(1, Ordering::SeqCst);
+                    guard.unlock().await?;
+                } else {
+                    let guard = lock.read(&session).await?;
+                    readers.fetch_add(1, Ordering::SeqCst);
+                    assert_eq!(writers.load(Ordering::SeqCst), 0);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    readers.fetch_sub(1, Ordering::SeqCst);
+                    guard.unlock().await?;
+                }
+            }
+            Ok::<_, etcd_client::Error>(())
+        }));
+    }
+    // every contender gets the lock, neither readers nor writers starve
+    for contender in contenders {
+        contender.await.unwrap()?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cache() -> Result<()> {
+    let mut client = get_client().await?;
+    client.put("test-cache/a", "1", None).await?;
+    client.put("test-cache/b", "2", None).await?;
+
+    let cache = Cache::new(&client, "test-cache/", None).await?;
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get("test-cache/a").unwrap().value(), b"1");
+    let revision = cache.revision();
+    assert!(revision > 0);
+
+    let mut changes = cache.subscribe();
+    client.put("test-cache/c", "3", None).await?;
+    match changes.message().await {
+        Some(CacheEvent::Put(kv)) => assert_eq!(kv.key(), b"test-cache/c"),
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert_eq!(cache.get("test-cache/c").unwrap().value(), b"3");
+
+    client.delete("test-cache/a", None).await?;
+    match changes.message().await {
+        // the deleted key-value as it was cached
+        Some(CacheEvent::Delete(kv)) => assert_eq!(kv.value(), b"1"),
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert!(cache.get("test-cache/a").is_none());
+    assert_eq!(cache.iter_snapshot().count(), 2);
+    assert!(cache.revision() > revision);
+
+    drop(cache);
+    assert!(changes.message().await.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mirror() -> Result<()> {
+    let mut client = get_client().await?;
+    for i in 0..5 {
+        client
+            .put(format!("test-mirror/src/{}", i), i.to_string(), None)
+            .await?;
+    }
+
+    // mirrored within the same cluster, under another prefix
+    let options = MirrorOptions::new("test-mirror/src/")
+        .with_dst_prefix("test-mirror/dst/")
+        .with_batch_size(2);
+    let mut mirror = mirror(&client, &client, options);
+    let revision = loop {
+        match mirror.message().await {
+            Some(MirrorEvent::Copied { keys }) => assert!(keys <= 5),
+            Some(MirrorEvent::Synced { keys, revision }) => {
+                assert_eq!(keys, 5);
+                break revision;
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    };
+    let resp = client.get("test-mirror/dst/3", None).await?;
+    assert_eq!(resp.kvs()[0].value(), b"3");
+
+    client.put("test-mirror/src/5", "5", None).await?;
+    let resp = client.delete("test-mirror/src/0", None).await?;
+    let deleted = resp.header().unwrap().revision();
+    loop {
+        match mirror.message().await {
+            Some(MirrorEvent::Applied {
+                revision: applied,
+                lag,
+            }) => {
+                assert!(applied > revision);
+                assert!(lag >= 0);
+                if applied == deleted {
+                    break;
+                }
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+    let resp = client.get("test-mirror/dst/5", None).await?;
+    assert_eq!(resp.kvs()[0].value(), b"5");
+    let resp = client.get("test-mirror/dst/0", None).await?;
+    assert!(resp.kvs().is_empty());
+
+    mirror.stop().await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_expiry_monitor() -> Result<()> {
+    let mut client = get_client().await?;
+    let mut monitor = expiry_monitor(&client, "test-expiry/");
+    // The deletions are watched from the creation of the watch in the background.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // deleted explicitly, while its lease is alive
+    let lease = client.lease_grant(60, None).await?.id();
+    let options = PutOptions::new().with_lease(lease);
+    client
+        .put("test-expiry/deleted", "v", Some(options))
+        .await?;
+    client.delete("test-expiry/deleted", None).await?;
+    assert_eq!(
+        monitor.message().await,
+        Some(ExpiryEvent::Deleted {
+            key: b"test-expiry/deleted".to_vec()
+        })
+    );
+    client.lease_revoke(lease).await?;
+
+    // deleted by the expiry of its lease
+    let lease = client.lease_grant(1, None).await?.id();
+    let options = PutOptions::new().with_lease(lease);
+    client
+        .put("test-expiry/expired", "v", Some(options))
+        .await?;
+    let event = tokio::time::timeout(Duration::from_secs(10), monitor.message()).await;
+    assert_eq!(
+        event.expect("lease not expired"),
+        Some(ExpiryEvent::Expired {
+            key: b"test-expiry/expired".to_vec(),
+            lease
+        })
+    );
+
+    monitor.stop().await?;
+
+    Ok(())
+}
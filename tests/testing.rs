@@ -1,13 +1,60 @@
-use etcd_client::{Client, Error};
-
-pub const DEFAULT_TEST_ENDPOINT: &str = "localhost:2379";
+use etcd_client::test_util::EtcdCluster;
+use etcd_client::{Client, ConnectOptions, Error};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Weak};
+use tokio::sync::Mutex;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The cluster shared by the tests running concurrently, torn down after the last of them.
+static CLUSTER: Mutex<Weak<EtcdCluster>> = Mutex::const_new(Weak::new());
+
+/// Get the single-member cluster for testing, starting it if no running test holds it.
+pub async fn cluster() -> Result<Arc<EtcdCluster>> {
+    let mut shared = CLUSTER.lock().await;
+    if let Some(cluster) = shared.upgrade() {
+        return Ok(cluster);
+    }
+    let cluster = Arc::new(EtcdCluster::start(1).await?);
+    *shared = Arc::downgrade(&cluster);
+    Ok(cluster)
+}
+
+/// The endpoint of the cluster for testing.
+#[allow(dead_code)] // Not used by every test crate.
+pub fn endpoint(cluster: &EtcdCluster) -> String {
+    cluster.endpoints().remove(0)
+}
+
+/// A client keeping the cluster for testing running.
+pub struct TestClient {
+    client: Client,
+    _cluster: Arc<EtcdCluster>,
+}
+
+impl Deref for TestClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl DerefMut for TestClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        &mut self.client
+    }
+}
+
 /// Get client for testing.
-pub async fn get_client() -> Result<Client> {
+pub async fn get_client() -> Result<TestClient> {
+    let cluster = cluster().await?;
     // Require a leader be present -- with only a single node, this
     // should never fail.
-    let options = etcd_client::ConnectOptions::new().with_require_leader(true);
-    Client::connect([DEFAULT_TEST_ENDPOINT], Some(options)).await
+    let options = ConnectOptions::new().with_require_leader(true);
+    let client = cluster.client_with(Some(options)).await?;
+    Ok(TestClient {
+        client,
+        _cluster: cluster,
+    })
 }